@@ -85,7 +85,7 @@ fn bench_windowing(c: &mut Criterion) {
 
 fn bench_filtering(c: &mut Criterion) {
     use kashshaf_reuse::filter::find_candidate_pairs;
-    use kashshaf_reuse::models::Window;
+    use kashshaf_reuse::models::{CandidateMethod, Window};
 
     let params = ComparisonParams::default();
 
@@ -107,6 +107,10 @@ fn bench_filtering(c: &mut Criterion) {
                 end_page: (1, 1),
                 end_offset: 0,
                 lemma_ids: (i * 50..i * 50 + 275).map(|x| x as u32).collect(),
+                root_ids: vec![0; 275],
+                token_ids: Vec::new(),
+                alt_lemma_ids: Vec::new(),
+                shingle_hashes: Vec::new(),
             })
             .collect();
 
@@ -131,6 +135,10 @@ fn bench_filtering(c: &mut Criterion) {
                         }
                     })
                     .collect(),
+                root_ids: vec![0; 275],
+                token_ids: Vec::new(),
+                alt_lemma_ids: Vec::new(),
+                shingle_hashes: Vec::new(),
             })
             .collect();
 
@@ -147,13 +155,26 @@ fn bench_filtering(c: &mut Criterion) {
                 })
             },
         );
+
+        for (name, candidate_method) in [("winnowing", CandidateMethod::Winnowing), ("minhash", CandidateMethod::MinHash)] {
+            let params = ComparisonParams {
+                candidate_method,
+                ..params.clone()
+            };
+            group.bench_with_input(BenchmarkId::new(name, count), &count, |b, _| {
+                b.iter(|| find_candidate_pairs(black_box(&windows_a), black_box(&windows_b), &params))
+            });
+        }
     }
 
     group.finish();
 }
 
 fn bench_shingling(c: &mut Criterion) {
-    use kashshaf_reuse::filter::generate_shingles;
+    use kashshaf_reuse::filter::{compute_shingle_hashes, generate_shingles, shingle_hash};
+    use kashshaf_reuse::models::{MatchMode, ShingleKind, Window};
+    use std::collections::hash_map::DefaultHasher;
+    use std::hash::{Hash, Hasher};
 
     let mut group = c.benchmark_group("shingling");
 
@@ -161,10 +182,46 @@ fn bench_shingling(c: &mut Criterion) {
 
     for size in sizes {
         let seq: Vec<u32> = (0..size as u32).collect();
+        let window = Window {
+            book_id: 1,
+            window_idx: 0,
+            global_start: 0,
+            global_end: size,
+            start_page: (1, 1),
+            start_offset: 0,
+            end_page: (1, 1),
+            end_offset: 0,
+            lemma_ids: seq.clone(),
+            root_ids: vec![0; size],
+            token_ids: Vec::new(),
+            alt_lemma_ids: Vec::new(),
+            shingle_hashes: Vec::new(),
+        };
 
+        // Heap-allocated n-gram keys
         group.bench_with_input(BenchmarkId::new("generate", size), &size, |b, _| {
             b.iter(|| generate_shingles(black_box(&seq), 5))
         });
+        // The same n-grams hashed with the standard library's SipHash
+        group.bench_with_input(BenchmarkId::new("siphash", size), &size, |b, _| {
+            b.iter(|| {
+                black_box(&seq)
+                    .windows(5)
+                    .map(|ngram| {
+                        let mut hasher = DefaultHasher::new();
+                        ngram.hash(&mut hasher);
+                        hasher.finish()
+                    })
+                    .collect::<Vec<_>>()
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("shingle_hash", size), &size, |b, _| {
+            b.iter(|| black_box(&seq).windows(5).map(shingle_hash).collect::<Vec<_>>())
+        });
+        // Sorted, distinct hashes as stored on windows
+        group.bench_with_input(BenchmarkId::new("window_hashes", size), &size, |b, _| {
+            b.iter(|| compute_shingle_hashes(black_box(&window), 5, ShingleKind::Contiguous, MatchMode::Lemma, None))
+        });
     }
 
     group.finish();
@@ -201,6 +258,7 @@ fn bench_merging(c: &mut Criterion) {
                 lemma_matches: 80,
                 gaps: 5,
                 lemma_similarity: 0.8,
+                ..Default::default()
             })
             .collect();
 
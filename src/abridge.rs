@@ -0,0 +1,300 @@
+//! Abridgment detection.
+//!
+//! An abridgment (mukhtaṣar) walks through its source in order, keeping much
+//! of it but compressing each section. Across a comparison this shows up as
+//! edges that cover a large share of book A, run in the same order through
+//! both books, and have target spans shorter than their source spans.
+//!
+//! The ordered backbone is the chain of edges, increasing in both source and
+//! target position, that covers the most source tokens. Out-of-order edges
+//! (topical quotations, repeated formulae) fall outside it. The chain is then
+//! grouped into corresponding sections, giving a mapping table from A to B.
+
+use crate::compare::chrono_lite_timestamp;
+use crate::models::{
+    AbridgmentReport, AbridgmentSection, ComparisonResult, ComparisonResultWithText, ReuseEdge,
+    ReuseEdgeWithText,
+};
+
+/// Share of book A the ordered chain must cover.
+pub const MIN_SOURCE_COVERAGE: f32 = 0.5;
+
+/// Share of all matched source tokens that must lie on the ordered chain.
+pub const MIN_ORDERED_FRACTION: f32 = 0.8;
+
+/// Largest target / source span ratio along the chain that still counts as
+/// compression.
+pub const MAX_SPAN_RATIO: f32 = 0.9;
+
+/// Chain edges this close (in tokens, on both sides) share a section.
+pub const SECTION_GAP: usize = 500;
+
+/// The source and target ranges of one edge.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EdgeSpan {
+    pub id: u64,
+    pub source: (usize, usize),
+    pub target: (usize, usize),
+}
+
+impl From<&ReuseEdge> for EdgeSpan {
+    fn from(edge: &ReuseEdge) -> Self {
+        EdgeSpan {
+            id: edge.id,
+            source: (edge.source_global_start, edge.source_global_end),
+            target: (edge.target_global_start, edge.target_global_end),
+        }
+    }
+}
+
+impl From<&ReuseEdgeWithText> for EdgeSpan {
+    fn from(edge: &ReuseEdgeWithText) -> Self {
+        EdgeSpan {
+            id: edge.id,
+            source: edge.source.global_range,
+            target: edge.target.global_range,
+        }
+    }
+}
+
+/// Abridgment report for a comparison result.
+pub fn detect_abridgment(result: &ComparisonResult) -> AbridgmentReport {
+    let spans: Vec<EdgeSpan> = result.edges.iter().map(EdgeSpan::from).collect();
+    abridgment_report(
+        (result.book_a.id, result.book_a.token_count),
+        (result.book_b.id, result.book_b.token_count),
+        &spans,
+    )
+}
+
+/// Abridgment report for a comparison result with text.
+pub fn detect_abridgment_with_text(result: &ComparisonResultWithText) -> AbridgmentReport {
+    let spans: Vec<EdgeSpan> = result.edges.iter().map(EdgeSpan::from).collect();
+    abridgment_report(
+        (result.book_a.id, result.book_a.token_count),
+        (result.book_b.id, result.book_b.token_count),
+        &spans,
+    )
+}
+
+/// Whether book B reads as an abridgment of book A, with the ordered
+/// section mapping behind the verdict. Books are `(id, token_count)`.
+pub fn abridgment_report(book_a: (u32, u64), book_b: (u32, u64), spans: &[EdgeSpan]) -> AbridgmentReport {
+    let chain: Vec<EdgeSpan> = ordered_chain(spans).into_iter().map(|i| spans[i]).collect();
+
+    let total_source: usize = spans.iter().map(|s| s.source.1 - s.source.0).sum();
+    let chain_source: usize = chain.iter().map(|s| s.source.1 - s.source.0).sum();
+    let chain_target: usize = chain.iter().map(|s| s.target.1 - s.target.0).sum();
+
+    let source_coverage = ratio(union_len(chain.iter().map(|s| s.source)), book_a.1 as usize);
+    let target_coverage = ratio(union_len(chain.iter().map(|s| s.target)), book_b.1 as usize);
+    let ordered_fraction = ratio(chain_source, total_source);
+    let span_ratio = ratio(chain_target, chain_source);
+
+    let is_abridgment = source_coverage >= MIN_SOURCE_COVERAGE
+        && ordered_fraction >= MIN_ORDERED_FRACTION
+        && span_ratio > 0.0
+        && span_ratio <= MAX_SPAN_RATIO;
+
+    AbridgmentReport {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        generated_at: chrono_lite_timestamp(),
+        book_a: book_a.0,
+        book_b: book_b.0,
+        edge_count: spans.len(),
+        chain_edges: chain.len(),
+        source_coverage,
+        target_coverage,
+        ordered_fraction,
+        span_ratio,
+        is_abridgment,
+        sections: group_sections(&chain),
+    }
+}
+
+/// Indices of the edges forming the chain, increasing in both source and
+/// target start, with the most source tokens. Returned in source order.
+pub fn ordered_chain(spans: &[EdgeSpan]) -> Vec<usize> {
+    let mut order: Vec<usize> = (0..spans.len()).collect();
+    order.sort_by_key(|&i| (spans[i].source.0, spans[i].target.0));
+
+    // Rank target starts so "every earlier target" is a prefix query
+    let mut targets: Vec<usize> = spans.iter().map(|s| s.target.0).collect();
+    targets.sort_unstable();
+    targets.dedup();
+
+    // best[i]: most source tokens on a chain ending at edge i
+    let mut best = vec![0usize; spans.len()];
+    let mut prev: Vec<Option<usize>> = vec![None; spans.len()];
+    let mut tree = MaxTree::new(targets.len());
+
+    for &i in &order {
+        let rank = targets.binary_search(&spans[i].target.0).unwrap();
+        let (before, from) = tree.prefix_max(rank);
+        best[i] = before + (spans[i].source.1 - spans[i].source.0);
+        prev[i] = from;
+        tree.update(rank, best[i], i);
+    }
+
+    let Some(mut end) = (0..spans.len()).max_by_key(|&i| best[i]) else {
+        return Vec::new();
+    };
+    let mut chain = vec![end];
+    while let Some(p) = prev[end] {
+        chain.push(p);
+        end = p;
+    }
+    chain.reverse();
+    chain
+}
+
+/// Group chain edges into sections, closing a section at a gap wider than
+/// [`SECTION_GAP`] on either side.
+fn group_sections(chain: &[EdgeSpan]) -> Vec<AbridgmentSection> {
+    let mut sections: Vec<AbridgmentSection> = Vec::new();
+    let mut matched = (0usize, 0usize);
+
+    for span in chain {
+        let joins = sections.last().is_some_and(|s| {
+            span.source.0 <= s.source_end + SECTION_GAP && span.target.0 <= s.target_end + SECTION_GAP
+        });
+        if !joins {
+            if let Some(last) = sections.last_mut() {
+                last.span_ratio = ratio(matched.1, matched.0);
+            }
+            matched = (0, 0);
+            sections.push(AbridgmentSection {
+                source_start: span.source.0,
+                source_end: span.source.1,
+                target_start: span.target.0,
+                target_end: span.target.1,
+                edge_ids: Vec::new(),
+                span_ratio: 0.0,
+            });
+        }
+        let section = sections.last_mut().unwrap();
+        section.source_end = section.source_end.max(span.source.1);
+        section.target_end = section.target_end.max(span.target.1);
+        section.edge_ids.push(span.id);
+        matched.0 += span.source.1 - span.source.0;
+        matched.1 += span.target.1 - span.target.0;
+    }
+    if let Some(last) = sections.last_mut() {
+        last.span_ratio = ratio(matched.1, matched.0);
+    }
+    sections
+}
+
+/// Total length of a union of ranges sorted by start.
+fn union_len(ranges: impl Iterator<Item = (usize, usize)>) -> usize {
+    let mut total = 0;
+    let mut covered_to = 0;
+    for (start, end) in ranges {
+        let start = start.max(covered_to);
+        if end > start {
+            total += end - start;
+            covered_to = end;
+        }
+    }
+    total
+}
+
+fn ratio(numerator: usize, denominator: usize) -> f32 {
+    if denominator > 0 {
+        numerator as f32 / denominator as f32
+    } else {
+        0.0
+    }
+}
+
+/// Fenwick tree over ranks answering "best value (and its edge) at a rank
+/// strictly below r".
+struct MaxTree {
+    nodes: Vec<(usize, Option<usize>)>,
+}
+
+impl MaxTree {
+    fn new(len: usize) -> Self {
+        MaxTree {
+            nodes: vec![(0, None); len + 1],
+        }
+    }
+
+    fn prefix_max(&self, rank: usize) -> (usize, Option<usize>) {
+        let mut best = (0, None);
+        let mut pos = rank;
+        while pos > 0 {
+            if self.nodes[pos].0 > best.0 {
+                best = self.nodes[pos];
+            }
+            pos &= pos - 1;
+        }
+        best
+    }
+
+    fn update(&mut self, rank: usize, value: usize, edge: usize) {
+        let mut pos = rank + 1;
+        while pos < self.nodes.len() {
+            if value > self.nodes[pos].0 {
+                self.nodes[pos] = (value, Some(edge));
+            }
+            pos += pos & pos.wrapping_neg();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(id: u64, source: usize, source_len: usize, target: usize, target_len: usize) -> EdgeSpan {
+        EdgeSpan {
+            id,
+            source: (source, source + source_len),
+            target: (target, target + target_len),
+        }
+    }
+
+    #[test]
+    fn test_ordered_chain_skips_scattered_edges() {
+        let spans = vec![
+            span(1, 0, 100, 0, 60),
+            span(2, 200, 100, 5000, 60), // Quoted far out of order
+            span(3, 400, 100, 100, 60),
+            span(4, 600, 100, 200, 60),
+        ];
+        let chain: Vec<u64> = ordered_chain(&spans).iter().map(|&i| spans[i].id).collect();
+        assert_eq!(chain, vec![1, 3, 4]);
+        assert!(ordered_chain(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_systematic_abridgment() {
+        // Ten sections of A, each kept at 60% in B, in order, two chapters apart
+        let spans: Vec<EdgeSpan> = (0..10)
+            .map(|i| span(i as u64, i * 180 + (i / 5) * 1000, 150, i * 100 + (i / 5) * 1000, 90))
+            .collect();
+        let report = abridgment_report((1, 3000), (2, 2000), &spans);
+
+        assert!(report.is_abridgment);
+        assert_eq!(report.chain_edges, 10);
+        assert!((report.source_coverage - 0.5).abs() < 1e-6);
+        assert!((report.span_ratio - 0.6).abs() < 1e-6);
+        assert_eq!(report.ordered_fraction, 1.0);
+        assert_eq!(report.sections.len(), 2);
+        assert_eq!(report.sections[0].edge_ids, vec![0, 1, 2, 3, 4]);
+        assert_eq!((report.sections[1].source_start, report.sections[1].target_start), (1900, 1500));
+    }
+
+    #[test]
+    fn test_scattered_quotation_is_not_abridgment() {
+        // Verbatim quotations in reverse order
+        let spans: Vec<EdgeSpan> = (0..10)
+            .map(|i| span(i as u64, i * 300, 150, (9 - i) * 300, 150))
+            .collect();
+        let report = abridgment_report((1, 3000), (2, 3000), &spans);
+        assert!(!report.is_abridgment);
+        assert_eq!(report.chain_edges, 1);
+        assert!(report.ordered_fraction < MIN_ORDERED_FRACTION);
+    }
+}
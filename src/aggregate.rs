@@ -0,0 +1,295 @@
+//! Merging the output directories of a sharded batch run.
+//!
+//! Each shard of a split run (`BatchOptions::shard`) writes a complete batch
+//! layout of its own. `aggregate_batches` checks that the directories belong
+//! to the same run, copies their pair directories into one output directory
+//! and writes a merged manifest, so the result is laid out like an unsharded
+//! run, plus corpus-level outputs:
+//!
+//! ```text
+//! <output_dir>/
+//!   manifest.json
+//!   corpus_summary.json
+//!   network.csv
+//!   results/{a}_{b}/...
+//! ```
+//!
+//! `network.csv` is a weighted, undirected edge list of the books that share
+//! material, ready for a graph tool or a clustering pass.
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::batch::{pair_dir, record_files_intact, MANIFEST_FILE};
+use crate::compare::chrono_lite_timestamp;
+use crate::models::{BatchManifest, CorpusSummary, NetworkEdge, PairRecord, PairStatus};
+use crate::output::{
+    read_manifest_file, write_corpus_summary_file, write_manifest_file, write_network_csv_file, OutputError,
+};
+
+/// Name of the corpus summary file.
+pub const CORPUS_SUMMARY_FILE: &str = "corpus_summary.json";
+/// Name of the book network edge list.
+pub const NETWORK_FILE: &str = "network.csv";
+
+#[derive(Error, Debug)]
+pub enum AggregateError {
+    #[error("no input directories given")]
+    NoInputs,
+    #[error("{}: cannot read {}: {source}", dir.display(), MANIFEST_FILE)]
+    Manifest { dir: PathBuf, source: OutputError },
+    #[error("{}: parameter fingerprint {found} differs from {expected}", dir.display())]
+    FingerprintMismatch {
+        dir: PathBuf,
+        expected: String,
+        found: String,
+    },
+    #[error("{}: not a different shard of the same split as the other directories", dir.display())]
+    ShardMismatch { dir: PathBuf },
+    #[error("{}: book {book_id} has different content than in another directory", dir.display())]
+    BookHashMismatch { dir: PathBuf, book_id: u32 },
+    #[error("pair {book_a}-{book_b} appears in more than one directory")]
+    DuplicatePair { book_a: u32, book_b: u32 },
+    #[error("{}: files of pair {book_a}-{book_b} are missing or do not match the manifest", dir.display())]
+    CorruptPair { dir: PathBuf, book_a: u32, book_b: u32 },
+    #[error(transparent)]
+    Output(#[from] OutputError),
+}
+
+/// Merge the batch output directories in `input_dirs` into `output_dir`.
+///
+/// All inputs must have the same parameter fingerprint, agree on the content
+/// hash of every book they share, and (if sharded) be distinct shards of the
+/// same split. Every compared pair's files are checked against their recorded
+/// checksums before being copied. Missing shards are not an error; they are
+/// listed in the corpus summary.
+pub fn aggregate_batches(
+    input_dirs: &[PathBuf],
+    output_dir: &Path,
+) -> Result<(BatchManifest, CorpusSummary), AggregateError> {
+    let mut manifests = Vec::with_capacity(input_dirs.len());
+    for dir in input_dirs {
+        let manifest = read_manifest_file(&dir.join(MANIFEST_FILE)).map_err(|source| AggregateError::Manifest {
+            dir: dir.clone(),
+            source,
+        })?;
+        manifests.push((dir, manifest));
+    }
+    let Some((_, first)) = manifests.first() else {
+        return Err(AggregateError::NoInputs);
+    };
+    let params_fingerprint = first.params_fingerprint.clone();
+    let shard_count = first.shard.map(|s| s.count);
+
+    let mut shard_indices = BTreeSet::new();
+    let mut book_hashes = BTreeMap::new();
+    for (dir, manifest) in &manifests {
+        if manifest.params_fingerprint != params_fingerprint {
+            return Err(AggregateError::FingerprintMismatch {
+                dir: dir.to_path_buf(),
+                expected: params_fingerprint,
+                found: manifest.params_fingerprint.clone(),
+            });
+        }
+        if manifest.shard.map(|s| s.count) != shard_count
+            || manifest.shard.is_some_and(|s| !shard_indices.insert(s.index))
+        {
+            return Err(AggregateError::ShardMismatch { dir: dir.to_path_buf() });
+        }
+        for (&book_id, hash) in &manifest.book_hashes {
+            if book_hashes.insert(book_id, hash.clone()).is_some_and(|h| h != *hash) {
+                return Err(AggregateError::BookHashMismatch {
+                    dir: dir.to_path_buf(),
+                    book_id,
+                });
+            }
+        }
+    }
+
+    std::fs::create_dir_all(output_dir).map_err(OutputError::from)?;
+    let output_canonical = output_dir.canonicalize().map_err(OutputError::from)?;
+
+    let mut seen = HashSet::new();
+    let mut pairs = Vec::new();
+    for (dir, manifest) in &manifests {
+        // Merging into one of the inputs leaves that input's files in place
+        let in_place = dir.canonicalize().is_ok_and(|d| d == output_canonical);
+        for record in &manifest.pairs {
+            if !seen.insert((record.book_a, record.book_b)) {
+                return Err(AggregateError::DuplicatePair {
+                    book_a: record.book_a,
+                    book_b: record.book_b,
+                });
+            }
+            if record.status.is_compared() {
+                if !record_files_intact(dir, record) {
+                    return Err(AggregateError::CorruptPair {
+                        dir: dir.to_path_buf(),
+                        book_a: record.book_a,
+                        book_b: record.book_b,
+                    });
+                }
+                if !in_place {
+                    copy_pair_files(dir, record, output_dir)?;
+                }
+            }
+            let mut record = record.clone();
+            if record.output.is_some() {
+                record.output = Some(pair_dir(record.book_a, record.book_b));
+            }
+            pairs.push(record);
+        }
+    }
+    pairs.sort_by_key(|p| (p.book_a, p.book_b));
+
+    // Shards run on different machines have different copies of corpus.db;
+    // the book hashes checked above are what ties them together
+    let db_fingerprint = if manifests.iter().all(|(_, m)| m.db_fingerprint == first.db_fingerprint) {
+        first.db_fingerprint.clone()
+    } else {
+        String::new()
+    };
+
+    let manifest = BatchManifest {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        generated_at: chrono_lite_timestamp(),
+        parameters: first.parameters.clone(),
+        params_fingerprint,
+        db_fingerprint,
+        book_hashes,
+        min_prescan_overlap: first.min_prescan_overlap,
+        shard: None,
+        pairs,
+    };
+
+    let mut summary = summarize_corpus(&manifest);
+    summary.shard_count = shard_count;
+    summary.missing_shards = shard_count
+        .map(|count| (1..=count).filter(|i| !shard_indices.contains(i)).collect())
+        .unwrap_or_default();
+
+    write_manifest_file(&manifest, &output_dir.join(MANIFEST_FILE))?;
+    write_corpus_summary_file(&summary, &output_dir.join(CORPUS_SUMMARY_FILE))?;
+    write_network_csv_file(&network_edges(&manifest), &output_dir.join(NETWORK_FILE))?;
+
+    Ok((manifest, summary))
+}
+
+/// Copy a compared pair's files into the same pair directory under `output_dir`.
+fn copy_pair_files(input_dir: &Path, record: &PairRecord, output_dir: &Path) -> Result<(), OutputError> {
+    let Some(source) = &record.output else {
+        return Ok(());
+    };
+    let target = output_dir.join(pair_dir(record.book_a, record.book_b));
+    std::fs::create_dir_all(&target)?;
+    for file in record.checksums.keys() {
+        std::fs::copy(input_dir.join(source).join(file), target.join(file))?;
+    }
+    Ok(())
+}
+
+/// Corpus-level totals over every pair in a manifest.
+pub fn summarize_corpus(manifest: &BatchManifest) -> CorpusSummary {
+    let summaries = || manifest.pairs.iter().filter_map(|p| p.summary.as_ref());
+    let count = |status| manifest.pairs.iter().filter(|p| p.status == status).count();
+
+    CorpusSummary {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        generated_at: chrono_lite_timestamp(),
+        params_fingerprint: manifest.params_fingerprint.clone(),
+        shard_count: manifest.shard.map(|s| s.count),
+        missing_shards: Vec::new(),
+        pair_count: manifest.pairs.len(),
+        compared_pairs: manifest.pairs.iter().filter(|p| p.status.is_compared()).count(),
+        skipped_pairs: count(PairStatus::SkippedPrescan),
+        timeout_pairs: count(PairStatus::Timeout),
+        error_pairs: count(PairStatus::Error),
+        pairs_with_reuse: manifest.pairs.iter().filter(|p| p.edge_count > 0).count(),
+        total_edges: manifest.pairs.iter().map(|p| p.edge_count).sum(),
+        total_aligned_tokens: summaries().map(|s| s.total_aligned_tokens).sum(),
+        aborted_alignments: summaries().map(|s| s.aborted_alignments).sum(),
+    }
+}
+
+/// One network edge per compared pair with at least one reuse edge, weighted
+/// by aligned tokens.
+pub fn network_edges(manifest: &BatchManifest) -> Vec<NetworkEdge> {
+    manifest
+        .pairs
+        .iter()
+        .filter(|p| p.edge_count > 0)
+        .filter_map(|p| Some(NetworkEdge::from_summary(p.book_a, p.book_b, p.summary.as_ref()?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ComparisonParams, ComparisonSummary};
+
+    fn record(book_a: u32, book_b: u32, edges: usize) -> PairRecord {
+        PairRecord {
+            book_a,
+            book_b,
+            status: PairStatus::Timeout,
+            prescan_overlap: None,
+            edge_count: edges,
+            output: Some(pair_dir(book_a, book_b)),
+            summary: Some(ComparisonSummary {
+                edge_count: edges,
+                total_aligned_tokens: edges * 100,
+                book_a_coverage: 0.1,
+                book_b_coverage: 0.2,
+                avg_similarity: 0.9,
+                avg_weighted_similarity: 0.9,
+                book_a_coverage_ci: None,
+                book_b_coverage_ci: None,
+                order_tau: 1.0,
+                aborted_alignments: 1,
+                ..Default::default()
+            }),
+            checksums: BTreeMap::new(),
+            cached: false,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_summarize_corpus_and_network() {
+        let mut skipped = record(2, 3, 0);
+        skipped.status = PairStatus::SkippedPrescan;
+        skipped.output = None;
+        skipped.summary = None;
+        let mut failed = skipped.clone();
+        failed.book_a = 4;
+        failed.status = PairStatus::Error;
+        failed.error = Some("Book not found: 4".to_string());
+        let manifest = BatchManifest {
+            version: String::new(),
+            generated_at: String::new(),
+            parameters: ComparisonParams::default(),
+            params_fingerprint: "abc".to_string(),
+            db_fingerprint: String::new(),
+            book_hashes: BTreeMap::new(),
+            min_prescan_overlap: Some(0.1),
+            shard: None,
+            pairs: vec![record(1, 2, 3), record(1, 3, 0), skipped, failed],
+        };
+
+        let summary = summarize_corpus(&manifest);
+        assert_eq!(summary.pair_count, 4);
+        assert_eq!(summary.compared_pairs, 2);
+        assert_eq!(summary.skipped_pairs, 1);
+        assert_eq!(summary.timeout_pairs, 2);
+        assert_eq!(summary.error_pairs, 1);
+        assert_eq!(summary.pairs_with_reuse, 1);
+        assert_eq!(summary.total_edges, 3);
+        assert_eq!(summary.total_aligned_tokens, 300);
+        assert_eq!(summary.aborted_alignments, 2);
+
+        let network = network_edges(&manifest);
+        assert_eq!(network.len(), 1);
+        assert_eq!((network[0].source, network[0].target, network[0].weight), (1, 2, 300));
+    }
+}
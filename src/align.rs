@@ -5,6 +5,8 @@
 
 use crate::models::{Alignment, ComparisonParams, MatchMode};
 use std::collections::HashSet;
+use std::ops::Range;
+use std::time::{Duration, Instant};
 
 /// Smith-Waterman local alignment on lemma ID sequences.
 ///
@@ -45,17 +47,62 @@ pub fn align_sequences(
     roots_b: &[u32],
     params: &ComparisonParams,
 ) -> Option<Alignment> {
+    align_sequences_with_alternatives(lemmas_a, lemmas_b, roots_a, roots_b, &[], &[], params)
+}
+
+/// Like `align_sequences`, but two positions also count as a lemma match when any
+/// alternative analysis of one coincides with the lemma or an alternative of the other.
+///
+/// `alts_a` / `alts_b` hold alternative lemma IDs per position; pass empty slices
+/// when no alternatives are available.
+///
+/// An alignment abandoned at the limits in `params` returns None; use
+/// [`try_align_sequences_with_alternatives`] to tell it apart from no match.
+#[inline]
+pub fn align_sequences_with_alternatives(
+    lemmas_a: &[u32],
+    lemmas_b: &[u32],
+    roots_a: &[u32],
+    roots_b: &[u32],
+    alts_a: &[Vec<u32>],
+    alts_b: &[Vec<u32>],
+    params: &ComparisonParams,
+) -> Option<Alignment> {
+    try_align_sequences_with_alternatives(lemmas_a, lemmas_b, roots_a, roots_b, alts_a, alts_b, params)
+        .ok()
+        .flatten()
+}
+
+/// `align_sequences_with_alternatives`, failing with [`AlignmentAborted`] when
+/// the alignment exceeds `max_alignment_cells` or `alignment_timeout_ms`.
+pub fn try_align_sequences_with_alternatives(
+    lemmas_a: &[u32],
+    lemmas_b: &[u32],
+    roots_a: &[u32],
+    roots_b: &[u32],
+    alts_a: &[Vec<u32>],
+    alts_b: &[Vec<u32>],
+    params: &ComparisonParams,
+) -> Result<Option<Alignment>, AlignmentAborted> {
     let n = lemmas_a.len();
     let m = lemmas_b.len();
 
     if n == 0 || m == 0 {
-        return None;
+        return Ok(None);
+    }
+
+    check_cells(n, m, params)?;
+    let deadline = Deadline::start(params);
+
+    if (n + 1) * (m + 1) > LINEAR_SPACE_MIN_CELLS {
+        return linear_space(lemmas_a, lemmas_b, roots_a, roots_b, alts_a, alts_b, None, params, &deadline);
     }
 
     // DP matrix - use flat Vec for cache efficiency
     // H[i][j] = H[i * (m+1) + j]
     let width = m + 1;
     let mut h = vec![0i32; (n + 1) * width];
+    let (stop_a, stop_b) = (stop_mask(lemmas_a, params), stop_mask(lemmas_b, params));
 
     // Track max score position for traceback
     let mut max_score = 0i32;
@@ -64,19 +111,25 @@ pub fn align_sequences(
 
     // Fill DP matrix
     for i in 1..=n {
+        deadline.check()?;
         let lemma_a = lemmas_a[i - 1];
         let root_a = if i - 1 < roots_a.len() { roots_a[i - 1] } else { 0 };
+        let alt_a = alternatives_at(alts_a, i - 1);
         let row_offset = i * width;
         let prev_row_offset = (i - 1) * width;
+        let mut row_max = 0i32;
 
         for j in 1..=m {
             let lemma_b = lemmas_b[j - 1];
             let root_b = if j - 1 < roots_b.len() { roots_b[j - 1] } else { 0 };
+            let shared = shared_lemma(lemma_a, lemma_b, alt_a, alternatives_at(alts_b, j - 1));
 
             // Calculate match/mismatch score based on mode
-            let match_score = calculate_match_score(
-                lemma_a, lemma_b, root_a, root_b, params
-            );
+            let match_score = if is_neutral(&stop_a, &stop_b, i - 1, j - 1) {
+                0
+            } else {
+                calculate_match_score(shared, root_a, root_b, params)
+            };
 
             // Compute cell value: max of 0, diagonal+match, up+gap, left+gap
             let diagonal = h[prev_row_offset + (j - 1)] + match_score;
@@ -85,6 +138,7 @@ pub fn align_sequences(
 
             let score = 0.max(diagonal).max(up).max(left);
             h[row_offset + j] = score;
+            row_max = row_max.max(score);
 
             if score > max_score {
                 max_score = score;
@@ -92,16 +146,19 @@ pub fn align_sequences(
                 max_j = j;
             }
         }
+        if xdrop_reached(row_max, max_score, params) {
+            break;
+        }
     }
 
     // Early exit if no significant alignment
     let min_score_threshold = match params.mode {
-        MatchMode::Lemma => (params.min_length as i32 * params.lemma_score) / 2,
+        MatchMode::Lemma | MatchMode::Surface => (params.min_length as i32 * params.lemma_score) / 2,
         MatchMode::Root => (params.min_length as i32 * params.lemma_score) / 2,
         MatchMode::Combined => (params.min_length as i32 * params.lemma_score) / 2,
     };
     if max_score < min_score_threshold {
-        return None;
+        return Ok(None);
     }
 
     // Traceback to recover alignment
@@ -130,16 +187,26 @@ pub fn align_sequences(
         let root_a = if i - 1 < roots_a.len() { roots_a[i - 1] } else { 0 };
         let root_b = if j - 1 < roots_b.len() { roots_b[j - 1] } else { 0 };
 
-        let match_score = calculate_match_score(lemma_a, lemma_b, root_a, root_b, params);
+        let shared = shared_lemma(
+            lemma_a,
+            lemma_b,
+            alternatives_at(alts_a, i - 1),
+            alternatives_at(alts_b, j - 1),
+        );
+        let match_score = if is_neutral(&stop_a, &stop_b, i - 1, j - 1) {
+            0
+        } else {
+            calculate_match_score(shared, root_a, root_b, params)
+        };
 
         if current == diagonal + match_score {
             // Match or mismatch - record the pair
             aligned_pairs.push((i - 1, j - 1));
 
             // Track what kind of match it was
-            if lemma_a == lemma_b {
+            if let Some(lemma) = shared {
                 lemma_matches += 1;
-                unique_matched_lemmas.insert(lemma_a);
+                unique_matched_lemmas.insert(lemma);
             } else if root_a == root_b && root_a != 0 {
                 root_only_matches += 1;
             } else {
@@ -165,12 +232,17 @@ pub fn align_sequences(
 
     // Check minimum length
     if aligned_pairs.len() < params.min_length {
-        return None;
+        return Ok(None);
+    }
+
+    // Check minimum number of actually matched tokens
+    if !meets_min_matched_tokens(lemma_matches, root_only_matches, params) {
+        return Ok(None);
     }
 
     // Check minimum similarity based on mode
     let similarity = match params.mode {
-        MatchMode::Lemma => lemma_matches as f32 / aligned_pairs.len() as f32,
+        MatchMode::Lemma | MatchMode::Surface => lemma_matches as f32 / aligned_pairs.len() as f32,
         MatchMode::Root => {
             // In root mode, count root matches (including lemma matches which share roots)
             let root_matches = count_root_matches(&aligned_pairs, lemmas_a, lemmas_b, roots_a, roots_b);
@@ -183,7 +255,7 @@ pub fn align_sequences(
     };
 
     if similarity < params.min_similarity {
-        return None;
+        return Ok(None);
     }
 
     // Find start/end positions
@@ -197,7 +269,7 @@ pub fn align_sequences(
         0.0
     };
 
-    Some(Alignment {
+    Ok(Some(Alignment {
         start_a,
         end_a: end_a + 1,
         start_b,
@@ -210,21 +282,22 @@ pub fn align_sequences(
         score: max_score,
         match_weight_sum: 0.0,
         lexical_diversity,
-    })
+    }))
 }
 
 /// Calculate the match score for a pair of positions based on matching mode.
+/// Surface mode scores as lemma mode: the comparison puts surface IDs in
+/// place of the lemma IDs beforehand.
 #[inline(always)]
 fn calculate_match_score(
-    lemma_a: u32,
-    lemma_b: u32,
+    shared: Option<u32>,
     root_a: u32,
     root_b: u32,
     params: &ComparisonParams,
 ) -> i32 {
     match params.mode {
-        MatchMode::Lemma => {
-            if lemma_a == lemma_b {
+        MatchMode::Lemma | MatchMode::Surface => {
+            if shared.is_some() {
                 params.lemma_score
             } else {
                 params.mismatch_penalty
@@ -238,7 +311,7 @@ fn calculate_match_score(
             }
         }
         MatchMode::Combined => {
-            if lemma_a == lemma_b {
+            if shared.is_some() {
                 params.lemma_score
             } else if root_a == root_b && root_a != 0 {
                 params.root_score
@@ -249,6 +322,62 @@ fn calculate_match_score(
     }
 }
 
+/// Whether an alignment has at least `params.min_matched_tokens` real matches.
+///
+/// Substitutions and gaps don't count. Root-only matches count unless the mode
+/// is lemma-only.
+#[inline]
+fn meets_min_matched_tokens(lemma_matches: u32, root_only_matches: u32, params: &ComparisonParams) -> bool {
+    let matched = match params.mode {
+        MatchMode::Lemma | MatchMode::Surface => lemma_matches,
+        MatchMode::Root | MatchMode::Combined => lemma_matches + root_only_matches,
+    };
+    params
+        .min_matched_tokens
+        .is_none_or(|min| matched as usize >= min)
+}
+
+/// Alternatives for one position, or an empty slice if none were supplied.
+#[inline(always)]
+fn alternatives_at(alts: &[Vec<u32>], idx: usize) -> &[u32] {
+    alts.get(idx).map_or(&[], |v| v.as_slice())
+}
+
+/// Per-position flags marking `params.stop_lemmas`, or empty when none are set.
+fn stop_mask(lemmas: &[u32], params: &ComparisonParams) -> Vec<bool> {
+    match &params.stop_lemmas {
+        Some(stop) if !stop.is_empty() => lemmas.iter().map(|l| stop.contains(l)).collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Whether either position holds a stop lemma; such pairs score zero.
+#[inline(always)]
+fn is_neutral(stop_a: &[bool], stop_b: &[bool], i: usize, j: usize) -> bool {
+    stop_a.get(i).copied().unwrap_or(false) || stop_b.get(j).copied().unwrap_or(false)
+}
+
+/// The lemma two positions have in common, if any.
+///
+/// Primary lemmas are compared first; alternative analyses are only consulted
+/// when present, so the common unambiguous case stays a single comparison.
+#[inline(always)]
+fn shared_lemma(lemma_a: u32, lemma_b: u32, alt_a: &[u32], alt_b: &[u32]) -> Option<u32> {
+    if lemma_a == lemma_b {
+        return Some(lemma_a);
+    }
+    if alt_a.is_empty() && alt_b.is_empty() {
+        return None;
+    }
+    if alt_b.contains(&lemma_a) {
+        return Some(lemma_a);
+    }
+    alt_a
+        .iter()
+        .copied()
+        .find(|&lemma| lemma == lemma_b || alt_b.contains(&lemma))
+}
+
 /// Count root matches in aligned pairs (including lemma matches that share roots).
 #[inline]
 fn count_root_matches(
@@ -268,7 +397,974 @@ fn count_root_matches(
         .count() as u32
 }
 
-/// Banded Smith-Waterman for even faster alignment.
+/// Banded Smith-Waterman for even faster alignment.
+///
+/// Only cells within `band_width` of the main diagonal are filled, which is
+/// sufficient for near-verbatim reuse where the two windows are roughly in
+/// register. Cells outside the band are treated as zero.
+pub fn align_lemma_sequences_banded(
+    seq_a: &[u32],
+    seq_b: &[u32],
+    params: &ComparisonParams,
+    band_width: usize,
+) -> Option<Alignment> {
+    let n = seq_a.len();
+    let m = seq_b.len();
+
+    if n == 0 || m == 0 {
+        return None;
+    }
+
+    let width = m + 1;
+    let mut h = vec![0i32; (n + 1) * width];
+
+    let mut max_score = 0i32;
+    let mut max_i = 0usize;
+    let mut max_j = 0usize;
+
+    for i in 1..=n {
+        let j_start = i.saturating_sub(band_width).max(1);
+        let j_end = (i + band_width).min(m);
+        let row_offset = i * width;
+        let prev_row_offset = (i - 1) * width;
+        let mut row_max = 0i32;
+
+        for j in j_start..=j_end {
+            let match_score = if seq_a[i - 1] == seq_b[j - 1] {
+                params.lemma_score
+            } else {
+                params.mismatch_penalty
+            };
+
+            let diagonal = h[prev_row_offset + (j - 1)] + match_score;
+            let up = h[prev_row_offset + j] + params.gap_penalty;
+            let left = h[row_offset + (j - 1)] + params.gap_penalty;
+
+            let score = 0.max(diagonal).max(up).max(left);
+            h[row_offset + j] = score;
+            row_max = row_max.max(score);
+
+            if score > max_score {
+                max_score = score;
+                max_i = i;
+                max_j = j;
+            }
+        }
+        if xdrop_reached(row_max, max_score, params) {
+            break;
+        }
+    }
+
+    let min_score_threshold = (params.min_length as i32 * params.lemma_score) / 2;
+    if max_score < min_score_threshold {
+        return None;
+    }
+
+    // Traceback (cells outside the band are zero, so the walk stays inside it)
+    let mut aligned_pairs = Vec::with_capacity(n.min(m));
+    let mut i = max_i;
+    let mut j = max_j;
+    let mut gaps = 0u32;
+    let mut lemma_matches = 0u32;
+    let mut substitutions = 0u32;
+    let mut unique_matched_lemmas: HashSet<u32> = HashSet::new();
+
+    while i > 0 && j > 0 && h[i * width + j] > 0 {
+        let current = h[i * width + j];
+        let diagonal = h[(i - 1) * width + (j - 1)];
+        let up = h[(i - 1) * width + j];
+
+        let is_match = seq_a[i - 1] == seq_b[j - 1];
+        let match_score = if is_match {
+            params.lemma_score
+        } else {
+            params.mismatch_penalty
+        };
+
+        if current == diagonal + match_score {
+            aligned_pairs.push((i - 1, j - 1));
+            if is_match {
+                lemma_matches += 1;
+                unique_matched_lemmas.insert(seq_a[i - 1]);
+            } else {
+                substitutions += 1;
+            }
+            i -= 1;
+            j -= 1;
+        } else if current == up + params.gap_penalty {
+            gaps += 1;
+            i -= 1;
+        } else {
+            gaps += 1;
+            j -= 1;
+        }
+    }
+
+    aligned_pairs.reverse();
+
+    if aligned_pairs.len() < params.min_length {
+        return None;
+    }
+
+    if !meets_min_matched_tokens(lemma_matches, 0, params) {
+        return None;
+    }
+
+    let similarity = lemma_matches as f32 / aligned_pairs.len() as f32;
+    if similarity < params.min_similarity {
+        return None;
+    }
+
+    let (start_a, start_b) = aligned_pairs.first().copied().unwrap_or((0, 0));
+    let (end_a, end_b) = aligned_pairs.last().copied().unwrap_or((0, 0));
+
+    let lexical_diversity = if lemma_matches > 0 {
+        unique_matched_lemmas.len() as f32 / lemma_matches as f32
+    } else {
+        0.0
+    };
+
+    Some(Alignment {
+        start_a,
+        end_a: end_a + 1,
+        start_b,
+        end_b: end_b + 1,
+        aligned_pairs,
+        lemma_matches,
+        substitutions,
+        root_only_matches: 0,
+        gaps,
+        score: max_score,
+        match_weight_sum: 0.0,
+        lexical_diversity,
+    })
+}
+
+/// Cheap pre-check: do two sequences share at least `min_shared` distinct lemmas?
+///
+/// Useful for rejecting hopeless pairs before running the full DP.
+pub fn quick_similarity_check(seq_a: &[u32], seq_b: &[u32], min_shared: usize) -> bool {
+    let set_a: HashSet<u32> = seq_a.iter().copied().collect();
+    let shared = seq_b
+        .iter()
+        .copied()
+        .collect::<HashSet<u32>>()
+        .intersection(&set_a)
+        .count();
+    shared >= min_shared
+}
+
+
+/// Smith-Waterman local alignment with document-internal IDF weighting.
+///
+/// This version uses per-book lemma weights to influence alignment scoring.
+/// Rare lemmas contribute more to alignment than common lemmas.
+///
+/// # Arguments
+/// * `lemmas_a` - Lemma IDs for sequence A
+/// * `lemmas_b` - Lemma IDs for sequence B
+/// * `roots_a` - Root IDs for sequence A (0 = no root)
+/// * `roots_b` - Root IDs for sequence B (0 = no root)
+/// * `weights_a` - IDF weights for book A (indexed by lemma ID)
+/// * `weights_b` - IDF weights for book B (indexed by lemma ID)
+/// * `params` - Comparison parameters including match mode
+#[inline]
+pub fn align_sequences_weighted(
+    lemmas_a: &[u32],
+    lemmas_b: &[u32],
+    roots_a: &[u32],
+    roots_b: &[u32],
+    weights_a: &[f32],
+    weights_b: &[f32],
+    params: &ComparisonParams,
+) -> Option<Alignment> {
+    align_sequences_weighted_with_alternatives(
+        lemmas_a, lemmas_b, roots_a, roots_b, &[], &[], weights_a, weights_b, params,
+    )
+}
+
+/// IDF-weighted alignment that also accepts alternative lemma analyses per position.
+/// See `align_sequences_with_alternatives`.
+#[inline]
+#[allow(clippy::too_many_arguments)]
+pub fn align_sequences_weighted_with_alternatives(
+    lemmas_a: &[u32],
+    lemmas_b: &[u32],
+    roots_a: &[u32],
+    roots_b: &[u32],
+    alts_a: &[Vec<u32>],
+    alts_b: &[Vec<u32>],
+    weights_a: &[f32],
+    weights_b: &[f32],
+    params: &ComparisonParams,
+) -> Option<Alignment> {
+    try_align_sequences_weighted_with_alternatives(
+        lemmas_a, lemmas_b, roots_a, roots_b, alts_a, alts_b, weights_a, weights_b, params,
+    )
+    .ok()
+    .flatten()
+}
+
+/// `align_sequences_weighted_with_alternatives`, failing with
+/// [`AlignmentAborted`] at the limits in `params`.
+#[allow(clippy::too_many_arguments)]
+pub fn try_align_sequences_weighted_with_alternatives(
+    lemmas_a: &[u32],
+    lemmas_b: &[u32],
+    roots_a: &[u32],
+    roots_b: &[u32],
+    alts_a: &[Vec<u32>],
+    alts_b: &[Vec<u32>],
+    weights_a: &[f32],
+    weights_b: &[f32],
+    params: &ComparisonParams,
+) -> Result<Option<Alignment>, AlignmentAborted> {
+    let n = lemmas_a.len();
+    let m = lemmas_b.len();
+
+    if n == 0 || m == 0 {
+        return Ok(None);
+    }
+
+    check_cells(n, m, params)?;
+    let deadline = Deadline::start(params);
+
+    if (n + 1) * (m + 1) > LINEAR_SPACE_MIN_CELLS {
+        return linear_space(
+            lemmas_a, lemmas_b, roots_a, roots_b, alts_a, alts_b,
+            Some((weights_a, weights_b)), params, &deadline,
+        );
+    }
+
+    // DP matrix - use flat Vec for cache efficiency
+    let width = m + 1;
+    let mut h = vec![0i32; (n + 1) * width];
+    let (stop_a, stop_b) = (stop_mask(lemmas_a, params), stop_mask(lemmas_b, params));
+
+    // Track max score position for traceback
+    let mut max_score = 0i32;
+    let mut max_i = 0usize;
+    let mut max_j = 0usize;
+
+    // Fill DP matrix with weighted scoring
+    for i in 1..=n {
+        deadline.check()?;
+        let lemma_a = lemmas_a[i - 1];
+        let root_a = if i - 1 < roots_a.len() { roots_a[i - 1] } else { 0 };
+        let alt_a = alternatives_at(alts_a, i - 1);
+        let row_offset = i * width;
+        let prev_row_offset = (i - 1) * width;
+        let mut row_max = 0i32;
+
+        for j in 1..=m {
+            let lemma_b = lemmas_b[j - 1];
+            let root_b = if j - 1 < roots_b.len() { roots_b[j - 1] } else { 0 };
+            let shared = shared_lemma(lemma_a, lemma_b, alt_a, alternatives_at(alts_b, j - 1));
+
+            // Calculate weighted match score
+            let match_score = if is_neutral(&stop_a, &stop_b, i - 1, j - 1) {
+                0
+            } else {
+                calculate_weighted_match_score(shared, root_a, root_b, weights_a, weights_b, params)
+            };
+
+            // Compute cell value: max of 0, diagonal+match, up+gap, left+gap
+            let diagonal = h[prev_row_offset + (j - 1)] + match_score;
+            let up = h[prev_row_offset + j] + params.gap_penalty;
+            let left = h[row_offset + (j - 1)] + params.gap_penalty;
+
+            let score = 0.max(diagonal).max(up).max(left);
+            h[row_offset + j] = score;
+            row_max = row_max.max(score);
+
+            if score > max_score {
+                max_score = score;
+                max_i = i;
+                max_j = j;
+            }
+        }
+        if xdrop_reached(row_max, max_score, params) {
+            break;
+        }
+    }
+
+    // Early exit if no significant alignment
+    let min_score_threshold = (params.min_length as i32 * params.lemma_score) / 2;
+    if max_score < min_score_threshold {
+        return Ok(None);
+    }
+
+    // Traceback to recover alignment and compute match_weight_sum
+    let mut aligned_pairs = Vec::with_capacity(n.min(m));
+    let mut i = max_i;
+    let mut j = max_j;
+    let mut gaps = 0u32;
+    let mut lemma_matches = 0u32;
+    let mut substitutions = 0u32;
+    let mut root_only_matches = 0u32;
+    let mut match_weight_sum = 0.0f32;
+    // Track unique matched lemma IDs for lexical diversity calculation
+    let mut unique_matched_lemmas: HashSet<u32> = HashSet::new();
+
+    while i > 0 && j > 0 && h[i * width + j] > 0 {
+        let current = h[i * width + j];
+        let diagonal = h[(i - 1) * width + (j - 1)];
+        let up = h[(i - 1) * width + j];
+
+        let lemma_a = lemmas_a[i - 1];
+        let lemma_b = lemmas_b[j - 1];
+        let root_a = if i - 1 < roots_a.len() { roots_a[i - 1] } else { 0 };
+        let root_b = if j - 1 < roots_b.len() { roots_b[j - 1] } else { 0 };
+
+        let shared = shared_lemma(
+            lemma_a,
+            lemma_b,
+            alternatives_at(alts_a, i - 1),
+            alternatives_at(alts_b, j - 1),
+        );
+        let match_score = if is_neutral(&stop_a, &stop_b, i - 1, j - 1) {
+            0
+        } else {
+            calculate_weighted_match_score(shared, root_a, root_b, weights_a, weights_b, params)
+        };
+
+        if current == diagonal + match_score {
+            // Match or mismatch - record the pair
+            aligned_pairs.push((i - 1, j - 1));
+
+            // Track what kind of match it was
+            if let Some(lemma) = shared {
+                lemma_matches += 1;
+                unique_matched_lemmas.insert(lemma);
+                // Add weight to match_weight_sum: min(weight_A, weight_B)
+                let w_a = get_weight(lemma, weights_a);
+                let w_b = get_weight(lemma, weights_b);
+                match_weight_sum += w_a.min(w_b);
+            } else if root_a == root_b && root_a != 0 {
+                root_only_matches += 1;
+            } else {
+                // Neither lemma nor root matched - this is a substitution
+                substitutions += 1;
+            }
+
+            i -= 1;
+            j -= 1;
+        } else if current == up + params.gap_penalty {
+            // Gap in seq_b
+            gaps += 1;
+            i -= 1;
+        } else {
+            // Gap in seq_a
+            gaps += 1;
+            j -= 1;
+        }
+    }
+
+    // Alignment is built backwards, reverse it
+    aligned_pairs.reverse();
+
+    // Check minimum length
+    if aligned_pairs.len() < params.min_length {
+        return Ok(None);
+    }
+
+    // Check minimum number of actually matched tokens
+    if !meets_min_matched_tokens(lemma_matches, root_only_matches, params) {
+        return Ok(None);
+    }
+
+    // Check minimum similarity based on mode
+    let similarity = match params.mode {
+        MatchMode::Lemma | MatchMode::Surface => lemma_matches as f32 / aligned_pairs.len() as f32,
+        MatchMode::Root => {
+            let root_matches = count_root_matches(&aligned_pairs, lemmas_a, lemmas_b, roots_a, roots_b);
+            root_matches as f32 / aligned_pairs.len() as f32
+        }
+        MatchMode::Combined => {
+            (lemma_matches as f32 + 0.5 * root_only_matches as f32) / aligned_pairs.len() as f32
+        }
+    };
+
+    if similarity < params.min_similarity {
+        return Ok(None);
+    }
+
+    // Find start/end positions
+    let (start_a, start_b) = aligned_pairs.first().copied().unwrap_or((0, 0));
+    let (end_a, end_b) = aligned_pairs.last().copied().unwrap_or((0, 0));
+
+    // Compute lexical diversity: unique matched lemmas / total lemma matches
+    let lexical_diversity = if lemma_matches > 0 {
+        unique_matched_lemmas.len() as f32 / lemma_matches as f32
+    } else {
+        0.0
+    };
+
+    Ok(Some(Alignment {
+        start_a,
+        end_a: end_a + 1,
+        start_b,
+        end_b: end_b + 1,
+        aligned_pairs,
+        lemma_matches,
+        substitutions,
+        root_only_matches,
+        gaps,
+        score: max_score,
+        match_weight_sum,
+        lexical_diversity,
+    }))
+}
+
+/// Calculate weighted match score using document-internal IDF weights.
+#[inline(always)]
+fn calculate_weighted_match_score(
+    shared: Option<u32>,
+    root_a: u32,
+    root_b: u32,
+    weights_a: &[f32],
+    weights_b: &[f32],
+    params: &ComparisonParams,
+) -> i32 {
+    match params.mode {
+        MatchMode::Lemma | MatchMode::Surface => {
+            if let Some(lemma) = shared {
+                // Weight the score by min(weight_A, weight_B)
+                let w_a = get_weight(lemma, weights_a);
+                let w_b = get_weight(lemma, weights_b);
+                let w = w_a.min(w_b);
+                (params.lemma_score as f32 * w) as i32
+            } else {
+                params.mismatch_penalty
+            }
+        }
+        MatchMode::Root => {
+            if root_a == root_b && root_a != 0 {
+                params.lemma_score
+            } else {
+                params.mismatch_penalty
+            }
+        }
+        MatchMode::Combined => {
+            if let Some(lemma) = shared {
+                let w_a = get_weight(lemma, weights_a);
+                let w_b = get_weight(lemma, weights_b);
+                let w = w_a.min(w_b);
+                (params.lemma_score as f32 * w) as i32
+            } else if root_a == root_b && root_a != 0 {
+                params.root_score
+            } else {
+                params.mismatch_penalty
+            }
+        }
+    }
+}
+
+/// Get weight for a lemma ID, with bounds checking and default.
+#[inline(always)]
+fn get_weight(lemma_id: u32, weights: &[f32]) -> f32 {
+    let idx = lemma_id as usize;
+    if idx < weights.len() && weights[idx] > 0.0 {
+        weights[idx]
+    } else {
+        1.0 // Default weight for unknown lemmas
+    }
+}
+
+// ============================================================================
+// Linear-space alignment
+// ============================================================================
+
+/// DP cell count above which the full-matrix aligners switch to
+/// [`align_sequences_linear_space`]. 4M cells is 16 MB of `i32` per aligning
+/// thread; two 2,000-token windows already exceed it.
+pub const LINEAR_SPACE_MIN_CELLS: usize = 4_000_000;
+
+/// One step of an alignment path: an aligned pair or a gap on either side.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    Pair(usize, usize),
+    Gap,
+}
+
+/// Smith-Waterman in linear memory, for very long windows.
+///
+/// Produces the same local alignment score as the full-matrix aligners while
+/// keeping only two DP rows at a time:
+///
+/// 1. A forward score pass finds where the best local alignment ends.
+/// 2. A backward pass anchored at that end finds where it starts.
+/// 3. Hirschberg's divide and conquer recovers the path between the two
+///    as a global alignment, recursing on row midpoints.
+///
+/// This costs roughly three times the time of the full-matrix version, so it
+/// is only used above [`LINEAR_SPACE_MIN_CELLS`]. Where several paths share
+/// the best score, the one recovered may differ from the full traceback.
+///
+/// Pass `weights` to score with IDF weights as in
+/// `align_sequences_weighted_with_alternatives`.
+#[allow(clippy::too_many_arguments)]
+pub fn align_sequences_linear_space(
+    lemmas_a: &[u32],
+    lemmas_b: &[u32],
+    roots_a: &[u32],
+    roots_b: &[u32],
+    alts_a: &[Vec<u32>],
+    alts_b: &[Vec<u32>],
+    weights: Option<(&[f32], &[f32])>,
+    params: &ComparisonParams,
+) -> Option<Alignment> {
+    check_cells(lemmas_a.len(), lemmas_b.len(), params).ok()?;
+    let deadline = Deadline::start(params);
+    linear_space(lemmas_a, lemmas_b, roots_a, roots_b, alts_a, alts_b, weights, params, &deadline)
+        .ok()
+        .flatten()
+}
+
+#[allow(clippy::too_many_arguments)]
+fn linear_space(
+    lemmas_a: &[u32],
+    lemmas_b: &[u32],
+    roots_a: &[u32],
+    roots_b: &[u32],
+    alts_a: &[Vec<u32>],
+    alts_b: &[Vec<u32>],
+    weights: Option<(&[f32], &[f32])>,
+    params: &ComparisonParams,
+    deadline: &Deadline,
+) -> Result<Option<Alignment>, AlignmentAborted> {
+    let n = lemmas_a.len();
+    let m = lemmas_b.len();
+
+    if n == 0 || m == 0 {
+        return Ok(None);
+    }
+
+    let root_at = |roots: &[u32], idx: usize| roots.get(idx).copied().unwrap_or(0);
+    let shared_at = |i: usize, j: usize| {
+        shared_lemma(
+            lemmas_a[i],
+            lemmas_b[j],
+            alternatives_at(alts_a, i),
+            alternatives_at(alts_b, j),
+        )
+    };
+    let (stop_a, stop_b) = (stop_mask(lemmas_a, params), stop_mask(lemmas_b, params));
+    let score_at = |i: usize, j: usize| {
+        if is_neutral(&stop_a, &stop_b, i, j) {
+            return 0;
+        }
+        let shared = shared_at(i, j);
+        let (root_a, root_b) = (root_at(roots_a, i), root_at(roots_b, j));
+        match weights {
+            Some((weights_a, weights_b)) => {
+                calculate_weighted_match_score(shared, root_a, root_b, weights_a, weights_b, params)
+            }
+            None => calculate_match_score(shared, root_a, root_b, params),
+        }
+    };
+    let gap = params.gap_penalty;
+
+    // Forward pass: best local score and where it ends
+    let mut prev = vec![0i32; m + 1];
+    let mut curr = vec![0i32; m + 1];
+    let mut max_score = 0i32;
+    let (mut end_a, mut end_b) = (0usize, 0usize);
+    for i in 1..=n {
+        deadline.check()?;
+        curr[0] = 0;
+        let mut row_max = 0i32;
+        for j in 1..=m {
+            let diagonal = prev[j - 1] + score_at(i - 1, j - 1);
+            let score = 0.max(diagonal).max(prev[j] + gap).max(curr[j - 1] + gap);
+            curr[j] = score;
+            row_max = row_max.max(score);
+            if score > max_score {
+                max_score = score;
+                end_a = i;
+                end_b = j;
+            }
+        }
+        std::mem::swap(&mut prev, &mut curr);
+        if xdrop_reached(row_max, max_score, params) {
+            break;
+        }
+    }
+
+    let min_score_threshold = (params.min_length as i32 * params.lemma_score) / 2;
+    if max_score < min_score_threshold {
+        return Ok(None);
+    }
+
+    // Backward pass: alignments anchored at the end, walking towards the
+    // start. The shortest one reaching the best score gives the start.
+    let Some((start_a, start_b)) = local_start(end_a, end_b, max_score, gap, &score_at, deadline)? else {
+        return Ok(None);
+    };
+
+    let mut path = Vec::with_capacity((end_a - start_a).max(end_b - start_b));
+    hirschberg(start_a..end_a, start_b..end_b, gap, &score_at, deadline, &mut path)?;
+
+    let mut aligned_pairs = Vec::with_capacity(path.len());
+    let mut gaps = 0u32;
+    let mut lemma_matches = 0u32;
+    let mut substitutions = 0u32;
+    let mut root_only_matches = 0u32;
+    let mut match_weight_sum = 0.0f32;
+    let mut unique_matched_lemmas: HashSet<u32> = HashSet::new();
+
+    for step in path {
+        let Step::Pair(i, j) = step else {
+            gaps += 1;
+            continue;
+        };
+        aligned_pairs.push((i, j));
+        let (root_a, root_b) = (root_at(roots_a, i), root_at(roots_b, j));
+        if let Some(lemma) = shared_at(i, j) {
+            lemma_matches += 1;
+            unique_matched_lemmas.insert(lemma);
+            if let Some((weights_a, weights_b)) = weights {
+                match_weight_sum += get_weight(lemma, weights_a).min(get_weight(lemma, weights_b));
+            }
+        } else if root_a == root_b && root_a != 0 {
+            root_only_matches += 1;
+        } else {
+            substitutions += 1;
+        }
+    }
+
+    if aligned_pairs.len() < params.min_length {
+        return Ok(None);
+    }
+
+    if !meets_min_matched_tokens(lemma_matches, root_only_matches, params) {
+        return Ok(None);
+    }
+
+    let similarity = match params.mode {
+        MatchMode::Lemma | MatchMode::Surface => lemma_matches as f32 / aligned_pairs.len() as f32,
+        MatchMode::Root => {
+            let root_matches = count_root_matches(&aligned_pairs, lemmas_a, lemmas_b, roots_a, roots_b);
+            root_matches as f32 / aligned_pairs.len() as f32
+        }
+        MatchMode::Combined => {
+            (lemma_matches as f32 + 0.5 * root_only_matches as f32) / aligned_pairs.len() as f32
+        }
+    };
+
+    if similarity < params.min_similarity {
+        return Ok(None);
+    }
+
+    let (start_a, start_b) = aligned_pairs.first().copied().unwrap_or((0, 0));
+    let (last_a, last_b) = aligned_pairs.last().copied().unwrap_or((0, 0));
+
+    let lexical_diversity = if lemma_matches > 0 {
+        unique_matched_lemmas.len() as f32 / lemma_matches as f32
+    } else {
+        0.0
+    };
+
+    Ok(Some(Alignment {
+        start_a,
+        end_a: last_a + 1,
+        start_b,
+        end_b: last_b + 1,
+        aligned_pairs,
+        lemma_matches,
+        substitutions,
+        root_only_matches,
+        gaps,
+        score: max_score,
+        match_weight_sum,
+        lexical_diversity,
+    }))
+}
+
+/// Start of the best local alignment ending at `(end_a, end_b)`.
+///
+/// Row `i` of the backward DP holds the best score of an alignment of
+/// `a[end_a - i..end_a]` against `b[end_b - j..end_b]`; the first cell to
+/// reach `target` marks the start.
+fn local_start(
+    end_a: usize,
+    end_b: usize,
+    target: i32,
+    gap: i32,
+    score_at: &impl Fn(usize, usize) -> i32,
+    deadline: &Deadline,
+) -> Result<Option<(usize, usize)>, AlignmentAborted> {
+    let mut prev: Vec<i32> = (0..=end_b as i32).map(|j| j * gap).collect();
+    let mut curr = vec![0i32; end_b + 1];
+    for i in 1..=end_a {
+        deadline.check()?;
+        curr[0] = i as i32 * gap;
+        for j in 1..=end_b {
+            let diagonal = prev[j - 1] + score_at(end_a - i, end_b - j);
+            let score = diagonal.max(prev[j] + gap).max(curr[j - 1] + gap);
+            curr[j] = score;
+            if score >= target && diagonal == score {
+                return Ok(Some((end_a - i, end_b - j)));
+            }
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    Ok(None)
+}
+
+/// Last row of the global alignment DP of `a` against `b`, in linear memory.
+///
+/// Forward, `row[j]` scores `a` against the first `j` tokens of `b`; reversed,
+/// both sequences are read back to front and `row[j]` scores `a` against the
+/// last `j` tokens of `b`.
+fn global_last_row(
+    a: Range<usize>,
+    b: Range<usize>,
+    reversed: bool,
+    gap: i32,
+    score_at: &impl Fn(usize, usize) -> i32,
+    deadline: &Deadline,
+) -> Result<Vec<i32>, AlignmentAborted> {
+    let m = b.len();
+    let mut prev: Vec<i32> = (0..=m as i32).map(|j| j * gap).collect();
+    let mut curr = vec![0i32; m + 1];
+    for i in 1..=a.len() {
+        deadline.check()?;
+        let pos_a = if reversed { a.end - i } else { a.start + i - 1 };
+        curr[0] = i as i32 * gap;
+        for j in 1..=m {
+            let pos_b = if reversed { b.end - j } else { b.start + j - 1 };
+            curr[j] = (prev[j - 1] + score_at(pos_a, pos_b))
+                .max(prev[j] + gap)
+                .max(curr[j - 1] + gap);
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    Ok(prev)
+}
+
+/// Hirschberg's divide and conquer: the best global alignment of `a` against
+/// `b`, appended to `path` in order.
+fn hirschberg(
+    a: Range<usize>,
+    b: Range<usize>,
+    gap: i32,
+    score_at: &impl Fn(usize, usize) -> i32,
+    deadline: &Deadline,
+    path: &mut Vec<Step>,
+) -> Result<(), AlignmentAborted> {
+    if a.is_empty() || b.is_empty() {
+        path.extend(std::iter::repeat_n(Step::Gap, a.len() + b.len()));
+        return Ok(());
+    }
+
+    if a.len() == 1 {
+        // Either pair the single token with its best partner, or gap everything
+        let i = a.start;
+        let (best_j, best) = b
+            .clone()
+            .map(|j| (j, score_at(i, j)))
+            .fold((b.start, i32::MIN), |acc, cur| if cur.1 > acc.1 { cur } else { acc });
+        if best >= 2 * gap {
+            path.extend(std::iter::repeat_n(Step::Gap, best_j - b.start));
+            path.push(Step::Pair(i, best_j));
+            path.extend(std::iter::repeat_n(Step::Gap, b.end - best_j - 1));
+        } else {
+            path.extend(std::iter::repeat_n(Step::Gap, b.len() + 1));
+        }
+        return Ok(());
+    }
+
+    let mid = a.start + a.len() / 2;
+    let forward = global_last_row(a.start..mid, b.clone(), false, gap, score_at, deadline)?;
+    let backward = global_last_row(mid..a.end, b.clone(), true, gap, score_at, deadline)?;
+    let m = b.len();
+    let split = (0..=m)
+        .max_by_key(|&k| (forward[k] + backward[m - k], std::cmp::Reverse(k)))
+        .unwrap_or(0);
+
+    hirschberg(a.start..mid, b.start..b.start + split, gap, score_at, deadline, path)?;
+    hirschberg(mid..a.end, b.start + split..b.end, gap, score_at, deadline, path)
+}
+
+// ============================================================================
+// Multiple alignment
+// ============================================================================
+
+/// Columns of a multiple alignment: `columns[k][w]` is the position in
+/// sequence `w` placed in column `k`, or None where `w` has a gap.
+pub type AlignedColumns = Vec<Vec<Option<usize>>>;
+
+/// Progressive global alignment of the same passage in several witnesses.
+///
+/// The witness closest to all others (highest total pairwise global score)
+/// seeds the alignment; the rest join in order of their score against it,
+/// each aligned globally to the columns built so far. A token scores against
+/// a column the sum of `lemma_score` / `mismatch_penalty` over the tokens
+/// already there; a gap on either side costs `gap_penalty`. Lemma 0 matches
+/// nothing.
+///
+/// Every witness is aligned whole, so pass spans trimmed to the passage. The
+/// alignment limits in `params` don't apply.
+pub fn align_multi(sequences: &[&[u32]], params: &ComparisonParams) -> AlignedColumns {
+    let score = |x: u32, y: u32| {
+        if x == y && x != 0 {
+            params.lemma_score
+        } else {
+            params.mismatch_penalty
+        }
+    };
+    let gap = params.gap_penalty;
+    let no_deadline = Deadline(None);
+
+    // Pairwise global scores choose the seed and the order of joining
+    let n = sequences.len();
+    let mut pair_scores = vec![vec![0i32; n]; n];
+    for a in 0..n {
+        for b in a + 1..n {
+            let (seq_a, seq_b) = (sequences[a], sequences[b]);
+            let score_at = |i: usize, j: usize| score(seq_a[i], seq_b[j]);
+            let row = global_last_row(0..seq_a.len(), 0..seq_b.len(), false, gap, &score_at, &no_deadline)
+                .expect("no deadline to miss");
+            pair_scores[a][b] = row[seq_b.len()];
+            pair_scores[b][a] = row[seq_b.len()];
+        }
+    }
+    let Some(seed) = (0..n).max_by_key(|&w| (pair_scores[w].iter().sum::<i32>(), std::cmp::Reverse(w))) else {
+        return Vec::new();
+    };
+    let mut order: Vec<usize> = (0..n).filter(|&w| w != seed).collect();
+    order.sort_by_key(|&w| (std::cmp::Reverse(pair_scores[seed][w]), w));
+
+    let mut columns: AlignedColumns = (0..sequences[seed].len())
+        .map(|i| {
+            let mut column = vec![None; n];
+            column[seed] = Some(i);
+            column
+        })
+        .collect();
+    for w in order {
+        columns = align_to_columns(columns, sequences, w, &score, gap);
+    }
+    columns
+}
+
+/// Needleman-Wunsch of sequence `w` against the columns built so far.
+fn align_to_columns(
+    columns: AlignedColumns,
+    sequences: &[&[u32]],
+    w: usize,
+    score: &impl Fn(u32, u32) -> i32,
+    gap: i32,
+) -> AlignedColumns {
+    let seq = sequences[w];
+    let (p, m) = (columns.len(), seq.len());
+    let column_score = |k: usize, x: u32| -> i32 {
+        columns[k]
+            .iter()
+            .enumerate()
+            .filter_map(|(v, pos)| pos.map(|i| score(sequences[v][i], x)))
+            .sum()
+    };
+
+    // dp[k][j]: first k columns against the first j tokens
+    let mut dp = vec![vec![0i32; m + 1]; p + 1];
+    for (j, cell) in dp[0].iter_mut().enumerate() {
+        *cell = j as i32 * gap;
+    }
+    for k in 1..=p {
+        dp[k][0] = k as i32 * gap;
+        for j in 1..=m {
+            dp[k][j] = (dp[k - 1][j - 1] + column_score(k - 1, seq[j - 1]))
+                .max(dp[k - 1][j] + gap)
+                .max(dp[k][j - 1] + gap);
+        }
+    }
+
+    // Trace back, preferring a pair, then a gap in `w`, then a new column
+    let mut aligned = Vec::with_capacity(p.max(m));
+    let (mut k, mut j) = (p, m);
+    while k > 0 || j > 0 {
+        if k > 0 && j > 0 && dp[k][j] == dp[k - 1][j - 1] + column_score(k - 1, seq[j - 1]) {
+            let mut column = columns[k - 1].clone();
+            column[w] = Some(j - 1);
+            aligned.push(column);
+            k -= 1;
+            j -= 1;
+        } else if k > 0 && (j == 0 || dp[k][j] == dp[k - 1][j] + gap) {
+            aligned.push(columns[k - 1].clone());
+            k -= 1;
+        } else {
+            let mut column = vec![None; sequences.len()];
+            column[w] = Some(j - 1);
+            aligned.push(column);
+            j -= 1;
+        }
+    }
+    aligned.reverse();
+    aligned
+}
+
+// ============================================================================
+// Alignment limits
+// ============================================================================
+
+/// Why an alignment was abandoned before it finished.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlignmentAborted {
+    /// The DP matrix has this many cells, over `max_alignment_cells`
+    TooManyCells(usize),
+    /// Still running after `alignment_timeout_ms`
+    Timeout,
+}
+
+impl std::fmt::Display for AlignmentAborted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlignmentAborted::TooManyCells(cells) => write!(f, "{} DP cells over the cap", cells),
+            AlignmentAborted::Timeout => write!(f, "timed out"),
+        }
+    }
+}
+
+/// X-drop test after filling a DP row: every cell of the row has fallen more
+/// than `params.xdrop` below the best score so far.
+///
+/// Local scores never drop below zero, so a row this far down means the best
+/// alignment has ended and nothing close to it is under way. The rows below
+/// could only hold a separate, later alignment, which is given up; candidate
+/// windows usually share a single passage, so this skips most of the fill.
+#[inline]
+fn xdrop_reached(row_max: i32, max_score: i32, params: &ComparisonParams) -> bool {
+    params.xdrop.is_some_and(|xdrop| i64::from(max_score - row_max) > i64::from(xdrop))
+}
+
+/// Reject an `n` x `m` alignment over `params.max_alignment_cells`.
+fn check_cells(n: usize, m: usize, params: &ComparisonParams) -> Result<(), AlignmentAborted> {
+    let cells = n.saturating_mul(m);
+    match params.max_alignment_cells {
+        Some(max) if cells > max => Err(AlignmentAborted::TooManyCells(cells)),
+        _ => Ok(()),
+    }
+}
+
+/// Wall-clock limit of one alignment, checked once per DP row.
+struct Deadline(Option<Instant>);
+
+impl Deadline {
+    fn start(params: &ComparisonParams) -> Self {
+        Deadline(params.alignment_timeout_ms.map(|ms| Instant::now() + Duration::from_millis(ms)))
+    }
+
+    #[inline]
+    fn check(&self) -> Result<(), AlignmentAborted> {
+        match self.0 {
+            Some(deadline) if Instant::now() >= deadline => Err(AlignmentAborted::Timeout),
+            _ => Ok(()),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -302,7 +1398,7 @@ mod tests {
     fn test_partial_match() {
         let seq_a: Vec<u32> = (0..20).collect();
         let seq_b: Vec<u32> = (0..20)
-            .map(|i| if i >= 5 && i < 15 { i } else { i + 1000 })
+            .map(|i| if (5..15).contains(&i) { i } else { i + 1000 })
             .collect();
         let params = default_params();
 
@@ -347,6 +1443,22 @@ mod tests {
         assert!(alignment.lemma_matches >= 10);
     }
 
+    #[test]
+    fn test_min_matched_tokens_excludes_substitutions() {
+        // 12 aligned positions, 10 matched: passes min_length either way
+        let seq_a: Vec<u32> = (1..=12).collect();
+        let seq_b: Vec<u32> = vec![1, 2, 100, 4, 5, 6, 101, 8, 9, 10, 11, 12];
+        let mut params = default_params();
+
+        params.min_matched_tokens = Some(10);
+        let alignment = align_lemma_sequences(&seq_a, &seq_b, &params).unwrap();
+        assert_eq!(alignment.lemma_matches, 10);
+        assert_eq!(alignment.substitutions, 2);
+
+        params.min_matched_tokens = Some(11);
+        assert!(align_lemma_sequences(&seq_a, &seq_b, &params).is_none());
+    }
+
     #[test]
     fn test_min_length_threshold() {
         let seq: Vec<u32> = (0..8).collect(); // Less than min_length of 10
@@ -372,7 +1484,7 @@ mod tests {
             ..Default::default()
         };
 
-        let result = align_lemma_sequences(&seq_a, &seq_b, &params);
+        let _result = align_lemma_sequences(&seq_a, &seq_b, &params);
         // Result depends on whether the alignment meets the 40% threshold
         // With only ~33% matches, it should likely fail
     }
@@ -521,248 +1633,216 @@ mod tests {
         assert!(alignment.lemma_matches >= 7);
         assert!(alignment.root_only_matches >= 3);
     }
-}
-
-/// Smith-Waterman local alignment with document-internal IDF weighting.
-///
-/// This version uses per-book lemma weights to influence alignment scoring.
-/// Rare lemmas contribute more to alignment than common lemmas.
-///
-/// # Arguments
-/// * `lemmas_a` - Lemma IDs for sequence A
-/// * `lemmas_b` - Lemma IDs for sequence B
-/// * `roots_a` - Root IDs for sequence A (0 = no root)
-/// * `roots_b` - Root IDs for sequence B (0 = no root)
-/// * `weights_a` - IDF weights for book A (indexed by lemma ID)
-/// * `weights_b` - IDF weights for book B (indexed by lemma ID)
-/// * `params` - Comparison parameters including match mode
-#[inline]
-pub fn align_sequences_weighted(
-    lemmas_a: &[u32],
-    lemmas_b: &[u32],
-    roots_a: &[u32],
-    roots_b: &[u32],
-    weights_a: &[f32],
-    weights_b: &[f32],
-    params: &ComparisonParams,
-) -> Option<Alignment> {
-    let n = lemmas_a.len();
-    let m = lemmas_b.len();
-
-    if n == 0 || m == 0 {
-        return None;
-    }
-
-    // DP matrix - use flat Vec for cache efficiency
-    let width = m + 1;
-    let mut h = vec![0i32; (n + 1) * width];
-
-    // Track max score position for traceback
-    let mut max_score = 0i32;
-    let mut max_i = 0usize;
-    let mut max_j = 0usize;
-
-    // Fill DP matrix with weighted scoring
-    for i in 1..=n {
-        let lemma_a = lemmas_a[i - 1];
-        let root_a = if i - 1 < roots_a.len() { roots_a[i - 1] } else { 0 };
-        let row_offset = i * width;
-        let prev_row_offset = (i - 1) * width;
-
-        for j in 1..=m {
-            let lemma_b = lemmas_b[j - 1];
-            let root_b = if j - 1 < roots_b.len() { roots_b[j - 1] } else { 0 };
 
-            // Calculate weighted match score
-            let match_score = calculate_weighted_match_score(
-                lemma_a, lemma_b, root_a, root_b,
-                weights_a, weights_b, params
-            );
+    #[test]
+    fn test_alternative_lemma_matches() {
+        // Middle 5 tokens of B have a different primary lemma, but one of their
+        // alternative analyses matches A
+        let lemmas_a: Vec<u32> = (0..15).collect();
+        let lemmas_b: Vec<u32> = (0..15)
+            .map(|i| if (5..10).contains(&i) { i + 1000 } else { i })
+            .collect();
+        let roots: Vec<u32> = vec![0; 15];
+        let alts_b: Vec<Vec<u32>> = (0..15)
+            .map(|i| if (5..10).contains(&i) { vec![i] } else { Vec::new() })
+            .collect();
 
-            // Compute cell value: max of 0, diagonal+match, up+gap, left+gap
-            let diagonal = h[prev_row_offset + (j - 1)] + match_score;
-            let up = h[prev_row_offset + j] + params.gap_penalty;
-            let left = h[row_offset + (j - 1)] + params.gap_penalty;
+        let mut params = default_params();
+        params.mode = MatchMode::Lemma;
 
-            let score = 0.max(diagonal).max(up).max(left);
-            h[row_offset + j] = score;
+        let plain = align_sequences(&lemmas_a, &lemmas_b, &roots, &roots, &params).unwrap();
+        assert!(plain.lemma_matches < 15);
 
-            if score > max_score {
-                max_score = score;
-                max_i = i;
-                max_j = j;
-            }
-        }
+        let alignment =
+            align_sequences_with_alternatives(&lemmas_a, &lemmas_b, &roots, &roots, &[], &alts_b, &params)
+                .unwrap();
+        assert_eq!(alignment.lemma_matches, 15);
+        assert_eq!(alignment.gaps, 0);
     }
 
-    // Early exit if no significant alignment
-    let min_score_threshold = (params.min_length as i32 * params.lemma_score) / 2;
-    if max_score < min_score_threshold {
-        return None;
+    /// Noise from a small LCG, with a shared passage planted in both sequences.
+    /// B's copy has two substitutions and a three-token insertion.
+    fn planted_pair() -> (Vec<u32>, Vec<u32>) {
+        let mut state = 7u64;
+        let mut noise = |len: usize| -> Vec<u32> {
+            (0..len)
+                .map(|_| {
+                    state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                    1000 + (state >> 33) as u32 % 5000
+                })
+                .collect()
+        };
+        let passage: Vec<u32> = (0..60).collect();
+        let mut copy = passage.clone();
+        copy[12] = 500;
+        copy[40] = 501;
+        copy.splice(25..25, [502, 503, 504]);
+
+        let seq_a = [noise(40), passage, noise(30)].concat();
+        let seq_b = [noise(15), copy, noise(50)].concat();
+        (seq_a, seq_b)
     }
 
-    // Traceback to recover alignment and compute match_weight_sum
-    let mut aligned_pairs = Vec::with_capacity(n.min(m));
-    let mut i = max_i;
-    let mut j = max_j;
-    let mut gaps = 0u32;
-    let mut lemma_matches = 0u32;
-    let mut substitutions = 0u32;
-    let mut root_only_matches = 0u32;
-    let mut match_weight_sum = 0.0f32;
-    // Track unique matched lemma IDs for lexical diversity calculation
-    let mut unique_matched_lemmas: HashSet<u32> = HashSet::new();
+    #[test]
+    fn test_linear_space_matches_full_matrix() {
+        let (seq_a, seq_b) = planted_pair();
+        let roots_a = vec![0; seq_a.len()];
+        let roots_b = vec![0; seq_b.len()];
+        let weights: Vec<f32> = (0..6000).map(|id| 1.0 + (id % 3) as f32).collect();
+        let params = default_params();
 
-    while i > 0 && j > 0 && h[i * width + j] > 0 {
-        let current = h[i * width + j];
-        let diagonal = h[(i - 1) * width + (j - 1)];
-        let up = h[(i - 1) * width + j];
+        let full = align_sequences(&seq_a, &seq_b, &roots_a, &roots_b, &params).unwrap();
+        let linear =
+            align_sequences_linear_space(&seq_a, &seq_b, &roots_a, &roots_b, &[], &[], None, &params).unwrap();
+        assert_eq!(linear.score, full.score);
+        assert_eq!(linear.aligned_pairs, full.aligned_pairs);
+        assert_eq!((linear.start_a, linear.end_a), (40, 100));
+        assert_eq!((linear.start_b, linear.end_b), (15, 78));
+        assert_eq!(linear.lemma_matches, 58);
+        assert_eq!(linear.substitutions, 2);
+        assert_eq!(linear.gaps, 3);
+
+        let full = align_sequences_weighted(&seq_a, &seq_b, &roots_a, &roots_b, &weights, &weights, &params)
+            .unwrap();
+        let linear = align_sequences_linear_space(
+            &seq_a, &seq_b, &roots_a, &roots_b, &[], &[], Some((&weights, &weights)), &params,
+        )
+        .unwrap();
+        assert_eq!(linear.score, full.score);
+        assert_eq!(linear.aligned_pairs, full.aligned_pairs);
+        assert_eq!(linear.match_weight_sum, full.match_weight_sum);
+    }
 
-        let lemma_a = lemmas_a[i - 1];
-        let lemma_b = lemmas_b[j - 1];
-        let root_a = if i - 1 < roots_a.len() { roots_a[i - 1] } else { 0 };
-        let root_b = if j - 1 < roots_b.len() { roots_b[j - 1] } else { 0 };
+    #[test]
+    fn test_large_windows_use_linear_space() {
+        // 2,100 x 2,100 is past LINEAR_SPACE_MIN_CELLS
+        let seq: Vec<u32> = (0..2100).collect();
+        let roots = vec![0; seq.len()];
+        assert!((seq.len() + 1).pow(2) > LINEAR_SPACE_MIN_CELLS);
+
+        let alignment = align_sequences(&seq, &seq, &roots, &roots, &default_params()).unwrap();
+        assert_eq!(alignment.lemma_matches, 2100);
+        assert_eq!((alignment.start_a, alignment.end_a), (0, 2100));
+        assert_eq!(alignment.gaps, 0);
+    }
 
-        let match_score = calculate_weighted_match_score(
-            lemma_a, lemma_b, root_a, root_b,
-            weights_a, weights_b, params
-        );
+    #[test]
+    fn test_alignment_limits() {
+        let seq: Vec<u32> = (0..100).collect();
+        let roots = vec![0; seq.len()];
+        let try_align = |params: &ComparisonParams| {
+            try_align_sequences_with_alternatives(&seq, &seq, &roots, &roots, &[], &[], params)
+        };
 
-        if current == diagonal + match_score {
-            // Match or mismatch - record the pair
-            aligned_pairs.push((i - 1, j - 1));
+        let capped = ComparisonParams {
+            max_alignment_cells: Some(5_000),
+            ..default_params()
+        };
+        assert_eq!(try_align(&capped).unwrap_err(), AlignmentAborted::TooManyCells(10_000));
+        assert!(align_sequences(&seq, &seq, &roots, &roots, &capped).is_none());
 
-            // Track what kind of match it was
-            if lemma_a == lemma_b {
-                lemma_matches += 1;
-                unique_matched_lemmas.insert(lemma_a);
-                // Add weight to match_weight_sum: min(weight_A, weight_B)
-                let w_a = get_weight(lemma_a, weights_a);
-                let w_b = get_weight(lemma_b, weights_b);
-                match_weight_sum += w_a.min(w_b);
-            } else if root_a == root_b && root_a != 0 {
-                root_only_matches += 1;
-            } else {
-                // Neither lemma nor root matched - this is a substitution
-                substitutions += 1;
-            }
+        let timed_out = ComparisonParams {
+            alignment_timeout_ms: Some(0),
+            ..default_params()
+        };
+        assert_eq!(try_align(&timed_out).unwrap_err(), AlignmentAborted::Timeout);
 
-            i -= 1;
-            j -= 1;
-        } else if current == up + params.gap_penalty {
-            // Gap in seq_b
-            gaps += 1;
-            i -= 1;
-        } else {
-            // Gap in seq_a
-            gaps += 1;
-            j -= 1;
-        }
+        let generous = ComparisonParams {
+            max_alignment_cells: Some(10_000),
+            alignment_timeout_ms: Some(60_000),
+            ..default_params()
+        };
+        assert!(try_align(&generous).unwrap().is_some());
     }
 
-    // Alignment is built backwards, reverse it
-    aligned_pairs.reverse();
-
-    // Check minimum length
-    if aligned_pairs.len() < params.min_length {
-        return None;
-    }
+    #[test]
+    fn test_xdrop_stops_after_first_passage() {
+        // A short shared passage, unrelated text, then a longer shared passage
+        let build = |filler: u32| -> Vec<u32> {
+            (100..120).chain(filler..filler + 60).chain(200..250).collect()
+        };
+        let (seq_a, seq_b) = (build(1000), build(2000));
+        let roots = vec![0; seq_a.len()];
+        let params = default_params();
 
-    // Check minimum similarity based on mode
-    let similarity = match params.mode {
-        MatchMode::Lemma => lemma_matches as f32 / aligned_pairs.len() as f32,
-        MatchMode::Root => {
-            let root_matches = count_root_matches(&aligned_pairs, lemmas_a, lemmas_b, roots_a, roots_b);
-            root_matches as f32 / aligned_pairs.len() as f32
-        }
-        MatchMode::Combined => {
-            (lemma_matches as f32 + 0.5 * root_only_matches as f32) / aligned_pairs.len() as f32
-        }
-    };
+        let full = align_sequences(&seq_a, &seq_b, &roots, &roots, &params).unwrap();
+        assert_eq!((full.start_a, full.end_a), (80, 130));
 
-    if similarity < params.min_similarity {
-        return None;
+        let xdrop = ComparisonParams {
+            xdrop: Some(10),
+            ..default_params()
+        };
+        let pruned = align_sequences(&seq_a, &seq_b, &roots, &roots, &xdrop).unwrap();
+        assert_eq!((pruned.start_a, pruned.end_a), (0, 20));
+        let linear =
+            align_sequences_linear_space(&seq_a, &seq_b, &roots, &roots, &[], &[], None, &xdrop).unwrap();
+        assert_eq!(linear.aligned_pairs, pruned.aligned_pairs);
+
+        // A drop wider than the gap between the passages changes nothing
+        let wide = ComparisonParams {
+            xdrop: Some(1_000),
+            ..default_params()
+        };
+        let unpruned = align_sequences(&seq_a, &seq_b, &roots, &roots, &wide).unwrap();
+        assert_eq!(unpruned.aligned_pairs, full.aligned_pairs);
     }
 
-    // Find start/end positions
-    let (start_a, start_b) = aligned_pairs.first().copied().unwrap_or((0, 0));
-    let (end_a, end_b) = aligned_pairs.last().copied().unwrap_or((0, 0));
+    #[test]
+    fn test_stop_lemmas_score_neutral() {
+        // Content lemmas 100.. interleaved with particles 1 and 2
+        let seq: Vec<u32> = (0..40).map(|i| if i % 2 == 0 { 1 + (i % 4) / 2 } else { 100 + i }).collect();
+        let roots = vec![0; seq.len()];
+        let params = default_params();
+        let stopped = ComparisonParams {
+            stop_lemmas: Some(HashSet::from([1, 2])),
+            ..default_params()
+        };
 
-    // Compute lexical diversity: unique matched lemmas / total lemma matches
-    let lexical_diversity = if lemma_matches > 0 {
-        unique_matched_lemmas.len() as f32 / lemma_matches as f32
-    } else {
-        0.0
-    };
+        let full = align_sequences(&seq, &seq, &roots, &roots, &params).unwrap();
+        let neutral = align_sequences(&seq, &seq, &roots, &roots, &stopped).unwrap();
+        assert_eq!(full.score, 40 * params.lemma_score);
+        assert_eq!(neutral.score, 20 * params.lemma_score);
+        let linear =
+            align_sequences_linear_space(&seq, &seq, &roots, &roots, &[], &[], None, &stopped).unwrap();
+        assert_eq!(linear.score, neutral.score);
+
+        // A run made only of particles no longer aligns at all
+        let particles: Vec<u32> = (0..40).map(|i| 1 + i % 2).collect();
+        assert!(align_sequences(&particles, &particles, &roots, &roots, &params).is_some());
+        assert!(align_sequences(&particles, &particles, &roots, &roots, &stopped).is_none());
+    }
 
-    Some(Alignment {
-        start_a,
-        end_a: end_a + 1,
-        start_b,
-        end_b: end_b + 1,
-        aligned_pairs,
-        lemma_matches,
-        substitutions,
-        root_only_matches,
-        gaps,
-        score: max_score,
-        match_weight_sum,
-        lexical_diversity,
-    })
-}
+    #[test]
+    fn test_align_multi() {
+        // The middle witness has every word; one drops 3, one replaces 5
+        let full: Vec<u32> = (1..=6).collect();
+        let short = vec![1, 2, 4, 5, 6];
+        let varied = vec![1, 2, 3, 4, 9, 6];
+        let columns = align_multi(&[&short, &full, &varied], &default_params());
+        let rows: Vec<Vec<Option<u32>>> = columns
+            .iter()
+            .map(|column| {
+                let seqs = [&short, &full, &varied];
+                column.iter().enumerate().map(|(w, pos)| pos.map(|i| seqs[w][i])).collect()
+            })
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                vec![Some(1), Some(1), Some(1)],
+                vec![Some(2), Some(2), Some(2)],
+                vec![None, Some(3), Some(3)],
+                vec![Some(4), Some(4), Some(4)],
+                vec![Some(5), Some(5), Some(9)],
+                vec![Some(6), Some(6), Some(6)],
+            ]
+        );
 
-/// Calculate weighted match score using document-internal IDF weights.
-#[inline(always)]
-fn calculate_weighted_match_score(
-    lemma_a: u32,
-    lemma_b: u32,
-    root_a: u32,
-    root_b: u32,
-    weights_a: &[f32],
-    weights_b: &[f32],
-    params: &ComparisonParams,
-) -> i32 {
-    match params.mode {
-        MatchMode::Lemma => {
-            if lemma_a == lemma_b {
-                // Weight the score by min(weight_A, weight_B)
-                let w_a = get_weight(lemma_a, weights_a);
-                let w_b = get_weight(lemma_b, weights_b);
-                let w = w_a.min(w_b);
-                (params.lemma_score as f32 * w) as i32
-            } else {
-                params.mismatch_penalty
-            }
-        }
-        MatchMode::Root => {
-            if root_a == root_b && root_a != 0 {
-                params.lemma_score
-            } else {
-                params.mismatch_penalty
-            }
-        }
-        MatchMode::Combined => {
-            if lemma_a == lemma_b {
-                let w_a = get_weight(lemma_a, weights_a);
-                let w_b = get_weight(lemma_b, weights_b);
-                let w = w_a.min(w_b);
-                (params.lemma_score as f32 * w) as i32
-            } else if root_a == root_b && root_a != 0 {
-                params.root_score
-            } else {
-                params.mismatch_penalty
-            }
+        // Every position of every witness appears once, in order
+        for w in 0..3 {
+            let positions: Vec<usize> = columns.iter().filter_map(|column| column[w]).collect();
+            assert_eq!(positions, (0..[5, 6, 6][w]).collect::<Vec<_>>());
         }
-    }
-}
-
-/// Get weight for a lemma ID, with bounds checking and default.
-#[inline(always)]
-fn get_weight(lemma_id: u32, weights: &[f32]) -> f32 {
-    let idx = lemma_id as usize;
-    if idx < weights.len() && weights[idx] > 0.0 {
-        weights[idx]
-    } else {
-        1.0 // Default weight for unknown lemmas
+        assert!(align_multi(&[], &default_params()).is_empty());
     }
 }
@@ -0,0 +1,361 @@
+//! Passage clusters across many comparison results.
+//!
+//! A passage that circulated through the tradition shows up in several
+//! pairwise results: book A quotes it from B, C from A, D from B, each in
+//! its own file. Every edge joins a passage of one book to a passage of
+//! another, and passages of the same book that overlap are the same passage
+//! seen from different pairs. Following both links transitively gathers all
+//! witnesses of a passage into one cluster.
+//!
+//! Overlap chains can drift: a long passage may join two clusters that only
+//! share its middle. `min_overlap` (relative to the shorter passage) keeps
+//! passages from joining on a few shared tokens.
+//!
+//! [`collate_passages`] then lines up the witnesses of one cluster word by
+//! word with [`align_multi`], for collation of their readings.
+
+use std::collections::{BTreeSet, HashMap};
+
+use crate::align::align_multi;
+use crate::compare::chrono_lite_timestamp;
+use crate::models::{
+    BookTokenStream, ClusterMember, ClusterReport, Collation, CollationColumn, CollationWitness, ComparisonParams,
+    ComparisonResultWithText, PassageCluster, PassageRef, SurfaceLookup, ViewerBookInfo,
+};
+
+/// What counts as one passage and which clusters are reported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClusterOptions {
+    /// Fewest distinct books a cluster must span to be reported
+    pub min_witnesses: usize,
+    /// Share of the shorter of two passages in the same book that must
+    /// overlap for them to count as the same passage
+    pub min_overlap: f32,
+}
+
+impl Default for ClusterOptions {
+    fn default() -> Self {
+        Self {
+            min_witnesses: 3,
+            min_overlap: 0.5,
+        }
+    }
+}
+
+/// One side of an edge
+struct Passage<'a> {
+    book: &'a ViewerBookInfo,
+    passage: &'a PassageRef,
+}
+
+/// Group the edges of `results` into multi-witness passages.
+///
+/// Clusters spanning fewer than `min_witnesses` books are dropped. The rest
+/// are sorted by witness count, then edge count, and numbered from 1.
+pub fn cluster_edges(results: &[ComparisonResultWithText], options: &ClusterOptions) -> ClusterReport {
+    let mut passages = Vec::new();
+    for result in results {
+        for edge in &result.edges {
+            let book = |book_id| if book_id == result.book_a.id { &result.book_a } else { &result.book_b };
+            passages.push(Passage {
+                book: book(edge.source.book_id),
+                passage: &edge.source,
+            });
+            passages.push(Passage {
+                book: book(edge.target.book_id),
+                passage: &edge.target,
+            });
+        }
+    }
+
+    // Passages 2i and 2i + 1 are the two sides of one edge
+    let mut sets = DisjointSets::new(passages.len());
+    for i in (0..passages.len()).step_by(2) {
+        sets.union(i, i + 1);
+    }
+    let mut by_book: HashMap<u32, Vec<usize>> = HashMap::new();
+    for (i, p) in passages.iter().enumerate() {
+        by_book.entry(p.passage.book_id).or_default().push(i);
+    }
+    for indices in by_book.values_mut() {
+        indices.sort_by_key(|&i| passages[i].passage.global_range);
+        // Sweep in start order, keeping the passages still open
+        let mut open: Vec<usize> = Vec::new();
+        for &i in indices.iter() {
+            let (start, end) = passages[i].passage.global_range;
+            open.retain(|&j| passages[j].passage.global_range.1 > start);
+            for &j in &open {
+                let (other_start, other_end) = passages[j].passage.global_range;
+                let shared = end.min(other_end) - start.max(other_start);
+                let shorter = (end - start).min(other_end - other_start);
+                if shared as f32 >= options.min_overlap * shorter as f32 {
+                    sets.union(i, j);
+                }
+            }
+            open.push(i);
+        }
+    }
+
+    let mut components: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..passages.len() {
+        components.entry(sets.find(i)).or_default().push(i);
+    }
+    let mut clusters: Vec<PassageCluster> = components
+        .into_values()
+        .filter_map(|members| {
+            let books: BTreeSet<u32> = members.iter().map(|&i| passages[i].passage.book_id).collect();
+            (books.len() >= options.min_witnesses).then(|| PassageCluster {
+                id: 0,
+                witnesses: books.len(),
+                edge_count: members.len() / 2,
+                members: cluster_members(&passages, members),
+            })
+        })
+        .collect();
+    clusters.sort_by(|a, b| {
+        (b.witnesses, b.edge_count)
+            .cmp(&(a.witnesses, a.edge_count))
+            .then_with(|| member_key(&a.members[0]).cmp(&member_key(&b.members[0])))
+    });
+    for (i, cluster) in clusters.iter_mut().enumerate() {
+        cluster.id = i + 1;
+    }
+
+    ClusterReport {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        generated_at: chrono_lite_timestamp(),
+        result_count: results.len(),
+        min_witnesses: options.min_witnesses,
+        min_overlap: options.min_overlap,
+        clusters,
+    }
+}
+
+/// A cluster's passages, overlapping ones in the same book joined, in
+/// order of death date, then book ID and position.
+fn cluster_members(passages: &[Passage], mut indices: Vec<usize>) -> Vec<ClusterMember> {
+    indices.sort_by_key(|&i| (passages[i].passage.book_id, passages[i].passage.global_range));
+    let mut members: Vec<(ClusterMember, usize)> = Vec::new();
+    for i in indices {
+        let Passage { book, passage } = &passages[i];
+        let (start, end) = passage.global_range;
+        if let Some((member, longest)) = members.last_mut() {
+            if member.book_id == passage.book_id && start < member.global_range.1 {
+                member.global_range.1 = member.global_range.1.max(end);
+                if end - start > *longest {
+                    *longest = end - start;
+                    member.location.clone_from(&passage.location);
+                    member.text.clone_from(&passage.text.matched);
+                }
+                continue;
+            }
+        }
+        members.push((
+            ClusterMember {
+                book_id: passage.book_id,
+                title: book.title.clone(),
+                author: book.author.clone(),
+                death_ah: book.death_ah,
+                global_range: passage.global_range,
+                location: passage.location.clone(),
+                text: passage.text.matched.clone(),
+            },
+            end - start,
+        ));
+    }
+    let mut members: Vec<ClusterMember> = members.into_iter().map(|(member, _)| member).collect();
+    members.sort_by_key(member_key);
+    members
+}
+
+/// Undated books last
+fn member_key(member: &ClusterMember) -> (u32, u32, usize) {
+    (member.death_ah.unwrap_or(u32::MAX), member.book_id, member.global_range.0)
+}
+
+/// Align the members of `cluster` word by word, `streams[i]` holding the
+/// book of `cluster.members[i]`.
+///
+/// Lemmas are aligned (see [`align_multi`]) and shown by their surface
+/// forms. A column is a variant where the witnesses' lemmas differ or some
+/// witness has a gap.
+pub fn collate_passages<S: SurfaceLookup + ?Sized>(
+    cluster: &PassageCluster,
+    streams: &[&BookTokenStream],
+    token_to_surface: &S,
+    params: &ComparisonParams,
+) -> Collation {
+    let spans: Vec<(Vec<u32>, Vec<u32>)> = cluster
+        .members
+        .iter()
+        .zip(streams)
+        .map(|(member, stream)| {
+            let (lemmas, tokens) = (stream.flat_lemma_ids(), stream.flat_token_ids());
+            let end = member.global_range.1.min(lemmas.len());
+            let start = member.global_range.0.min(end);
+            (lemmas[start..end].to_vec(), tokens[start..end].to_vec())
+        })
+        .collect();
+    let lemmas: Vec<&[u32]> = spans.iter().map(|(lemmas, _)| lemmas.as_slice()).collect();
+
+    let columns: Vec<CollationColumn> = align_multi(&lemmas, params)
+        .into_iter()
+        .map(|column| {
+            let mut column_lemmas = column.iter().zip(&lemmas).map(|(pos, seq)| pos.map(|i| seq[i]));
+            let first = column_lemmas.next().flatten();
+            let variant = first.is_none() || column_lemmas.any(|lemma| lemma != first);
+            let words = column
+                .iter()
+                .zip(&spans)
+                .map(|(pos, (_, tokens))| {
+                    pos.map(|i| token_to_surface.surface(tokens[i]).unwrap_or_default().to_string())
+                })
+                .collect();
+            CollationColumn { words, variant }
+        })
+        .collect();
+
+    Collation {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        generated_at: chrono_lite_timestamp(),
+        cluster_id: cluster.id,
+        witnesses: cluster
+            .members
+            .iter()
+            .map(|member| CollationWitness {
+                book_id: member.book_id,
+                title: member.title.clone(),
+                death_ah: member.death_ah,
+                global_range: member.global_range,
+                location: member.location.clone(),
+            })
+            .collect(),
+        variant_columns: columns.iter().filter(|column| column.variant).count(),
+        columns,
+    }
+}
+
+/// Union-find over passage indices
+struct DisjointSets {
+    parent: Vec<usize>,
+}
+
+impl DisjointSets {
+    fn new(len: usize) -> Self {
+        Self {
+            parent: (0..len).collect(),
+        }
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            self.parent[a.max(b)] = a.min(b);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        BookTokenStream, ComparisonParams, ComparisonSummary, ReuseEdge, ReuseEdgeWithText, RunInfo, SurfaceTable,
+    };
+
+    fn book(id: u32, death_ah: Option<u32>) -> ViewerBookInfo {
+        ViewerBookInfo {
+            id,
+            title: format!("book {}", id),
+            author: String::new(),
+            death_ah,
+            token_count: 10_000,
+            page_count: 10,
+            stream_hash: String::new(),
+        }
+    }
+
+    type Range = (usize, usize);
+
+    /// A result between `a` and `b` with one edge per (source range, target range)
+    fn result(a: ViewerBookInfo, b: ViewerBookInfo, spans: &[(Range, Range)]) -> ComparisonResultWithText {
+        let no_text = |book_id| BookTokenStream {
+            book_id,
+            total_tokens: 0,
+            pages: Vec::new(),
+            corpus_weights: None,
+            quran: None,
+        };
+        let (text_a, text_b) = (no_text(a.id), no_text(b.id));
+        let edges = spans
+            .iter()
+            .map(|&((source_start, source_end), (target_start, target_end))| {
+                let edge = ReuseEdge {
+                    source_book_id: a.id,
+                    source_global_start: source_start,
+                    source_global_end: source_end,
+                    target_book_id: b.id,
+                    target_global_start: target_start,
+                    target_global_end: target_end,
+                    ..Default::default()
+                };
+                ReuseEdgeWithText::from_edge(&edge, &text_a, &text_b, &SurfaceTable::default(), 0)
+            })
+            .collect();
+        ComparisonResultWithText {
+            version: String::new(),
+            generated_at: String::new(),
+            parameters: ComparisonParams::default(),
+            book_a: a,
+            book_b: b,
+            summary: ComparisonSummary {
+                edge_count: spans.len(),
+                total_aligned_tokens: 0,
+                book_a_coverage: 0.0,
+                book_b_coverage: 0.0,
+                avg_similarity: 0.0,
+                avg_weighted_similarity: 0.0,
+                book_a_coverage_ci: None,
+                book_b_coverage_ci: None,
+                ..Default::default()
+            },
+            edges,
+            macro_edges: Vec::new(),
+            coverage_profiles: Vec::new(),
+            run_info: RunInfo::default(),
+            redaction: None,
+        }
+    }
+
+    #[test]
+    fn test_cluster_edges() {
+        // A passage of book 1 (d. 200) reaches 2 (d. 300) and 3 (d. 250); 3's
+        // copy reaches 4 (undated). A second passage is shared by 1 and 2 only.
+        let results = |third: (usize, usize)| {
+            vec![
+                result(book(1, Some(200)), book(2, Some(300)), &[((100, 200), (500, 600)), ((900, 950), (50, 100))]),
+                result(book(1, Some(200)), book(3, Some(250)), &[((110, 210), (1000, 1100))]),
+                result(book(3, Some(250)), book(4, None), &[(third, (7, 87))]),
+            ]
+        };
+        let report = cluster_edges(&results((1020, 1100)), &ClusterOptions::default());
+        assert_eq!(report.result_count, 3);
+        assert_eq!(report.clusters.len(), 1);
+        let cluster = &report.clusters[0];
+        assert_eq!((cluster.id, cluster.witnesses, cluster.edge_count), (1, 4, 3));
+        let members: Vec<_> = cluster.members.iter().map(|m| (m.book_id, m.global_range)).collect();
+        assert_eq!(members, vec![(1, (100, 210)), (3, (1000, 1100)), (2, (500, 600)), (4, (7, 87))]);
+
+        // Book 3's passages barely overlap when its copy for 4 starts later
+        let report = cluster_edges(&results((1090, 1170)), &ClusterOptions::default());
+        assert_eq!(report.clusters.len(), 1);
+        assert_eq!(report.clusters[0].witnesses, 3);
+    }
+}
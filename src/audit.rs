@@ -0,0 +1,151 @@
+//! Stratified edge sampling for manual quality audits.
+//!
+//! Large result sets can't be reviewed exhaustively. This module draws a
+//! reproducible random sample stratified by core similarity so that reviewers
+//! see borderline matches as well as near-verbatim ones, instead of whatever
+//! happens to sort to the top.
+
+use crate::compare::chrono_lite_timestamp;
+use crate::models::{AuditBand, AuditSample, ComparisonResultWithText};
+use crate::rng::SplitMix64;
+
+/// Core-similarity band edges used for stratification (lower bound inclusive).
+///
+/// The bands are finest near the default core-similarity filter (0.85), where
+/// reviewer judgements are most informative.
+pub const AUDIT_BAND_EDGES: [f32; 5] = [0.0, 0.85, 0.90, 0.95, 1.0];
+
+/// Default seed for `--audit-sample` so that repeated runs draw the same sample.
+pub const DEFAULT_AUDIT_SEED: u64 = 42;
+
+/// Index of the band a score falls into.
+fn band_index(score: f32) -> usize {
+    let last = AUDIT_BAND_EDGES.len() - 2;
+    (0..=last)
+        .find(|&i| score < AUDIT_BAND_EDGES[i + 1])
+        .unwrap_or(last)
+}
+
+/// Split `n` across strata of the given sizes.
+///
+/// Each non-empty stratum gets an equal share (capped at its size); capacity
+/// left over by small strata is handed out round-robin to the rest. Equal
+/// allocation deliberately oversamples rare bands relative to their share of
+/// the population.
+fn allocate(sizes: &[usize], n: usize) -> Vec<usize> {
+    let mut alloc = vec![0; sizes.len()];
+    let mut remaining = n.min(sizes.iter().sum());
+
+    while remaining > 0 {
+        for (i, &size) in sizes.iter().enumerate() {
+            if remaining == 0 {
+                break;
+            }
+            if alloc[i] < size {
+                alloc[i] += 1;
+                remaining -= 1;
+            }
+        }
+    }
+
+    alloc
+}
+
+/// Draw up to `n` indices from `scores`, stratified by [`AUDIT_BAND_EDGES`].
+///
+/// Returns the sampled indices grouped by band (ascending), ascending within each
+/// band, plus the per-band population and sample counts.
+pub fn stratified_sample_indices(scores: &[f32], n: usize, seed: u64) -> (Vec<usize>, Vec<AuditBand>) {
+    let band_count = AUDIT_BAND_EDGES.len() - 1;
+    let mut strata: Vec<Vec<usize>> = vec![Vec::new(); band_count];
+    for (idx, &score) in scores.iter().enumerate() {
+        strata[band_index(score)].push(idx);
+    }
+
+    let sizes: Vec<usize> = strata.iter().map(|s| s.len()).collect();
+    let alloc = allocate(&sizes, n);
+
+    let mut rng = SplitMix64::new(seed);
+    let mut sampled = Vec::new();
+    let mut bands = Vec::with_capacity(band_count);
+
+    for (band, (mut members, take)) in strata.into_iter().zip(alloc).enumerate() {
+        // Partial Fisher-Yates: the first `take` slots become a uniform sample
+        for i in 0..take {
+            let j = i + rng.below(members.len() - i);
+            members.swap(i, j);
+        }
+        let mut chosen = members[..take].to_vec();
+        chosen.sort_unstable();
+        sampled.extend(chosen);
+
+        bands.push(AuditBand {
+            min_core_similarity: AUDIT_BAND_EDGES[band],
+            max_core_similarity: AUDIT_BAND_EDGES[band + 1],
+            population: sizes[band],
+            sampled: take,
+        });
+    }
+
+    (sampled, bands)
+}
+
+/// Build an audit sample of `n` edges from a comparison result.
+pub fn sample_edges_for_audit(result: &ComparisonResultWithText, n: usize, seed: u64) -> AuditSample {
+    let scores: Vec<f32> = result
+        .edges
+        .iter()
+        .map(|e| e.alignment.core_similarity)
+        .collect();
+    let (indices, bands) = stratified_sample_indices(&scores, n, seed);
+
+    AuditSample {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        generated_at: chrono_lite_timestamp(),
+        seed,
+        requested: n,
+        total_edges: result.edges.len(),
+        book_a: result.book_a.clone(),
+        book_b: result.book_b.clone(),
+        bands,
+        edges: indices.iter().map(|&i| result.edges[i].clone()).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_band_index() {
+        assert_eq!(band_index(0.5), 0);
+        assert_eq!(band_index(0.85), 1);
+        assert_eq!(band_index(0.93), 2);
+        assert_eq!(band_index(0.99), 3);
+        assert_eq!(band_index(1.0), 3);
+    }
+
+    #[test]
+    fn test_allocate_redistributes() {
+        assert_eq!(allocate(&[1, 10, 10, 0], 9), vec![1, 4, 4, 0]);
+        assert_eq!(allocate(&[2, 2], 10), vec![2, 2]);
+        assert_eq!(allocate(&[5, 5, 5, 5], 0), vec![0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn test_stratified_sample_deterministic() {
+        let scores: Vec<f32> = (0..200).map(|i| 0.8 + (i as f32 / 1000.0)).collect();
+
+        let (a, bands) = stratified_sample_indices(&scores, 20, 7);
+        let (b, _) = stratified_sample_indices(&scores, 20, 7);
+        assert_eq!(a, b);
+        assert_eq!(a.len(), 20);
+        assert_eq!(bands.iter().map(|b| b.population).sum::<usize>(), 200);
+        assert_eq!(bands.iter().map(|b| b.sampled).sum::<usize>(), 20);
+
+        // No duplicates
+        let mut dedup = a.clone();
+        dedup.dedup();
+        assert_eq!(dedup.len(), a.len());
+    }
+}
@@ -0,0 +1,171 @@
+//! Author-level self-reuse reports.
+//!
+//! Summarises a batch run over all books of one author: which pairs share
+//! material, how much, and how much of each book reappears elsewhere in the
+//! author's own works.
+
+use std::collections::HashMap;
+
+use crate::compare::chrono_lite_timestamp;
+use crate::models::{
+    AuthorBookReuse, AuthorPairReuse, AuthorReport, BatchManifest, BookMetadata, PairStatus,
+};
+
+/// Build a self-reuse report for `author_id` from a completed batch manifest.
+///
+/// `books` is the author's metadata; pairs in the manifest involving other
+/// books are ignored.
+pub fn build_author_report(
+    author_id: u32,
+    books: &[BookMetadata],
+    manifest: &BatchManifest,
+) -> AuthorReport {
+    let mut book_stats: HashMap<u32, AuthorBookReuse> = books
+        .iter()
+        .map(|b| {
+            (
+                b.id,
+                AuthorBookReuse {
+                    book_id: b.id,
+                    title: b.title.clone(),
+                    death_ah: b.death_ah,
+                    pairs_with_reuse: 0,
+                    max_coverage: 0.0,
+                },
+            )
+        })
+        .collect();
+
+    let mut pairs = Vec::new();
+    let mut skipped_pairs = 0;
+
+    for record in &manifest.pairs {
+        if !book_stats.contains_key(&record.book_a) || !book_stats.contains_key(&record.book_b) {
+            continue;
+        }
+        if record.status == PairStatus::SkippedPrescan {
+            skipped_pairs += 1;
+            continue;
+        }
+        let Some(summary) = &record.summary else {
+            continue;
+        };
+
+        if summary.edge_count > 0 {
+            for (book_id, coverage) in [
+                (record.book_a, summary.book_a_coverage),
+                (record.book_b, summary.book_b_coverage),
+            ] {
+                if let Some(stats) = book_stats.get_mut(&book_id) {
+                    stats.pairs_with_reuse += 1;
+                    stats.max_coverage = stats.max_coverage.max(coverage);
+                }
+            }
+        }
+
+        pairs.push(AuthorPairReuse {
+            book_a: record.book_a,
+            book_b: record.book_b,
+            edge_count: summary.edge_count,
+            total_aligned_tokens: summary.total_aligned_tokens,
+            book_a_coverage: summary.book_a_coverage,
+            book_b_coverage: summary.book_b_coverage,
+            output: record.output.clone(),
+        });
+    }
+
+    pairs.sort_by_key(|p| std::cmp::Reverse(p.total_aligned_tokens));
+
+    let mut book_list: Vec<AuthorBookReuse> = book_stats.into_values().collect();
+    book_list.sort_by_key(|b| b.book_id);
+
+    AuthorReport {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        generated_at: chrono_lite_timestamp(),
+        author_id,
+        book_count: book_list.len(),
+        pair_count: pairs.len() + skipped_pairs,
+        pairs_with_reuse: pairs.iter().filter(|p| p.edge_count > 0).count(),
+        skipped_pairs,
+        total_edges: pairs.iter().map(|p| p.edge_count).sum(),
+        total_aligned_tokens: pairs.iter().map(|p| p.total_aligned_tokens).sum(),
+        books: book_list,
+        pairs,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{ComparisonParams, ComparisonSummary, PairRecord};
+
+    fn compared(book_a: u32, book_b: u32, edges: usize, tokens: usize, cov: (f32, f32)) -> PairRecord {
+        PairRecord {
+            book_a,
+            book_b,
+            status: PairStatus::Ok,
+            prescan_overlap: None,
+            edge_count: edges,
+            output: Some(crate::batch::pair_dir(book_a, book_b)),
+            summary: Some(ComparisonSummary {
+                edge_count: edges,
+                total_aligned_tokens: tokens,
+                book_a_coverage: cov.0,
+                book_b_coverage: cov.1,
+                avg_similarity: 0.9,
+                avg_weighted_similarity: 0.9,
+                book_a_coverage_ci: None,
+                book_b_coverage_ci: None,
+                order_tau: 1.0,
+                aborted_alignments: 0,
+                ..Default::default()
+            }),
+            checksums: Default::default(),
+            cached: false,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_build_author_report() {
+        let books: Vec<BookMetadata> = [1, 2, 3]
+            .iter()
+            .map(|&id| BookMetadata {
+                id,
+                author_id: Some(7),
+                ..Default::default()
+            })
+            .collect();
+
+        let mut skipped = compared(2, 3, 0, 0, (0.0, 0.0));
+        skipped.status = PairStatus::SkippedPrescan;
+        skipped.summary = None;
+
+        let manifest = BatchManifest {
+            version: String::new(),
+            generated_at: String::new(),
+            parameters: ComparisonParams::default(),
+            params_fingerprint: String::new(),
+            db_fingerprint: String::new(),
+            book_hashes: Default::default(),
+            min_prescan_overlap: Some(0.1),
+            shard: None,
+            pairs: vec![
+                compared(1, 2, 3, 100, (0.10, 0.20)),
+                compared(1, 3, 5, 400, (0.30, 0.05)),
+                skipped,
+            ],
+        };
+
+        let report = build_author_report(7, &books, &manifest);
+        assert_eq!(report.book_count, 3);
+        assert_eq!(report.pair_count, 3);
+        assert_eq!(report.skipped_pairs, 1);
+        assert_eq!(report.pairs_with_reuse, 2);
+        assert_eq!(report.total_edges, 8);
+        assert_eq!(report.pairs[0].book_b, 3); // Largest first
+        assert_eq!(report.books[0].pairs_with_reuse, 2);
+        assert!((report.books[0].max_coverage - 0.30).abs() < 1e-6);
+        assert!((report.books[1].max_coverage - 0.20).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,682 @@
+//! Batch comparison across many book pairs (corpus mode).
+//!
+//! Loads the token mappings once, caches book streams across pairs in a
+//! [`Corpus`] (least recently used first out, within its byte budget), and writes
+//! a fixed output layout so every front-end finds results in the same place:
+//!
+//! ```text
+//! <output_dir>/
+//!   manifest.json
+//!   results/{a}_{b}/result.json
+//!   results/{a}_{b}/summary.json
+//!   results/{a}_{b}/viewer.html
+//! ```
+//!
+//! The manifest records what happened to every pair (see `PairStatus`: found
+//! reuse, found none, lost alignments to a limit, skipped by the vocabulary
+//! prescan, or failed) and the SHA-256 of each file written. A pair that fails
+//! (say, a book missing from corpus.db) is recorded and the run moves on.
+//!
+//! A run can be split across machines with `BatchOptions::shard`: each shard
+//! compares a fixed, deterministic slice of the pairs into its own output
+//! directory, with the same layout, for later aggregation.
+//!
+//! While a run is in progress, each finished pair is also appended to
+//! `checkpoint.jsonl`, so a run interrupted before the manifest is written can
+//! be resumed (`BatchOptions::resume`) without redoing the pairs it finished.
+
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{BufRead, Write};
+use std::path::Path;
+use std::time::Instant;
+use thiserror::Error;
+
+use crate::compare::{
+    chrono_lite_timestamp, compare_token_streams_with_shared_shingles, record_load, resolve_root_modes,
+};
+use crate::corpus::{Corpus, StreamPair};
+use crate::db::DbError;
+use crate::filter::{vocabulary_overlap, CorpusShingles};
+use crate::models::{
+    BatchManifest, CheckpointEntry, CheckpointHeader, ComparisonParams, PairRecord, PairStatus, Shard,
+    WeightSource,
+};
+use crate::output::{
+    read_manifest_file, write_json_with_text_file, write_manifest_file, write_summary_file, write_viewer_html_file,
+    OutputError, ViewerAssets,
+};
+use crate::validate::{
+    validate_book_pair, validate_context_tokens, validate_params, validate_shard, ValidationError,
+};
+
+/// Name of the top-level manifest file.
+pub const MANIFEST_FILE: &str = "manifest.json";
+/// Progress log of an unfinished run, one JSON line per completed pair.
+pub const CHECKPOINT_FILE: &str = "checkpoint.jsonl";
+/// Directory (under the output directory) holding one subdirectory per pair.
+pub const RESULTS_DIR: &str = "results";
+/// Full comparison result with text, in each pair directory.
+pub const RESULT_FILE: &str = "result.json";
+/// Comparison summary alone, in each pair directory.
+pub const SUMMARY_FILE: &str = "summary.json";
+/// Self-contained HTML viewer, in each pair directory.
+pub const VIEWER_FILE: &str = "viewer.html";
+
+#[derive(Error, Debug)]
+pub enum BatchError {
+    #[error(transparent)]
+    Db(#[from] DbError),
+    #[error(transparent)]
+    Output(#[from] OutputError),
+    #[error(transparent)]
+    Invalid(#[from] ValidationError),
+}
+
+/// Batch-level options that don't belong in per-pair `ComparisonParams`.
+#[derive(Debug, Clone)]
+pub struct BatchOptions {
+    /// Skip pairs whose weighted vocabulary overlap is below this floor.
+    /// Most pairs in an all-vs-all run share essentially no content, and the
+    /// prescan costs a single pass over each book's lemmas.
+    pub min_prescan_overlap: Option<f32>,
+    /// Number of context tokens before/after each match
+    pub context_tokens: usize,
+    /// Recompare every pair even if an earlier run's results are reusable
+    pub force: bool,
+    /// Also reuse pairs finished by an interrupted run, from its checkpoint
+    pub resume: bool,
+    /// Only compare the pairs in this shard (None = every pair)
+    pub shard: Option<Shard>,
+    /// Where each pair's viewer.html loads its libraries from
+    pub viewer_assets: ViewerAssets,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            min_prescan_overlap: None,
+            context_tokens: 30,
+            force: false,
+            resume: false,
+            shard: None,
+            viewer_assets: ViewerAssets::Cdn,
+        }
+    }
+}
+
+/// All unordered pairs (a, b) with a before b in the given order.
+pub fn all_pairs(book_ids: &[u32]) -> Vec<(u32, u32)> {
+    let mut pairs = Vec::new();
+    for (i, &a) in book_ids.iter().enumerate() {
+        for &b in &book_ids[i + 1..] {
+            pairs.push((a, b));
+        }
+    }
+    pairs
+}
+
+/// Every unordered pair with one book from each list, for comparing one set
+/// of books against another. A book in both lists is never paired with
+/// itself, and a pair reachable both ways is listed once.
+pub fn cross_pairs(books_a: &[u32], books_b: &[u32]) -> Vec<(u32, u32)> {
+    let mut seen = HashSet::new();
+    let mut pairs = Vec::new();
+    for &a in books_a {
+        for &b in books_b {
+            if a != b && seen.insert((a.min(b), a.max(b))) {
+                pairs.push((a, b));
+            }
+        }
+    }
+    pairs
+}
+
+/// The shard (1-based, out of `count`) an unordered book pair belongs to.
+///
+/// Depends only on the two book IDs, so every node of a split run agrees on
+/// the partition whatever order its book list is in.
+pub fn pair_shard(book_a: u32, book_b: u32, count: u32) -> u32 {
+    let (low, high) = (book_a.min(book_b) as u64, book_a.max(book_b) as u64);
+    // SplitMix64 finalizer: spreads consecutive IDs evenly across shards
+    let mut x = (low << 32 | high).wrapping_add(0x9E37_79B9_7F4A_7C15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    x ^= x >> 31;
+    (x % count.max(1) as u64) as u32 + 1
+}
+
+/// The pairs that fall in `shard`, in their original order.
+pub fn shard_pairs(pairs: &[(u32, u32)], shard: Shard) -> Vec<(u32, u32)> {
+    pairs
+        .iter()
+        .copied()
+        .filter(|&(a, b)| pair_shard(a, b, shard.count) == shard.index)
+        .collect()
+}
+
+/// Directory of a pair's results, relative to the output directory.
+pub fn pair_dir(book_a: u32, book_b: u32) -> String {
+    format!("{}/{}_{}", RESULTS_DIR, book_a, book_b)
+}
+
+/// Hex-encoded SHA-256 of a byte string.
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes).iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Hex-encoded SHA-256 of a file's contents.
+fn file_sha256(path: &Path) -> Result<String, OutputError> {
+    Ok(sha256_hex(&std::fs::read(path)?))
+}
+
+/// Fingerprint of every setting that determines a pair's output files.
+pub fn params_fingerprint(params: &ComparisonParams, options: &BatchOptions) -> String {
+    let settings = serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "parameters": params,
+        "min_prescan_overlap": options.min_prescan_overlap,
+        "context_tokens": options.context_tokens,
+    });
+    sha256_hex(settings.to_string().as_bytes())
+}
+
+/// Cheap fingerprint of the corpus database from its size and modification time.
+///
+/// Hashing the full file would cost more than many comparisons; any rewrite of
+/// the database changes its modification time.
+pub fn database_fingerprint(db_path: &Path) -> Result<String, OutputError> {
+    let meta = std::fs::metadata(db_path)?;
+    let modified = meta
+        .modified()?
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or(0);
+    Ok(sha256_hex(format!("{}:{}", meta.len(), modified).as_bytes()))
+}
+
+/// Results of a previous run in `output_dir` that may be reusable.
+#[derive(Default)]
+struct CachedRun {
+    records: HashMap<(u32, u32), PairRecord>,
+    /// Stream content hashes recorded by that run
+    book_hashes: BTreeMap<u32, String>,
+    /// corpus.db looks untouched since that run
+    db_unchanged: bool,
+}
+
+impl CachedRun {
+    /// Previous records with the same parameter fingerprint whose files (for
+    /// compared pairs) still match their recorded checksums.
+    fn load(output_dir: &Path, params_fingerprint: &str, db_fingerprint: &str) -> Self {
+        let manifest = match read_manifest_file(&output_dir.join(MANIFEST_FILE)) {
+            Ok(manifest) => manifest,
+            Err(_) => return Self::default(),
+        };
+        if manifest.params_fingerprint != params_fingerprint {
+            return Self::default();
+        }
+
+        let records = manifest
+            .pairs
+            .into_iter()
+            .filter(|record| record_files_intact(output_dir, record))
+            .map(|record| ((record.book_a, record.book_b), record))
+            .collect();
+
+        Self {
+            records,
+            book_hashes: manifest.book_hashes,
+            db_unchanged: !manifest.db_fingerprint.is_empty() && manifest.db_fingerprint == db_fingerprint,
+        }
+    }
+
+    /// Add the pairs finished by an interrupted run with the same parameter
+    /// fingerprint. Unreadable lines (a pair cut off mid-write) are skipped.
+    fn resume(&mut self, output_dir: &Path, params_fingerprint: &str, db_fingerprint: &str) {
+        let Ok(file) = std::fs::File::open(output_dir.join(CHECKPOINT_FILE)) else {
+            return;
+        };
+        let mut lines = std::io::BufReader::new(file).lines().map_while(Result::ok);
+        let header = match lines.next().map(|line| serde_json::from_str::<CheckpointHeader>(&line)) {
+            Some(Ok(header)) if header.params_fingerprint == params_fingerprint => header,
+            _ => return,
+        };
+
+        // Stream hashes are only trusted without rechecking if corpus.db is
+        // untouched since both the manifest and the checkpoint were written
+        self.db_unchanged = (self.records.is_empty() || self.db_unchanged) && header.db_fingerprint == db_fingerprint;
+
+        let mut resumed = HashSet::new();
+        for entry in lines.filter_map(|line| serde_json::from_str::<CheckpointEntry>(&line).ok()) {
+            if !record_files_intact(output_dir, &entry.record) {
+                continue;
+            }
+            for (book_id, hash) in entry.book_hashes {
+                // A book updated between the manifest and the checkpoint
+                // invalidates the manifest's pairs involving it
+                if self.book_hashes.get(&book_id).is_some_and(|h| *h != hash) {
+                    self.records
+                        .retain(|key, _| resumed.contains(key) || (key.0 != book_id && key.1 != book_id));
+                }
+                self.book_hashes.insert(book_id, hash);
+            }
+            let key = (entry.record.book_a, entry.record.book_b);
+            resumed.insert(key);
+            self.records.insert(key, entry.record);
+        }
+    }
+
+    /// Whether a book's stream still has the content hash recorded by the earlier run.
+    fn book_unchanged(&self, book_id: u32, current_hash: &str) -> bool {
+        self.book_hashes.get(&book_id).is_some_and(|h| h == current_hash)
+    }
+}
+
+/// Whether a recorded pair's files (if it was compared) still match their checksums.
+///
+/// Failed pairs never count as intact, so a later run retries them.
+pub(crate) fn record_files_intact(output_dir: &Path, record: &PairRecord) -> bool {
+    match (record.status, &record.output) {
+        (PairStatus::SkippedPrescan, _) => true,
+        (PairStatus::Error, _) => false,
+        (status, Some(dir)) if status.is_compared() => {
+            !record.checksums.is_empty()
+                && record.checksums.iter().all(|(file, checksum)| {
+                    file_sha256(&output_dir.join(dir).join(file)).is_ok_and(|c| &c == checksum)
+                })
+        }
+        _ => false,
+    }
+}
+
+/// Append-only log of the pairs finished so far in the current run.
+struct Checkpoint {
+    file: std::fs::File,
+}
+
+impl Checkpoint {
+    /// Start a fresh checkpoint for this run, replacing any earlier one.
+    fn create(output_dir: &Path, header: &CheckpointHeader) -> Result<Self, OutputError> {
+        let mut file = std::fs::File::create(output_dir.join(CHECKPOINT_FILE))?;
+        writeln!(file, "{}", serde_json::to_string(header)?)?;
+        Ok(Self { file })
+    }
+
+    /// Record a finished pair. Each line goes straight to the file, so an
+    /// interruption loses at most the pair being written.
+    fn append(&mut self, entry: &CheckpointEntry) -> Result<(), OutputError> {
+        writeln!(self.file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+}
+
+/// Load what every pair shares: the token mappings, and the corpus-wide
+/// weights and Quran index when the parameters use them.
+fn load_shared(corpus: &mut Corpus, params: &ComparisonParams, show_progress: bool) -> Result<(), DbError> {
+    if show_progress {
+        eprintln!("Loading token mappings (lemma + root + surface)...");
+    }
+    corpus.token_mappings()?;
+    if params.use_weights && params.weight_source == WeightSource::Corpus {
+        if show_progress {
+            eprintln!("Loading corpus-wide IDF weights...");
+        }
+        corpus.corpus_weights()?;
+    }
+    if let Some(book_id) = params.quran_book_id {
+        if show_progress {
+            eprintln!("Indexing the Quran (book {})...", book_id);
+        }
+        corpus.quran_index(book_id)?;
+    }
+    Ok(())
+}
+
+/// Both books' streams from the corpus cache, recording each stream's content
+/// hash the first time it is seen.
+fn load_pair<'p>(
+    corpus: &mut Corpus,
+    hashes: &mut BTreeMap<u32, String>,
+    (book_a, book_b): (u32, u32),
+    params: &'p ComparisonParams,
+) -> Result<StreamPair<'p>, DbError> {
+    let pair = corpus.stream_pair(book_a, book_b, params)?;
+    for stream in [&pair.0, &pair.1] {
+        hashes.entry(stream.book_id).or_insert_with(|| stream.content_hash());
+    }
+    Ok(pair)
+}
+
+/// Compare every pair in `pairs`, writing per-pair result directories and a
+/// `manifest.json` into `output_dir` (see the module docs for the layout).
+///
+/// Unless `options.force` is set, pairs already present in an earlier
+/// `manifest.json` with the same parameter fingerprint are reused without
+/// recomputation. If corpus.db has changed since that run, a pair is only
+/// reused when both books' streams still have the content hashes recorded in
+/// the manifest; pairs involving an updated book are recompared. With
+/// corpus-wide IDF weights every pair is recompared, since the weights depend
+/// on the whole corpus.
+///
+/// With `options.shard`, only the pairs in that shard are compared, and the
+/// manifest records the shard.
+///
+/// Each finished pair is appended to `checkpoint.jsonl`, which is removed once
+/// the manifest is written. With `options.resume`, pairs recorded in the
+/// checkpoint of an interrupted run are reused the same way.
+pub fn compare_book_pairs(
+    db_path: &Path,
+    pairs: &[(u32, u32)],
+    params: &ComparisonParams,
+    options: &BatchOptions,
+    output_dir: &Path,
+    show_progress: bool,
+) -> Result<BatchManifest, BatchError> {
+    validate_params(params)?;
+    validate_context_tokens(options.context_tokens)?;
+    for &(book_a, book_b) in pairs {
+        validate_book_pair(book_a, book_b)?;
+    }
+    let sharded;
+    let pairs = match options.shard {
+        Some(shard) => {
+            validate_shard(shard)?;
+            sharded = shard_pairs(pairs, shard);
+            &sharded[..]
+        }
+        None => pairs,
+    };
+
+    std::fs::create_dir_all(output_dir).map_err(OutputError::from)?;
+
+    let params_fingerprint = params_fingerprint(params, options);
+    let db_fingerprint = database_fingerprint(db_path)?;
+    let mut cached = if options.force {
+        CachedRun::default()
+    } else {
+        CachedRun::load(output_dir, &params_fingerprint, &db_fingerprint)
+    };
+    if options.resume && !options.force {
+        cached.resume(output_dir, &params_fingerprint, &db_fingerprint);
+    }
+    let mut checkpoint = Checkpoint::create(
+        output_dir,
+        &CheckpointHeader {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            params_fingerprint: params_fingerprint.clone(),
+            db_fingerprint: db_fingerprint.clone(),
+        },
+    )?;
+
+    // Token mappings are only loaded once a pair actually needs them
+    let mut corpus = Corpus::open(db_path)?;
+    // Content hash of every stream loaded so far
+    let mut loaded_hashes = BTreeMap::new();
+    // Hashes carried over for books reused from the earlier run without loading
+    let mut book_hashes = BTreeMap::new();
+    // One shingle dictionary for the whole run; each book is shingled once
+    let mut shingles = CorpusShingles::new();
+    let mut records = Vec::with_capacity(pairs.len());
+    // Root modes fall back to lemma mode once the mappings show no root data
+    let mut effective_params = None;
+
+    for (pair_idx, &(book_a, book_b)) in pairs.iter().enumerate() {
+        if let Some(mut record) = cached.records.remove(&(book_a, book_b)) {
+            let reusable = if cached.db_unchanged {
+                for book_id in [book_a, book_b] {
+                    if let Some(hash) = cached.book_hashes.get(&book_id) {
+                        book_hashes.insert(book_id, hash.clone());
+                    }
+                }
+                true
+            } else if params.use_weights && params.weight_source == WeightSource::Corpus {
+                // Corpus-wide weights depend on every book, not just these two
+                false
+            } else {
+                // corpus.db was rewritten: verify both books are unchanged
+                // (a book that no longer loads is recompared, and fails there)
+                load_pair(&mut corpus, &mut loaded_hashes, (book_a, book_b), params).is_ok()
+                    && cached.book_unchanged(book_a, &loaded_hashes[&book_a])
+                    && cached.book_unchanged(book_b, &loaded_hashes[&book_b])
+            };
+
+            if reusable {
+                record.cached = true;
+                if show_progress {
+                    eprintln!("[{}/{}] {} vs {}: cached", pair_idx + 1, pairs.len(), book_a, book_b);
+                }
+                let hashes = pair_hashes(&[&book_hashes, &loaded_hashes], book_a, book_b);
+                checkpoint.append(&CheckpointEntry {
+                    record: record.clone(),
+                    book_hashes: hashes,
+                })?;
+                records.push(record);
+                continue;
+            }
+        }
+
+        // The token mappings are needed by every pair, so failing to load
+        // them ends the run; a book that fails to load only fails its pairs
+        if effective_params.is_none() {
+            load_shared(&mut corpus, params, show_progress)?;
+            let token_to_root = &corpus.token_mappings()?.1;
+            effective_params = Some(resolve_root_modes(params, token_to_root, show_progress)?.into_owned());
+        }
+        let record = match compare_pair(
+            &mut corpus,
+            &mut loaded_hashes,
+            &mut shingles,
+            (book_a, book_b),
+            effective_params.as_ref().expect("resolved above"),
+            options,
+            db_path,
+            output_dir,
+        ) {
+            Ok(record) => record,
+            Err(BatchError::Db(err)) => PairRecord {
+                book_a,
+                book_b,
+                status: PairStatus::Error,
+                prescan_overlap: None,
+                edge_count: 0,
+                output: None,
+                summary: None,
+                checksums: BTreeMap::new(),
+                cached: false,
+                error: Some(err.to_string()),
+            },
+            Err(err) => return Err(err),
+        };
+
+        if show_progress {
+            let status = match record.status {
+                PairStatus::Ok => format!("{} edges", record.edge_count),
+                PairStatus::Empty => "no edges".to_string(),
+                PairStatus::Timeout => format!(
+                    "{} edges, {} alignments abandoned",
+                    record.edge_count,
+                    record.summary.as_ref().map_or(0, |s| s.aborted_alignments)
+                ),
+                PairStatus::SkippedPrescan => format!(
+                    "skipped (overlap {:.3})",
+                    record.prescan_overlap.unwrap_or(0.0)
+                ),
+                PairStatus::Error => format!("error: {}", record.error.as_deref().unwrap_or_default()),
+            };
+            eprintln!(
+                "[{}/{}] {} vs {}: {}",
+                pair_idx + 1,
+                pairs.len(),
+                book_a,
+                book_b,
+                status
+            );
+        }
+
+        checkpoint.append(&CheckpointEntry {
+            record: record.clone(),
+            book_hashes: pair_hashes(&[&loaded_hashes], book_a, book_b),
+        })?;
+        records.push(record);
+    }
+
+    // Hashes of freshly loaded streams take precedence over carried-over ones
+    book_hashes.extend(loaded_hashes);
+
+    let manifest = BatchManifest {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        generated_at: chrono_lite_timestamp(),
+        parameters: params.clone(),
+        params_fingerprint,
+        db_fingerprint,
+        book_hashes,
+        min_prescan_overlap: options.min_prescan_overlap,
+        shard: options.shard,
+        pairs: records,
+    };
+
+    write_manifest_file(&manifest, &output_dir.join(MANIFEST_FILE))?;
+    drop(checkpoint);
+    std::fs::remove_file(output_dir.join(CHECKPOINT_FILE)).map_err(OutputError::from)?;
+
+    Ok(manifest)
+}
+
+/// Prescan and compare one pair, writing its result files.
+#[allow(clippy::too_many_arguments)]
+fn compare_pair(
+    corpus: &mut Corpus,
+    hashes: &mut BTreeMap<u32, String>,
+    shingles: &mut CorpusShingles,
+    (book_a, book_b): (u32, u32),
+    params: &ComparisonParams,
+    options: &BatchOptions,
+    db_path: &Path,
+    output_dir: &Path,
+) -> Result<PairRecord, BatchError> {
+    let load_start = Instant::now();
+    let (stream_a, stream_b, params) = load_pair(corpus, hashes, (book_a, book_b), params)?;
+    let load_ms = load_start.elapsed().as_millis() as u64;
+
+    // Cheap vocabulary prescan before any windowing or alignment
+    let prescan_overlap = options.min_prescan_overlap.map(|_| {
+        vocabulary_overlap(&stream_a.flat_lemma_ids(), &stream_b.flat_lemma_ids())
+    });
+
+    let skip = match (prescan_overlap, options.min_prescan_overlap) {
+        (Some(overlap), Some(floor)) => overlap < floor,
+        _ => false,
+    };
+
+    let record = if skip {
+        PairRecord {
+            book_a,
+            book_b,
+            status: PairStatus::SkippedPrescan,
+            prescan_overlap,
+            edge_count: 0,
+            output: None,
+            summary: None,
+            checksums: BTreeMap::new(),
+            cached: false,
+            error: None,
+        }
+    } else {
+        let stop_lemmas = corpus.stop_lemmas(&params)?;
+        let mut result = compare_token_streams_with_shared_shingles(
+            &stream_a,
+            &stream_b,
+            &corpus.token_mappings()?.2,
+            &params,
+            &stop_lemmas,
+            options.context_tokens,
+            shingles,
+            false,
+        )?;
+        record_load(&mut result.run_info, db_path, load_ms);
+
+        let dir_name = pair_dir(book_a, book_b);
+        let dir = output_dir.join(&dir_name);
+        std::fs::create_dir_all(&dir).map_err(OutputError::from)?;
+
+        write_json_with_text_file(&result, &dir.join(RESULT_FILE))?;
+        write_summary_file(&result.summary, &dir.join(SUMMARY_FILE))?;
+        write_viewer_html_file(&result, &options.viewer_assets, &dir.join(VIEWER_FILE))?;
+
+        let mut checksums = BTreeMap::new();
+        for file in [RESULT_FILE, SUMMARY_FILE, VIEWER_FILE] {
+            checksums.insert(file.to_string(), file_sha256(&dir.join(file))?);
+        }
+
+        PairRecord {
+            book_a,
+            book_b,
+            status: PairStatus::from_summary(&result.summary),
+            prescan_overlap,
+            edge_count: result.edges.len(),
+            output: Some(dir_name),
+            summary: Some(result.summary.clone()),
+            checksums,
+            cached: false,
+            error: None,
+        }
+    };
+
+    Ok(record)
+}
+
+/// The known stream hashes of a pair's two books, first source first.
+fn pair_hashes(sources: &[&BTreeMap<u32, String>], book_a: u32, book_b: u32) -> BTreeMap<u32, String> {
+    [book_a, book_b]
+        .into_iter()
+        .filter_map(|book_id| {
+            sources
+                .iter()
+                .find_map(|hashes| hashes.get(&book_id))
+                .map(|hash| (book_id, hash.clone()))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_all_pairs() {
+        assert_eq!(all_pairs(&[1, 2, 3]), vec![(1, 2), (1, 3), (2, 3)]);
+        assert!(all_pairs(&[1]).is_empty());
+        assert!(all_pairs(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_cross_pairs() {
+        assert_eq!(cross_pairs(&[1, 2], &[3, 4]), vec![(1, 3), (1, 4), (2, 3), (2, 4)]);
+        // Shared book 2: no self-pair, and 2-1 is not repeated after 1-2
+        assert_eq!(cross_pairs(&[1, 2], &[1, 2, 3]), vec![(1, 2), (1, 3), (2, 3)]);
+        assert!(cross_pairs(&[1], &[]).is_empty());
+    }
+
+    #[test]
+    fn test_shard_pairs_partition() {
+        let pairs = all_pairs(&(1..=40).collect::<Vec<_>>());
+        let shards: Vec<Vec<(u32, u32)>> = (1..=4)
+            .map(|index| shard_pairs(&pairs, Shard { index, count: 4 }))
+            .collect();
+
+        // Every pair lands in exactly one shard, and the shards are balanced
+        assert_eq!(shards.iter().map(Vec::len).sum::<usize>(), pairs.len());
+        for shard in &shards {
+            assert!(shard.len() > pairs.len() / 8, "unbalanced: {}", shard.len());
+        }
+        // Book order does not change a pair's shard
+        assert_eq!(pair_shard(3, 17, 4), pair_shard(17, 3, 4));
+        assert_eq!(shard_pairs(&pairs, Shard { index: 1, count: 1 }), pairs);
+    }
+
+    #[test]
+    fn test_pair_dir() {
+        assert_eq!(pair_dir(230, 553), "results/230_553");
+    }
+}
@@ -0,0 +1,82 @@
+//! Bootstrap confidence intervals for coverage figures.
+//!
+//! Coverage is a ratio of covered tokens to book tokens. Reuse clusters on
+//! pages, so tokens aren't independent; we resample whole pages with
+//! replacement and recompute the ratio, taking the 2.5th and 97.5th
+//! percentiles as a 95% interval.
+
+use crate::rng::SplitMix64;
+
+/// Fixed seed so that repeated runs report the same interval.
+pub const BOOTSTRAP_SEED: u64 = 0x6b61_7368_7368_6166;
+
+/// 95% percentile-bootstrap interval for `sum(covered) / sum(sizes)` over pages.
+///
+/// `covered[i]` and `sizes[i]` are the covered and total token counts of page
+/// `i`. Returns None if there are no pages or no samples requested.
+pub fn bootstrap_coverage_interval(
+    covered: &[usize],
+    sizes: &[usize],
+    samples: usize,
+    seed: u64,
+) -> Option<(f32, f32)> {
+    let pages = covered.len().min(sizes.len());
+    if pages == 0 || samples == 0 {
+        return None;
+    }
+
+    let mut rng = SplitMix64::new(seed);
+    let mut estimates: Vec<f32> = (0..samples)
+        .map(|_| {
+            let mut covered_sum = 0usize;
+            let mut size_sum = 0usize;
+            for _ in 0..pages {
+                let page = rng.below(pages);
+                covered_sum += covered[page];
+                size_sum += sizes[page];
+            }
+            if size_sum == 0 {
+                0.0
+            } else {
+                covered_sum as f32 / size_sum as f32
+            }
+        })
+        .collect();
+
+    estimates.sort_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    let lower = estimates[((samples as f32 * 0.025) as usize).min(samples - 1)];
+    let upper = estimates[((samples as f32 * 0.975) as usize).min(samples - 1)];
+
+    Some((lower, upper))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interval_brackets_point_estimate() {
+        let sizes = vec![100; 40];
+        let covered: Vec<usize> = (0..40).map(|i| if i % 4 == 0 { 80 } else { 0 }).collect();
+        let point = covered.iter().sum::<usize>() as f32 / sizes.iter().sum::<usize>() as f32;
+
+        let (lo, hi) = bootstrap_coverage_interval(&covered, &sizes, 1000, BOOTSTRAP_SEED).unwrap();
+        assert!(lo <= point && point <= hi);
+        assert!(hi > lo);
+    }
+
+    #[test]
+    fn test_uniform_coverage_has_zero_width() {
+        let sizes = vec![50; 10];
+        let covered = vec![25; 10];
+        let (lo, hi) = bootstrap_coverage_interval(&covered, &sizes, 200, 1).unwrap();
+        assert!((lo - 0.5).abs() < 1e-6);
+        assert!((hi - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_empty_input() {
+        assert!(bootstrap_coverage_interval(&[], &[], 100, 1).is_none());
+        assert!(bootstrap_coverage_interval(&[1], &[2], 0, 1).is_none());
+    }
+}
@@ -5,18 +5,50 @@
 
 use indicatif::{ProgressBar, ProgressStyle};
 use rayon::prelude::*;
+use rusqlite::Connection;
+use std::borrow::Cow;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::path::Path;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::Instant;
 
-use crate::align::{align_sequences, align_sequences_weighted};
+use crate::align::{
+    align_sequences_weighted_with_alternatives, align_sequences_with_alternatives,
+    try_align_sequences_weighted_with_alternatives, try_align_sequences_with_alternatives, AlignmentAborted,
+    LINEAR_SPACE_MIN_CELLS,
+};
+use crate::batch::database_fingerprint;
+use crate::bootstrap::{bootstrap_coverage_interval, BOOTSTRAP_SEED};
+use crate::corpus::Corpus;
+use crate::db::import::{create_schema, parse_plain_text, CorpusWriter, Lexicon};
 use crate::db::{
-    load_all_token_mappings, load_book_lemma_stream, load_book_token_stream_with_root,
-    load_token_to_lemma, DbError,
+    find_surface_token_ids, has_root_data, load_all_token_mappings, load_book_lemma_stream, load_book_token_stream_with_root,
+    load_lemma_alternatives, load_lemma_root_mappings, load_surface_classes, load_surfaces, load_token_to_lemma, DbError,
+};
+use crate::filter::{
+    find_candidate_pairs, find_candidate_pairs_with_counts, find_capped_candidate_pairs, span_shingle_jaccard, weighted_shingle_overlap,
+    weighted_shingles, CorpusShingles,
 };
-use crate::filter::find_candidate_pairs;
-use crate::merge::merge_overlapping_edges;
+use crate::idf::{corpus_weights_for, stop_lemmas_for};
+use crate::merge::{
+    chain_collinear_edges, merge_overlapping_edges, merge_overlapping_edges_gated, refine_merged_edges,
+};
+use crate::metrics;
 use crate::models::*;
-use crate::window::{generate_windows, generate_windows_with_roots};
+use crate::normalize::{NormalizationConfig, SurfaceClasses};
+use crate::quran::{quran_index_for, tag_quran_edges};
+use crate::script::{is_non_arabic_token, tag_suspect_edges, NonArabicTokens};
+use crate::segment::{isnad_marker_matcher, isnad_segments, IsnadMarkers};
+use crate::signature::{span_signature, StopLemmas};
+use crate::source::SqliteSource;
+use crate::tokenize::SimpleTokenizer;
+use crate::verse::{is_hemistich_marker, tag_verse_edges, VerseMarkers};
+use crate::window::{
+    generate_segment_windows, generate_windows, generate_windows_with_roots, retain_windows_in_range,
+    window_for_lemma_range,
+    window_for_range,
+};
 
 /// Static counter for generating unique edge IDs
 static EDGE_COUNTER: AtomicU64 = AtomicU64::new(0);
@@ -29,6 +61,8 @@ pub fn compare_books(
     params: &ComparisonParams,
     show_progress: bool,
 ) -> Result<ComparisonResult, DbError> {
+    let mut stage = Instant::now();
+
     // Load token->lemma mapping
     if show_progress {
         eprintln!("Loading token-to-lemma mapping...");
@@ -45,25 +79,58 @@ pub fn compare_books(
         eprintln!("Loading book {} lemma stream...", book_b_id);
     }
     let stream_b = load_book_lemma_stream(db_path, book_b_id, &token_to_lemma)?;
+    let load_ms = lap(&mut stage);
 
-    compare_books_from_streams(&stream_a, &stream_b, params, show_progress)
+    let mut result = compare_books_from_streams(&stream_a, &stream_b, params, show_progress)?;
+    record_load(&mut result.run_info, db_path, load_ms);
+    Ok(result)
 }
 
 /// Compare two books given their already-loaded lemma streams.
 /// Note: This function uses lemma-only matching for backward compatibility.
-/// For root-based matching, use compare_books_from_token_streams.
+/// For root-based matching, use compare_books_from_token_streams. Surface
+/// mode also matches lemmas here, as lemma streams carry no token IDs.
 pub fn compare_books_from_streams(
     stream_a: &BookLemmaStream,
     stream_b: &BookLemmaStream,
     params: &ComparisonParams,
     show_progress: bool,
 ) -> Result<ComparisonResult, DbError> {
+    let mut run_info = RunInfo::default();
+    let run_start = Instant::now();
+    let mut stage = Instant::now();
+
+    // Build lemma weights for IDF weighting (if enabled)
+    let (weights_a, weights_b) = if params.use_weights {
+        if show_progress {
+            eprintln!("Building document-internal IDF weights...");
+        }
+        let lemmas_a = stream_a.flat_lemmas();
+        let lemmas_b = stream_b.flat_lemmas();
+        let max_lemma_id = lemmas_a
+            .iter()
+            .chain(lemmas_b.iter())
+            .copied()
+            .max()
+            .unwrap_or(0) as usize;
+        (build_lemma_weights(&lemmas_a, max_lemma_id), build_lemma_weights(&lemmas_b, max_lemma_id))
+    } else {
+        (Vec::new(), Vec::new())
+    };
+
     // Generate windows
     if show_progress {
+        if params.segmentation == Segmentation::Hadith {
+            eprintln!("Warning: hadith segmentation needs token streams; using windows.");
+        }
         eprintln!("Generating windows...");
     }
-    let windows_a = generate_windows(stream_a, params);
+    let mut windows_a = generate_windows(stream_a, params);
+    if let Some(range) = params.source_range {
+        retain_windows_in_range(&mut windows_a, range);
+    }
     let windows_b = generate_windows(stream_b, params);
+    run_info.window_ms = lap(&mut stage);
 
     if show_progress {
         eprintln!("  Book A: {} windows ({} tokens)", windows_a.len(), stream_a.total_tokens);
@@ -81,7 +148,7 @@ pub fn compare_books_from_streams(
             eprintln!("Finding candidate pairs (n-gram filtering)...");
         }
     }
-    let candidates = find_candidate_pairs(&windows_a, &windows_b, params);
+    let (mut candidates, cap) = find_capped_candidate_pairs(&windows_a, &windows_b, params);
 
     if show_progress {
         let total_pairs = windows_a.len() * windows_b.len();
@@ -95,9 +162,34 @@ pub fn compare_books_from_streams(
             candidates.len(),
             filter_rate
         );
+        if cap.dropped_pairs > 0 {
+            eprintln!(
+                "  Candidate cap: {} pairs dropped from {} windows",
+                cap.dropped_pairs, cap.capped_windows
+            );
+        }
+    }
+
+    // Two-pass mode: only align candidates inside the coarse pass's reuse regions
+    let mut coarse_aborted = 0;
+    if let Some(coarse) = coarse_params(params) {
+        if show_progress {
+            eprintln!("Coarse pass (window {}, stride {})...", coarse.window_size, coarse.stride);
+        }
+        let coarse_result = compare_books_from_streams(stream_a, stream_b, &coarse, false)?;
+        coarse_aborted = coarse_result.summary.aborted_alignments;
+        let regions = coarse_regions(&coarse_result.edges, params.window_size);
+        retain_candidates_in_regions(&mut candidates, &windows_a, &windows_b, &regions);
+        if show_progress {
+            eprintln!("  Reuse regions: {} ({} candidate pairs inside)", regions.len(), candidates.len());
+        }
     }
 
-    // Align candidate pairs in parallel
+    run_info.filter_ms = lap(&mut stage);
+
+    // Align candidate pairs in parallel; pathological pairs past the
+    // alignment limits are counted and skipped
+    let aborted = AtomicUsize::new(0);
     let progress = if show_progress {
         let pb = ProgressBar::new(candidates.len() as u64);
         pb.set_style(
@@ -119,66 +211,121 @@ pub fn compare_books_from_streams(
             let window_a = &windows_a[idx_a];
             let window_b = &windows_b[idx_b];
 
-            // Use align_sequences with root support (root_ids are empty for lemma streams)
-            let alignment = align_sequences(
-                &window_a.lemma_ids,
-                &window_b.lemma_ids,
-                &window_a.root_ids,
-                &window_b.root_ids,
-                params,
-            )?;
+            // Root IDs are empty for lemma streams, so root matching never fires here
+            let outcome = if params.use_weights && !weights_a.is_empty() {
+                try_align_sequences_weighted_with_alternatives(
+                    &window_a.lemma_ids,
+                    &window_b.lemma_ids,
+                    &window_a.root_ids,
+                    &window_b.root_ids,
+                    &[],
+                    &[],
+                    &weights_a,
+                    &weights_b,
+                    params,
+                )
+            } else {
+                try_align_sequences_with_alternatives(
+                    &window_a.lemma_ids,
+                    &window_b.lemma_ids,
+                    &window_a.root_ids,
+                    &window_b.root_ids,
+                    &[],
+                    &[],
+                    params,
+                )
+            };
+            let alignment = match outcome {
+                Ok(alignment) => alignment?,
+                Err(reason) => {
+                    note_aborted(reason, window_a, window_b, &aborted, progress.as_ref());
+                    return None;
+                }
+            };
 
             if let Some(ref pb) = progress {
                 pb.inc(1);
             }
 
             // Convert alignment to edge
-            Some(alignment_to_edge(window_a, window_b, &alignment))
+            Some(alignment_to_edge(window_a, window_b, &alignment, params.ngram_size))
         })
         .collect();
+    run_info.align_ms = lap(&mut stage);
 
     if let Some(pb) = progress {
         pb.finish_with_message("Done");
     }
+    let fine_aborted = aborted.into_inner();
+    let aborted = fine_aborted + coarse_aborted;
+    if show_progress && aborted > 0 {
+        eprintln!("  Abandoned alignments: {}", aborted);
+    }
 
     // Merge overlapping edges
     if show_progress {
         eprintln!("Merging overlapping edges ({} raw edges)...", edges.len());
     }
-    let merged_edges = merge_overlapping_edges(edges);
+    let raw_edges = edges.len();
+    let merged_edges = merge_edges_by_params(edges, params, &weights_a, &weights_b, |edge| {
+        (
+            window_for_lemma_range(stream_a, (edge.source_global_start, edge.source_global_end)),
+            window_for_lemma_range(stream_b, (edge.target_global_start, edge.target_global_end)),
+        )
+    });
 
     if show_progress {
         eprintln!("  Merged edges: {}", merged_edges.len());
     }
 
+    run_info.merge_ms = lap(&mut stage);
+
     // Apply metric-based filters
     let filtered_edges = filter_edges_by_params(&merged_edges, params);
 
     if show_progress && filtered_edges.len() != merged_edges.len() {
         eprintln!("  After filtering: {}", filtered_edges.len());
     }
+    let macro_edges = match params.chain_gap {
+        Some(max_gap) => chain_collinear_edges(&filtered_edges, max_gap),
+        None => Vec::new(),
+    };
+    if show_progress && !macro_edges.is_empty() {
+        eprintln!("  Macro edges: {}", macro_edges.len());
+    }
 
     // Build result
     let summary = ComparisonSummary {
-        edge_count: filtered_edges.len(),
-        total_aligned_tokens: filtered_edges
-            .iter()
-            .map(|e| e.aligned_length as usize)
-            .sum(),
-        book_a_coverage: calculate_coverage(&filtered_edges, stream_a.book_id, stream_a.total_tokens),
-        book_b_coverage: calculate_coverage(&filtered_edges, stream_b.book_id, stream_b.total_tokens),
-        avg_similarity: if filtered_edges.is_empty() {
-            0.0
-        } else {
-            filtered_edges.iter().map(|e| e.lemma_similarity).sum::<f32>()
-                / filtered_edges.len() as f32
-        },
-        avg_weighted_similarity: if filtered_edges.is_empty() {
-            0.0
-        } else {
-            filtered_edges.iter().map(|e| e.weighted_similarity).sum::<f32>()
-                / filtered_edges.len() as f32
-        },
+        book_a_coverage_ci: params.bootstrap_samples.and_then(|samples| {
+            let sizes: Vec<usize> = stream_a.pages.iter().map(|p| p.lemma_ids.len()).collect();
+            coverage_interval(&filtered_edges, stream_a.book_id, &sizes, samples)
+        }),
+        book_b_coverage_ci: params.bootstrap_samples.and_then(|samples| {
+            let sizes: Vec<usize> = stream_b.pages.iter().map(|p| p.lemma_ids.len()).collect();
+            coverage_interval(&filtered_edges, stream_b.book_id, &sizes, samples)
+        }),
+        aborted_alignments: aborted,
+        ..summarize_edges(
+            &filtered_edges,
+            (stream_a.book_id, stream_a.total_tokens),
+            (stream_b.book_id, stream_b.total_tokens),
+        )
+    };
+    run_info.filter_metrics_ms = lap(&mut stage);
+    run_info.wall_ms = run_start.elapsed().as_millis() as u64;
+    run_info.hostname = hostname();
+    run_info.peak_rss_bytes = peak_rss_bytes();
+    run_info.stats = RunStats {
+        source_windows: windows_a.len(),
+        target_windows: windows_b.len(),
+        candidate_pairs: candidates.len(),
+        capped_pairs: cap.dropped_pairs,
+        capped_windows: cap.capped_windows,
+        alignments: candidates.len() - fine_aborted,
+        rejected_alignments: candidates.len() - fine_aborted - raw_edges,
+        raw_edges,
+        merged_edges: merged_edges.len(),
+        dropped_edges: merged_edges.len() - filtered_edges.len(),
     };
 
     Ok(ComparisonResult {
@@ -188,19 +335,48 @@ pub fn compare_books_from_streams(
             id: stream_a.book_id,
             token_count: stream_a.total_tokens as u64,
             page_count: stream_a.page_count() as u32,
+            stream_hash: stream_a.content_hash(),
             ..Default::default()
         },
         book_b: BookMetadata {
             id: stream_b.book_id,
             token_count: stream_b.total_tokens as u64,
             page_count: stream_b.page_count() as u32,
+            stream_hash: stream_b.content_hash(),
             ..Default::default()
         },
         summary,
+        coverage_profiles: coverage_profiles(
+            &filtered_edges,
+            [
+                (stream_a.book_id, stream_a.pages.iter().map(|p| p.lemma_ids.len()).collect()),
+                (stream_b.book_id, stream_b.pages.iter().map(|p| p.lemma_ids.len()).collect()),
+            ],
+            params,
+        ),
         edges: filtered_edges,
+        macro_edges,
+        run_info,
     })
 }
 
+/// Count an alignment abandoned at the limits, and note where it was.
+fn note_aborted(
+    reason: AlignmentAborted,
+    window_a: &Window,
+    window_b: &Window,
+    aborted: &AtomicUsize,
+    progress: Option<&ProgressBar>,
+) {
+    aborted.fetch_add(1, Ordering::Relaxed);
+    if let Some(pb) = progress {
+        pb.println(format!(
+            "  Alignment abandoned ({}): source window at {}, target window at {}",
+            reason, window_a.global_start, window_b.global_start
+        ));
+    }
+}
+
 /// Filter edges based on the metric parameters.
 fn filter_edges_by_params(edges: &[ReuseEdge], params: &ComparisonParams) -> Vec<ReuseEdge> {
     edges
@@ -234,65 +410,93 @@ fn filter_edges_by_params(edges: &[ReuseEdge], params: &ComparisonParams) -> Vec
                     return false;
                 }
             }
+            if let Some(min) = params.min_span_jaccard {
+                if edge.shingle_jaccard < min {
+                    return false;
+                }
+            }
+            // Verse is quoted verbatim far more than prose
+            if let Some(min) = params.min_verse_core_similarity {
+                if edge.is_verse && edge.core_similarity < min {
+                    return false;
+                }
+            }
+            if params.drop_suspect && edge.suspect {
+                return false;
+            }
+            // Shared scripture rather than borrowing between the two books
+            if let Some(max) = params.max_quran_overlap {
+                if edge.quran_overlap > max {
+                    return false;
+                }
+            }
+            // Abridgment or expansion: one span much longer than the other
+            if let Some(max) = params.max_span_asymmetry {
+                if metrics::span_asymmetry(edge.source_span_length, edge.target_span_length) > max {
+                    return false;
+                }
+            }
             true
         })
         .cloned()
         .collect()
 }
 
+/// Align one window pair, weighted when `params` asks for weights and they
+/// are available. Alternative lemmas are empty unless attached to the streams.
+pub(crate) fn align_window_pair(
+    window_a: &Window,
+    window_b: &Window,
+    weights_a: &[f32],
+    weights_b: &[f32],
+    params: &ComparisonParams,
+) -> Result<Option<Alignment>, AlignmentAborted> {
+    if params.use_weights && !weights_a.is_empty() {
+        try_align_sequences_weighted_with_alternatives(
+            &window_a.lemma_ids,
+            &window_b.lemma_ids,
+            &window_a.root_ids,
+            &window_b.root_ids,
+            &window_a.alt_lemma_ids,
+            &window_b.alt_lemma_ids,
+            weights_a,
+            weights_b,
+            params,
+        )
+    } else {
+        try_align_sequences_with_alternatives(
+            &window_a.lemma_ids,
+            &window_b.lemma_ids,
+            &window_a.root_ids,
+            &window_b.root_ids,
+            &window_a.alt_lemma_ids,
+            &window_b.alt_lemma_ids,
+            params,
+        )
+    }
+}
+
 /// Convert an alignment result to a ReuseEdge.
-fn alignment_to_edge(window_a: &Window, window_b: &Window, alignment: &Alignment) -> ReuseEdge {
+fn alignment_to_edge(
+    window_a: &Window,
+    window_b: &Window,
+    alignment: &Alignment,
+    ngram_size: usize,
+) -> ReuseEdge {
     let id = EDGE_COUNTER.fetch_add(1, Ordering::Relaxed);
 
     // aligned_length includes diagonal moves (aligned_pairs) + gaps
-    let aligned_length = alignment.aligned_pairs.len() as u32 + alignment.gaps;
-    let aligned_len_f32 = aligned_length as f32;
-
-    // === Three orthogonal metrics ===
-
-    // Core similarity: quotation exactness (ignores gaps)
-    // matches / (matches + substitutions) - how exact is the quoted content
-    let match_sub_total = alignment.lemma_matches + alignment.substitutions;
-    let core_similarity = if match_sub_total > 0 {
-        alignment.lemma_matches as f32 / match_sub_total as f32
-    } else {
-        0.0
-    };
-
-    // Span coverage: reuse vs padding ratio
-    // (matches + substitutions) / aligned_length - how much is actual content
-    let span_coverage = if aligned_length > 0 {
-        match_sub_total as f32 / aligned_len_f32
-    } else {
-        0.0
-    };
-
-    // Content weight: average IDF of matched lemmas
-    let content_weight = if alignment.lemma_matches > 0 {
-        alignment.match_weight_sum / alignment.lemma_matches as f32
-    } else {
-        0.0
-    };
-
-    // === Legacy metrics (for backward compatibility) ===
-
-    let lemma_similarity = if aligned_len_f32 > 0.0 {
-        alignment.lemma_matches as f32 / aligned_len_f32
-    } else {
-        0.0
-    };
-
-    let combined_similarity = if aligned_len_f32 > 0.0 {
-        (alignment.lemma_matches as f32 + 0.5 * alignment.root_only_matches as f32) / aligned_len_f32
-    } else {
-        0.0
-    };
-
-    let weighted_similarity = if aligned_len_f32 > 0.0 {
-        alignment.match_weight_sum / aligned_len_f32
-    } else {
-        0.0
-    };
+    let aligned_length = metrics::aligned_length(alignment);
+    let m = metrics::compute_edge_metrics(alignment, aligned_length);
+    let source_span_length = (alignment.end_a - alignment.start_a) as u32;
+    let target_span_length = (alignment.end_b - alignment.start_b) as u32;
+
+    // Shingle Jaccard over the matched spans: robust to gap placement
+    let shingle_jaccard = span_shingle_jaccard(
+        &window_a.lemma_ids[alignment.start_a..alignment.end_a],
+        &window_b.lemma_ids[alignment.start_b..alignment.end_b],
+        ngram_size,
+    );
 
     ReuseEdge {
         id,
@@ -315,170 +519,1539 @@ fn alignment_to_edge(window_a: &Window, window_b: &Window, alignment: &Alignment
         substitutions: alignment.substitutions,
         root_only_matches: alignment.root_only_matches,
         gaps: alignment.gaps,
-        core_similarity,
-        span_coverage,
-        content_weight,
+        core_similarity: m.core_similarity,
+        span_coverage: m.span_coverage,
+        content_weight: m.content_weight,
         lexical_diversity: alignment.lexical_diversity,
-        lemma_similarity,
-        combined_similarity,
-        weighted_similarity,
-        avg_match_weight: content_weight, // Same as content_weight
+        shingle_jaccard,
+        is_verse: false, // Tagged after merging, where surface forms are known
+        suspect: false,
+        quran_overlap: 0.0,
+        source_span_length,
+        target_span_length,
+        span_ratio: metrics::span_ratio(source_span_length, target_span_length),
+        direction: Direction::Unknown, // Set from metadata, where it is known
+        validation: None,
+        lemma_similarity: m.lemma_similarity,
+        combined_similarity: m.combined_similarity,
+        weighted_similarity: m.weighted_similarity,
+        avg_match_weight: m.content_weight, // Same as content_weight
+        score: alignment.score,
+        normalized_score: m.normalized_score,
     }
 }
 
-/// Calculate coverage as the fraction of a book covered by reuse edges.
-fn calculate_coverage(edges: &[ReuseEdge], book_id: u32, total_tokens: usize) -> f32 {
-    if total_tokens == 0 {
-        return 0.0;
-    }
+/// Re-align a widened region around an existing edge and return the maximal
+/// alignment.
+///
+/// Both spans are padded by `params.window_size` tokens on each side (clamped
+/// to the books) and aligned afresh, so a match that was cut short at a
+/// window boundary can grow to its full extent. The original edge is returned
+/// unchanged when the re-alignment finds nothing, drifts off the edge, or
+/// comes out shorter.
+pub fn expand_edge(
+    edge: &ReuseEdge,
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+    params: &ComparisonParams,
+) -> ReuseEdge {
+    let (weights_a, weights_b) = stream_weights(stream_a, stream_b, params);
+    expand_edge_with_weights(edge, stream_a, stream_b, &weights_a, &weights_b, params)
+}
 
-    // Calculate unique covered positions (accounting for overlaps)
-    let mut covered_ranges: Vec<(usize, usize)> = edges
-        .iter()
-        .filter_map(|e| {
-            if e.source_book_id == book_id {
-                Some((e.source_global_start, e.source_global_end))
-            } else if e.target_book_id == book_id {
-                Some((e.target_global_start, e.target_global_end))
-            } else {
-                None
-            }
-        })
-        .collect();
+/// Expand every edge of a result against the same pair of streams.
+/// IDF weights are built once for the whole list.
+pub fn expand_edges(
+    edges: &[ReuseEdge],
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+    params: &ComparisonParams,
+) -> Vec<ReuseEdge> {
+    let (weights_a, weights_b) = stream_weights(stream_a, stream_b, params);
+    edges
+        .par_iter()
+        .map(|edge| expand_edge_with_weights(edge, stream_a, stream_b, &weights_a, &weights_b, params))
+        .collect()
+}
 
-    // Sort and merge overlapping ranges
-    covered_ranges.sort_by_key(|r| r.0);
-    let merged_ranges = merge_ranges(&covered_ranges);
+/// Expand edges read back from an earlier run, loading each book they
+/// reference from corpus.db once. Edges keep their order.
+pub fn expand_edges_from_db(
+    edges: &[ReuseEdge],
+    db_path: &Path,
+    params: &ComparisonParams,
+    show_progress: bool,
+) -> Result<Vec<ReuseEdge>, DbError> {
+    let (token_to_lemma, token_to_root, _) = load_all_token_mappings(db_path)?;
+    let params = &*resolve_root_modes(params, &token_to_root, show_progress)?;
+    let alternatives = if params.use_lemma_alternatives {
+        Some(load_lemma_alternatives(db_path)?)
+    } else {
+        None
+    };
+    let corpus_weights = corpus_weights_for(params, db_path, &token_to_lemma)?;
 
-    // Calculate total covered tokens
-    let covered: usize = merged_ranges.iter().map(|(s, e)| e - s).sum();
+    let mut pairs: BTreeMap<(u32, u32), Vec<usize>> = BTreeMap::new();
+    for (idx, edge) in edges.iter().enumerate() {
+        pairs.entry((edge.source_book_id, edge.target_book_id)).or_default().push(idx);
+    }
 
-    covered as f32 / total_tokens as f32
-}
+    let mut streams: HashMap<u32, BookTokenStream> = HashMap::new();
+    let mut expanded = edges.to_vec();
+    for ((book_a, book_b), indices) in pairs {
+        for book_id in [book_a, book_b] {
+            if let Entry::Vacant(slot) = streams.entry(book_id) {
+                if show_progress {
+                    eprintln!("Loading book {} token stream...", book_id);
+                }
+                let mut stream =
+                    load_book_token_stream_with_root(db_path, book_id, &token_to_lemma, &token_to_root)?;
+                if let Some(alternatives) = &alternatives {
+                    stream.attach_lemma_alternatives(alternatives);
+                }
+                if let Some(weights) = &corpus_weights {
+                    stream.attach_corpus_weights(weights);
+                }
+                slot.insert(stream);
+            }
+        }
 
-/// Merge overlapping ranges into non-overlapping ranges.
-fn merge_ranges(ranges: &[(usize, usize)]) -> Vec<(usize, usize)> {
-    if ranges.is_empty() {
-        return Vec::new();
+        if show_progress {
+            eprintln!("Expanding {} edges between books {} and {}...", indices.len(), book_a, book_b);
+        }
+        let pair_edges: Vec<ReuseEdge> = indices.iter().map(|&i| edges[i].clone()).collect();
+        let grown = expand_edges(&pair_edges, &streams[&book_a], &streams[&book_b], params);
+        for (i, edge) in indices.into_iter().zip(grown) {
+            expanded[i] = edge;
+        }
     }
 
-    let mut merged: Vec<(usize, usize)> = Vec::new();
-    let mut current = ranges[0];
+    Ok(expanded)
+}
 
-    for &(start, end) in &ranges[1..] {
-        if start <= current.1 {
-            // Overlapping - extend current range
-            current.1 = current.1.max(end);
-        } else {
-            // Non-overlapping - save current and start new
-            merged.push(current);
-            current = (start, end);
-        }
+fn expand_edge_with_weights(
+    edge: &ReuseEdge,
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+    weights_a: &[f32],
+    weights_b: &[f32],
+    params: &ComparisonParams,
+) -> ReuseEdge {
+    let margin = params.window_size;
+    let window_a = window_for_range(
+        stream_a,
+        (edge.source_global_start.saturating_sub(margin), edge.source_global_end + margin),
+    );
+    let window_b = window_for_range(
+        stream_b,
+        (edge.target_global_start.saturating_sub(margin), edge.target_global_end + margin),
+    );
+
+    let Some(expanded) = realign_windows(&window_a, &window_b, weights_a, weights_b, params) else {
+        return edge.clone();
+    };
+
+    // Local alignment may settle on a stronger neighbouring match instead
+    let stays_on_edge = expanded.source_global_start < edge.source_global_end
+        && expanded.source_global_end > edge.source_global_start
+        && expanded.target_global_start < edge.target_global_end
+        && expanded.target_global_end > edge.target_global_start;
+    if !stays_on_edge || expanded.aligned_length < edge.aligned_length {
+        return edge.clone();
     }
-    merged.push(current);
 
-    merged
+    ReuseEdge { id: edge.id, ..expanded }
 }
 
-// ============================================================================
-// Enhanced comparison with text reconstruction
-// ============================================================================
+/// Align two range windows afresh and convert the result to an edge.
+fn realign_windows(
+    window_a: &Window,
+    window_b: &Window,
+    weights_a: &[f32],
+    weights_b: &[f32],
+    params: &ComparisonParams,
+) -> Option<ReuseEdge> {
+    let alignment = if params.use_weights && !weights_a.is_empty() {
+        align_sequences_weighted_with_alternatives(
+            &window_a.lemma_ids,
+            &window_b.lemma_ids,
+            &window_a.root_ids,
+            &window_b.root_ids,
+            &window_a.alt_lemma_ids,
+            &window_b.alt_lemma_ids,
+            weights_a,
+            weights_b,
+            params,
+        )?
+    } else {
+        align_sequences_with_alternatives(
+            &window_a.lemma_ids,
+            &window_b.lemma_ids,
+            &window_a.root_ids,
+            &window_b.root_ids,
+            &window_a.alt_lemma_ids,
+            &window_b.alt_lemma_ids,
+            params,
+        )?
+    };
+    Some(alignment_to_edge(window_a, window_b, &alignment, params.ngram_size))
+}
 
-/// Compare two books and produce results with reconstructed Arabic text.
-/// This is the main function for generating viewer-compatible output.
-/// Supports all matching modes (lemma, root, combined).
-pub fn compare_books_with_text(
-    book_a_id: u32,
-    book_b_id: u32,
-    db_path: &Path,
+/// Merge raw edges, gating each merge on re-alignment quality when
+/// `min_merge_core_similarity` is set, or re-aligning merged spans afterwards
+/// when `refine_merges` is. `range_windows` builds the book A and book B
+/// windows for a merged edge's spans.
+fn merge_edges_by_params<W>(
+    edges: Vec<ReuseEdge>,
     params: &ComparisonParams,
-    context_tokens: usize,
-    show_progress: bool,
-) -> Result<ComparisonResultWithText, DbError> {
-    // Load all mappings in a single pass for efficiency
-    if show_progress {
-        eprintln!("Loading token mappings (lemma + root + surface)...");
+    weights_a: &[f32],
+    weights_b: &[f32],
+    range_windows: W,
+) -> Vec<ReuseEdge>
+where
+    W: Fn(&ReuseEdge) -> (Window, Window),
+{
+    let realign = |merged: &ReuseEdge| {
+        let (window_a, window_b) = range_windows(merged);
+        realign_windows(&window_a, &window_b, weights_a, weights_b, params)
+    };
+    match params.min_merge_core_similarity {
+        // Gated merges are re-aligned already
+        Some(min) => merge_overlapping_edges_gated(edges, min, realign),
+        None if params.refine_merges => {
+            let raw = edges.clone();
+            refine_merged_edges(&raw, merge_overlapping_edges(edges), realign)
+        }
+        None => merge_overlapping_edges(edges),
     }
-    let (token_to_lemma, token_to_root, token_to_surface) = load_all_token_mappings(db_path)?;
+}
 
-    // Load token streams (includes token_ids, lemma_ids, and root_ids)
-    if show_progress {
-        eprintln!("Loading book {} token stream...", book_a_id);
+/// Marker tokens recognized from surface forms, for pipelines that have them.
+struct SurfaceMarkers {
+    /// Isnād starts for hadith segmentation (None in window mode)
+    isnad: Option<IsnadMarkers>,
+    /// Hemistich breaks for verse tagging
+    verse: VerseMarkers,
+    /// Digits and foreign-script tokens for suspect tagging
+    non_arabic: NonArabicTokens,
+    /// token_id -> normalized surface class, for surface matching (None otherwise)
+    surface_classes: Option<Vec<u32>>,
+}
+
+impl SurfaceMarkers {
+    fn new(params: &ComparisonParams, token_to_surface: &SurfaceTable) -> Self {
+        let surface_classes = uses_surfaces(params).then(|| {
+            let mut classes = SurfaceClasses::new(NormalizationConfig::default());
+            for (token_id, surface) in token_to_surface.iter() {
+                classes.push(token_id, surface);
+            }
+            classes.finish()
+        });
+        SurfaceMarkers {
+            isnad: (params.segmentation == Segmentation::Hadith)
+                .then(|| IsnadMarkers::from_surfaces(token_to_surface)),
+            verse: VerseMarkers::from_surfaces(token_to_surface),
+            non_arabic: NonArabicTokens::from_surfaces(token_to_surface),
+            surface_classes,
+        }
     }
-    let stream_a = load_book_token_stream_with_root(db_path, book_a_id, &token_to_lemma, &token_to_root)?;
 
-    if show_progress {
-        eprintln!("Loading book {} token stream...", book_b_id);
+    /// Markers found by scanning token_definitions, without loading the
+    /// surface table.
+    fn from_db(params: &ComparisonParams, db_path: &Path) -> Result<Self, DbError> {
+        let isnad = if params.segmentation == Segmentation::Hadith {
+            let token_ids = find_surface_token_ids(db_path, isnad_marker_matcher())?;
+            Some(IsnadMarkers::from_token_ids(token_ids))
+        } else {
+            None
+        };
+        Ok(SurfaceMarkers {
+            isnad,
+            verse: VerseMarkers::from_token_ids(find_surface_token_ids(db_path, is_hemistich_marker)?),
+            non_arabic: NonArabicTokens::from_token_ids(find_surface_token_ids(db_path, is_non_arabic_token)?),
+            surface_classes: if uses_surfaces(params) {
+                Some(load_surface_classes(db_path, NormalizationConfig::default())?)
+            } else {
+                None
+            },
+        })
     }
-    let stream_b = load_book_token_stream_with_root(db_path, book_b_id, &token_to_lemma, &token_to_root)?;
+}
 
-    // Run comparison with root support
-    let result = compare_token_streams_internal(&stream_a, &stream_b, params, show_progress)?;
+/// Whether alignment or filtering compares surface forms.
+fn uses_surfaces(params: &ComparisonParams) -> bool {
+    params.mode == MatchMode::Surface || params.filter_mode == MatchMode::Surface
+}
 
-    // Reconstruct text for each edge
-    if show_progress {
-        eprintln!("Reconstructing text for {} edges...", result.edges.len());
+/// `stream` with token IDs replaced by their normalized surface classes, when
+/// `surface_classes` are given. In surface mode the classes also stand in for
+/// lemmas, so alignment and IDF weighting count spellings; roots, lemma
+/// alternatives and corpus weights no longer apply and are dropped.
+fn surface_stream<'a>(
+    stream: &'a BookTokenStream,
+    params: &ComparisonParams,
+    surface_classes: Option<&[u32]>,
+) -> Cow<'a, BookTokenStream> {
+    let Some(classes) = surface_classes else {
+        return Cow::Borrowed(stream);
+    };
+    let surface_mode = params.mode == MatchMode::Surface;
+    let mut stream = stream.clone();
+    for page in &mut stream.pages {
+        for token_id in &mut page.token_ids {
+            *token_id = classes.get(*token_id as usize).copied().unwrap_or(0);
+        }
+        if surface_mode {
+            page.lemma_ids.clone_from(&page.token_ids);
+            page.root_ids.fill(0);
+            page.alt_lemma_ids.clear();
+        }
+    }
+    if surface_mode {
+        stream.corpus_weights = None;
     }
+    Cow::Owned(stream)
+}
 
-    let edges_with_text: Vec<ReuseEdgeWithText> = result
-        .edges
+/// A reuse region localized by the coarse pass: source and target global ranges.
+type Region = ((usize, usize), (usize, usize));
+
+/// Parameters for the coarse pass of a two-pass comparison, or None when
+/// `coarse_window_size` is unset. Metric filters are off: the coarse pass only
+/// localizes reuse, and the final edges come from the fine pass.
+fn coarse_params(params: &ComparisonParams) -> Option<ComparisonParams> {
+    let window_size = params.coarse_window_size?;
+    Some(ComparisonParams {
+        window_size,
+        stride: params.coarse_stride.unwrap_or((window_size / 2).max(1)),
+        no_filters: true,
+        min_weighted_similarity: None,
+        min_span_jaccard: None,
+        drop_suspect: false,
+        max_quran_overlap: None,
+        max_span_asymmetry: None,
+        min_merge_core_similarity: None,
+        refine_merges: false,
+        chain_gap: None,
+        coverage_bins: None,
+        bootstrap_samples: None,
+        coarse_window_size: None,
+        coarse_stride: None,
+        ..params.clone()
+    })
+}
+
+/// Regions around coarse edges, padded by `padding` tokens on each side so
+/// fine windows straddling a region boundary are kept.
+fn coarse_regions(edges: &[ReuseEdge], padding: usize) -> Vec<Region> {
+    edges
         .iter()
-        .map(|edge| {
-            ReuseEdgeWithText::from_edge(
-                edge,
-                &stream_a,
-                &stream_b,
-                &token_to_surface,
-                context_tokens,
+        .map(|e| {
+            (
+                (e.source_global_start.saturating_sub(padding), e.source_global_end + padding),
+                (e.target_global_start.saturating_sub(padding), e.target_global_end + padding),
             )
         })
-        .collect();
-
-    // Get current timestamp
-    let generated_at = chrono_lite_timestamp();
+        .collect()
+}
 
-    Ok(ComparisonResultWithText {
-        version: result.version,
-        generated_at,
-        parameters: result.parameters,
-        book_a: ViewerBookInfo::from(&result.book_a),
-        book_b: ViewerBookInfo::from(&result.book_b),
-        summary: result.summary,
-        edges: edges_with_text,
-    })
+/// Keep only candidate pairs whose windows fall in the same region.
+fn retain_candidates_in_regions(
+    candidates: &mut Vec<(usize, usize)>,
+    windows_a: &[Window],
+    windows_b: &[Window],
+    regions: &[Region],
+) {
+    let overlaps = |w: &Window, (start, end): (usize, usize)| w.global_start < end && w.global_end > start;
+    candidates.retain(|&(idx_a, idx_b)| {
+        regions.iter().any(|&(source, target)| {
+            overlaps(&windows_a[idx_a], source) && overlaps(&windows_b[idx_b], target)
+        })
+    });
 }
 
-/// Internal comparison using token streams with full root support.
-fn compare_token_streams_internal(
-    stream_a: &BookTokenStream,
-    stream_b: &BookTokenStream,
+/// IDF weights for both streams, or empty slices when weighting is disabled.
+///
+/// With `WeightSource::Corpus`, both sides share the corpus weights attached
+/// to the streams; streams without them fall back to document-internal weights.
+pub(crate) fn stream_weights<'a>(
+    stream_a: &'a BookTokenStream,
+    stream_b: &'a BookTokenStream,
     params: &ComparisonParams,
-    show_progress: bool,
-) -> Result<ComparisonResult, DbError> {
-    // Build lemma weights for IDF weighting (if enabled)
-    let (weights_a, weights_b) = if params.use_weights {
+) -> (Cow<'a, [f32]>, Cow<'a, [f32]>) {
+    if !params.use_weights {
+        return (Cow::Borrowed(&[]), Cow::Borrowed(&[]));
+    }
+    if params.weight_source == WeightSource::Corpus {
+        if let (Some(weights_a), Some(weights_b)) = (&stream_a.corpus_weights, &stream_b.corpus_weights) {
+            return (Cow::Borrowed(&weights_a[..]), Cow::Borrowed(&weights_b[..]));
+        }
+    }
+    let max_lemma_id = find_max_lemma_id(stream_a, stream_b);
+    (
+        Cow::Owned(build_lemma_weights(&stream_a.flat_lemma_ids(), max_lemma_id)),
+        Cow::Owned(build_lemma_weights(&stream_b.flat_lemma_ids(), max_lemma_id)),
+    )
+}
+
+/// Summary counts, coverage and averages of an edge list, given each book's
+/// `(id, total_tokens)`. Coverage intervals are left unset: they need the
+/// page structure, which only the comparison pipeline has.
+fn summarize_edges(
+    edges: &[ReuseEdge],
+    (book_a_id, book_a_tokens): (u32, usize),
+    (book_b_id, book_b_tokens): (u32, usize),
+) -> ComparisonSummary {
+    ComparisonSummary {
+        edge_count: edges.len(),
+        total_aligned_tokens: edges.iter().map(|e| e.aligned_length as usize).sum(),
+        book_a_aligned_tokens: edges.iter().map(|e| e.source_global_end - e.source_global_start).sum(),
+        book_b_aligned_tokens: edges.iter().map(|e| e.target_global_end - e.target_global_start).sum(),
+        book_a_coverage: calculate_coverage(edges, book_a_id, book_a_tokens),
+        book_b_coverage: calculate_coverage(edges, book_b_id, book_b_tokens),
+        avg_similarity: mean(edges.iter().map(|e| e.lemma_similarity)),
+        avg_weighted_similarity: mean(edges.iter().map(|e| e.weighted_similarity)),
+        book_a_coverage_ci: None,
+        book_b_coverage_ci: None,
+        aborted_alignments: 0,
+        order_tau: order_tau(
+            edges
+                .iter()
+                .map(|e| (e.source_global_start, e.target_global_start))
+                .collect(),
+        ),
+        edge_classes: EdgeClassCounts::count(edges.iter().map(|e| (e.is_verse, e.suspect, e.quran_overlap))),
+        length_distribution: Distribution::new(edges.iter().map(|e| e.aligned_length as f32).collect(), &LENGTH_BINS),
+        similarity_distribution: Distribution::new(
+            edges.iter().map(|e| e.lemma_similarity).collect(),
+            &SIMILARITY_BINS,
+        ),
+    }
+}
+
+fn mean(values: impl ExactSizeIterator<Item = f32>) -> f32 {
+    let n = values.len();
+    if n == 0 {
+        0.0
+    } else {
+        values.sum::<f32>() / n as f32
+    }
+}
+
+/// Milliseconds since `start`, restarting the clock for the next stage.
+fn lap(start: &mut Instant) -> u64 {
+    let ms = start.elapsed().as_millis() as u64;
+    *start = Instant::now();
+    ms
+}
+
+/// Record that a result's books were loaded from `db_path` in `load_ms`
+/// (counted into the wall time too), with the database's path and fingerprint.
+pub(crate) fn record_load(run_info: &mut RunInfo, db_path: &Path, load_ms: u64) {
+    run_info.load_ms = load_ms;
+    run_info.wall_ms += load_ms;
+    run_info.corpus_db = db_path
+        .canonicalize()
+        .unwrap_or_else(|_| db_path.to_path_buf())
+        .display()
+        .to_string();
+    run_info.db_fingerprint = database_fingerprint(db_path).unwrap_or_default();
+}
+
+/// This machine's host name, or an empty string if none can be found.
+fn hostname() -> String {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .filter_map(|path| std::fs::read_to_string(path).ok())
+        .chain(["COMPUTERNAME", "HOSTNAME"].iter().filter_map(|var| std::env::var(var).ok()))
+        .map(|name| name.trim().to_string())
+        .find(|name| !name.is_empty())
+        .unwrap_or_default()
+}
+
+/// The process's peak resident set size so far, from `VmHWM` in
+/// /proc/self/status; None where that isn't available (outside Linux).
+fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kib: u64 = line.trim_start_matches("VmHWM:").trim().trim_end_matches("kB").trim().parse().ok()?;
+    Some(kib * 1024)
+}
+
+impl ComparisonResult {
+    /// Recompute the summary from the current edge list.
+    ///
+    /// Call this after any post-processing that changes `edges` (re-filtering,
+    /// re-merging) so the summary matches the edges it accompanies. Overlapping
+    /// edges are counted once toward coverage. Bootstrap coverage intervals
+    /// cannot be recomputed without the page structure and are cleared;
+    /// coverage profiles keep their bins and are recounted.
+    pub fn recompute_summary(&mut self) {
+        for profile in &mut self.coverage_profiles {
+            profile.covered = covered_tokens_per_bin(&covered_ranges(&self.edges, profile.book_id), &profile.sizes);
+        }
+        self.summary = ComparisonSummary {
+            aborted_alignments: self.summary.aborted_alignments,
+            ..summarize_edges(
+                &self.edges,
+                (self.book_a.id, self.book_a.token_count as usize),
+                (self.book_b.id, self.book_b.token_count as usize),
+            )
+        };
+    }
+}
+
+impl ComparisonResultWithText {
+    /// Recompute the summary from the current edge list, as
+    /// [`ComparisonResult::recompute_summary`] does.
+    ///
+    /// In a same-book result (see [`compare_ranges`]) both sides share a book
+    /// ID, so book A's coverage is taken from edge sources and book B's from
+    /// edge targets.
+    pub fn recompute_summary(&mut self) {
+        let covered_ranges = |book_id: u32, side_b: bool| {
+            let mut ranges: Vec<(usize, usize)> = self
+                .edges
+                .iter()
+                .filter_map(|e| match (e.source.book_id == book_id, e.target.book_id == book_id) {
+                    (true, true) if side_b => Some(e.target.global_range),
+                    (true, _) => Some(e.source.global_range),
+                    (false, true) => Some(e.target.global_range),
+                    (false, false) => None,
+                })
+                .collect();
+            ranges.sort_by_key(|r| r.0);
+            merge_ranges(&ranges)
+        };
+        let coverage = |book: &ViewerBookInfo, side_b: bool| {
+            range_coverage(&covered_ranges(book.id, side_b), book.token_count as usize)
+        };
+        // Profiles are book A's, then book B's
+        for (i, profile) in self.coverage_profiles.iter_mut().enumerate() {
+            profile.covered = covered_tokens_per_bin(&covered_ranges(profile.book_id, i == 1), &profile.sizes);
+        }
+
+        self.summary = ComparisonSummary {
+            edge_count: self.edges.len(),
+            total_aligned_tokens: self.edges.iter().map(|e| e.alignment.length as usize).sum(),
+            book_a_aligned_tokens: self.edges.iter().map(|e| e.source.global_range.1 - e.source.global_range.0).sum(),
+            book_b_aligned_tokens: self.edges.iter().map(|e| e.target.global_range.1 - e.target.global_range.0).sum(),
+            book_a_coverage: coverage(&self.book_a, false),
+            book_b_coverage: coverage(&self.book_b, true),
+            avg_similarity: mean(self.edges.iter().map(|e| e.alignment.similarity)),
+            avg_weighted_similarity: mean(self.edges.iter().map(|e| e.alignment.weighted_similarity)),
+            book_a_coverage_ci: None,
+            book_b_coverage_ci: None,
+            aborted_alignments: self.summary.aborted_alignments,
+            order_tau: order_tau(
+                self.edges
+                    .iter()
+                    .map(|e| (e.source.global_range.0, e.target.global_range.0))
+                    .collect(),
+            ),
+            edge_classes: EdgeClassCounts::count(
+                self.edges
+                    .iter()
+                    .map(|e| (e.alignment.is_verse, e.alignment.suspect, e.alignment.quran_overlap)),
+            ),
+            length_distribution: Distribution::new(
+                self.edges.iter().map(|e| e.alignment.length as f32).collect(),
+                &LENGTH_BINS,
+            ),
+            similarity_distribution: Distribution::new(
+                self.edges.iter().map(|e| e.alignment.similarity).collect(),
+                &SIMILARITY_BINS,
+            ),
+        };
+    }
+}
+
+/// Kendall tau between the source and target order of edges, given their
+/// `(source_start, target_start)` positions.
+///
+/// 1 when reuse runs in the same order through both books, -1 when reversed,
+/// near 0 when scattered. Pairs tied on either side count as neither
+/// concordant nor discordant. Runs in O(n log n), counting discordant pairs
+/// as inversions during a merge sort.
+fn order_tau(mut starts: Vec<(usize, usize)>) -> f32 {
+    let n = starts.len();
+    if n < 2 {
+        return 0.0;
+    }
+    starts.sort_unstable();
+
+    let total = n * (n - 1) / 2;
+    let tied_source = tied_pairs(starts.iter().map(|s| s.0));
+    let tied_both = tied_pairs(starts.iter().copied());
+
+    // Within a tied source run targets ascend, so only strict pairs invert
+    let mut targets: Vec<usize> = starts.iter().map(|s| s.1).collect();
+    let discordant = count_inversions(&mut targets);
+    let tied_target = tied_pairs(targets.iter().copied());
+
+    let concordant = total + tied_both - discordant - tied_source - tied_target;
+    (concordant as f32 - discordant as f32) / total as f32
+}
+
+/// Pairs of equal values in a sorted sequence.
+fn tied_pairs<T: PartialEq>(sorted: impl Iterator<Item = T>) -> usize {
+    let mut pairs = 0;
+    let mut run = 0;
+    let mut last = None;
+    for value in sorted {
+        if last.as_ref() == Some(&value) {
+            run += 1;
+        } else {
+            pairs += run * (run + 1) / 2;
+            run = 0;
+            last = Some(value);
+        }
+    }
+    pairs + run * (run + 1) / 2
+}
+
+/// Sort `values` and return how many pairs were out of order.
+fn count_inversions(values: &mut [usize]) -> usize {
+    if values.len() < 2 {
+        return 0;
+    }
+    let mid = values.len() / 2;
+    let mut inversions = count_inversions(&mut values[..mid]) + count_inversions(&mut values[mid..]);
+
+    let mut merged = Vec::with_capacity(values.len());
+    let (mut i, mut j) = (0, mid);
+    while i < mid && j < values.len() {
+        if values[j] < values[i] {
+            inversions += mid - i;
+            merged.push(values[j]);
+            j += 1;
+        } else {
+            merged.push(values[i]);
+            i += 1;
+        }
+    }
+    merged.extend_from_slice(&values[i..mid]);
+    merged.extend_from_slice(&values[j..]);
+    values.copy_from_slice(&merged);
+    inversions
+}
+
+/// Calculate coverage as the fraction of a book covered by reuse edges.
+fn calculate_coverage(edges: &[ReuseEdge], book_id: u32, total_tokens: usize) -> f32 {
+    // Unique covered positions (accounting for overlaps)
+    range_coverage(&covered_ranges(edges, book_id), total_tokens)
+}
+
+/// Fraction of `total_tokens` covered by sorted, non-overlapping ranges.
+fn range_coverage(merged_ranges: &[(usize, usize)], total_tokens: usize) -> f32 {
+    if total_tokens == 0 {
+        return 0.0;
+    }
+
+    // Calculate total covered tokens
+    let covered: usize = merged_ranges.iter().map(|(s, e)| e - s).sum();
+
+    covered as f32 / total_tokens as f32
+}
+
+/// Covered token ranges of one book, sorted and merged.
+fn covered_ranges(edges: &[ReuseEdge], book_id: u32) -> Vec<(usize, usize)> {
+    let mut ranges: Vec<(usize, usize)> = edges
+        .iter()
+        .filter_map(|e| {
+            if e.source_book_id == book_id {
+                Some((e.source_global_start, e.source_global_end))
+            } else if e.target_book_id == book_id {
+                Some((e.target_global_start, e.target_global_end))
+            } else {
+                None
+            }
+        })
+        .collect();
+    ranges.sort_by_key(|r| r.0);
+    merge_ranges(&ranges)
+}
+
+/// Covered token count of each page, given page sizes in stream order.
+fn covered_tokens_per_page(edges: &[ReuseEdge], book_id: u32, page_sizes: &[usize]) -> Vec<usize> {
+    covered_tokens_per_bin(&covered_ranges(edges, book_id), page_sizes)
+}
+
+/// Tokens of sorted, non-overlapping ranges falling in each of a run of
+/// consecutive bins starting at token 0.
+pub(crate) fn covered_tokens_per_bin(merged_ranges: &[(usize, usize)], bin_sizes: &[usize]) -> Vec<usize> {
+    let mut covered = Vec::with_capacity(bin_sizes.len());
+    let mut bin_start = 0;
+
+    for &size in bin_sizes {
+        let bin_end = bin_start + size;
+        let count: usize = merged_ranges
+            .iter()
+            .map(|&(s, e)| e.min(bin_end).saturating_sub(s.max(bin_start)))
+            .sum();
+        covered.push(count);
+        bin_start = bin_end;
+    }
+
+    covered
+}
+
+/// Coverage profiles of books given as `(book_id, page sizes)`, if
+/// `params.coverage_bins` asks for them.
+fn coverage_profiles(
+    edges: &[ReuseEdge],
+    books: [(u32, Vec<usize>); 2],
+    params: &ComparisonParams,
+) -> Vec<CoverageProfile> {
+    let Some(bins) = params.coverage_bins else {
+        return Vec::new();
+    };
+    books
+        .into_iter()
+        .map(|(book_id, page_sizes)| {
+            let sizes = match bins {
+                CoverageBins::Pages => page_sizes,
+                CoverageBins::Tokens(n) => {
+                    let total: usize = page_sizes.iter().sum();
+                    (0..total).step_by(n.max(1)).map(|start| n.min(total - start)).collect()
+                }
+            };
+            CoverageProfile {
+                book_id,
+                bins,
+                covered: covered_tokens_per_bin(&covered_ranges(edges, book_id), &sizes),
+                sizes,
+            }
+        })
+        .collect()
+}
+
+/// 95% bootstrap interval for a book's coverage, resampling pages.
+fn coverage_interval(
+    edges: &[ReuseEdge],
+    book_id: u32,
+    page_sizes: &[usize],
+    samples: usize,
+) -> Option<(f32, f32)> {
+    let covered = covered_tokens_per_page(edges, book_id, page_sizes);
+    bootstrap_coverage_interval(&covered, page_sizes, samples, BOOTSTRAP_SEED)
+}
+
+/// Merge overlapping ranges into non-overlapping ranges.
+pub(crate) fn merge_ranges(ranges: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    if ranges.is_empty() {
+        return Vec::new();
+    }
+
+    let mut merged: Vec<(usize, usize)> = Vec::new();
+    let mut current = ranges[0];
+
+    for &(start, end) in &ranges[1..] {
+        if start <= current.1 {
+            // Overlapping - extend current range
+            current.1 = current.1.max(end);
+        } else {
+            // Non-overlapping - save current and start new
+            merged.push(current);
+            current = (start, end);
+        }
+    }
+    merged.push(current);
+
+    merged
+}
+
+// ============================================================================
+// Enhanced comparison with text reconstruction
+// ============================================================================
+
+/// The parameters to match with, given the loaded token_id -> root_id mapping.
+///
+/// Without root data every root is 0, so root and combined matching would
+/// quietly score like lemma matching (root mode matching nothing at all).
+/// Whichever of `mode` and `filter_mode` asks for roots falls back to lemma
+/// mode with a warning, or the run fails with [`DbError::NoRootData`]
+/// if `require_roots` is set. The returned parameters are the ones the
+/// result records.
+pub fn resolve_root_modes<'a>(
+    params: &'a ComparisonParams,
+    token_to_root: &[u32],
+    show_progress: bool,
+) -> Result<Cow<'a, ComparisonParams>, DbError> {
+    let needs_roots = |mode: MatchMode| matches!(mode, MatchMode::Root | MatchMode::Combined);
+    if !(needs_roots(params.mode) || needs_roots(params.filter_mode)) || has_root_data(token_to_root) {
+        return Ok(Cow::Borrowed(params));
+    }
+    if params.require_roots {
+        return Err(DbError::NoRootData);
+    }
+    if show_progress {
+        eprintln!(
+            "Warning: corpus.db has no root data; matching in lemma mode instead of {:?}/{:?}.",
+            params.mode, params.filter_mode
+        );
+    }
+    let lemma_for_roots = |mode: MatchMode| if needs_roots(mode) { MatchMode::Lemma } else { mode };
+    Ok(Cow::Owned(ComparisonParams {
+        mode: lemma_for_roots(params.mode),
+        filter_mode: lemma_for_roots(params.filter_mode),
+        ..params.clone()
+    }))
+}
+
+/// Compare two books and produce results with reconstructed Arabic text.
+/// This is the main function for generating viewer-compatible output.
+/// Supports all matching modes (lemma, root, combined).
+pub fn compare_books_with_text(
+    book_a_id: u32,
+    book_b_id: u32,
+    db_path: &Path,
+    params: &ComparisonParams,
+    context_tokens: usize,
+    show_progress: bool,
+) -> Result<ComparisonResultWithText, DbError> {
+    let mut stage = Instant::now();
+
+    // Load all mappings in a single pass for efficiency
+    if show_progress {
+        eprintln!("Loading token mappings (lemma + root + surface)...");
+    }
+    let (token_to_lemma, token_to_root, token_to_surface) = load_all_token_mappings(db_path)?;
+    let params = &*resolve_root_modes(params, &token_to_root, show_progress)?;
+
+    // Load token streams (includes token_ids, lemma_ids, and root_ids)
+    if show_progress {
+        eprintln!("Loading book {} token stream...", book_a_id);
+    }
+    let mut stream_a = load_book_token_stream_with_root(db_path, book_a_id, &token_to_lemma, &token_to_root)?;
+
+    if show_progress {
+        eprintln!("Loading book {} token stream...", book_b_id);
+    }
+    let mut stream_b = load_book_token_stream_with_root(db_path, book_b_id, &token_to_lemma, &token_to_root)?;
+
+    if params.use_lemma_alternatives {
+        let alternatives = load_lemma_alternatives(db_path)?;
+        stream_a.attach_lemma_alternatives(&alternatives);
+        stream_b.attach_lemma_alternatives(&alternatives);
+    }
+    if let Some(weights) = corpus_weights_for(params, db_path, &token_to_lemma)? {
+        stream_a.attach_corpus_weights(&weights);
+        stream_b.attach_corpus_weights(&weights);
+    }
+    if let Some(index) = quran_index_for(params, db_path, &token_to_lemma)? {
+        stream_a.attach_quran(&index);
+        stream_b.attach_quran(&index);
+    }
+    let stop_lemmas = stop_lemmas_for(params, db_path, &token_to_lemma)?;
+    let load_ms = lap(&mut stage);
+
+    let mut result = compare_token_streams_with_text(
+        &stream_a,
+        &stream_b,
+        &token_to_surface,
+        params,
+        &stop_lemmas,
+        context_tokens,
+        show_progress,
+    )?;
+    record_load(&mut result.run_info, db_path, load_ms);
+    Ok(result)
+}
+
+/// Compare one source book against several targets, without text.
+///
+/// The token mapping and book A are loaded once; each target is loaded,
+/// compared and handed to `on_result` in turn, so only one result is held in
+/// memory at a time.
+pub fn compare_book_targets<E, F>(
+    book_a_id: u32,
+    target_ids: &[u32],
+    db_path: &Path,
+    params: &ComparisonParams,
+    show_progress: bool,
+    mut on_result: F,
+) -> Result<(), E>
+where
+    E: From<DbError>,
+    F: FnMut(u32, ComparisonResult) -> Result<(), E>,
+{
+    let mut stage = Instant::now();
+    if show_progress {
+        eprintln!("Loading token-to-lemma mapping...");
+    }
+    let token_to_lemma = load_token_to_lemma(db_path)?;
+
+    if show_progress {
+        eprintln!("Loading book {} lemma stream...", book_a_id);
+    }
+    let stream_a = load_book_lemma_stream(db_path, book_a_id, &token_to_lemma)?;
+
+    for &book_b_id in target_ids {
         if show_progress {
-            eprintln!("Building document-internal IDF weights...");
+            eprintln!("Loading book {} lemma stream...", book_b_id);
         }
-        let lemmas_a = stream_a.flat_lemma_ids();
-        let lemmas_b = stream_b.flat_lemma_ids();
-        let max_lemma_id = find_max_lemma_id(stream_a, stream_b);
-        (build_lemma_weights(&lemmas_a, max_lemma_id), build_lemma_weights(&lemmas_b, max_lemma_id))
+        let stream_b = load_book_lemma_stream(db_path, book_b_id, &token_to_lemma)?;
+        let load_ms = lap(&mut stage);
+        let mut result = compare_books_from_streams(&stream_a, &stream_b, params, show_progress)?;
+        record_load(&mut result.run_info, db_path, load_ms);
+        on_result(book_b_id, result)?;
+        stage = Instant::now();
+    }
+    Ok(())
+}
+
+/// Compare one source book against several targets with text reconstruction.
+///
+/// Like [`compare_book_targets`]; the token mappings, book A and book A's
+/// interned shingles are shared across all targets.
+pub fn compare_book_targets_with_text<E, F>(
+    book_a_id: u32,
+    target_ids: &[u32],
+    db_path: &Path,
+    params: &ComparisonParams,
+    context_tokens: usize,
+    show_progress: bool,
+    on_result: F,
+) -> Result<(), E>
+where
+    E: From<DbError>,
+    F: FnMut(u32, ComparisonResultWithText) -> Result<(), E>,
+{
+    compare_targets_with_text(
+        book_a_id,
+        target_ids,
+        db_path,
+        params,
+        context_tokens,
+        false,
+        show_progress,
+        on_result,
+    )
+}
+
+/// Like [`compare_book_targets_with_text`], but without the surface table,
+/// which holds every token's surface form (~1.8M strings).
+///
+/// Marker tokens are found in a streaming pass over token_definitions, and
+/// each result queries only the surfaces its edges need (both spans plus
+/// context). The output is the same; startup memory is much lower when
+/// results have few edges.
+pub fn compare_book_targets_with_targeted_text<E, F>(
+    book_a_id: u32,
+    target_ids: &[u32],
+    db_path: &Path,
+    params: &ComparisonParams,
+    context_tokens: usize,
+    show_progress: bool,
+    on_result: F,
+) -> Result<(), E>
+where
+    E: From<DbError>,
+    F: FnMut(u32, ComparisonResultWithText) -> Result<(), E>,
+{
+    compare_targets_with_text(
+        book_a_id,
+        target_ids,
+        db_path,
+        params,
+        context_tokens,
+        true,
+        show_progress,
+        on_result,
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn compare_targets_with_text<E, F>(
+    book_a_id: u32,
+    target_ids: &[u32],
+    db_path: &Path,
+    params: &ComparisonParams,
+    context_tokens: usize,
+    targeted_surfaces: bool,
+    show_progress: bool,
+    mut on_result: F,
+) -> Result<(), E>
+where
+    E: From<DbError>,
+    F: FnMut(u32, ComparisonResultWithText) -> Result<(), E>,
+{
+    let mut stage = Instant::now();
+    let (token_to_lemma, token_to_root, token_to_surface) = if targeted_surfaces {
+        if show_progress {
+            eprintln!("Loading token mappings (lemma + root)...");
+        }
+        let (token_to_lemma, token_to_root) = load_lemma_root_mappings(db_path)?;
+        (token_to_lemma, token_to_root, None)
     } else {
-        (Vec::new(), Vec::new())
+        if show_progress {
+            eprintln!("Loading token mappings (lemma + root + surface)...");
+        }
+        let (token_to_lemma, token_to_root, token_to_surface) = load_all_token_mappings(db_path)?;
+        (token_to_lemma, token_to_root, Some(token_to_surface))
     };
+    let params = &*resolve_root_modes(params, &token_to_root, show_progress)?;
+    let markers = match &token_to_surface {
+        Some(token_to_surface) => SurfaceMarkers::new(params, token_to_surface),
+        None => SurfaceMarkers::from_db(params, db_path)?,
+    };
+    let alternatives = if params.use_lemma_alternatives {
+        Some(load_lemma_alternatives(db_path)?)
+    } else {
+        None
+    };
+    let corpus_weights = corpus_weights_for(params, db_path, &token_to_lemma)?;
+    let quran = quran_index_for(params, db_path, &token_to_lemma)?;
+    let stop_lemmas = stop_lemmas_for(params, db_path, &token_to_lemma)?;
+
+    if show_progress {
+        eprintln!("Loading book {} token stream...", book_a_id);
+    }
+    let mut stream_a = load_book_token_stream_with_root(db_path, book_a_id, &token_to_lemma, &token_to_root)?;
+    if let Some(alternatives) = &alternatives {
+        stream_a.attach_lemma_alternatives(alternatives);
+    }
+    if let Some(weights) = &corpus_weights {
+        stream_a.attach_corpus_weights(weights);
+    }
+    if let Some(index) = &quran {
+        stream_a.attach_quran(index);
+    }
+
+    let mut shingles = CorpusShingles::new();
+    for &book_b_id in target_ids {
+        if show_progress {
+            eprintln!("Loading book {} token stream...", book_b_id);
+        }
+        let mut stream_b = load_book_token_stream_with_root(db_path, book_b_id, &token_to_lemma, &token_to_root)?;
+        if let Some(alternatives) = &alternatives {
+            stream_b.attach_lemma_alternatives(alternatives);
+        }
+        if let Some(weights) = &corpus_weights {
+            stream_b.attach_corpus_weights(weights);
+        }
+        if let Some(index) = &quran {
+            stream_b.attach_quran(index);
+        }
+        let load_ms = lap(&mut stage);
+
+        let result = compare_token_streams_internal(
+            &stream_a,
+            &stream_b,
+            params,
+            Some(&mut shingles),
+            Some(&markers),
+            show_progress,
+        )?;
+        let mut result = match &token_to_surface {
+            Some(token_to_surface) => {
+                attach_text(result, &stream_a, &stream_b, token_to_surface, &stop_lemmas, context_tokens, show_progress)
+            }
+            None => {
+                let mut query = Instant::now();
+                let token_ids = text_token_ids(&result, &stream_a, &stream_b, context_tokens);
+                let surfaces = load_surfaces(db_path, &token_ids)?;
+                let query_ms = lap(&mut query);
+                let mut result =
+                    attach_text(result, &stream_a, &stream_b, &surfaces, &stop_lemmas, context_tokens, show_progress);
+                result.run_info.text_ms += query_ms;
+                result.run_info.wall_ms += query_ms;
+                result
+            }
+        };
+        record_load(&mut result.run_info, db_path, load_ms);
+        on_result(book_b_id, result)?;
+        stage = Instant::now();
+    }
+    Ok(())
+}
+
+/// Compare two sections of the same book, e.g. an abridgment bound into the
+/// same codex as its source.
+///
+/// Edges keep the book's global token positions and page references, and
+/// context text may extend beyond either range. Coverage in the summary is
+/// relative to each range rather than the whole book; bootstrap intervals are
+/// not computed.
+pub fn compare_ranges(
+    book_id: u32,
+    range_a: PageRange,
+    range_b: PageRange,
+    db_path: &Path,
+    params: &ComparisonParams,
+    context_tokens: usize,
+    show_progress: bool,
+) -> Result<ComparisonResultWithText, DbError> {
+    let mut stage = Instant::now();
+    if show_progress {
+        eprintln!("Loading token mappings (lemma + root + surface)...");
+    }
+    let (token_to_lemma, token_to_root, token_to_surface) = load_all_token_mappings(db_path)?;
+    let params = &*resolve_root_modes(params, &token_to_root, show_progress)?;
+
+    if show_progress {
+        eprintln!("Loading book {} token stream...", book_id);
+    }
+    let mut stream = load_book_token_stream_with_root(db_path, book_id, &token_to_lemma, &token_to_root)?;
+    if params.use_lemma_alternatives {
+        stream.attach_lemma_alternatives(&load_lemma_alternatives(db_path)?);
+    }
+    if let Some(weights) = corpus_weights_for(params, db_path, &token_to_lemma)? {
+        stream.attach_corpus_weights(&weights);
+    }
+    if let Some(index) = quran_index_for(params, db_path, &token_to_lemma)? {
+        stream.attach_quran(&index);
+    }
+    let stop_lemmas = stop_lemmas_for(params, db_path, &token_to_lemma)?;
+    let load_ms = lap(&mut stage);
+
+    let (section_a, offset_a) = stream.page_range(range_a);
+    let (section_b, offset_b) = stream.page_range(range_b);
+
+    let markers = SurfaceMarkers::new(params, &token_to_surface);
+    let mut result = compare_token_streams_internal(
+        &section_a,
+        &section_b,
+        params,
+        None,
+        Some(&markers),
+        show_progress,
+    )?;
+    record_load(&mut result.run_info, db_path, load_ms);
+    result.coverage_profiles.clear();
+
+    // Shift section-relative positions back into the book
+    for edge in &mut result.edges {
+        edge.source_global_start += offset_a;
+        edge.source_global_end += offset_a;
+        edge.target_global_start += offset_b;
+        edge.target_global_end += offset_b;
+    }
+
+    // Both sides share a book ID, so coverage is taken by side
+    let mut result =
+        attach_text(result, &stream, &stream, &token_to_surface, &stop_lemmas, context_tokens, show_progress);
+    result.recompute_summary();
+    Ok(result)
+}
+
+/// Compare two raw texts without a corpus.db.
+///
+/// Both texts are tokenized with [`SimpleTokenizer`] and cut into pages at
+/// form feeds, then loaded as books 1 and 2 of an in-memory corpus. Lemmas
+/// and roots come from `lexicon` where it lists a surface form; other forms
+/// match after the lexicon's [normalization](Lexicon::normalization), and
+/// root or combined modes fall back to lemma mode when it supplies no roots.
+pub fn compare_texts(
+    text_a: &str,
+    text_b: &str,
+    lexicon: &Lexicon,
+    params: &ComparisonParams,
+    context_tokens: usize,
+) -> Result<ComparisonResultWithText, DbError> {
+    let mut conn = Connection::open_in_memory()?;
+    create_schema(&conn)?;
+    let mut writer = CorpusWriter::new(conn.transaction()?);
+    writer.lexicon = Some(lexicon);
+    for (book_id, text) in [(1, text_a), (2, text_b)] {
+        for page in parse_plain_text(text, &SimpleTokenizer) {
+            writer.add_page(book_id, page.volume, page.page, &page.tokens)?;
+        }
+    }
+    writer.commit()?;
+
+    Corpus::from_source(SqliteSource::from_connection(conn)).compare_with_text(1, 2, params, context_tokens)
+}
+
+/// Compare two already-loaded token streams, with root matching, verse
+/// tagging and hadith segmentation, but without text reconstruction.
+pub fn compare_token_streams(
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+    token_to_surface: &SurfaceTable,
+    params: &ComparisonParams,
+    show_progress: bool,
+) -> Result<ComparisonResult, DbError> {
+    let markers = SurfaceMarkers::new(params, token_to_surface);
+    compare_token_streams_internal(stream_a, stream_b, params, None, Some(&markers), show_progress)
+}
+
+/// Compare two already-loaded token streams and reconstruct text for each edge.
+/// Lets callers that compare many pairs load the token mappings only once.
+///
+/// Edge signatures drop `stop_lemmas` (see [`crate::idf::stop_lemmas_for`]).
+pub fn compare_token_streams_with_text(
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+    token_to_surface: &SurfaceTable,
+    params: &ComparisonParams,
+    stop_lemmas: &StopLemmas,
+    context_tokens: usize,
+    show_progress: bool,
+) -> Result<ComparisonResultWithText, DbError> {
+    // Run comparison with root support
+    let markers = SurfaceMarkers::new(params, token_to_surface);
+    let result =
+        compare_token_streams_internal(stream_a, stream_b, params, None, Some(&markers), show_progress)?;
+
+    Ok(attach_text(result, stream_a, stream_b, token_to_surface, stop_lemmas, context_tokens, show_progress))
+}
+
+/// Like [`compare_token_streams_with_text`], but candidate filtering reuses
+/// interned shingles shared across a corpus run (see [`CorpusShingles`]).
+#[allow(clippy::too_many_arguments)]
+pub fn compare_token_streams_with_shared_shingles(
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+    token_to_surface: &SurfaceTable,
+    params: &ComparisonParams,
+    stop_lemmas: &StopLemmas,
+    context_tokens: usize,
+    shingles: &mut CorpusShingles,
+    show_progress: bool,
+) -> Result<ComparisonResultWithText, DbError> {
+    let markers = SurfaceMarkers::new(params, token_to_surface);
+    let result = compare_token_streams_internal(
+        stream_a,
+        stream_b,
+        params,
+        Some(shingles),
+        Some(&markers),
+        show_progress,
+    )?;
+
+    Ok(attach_text(result, stream_a, stream_b, token_to_surface, stop_lemmas, context_tokens, show_progress))
+}
+
+/// Token IDs whose surfaces the text of `result` needs: every edge's source
+/// and target span with as much context on either side as it could grow to.
+fn text_token_ids(
+    result: &ComparisonResult,
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+    context_tokens: usize,
+) -> BTreeSet<u32> {
+    let context_tokens = PassageContext::new(context_tokens, &result.parameters).reach();
+    let (ids_a, ids_b) = (stream_a.flat_token_ids(), stream_b.flat_token_ids());
+    let span = |ids: &[u32], start: usize, end: usize| {
+        let end = (end + context_tokens).min(ids.len());
+        let start = start.saturating_sub(context_tokens).min(end);
+        ids[start..end].to_vec()
+    };
+    result
+        .edges
+        .iter()
+        .flat_map(|edge| {
+            let source = span(&ids_a, edge.source_global_start, edge.source_global_end);
+            let target = span(&ids_b, edge.target_global_start, edge.target_global_end);
+            source.into_iter().chain(target)
+        })
+        .collect()
+}
+
+/// Reconstruct surface text with context for every edge of a result.
+fn attach_text<S: SurfaceLookup + ?Sized>(
+    result: ComparisonResult,
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+    token_to_surface: &S,
+    stop_lemmas: &StopLemmas,
+    context_tokens: usize,
+    show_progress: bool,
+) -> ComparisonResultWithText {
+    // Reconstruct text for each edge
+    if show_progress {
+        eprintln!("Reconstructing text for {} edges...", result.edges.len());
+    }
+    let mut stage = Instant::now();
+
+    let context = PassageContext::new(context_tokens, &result.parameters);
+    let edges_with_text: Vec<ReuseEdgeWithText> = result
+        .edges
+        .iter()
+        .map(|edge| ReuseEdgeWithText {
+            signature: span_signature(
+                stream_a,
+                (edge.source_global_start, edge.source_global_end),
+                token_to_surface,
+                stop_lemmas,
+            ),
+            ..ReuseEdgeWithText::from_edge(
+                edge,
+                stream_a,
+                stream_b,
+                token_to_surface,
+                context,
+            )
+        })
+        .collect();
+    let text_ms = lap(&mut stage);
+    let run_info = RunInfo {
+        text_ms,
+        wall_ms: result.run_info.wall_ms + text_ms,
+        ..result.run_info
+    };
+
+    // Get current timestamp
+    let generated_at = chrono_lite_timestamp();
+
+    ComparisonResultWithText {
+        version: result.version,
+        generated_at,
+        parameters: result.parameters,
+        book_a: ViewerBookInfo::from(&result.book_a),
+        book_b: ViewerBookInfo::from(&result.book_b),
+        summary: result.summary,
+        edges: edges_with_text,
+        macro_edges: result.macro_edges,
+        coverage_profiles: result.coverage_profiles,
+        run_info,
+        redaction: None,
+    }
+}
+
+/// Comparison units of both streams: windows with root support, or one unit
+/// per hadith report when isnād markers are given. Book A is restricted to
+/// `params.source_range`.
+fn token_stream_windows(
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+    params: &ComparisonParams,
+    isnad_markers: Option<&IsnadMarkers>,
+) -> (Vec<Window>, Vec<Window>) {
+    let (mut windows_a, windows_b) = match isnad_markers {
+        Some(isnad_markers) => (
+            generate_segment_windows(stream_a, &isnad_segments(stream_a, isnad_markers), params),
+            generate_segment_windows(stream_b, &isnad_segments(stream_b, isnad_markers), params),
+        ),
+        None => (generate_windows_with_roots(stream_a, params), generate_windows_with_roots(stream_b, params)),
+    };
+    if let Some(range) = params.source_range {
+        retain_windows_in_range(&mut windows_a, range);
+    }
+    (windows_a, windows_b)
+}
+
+/// Windowing and candidate filtering only, without alignment: every window
+/// pair that would be aligned, with the number of shingles it shares and
+/// their IDF-weighted Jaccard and containment. The weights are those of
+/// alignment (`weight_source`); without `use_weights`, the two are unweighted.
+///
+/// The candidates are those of a single-pass comparison; the two-pass mode's
+/// coarse pass needs alignment, so `coarse_window_size` is ignored here.
+pub fn candidate_pairs_from_streams(
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+    token_to_surface: &SurfaceTable,
+    params: &ComparisonParams,
+) -> CandidateSet {
+    let markers = SurfaceMarkers::new(params, token_to_surface);
+    let surface_classes = markers.surface_classes.as_deref();
+    let (matched_a, matched_b) = (
+        surface_stream(stream_a, params, surface_classes),
+        surface_stream(stream_b, params, surface_classes),
+    );
+    let (windows_a, windows_b) = token_stream_windows(&matched_a, &matched_b, params, markers.isnad.as_ref());
+    let (weights_a, weights_b) = stream_weights(&matched_a, &matched_b, params);
+
+    // Weighted shingle sets, built once per window that is in some candidate
+    let mut shingles_a = HashMap::new();
+    let mut shingles_b = HashMap::new();
+    let candidates = find_candidate_pairs_with_counts(&windows_a, &windows_b, params)
+        .into_iter()
+        .map(|(idx_a, idx_b, shared_shingles)| {
+            let (window_a, window_b) = (&windows_a[idx_a], &windows_b[idx_b]);
+            let overlap = weighted_shingle_overlap(
+                shingles_a
+                    .entry(idx_a)
+                    .or_insert_with(|| weighted_shingles(window_a, params, &weights_a)),
+                shingles_b
+                    .entry(idx_b)
+                    .or_insert_with(|| weighted_shingles(window_b, params, &weights_b)),
+            );
+            CandidatePair {
+                source_window: window_a.window_idx,
+                target_window: window_b.window_idx,
+                source_start: window_a.global_start,
+                source_end: window_a.global_end,
+                target_start: window_b.global_start,
+                target_end: window_b.global_end,
+                shared_shingles,
+                weighted_jaccard: overlap.jaccard,
+                weighted_containment: overlap.containment,
+            }
+        })
+        .collect();
+
+    CandidateSet {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        generated_at: chrono_lite_timestamp(),
+        parameters: params.clone(),
+        book_a: BookMetadata {
+            id: stream_a.book_id,
+            token_count: stream_a.total_tokens as u64,
+            page_count: stream_a.page_count() as u32,
+            stream_hash: stream_a.content_hash(),
+            ..Default::default()
+        },
+        book_b: BookMetadata {
+            id: stream_b.book_id,
+            token_count: stream_b.total_tokens as u64,
+            page_count: stream_b.page_count() as u32,
+            stream_hash: stream_b.content_hash(),
+            ..Default::default()
+        },
+        source_windows: windows_a.len(),
+        target_windows: windows_b.len(),
+        candidates,
+    }
+}
+
+/// Number of book A windows filtered when estimating a plan's candidate count.
+pub const PLAN_SAMPLE_WINDOWS: usize = 256;
+
+/// Load two books and estimate the cost of comparing them. See
+/// [`plan_token_streams`].
+///
+/// The surface table is loaded only for surface matching or hadith
+/// segmentation, the two settings that change how windows are cut.
+pub fn plan_comparison(
+    db_path: &Path,
+    book_a_id: u32,
+    book_b_id: u32,
+    params: &ComparisonParams,
+) -> Result<ComparisonPlan, DbError> {
+    let (token_to_lemma, token_to_root, token_to_surface) =
+        if uses_surfaces(params) || params.segmentation == Segmentation::Hadith {
+            load_all_token_mappings(db_path)?
+        } else {
+            let (token_to_lemma, token_to_root) = load_lemma_root_mappings(db_path)?;
+            (token_to_lemma, token_to_root, SurfaceTable::default())
+        };
+    let params = &*resolve_root_modes(params, &token_to_root, false)?;
+    let stream_a = load_book_token_stream_with_root(db_path, book_a_id, &token_to_lemma, &token_to_root)?;
+    let stream_b = load_book_token_stream_with_root(db_path, book_b_id, &token_to_lemma, &token_to_root)?;
+    Ok(plan_token_streams(&stream_a, &stream_b, &token_to_surface, params))
+}
 
-    // Generate windows with root support
+/// Window counts, an estimated candidate count and estimated memory for a
+/// comparison, without aligning anything.
+///
+/// Windows are generated in full (they are cheap next to alignment), but
+/// only an evenly spaced sample of up to [`PLAN_SAMPLE_WINDOWS`] book A
+/// windows is run through the candidate filter; the count is scaled up to all
+/// of book A. As with candidate export, the two-pass mode's coarse pass is
+/// ignored, so with `coarse_window_size` the estimate is an upper bound.
+pub fn plan_token_streams(
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+    token_to_surface: &SurfaceTable,
+    params: &ComparisonParams,
+) -> ComparisonPlan {
+    let markers = SurfaceMarkers::new(params, token_to_surface);
+    let surface_classes = markers.surface_classes.as_deref();
+    let (matched_a, matched_b) = (
+        surface_stream(stream_a, params, surface_classes),
+        surface_stream(stream_b, params, surface_classes),
+    );
+    let (windows_a, windows_b) = token_stream_windows(&matched_a, &matched_b, params, markers.isnad.as_ref());
+
+    let (sampled_windows, estimated_candidates) = if params.brute_force || windows_a.is_empty() {
+        (windows_a.len(), (windows_a.len() * windows_b.len()) as u64)
+    } else {
+        let step = windows_a.len().div_ceil(PLAN_SAMPLE_WINDOWS);
+        let sample: Vec<Window> = windows_a.iter().step_by(step).cloned().collect();
+        let sampled = find_candidate_pairs(&sample, &windows_b, params).len() as f64;
+        let scaled = sampled * windows_a.len() as f64 / sample.len() as f64;
+        (sample.len(), scaled.round() as u64)
+    };
+
+    let mean_len = |windows: &[Window]| {
+        windows.iter().map(|w| w.lemma_ids.len() as u64).sum::<u64>() / windows.len().max(1) as u64
+    };
+    let (len_a, len_b) = (mean_len(&windows_a), mean_len(&windows_b));
+    let cells_per_pair = (len_a + 1) * (len_b + 1);
+
+    let stream_bytes = |stream: &BookTokenStream| stream.total_tokens as u64 * 12;
+    let window_bytes = |windows: &[Window]| {
+        windows
+            .iter()
+            .map(|w| {
+                (std::mem::size_of::<Window>()
+                    + (w.lemma_ids.len() + w.root_ids.len()) * 4
+                    + w.shingle_hashes.len() * 8) as u64
+            })
+            .sum::<u64>()
+    };
+    // Each alignment thread holds one matrix, or two rows in linear space
+    let matrix_bytes = if cells_per_pair as usize > LINEAR_SPACE_MIN_CELLS {
+        (len_b + 1) * 2 * 4
+    } else {
+        cells_per_pair * 4
+    };
+    let estimated_memory_bytes = stream_bytes(stream_a)
+        + stream_bytes(stream_b)
+        + window_bytes(&windows_a)
+        + window_bytes(&windows_b)
+        + estimated_candidates * std::mem::size_of::<(usize, usize)>() as u64
+        + matrix_bytes * rayon::current_num_threads() as u64;
+
+    ComparisonPlan {
+        book_a: stream_a.book_id,
+        book_b: stream_b.book_id,
+        tokens_a: stream_a.total_tokens,
+        tokens_b: stream_b.total_tokens,
+        windows_a: windows_a.len(),
+        windows_b: windows_b.len(),
+        sampled_windows,
+        estimated_candidates,
+        estimated_alignment_cells: estimated_candidates * cells_per_pair,
+        estimated_memory_bytes,
+    }
+}
+
+/// Internal comparison using token streams with full root support.
+fn compare_token_streams_internal(
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+    params: &ComparisonParams,
+    shingles: Option<&mut CorpusShingles>,
+    markers: Option<&SurfaceMarkers>,
+    show_progress: bool,
+) -> Result<ComparisonResult, DbError> {
+    let mut run_info = RunInfo::default();
+    let run_start = Instant::now();
+    let mut stage = Instant::now();
+
+    // Surface matching works on normalized spellings; tagging and metadata
+    // keep the streams as loaded
+    let surface_classes = markers.and_then(|m| m.surface_classes.as_deref());
+    let matched_a = surface_stream(stream_a, params, surface_classes);
+    let matched_b = surface_stream(stream_b, params, surface_classes);
+
+    // Build lemma weights for IDF weighting (if enabled)
+    if show_progress && params.use_weights && params.weight_source == WeightSource::Document {
+        eprintln!("Building document-internal IDF weights...");
+    }
+    let (weights_a, weights_b) = stream_weights(&matched_a, &matched_b, params);
+
+    // Generate windows with root support, or one unit per hadith report
+    let isnad_markers = markers.and_then(|m| m.isnad.as_ref());
     if show_progress {
-        eprintln!("Generating windows (with root support)...");
+        if isnad_markers.is_some() {
+            eprintln!("Segmenting streams at isnad starts...");
+        } else {
+            eprintln!("Generating windows (with root support)...");
+        }
     }
-    let windows_a = generate_windows_with_roots(stream_a, params);
-    let windows_b = generate_windows_with_roots(stream_b, params);
+    let (windows_a, windows_b) = token_stream_windows(&matched_a, &matched_b, params, isnad_markers);
+    run_info.window_ms = lap(&mut stage);
 
     if show_progress {
         eprintln!("  Book A: {} windows ({} tokens)", windows_a.len(), stream_a.total_tokens);
         eprintln!("  Book B: {} windows ({} tokens)", windows_b.len(), stream_b.total_tokens);
-        eprintln!("  Match mode: {:?}", params.mode);
+        eprintln!("  Match mode: {:?} (filter: {:?})", params.mode, params.filter_mode);
     }
 
     // Find candidate pairs
@@ -492,7 +2065,14 @@ fn compare_token_streams_internal(
             eprintln!("Finding candidate pairs (n-gram filtering)...");
         }
     }
-    let candidates = find_candidate_pairs(&windows_a, &windows_b, params);
+    // Shared shingle sets cover whole-book windows, so a restricted book A or
+    // report segments bypass them
+    let (mut candidates, cap) = match shingles {
+        Some(shingles) if params.source_range.is_none() && isnad_markers.is_none() => {
+            shingles.capped_candidate_pairs(stream_a.book_id, &windows_a, stream_b.book_id, &windows_b, params)
+        }
+        _ => find_capped_candidate_pairs(&windows_a, &windows_b, params),
+    };
 
     if show_progress {
         let total_pairs = windows_a.len() * windows_b.len();
@@ -506,9 +2086,34 @@ fn compare_token_streams_internal(
             candidates.len(),
             filter_rate
         );
+        if cap.dropped_pairs > 0 {
+            eprintln!(
+                "  Candidate cap: {} pairs dropped from {} windows",
+                cap.dropped_pairs, cap.capped_windows
+            );
+        }
+    }
+
+    // Two-pass mode: only align candidates inside the coarse pass's reuse regions
+    let mut coarse_aborted = 0;
+    if let Some(coarse) = coarse_params(params) {
+        if show_progress {
+            eprintln!("Coarse pass (window {}, stride {})...", coarse.window_size, coarse.stride);
+        }
+        let coarse_result = compare_token_streams_internal(&matched_a, &matched_b, &coarse, None, None, false)?;
+        coarse_aborted = coarse_result.summary.aborted_alignments;
+        let regions = coarse_regions(&coarse_result.edges, params.window_size);
+        retain_candidates_in_regions(&mut candidates, &windows_a, &windows_b, &regions);
+        if show_progress {
+            eprintln!("  Reuse regions: {} ({} candidate pairs inside)", regions.len(), candidates.len());
+        }
     }
 
-    // Align candidate pairs in parallel
+    run_info.filter_ms = lap(&mut stage);
+
+    // Align candidate pairs in parallel; pathological pairs past the
+    // alignment limits are counted and skipped
+    let aborted = AtomicUsize::new(0);
     let progress = if show_progress {
         let pb = ProgressBar::new(candidates.len() as u64);
         pb.set_style(
@@ -527,33 +2132,19 @@ fn compare_token_streams_internal(
     // Share weights across threads
     let weights_a_ref = &weights_a;
     let weights_b_ref = &weights_b;
-    let use_weights = params.use_weights;
 
-    let edges: Vec<ReuseEdge> = candidates
-        .par_iter()
-        .filter_map(|&(idx_a, idx_b)| {
-            let window_a = &windows_a[idx_a];
-            let window_b = &windows_b[idx_b];
-
-            // Use weighted or unweighted alignment based on params
-            let alignment = if use_weights && !weights_a_ref.is_empty() {
-                align_sequences_weighted(
-                    &window_a.lemma_ids,
-                    &window_b.lemma_ids,
-                    &window_a.root_ids,
-                    &window_b.root_ids,
-                    weights_a_ref,
-                    weights_b_ref,
-                    params,
-                )?
-            } else {
-                align_sequences(
-                    &window_a.lemma_ids,
-                    &window_b.lemma_ids,
-                    &window_a.root_ids,
-                    &window_b.root_ids,
-                    params,
-                )?
+    let edges: Vec<ReuseEdge> = candidates
+        .par_iter()
+        .filter_map(|&(idx_a, idx_b)| {
+            let window_a = &windows_a[idx_a];
+            let window_b = &windows_b[idx_b];
+
+            let alignment = match align_window_pair(window_a, window_b, weights_a_ref, weights_b_ref, params) {
+                Ok(alignment) => alignment?,
+                Err(reason) => {
+                    note_aborted(reason, window_a, window_b, &aborted, progress.as_ref());
+                    return None;
+                }
             };
 
             if let Some(ref pb) = progress {
@@ -561,52 +2152,93 @@ fn compare_token_streams_internal(
             }
 
             // Convert alignment to edge
-            Some(alignment_to_edge(window_a, window_b, &alignment))
+            Some(alignment_to_edge(window_a, window_b, &alignment, params.ngram_size))
         })
         .collect();
+    run_info.align_ms = lap(&mut stage);
 
     if let Some(pb) = progress {
         pb.finish_with_message("Done");
     }
+    let fine_aborted = aborted.into_inner();
+    let aborted = fine_aborted + coarse_aborted;
+    if show_progress && aborted > 0 {
+        eprintln!("  Abandoned alignments: {}", aborted);
+    }
 
     // Merge overlapping edges
     if show_progress {
         eprintln!("Merging overlapping edges ({} raw edges)...", edges.len());
     }
-    let merged_edges = merge_overlapping_edges(edges);
+    let raw_edges = edges.len();
+    let mut merged_edges = merge_edges_by_params(edges, params, &weights_a, &weights_b, |edge| {
+        (
+            window_for_range(&matched_a, (edge.source_global_start, edge.source_global_end)),
+            window_for_range(&matched_b, (edge.target_global_start, edge.target_global_end)),
+        )
+    });
+
+    // Tag poetic quotations and script junk before filtering, so their thresholds can apply
+    if let Some(markers) = markers {
+        tag_verse_edges(&mut merged_edges, stream_a, stream_b, &markers.verse);
+        tag_suspect_edges(&mut merged_edges, stream_a, stream_b, &markers.non_arabic);
+    }
+    if let Some(index) = &stream_a.quran {
+        tag_quran_edges(&mut merged_edges, stream_a, stream_b, index);
+    }
 
     if show_progress {
         eprintln!("  Merged edges: {}", merged_edges.len());
     }
 
+    run_info.merge_ms = lap(&mut stage);
+
     // Apply metric-based filters
     let filtered_edges = filter_edges_by_params(&merged_edges, params);
 
     if show_progress && filtered_edges.len() != merged_edges.len() {
         eprintln!("  After filtering: {}", filtered_edges.len());
     }
+    let macro_edges = match params.chain_gap {
+        Some(max_gap) => chain_collinear_edges(&filtered_edges, max_gap),
+        None => Vec::new(),
+    };
+    if show_progress && !macro_edges.is_empty() {
+        eprintln!("  Macro edges: {}", macro_edges.len());
+    }
 
     // Build result
     let summary = ComparisonSummary {
-        edge_count: filtered_edges.len(),
-        total_aligned_tokens: filtered_edges
-            .iter()
-            .map(|e| e.aligned_length as usize)
-            .sum(),
-        book_a_coverage: calculate_coverage(&filtered_edges, stream_a.book_id, stream_a.total_tokens),
-        book_b_coverage: calculate_coverage(&filtered_edges, stream_b.book_id, stream_b.total_tokens),
-        avg_similarity: if filtered_edges.is_empty() {
-            0.0
-        } else {
-            filtered_edges.iter().map(|e| e.lemma_similarity).sum::<f32>()
-                / filtered_edges.len() as f32
-        },
-        avg_weighted_similarity: if filtered_edges.is_empty() {
-            0.0
-        } else {
-            filtered_edges.iter().map(|e| e.weighted_similarity).sum::<f32>()
-                / filtered_edges.len() as f32
-        },
+        book_a_coverage_ci: params.bootstrap_samples.and_then(|samples| {
+            let sizes: Vec<usize> = stream_a.pages.iter().map(|p| p.lemma_ids.len()).collect();
+            coverage_interval(&filtered_edges, stream_a.book_id, &sizes, samples)
+        }),
+        book_b_coverage_ci: params.bootstrap_samples.and_then(|samples| {
+            let sizes: Vec<usize> = stream_b.pages.iter().map(|p| p.lemma_ids.len()).collect();
+            coverage_interval(&filtered_edges, stream_b.book_id, &sizes, samples)
+        }),
+        aborted_alignments: aborted,
+        ..summarize_edges(
+            &filtered_edges,
+            (stream_a.book_id, stream_a.total_tokens),
+            (stream_b.book_id, stream_b.total_tokens),
+        )
+    };
+    run_info.filter_metrics_ms = lap(&mut stage);
+    run_info.wall_ms = run_start.elapsed().as_millis() as u64;
+    run_info.hostname = hostname();
+    run_info.peak_rss_bytes = peak_rss_bytes();
+    run_info.stats = RunStats {
+        source_windows: windows_a.len(),
+        target_windows: windows_b.len(),
+        candidate_pairs: candidates.len(),
+        capped_pairs: cap.dropped_pairs,
+        capped_windows: cap.capped_windows,
+        alignments: candidates.len() - fine_aborted,
+        rejected_alignments: candidates.len() - fine_aborted - raw_edges,
+        raw_edges,
+        merged_edges: merged_edges.len(),
+        dropped_edges: merged_edges.len() - filtered_edges.len(),
     };
 
     Ok(ComparisonResult {
@@ -616,113 +2248,684 @@ fn compare_token_streams_internal(
             id: stream_a.book_id,
             token_count: stream_a.total_tokens as u64,
             page_count: stream_a.page_count() as u32,
+            stream_hash: stream_a.content_hash(),
             ..Default::default()
         },
         book_b: BookMetadata {
             id: stream_b.book_id,
             token_count: stream_b.total_tokens as u64,
             page_count: stream_b.page_count() as u32,
+            stream_hash: stream_b.content_hash(),
             ..Default::default()
         },
         summary,
+        coverage_profiles: coverage_profiles(
+            &filtered_edges,
+            [
+                (stream_a.book_id, stream_a.pages.iter().map(|p| p.lemma_ids.len()).collect()),
+                (stream_b.book_id, stream_b.pages.iter().map(|p| p.lemma_ids.len()).collect()),
+            ],
+            params,
+        ),
         edges: filtered_edges,
+        macro_edges,
+        run_info,
     })
 }
 
 /// Simple timestamp function without external chrono dependency
-fn chrono_lite_timestamp() -> String {
+pub(crate) fn chrono_lite_timestamp() -> String {
     use std::time::{SystemTime, UNIX_EPOCH};
 
-    let duration = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap_or_default();
+    let duration = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+
+    let secs = duration.as_secs();
+
+    // Simple ISO 8601-ish format
+    // Calculate approximate date/time (not accounting for leap seconds, etc.)
+    let days_since_epoch = secs / 86400;
+    let secs_today = secs % 86400;
+
+    // Approximate year/month/day calculation
+    let mut year = 1970;
+    let mut remaining_days = days_since_epoch;
+
+    loop {
+        let days_in_year = if is_leap_year(year) { 366 } else { 365 };
+        if remaining_days < days_in_year {
+            break;
+        }
+        remaining_days -= days_in_year;
+        year += 1;
+    }
+
+    let month_days = if is_leap_year(year) {
+        [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31]
+    } else {
+        [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31]
+    };
+
+    let mut month = 1;
+    for days in month_days.iter() {
+        if remaining_days < *days {
+            break;
+        }
+        remaining_days -= *days;
+        month += 1;
+    }
+
+    let day = remaining_days + 1;
+    let hour = secs_today / 3600;
+    let minute = (secs_today % 3600) / 60;
+    let second = secs_today % 60;
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
+fn is_leap_year(year: u64) -> bool {
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
+}
+
+
+// Default implementation for ReuseEdge (for tests)
+impl Default for ReuseEdge {
+    fn default() -> Self {
+        ReuseEdge {
+            id: 0,
+            source_book_id: 0,
+            source_start_page: (0, 0),
+            source_start_offset: 0,
+            source_end_page: (0, 0),
+            source_end_offset: 0,
+            source_global_start: 0,
+            source_global_end: 0,
+            target_book_id: 0,
+            target_start_page: (0, 0),
+            target_start_offset: 0,
+            target_end_page: (0, 0),
+            target_end_offset: 0,
+            target_global_start: 0,
+            target_global_end: 0,
+            aligned_length: 0,
+            lemma_matches: 0,
+            substitutions: 0,
+            root_only_matches: 0,
+            gaps: 0,
+            core_similarity: 0.0,
+            span_coverage: 0.0,
+            content_weight: 0.0,
+            lexical_diversity: 0.0,
+            shingle_jaccard: 0.0,
+            is_verse: false,
+            suspect: false,
+            quran_overlap: 0.0,
+            source_span_length: 0,
+            target_span_length: 0,
+            span_ratio: 0.0,
+            direction: Direction::Unknown,
+            validation: None,
+            lemma_similarity: 0.0,
+            combined_similarity: 0.0,
+            weighted_similarity: 0.0,
+            avg_match_weight: 0.0,
+            score: 0,
+            normalized_score: 0.0,
+        }
+    }
+}
+
+// ============================================================================
+// Alignment traces
+// ============================================================================
+
+/// What aligning one window pair came to.
+#[derive(Debug, Clone)]
+pub enum TraceOutcome {
+    /// An alignment that met the length and similarity thresholds
+    Aligned(Alignment),
+    /// No local alignment met the thresholds
+    Rejected,
+    /// Abandoned at the alignment limits
+    Aborted(AlignmentAborted),
+}
+
+/// One window pair run through alignment, for inspecting scoring decisions.
+#[derive(Debug, Clone)]
+pub struct WindowPairTrace {
+    pub source_window: u32,
+    pub target_window: u32,
+    /// Global offset of each window's first token
+    pub source_start: usize,
+    pub target_start: usize,
+    pub source_lemmas: Vec<u32>,
+    pub target_lemmas: Vec<u32>,
+    /// Whether the candidate filter passes the pair on to alignment
+    pub candidate: bool,
+    pub outcome: TraceOutcome,
+}
+
+/// Selected window pairs of a comparison, with the edges the comparison
+/// ended up with.
+#[derive(Debug, Clone)]
+pub struct AlignmentTrace {
+    pub book_a: u32,
+    pub book_b: u32,
+    pub pairs: Vec<WindowPairTrace>,
+    /// Final edges, after merging and filtering
+    pub edges: Vec<ReuseEdge>,
+}
+
+/// Align the window pairs `pairs` (source and target window indices) as the
+/// comparison would, and run the comparison itself for its final edges.
+///
+/// Pairs are aligned whether or not the candidate filter passes them, so a
+/// missed match can be told apart from a filtered one. `candidate` reflects
+/// the single-pass filter; the coarse pass of a two-pass comparison is not
+/// consulted. Indices past either book's windows are skipped.
+pub fn trace_window_pairs(
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+    token_to_surface: &SurfaceTable,
+    params: &ComparisonParams,
+    pairs: &[(u32, u32)],
+) -> Result<AlignmentTrace, DbError> {
+    let markers = SurfaceMarkers::new(params, token_to_surface);
+    let result = compare_token_streams_internal(stream_a, stream_b, params, None, Some(&markers), false)?;
+
+    let surface_classes = markers.surface_classes.as_deref();
+    let (matched_a, matched_b) = (
+        surface_stream(stream_a, params, surface_classes),
+        surface_stream(stream_b, params, surface_classes),
+    );
+    let (weights_a, weights_b) = stream_weights(&matched_a, &matched_b, params);
+    let (windows_a, windows_b) = token_stream_windows(&matched_a, &matched_b, params, markers.isnad.as_ref());
+    let candidates: HashSet<(u32, u32)> = find_candidate_pairs(&windows_a, &windows_b, params)
+        .into_iter()
+        .map(|(idx_a, idx_b)| (windows_a[idx_a].window_idx, windows_b[idx_b].window_idx))
+        .collect();
+    fn find(windows: &[Window], idx: u32) -> Option<&Window> {
+        windows.iter().find(|w| w.window_idx == idx)
+    }
+
+    let pairs = pairs
+        .iter()
+        .filter_map(|&(idx_a, idx_b)| {
+            let (window_a, window_b) = (find(&windows_a, idx_a)?, find(&windows_b, idx_b)?);
+            let outcome = match align_window_pair(window_a, window_b, &weights_a, &weights_b, params) {
+                Ok(Some(alignment)) => TraceOutcome::Aligned(alignment),
+                Ok(None) => TraceOutcome::Rejected,
+                Err(reason) => TraceOutcome::Aborted(reason),
+            };
+            Some(WindowPairTrace {
+                source_window: idx_a,
+                target_window: idx_b,
+                source_start: window_a.global_start,
+                target_start: window_b.global_start,
+                source_lemmas: window_a.lemma_ids.clone(),
+                target_lemmas: window_b.lemma_ids.clone(),
+                candidate: candidates.contains(&(idx_a, idx_b)),
+                outcome,
+            })
+        })
+        .collect();
+
+    Ok(AlignmentTrace {
+        book_a: stream_a.book_id,
+        book_b: stream_b.book_id,
+        pairs,
+        edges: result.edges,
+    })
+}
+
+// ============================================================================
+// Document-internal IDF weighting
+// ============================================================================
+
+/// Build document-internal IDF weights for a book's lemma stream.
+///
+/// For each lemma ℓ in book B:
+///   weight_B(ℓ) = ln(total_tokens_B / df_B(ℓ))
+///
+/// Weights are clamped to [0.5, 3.0] for stability.
+///
+/// Returns a Vec indexed by lemma_id, with weights for each lemma seen in the book.
+pub fn build_lemma_weights(lemma_ids: &[u32], max_lemma_id: usize) -> Vec<f32> {
+    // Count document frequency for each lemma
+    let mut counts = vec![0u32; max_lemma_id + 1];
+
+    for &id in lemma_ids {
+        if (id as usize) < counts.len() {
+            counts[id as usize] += 1;
+        }
+    }
+
+    let total = lemma_ids.len() as f32;
+    let mut weights = vec![0.0f32; max_lemma_id + 1];
+
+    for (id, &df) in counts.iter().enumerate() {
+        if df > 0 {
+            // IDF formula: ln(total / df), clamped to [0.5, 3.0]
+            let w = (total / df as f32).ln().clamp(0.5, 3.0);
+            weights[id] = w;
+        }
+    }
+
+    weights
+}
+
+/// Find the maximum lemma ID in the token streams.
+pub fn find_max_lemma_id(stream_a: &BookTokenStream, stream_b: &BookTokenStream) -> usize {
+    let max_a = stream_a.flat_lemma_ids().iter().copied().max().unwrap_or(0) as usize;
+    let max_b = stream_b.flat_lemma_ids().iter().copied().max().unwrap_or(0) as usize;
+    max_a.max(max_b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{PageLemmas, PageTokens};
+
+    fn create_test_stream(book_id: u32, lemmas: Vec<u32>) -> BookLemmaStream {
+        let total_tokens = lemmas.len();
+        BookLemmaStream {
+            book_id,
+            total_tokens,
+            pages: vec![PageLemmas {
+                part_index: 1,
+                page_id: 1,
+                lemma_ids: lemmas,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_compare_identical_streams() {
+        let lemmas: Vec<u32> = (0..100).collect();
+        let stream_a = create_test_stream(1, lemmas.clone());
+        let stream_b = create_test_stream(2, lemmas);
+
+        let params = ComparisonParams {
+            window_size: 50,
+            stride: 25,
+            min_length: 10,
+            min_similarity: 0.5,
+            ..Default::default()
+        };
+
+        let result = compare_books_from_streams(&stream_a, &stream_b, &params, false).unwrap();
+
+        assert!(!result.edges.is_empty());
+        assert!(result.summary.avg_similarity > 0.9);
+        assert!(result.edges.iter().all(|e| e.shingle_jaccard == 1.0));
+    }
+
+    #[test]
+    fn test_alignment_cell_cap_skips_pairs() {
+        let lemmas: Vec<u32> = (0..100).collect();
+        let stream_a = create_test_stream(1, lemmas.clone());
+        let stream_b = create_test_stream(2, lemmas);
+
+        let params = ComparisonParams {
+            window_size: 50,
+            stride: 25,
+            min_length: 10,
+            max_alignment_cells: Some(50 * 50 - 1),
+            ..Default::default()
+        };
+
+        let result = compare_books_from_streams(&stream_a, &stream_b, &params, false).unwrap();
+        assert!(result.edges.is_empty());
+        assert!(result.summary.aborted_alignments > 0);
+    }
+
+    #[test]
+    fn test_source_range_restricts_book_a() {
+        let lemmas: Vec<u32> = (0..300).collect();
+        let stream_a = create_test_stream(1, lemmas.clone());
+        let stream_b = create_test_stream(2, lemmas);
+
+        let params = ComparisonParams {
+            window_size: 50,
+            stride: 25,
+            source_range: Some((200, 220)),
+            ..Default::default()
+        };
+
+        let result = compare_books_from_streams(&stream_a, &stream_b, &params, false).unwrap();
+        assert!(!result.edges.is_empty());
+        // Only windows [175,225) and [200,250) take part
+        for edge in &result.edges {
+            assert!(edge.source_global_start >= 175 && edge.source_global_end <= 250);
+        }
+    }
+
+    #[test]
+    fn test_min_span_jaccard_filter() {
+        // Every fourth lemma differs: alignments survive, but no 5-gram is shared
+        let lemmas_a: Vec<u32> = (0..100).collect();
+        let lemmas_b: Vec<u32> = (0..100).map(|i| if i % 4 == 0 { 1000 + i } else { i }).collect();
+        let stream_a = create_test_stream(1, lemmas_a);
+        let stream_b = create_test_stream(2, lemmas_b);
+
+        let mut params = ComparisonParams {
+            window_size: 50,
+            stride: 25,
+            brute_force: true,
+            no_filters: true,
+            ..Default::default()
+        };
+
+        let result = compare_books_from_streams(&stream_a, &stream_b, &params, false).unwrap();
+        assert!(!result.edges.is_empty());
+        assert!(result.edges.iter().all(|e| e.shingle_jaccard < 0.1));
+
+        params.min_span_jaccard = Some(0.2);
+        let result = compare_books_from_streams(&stream_a, &stream_b, &params, false).unwrap();
+        assert!(result.edges.is_empty());
+    }
 
-    let secs = duration.as_secs();
+    #[test]
+    fn test_expand_edge_recovers_full_passage() {
+        // A 200-token passage at A[100..300) and B[50..250)
+        let passage: Vec<u32> = (1..=200).collect();
+        let stream_a = BookTokenStream::single_page(1, (1000..1100).chain(passage.clone()).chain(2000..2100).collect());
+        let stream_b = BookTokenStream::single_page(2, (3000..3050).chain(passage).chain(4000..4050).collect());
+
+        // An edge reporting only the middle 80 tokens
+        let edge = ReuseEdge {
+            id: 42,
+            source_book_id: 1,
+            source_global_start: 160,
+            source_global_end: 240,
+            target_book_id: 2,
+            target_global_start: 110,
+            target_global_end: 190,
+            aligned_length: 80,
+            ..Default::default()
+        };
+        let params = ComparisonParams {
+            window_size: 100,
+            ..Default::default()
+        };
 
-    // Simple ISO 8601-ish format
-    // Calculate approximate date/time (not accounting for leap seconds, etc.)
-    let days_since_epoch = secs / 86400;
-    let secs_today = secs % 86400;
+        let expanded = expand_edge(&edge, &stream_a, &stream_b, &params);
+        assert_eq!(expanded.id, 42);
+        assert_eq!((expanded.source_global_start, expanded.source_global_end), (100, 300));
+        assert_eq!((expanded.target_global_start, expanded.target_global_end), (50, 250));
+        assert_eq!(expanded.lemma_matches, 200);
 
-    // Approximate year/month/day calculation
-    let mut year = 1970;
-    let mut remaining_days = days_since_epoch;
+        // Nothing further to find: the edge comes back as it was
+        let again = expand_edge(&expanded, &stream_a, &stream_b, &params);
+        assert_eq!(again.source_global_start, 100);
+        assert_eq!(again.source_global_end, 300);
+    }
 
-    loop {
-        let days_in_year = if is_leap_year(year) { 366 } else { 365 };
-        if remaining_days < days_in_year {
-            break;
-        }
-        remaining_days -= days_in_year;
-        year += 1;
+    #[test]
+    fn test_gated_merge_on_clean_reuse() {
+        let lemmas: Vec<u32> = (0..300).collect();
+        let stream_a = create_test_stream(1, lemmas.clone());
+        let stream_b = create_test_stream(2, lemmas);
+        let params = ComparisonParams {
+            window_size: 100,
+            stride: 50,
+            min_merge_core_similarity: Some(0.9),
+            ..Default::default()
+        };
+
+        // Verbatim reuse re-aligns cleanly, so every merge goes through
+        let result = compare_books_from_streams(&stream_a, &stream_b, &params, false).unwrap();
+        assert_eq!(result.edges.len(), 1);
+        let edge = &result.edges[0];
+        assert_eq!((edge.source_global_start, edge.source_global_end), (0, 300));
+        assert_eq!(edge.lemma_matches, 300);
+        assert_eq!(edge.core_similarity, 1.0);
     }
 
-    let month_days = if is_leap_year(year) {
-        [31, 29, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31]
-    } else {
-        [31, 28, 31, 30, 31, 30, 31, 31, 30, 31, 30, 31]
-    };
+    #[test]
+    fn test_refined_merge_counts_exactly() {
+        // Every seventh token of B is substituted, and every third around 120..150
+        let substituted = |l: usize| l.is_multiple_of(7) || ((120..150).contains(&l) && l.is_multiple_of(3));
+        let lemmas_a: Vec<u32> = (1..=300).collect();
+        let lemmas_b: Vec<u32> = lemmas_a
+            .iter()
+            .map(|&l| if substituted(l as usize) { l + 1000 } else { l })
+            .collect();
+        let stream_a = create_test_stream(1, lemmas_a);
+        let stream_b = create_test_stream(2, lemmas_b);
+        let mut params = ComparisonParams {
+            window_size: 100,
+            stride: 50,
+            no_filters: true,
+            ..Default::default()
+        };
+        let estimated = compare_books_from_streams(&stream_a, &stream_b, &params, false).unwrap();
+
+        params.refine_merges = true;
+        let refined = compare_books_from_streams(&stream_a, &stream_b, &params, false).unwrap();
+        assert_eq!(refined.edges.len(), 1);
+        let edge = &refined.edges[0];
+        let expected = (edge.source_global_start + 1..=edge.source_global_end)
+            .filter(|&l| substituted(l))
+            .count() as u32;
+        assert_eq!(edge.substitutions, expected);
+        assert_eq!(edge.lemma_matches + edge.substitutions, edge.aligned_length);
+        assert_ne!(
+            (estimated.edges[0].lemma_matches, estimated.edges[0].substitutions),
+            (edge.lemma_matches, edge.substitutions)
+        );
+    }
 
-    let mut month = 1;
-    for days in month_days.iter() {
-        if remaining_days < *days {
-            break;
-        }
-        remaining_days -= *days;
-        month += 1;
+    #[test]
+    fn test_chain_gap_bridges_commentary() {
+        // A 300-token borrowing, interrupted in B by 40 tokens of commentary
+        let passage: Vec<u32> = (1..=300).collect();
+        let lemmas_a: Vec<u32> = (1000..1100).chain(passage.clone()).chain(2000..2100).collect();
+        let lemmas_b: Vec<u32> = (3000..3100)
+            .chain(passage[..150].iter().copied())
+            .chain(4000..4040)
+            .chain(passage[150..].iter().copied())
+            .chain(5000..5100)
+            .collect();
+        let stream_a = create_test_stream(1, lemmas_a);
+        let stream_b = create_test_stream(2, lemmas_b);
+        let mut params = ComparisonParams {
+            window_size: 50,
+            stride: 25,
+            ..Default::default()
+        };
+        let plain = compare_books_from_streams(&stream_a, &stream_b, &params, false).unwrap();
+        assert!(plain.macro_edges.is_empty());
+
+        params.chain_gap = Some(60);
+        let chained = compare_books_from_streams(&stream_a, &stream_b, &params, false).unwrap();
+        assert_eq!(chained.edges.len(), plain.edges.len());
+        assert_eq!(chained.macro_edges.len(), 1);
+        let chain = &chained.macro_edges[0];
+        assert_eq!(chain.edge_ids.len(), chained.edges.len());
+        assert_eq!((chain.source_global_start, chain.source_global_end), (100, 400));
+        assert_eq!((chain.target_global_start, chain.target_global_end), (100, 440));
+        assert_eq!(chain.target_gap_tokens, 40);
     }
 
-    let day = remaining_days + 1;
-    let hour = secs_today / 3600;
-    let minute = (secs_today % 3600) / 60;
-    let second = secs_today % 60;
+    #[test]
+    fn test_two_pass_matches_single_pass() {
+        // A 150-token passage at A[400..550) and B[300..450)
+        let passage: Vec<u32> = (1..=150).collect();
+        let lemmas_a: Vec<u32> = (1000..1400).chain(passage.clone()).chain(2000..2450).collect();
+        let lemmas_b: Vec<u32> = (3000..3300).chain(passage).chain(4000..4350).collect();
+        let stream_a = create_test_stream(1, lemmas_a);
+        let stream_b = create_test_stream(2, lemmas_b);
+
+        let mut params = ComparisonParams {
+            window_size: 50,
+            stride: 25,
+            ..Default::default()
+        };
+        let single = compare_books_from_streams(&stream_a, &stream_b, &params, false).unwrap();
 
-    format!(
-        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
-        year, month, day, hour, minute, second
-    )
-}
+        params.coarse_window_size = Some(200);
+        let two_pass = compare_books_from_streams(&stream_a, &stream_b, &params, false).unwrap();
 
-fn is_leap_year(year: u64) -> bool {
-    (year % 4 == 0 && year % 100 != 0) || (year % 400 == 0)
-}
+        let spans = |result: &ComparisonResult| -> Vec<_> {
+            result
+                .edges
+                .iter()
+                .map(|e| (e.source_global_start, e.source_global_end, e.target_global_start, e.target_global_end))
+                .collect()
+        };
+        assert_eq!(spans(&two_pass), vec![(400, 550, 300, 450)]);
+        assert_eq!(spans(&two_pass), spans(&single));
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::models::PageLemmas;
+    #[test]
+    fn test_retain_candidates_in_regions() {
+        let window = |start: usize| Window {
+            book_id: 1,
+            window_idx: 0,
+            global_start: start,
+            global_end: start + 50,
+            start_page: (1, 1),
+            start_offset: 0,
+            end_page: (1, 1),
+            end_offset: 0,
+            lemma_ids: Vec::new(),
+            root_ids: Vec::new(),
+            token_ids: Vec::new(),
+            alt_lemma_ids: Vec::new(),
+            shingle_hashes: Vec::new(),
+        };
+        let windows_a: Vec<Window> = [0, 100, 200].into_iter().map(window).collect();
+        let windows_b: Vec<Window> = [0, 100, 200].into_iter().map(window).collect();
+        let regions = vec![((100, 150), (200, 250))];
 
-    fn create_test_stream(book_id: u32, lemmas: Vec<u32>) -> BookLemmaStream {
-        let total_tokens = lemmas.len();
-        BookLemmaStream {
-            book_id,
-            total_tokens,
-            pages: vec![PageLemmas {
-                part_index: 1,
-                page_id: 1,
-                lemma_ids: lemmas,
-            }],
-        }
+        let mut candidates = vec![(0, 0), (1, 2), (1, 1), (2, 2)];
+        retain_candidates_in_regions(&mut candidates, &windows_a, &windows_b, &regions);
+        assert_eq!(candidates, vec![(1, 2)]);
     }
 
     #[test]
-    fn test_compare_identical_streams() {
-        let lemmas: Vec<u32> = (0..100).collect();
-        let stream_a = create_test_stream(1, lemmas.clone());
-        let stream_b = create_test_stream(2, lemmas);
+    fn test_hadith_segmentation_one_edge_per_report() {
+        // Token 1 is "حدثنا"; each report opens with a two-link chain
+        let report = |k: u32| -> Vec<u32> {
+            [1, 500 + k, 600 + k, 1, 700 + k]
+                .into_iter()
+                .chain(k * 1000..k * 1000 + 80)
+                .collect()
+        };
+        let preface: Vec<u32> = (9000..9020).collect();
+        let stream_a = BookTokenStream::single_page(1, [preface, report(1), report(2), report(3)].concat());
+        let stream_b = BookTokenStream::single_page(2, [report(4), report(3), report(5), report(2)].concat());
 
+        let mut surfaces = vec!["w"; 9100];
+        surfaces[1] = "حدثنا";
+        let surfaces: SurfaceTable = surfaces.into_iter().collect();
         let params = ComparisonParams {
             window_size: 50,
             stride: 25,
-            min_length: 10,
-            min_similarity: 0.5,
+            no_filters: true,
+            segmentation: Segmentation::Hadith,
             ..Default::default()
         };
 
-        let result = compare_books_from_streams(&stream_a, &stream_b, &params, false).unwrap();
+        let stop_lemmas = StopLemmas::default();
+        let result =
+            compare_token_streams_with_text(&stream_a, &stream_b, &surfaces, &params, &stop_lemmas, 0, false).unwrap();
+        let mut spans: Vec<_> = result
+            .edges
+            .iter()
+            .map(|e| (e.source.global_range, e.target.global_range))
+            .collect();
+        spans.sort();
+        assert_eq!(spans, vec![((105, 190), (255, 340)), ((190, 275), (85, 170))]);
+    }
 
-        assert!(!result.edges.is_empty());
-        assert!(result.summary.avg_similarity > 0.9);
+    #[test]
+    fn test_context_grows_to_boundary() {
+        // Two pages of ten tokens; tokens 3 and 14 end a sentence
+        let page = |page_id: u32, tokens: std::ops::Range<u32>| PageTokens {
+            part_index: 1,
+            page_id,
+            token_ids: tokens.clone().collect(),
+            lemma_ids: tokens.clone().collect(),
+            root_ids: vec![0; tokens.len()],
+            alt_lemma_ids: Vec::new(),
+        };
+        let stream = BookTokenStream {
+            book_id: 1,
+            total_tokens: 20,
+            pages: vec![page(1, 0..10), page(2, 10..20)],
+            corpus_weights: None,
+            quran: None,
+        };
+        let surfaces: SurfaceTable = (0..20)
+            .map(|i| match i {
+                3 => "t3.".to_string(),
+                14 => "t14\u{61F}".to_string(),
+                _ => format!("t{i}"),
+            })
+            .collect();
+        let context = |boundary, max_context_tokens| {
+            let params = ComparisonParams {
+                context_boundary: boundary,
+                max_context_tokens,
+                ..Default::default()
+            };
+            let text = stream.get_surface_text_with_context(6, 8, PassageContext::new(1, &params), &surfaces);
+            (text.before, text.after)
+        };
+
+        assert_eq!(context(ContextBoundary::Fixed, None), ("t5".to_string(), "t8".to_string()));
+        assert_eq!(
+            context(ContextBoundary::Sentence, None),
+            ("t4 t5".to_string(), "t8 t9 t10 t11 t12 t13 t14\u{61F}".to_string())
+        );
+        assert_eq!(
+            context(ContextBoundary::Page, None),
+            ("t0 t1 t2 t3. t4 t5".to_string(), "t8 t9".to_string())
+        );
+        // The cap wins over an unreached boundary
+        assert_eq!(context(ContextBoundary::Sentence, Some(3)), ("t4 t5".to_string(), "t8 t9 t10".to_string()));
+    }
+
+    #[test]
+    fn test_min_verse_core_similarity_applies_to_verse_only() {
+        let edge = |is_verse| ReuseEdge {
+            core_similarity: 0.9,
+            is_verse,
+            ..Default::default()
+        };
+        let params = ComparisonParams {
+            no_filters: true,
+            min_verse_core_similarity: Some(0.95),
+            ..Default::default()
+        };
+        let kept = filter_edges_by_params(&[edge(false), edge(true)], &params);
+        assert_eq!(kept.len(), 1);
+        assert!(!kept[0].is_verse);
+    }
+
+    #[test]
+    fn test_max_span_asymmetry_filter() {
+        let edge = |source_span_length, target_span_length| ReuseEdge {
+            source_span_length,
+            target_span_length,
+            ..Default::default()
+        };
+        let params = ComparisonParams {
+            no_filters: true,
+            max_span_asymmetry: Some(1.5),
+            ..Default::default()
+        };
+        // Near-verbatim, an abridgment, and an expansion
+        let edges = [edge(100, 110), edge(100, 40), edge(40, 100)];
+        let kept = filter_edges_by_params(&edges, &params);
+        assert_eq!(kept.len(), 1);
+        assert_eq!(kept[0].target_span_length, 110);
     }
 
     #[test]
@@ -773,6 +2976,160 @@ mod tests {
         assert!((coverage - 0.75).abs() < 0.01);
     }
 
+    #[test]
+    fn test_recompute_summary_after_refilter() {
+        let lemmas: Vec<u32> = (0..300).collect();
+        let stream_a = create_test_stream(1, lemmas.clone());
+        let stream_b = create_test_stream(2, lemmas);
+        let params = ComparisonParams {
+            window_size: 50,
+            stride: 25,
+            ..Default::default()
+        };
+
+        let mut result = compare_books_from_streams(&stream_a, &stream_b, &params, false).unwrap();
+        let before = result.summary.clone();
+        result.recompute_summary();
+        assert_eq!(result.summary.edge_count, before.edge_count);
+        assert!((result.summary.book_a_coverage - before.book_a_coverage).abs() < 1e-6);
+
+        // Drop edges past token 100, as a later re-filter might
+        result.edges.retain(|e| e.source_global_end <= 100);
+        result.recompute_summary();
+        assert_eq!(result.summary.edge_count, result.edges.len());
+        assert!(result.summary.book_a_coverage <= 100.0 / 300.0 + 1e-6);
+
+        let no_text = |book_id| BookTokenStream {
+            book_id,
+            total_tokens: 0,
+            pages: Vec::new(),
+            corpus_weights: None,
+            quran: None,
+        };
+        let (text_a, text_b) = (no_text(1), no_text(2));
+        let mut with_text = ComparisonResultWithText {
+            version: String::new(),
+            generated_at: String::new(),
+            parameters: params,
+            book_a: ViewerBookInfo::from(&result.book_a),
+            book_b: ViewerBookInfo::from(&result.book_b),
+            summary: before,
+            edges: result
+                .edges
+                .iter()
+                .map(|e| ReuseEdgeWithText::from_edge(e, &text_a, &text_b, &SurfaceTable::default(), 0))
+                .collect(),
+            macro_edges: Vec::new(),
+            coverage_profiles: result.coverage_profiles.clone(),
+            run_info: result.run_info.clone(),
+            redaction: None,
+        };
+        with_text.recompute_summary();
+        assert_eq!(with_text.summary.edge_count, result.summary.edge_count);
+        assert!((with_text.summary.book_a_coverage - result.summary.book_a_coverage).abs() < 1e-6);
+        assert!((with_text.summary.avg_similarity - result.summary.avg_similarity).abs() < 1e-6);
+        assert_eq!(with_text.summary.book_a_aligned_tokens, result.summary.book_a_aligned_tokens);
+        assert_eq!(with_text.summary.edge_classes, result.summary.edge_classes);
+        assert_eq!(with_text.summary.length_distribution, result.summary.length_distribution);
+        assert_eq!(with_text.summary.similarity_distribution, result.summary.similarity_distribution);
+    }
+
+    #[test]
+    fn test_summary_distributions() {
+        let lengths = Distribution::new(vec![30.0, 10.0, 250.0, 40.0, 120.0, 6000.0], &LENGTH_BINS);
+        assert_eq!(
+            (lengths.min, lengths.p25, lengths.median, lengths.p75, lengths.p90, lengths.max),
+            (10.0, 30.0, 40.0, 250.0, 6000.0, 6000.0)
+        );
+        assert_eq!(lengths.counts, vec![1, 2, 0, 1, 1, 0, 0, 0, 1]);
+
+        // Bounds fall in the bin they open
+        let similarities = Distribution::new(vec![0.5, 0.9, 1.0], &SIMILARITY_BINS);
+        assert_eq!(similarities.counts, vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 2]);
+        assert_eq!(Distribution::new(Vec::new(), &SIMILARITY_BINS).median, 0.0);
+
+        let classes = EdgeClassCounts::count(
+            [(false, false, 0.0), (true, false, 0.0), (true, true, 0.9), (false, false, 0.5)].into_iter(),
+        );
+        assert_eq!(
+            classes,
+            EdgeClassCounts {
+                prose: 2,
+                verse: 2,
+                suspect: 1,
+                quran: 1
+            }
+        );
+    }
+
+    #[test]
+    fn test_coverage_profiles() {
+        // Book B holds A's first 100 tokens after 150 of its own
+        let stream_a = create_test_stream(1, (0..250).collect());
+        let stream_b = create_test_stream(2, (1000..1150).chain(0..100).collect());
+        let params = ComparisonParams {
+            window_size: 50,
+            stride: 25,
+            coverage_bins: Some(CoverageBins::Tokens(100)),
+            ..Default::default()
+        };
+
+        let mut result = compare_books_from_streams(&stream_a, &stream_b, &params, false).unwrap();
+        let profiles: Vec<(u32, Vec<usize>, Vec<usize>)> = result
+            .coverage_profiles
+            .iter()
+            .map(|p| (p.book_id, p.sizes.clone(), p.covered.clone()))
+            .collect();
+        assert_eq!(
+            profiles,
+            vec![(1, vec![100, 100, 50], vec![100, 0, 0]), (2, vec![100, 100, 50], vec![0, 50, 50])]
+        );
+
+        // Recounted from the edges left, bins unchanged
+        result.edges.clear();
+        result.recompute_summary();
+        assert_eq!(result.coverage_profiles[1].covered, vec![0, 0, 0]);
+        assert_eq!(result.coverage_profiles[1].sizes, vec![100, 100, 50]);
+
+        let pages = ComparisonParams {
+            coverage_bins: Some(CoverageBins::Pages),
+            ..params
+        };
+        let result = compare_books_from_streams(&stream_a, &stream_b, &pages, false).unwrap();
+        assert_eq!(result.coverage_profiles[0].sizes, vec![250]);
+        assert_eq!(result.coverage_profiles[0].covered, vec![100]);
+        let none = compare_books_from_streams(&stream_a, &stream_b, &ComparisonParams::default(), false).unwrap();
+        assert!(none.coverage_profiles.is_empty());
+    }
+
+    #[test]
+    fn test_order_tau() {
+        // Same order in both books
+        assert_eq!(order_tau(vec![(0, 10), (100, 20), (200, 30), (300, 40)]), 1.0);
+        // Reversed
+        assert_eq!(order_tau(vec![(0, 40), (100, 30), (200, 20), (300, 10)]), -1.0);
+        // One swap of six pairs: 5 concordant, 1 discordant
+        assert!((order_tau(vec![(0, 10), (100, 30), (200, 20), (300, 40)]) - 4.0 / 6.0).abs() < 1e-6);
+        // A target tie counts as neither
+        assert!((order_tau(vec![(0, 10), (100, 10), (200, 20)]) - 2.0 / 3.0).abs() < 1e-6);
+        assert_eq!(order_tau(vec![(0, 10)]), 0.0);
+    }
+
+    #[test]
+    fn test_covered_tokens_per_page() {
+        let edges = vec![ReuseEdge {
+            source_book_id: 1,
+            source_global_start: 5,
+            source_global_end: 25,
+            target_book_id: 2,
+            ..Default::default()
+        }];
+
+        // Pages of 10 tokens: edge covers 5 of page 0, all of page 1, 5 of page 2
+        assert_eq!(covered_tokens_per_page(&edges, 1, &[10, 10, 10, 10]), vec![5, 10, 5, 0]);
+        assert_eq!(covered_tokens_per_page(&edges, 3, &[10]), vec![0]);
+    }
+
     #[test]
     fn test_merge_ranges() {
         let ranges = vec![(0, 50), (25, 75), (100, 150)];
@@ -798,82 +3155,3 @@ mod tests {
         assert_eq!(merged[0], (0, 50));
     }
 }
-
-// Default implementation for ReuseEdge (for tests)
-impl Default for ReuseEdge {
-    fn default() -> Self {
-        ReuseEdge {
-            id: 0,
-            source_book_id: 0,
-            source_start_page: (0, 0),
-            source_start_offset: 0,
-            source_end_page: (0, 0),
-            source_end_offset: 0,
-            source_global_start: 0,
-            source_global_end: 0,
-            target_book_id: 0,
-            target_start_page: (0, 0),
-            target_start_offset: 0,
-            target_end_page: (0, 0),
-            target_end_offset: 0,
-            target_global_start: 0,
-            target_global_end: 0,
-            aligned_length: 0,
-            lemma_matches: 0,
-            substitutions: 0,
-            root_only_matches: 0,
-            gaps: 0,
-            core_similarity: 0.0,
-            span_coverage: 0.0,
-            content_weight: 0.0,
-            lexical_diversity: 0.0,
-            lemma_similarity: 0.0,
-            combined_similarity: 0.0,
-            weighted_similarity: 0.0,
-            avg_match_weight: 0.0,
-        }
-    }
-}
-
-// ============================================================================
-// Document-internal IDF weighting
-// ============================================================================
-
-/// Build document-internal IDF weights for a book's lemma stream.
-///
-/// For each lemma ℓ in book B:
-///   weight_B(ℓ) = ln(total_tokens_B / df_B(ℓ))
-///
-/// Weights are clamped to [0.5, 3.0] for stability.
-///
-/// Returns a Vec indexed by lemma_id, with weights for each lemma seen in the book.
-pub fn build_lemma_weights(lemma_ids: &[u32], max_lemma_id: usize) -> Vec<f32> {
-    // Count document frequency for each lemma
-    let mut counts = vec![0u32; max_lemma_id + 1];
-
-    for &id in lemma_ids {
-        if (id as usize) < counts.len() {
-            counts[id as usize] += 1;
-        }
-    }
-
-    let total = lemma_ids.len() as f32;
-    let mut weights = vec![0.0f32; max_lemma_id + 1];
-
-    for (id, &df) in counts.iter().enumerate() {
-        if df > 0 {
-            // IDF formula: ln(total / df), clamped to [0.5, 3.0]
-            let w = (total / df as f32).ln().clamp(0.5, 3.0);
-            weights[id] = w;
-        }
-    }
-
-    weights
-}
-
-/// Find the maximum lemma ID in the token streams.
-pub fn find_max_lemma_id(stream_a: &BookTokenStream, stream_b: &BookTokenStream) -> usize {
-    let max_a = stream_a.flat_lemma_ids().iter().copied().max().unwrap_or(0) as usize;
-    let max_b = stream_b.flat_lemma_ids().iter().copied().max().unwrap_or(0) as usize;
-    max_a.max(max_b)
-}
@@ -0,0 +1,104 @@
+//! Comparison parameters from TOML run configuration files.
+//!
+//! A run configuration keeps a tuned set of parameters next to the results
+//! it produced. Its `[params]` table takes `ComparisonParams` fields by name,
+//! as in the `parameters` block of a result file; fields left out keep their
+//! defaults:
+//!
+//! ```toml
+//! [params]
+//! window_size = 300
+//! min_core_similarity = 0.9
+//! weight_source = "Corpus"
+//! ```
+//!
+//! The CLI reads the rest of the file as the subcommand's flags (`--config`,
+//! see the README); the library only reads `[params]`.
+
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::models::ComparisonParams;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("{}: {source}", path.display())]
+    Io { path: PathBuf, source: std::io::Error },
+    #[error("{}: {source}", path.display())]
+    Toml { path: PathBuf, source: toml::de::Error },
+    #[error("[params]: {0}")]
+    Params(String),
+}
+
+impl ComparisonParams {
+    /// Parameters from a table of `ComparisonParams` fields: the defaults,
+    /// overridden by the table's values. Unknown fields are an error.
+    pub fn from_toml_table(table: &toml::Table) -> Result<Self, ConfigError> {
+        let serde_json::Value::Object(mut fields) =
+            serde_json::to_value(ComparisonParams::default()).map_err(|e| ConfigError::Params(e.to_string()))?
+        else {
+            unreachable!("ComparisonParams serializes to an object");
+        };
+        for (key, value) in table {
+            if !fields.contains_key(key) {
+                return Err(ConfigError::Params(format!("unknown parameter `{}`", key)));
+            }
+            let value = serde_json::to_value(value).map_err(|e| ConfigError::Params(format!("{}: {}", key, e)))?;
+            fields.insert(key.clone(), value);
+        }
+        serde_json::from_value(serde_json::Value::Object(fields)).map_err(|e| ConfigError::Params(e.to_string()))
+    }
+
+    /// Parameters from the `[params]` table of a TOML run configuration
+    /// file; a file without one yields the defaults.
+    pub fn from_toml_file(path: &Path) -> Result<Self, ConfigError> {
+        let table = read_toml_file(path)?;
+        match table.get("params") {
+            Some(toml::Value::Table(params)) => Self::from_toml_table(params),
+            Some(_) => Err(ConfigError::Params("not a table".to_string())),
+            None => Ok(Self::default()),
+        }
+    }
+}
+
+/// Read a TOML file as a table.
+pub fn read_toml_file(path: &Path) -> Result<toml::Table, ConfigError> {
+    let text = std::fs::read_to_string(path).map_err(|source| ConfigError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    toml::from_str(&text).map_err(|source| ConfigError::Toml {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::WeightSource;
+
+    #[test]
+    fn test_params_from_toml_table() {
+        let table: toml::Table = toml::from_str(
+            r#"
+            window_size = 300
+            min_core_similarity = 0.9
+            weight_source = "Corpus"
+            stop_lemmas = [7, 8]
+            "#,
+        )
+        .unwrap();
+        let params = ComparisonParams::from_toml_table(&table).unwrap();
+        assert_eq!(params.window_size, 300);
+        assert_eq!(params.min_core_similarity, Some(0.9));
+        assert_eq!(params.weight_source, WeightSource::Corpus);
+        assert_eq!(params.stop_lemmas.map(|s| s.len()), Some(2));
+        assert_eq!(params.stride, ComparisonParams::default().stride);
+
+        let typo: toml::Table = toml::from_str("windowsize = 300").unwrap();
+        assert!(matches!(ComparisonParams::from_toml_table(&typo), Err(ConfigError::Params(_))));
+        let wrong_type: toml::Table = toml::from_str("window_size = \"wide\"").unwrap();
+        assert!(matches!(ComparisonParams::from_toml_table(&wrong_type), Err(ConfigError::Params(_))));
+    }
+}
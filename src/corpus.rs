@@ -0,0 +1,474 @@
+//! In-process corpus cache for library users.
+//!
+//! Every `compare_books*` call opens corpus.db and reloads the 1.8M-entry
+//! token tables before touching a single book. A [`Corpus`] keeps one
+//! connection open, loads the token mappings on first use, and keeps recently
+//! used book streams in memory up to a byte budget, evicting the least
+//! recently used first:
+//!
+//! ```no_run
+//! use kashshaf_reuse::corpus::Corpus;
+//! use kashshaf_reuse::ComparisonParams;
+//!
+//! let mut corpus = Corpus::open("corpus.db".as_ref()).unwrap();
+//! let params = ComparisonParams::default();
+//! for target in [553, 1021, 1402] {
+//!     let result = corpus.compare(230, target, &params).unwrap();
+//!     println!("230 vs {}: {} edges", target, result.edges.len());
+//! }
+//! ```
+//!
+//! A [`CorpusBuilder`] makes a corpus from raw texts instead, held in an
+//! in-memory database; handy for examples, tests and small ad hoc comparisons.
+//! [`Corpus::from_source`] reads from another storage backend (see
+//! [`crate::source`]).
+
+use rusqlite::Connection;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::abridge::EdgeSpan;
+use crate::analysis::collate_passages;
+use crate::compare::{
+    candidate_pairs_from_streams, compare_token_streams, compare_token_streams_with_text, plan_token_streams,
+    record_load, resolve_root_modes, trace_window_pairs, AlignmentTrace,
+};
+use crate::db::import::{create_schema, CorpusWriter};
+use crate::db::{DbError, TokenMappings};
+use crate::idf::{load_corpus_stop_lemmas, load_corpus_weights, weights_from_counts};
+use crate::models::{
+    BookTokenStream, CandidateSet, Collation, ComparisonParams, ComparisonPlan, ComparisonResult,
+    ComparisonResultWithText, LemmaAlternatives, PassageCluster, RunInfo, SubstitutionReport, WeightSource,
+};
+use crate::quran::QuranIndex;
+use crate::signature::StopLemmas;
+use crate::source::{CorpusSource, SqliteSource};
+use crate::substitution::{attach_alignment_ops, substitution_report};
+use crate::tokenize::{SimpleTokenizer, Tokenizer};
+
+/// Default memory budget for cached book streams: 1 GiB.
+pub const DEFAULT_CACHE_BYTES: usize = 1 << 30;
+
+/// A cached stream, keyed by book ID, whether lemma alternatives and
+/// corpus-wide weights are attached, and the book indexed as the Quran.
+type StreamKey = (u32, bool, bool, Option<u32>);
+
+/// Two books' streams and the parameters to compare them with.
+pub(crate) type StreamPair<'p> = (Arc<BookTokenStream>, Arc<BookTokenStream>, Cow<'p, ComparisonParams>);
+
+struct CachedStream {
+    stream: Arc<BookTokenStream>,
+    bytes: usize,
+    last_used: u64,
+}
+
+/// An open corpus (corpus.db or another [`CorpusSource`]) with lazily loaded
+/// token mappings and an LRU cache of book token streams.
+pub struct Corpus {
+    source: Box<dyn CorpusSource + Send>,
+    /// None for an in-memory corpus or another source
+    db_path: Option<PathBuf>,
+    mappings: Option<TokenMappings>,
+    alternatives: Option<LemmaAlternatives>,
+    corpus_weights: Option<Arc<Vec<f32>>>,
+    stop_lemmas: Option<StopLemmas>,
+    /// The Quran index and the book it was built from
+    quran: Option<(u32, Arc<QuranIndex>)>,
+    streams: HashMap<StreamKey, CachedStream>,
+    cached_bytes: usize,
+    max_cache_bytes: usize,
+    /// Logical clock for recency; bumped on every stream access
+    clock: u64,
+}
+
+impl Corpus {
+    /// Open corpus.db. Nothing is loaded until a comparison needs it.
+    pub fn open(db_path: &Path) -> Result<Self, DbError> {
+        Ok(Self::with_source(Box::new(SqliteSource::open(db_path)?), Some(db_path.to_path_buf())))
+    }
+
+    /// A corpus read from another storage backend. Corpus-wide weights are
+    /// counted through the source, since there is no sidecar file to cache them.
+    pub fn from_source(source: impl CorpusSource + Send + 'static) -> Self {
+        Self::with_source(Box::new(source), None)
+    }
+
+    fn with_source(source: Box<dyn CorpusSource + Send>, db_path: Option<PathBuf>) -> Self {
+        Self {
+            source,
+            db_path,
+            mappings: None,
+            alternatives: None,
+            corpus_weights: None,
+            stop_lemmas: None,
+            quran: None,
+            streams: HashMap::new(),
+            cached_bytes: 0,
+            max_cache_bytes: DEFAULT_CACHE_BYTES,
+            clock: 0,
+        }
+    }
+
+    /// Set the memory budget for cached streams (default [`DEFAULT_CACHE_BYTES`]).
+    /// The two streams of the comparison in progress are always kept.
+    pub fn with_cache_limit(mut self, max_bytes: usize) -> Self {
+        self.max_cache_bytes = max_bytes;
+        self
+    }
+
+    /// The storage the corpus reads from, e.g. to list its books.
+    pub fn source(&self) -> &dyn CorpusSource {
+        self.source.as_ref()
+    }
+
+    /// The token mappings (lemma, root, surface), loaded on first use.
+    pub fn token_mappings(&mut self) -> Result<&TokenMappings, DbError> {
+        if self.mappings.is_none() {
+            self.mappings = Some(self.source.load_token_mappings()?);
+        }
+        Ok(self.mappings.as_ref().expect("mappings loaded above"))
+    }
+
+    /// A book's token stream, from the cache or loaded from the database.
+    /// With `with_alternatives`, alternative lemma analyses are attached.
+    pub fn book_stream(&mut self, book_id: u32, with_alternatives: bool) -> Result<Arc<BookTokenStream>, DbError> {
+        self.load_stream(book_id, with_alternatives, false, None)
+    }
+
+    /// Corpus-wide IDF weights, from the sidecar file or counted on first use.
+    /// An in-memory corpus or another source has no sidecar and always counts.
+    /// See [`crate::idf`].
+    pub fn corpus_weights(&mut self) -> Result<Arc<Vec<f32>>, DbError> {
+        if self.corpus_weights.is_none() {
+            self.token_mappings()?;
+            let token_to_lemma = &self.mappings.as_ref().expect("mappings loaded above").0;
+            let weights = match &self.db_path {
+                Some(db_path) => load_corpus_weights(db_path, token_to_lemma)?,
+                None => Arc::new(weights_from_counts(&self.source.count_lemmas(token_to_lemma)?)),
+            };
+            self.corpus_weights = Some(weights);
+        }
+        Ok(Arc::clone(self.corpus_weights.as_ref().expect("weights loaded above")))
+    }
+
+    /// Stop-lemmas for edge signatures: `params.stop_lemmas` when set,
+    /// otherwise the corpus-wide list, cached alongside the weights.
+    pub fn stop_lemmas(&mut self, params: &ComparisonParams) -> Result<StopLemmas, DbError> {
+        StopLemmas::for_params(params, || {
+            if self.stop_lemmas.is_none() {
+                self.token_mappings()?;
+                let token_to_lemma = &self.mappings.as_ref().expect("mappings loaded above").0;
+                let stop_lemmas = match &self.db_path {
+                    Some(db_path) => load_corpus_stop_lemmas(db_path, token_to_lemma)?,
+                    None => StopLemmas::from_counts(&self.source.count_lemmas(token_to_lemma)?),
+                };
+                self.stop_lemmas = Some(stop_lemmas);
+            }
+            Ok(self.stop_lemmas.clone().expect("stop-lemmas loaded above"))
+        })
+    }
+
+    /// Book `book_id` indexed as the Quran (see [`crate::quran`]), built on
+    /// first use.
+    pub fn quran_index(&mut self, book_id: u32) -> Result<Arc<QuranIndex>, DbError> {
+        match &self.quran {
+            Some((cached_id, index)) if *cached_id == book_id => Ok(Arc::clone(index)),
+            _ => {
+                self.token_mappings()?;
+                let token_to_lemma = &self.mappings.as_ref().expect("mappings loaded above").0;
+                let stream = self.source.load_book_stream(book_id, token_to_lemma, &[])?;
+                let index = Arc::new(QuranIndex::from_stream(&stream));
+                self.quran = Some((book_id, Arc::clone(&index)));
+                Ok(index)
+            }
+        }
+    }
+
+    fn load_stream(
+        &mut self,
+        book_id: u32,
+        with_alternatives: bool,
+        with_corpus_weights: bool,
+        quran_book: Option<u32>,
+    ) -> Result<Arc<BookTokenStream>, DbError> {
+        self.clock += 1;
+        let key = (book_id, with_alternatives, with_corpus_weights, quran_book);
+        if let Some(cached) = self.streams.get_mut(&key) {
+            cached.last_used = self.clock;
+            return Ok(Arc::clone(&cached.stream));
+        }
+
+        self.token_mappings()?;
+        let (token_to_lemma, token_to_root, _) = self.mappings.as_ref().expect("mappings loaded above");
+        let mut stream = self.source.load_book_stream(book_id, token_to_lemma, token_to_root)?;
+        if with_alternatives {
+            if self.alternatives.is_none() {
+                self.alternatives = Some(self.source.load_lemma_alternatives()?);
+            }
+            stream.attach_lemma_alternatives(self.alternatives.as_ref().expect("alternatives loaded above"));
+        }
+        if with_corpus_weights {
+            stream.attach_corpus_weights(&self.corpus_weights()?);
+        }
+        if let Some(quran_book) = quran_book {
+            stream.attach_quran(&self.quran_index(quran_book)?);
+        }
+
+        let stream = Arc::new(stream);
+        let bytes = stream_bytes(&stream);
+        self.streams.insert(
+            key,
+            CachedStream {
+                stream: Arc::clone(&stream),
+                bytes,
+                last_used: self.clock,
+            },
+        );
+        self.cached_bytes += bytes;
+        self.evict();
+        Ok(stream)
+    }
+
+    /// Compare two books, as `compare --format json` does.
+    pub fn compare(&mut self, book_a: u32, book_b: u32, params: &ComparisonParams) -> Result<ComparisonResult, DbError> {
+        let (stream_a, stream_b, params) = self.stream_pair(book_a, book_b, params)?;
+        let token_to_surface = &self.mappings.as_ref().expect("mappings loaded with the streams").2;
+        let mut result = compare_token_streams(&stream_a, &stream_b, token_to_surface, &params, false)?;
+        self.record_source(&mut result.run_info);
+        Ok(result)
+    }
+
+    /// Compare two books with text reconstruction.
+    pub fn compare_with_text(
+        &mut self,
+        book_a: u32,
+        book_b: u32,
+        params: &ComparisonParams,
+        context_tokens: usize,
+    ) -> Result<ComparisonResultWithText, DbError> {
+        let (stream_a, stream_b, params) = self.stream_pair(book_a, book_b, params)?;
+        let stop_lemmas = self.stop_lemmas(&params)?;
+        let token_to_surface = &self.mappings.as_ref().expect("mappings loaded with the streams").2;
+        let mut result = compare_token_streams_with_text(
+            &stream_a,
+            &stream_b,
+            token_to_surface,
+            &params,
+            &stop_lemmas,
+            context_tokens,
+            false,
+        )?;
+        self.record_source(&mut result.run_info);
+        Ok(result)
+    }
+
+    /// Candidate window pairs between two books, without aligning them.
+    /// See [`candidate_pairs_from_streams`].
+    pub fn candidates(&mut self, book_a: u32, book_b: u32, params: &ComparisonParams) -> Result<CandidateSet, DbError> {
+        let (stream_a, stream_b, params) = self.stream_pair(book_a, book_b, params)?;
+        let token_to_surface = &self.mappings.as_ref().expect("mappings loaded with the streams").2;
+        Ok(candidate_pairs_from_streams(&stream_a, &stream_b, token_to_surface, &params))
+    }
+
+    /// Estimate the cost of comparing two books. See [`plan_token_streams`].
+    pub fn plan(&mut self, book_a: u32, book_b: u32, params: &ComparisonParams) -> Result<ComparisonPlan, DbError> {
+        let (stream_a, stream_b, params) = self.stream_pair(book_a, book_b, params)?;
+        let token_to_surface = &self.mappings.as_ref().expect("mappings loaded with the streams").2;
+        Ok(plan_token_streams(&stream_a, &stream_b, token_to_surface, &params))
+    }
+
+    /// Align selected window pairs between two books for debugging, with the
+    /// comparison's final edges. See [`trace_window_pairs`].
+    pub fn trace(
+        &mut self,
+        book_a: u32,
+        book_b: u32,
+        params: &ComparisonParams,
+        pairs: &[(u32, u32)],
+    ) -> Result<AlignmentTrace, DbError> {
+        let (stream_a, stream_b, params) = self.stream_pair(book_a, book_b, params)?;
+        let token_to_surface = &self.mappings.as_ref().expect("mappings loaded with the streams").2;
+        trace_window_pairs(&stream_a, &stream_b, token_to_surface, &params, pairs)
+    }
+
+    /// Lemma substitutions inside the edges of an earlier comparison of the
+    /// same two books. See [`substitution_report`].
+    pub fn substitutions(
+        &mut self,
+        book_a: u32,
+        book_b: u32,
+        params: &ComparisonParams,
+        spans: &[EdgeSpan],
+    ) -> Result<SubstitutionReport, DbError> {
+        let (stream_a, stream_b, params) = self.stream_pair(book_a, book_b, params)?;
+        let token_to_surface = &self.mappings.as_ref().expect("mappings loaded with the streams").2;
+        Ok(substitution_report(spans, &stream_a, &stream_b, token_to_surface, &params))
+    }
+
+    /// Set the alignment ops of every edge of a result compared from this
+    /// corpus; returns the number left without. See [`attach_alignment_ops`].
+    pub fn alignment_ops(&mut self, result: &mut ComparisonResultWithText) -> Result<usize, DbError> {
+        let (stream_a, stream_b, _) = self.stream_pair(result.book_a.id, result.book_b.id, &result.parameters)?;
+        Ok(attach_alignment_ops(result, &stream_a, &stream_b))
+    }
+
+    /// Align the passages of a cluster found in this corpus word by word. See
+    /// [`collate_passages`].
+    pub fn collate(&mut self, cluster: &PassageCluster, params: &ComparisonParams) -> Result<Collation, DbError> {
+        let streams = cluster
+            .members
+            .iter()
+            .map(|member| self.book_stream(member.book_id, false))
+            .collect::<Result<Vec<_>, _>>()?;
+        let streams: Vec<&BookTokenStream> = streams.iter().map(|stream| stream.as_ref()).collect();
+        let token_to_surface = &self.token_mappings()?.2;
+        Ok(collate_passages(cluster, &streams, token_to_surface, params))
+    }
+
+    /// Number of book streams currently cached.
+    pub fn cached_books(&self) -> usize {
+        self.streams.len()
+    }
+
+    /// Estimated memory held by cached streams, in bytes.
+    pub fn cached_bytes(&self) -> usize {
+        self.cached_bytes
+    }
+
+    /// Drop every cached stream; the token mappings stay loaded.
+    pub fn clear_cache(&mut self) {
+        self.streams.clear();
+        self.cached_bytes = 0;
+    }
+
+    /// Record the database file a result's books came from; streams come
+    /// from the cache, so no load time is recorded.
+    fn record_source(&self, run_info: &mut RunInfo) {
+        if let Some(db_path) = &self.db_path {
+            record_load(run_info, db_path, 0);
+        }
+    }
+
+    /// Both books' streams, with the parameters to compare them with (see
+    /// [`resolve_root_modes`]).
+    pub(crate) fn stream_pair<'p>(
+        &mut self,
+        book_a: u32,
+        book_b: u32,
+        params: &'p ComparisonParams,
+    ) -> Result<StreamPair<'p>, DbError> {
+        let with_corpus_weights = params.use_weights && params.weight_source == WeightSource::Corpus;
+        let (alternatives, quran_book) = (params.use_lemma_alternatives, params.quran_book_id);
+        let stream_a = self.load_stream(book_a, alternatives, with_corpus_weights, quran_book)?;
+        let stream_b = self.load_stream(book_b, alternatives, with_corpus_weights, quran_book)?;
+        let token_to_root = &self.mappings.as_ref().expect("mappings loaded with the streams").1;
+        Ok((stream_a, stream_b, resolve_root_modes(params, token_to_root, false)?))
+    }
+
+    /// Evict least recently used streams until the cache fits its budget.
+    /// The most recent stream is never evicted, and a stream still held by a
+    /// caller stays alive through its `Arc`.
+    fn evict(&mut self) {
+        while self.cached_bytes > self.max_cache_bytes && self.streams.len() > 1 {
+            let oldest = self
+                .streams
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(&key, _)| key)
+                .expect("cache is not empty");
+            if let Some(cached) = self.streams.remove(&oldest) {
+                self.cached_bytes -= cached.bytes;
+            }
+        }
+    }
+}
+
+/// Builds a [`Corpus`] from raw texts in an in-memory database.
+///
+/// Texts are split by a [`Tokenizer`] ([`SimpleTokenizer`] unless another is
+/// set). There is no morphological analysis: each distinct surface form is a
+/// token whose lemma is its [normalized orthography](normalize_orthography),
+/// so forms differing only in diacritics, hamza seats or ta marbuta match.
+/// Roots are left unset, so root matching never fires.
+///
+/// ```
+/// use kashshaf_reuse::corpus::CorpusBuilder;
+///
+/// let mut corpus = CorpusBuilder::new()
+///     .book(1, ["الصَّلاةُ خيرٌ من النوم", "حي على الفلاح"])
+///     .book(2, ["الصلاة خير من النوم"])
+///     .build()
+///     .unwrap();
+///
+/// let a = corpus.book_stream(1, false).unwrap();
+/// let b = corpus.book_stream(2, false).unwrap();
+/// assert_eq!((a.page_count(), a.total_tokens), (2, 7));
+/// // Different surface forms, same lemmas
+/// assert_ne!(a.pages[0].token_ids, b.pages[0].token_ids);
+/// assert_eq!(a.pages[0].lemma_ids, b.pages[0].lemma_ids);
+/// ```
+pub struct CorpusBuilder {
+    tokenizer: Box<dyn Tokenizer>,
+    books: Vec<(u32, Vec<String>)>,
+}
+
+impl Default for CorpusBuilder {
+    fn default() -> Self {
+        Self {
+            tokenizer: Box::new(SimpleTokenizer),
+            books: Vec::new(),
+        }
+    }
+}
+
+impl CorpusBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use `tokenizer` instead of [`SimpleTokenizer`].
+    pub fn tokenizer(mut self, tokenizer: impl Tokenizer + 'static) -> Self {
+        self.tokenizer = Box::new(tokenizer);
+        self
+    }
+
+    /// Add a book with one text per page. Pages are numbered from 1 in part 1.
+    pub fn book<S: AsRef<str>>(mut self, book_id: u32, pages: impl IntoIterator<Item = S>) -> Self {
+        self.books
+            .push((book_id, pages.into_iter().map(|p| p.as_ref().to_string()).collect()));
+        self
+    }
+
+    /// Tokenize every book and load it into a new in-memory corpus.
+    pub fn build(self) -> Result<Corpus, DbError> {
+        let mut conn = Connection::open_in_memory()?;
+        create_schema(&conn)?;
+        let mut writer = CorpusWriter::new(conn.transaction()?);
+        for (book_id, pages) in &self.books {
+            for (page, text) in pages.iter().enumerate() {
+                writer.add_page(*book_id, 1, page as u32 + 1, &self.tokenizer.tokenize(text))?;
+            }
+        }
+        writer.commit()?;
+
+        Ok(Corpus::with_source(Box::new(SqliteSource::from_connection(conn)), None))
+    }
+}
+
+/// Approximate heap size of a token stream.
+fn stream_bytes(stream: &BookTokenStream) -> usize {
+    stream
+        .pages
+        .iter()
+        .map(|page| {
+            let alternatives: usize = page
+                .alt_lemma_ids
+                .iter()
+                .map(|alts| std::mem::size_of::<Vec<u32>>() + alts.len() * 4)
+                .sum();
+            (page.token_ids.len() + page.lemma_ids.len() + page.root_ids.len()) * 4 + alternatives
+        })
+        .sum()
+}
@@ -1,12 +1,17 @@
 //! SQLite database access layer for corpus.db
 
 use crate::models::{
-    BookInfo, BookLemmaStream, BookTokenStream, CorpusStats, PageInfo, PageLemmas, PageTokens,
+    BookInfo, BookLemmaStream, BookTokenStream, CorpusStats, LemmaAlternatives, PageInfo, PageLemmas,
+    PageTokens, SurfaceTable, SurfaceTableBuilder,
 };
+use crate::normalize::{NormalizationConfig, SurfaceClasses};
 use rusqlite::{Connection, Result};
+use std::collections::{BTreeSet, HashMap};
 use std::path::Path;
 use thiserror::Error;
 
+pub mod import;
+
 #[derive(Error, Debug)]
 pub enum DbError {
     #[error("SQLite error: {0}")]
@@ -17,13 +22,22 @@ pub enum DbError {
     BookNotFound(u32),
     #[error("Invalid token blob size")]
     InvalidTokenBlob,
+    #[error("Collection not found: {0}")]
+    CollectionNotFound(String),
+    #[error("Corpus source error: {0}")]
+    Source(Box<dyn std::error::Error + Send + Sync>),
+    #[error("corpus.db has no root data (token_definitions.root_id is missing or NULL); root and combined modes need it")]
+    NoRootData,
 }
 
 /// Load token_id -> lemma_id mapping from token_definitions table.
 /// This is ~1.8M entries, optimized for fast lookup using a flat array.
 pub fn load_token_to_lemma(db_path: &Path) -> Result<Vec<u32>, DbError> {
-    let conn = Connection::open(db_path)?;
+    load_token_to_lemma_with_conn(&Connection::open(db_path)?)
+}
 
+/// [`load_token_to_lemma`] on an open connection.
+pub(crate) fn load_token_to_lemma_with_conn(conn: &Connection) -> Result<Vec<u32>, DbError> {
     // Get max token ID to size the array
     let max_id: u32 =
         conn.query_row("SELECT MAX(id) FROM token_definitions", [], |row| {
@@ -45,11 +59,48 @@ pub fn load_token_to_lemma(db_path: &Path) -> Result<Vec<u32>, DbError> {
     Ok(mapping)
 }
 
+/// Load alternative lemma analyses from the optional token_lemma_alternatives table.
+/// Returns an empty map when the table is absent.
+pub fn load_lemma_alternatives(db_path: &Path) -> Result<LemmaAlternatives, DbError> {
+    load_lemma_alternatives_with_conn(&Connection::open(db_path)?)
+}
+
+/// [`load_lemma_alternatives`] on an open connection.
+pub(crate) fn load_lemma_alternatives_with_conn(conn: &Connection) -> Result<LemmaAlternatives, DbError> {
+    let has_table: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master
+         WHERE type = 'table' AND name = 'token_lemma_alternatives'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_table {
+        return Ok(LemmaAlternatives::new());
+    }
+
+    let mut alternatives = LemmaAlternatives::new();
+    let mut stmt = conn.prepare("SELECT token_id, lemma_id FROM token_lemma_alternatives")?;
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let token_id: u32 = row.get(0)?;
+        let lemma_id: u32 = row.get(1)?;
+        alternatives.entry(token_id).or_default().push(lemma_id);
+    }
+
+    Ok(alternatives)
+}
+
+/// Token mappings indexed by token_id: (token_to_lemma, token_to_root, token_to_surface).
+pub type TokenMappings = (Vec<u32>, Vec<u32>, SurfaceTable);
+
 /// Load token_to_lemma, token_to_root, and token_to_surface mappings in a single pass.
 /// Most efficient when you need all three mappings.
-pub fn load_all_token_mappings(db_path: &Path) -> Result<(Vec<u32>, Vec<u32>, Vec<String>), DbError> {
-    let conn = Connection::open(db_path)?;
+pub fn load_all_token_mappings(db_path: &Path) -> Result<TokenMappings, DbError> {
+    load_all_token_mappings_with_conn(&Connection::open(db_path)?)
+}
 
+/// [`load_all_token_mappings`] on an open connection.
+pub(crate) fn load_all_token_mappings_with_conn(conn: &Connection) -> Result<TokenMappings, DbError> {
     // Get max token ID to size the arrays
     let max_id: u32 =
         conn.query_row("SELECT MAX(id) FROM token_definitions", [], |row| {
@@ -59,22 +110,121 @@ pub fn load_all_token_mappings(db_path: &Path) -> Result<(Vec<u32>, Vec<u32>, Ve
     // Pre-allocate arrays
     let mut lemma_mapping = vec![0u32; (max_id + 1) as usize];
     let mut root_mapping = vec![0u32; (max_id + 1) as usize];
-    let mut surface_mapping = vec![String::new(); (max_id + 1) as usize];
+    let mut surfaces = SurfaceTableBuilder::default();
 
-    let mut stmt = conn.prepare("SELECT id, surface, lemma_id, root_id FROM token_definitions")?;
+    // Ascending IDs let the surfaces be appended to the table in place
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id, surface, lemma_id, {} FROM token_definitions ORDER BY id",
+        root_column(conn)?
+    ))?;
     let mut rows = stmt.query([])?;
 
     while let Some(row) = rows.next()? {
         let token_id: u32 = row.get(0)?;
-        let surface: String = row.get(1)?;
+        let surface = row.get_ref(1)?.as_str().map_err(rusqlite::Error::from)?;
         let lemma_id: u32 = row.get(2)?;
         let root_id: Option<u32> = row.get(3)?;
         lemma_mapping[token_id as usize] = lemma_id;
         root_mapping[token_id as usize] = root_id.unwrap_or(0);
-        surface_mapping[token_id as usize] = surface;
+        surfaces.push(token_id, surface);
+    }
+
+    Ok((lemma_mapping, root_mapping, surfaces.finish()))
+}
+
+/// Load token_to_lemma and token_to_root without the surface table, for
+/// runs that query surfaces by token ID instead (see [`load_surfaces`]).
+pub fn load_lemma_root_mappings(db_path: &Path) -> Result<(Vec<u32>, Vec<u32>), DbError> {
+    let conn = Connection::open(db_path)?;
+    let max_id: u32 =
+        conn.query_row("SELECT MAX(id) FROM token_definitions", [], |row| {
+            row.get(0)
+        })?;
+
+    let mut lemma_mapping = vec![0u32; (max_id + 1) as usize];
+    let mut root_mapping = vec![0u32; (max_id + 1) as usize];
+
+    let mut stmt = conn.prepare(&format!("SELECT id, lemma_id, {} FROM token_definitions", root_column(&conn)?))?;
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let token_id: u32 = row.get(0)?;
+        let lemma_id: u32 = row.get(1)?;
+        let root_id: Option<u32> = row.get(2)?;
+        lemma_mapping[token_id as usize] = lemma_id;
+        root_mapping[token_id as usize] = root_id.unwrap_or(0);
+    }
+
+    Ok((lemma_mapping, root_mapping))
+}
+
+/// The root column to select from token_definitions: `root_id`, or `NULL`
+/// for a corpus.db built without one (every root then loads as 0).
+fn root_column(conn: &Connection) -> Result<&'static str, DbError> {
+    let has_root_id = conn
+        .prepare("SELECT 1 FROM pragma_table_info('token_definitions') WHERE name = 'root_id'")?
+        .exists([])?;
+    Ok(if has_root_id { "root_id" } else { "NULL" })
+}
+
+/// Whether a token_id -> root_id mapping holds any root at all. A corpus.db
+/// whose root_id column is missing or entirely NULL maps every token to 0.
+pub fn has_root_data(token_to_root: &[u32]) -> bool {
+    token_to_root.iter().any(|&root_id| root_id != 0)
+}
+
+/// Token IDs whose surface form passes `keep`, in one pass over
+/// token_definitions that holds only one surface in memory at a time.
+pub fn find_surface_token_ids(db_path: &Path, mut keep: impl FnMut(&str) -> bool) -> Result<Vec<u32>, DbError> {
+    let conn = Connection::open(db_path)?;
+    let mut stmt = conn.prepare("SELECT id, surface FROM token_definitions")?;
+    let mut rows = stmt.query([])?;
+
+    let mut token_ids = Vec::new();
+    while let Some(row) = rows.next()? {
+        let surface: String = row.get(1)?;
+        if keep(&surface) {
+            token_ids.push(row.get(0)?);
+        }
+    }
+    Ok(token_ids)
+}
+
+/// Normalized surface class of every token (see [`SurfaceClasses`]), in one
+/// pass over token_definitions like [`find_surface_token_ids`].
+pub fn load_surface_classes(db_path: &Path, config: NormalizationConfig) -> Result<Vec<u32>, DbError> {
+    let conn = Connection::open(db_path)?;
+    let mut stmt = conn.prepare("SELECT id, surface FROM token_definitions")?;
+    let mut rows = stmt.query([])?;
+
+    let mut classes = SurfaceClasses::new(config);
+    while let Some(row) = rows.next()? {
+        let surface: String = row.get(1)?;
+        classes.push(row.get(0)?, &surface);
     }
+    Ok(classes.finish())
+}
 
-    Ok((lemma_mapping, root_mapping, surface_mapping))
+/// Surface forms of the given tokens only, queried in batches.
+pub fn load_surfaces(db_path: &Path, token_ids: &BTreeSet<u32>) -> Result<HashMap<u32, String>, DbError> {
+    // Stay under SQLite's default limit on bound parameters
+    const BATCH: usize = 500;
+
+    let conn = Connection::open(db_path)?;
+    let ids: Vec<u32> = token_ids.iter().copied().collect();
+    let mut surfaces = HashMap::with_capacity(ids.len());
+    for batch in ids.chunks(BATCH) {
+        let placeholders = vec!["?"; batch.len()].join(",");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, surface FROM token_definitions WHERE id IN ({})",
+            placeholders
+        ))?;
+        let mut rows = stmt.query(rusqlite::params_from_iter(batch))?;
+        while let Some(row) = rows.next()? {
+            surfaces.insert(row.get(0)?, row.get(1)?);
+        }
+    }
+    Ok(surfaces)
 }
 
 /// Load full token stream for a book with pre-loaded root mapping.
@@ -85,8 +235,16 @@ pub fn load_book_token_stream_with_root(
     token_to_lemma: &[u32],
     token_to_root: &[u32],
 ) -> Result<BookTokenStream, DbError> {
-    let conn = Connection::open(db_path)?;
+    load_book_token_stream_with_conn(&Connection::open(db_path)?, book_id, token_to_lemma, token_to_root)
+}
 
+/// [`load_book_token_stream_with_root`] on an open connection.
+pub(crate) fn load_book_token_stream_with_conn(
+    conn: &Connection,
+    book_id: u32,
+    token_to_lemma: &[u32],
+    token_to_root: &[u32],
+) -> Result<BookTokenStream, DbError> {
     let mut stmt = conn.prepare(
         "SELECT part_index, page_id, token_ids
          FROM page_tokens
@@ -105,7 +263,7 @@ pub fn load_book_token_stream_with_root(
         let token_blob: Vec<u8> = row.get(2)?;
 
         // Validate blob size is multiple of 4
-        if token_blob.len() % 4 != 0 {
+        if !token_blob.len().is_multiple_of(4) {
             return Err(DbError::InvalidTokenBlob);
         }
 
@@ -147,6 +305,7 @@ pub fn load_book_token_stream_with_root(
             token_ids,
             lemma_ids,
             root_ids,
+            alt_lemma_ids: Vec::new(),
         });
     }
 
@@ -158,9 +317,65 @@ pub fn load_book_token_stream_with_root(
         book_id,
         total_tokens,
         pages,
+        corpus_weights: None,
+        quran: None,
     })
 }
 
+/// Count every lemma's occurrences over all pages of the corpus, indexed by
+/// lemma ID. Reads the whole page_tokens table one page at a time.
+pub fn count_corpus_lemmas(db_path: &Path, token_to_lemma: &[u32]) -> Result<Vec<u64>, DbError> {
+    count_corpus_lemmas_with_conn(&Connection::open(db_path)?, token_to_lemma)
+}
+
+/// [`count_corpus_lemmas`] on an open connection.
+pub(crate) fn count_corpus_lemmas_with_conn(conn: &Connection, token_to_lemma: &[u32]) -> Result<Vec<u64>, DbError> {
+    let max_lemma_id = token_to_lemma.iter().copied().max().unwrap_or(0) as usize;
+    let mut counts = vec![0u64; max_lemma_id + 1];
+
+    let mut stmt = conn.prepare("SELECT token_ids FROM page_tokens")?;
+    let mut rows = stmt.query([])?;
+
+    while let Some(row) = rows.next()? {
+        let token_blob: Vec<u8> = row.get(0)?;
+        if !token_blob.len().is_multiple_of(4) {
+            return Err(DbError::InvalidTokenBlob);
+        }
+        for chunk in token_blob.chunks_exact(4) {
+            let tid = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]) as usize;
+            let lemma = token_to_lemma.get(tid).copied().unwrap_or(0) as usize;
+            counts[lemma] += 1;
+        }
+    }
+
+    Ok(counts)
+}
+
+/// Book IDs of a named collection from the optional collections table, in
+/// ascending order. A collection with no books (or a database without the
+/// table) is an error, so a misspelt name doesn't silently compare nothing.
+pub fn load_collection(db_path: &Path, name: &str) -> Result<Vec<u32>, DbError> {
+    let conn = Connection::open(db_path)?;
+    let has_table: bool = conn.query_row(
+        "SELECT COUNT(*) > 0 FROM sqlite_master
+         WHERE type = 'table' AND name = 'collections'",
+        [],
+        |row| row.get(0),
+    )?;
+    if !has_table {
+        return Err(DbError::CollectionNotFound(name.to_string()));
+    }
+
+    let mut stmt = conn.prepare("SELECT DISTINCT book_id FROM collections WHERE name = ? ORDER BY book_id")?;
+    let book_ids = stmt
+        .query_map([name], |row| row.get(0))?
+        .collect::<Result<Vec<u32>, _>>()?;
+    if book_ids.is_empty() {
+        return Err(DbError::CollectionNotFound(name.to_string()));
+    }
+    Ok(book_ids)
+}
+
 /// Load lemma stream for a single book.
 /// Extracts all token IDs from page_tokens and maps them to lemma IDs.
 pub fn load_book_lemma_stream(
@@ -168,8 +383,15 @@ pub fn load_book_lemma_stream(
     book_id: u32,
     token_to_lemma: &[u32],
 ) -> Result<BookLemmaStream, DbError> {
-    let conn = Connection::open(db_path)?;
+    load_book_lemma_stream_with_conn(&Connection::open(db_path)?, book_id, token_to_lemma)
+}
 
+/// [`load_book_lemma_stream`] on an open connection.
+pub(crate) fn load_book_lemma_stream_with_conn(
+    conn: &Connection,
+    book_id: u32,
+    token_to_lemma: &[u32],
+) -> Result<BookLemmaStream, DbError> {
     let mut stmt = conn.prepare(
         "SELECT part_index, page_id, token_ids
          FROM page_tokens
@@ -188,7 +410,7 @@ pub fn load_book_lemma_stream(
         let token_blob: Vec<u8> = row.get(2)?;
 
         // Validate blob size is multiple of 4
-        if token_blob.len() % 4 != 0 {
+        if !token_blob.len().is_multiple_of(4) {
             return Err(DbError::InvalidTokenBlob);
         }
 
@@ -230,6 +452,18 @@ pub fn load_book_lemma_stream(
     })
 }
 
+/// IDs of every book in page_tokens, ascending.
+pub fn list_books(db_path: &Path) -> Result<Vec<u32>, DbError> {
+    list_books_with_conn(&Connection::open(db_path)?)
+}
+
+/// [`list_books`] on an open connection.
+pub(crate) fn list_books_with_conn(conn: &Connection) -> Result<Vec<u32>, DbError> {
+    let mut stmt = conn.prepare("SELECT DISTINCT book_id FROM page_tokens ORDER BY book_id")?;
+    let book_ids = stmt.query_map([], |row| row.get(0))?.collect::<Result<_>>()?;
+    Ok(book_ids)
+}
+
 /// Load corpus statistics
 pub fn load_corpus_stats(db_path: &Path) -> Result<CorpusStats, DbError> {
     let conn = Connection::open(db_path)?;
@@ -273,8 +507,11 @@ pub fn load_corpus_stats(db_path: &Path) -> Result<CorpusStats, DbError> {
 
 /// Load information about a specific book
 pub fn load_book_info(db_path: &Path, book_id: u32) -> Result<BookInfo, DbError> {
-    let conn = Connection::open(db_path)?;
+    load_book_info_with_conn(&Connection::open(db_path)?, book_id)
+}
 
+/// [`load_book_info`] on an open connection.
+pub(crate) fn load_book_info_with_conn(conn: &Connection, book_id: u32) -> Result<BookInfo, DbError> {
     // Get page count and total tokens
     let (page_count, total_tokens): (u64, u64) = conn.query_row(
         "SELECT COUNT(*), SUM(LENGTH(token_ids) / 4)
@@ -315,8 +552,8 @@ pub fn load_book_info(db_path: &Path, book_id: u32) -> Result<BookInfo, DbError>
     }
 
     // Count unique lemmas for this book
-    let token_to_lemma = load_token_to_lemma(db_path)?;
-    let stream = load_book_lemma_stream(db_path, book_id, &token_to_lemma)?;
+    let token_to_lemma = load_token_to_lemma_with_conn(conn)?;
+    let stream = load_book_lemma_stream_with_conn(conn, book_id, &token_to_lemma)?;
     let unique_lemmas = {
         let mut lemmas: Vec<u32> = stream.flat_lemmas();
         lemmas.sort_unstable();
@@ -335,8 +572,6 @@ pub fn load_book_info(db_path: &Path, book_id: u32) -> Result<BookInfo, DbError>
 
 #[cfg(test)]
 mod tests {
-    use super::*;
-
     #[test]
     fn test_token_blob_unpacking() {
         // Test that we correctly unpack little-endian u32 arrays
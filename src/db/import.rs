@@ -0,0 +1,575 @@
+//! Building a corpus.db from OpenITI mARkdown or plain text files.
+//!
+//! Each file becomes one book, numbered in the order given. In mARkdown, page markers
+//! (`PageV01P023`) close the page before them: its `part_index` and `page_id`
+//! are the volume and page numbers, and the `pages` table keeps the marker's
+//! digits as labels. Text after the last marker becomes the following page;
+//! a file without markers is a single page 1 of part 1. The metadata header,
+//! milestones (`ms012`) and `@` tags are dropped, and hemistich markers
+//! (`%~%`) are kept as tokens so verse is still recognized. Plain text files
+//! are cut into pages at form feeds only.
+//!
+//! Without a morphological analyzer, a token's lemma is its surface form with
+//! orthographic variants folded together, and no roots are recorded: root and
+//! combined modes fall back to lemma mode on such a database. A [`Lexicon`]
+//! decides the folding (its [`NormalizationConfig`], every step by default, as
+//! in [`CorpusBuilder`](crate::corpus::CorpusBuilder)), and the
+//! `surface,lemma,root` rows read into it supply lemmas and roots for the
+//! surface forms they list, matched after the same folding.
+
+use rusqlite::{Connection, Transaction};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::normalize::NormalizationConfig;
+use crate::tokenize::Tokenizer;
+
+/// Last line of the OpenITI metadata header.
+const HEADER_END: &str = "#META#Header#End#";
+/// OpenITI hemistich separator, kept as a token.
+const HEMISTICH: &str = "%~%";
+
+#[derive(Error, Debug)]
+pub enum ImportError {
+    #[error("{}: {source}", path.display())]
+    Io { path: PathBuf, source: std::io::Error },
+    #[error("SQLite error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+    #[error("dictionary CSV error: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("{} already exists; import into a new database", .0.display())]
+    Exists(PathBuf),
+    #[error("no input files given")]
+    NoInputs,
+}
+
+/// One page of a parsed text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextPage {
+    pub volume: u32,
+    pub page: u32,
+    /// Volume and page digits as written in the marker (empty without one)
+    pub volume_label: String,
+    pub page_label: String,
+    pub tokens: Vec<String>,
+}
+
+/// A book written to the database.
+#[derive(Debug, Clone)]
+pub struct ImportedBook {
+    pub book_id: u32,
+    /// File name of the text (for mARkdown, normally its OpenITI URI)
+    pub uri: String,
+    pub pages: usize,
+    pub tokens: usize,
+}
+
+/// What an import wrote.
+#[derive(Debug, Clone, Default)]
+pub struct ImportReport {
+    pub books: Vec<ImportedBook>,
+    pub token_definitions: usize,
+    pub lemmas: usize,
+    pub roots: usize,
+    /// Token definitions whose lemma came from the lexicon
+    pub lexicon_tokens: usize,
+}
+
+/// Lemma and root of surface forms, keyed by the surface as folded by the
+/// lexicon's normalization. An empty lexicon only folds.
+#[derive(Debug, Clone, Default)]
+pub struct Lexicon {
+    entries: HashMap<String, LexiconEntry>,
+    normalization: NormalizationConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LexiconEntry {
+    pub lemma: String,
+    pub root: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct LexiconRow {
+    surface: String,
+    lemma: String,
+    #[serde(default)]
+    root: Option<String>,
+}
+
+impl Lexicon {
+    /// An empty lexicon folding surface forms with `normalization`.
+    pub fn new(normalization: NormalizationConfig) -> Self {
+        Self {
+            entries: HashMap::new(),
+            normalization,
+        }
+    }
+
+    /// The entry for `surface`, after orthographic folding.
+    pub fn get(&self, surface: &str) -> Option<&LexiconEntry> {
+        self.entries.get(&self.normalization.normalize(surface))
+    }
+
+    /// Lemma of a surface form the lexicon does not list.
+    pub fn fold(&self, surface: &str) -> String {
+        self.normalization.normalize(surface)
+    }
+
+    pub fn normalization(&self) -> &NormalizationConfig {
+        &self.normalization
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+/// Read a dictionary CSV with a `surface,lemma,root` header; `root` may be
+/// empty or missing. When two rows fold to the same surface, the first wins.
+pub fn read_lexicon<R: Read>(reader: R, normalization: NormalizationConfig) -> Result<Lexicon, ImportError> {
+    let mut csv_reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader);
+    let mut lexicon = Lexicon::new(normalization);
+    for row in csv_reader.deserialize::<LexiconRow>() {
+        let row = row?;
+        if row.lemma.is_empty() {
+            continue;
+        }
+        lexicon.entries.entry(lexicon.fold(&row.surface)).or_insert(LexiconEntry {
+            lemma: row.lemma,
+            root: row.root.filter(|r| !r.is_empty()),
+        });
+    }
+    Ok(lexicon)
+}
+
+/// Read a dictionary CSV file.
+pub fn read_lexicon_file(path: &Path, normalization: NormalizationConfig) -> Result<Lexicon, ImportError> {
+    let file = std::fs::File::open(path).map_err(|source| ImportError::Io {
+        path: path.to_path_buf(),
+        source,
+    })?;
+    read_lexicon(std::io::BufReader::new(file), normalization)
+}
+
+/// Split a mARkdown text into pages of tokens, in page order. Pages with the
+/// same marker are joined.
+pub fn parse_openiti(text: &str, tokenizer: &dyn Tokenizer) -> Vec<TextPage> {
+    let body = match text.find(HEADER_END) {
+        Some(pos) => &text[pos + HEADER_END.len()..],
+        None => text.strip_prefix("######OpenITI#").unwrap_or(text),
+    };
+
+    let mut pages: BTreeMap<(u32, u32), TextPage> = BTreeMap::new();
+    let mut tokens = Vec::new();
+    let mut chunk = String::new();
+    let mut last = None;
+    for word in body.split_whitespace() {
+        if let Some((volume, page)) = page_marker(word) {
+            flush(&mut chunk, &mut tokens, tokenizer);
+            let key = (volume.parse().unwrap_or(0), page.parse().unwrap_or(0));
+            pages
+                .entry(key)
+                .or_insert_with(|| TextPage {
+                    volume: key.0,
+                    page: key.1,
+                    volume_label: volume.to_string(),
+                    page_label: page.to_string(),
+                    tokens: Vec::new(),
+                })
+                .tokens
+                .append(&mut tokens);
+            last = Some(key);
+        } else if word == HEMISTICH {
+            flush(&mut chunk, &mut tokens, tokenizer);
+            tokens.push(HEMISTICH.to_string());
+        } else if !is_markup(word) {
+            chunk.push_str(word);
+            chunk.push(' ');
+        }
+    }
+    flush(&mut chunk, &mut tokens, tokenizer);
+
+    if !tokens.is_empty() {
+        let (volume, page) = last.map_or((1, 1), |(volume, page)| (volume, page + 1));
+        pages.insert(
+            (volume, page),
+            TextPage {
+                volume,
+                page,
+                volume_label: String::new(),
+                page_label: String::new(),
+                tokens,
+            },
+        );
+    }
+    pages.into_values().collect()
+}
+
+/// Split a plain text into pages at form feeds, numbered from 1 in part 1.
+/// Pages without tokens are skipped.
+pub fn parse_plain_text(text: &str, tokenizer: &dyn Tokenizer) -> Vec<TextPage> {
+    text.split('\u{c}')
+        .map(|page| tokenizer.tokenize(page))
+        .filter(|tokens| !tokens.is_empty())
+        .zip(1..)
+        .map(|(tokens, page)| TextPage {
+            volume: 1,
+            page,
+            volume_label: String::new(),
+            page_label: String::new(),
+            tokens,
+        })
+        .collect()
+}
+
+/// Tokenize the text gathered since the last marker.
+fn flush(chunk: &mut String, tokens: &mut Vec<String>, tokenizer: &dyn Tokenizer) {
+    if !chunk.is_empty() {
+        tokens.extend(tokenizer.tokenize(chunk));
+        chunk.clear();
+    }
+}
+
+/// The volume and page digits of a `PageV01P023` marker.
+fn page_marker(word: &str) -> Option<(&str, &str)> {
+    let (volume, page) = word.strip_prefix("PageV")?.split_once('P')?;
+    let digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+    (digits(volume) && digits(page)).then_some((volume, page))
+}
+
+/// Milestones (`ms012`) and `@` tags.
+fn is_markup(word: &str) -> bool {
+    word.starts_with('@')
+        || word
+            .strip_prefix("ms")
+            .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+}
+
+/// Import mARkdown files into a new corpus.db at `db_path`, numbering the
+/// books from `first_book_id` in the order given. Surface forms are folded
+/// and looked up in `lexicon`. A failed import removes the database again.
+pub fn import_openiti(
+    files: &[PathBuf],
+    lexicon: &Lexicon,
+    db_path: &Path,
+    first_book_id: u32,
+    tokenizer: &dyn Tokenizer,
+    show_progress: bool,
+) -> Result<ImportReport, ImportError> {
+    if files.is_empty() {
+        return Err(ImportError::NoInputs);
+    }
+    new_database(db_path, |db_path| {
+        write_books(files, db_path, first_book_id, lexicon, show_progress, |text| {
+            parse_openiti(text, tokenizer)
+        })
+    })
+}
+
+/// Import every `.txt` file of `dir`, in file name order, into a new
+/// corpus.db at `db_path`, numbering the books from `first_book_id`. Surface
+/// forms are folded and looked up in `lexicon`. A failed import removes the
+/// database again.
+pub fn import_text(
+    dir: &Path,
+    lexicon: &Lexicon,
+    db_path: &Path,
+    first_book_id: u32,
+    tokenizer: &dyn Tokenizer,
+    show_progress: bool,
+) -> Result<ImportReport, ImportError> {
+    let io_error = |source| ImportError::Io {
+        path: dir.to_path_buf(),
+        source,
+    };
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(dir).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext == "txt") {
+            files.push(path);
+        }
+    }
+    if files.is_empty() {
+        return Err(ImportError::NoInputs);
+    }
+    files.sort();
+    new_database(db_path, |db_path| {
+        write_books(&files, db_path, first_book_id, lexicon, show_progress, |text| {
+            parse_plain_text(text, tokenizer)
+        })
+    })
+}
+
+/// Run `write` on a database that must not exist yet, removing it on failure.
+fn new_database(
+    db_path: &Path,
+    write: impl FnOnce(&Path) -> Result<ImportReport, ImportError>,
+) -> Result<ImportReport, ImportError> {
+    if db_path.exists() {
+        return Err(ImportError::Exists(db_path.to_path_buf()));
+    }
+    let report = write(db_path);
+    if report.is_err() {
+        let _ = std::fs::remove_file(db_path);
+    }
+    report
+}
+
+fn write_books(
+    files: &[PathBuf],
+    db_path: &Path,
+    first_book_id: u32,
+    lexicon: &Lexicon,
+    show_progress: bool,
+    parse: impl Fn(&str) -> Vec<TextPage>,
+) -> Result<ImportReport, ImportError> {
+    let mut conn = Connection::open(db_path)?;
+    create_schema(&conn)?;
+    let mut writer = CorpusWriter::new(conn.transaction()?);
+    writer.lexicon = Some(lexicon);
+    let mut books = Vec::with_capacity(files.len());
+    for (book_id, path) in (first_book_id..).zip(files) {
+        let text = std::fs::read_to_string(path).map_err(|source| ImportError::Io {
+            path: path.clone(),
+            source,
+        })?;
+        let uri = path.file_name().unwrap_or(path.as_os_str()).to_string_lossy().to_string();
+        let pages = parse(&text);
+        let mut tokens = 0;
+        for page in &pages {
+            writer.add_page(book_id, page.volume, page.page, &page.tokens)?;
+            writer.tx.execute(
+                "INSERT INTO pages (book_id, part_index, page_id, part_label, page_number) VALUES (?, ?, ?, ?, ?)",
+                rusqlite::params![
+                    book_id,
+                    page.volume,
+                    page.page,
+                    Some(&page.volume_label).filter(|l| !l.is_empty()),
+                    Some(&page.page_label).filter(|l| !l.is_empty()),
+                ],
+            )?;
+            tokens += page.tokens.len();
+        }
+        writer
+            .tx
+            .execute("INSERT INTO books (book_id, uri) VALUES (?, ?)", rusqlite::params![book_id, uri])?;
+        if show_progress {
+            eprintln!("Book {}: {} ({} pages, {} tokens)", book_id, uri, pages.len(), tokens);
+        }
+        books.push(ImportedBook {
+            book_id,
+            uri,
+            pages: pages.len(),
+            tokens,
+        });
+    }
+
+    let report = ImportReport {
+        books,
+        token_definitions: writer.tokens.len(),
+        lemmas: writer.lemmas.len(),
+        roots: writer.roots.len(),
+        lexicon_tokens: writer.lexicon_tokens,
+    };
+    writer.commit()?;
+    Ok(report)
+}
+
+/// Create the corpus.db tables: token and lemma definitions, page token
+/// blobs, page labels, the (empty) roots table and the source of each book.
+pub(crate) fn create_schema(conn: &Connection) -> Result<(), rusqlite::Error> {
+    conn.execute_batch(
+        "CREATE TABLE token_definitions (
+             id INTEGER PRIMARY KEY, surface TEXT NOT NULL, lemma_id INTEGER NOT NULL, root_id INTEGER
+         );
+         CREATE TABLE lemmas (id INTEGER PRIMARY KEY, lemma TEXT UNIQUE NOT NULL);
+         CREATE TABLE roots (id INTEGER PRIMARY KEY, root TEXT UNIQUE NOT NULL);
+         CREATE TABLE page_tokens (
+             book_id INTEGER NOT NULL, part_index INTEGER NOT NULL, page_id INTEGER NOT NULL,
+             token_ids BLOB NOT NULL, PRIMARY KEY (book_id, part_index, page_id)
+         );
+         CREATE TABLE pages (
+             book_id INTEGER NOT NULL, part_index INTEGER NOT NULL, page_id INTEGER NOT NULL,
+             part_label TEXT, page_number TEXT, PRIMARY KEY (book_id, part_index, page_id)
+         );
+         CREATE TABLE books (book_id INTEGER PRIMARY KEY, uri TEXT NOT NULL);",
+    )
+}
+
+/// Writes pages of surface tokens, defining each new surface form as a token
+/// whose lemma and root come from the lexicon, or whose lemma is its folded
+/// form (every normalization step without a lexicon).
+pub(crate) struct CorpusWriter<'c, 'd> {
+    pub(crate) tx: Transaction<'c>,
+    pub(crate) lexicon: Option<&'d Lexicon>,
+    tokens: HashMap<String, u32>,
+    lemmas: HashMap<String, u32>,
+    roots: HashMap<String, u32>,
+    lexicon_tokens: usize,
+}
+
+impl<'c> CorpusWriter<'c, '_> {
+    pub(crate) fn new(tx: Transaction<'c>) -> Self {
+        Self {
+            tx,
+            lexicon: None,
+            tokens: HashMap::new(),
+            lemmas: HashMap::new(),
+            roots: HashMap::new(),
+            lexicon_tokens: 0,
+        }
+    }
+
+    pub(crate) fn commit(self) -> Result<(), rusqlite::Error> {
+        self.tx.commit()
+    }
+
+    pub(crate) fn add_page(
+        &mut self,
+        book_id: u32,
+        part_index: u32,
+        page_id: u32,
+        surfaces: &[String],
+    ) -> Result<(), rusqlite::Error> {
+        let mut blob = Vec::with_capacity(surfaces.len() * 4);
+        for surface in surfaces {
+            let token_id = match self.tokens.get(surface) {
+                Some(&token_id) => token_id,
+                None => self.define_token(surface)?,
+            };
+            blob.extend_from_slice(&token_id.to_le_bytes());
+        }
+        self.tx.execute(
+            "INSERT INTO page_tokens (book_id, part_index, page_id, token_ids) VALUES (?, ?, ?, ?)",
+            rusqlite::params![book_id, part_index, page_id, blob],
+        )?;
+        Ok(())
+    }
+
+    fn define_token(&mut self, surface: &str) -> Result<u32, rusqlite::Error> {
+        // IDs start at 1: lemma 0 is reserved for unknown lemmas
+        let token_id = self.tokens.len() as u32 + 1;
+        let entry = self.lexicon.and_then(|lexicon| lexicon.get(surface));
+        let (lemma, root) = match entry {
+            Some(entry) => {
+                self.lexicon_tokens += 1;
+                (entry.lemma.clone(), entry.root.as_deref())
+            }
+            None => {
+                let lemma = match self.lexicon {
+                    Some(lexicon) => lexicon.fold(surface),
+                    None => NormalizationConfig::default().normalize(surface),
+                };
+                (lemma, None)
+            }
+        };
+        let lemma_id = intern(&self.tx, &mut self.lemmas, "lemmas", "lemma", &lemma)?;
+        let root_id = match root {
+            Some(root) => Some(intern(&self.tx, &mut self.roots, "roots", "root", root)?),
+            None => None,
+        };
+        self.tx.execute(
+            "INSERT INTO token_definitions (id, surface, lemma_id, root_id) VALUES (?, ?, ?, ?)",
+            rusqlite::params![token_id, surface, lemma_id, root_id],
+        )?;
+        self.tokens.insert(surface.to_string(), token_id);
+        Ok(token_id)
+    }
+}
+
+/// ID of `value` in a lemmas or roots table, inserting it when new.
+fn intern(
+    tx: &Transaction,
+    ids: &mut HashMap<String, u32>,
+    table: &str,
+    column: &str,
+    value: &str,
+) -> Result<u32, rusqlite::Error> {
+    if let Some(&id) = ids.get(value) {
+        return Ok(id);
+    }
+    let id = ids.len() as u32 + 1;
+    tx.execute(
+        &format!("INSERT INTO {} (id, {}) VALUES (?, ?)", table, column),
+        rusqlite::params![id, value],
+    )?;
+    ids.insert(value.to_string(), id);
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tokenize::SimpleTokenizer;
+
+    #[test]
+    fn test_parse_openiti() {
+        let text = "######OpenITI#\n\
+            #META# 000.SortField :: Shamela_0001\n\
+            #META#Header#End#\n\
+            \n\
+            ### | باب العلم\n\
+            # قال الشيخ، رحمه الله: ms001 العلم نور PageV01P001\n\
+            ~~ والجهل ظلام @QB@ وفيه %~% شعر PageV01P002\n\
+            # خاتمة";
+        let pages = parse_openiti(text, &SimpleTokenizer);
+        assert_eq!(pages.len(), 3);
+        assert_eq!((pages[0].volume, pages[0].page, pages[0].page_label.as_str()), (1, 1, "001"));
+        assert_eq!(pages[0].tokens, vec!["باب", "العلم", "قال", "الشيخ", "رحمه", "الله", "العلم", "نور"]);
+        assert_eq!(pages[1].tokens, vec!["والجهل", "ظلام", "وفيه", "%~%", "شعر"]);
+        assert_eq!((pages[2].volume, pages[2].page, pages[2].page_label.as_str()), (1, 3, ""));
+        assert_eq!(pages[2].tokens, vec!["خاتمة"]);
+
+        let plain = parse_openiti("نص بلا صفحات", &SimpleTokenizer);
+        assert_eq!((plain.len(), plain[0].volume, plain[0].page), (1, 1, 1));
+    }
+
+    #[test]
+    fn test_plain_text_with_dictionary() {
+        let pages = parse_plain_text("الصفحة الأولى\u{c}\n\u{c}الثانية", &SimpleTokenizer);
+        assert_eq!(pages.iter().map(|p| p.page).collect::<Vec<_>>(), vec![1, 2]);
+        assert_eq!(pages[1].tokens, vec!["الثانية"]);
+
+        let csv = "surface,lemma,root\nكتب,كتب,ك ت ب\nالكتاب,كتاب,ك ت ب\nإلى,إلى,\nالى,خطأ,\n";
+        let lexicon = read_lexicon(csv.as_bytes(), NormalizationConfig::default()).unwrap();
+        assert_eq!(lexicon.len(), 3);
+        // Looked up after folding; the first of two folded duplicates wins
+        assert_eq!(lexicon.get("الى").unwrap().lemma, "إلى");
+        assert_eq!(lexicon.get("الكتابُ").unwrap().root.as_deref(), Some("ك ت ب"));
+        assert!(lexicon.get("إلى").unwrap().root.is_none());
+
+        let keep_hamza = NormalizationConfig {
+            normalize_hamza: false,
+            ..Default::default()
+        };
+        let strict = read_lexicon(csv.as_bytes(), keep_hamza).unwrap();
+        assert_eq!((strict.len(), strict.get("الى").unwrap().lemma.as_str()), (4, "خطأ"));
+        assert_eq!(strict.fold("إلى"), "إلي");
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        create_schema(&conn).unwrap();
+        let mut writer = CorpusWriter::new(conn.transaction().unwrap());
+        writer.lexicon = Some(&lexicon);
+        let surfaces = ["كتب", "الكتاب", "إلى", "زيد"].map(String::from);
+        writer.add_page(1, 1, 1, &surfaces).unwrap();
+        assert_eq!((writer.lemmas.len(), writer.roots.len(), writer.lexicon_tokens), (4, 1, 3));
+        writer.commit().unwrap();
+
+        let roots: Vec<Option<u32>> = conn
+            .prepare("SELECT root_id FROM token_definitions ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(roots, vec![Some(1), Some(1), None, None]);
+    }
+}
@@ -2,9 +2,21 @@
 //!
 //! This module implements efficient filtering to reduce the number of
 //! window pairs that need full Smith-Waterman alignment.
+//!
+//! Candidate finding is pluggable through [`CandidateFinder`]: exact shared
+//! shingles (the default), winnowed fingerprints, MinHash with
+//! locality-sensitive hashing, and brute force are implementations, selected by
+//! `params.candidate_method` and `params.brute_force`.
 
-use crate::models::{ComparisonParams, Window};
+use crate::models::{CandidateMethod, ComparisonParams, MatchMode, ShingleKind, Window};
+use crate::rng::SplitMix64;
+use roaring::RoaringBitmap;
+use std::borrow::Cow;
 use std::collections::{HashMap, HashSet};
+use std::hash::{BuildHasherDefault, Hasher};
+
+/// Tag bit for lemma IDs standing in for missing roots in root-level shingles.
+const ROOTLESS_TAG: u32 = 1 << 31;
 
 /// Generate n-gram shingles from a lemma sequence.
 ///
@@ -18,10 +30,527 @@ pub fn generate_shingles(lemma_ids: &[u32], n: usize) -> HashSet<Vec<u32>> {
     lemma_ids.windows(n).map(|w| w.to_vec()).collect()
 }
 
-/// Find candidate window pairs that share enough shingles.
+/// Per-token IDs that shingles are built from under the given filter mode.
+///
+/// In root mode, tokens without a root (root 0, or windows built from
+/// lemma-only streams) fall back to their lemma, tagged so the lemma and root
+/// ID spaces never collide. Combined mode uses root keys as well: any shingle
+/// shared at the lemma level is also shared at the root level. Surface mode
+/// uses the window's token IDs, or its lemmas when it kept none. Tokens whose
+/// lemma is in `stop_lemmas` are dropped before n-grams are formed, so
+/// shingles span across them.
+fn shingle_keys<'a>(
+    window: &'a Window,
+    mode: MatchMode,
+    stop_lemmas: Option<&HashSet<u32>>,
+) -> Cow<'a, [u32]> {
+    let stop_lemmas = stop_lemmas.filter(|stop| !stop.is_empty());
+    let has_tokens = window.token_ids.len() == window.lemma_ids.len();
+    if stop_lemmas.is_none() {
+        match mode {
+            MatchMode::Lemma => return Cow::Borrowed(&window.lemma_ids),
+            MatchMode::Surface if has_tokens => return Cow::Borrowed(&window.token_ids),
+            _ => {}
+        }
+    }
+
+    Cow::Owned(
+        window
+            .lemma_ids
+            .iter()
+            .enumerate()
+            .filter(|(_, lemma)| !stop_lemmas.is_some_and(|stop| stop.contains(lemma)))
+            .map(|(i, &lemma)| match mode {
+                MatchMode::Lemma => lemma,
+                MatchMode::Surface if has_tokens => window.token_ids[i],
+                MatchMode::Surface => lemma,
+                MatchMode::Root | MatchMode::Combined => match window.root_ids.get(i) {
+                    Some(&root) if root != 0 => root,
+                    _ => lemma | ROOTLESS_TAG,
+                },
+            })
+            .collect(),
+    )
+}
+
+/// 64-bit hash identifying a shingle.
+///
+/// Keys are folded FxHash-style (rotate, xor, multiply), far cheaper than
+/// SipHash on a handful of integers, and the result goes through the
+/// SplitMix64 finalizer so every output bit depends on every key. Hashes are
+/// the same on every platform and Rust version. Distinct shingles collide
+/// with probability about 2^-64; `ComparisonParams::exact_shingles` rules
+/// collisions out altogether.
+pub fn shingle_hash(shingle: &[u32]) -> u64 {
+    const SEED: u64 = 0x517c_c1b7_2722_0a95;
+    let folded = shingle
+        .iter()
+        .fold(shingle.len() as u64, |h, &key| (h.rotate_left(5) ^ key as u64).wrapping_mul(SEED));
+    SplitMix64::new(folded).next_u64()
+}
+
+/// Hasher for keys that are already [`shingle_hash`]es: the key is the hash.
+#[derive(Debug, Default)]
+struct ShingleHasher(u64);
+
+impl Hasher for ShingleHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = self.0.rotate_left(8) ^ byte as u64;
+        }
+    }
+
+    fn write_u64(&mut self, hash: u64) {
+        self.0 = hash;
+    }
+}
+
+/// Sorted, distinct shingle hashes of a window under the given filter mode.
+///
+/// Window generation stores the result in `Window::shingle_hashes` so that
+/// later stages don't regenerate shingles.
+pub fn compute_shingle_hashes(
+    window: &Window,
+    ngram_size: usize,
+    kind: ShingleKind,
+    mode: MatchMode,
+    stop_lemmas: Option<&HashSet<u32>>,
+) -> Vec<u64> {
+    sequence_shingle_hashes(&shingle_keys(window, mode, stop_lemmas), ngram_size, kind)
+}
+
+/// Call `f` with every shingle of a key sequence, in order, along with the
+/// start of the n-gram it came from and the offset of the token left out of
+/// it (None for contiguous shingles).
+///
+/// Gapped shingles drop each inner token of every n-gram in turn, giving
+/// `ngram_size - 2` shingles of `ngram_size - 1` keys per n-gram; the first and
+/// last keys are always kept, so neighbouring n-grams don't repeat each other.
+/// Below an n-gram size of 3 there is no inner token and shingles are
+/// contiguous.
+fn for_each_shingle(keys: &[u32], ngram_size: usize, kind: ShingleKind, mut f: impl FnMut(&[u32], usize, Option<usize>)) {
+    if ngram_size == 0 || keys.len() < ngram_size {
+        return;
+    }
+    match kind {
+        ShingleKind::Gapped if ngram_size >= 3 => {
+            let mut shingle = Vec::with_capacity(ngram_size - 1);
+            for (start, ngram) in keys.windows(ngram_size).enumerate() {
+                for skip in 1..ngram_size - 1 {
+                    shingle.clear();
+                    shingle.extend_from_slice(&ngram[..skip]);
+                    shingle.extend_from_slice(&ngram[skip + 1..]);
+                    f(&shingle, start, Some(skip));
+                }
+            }
+        }
+        _ => {
+            for (start, ngram) in keys.windows(ngram_size).enumerate() {
+                f(ngram, start, None);
+            }
+        }
+    }
+}
+
+/// Shingle hashes of a key sequence, in shingle order.
+fn ordered_shingle_hashes(keys: &[u32], ngram_size: usize, kind: ShingleKind) -> Vec<u64> {
+    let mut hashes = Vec::new();
+    for_each_shingle(keys, ngram_size, kind, |shingle, _, _| hashes.push(shingle_hash(shingle)));
+    hashes
+}
+
+/// Sorted, distinct hashes of the shingles of a key sequence.
+fn sequence_shingle_hashes(keys: &[u32], ngram_size: usize, kind: ShingleKind) -> Vec<u64> {
+    let mut hashes = ordered_shingle_hashes(keys, ngram_size, kind);
+    hashes.sort_unstable();
+    hashes.dedup();
+    hashes
+}
+
+/// Lemma-shingle Jaccard between two aligned spans.
+///
+/// Unlike the alignment metrics this ignores how gaps were placed, so it stays
+/// meaningful when the DP produces odd gap patterns. Spans shorter than
+/// `ngram_size` are shingled at the shorter span's length.
+pub fn span_shingle_jaccard(span_a: &[u32], span_b: &[u32], ngram_size: usize) -> f32 {
+    let n = ngram_size.min(span_a.len()).min(span_b.len());
+    if n == 0 {
+        return 0.0;
+    }
+    shingle_jaccard(
+        &sequence_shingle_hashes(span_a, n, ShingleKind::Contiguous),
+        &sequence_shingle_hashes(span_b, n, ShingleKind::Contiguous),
+    )
+}
+
+/// A window's shingle hashes: the precomputed set if present, else computed now.
+fn window_shingle_hashes<'a>(window: &'a Window, params: &ComparisonParams) -> Cow<'a, [u64]> {
+    if window.shingle_hashes.is_empty() {
+        Cow::Owned(compute_shingle_hashes(
+            window,
+            params.ngram_size,
+            params.shingle_kind,
+            params.filter_mode,
+            params.stop_lemmas.as_ref(),
+        ))
+    } else {
+        Cow::Borrowed(&window.shingle_hashes)
+    }
+}
+
+/// Number of elements two sorted, distinct sets have in common.
+fn sorted_intersection_len<T: Ord>(a: &[T], b: &[T]) -> usize {
+    let (mut i, mut j, mut shared) = (0, 0, 0usize);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                shared += 1;
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    shared
+}
+
+/// Jaccard similarity of two sorted, distinct hash sets.
+pub fn shingle_jaccard(a: &[u64], b: &[u64]) -> f32 {
+    let shared = sorted_intersection_len(a, b);
+    let union = a.len() + b.len() - shared;
+    if union == 0 {
+        0.0
+    } else {
+        shared as f32 / union as f32
+    }
+}
+
+/// A window's distinct shingles as key sequences rather than hashes, sorted.
+fn exact_shingles(window: &Window, params: &ComparisonParams) -> Vec<Vec<u32>> {
+    let keys = shingle_keys(window, params.filter_mode, params.stop_lemmas.as_ref());
+    let mut shingles = Vec::new();
+    for_each_shingle(&keys, params.ngram_size, params.shingle_kind, |shingle, _, _| {
+        shingles.push(shingle.to_vec())
+    });
+    shingles.sort_unstable();
+    shingles.dedup();
+    shingles
+}
+
+/// With `params.exact_shingles`, recount the shingles each candidate's windows
+/// share by comparing the shingles themselves, and drop pairs below
+/// `min_shared_shingles`: pairs that only got there through a hash collision.
+/// Only the shingle method counts shared shingles, so other methods are left
+/// alone.
+fn retain_exact_shared(
+    candidates: &mut Vec<(usize, usize)>,
+    windows_a: &[Window],
+    windows_b: &[Window],
+    params: &ComparisonParams,
+) {
+    if !params.exact_shingles || params.candidate_method != CandidateMethod::Shingles {
+        return;
+    }
+    let min_shared = params.min_shared_shingles.max(1);
+    let mut shingles_a = HashMap::new();
+    let mut shingles_b = HashMap::new();
+    candidates.retain(|&(a, b)| {
+        let set_a = shingles_a.entry(a).or_insert_with(|| exact_shingles(&windows_a[a], params));
+        let set_b = shingles_b.entry(b).or_insert_with(|| exact_shingles(&windows_b[b], params));
+        sorted_intersection_len(set_a, set_b) >= min_shared
+    });
+}
+
+/// Drop candidates whose shingle-set Jaccard is below `params.min_shingle_jaccard`.
+fn retain_by_jaccard(
+    candidates: &mut Vec<(usize, usize)>,
+    windows_a: &[Window],
+    windows_b: &[Window],
+    params: &ComparisonParams,
+) {
+    let Some(min_jaccard) = params.min_shingle_jaccard else {
+        return;
+    };
+    candidates.retain(|&(a, b)| {
+        let hashes_a = window_shingle_hashes(&windows_a[a], params);
+        let hashes_b = window_shingle_hashes(&windows_b[b], params);
+        shingle_jaccard(&hashes_a, &hashes_b) >= min_jaccard
+    });
+}
+
+/// Candidate pairs cut by `params.max_candidates_per_window`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CandidateCap {
+    /// Pairs dropped
+    pub dropped_pairs: usize,
+    /// Windows, of either book, that lost at least one pair
+    pub capped_windows: usize,
+}
+
+/// Shingles two windows share, counted on their hashes.
+fn shared_shingle_count(window_a: &Window, window_b: &Window, params: &ComparisonParams) -> u64 {
+    sorted_intersection_len(&window_shingle_hashes(window_a, params), &window_shingle_hashes(window_b, params)) as u64
+}
+
+/// Apply `params.max_candidates_per_window` to `(idx_a, idx_b, shared_shingles)`
+/// candidates.
 ///
-/// This function builds an inverted index of shingles from windows_b,
-/// then queries it with shingles from windows_a to find potential matches.
+/// Pairs are taken densest first (most shared shingles, ties in window order)
+/// and a pair is kept while both of its windows are under the cap, so a
+/// formulaic window matching thousands of others keeps only its best matches.
+/// With a cap the candidates come back in that order, so the densest pairs
+/// are aligned first; without one they are returned untouched.
+fn cap_candidates(
+    candidates: &mut Vec<(usize, usize, u64)>,
+    params: &ComparisonParams,
+) -> CandidateCap {
+    let Some(cap) = params.max_candidates_per_window else {
+        return CandidateCap::default();
+    };
+    candidates.sort_unstable_by(|a, b| b.2.cmp(&a.2).then((a.0, a.1).cmp(&(b.0, b.1))));
+
+    let mut per_window_a: HashMap<usize, usize> = HashMap::new();
+    let mut per_window_b: HashMap<usize, usize> = HashMap::new();
+    let mut capped_a = HashSet::new();
+    let mut capped_b = HashSet::new();
+    let before = candidates.len();
+    candidates.retain(|&(idx_a, idx_b, _)| {
+        let count_a = per_window_a.entry(idx_a).or_default();
+        let count_b = per_window_b.entry(idx_b).or_default();
+        if *count_a >= cap || *count_b >= cap {
+            if *count_a >= cap {
+                capped_a.insert(idx_a);
+            }
+            if *count_b >= cap {
+                capped_b.insert(idx_b);
+            }
+            return false;
+        }
+        *count_a += 1;
+        *count_b += 1;
+        true
+    });
+    CandidateCap {
+        dropped_pairs: before - candidates.len(),
+        capped_windows: capped_a.len() + capped_b.len(),
+    }
+}
+
+/// Apply `params.max_candidates_per_window` to plain candidate pairs,
+/// counting their shared shingles first (see [`cap_candidates`]).
+fn cap_candidate_pairs(
+    candidates: &mut Vec<(usize, usize)>,
+    windows_a: &[Window],
+    windows_b: &[Window],
+    params: &ComparisonParams,
+) -> CandidateCap {
+    if params.max_candidates_per_window.is_none() {
+        return CandidateCap::default();
+    }
+    let mut counted: Vec<(usize, usize, u64)> = candidates
+        .iter()
+        .map(|&(a, b)| (a, b, shared_shingle_count(&windows_a[a], &windows_b[b], params)))
+        .collect();
+    let cap = cap_candidates(&mut counted, params);
+    *candidates = counted.into_iter().map(|(a, b, _)| (a, b)).collect();
+    cap
+}
+
+/// A window's shingles under `params.filter_mode`, each weighted by the mean
+/// IDF weight of the lemmas it is made of, so formulaic shingles made of
+/// common lemmas count for little.
+///
+/// `lemma_weights` is indexed by lemma ID. Lemmas past its end (all of them,
+/// when it is empty) weigh 1.0, which makes the measures of
+/// [`weighted_shingle_overlap`] plain Jaccard and containment.
+pub fn weighted_shingles(window: &Window, params: &ComparisonParams, lemma_weights: &[f32]) -> HashMap<u64, f32> {
+    let n = params.ngram_size;
+    let stop_lemmas = params.stop_lemmas.as_ref().filter(|stop| !stop.is_empty());
+    let keys = shingle_keys(window, params.filter_mode, stop_lemmas);
+    // Same tokens as the keys: stop lemmas dropped
+    let weights: Vec<f32> = window
+        .lemma_ids
+        .iter()
+        .filter(|lemma| !stop_lemmas.is_some_and(|stop| stop.contains(lemma)))
+        .map(|&lemma| lemma_weights.get(lemma as usize).copied().unwrap_or(1.0))
+        .collect();
+
+    let mut shingles: HashMap<u64, f32> = HashMap::new();
+    for_each_shingle(&keys, n, params.shingle_kind, |shingle, start, skip| {
+        let ngram = &weights[start..start + n];
+        let weight = (ngram.iter().sum::<f32>() - skip.map_or(0.0, |i| ngram[i])) / shingle.len() as f32;
+        let entry = shingles.entry(shingle_hash(shingle)).or_default();
+        *entry = entry.max(weight);
+    });
+    shingles
+}
+
+/// IDF-weighted overlap of two windows' shingle sets.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct WeightedOverlap {
+    /// Shared weight over the weight of the union
+    pub jaccard: f32,
+    /// Shared weight over the lighter window's total weight: 1.0 when one
+    /// window's shingles all occur in the other
+    pub containment: f32,
+}
+
+/// Weighted Jaccard and containment of two [`weighted_shingles`] sets. A
+/// shared shingle counts with the smaller of its two weights.
+pub fn weighted_shingle_overlap(a: &HashMap<u64, f32>, b: &HashMap<u64, f32>) -> WeightedOverlap {
+    let shared: f32 = a
+        .iter()
+        .filter_map(|(hash, &w_a)| b.get(hash).map(|&w_b| w_a.min(w_b)))
+        .sum();
+    let total_a: f32 = a.values().sum();
+    let total_b: f32 = b.values().sum();
+    let union = total_a + total_b - shared;
+    let lighter = total_a.min(total_b);
+    WeightedOverlap {
+        jaccard: if union > 0.0 { shared / union } else { 0.0 },
+        containment: if lighter > 0.0 { shared / lighter } else { 0.0 },
+    }
+}
+
+/// Global shingle dictionary mapping shingle hashes to dense `u32` IDs.
+///
+/// Interning turns each window's shingle set into a compact roaring bitmap of
+/// integers, and the inverted index into one bitmap posting list per ID. A single
+/// dictionary can be shared across every book in a corpus run so that IDs stay
+/// comparable between books and each book's shingles are computed only once.
+///
+/// Shingles are keyed by a 64-bit hash; a collision would at worst add a
+/// spurious candidate pair, which alignment then rejects (or
+/// `ComparisonParams::exact_shingles` drops beforehand).
+#[derive(Debug, Default)]
+pub struct ShingleDictionary {
+    ids: HashMap<u64, u32, BuildHasherDefault<ShingleHasher>>,
+}
+
+impl ShingleDictionary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of distinct shingles interned so far.
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    /// ID for a shingle, assigning the next free ID if it is new.
+    pub fn intern(&mut self, shingle: &[u32]) -> u32 {
+        self.intern_hash(shingle_hash(shingle))
+    }
+
+    /// ID for an already-hashed shingle (see [`shingle_hash`]).
+    pub fn intern_hash(&mut self, hash: u64) -> u32 {
+        let next = self.ids.len() as u32;
+        *self.ids.entry(hash).or_insert(next)
+    }
+
+    /// Set of shingle IDs of one window, reusing its precomputed hashes.
+    pub fn window_shingle_ids(&mut self, window: &Window, params: &ComparisonParams) -> RoaringBitmap {
+        window_shingle_hashes(window, params)
+            .iter()
+            .map(|&h| self.intern_hash(h))
+            .collect()
+    }
+
+    /// Interned shingle sets for every window, in window order.
+    pub fn intern_windows(&mut self, windows: &[Window], params: &ComparisonParams) -> Vec<RoaringBitmap> {
+        windows
+            .iter()
+            .map(|w| self.window_shingle_ids(w, params))
+            .collect()
+    }
+}
+
+/// Interned shingle sets per book, sharing one [`ShingleDictionary`] across a
+/// corpus run. Each book is shingled once, however many pairs it appears in.
+///
+/// The cached sets depend on the windowing and filter parameters, so one
+/// instance must only be used with a single `ComparisonParams`.
+#[derive(Debug, Default)]
+pub struct CorpusShingles {
+    dictionary: ShingleDictionary,
+    books: HashMap<u32, Vec<RoaringBitmap>>,
+}
+
+impl CorpusShingles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn dictionary(&self) -> &ShingleDictionary {
+        &self.dictionary
+    }
+
+    /// Candidate pairs between two books' windows, interning each book on first use.
+    pub fn candidate_pairs(
+        &mut self,
+        book_a: u32,
+        windows_a: &[Window],
+        book_b: u32,
+        windows_b: &[Window],
+        params: &ComparisonParams,
+    ) -> Vec<(usize, usize)> {
+        self.capped_candidate_pairs(book_a, windows_a, book_b, windows_b, params).0
+    }
+
+    /// Like [`candidate_pairs`](Self::candidate_pairs), also reporting what
+    /// `max_candidates_per_window` cut.
+    pub fn capped_candidate_pairs(
+        &mut self,
+        book_a: u32,
+        windows_a: &[Window],
+        book_b: u32,
+        windows_b: &[Window],
+        params: &ComparisonParams,
+    ) -> (Vec<(usize, usize)>, CandidateCap) {
+        // The cache holds exact shingle sets; other methods don't use it
+        if params.brute_force || params.candidate_method != CandidateMethod::Shingles {
+            return find_capped_candidate_pairs(windows_a, windows_b, params);
+        }
+
+        for (book_id, windows) in [(book_a, windows_a), (book_b, windows_b)] {
+            if !self.books.contains_key(&book_id) {
+                let sets = self.dictionary.intern_windows(windows, params);
+                self.books.insert(book_id, sets);
+            }
+        }
+
+        let mut candidates = find_candidate_pairs_interned(
+            &self.books[&book_a],
+            &self.books[&book_b],
+            self.dictionary.len(),
+            params,
+        );
+        retain_exact_shared(&mut candidates, windows_a, windows_b, params);
+        retain_by_jaccard(&mut candidates, windows_a, windows_b, params);
+        let cap = cap_candidate_pairs(&mut candidates, windows_a, windows_b, params);
+        (candidates, cap)
+    }
+}
+
+/// Find candidate window pairs worth aligning.
+///
+/// Pairs come from the finder selected by `params` (see [`candidate_finder`]).
+/// Shingles are built according to `params.filter_mode`, independently of the
+/// mode used for alignment. With `params.min_shingle_jaccard` set, pairs must
+/// also reach that shingle-set Jaccard similarity, and with
+/// `params.exact_shingles` their shared shingles are recounted without hashing.
+/// `params.max_candidates_per_window` then keeps each window's densest pairs
+/// (see [`find_capped_candidate_pairs`]). None of these apply in brute force
+/// mode.
 ///
 /// Returns pairs of window indices (idx_a, idx_b) that should be aligned.
 pub fn find_candidate_pairs(
@@ -29,36 +558,112 @@ pub fn find_candidate_pairs(
     windows_b: &[Window],
     params: &ComparisonParams,
 ) -> Vec<(usize, usize)> {
+    find_capped_candidate_pairs(windows_a, windows_b, params).0
+}
+
+/// Like [`find_candidate_pairs`], also reporting what
+/// `max_candidates_per_window` cut. With a cap, pairs come back densest first:
+/// most shared shingles, then window order.
+pub fn find_capped_candidate_pairs(
+    windows_a: &[Window],
+    windows_b: &[Window],
+    params: &ComparisonParams,
+) -> (Vec<(usize, usize)>, CandidateCap) {
+    let mut candidates = candidate_finder(params).find_candidates(windows_a, windows_b, params);
     if params.brute_force {
-        // Return all pairs for brute force mode
-        return generate_all_pairs(windows_a.len(), windows_b.len());
+        return (candidates, CandidateCap::default());
     }
+    retain_exact_shared(&mut candidates, windows_a, windows_b, params);
+    retain_by_jaccard(&mut candidates, windows_a, windows_b, params);
+    let cap = cap_candidate_pairs(&mut candidates, windows_a, windows_b, params);
+    (candidates, cap)
+}
 
-    // Build shingle index for windows_b
-    // Map: shingle -> list of window indices containing it
-    let shingle_index = build_shingle_index(windows_b, params.ngram_size);
+/// Like [`find_candidate_pairs`], returning `(idx_a, idx_b, shared_shingles)`.
+pub fn find_candidate_pairs_with_counts(
+    windows_a: &[Window],
+    windows_b: &[Window],
+    params: &ComparisonParams,
+) -> Vec<(usize, usize, u64)> {
+    let mut dictionary = ShingleDictionary::new();
+    let shingles_a = dictionary.intern_windows(windows_a, params);
+    let shingles_b = dictionary.intern_windows(windows_b, params);
 
-    // For each window in A, find windows in B that share enough shingles
-    let mut candidates = Vec::new();
+    let candidates = if params.brute_force || params.candidate_method == CandidateMethod::Shingles {
+        let mut candidates = find_candidate_pairs_interned(&shingles_a, &shingles_b, dictionary.len(), params);
+        if !params.brute_force {
+            retain_exact_shared(&mut candidates, windows_a, windows_b, params);
+            retain_by_jaccard(&mut candidates, windows_a, windows_b, params);
+        }
+        candidates
+    } else {
+        let mut candidates = candidate_finder(params).find_candidates(windows_a, windows_b, params);
+        retain_by_jaccard(&mut candidates, windows_a, windows_b, params);
+        candidates
+    };
+    let mut counted = candidates
+        .into_iter()
+        .map(|(a, b)| (a, b, shingles_a[a].intersection_len(&shingles_b[b])))
+        .collect();
+    if !params.brute_force {
+        cap_candidates(&mut counted, params);
+    }
+    counted
+}
+
+/// Candidate pairs from pre-interned shingle sets (see [`ShingleDictionary`]).
+///
+/// `dictionary_len` is the number of IDs in the dictionary both sets were
+/// interned with; it sizes the posting lists. For each window of A, the union
+/// of its shingles' posting bitmaps gives every window of B sharing at least
+/// one shingle, and the shared count is the size of the two sets' intersection.
+/// Shingles too frequent for `params.max_shingle_df` or
+/// `params.max_shingle_df_ratio` are left out of both (see [`frequent_shingles`]).
+pub fn find_candidate_pairs_interned(
+    shingles_a: &[RoaringBitmap],
+    shingles_b: &[RoaringBitmap],
+    dictionary_len: usize,
+    params: &ComparisonParams,
+) -> Vec<(usize, usize)> {
+    if params.brute_force {
+        return generate_all_pairs(shingles_a.len(), shingles_b.len());
+    }
 
-    for (idx_a, window_a) in windows_a.iter().enumerate() {
-        let shingles_a = generate_shingles(&window_a.lemma_ids, params.ngram_size);
+    let frequent = frequent_shingles(shingles_a, shingles_b, dictionary_len, params);
+    let (shingles_a, shingles_b): (Cow<[RoaringBitmap]>, Cow<[RoaringBitmap]>) = if frequent.is_empty() {
+        (Cow::Borrowed(shingles_a), Cow::Borrowed(shingles_b))
+    } else {
+        let prune = |sets: &[RoaringBitmap]| sets.iter().map(|ids| ids - &frequent).collect::<Vec<_>>();
+        (Cow::Owned(prune(shingles_a)), Cow::Owned(prune(shingles_b)))
+    };
 
-        // Count shared shingles with each window in B
-        let mut shared_counts: HashMap<usize, usize> = HashMap::new();
+    // Posting bitmap per shingle ID: windows of B containing it
+    let mut postings: Vec<RoaringBitmap> = vec![RoaringBitmap::new(); dictionary_len];
+    for (idx_b, ids) in shingles_b.iter().enumerate() {
+        for id in ids {
+            postings[id as usize].insert(idx_b as u32);
+        }
+    }
 
-        for shingle in &shingles_a {
-            if let Some(matching_windows) = shingle_index.get(shingle) {
-                for &idx_b in matching_windows {
-                    *shared_counts.entry(idx_b).or_default() += 1;
-                }
+    let min_shared = params.min_shared_shingles.max(1) as u64;
+    let mut candidates = Vec::new();
+
+    for (idx_a, ids) in shingles_a.iter().enumerate() {
+        if ids.len() < min_shared {
+            continue;
+        }
+
+        let mut touched = RoaringBitmap::new();
+        for id in ids {
+            if let Some(posting) = postings.get(id as usize) {
+                touched |= posting;
             }
         }
 
         // Keep pairs that meet threshold
-        for (idx_b, count) in shared_counts {
-            if count >= params.min_shared_shingles {
-                candidates.push((idx_a, idx_b));
+        for idx_b in &touched {
+            if min_shared == 1 || ids.intersection_len(&shingles_b[idx_b as usize]) >= min_shared {
+                candidates.push((idx_a, idx_b as usize));
             }
         }
     }
@@ -66,18 +671,256 @@ pub fn find_candidate_pairs(
     candidates
 }
 
-/// Build an inverted index mapping shingles to window indices
-fn build_shingle_index(windows: &[Window], ngram_size: usize) -> HashMap<Vec<u32>, Vec<usize>> {
-    let mut index: HashMap<Vec<u32>, Vec<usize>> = HashMap::new();
+/// Shingle IDs found in more windows than the document frequency limits
+/// allow: `params.max_shingle_df` windows, or `params.max_shingle_df_ratio` of
+/// all windows, counting the windows of both books together.
+///
+/// Such shingles are formulaic (a standard isnād link, a doxology); they say
+/// little about whether two windows share a passage, yet their posting lists
+/// make up most of the index and of the candidate pairs.
+pub fn frequent_shingles(
+    shingles_a: &[RoaringBitmap],
+    shingles_b: &[RoaringBitmap],
+    dictionary_len: usize,
+    params: &ComparisonParams,
+) -> RoaringBitmap {
+    if params.max_shingle_df.is_none() && params.max_shingle_df_ratio.is_none() {
+        return RoaringBitmap::new();
+    }
+    let windows = shingles_a.len() + shingles_b.len();
+    let limit = params
+        .max_shingle_df
+        .unwrap_or(usize::MAX)
+        .min(params.max_shingle_df_ratio.map_or(usize::MAX, |ratio| (ratio as f64 * windows as f64) as usize));
+
+    let mut df = vec![0usize; dictionary_len];
+    for ids in shingles_a.iter().chain(shingles_b) {
+        for id in ids {
+            if let Some(count) = df.get_mut(id as usize) {
+                *count += 1;
+            }
+        }
+    }
+    df.iter()
+        .enumerate()
+        .filter(|&(_, &count)| count > limit)
+        .map(|(id, _)| id as u32)
+        .collect()
+}
+
+/// A strategy for finding the window pairs worth aligning.
+///
+/// Finders return pairs of window indices `(idx_a, idx_b)`, sorted. Every
+/// comparison path goes through [`find_candidate_pairs`], so a new method
+/// only needs an implementation and a `CandidateMethod` variant.
+pub trait CandidateFinder: Send + Sync {
+    fn find_candidates(
+        &self,
+        windows_a: &[Window],
+        windows_b: &[Window],
+        params: &ComparisonParams,
+    ) -> Vec<(usize, usize)>;
+}
+
+/// Every window pair.
+pub struct BruteForceFinder;
+
+impl CandidateFinder for BruteForceFinder {
+    fn find_candidates(&self, windows_a: &[Window], windows_b: &[Window], _: &ComparisonParams) -> Vec<(usize, usize)> {
+        generate_all_pairs(windows_a.len(), windows_b.len())
+    }
+}
+
+/// Window pairs sharing at least `min_shared_shingles` shingles, through an
+/// inverted index of book B's shingles.
+pub struct ShingleFinder;
+
+impl CandidateFinder for ShingleFinder {
+    fn find_candidates(
+        &self,
+        windows_a: &[Window],
+        windows_b: &[Window],
+        params: &ComparisonParams,
+    ) -> Vec<(usize, usize)> {
+        let mut dictionary = ShingleDictionary::new();
+        let shingles_a = dictionary.intern_windows(windows_a, params);
+        let shingles_b = dictionary.intern_windows(windows_b, params);
+        find_candidate_pairs_interned(&shingles_a, &shingles_b, dictionary.len(), params)
+    }
+}
+
+/// Consecutive shingles winnowing picks one fingerprint from.
+///
+/// Any run of `WINNOW_WINDOW + ngram_size - 1` keys shared by two windows
+/// yields at least one shared fingerprint.
+pub const WINNOW_WINDOW: usize = 4;
+
+/// Winnowed fingerprints of a window, sorted and distinct: the smallest hash
+/// of every `WINNOW_WINDOW` consecutive shingles (Schleimer et al., 2003).
+pub fn winnow_fingerprints(window: &Window, params: &ComparisonParams) -> Vec<u64> {
+    let keys = shingle_keys(window, params.filter_mode, params.stop_lemmas.as_ref());
+    let hashes = ordered_shingle_hashes(&keys, params.ngram_size, params.shingle_kind);
+    if hashes.is_empty() {
+        return Vec::new();
+    }
+    let mut fingerprints: Vec<u64> = hashes
+        .windows(WINNOW_WINDOW.min(hashes.len()))
+        .filter_map(|run| run.iter().min().copied())
+        .collect();
+    fingerprints.sort_unstable();
+    fingerprints.dedup();
+    fingerprints
+}
+
+/// Window pairs sharing at least `min_shared_shingles` winnowed fingerprints.
+///
+/// About 2 / (`WINNOW_WINDOW` + 1) of the shingles are kept, so the index is
+/// smaller than [`ShingleFinder`]'s and each shared fingerprint stands for
+/// several shared shingles.
+pub struct WinnowingFinder;
+
+impl CandidateFinder for WinnowingFinder {
+    fn find_candidates(
+        &self,
+        windows_a: &[Window],
+        windows_b: &[Window],
+        params: &ComparisonParams,
+    ) -> Vec<(usize, usize)> {
+        let mut dictionary = ShingleDictionary::new();
+        let mut intern = |windows: &[Window]| -> Vec<RoaringBitmap> {
+            windows
+                .iter()
+                .map(|w| winnow_fingerprints(w, params).into_iter().map(|h| dictionary.intern_hash(h)).collect())
+                .collect()
+        };
+        let fingerprints_a = intern(windows_a);
+        let fingerprints_b = intern(windows_b);
+        find_candidate_pairs_interned(&fingerprints_a, &fingerprints_b, dictionary.len(), params)
+    }
+}
+
+/// Bands of a MinHash signature; two windows are candidates if any band matches.
+pub const MINHASH_BANDS: usize = 32;
+/// Hashes per MinHash band.
+pub const MINHASH_ROWS: usize = 2;
+/// Seed of the MinHash hash functions, fixed so signatures are reproducible.
+const MINHASH_SEED: u64 = 0x6b61_7368_7368_6166;
 
-    for (idx, window) in windows.iter().enumerate() {
-        let shingles = generate_shingles(&window.lemma_ids, ngram_size);
-        for shingle in shingles {
-            index.entry(shingle).or_default().push(idx);
+/// MinHash signature of a window's shingle set: for each of
+/// `MINHASH_BANDS * MINHASH_ROWS` seeded hash functions, the smallest hash of
+/// any shingle. None for a window without shingles.
+pub fn minhash_signature(window: &Window, params: &ComparisonParams) -> Option<Vec<u64>> {
+    let hashes = window_shingle_hashes(window, params);
+    if hashes.is_empty() {
+        return None;
+    }
+    let mut seeds = SplitMix64::new(MINHASH_SEED);
+    let signature = (0..MINHASH_BANDS * MINHASH_ROWS)
+        .map(|_| {
+            let seed = seeds.next_u64();
+            hashes.iter().map(|&h| SplitMix64::new(h ^ seed).next_u64()).min().unwrap_or(u64::MAX)
+        })
+        .collect();
+    Some(signature)
+}
+
+/// Window pairs whose MinHash signatures agree on at least one band.
+///
+/// A pair with shingle-set Jaccard `s` becomes a candidate with probability
+/// 1 - (1 - s^`MINHASH_ROWS`)^`MINHASH_BANDS`: about 0.73 at s = 0.2 and 0.95
+/// at s = 0.3. Lookup costs one hash probe per band instead of one per
+/// shingle, at the price of missing some short quotations that
+/// [`ShingleFinder`] would keep.
+pub struct MinHashFinder;
+
+impl CandidateFinder for MinHashFinder {
+    fn find_candidates(
+        &self,
+        windows_a: &[Window],
+        windows_b: &[Window],
+        params: &ComparisonParams,
+    ) -> Vec<(usize, usize)> {
+        let signatures_b: Vec<Option<Vec<u64>>> = windows_b.iter().map(|w| minhash_signature(w, params)).collect();
+        let mut buckets: HashMap<(usize, &[u64]), Vec<usize>> = HashMap::new();
+        for (idx_b, signature) in signatures_b.iter().enumerate() {
+            for (band, rows) in signature.iter().flat_map(|s| s.chunks(MINHASH_ROWS).enumerate()) {
+                buckets.entry((band, rows)).or_default().push(idx_b);
+            }
         }
+
+        let mut candidates = Vec::new();
+        for (idx_a, window) in windows_a.iter().enumerate() {
+            let Some(signature) = minhash_signature(window, params) else {
+                continue;
+            };
+            let mut matches: Vec<usize> = signature
+                .chunks(MINHASH_ROWS)
+                .enumerate()
+                .filter_map(|(band, rows)| buckets.get(&(band, rows)))
+                .flatten()
+                .copied()
+                .collect();
+            matches.sort_unstable();
+            matches.dedup();
+            candidates.extend(matches.into_iter().map(|idx_b| (idx_a, idx_b)));
+        }
+        candidates
+    }
+}
+
+/// The candidate finder `params` selects: brute force if set, else the
+/// `candidate_method`.
+pub fn candidate_finder(params: &ComparisonParams) -> &'static dyn CandidateFinder {
+    if params.brute_force {
+        return &BruteForceFinder;
+    }
+    match params.candidate_method {
+        CandidateMethod::Shingles => &ShingleFinder,
+        CandidateMethod::Winnowing => &WinnowingFinder,
+        CandidateMethod::MinHash => &MinHashFinder,
+    }
+}
+
+/// Cheap whole-book vocabulary overlap used to prescan book pairs.
+///
+/// Each distinct lemma is weighted by its document-internal IDF
+/// (`ln(total / df)`, clamped to [0.5, 3.0]); a shared lemma contributes the
+/// smaller of its two weights. The result is the weighted shared vocabulary
+/// divided by the smaller book's total vocabulary weight, so 1.0 means the
+/// smaller book's vocabulary is entirely contained in the other.
+///
+/// Lemma ID 0 (unmapped tokens) is ignored.
+pub fn vocabulary_overlap(lemmas_a: &[u32], lemmas_b: &[u32]) -> f32 {
+    let vocab_a = vocabulary_weights(lemmas_a);
+    let vocab_b = vocabulary_weights(lemmas_b);
+
+    let total_a: f32 = vocab_a.values().sum();
+    let total_b: f32 = vocab_b.values().sum();
+    let denominator = total_a.min(total_b);
+    if denominator <= 0.0 {
+        return 0.0;
+    }
+
+    let shared: f32 = vocab_a
+        .iter()
+        .filter_map(|(lemma, &w_a)| vocab_b.get(lemma).map(|&w_b| w_a.min(w_b)))
+        .sum();
+
+    shared / denominator
+}
+
+/// Map each distinct lemma to its document-internal IDF weight.
+fn vocabulary_weights(lemma_ids: &[u32]) -> HashMap<u32, f32> {
+    let mut counts: HashMap<u32, u32> = HashMap::new();
+    for &id in lemma_ids.iter().filter(|&&id| id != 0) {
+        *counts.entry(id).or_default() += 1;
     }
 
-    index
+    let total = lemma_ids.len() as f32;
+    counts
+        .into_iter()
+        .map(|(id, df)| (id, (total / df as f32).ln().clamp(0.5, 3.0)))
+        .collect()
 }
 
 /// Generate all pairs (brute force mode)
@@ -108,6 +951,9 @@ mod tests {
             end_offset: 0,
             lemma_ids: lemmas,
             root_ids: vec![0; len],  // Empty roots for testing
+            token_ids: Vec::new(),
+            alt_lemma_ids: Vec::new(),
+            shingle_hashes: Vec::new(),
         }
     }
 
@@ -194,4 +1040,387 @@ mod tests {
         assert!(!pairs.contains(&(1, 1))); // No shared shingles
     }
 
+    #[test]
+    fn test_find_candidate_pairs_with_counts() {
+        let windows_a = vec![create_test_window(1, 0, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10])];
+        let windows_b = vec![
+            create_test_window(2, 0, vec![1, 2, 3, 4, 5, 200, 201, 202, 203, 204]),
+            create_test_window(2, 1, vec![1, 2, 3, 4, 5, 6, 7, 8, 9, 10]),
+        ];
+        let params = ComparisonParams {
+            ngram_size: 3,
+            min_shared_shingles: 2,
+            ..Default::default()
+        };
+
+        let mut pairs = find_candidate_pairs_with_counts(&windows_a, &windows_b, &params);
+        pairs.sort();
+        // [1,2,3], [2,3,4], [3,4,5] shared with B[0]; all eight with B[1]
+        assert_eq!(pairs, vec![(0, 0, 3), (0, 1, 8)]);
+    }
+
+    #[test]
+    fn test_candidate_methods() {
+        let shared: Vec<u32> = (1..=40).collect();
+        let windows_a = vec![
+            create_test_window(1, 0, (100..140).collect()),
+            create_test_window(1, 1, shared.clone()),
+        ];
+        let windows_b = vec![
+            create_test_window(2, 0, (200..240).collect()),
+            create_test_window(2, 1, (300..340).collect()),
+            create_test_window(2, 2, shared),
+        ];
+
+        for method in [CandidateMethod::Shingles, CandidateMethod::Winnowing, CandidateMethod::MinHash] {
+            let params = ComparisonParams {
+                ngram_size: 3,
+                candidate_method: method,
+                ..Default::default()
+            };
+            assert_eq!(
+                find_candidate_pairs(&windows_a, &windows_b, &params),
+                vec![(1, 2)],
+                "{:?}",
+                method
+            );
+        }
+        let brute = ComparisonParams {
+            brute_force: true,
+            candidate_method: CandidateMethod::MinHash,
+            ..Default::default()
+        };
+        assert_eq!(find_candidate_pairs(&windows_a, &windows_b, &brute).len(), 6);
+    }
+
+    #[test]
+    fn test_winnow_fingerprints() {
+        let params = ComparisonParams {
+            ngram_size: 3,
+            ..Default::default()
+        };
+        let window = create_test_window(1, 0, (1..=30).collect());
+        let fingerprints = winnow_fingerprints(&window, &params);
+        let hashes = compute_shingle_hashes(&window, 3, ShingleKind::Contiguous, MatchMode::Lemma, None);
+        assert!(!fingerprints.is_empty() && fingerprints.len() < hashes.len());
+        assert!(fingerprints.iter().all(|h| hashes.binary_search(h).is_ok()));
+
+        // A shared run of WINNOW_WINDOW + ngram_size - 1 keys shares a fingerprint
+        let run: Vec<u32> = (10..10 + (WINNOW_WINDOW + 2) as u32).collect();
+        let mut lemmas = vec![500, 501, 502];
+        lemmas.extend(&run);
+        let other = winnow_fingerprints(&create_test_window(2, 0, lemmas), &params);
+        assert!(other.iter().any(|h| fingerprints.binary_search(h).is_ok()));
+    }
+
+    #[test]
+    fn test_shingle_dictionary_interning() {
+        let mut dict = ShingleDictionary::new();
+        let a = dict.intern(&[1, 2, 3]);
+        let b = dict.intern(&[2, 3, 4]);
+        assert_ne!(a, b);
+        assert_eq!(dict.intern(&[1, 2, 3]), a);
+        assert_eq!(dict.len(), 2);
+
+        // Repeated shingles within a window collapse to one ID
+        let window = create_test_window(1, 0, vec![1, 2, 3, 1, 2, 3]);
+        let params = ComparisonParams {
+            ngram_size: 3,
+            filter_mode: MatchMode::Lemma,
+            ..Default::default()
+        };
+        let ids = dict.window_shingle_ids(&window, &params);
+        assert_eq!(ids.len(), 3);
+        assert!(ids.contains(a));
+    }
+
+    #[test]
+    fn test_corpus_shingles_share_dictionary() {
+        let params = ComparisonParams {
+            ngram_size: 3,
+            min_shared_shingles: 2,
+            ..Default::default()
+        };
+        let book1 = vec![create_test_window(1, 0, vec![1, 2, 3, 4, 5])];
+        let book2 = vec![create_test_window(2, 0, vec![1, 2, 3, 4, 9])];
+        let book3 = vec![create_test_window(3, 0, vec![7, 1, 2, 3, 4])];
+
+        let mut corpus = CorpusShingles::new();
+        assert_eq!(corpus.candidate_pairs(1, &book1, 2, &book2, &params), vec![(0, 0)]);
+        let interned = corpus.dictionary().len();
+
+        // Book 1 is reused from the cache; only book 3's new shingle is added
+        assert_eq!(corpus.candidate_pairs(1, &book1, 3, &book3, &params), vec![(0, 0)]);
+        assert_eq!(corpus.dictionary().len(), interned + 1);
+        assert_eq!(
+            corpus.candidate_pairs(1, &book1, 3, &book3, &params),
+            find_candidate_pairs(&book1, &book3, &params)
+        );
+    }
+
+    #[test]
+    fn test_shingle_jaccard_post_check() {
+        assert_eq!(shingle_jaccard(&[1, 2, 3, 4], &[3, 4, 5, 6]), 2.0 / 6.0);
+        assert_eq!(shingle_jaccard(&[], &[]), 0.0);
+
+        // Windows share 3 of 7 distinct trigrams: passes the count, not the Jaccard
+        let windows_a = vec![create_test_window(1, 0, vec![1, 2, 3, 4, 5, 6, 7])];
+        let windows_b = vec![create_test_window(2, 0, vec![1, 2, 3, 4, 5, 9, 8])];
+        let mut params = ComparisonParams {
+            ngram_size: 3,
+            min_shared_shingles: 2,
+            ..Default::default()
+        };
+        assert_eq!(find_candidate_pairs(&windows_a, &windows_b, &params), vec![(0, 0)]);
+
+        params.min_shingle_jaccard = Some(0.5);
+        assert!(find_candidate_pairs(&windows_a, &windows_b, &params).is_empty());
+    }
+
+    #[test]
+    fn test_weighted_shingle_overlap() {
+        let params = ComparisonParams {
+            ngram_size: 2,
+            ..Default::default()
+        };
+        // Both windows share the formulaic [1, 2] and the rarer [7, 8]
+        let window_a = create_test_window(1, 0, vec![1, 2, 7, 8]);
+        let window_b = create_test_window(2, 0, vec![1, 2, 3, 7, 8]);
+
+        // Unweighted: 2 shared of 3 and 4 shingles
+        let overlap = weighted_shingle_overlap(
+            &weighted_shingles(&window_a, &params, &[]),
+            &weighted_shingles(&window_b, &params, &[]),
+        );
+        assert_eq!(overlap.jaccard, 2.0 / 5.0);
+        assert_eq!(overlap.containment, 2.0 / 3.0);
+
+        // Lemmas 1 and 2 are common, the rest rare
+        let weights = [0.0, 0.5, 0.5, 3.0, 3.0, 3.0, 3.0, 3.0, 3.0, 3.0];
+        let shingles_a = weighted_shingles(&window_a, &params, &weights);
+        assert_eq!(shingles_a.values().sum::<f32>(), 0.5 + 1.75 + 3.0);
+        let overlap = weighted_shingle_overlap(&shingles_a, &weighted_shingles(&window_b, &params, &weights));
+        assert_eq!(overlap.containment, 3.5 / 5.25);
+        assert!(overlap.jaccard < overlap.containment);
+
+        // Sharing only the formula: half of the shingles, but little of the weight
+        let only_formula = create_test_window(3, 0, vec![1, 2, 9]);
+        let overlap = weighted_shingle_overlap(&shingles_a, &weighted_shingles(&only_formula, &params, &weights));
+        assert_eq!(overlap.containment, 0.5 / 2.25);
+        assert_eq!(weighted_shingle_overlap(&HashMap::new(), &HashMap::new()), WeightedOverlap::default());
+    }
+
+    #[test]
+    fn test_span_shingle_jaccard() {
+        assert_eq!(span_shingle_jaccard(&[1, 2, 3, 4], &[1, 2, 3, 4], 3), 1.0);
+        // Trigrams {123, 234} vs {123, 239}
+        assert_eq!(span_shingle_jaccard(&[1, 2, 3, 4], &[1, 2, 3, 9], 3), 1.0 / 3.0);
+        // Short spans fall back to their own length
+        assert_eq!(span_shingle_jaccard(&[5, 6], &[5, 6], 3), 1.0);
+        assert_eq!(span_shingle_jaccard(&[], &[1, 2], 3), 0.0);
+    }
+
+    #[test]
+    fn test_vocabulary_overlap() {
+        let a: Vec<u32> = (1..=100).collect();
+        let b: Vec<u32> = (1..=100).rev().collect();
+        assert!((vocabulary_overlap(&a, &b) - 1.0).abs() < 1e-5);
+
+        let disjoint: Vec<u32> = (1000..1100).collect();
+        assert_eq!(vocabulary_overlap(&a, &disjoint), 0.0);
+
+        let half: Vec<u32> = (51..=150).collect();
+        let overlap = vocabulary_overlap(&a, &half);
+        assert!(overlap > 0.4 && overlap < 0.6);
+
+        assert_eq!(vocabulary_overlap(&[], &a), 0.0);
+    }
+
+    #[test]
+    fn test_find_candidate_pairs_root_filter_mode() {
+        // Same roots, different lemmas: only root-level shingles find the pair
+        let mut window_a = create_test_window(1, 0, vec![1, 2, 3, 4, 5]);
+        window_a.root_ids = vec![10, 20, 30, 40, 50];
+        let mut window_b = create_test_window(2, 0, vec![6, 7, 8, 9, 11]);
+        window_b.root_ids = vec![10, 20, 30, 40, 50];
+
+        let mut params = ComparisonParams {
+            ngram_size: 3,
+            min_shared_shingles: 2,
+            ..Default::default()
+        };
+        assert_eq!(params.filter_mode, MatchMode::Lemma);
+        assert!(find_candidate_pairs(&[window_a.clone()], &[window_b.clone()], &params).is_empty());
+
+        params.filter_mode = MatchMode::Root;
+        assert_eq!(find_candidate_pairs(&[window_a.clone()], &[window_b], &params), vec![(0, 0)]);
+
+        // Rootless tokens fall back to lemmas rather than matching each other on root 0
+        let rootless = create_test_window(2, 0, vec![6, 7, 8, 9, 11]);
+        window_a.root_ids = vec![0; 5];
+        assert!(find_candidate_pairs(&[window_a], &[rootless], &params).is_empty());
+    }
+
+    #[test]
+    fn test_shingle_hash_distinguishes_order_and_length() {
+        let hashes = [
+            shingle_hash(&[1, 2, 3]),
+            shingle_hash(&[3, 2, 1]),
+            shingle_hash(&[1, 2]),
+            shingle_hash(&[0, 1, 2]),
+            shingle_hash(&[]),
+            shingle_hash(&[0]),
+        ];
+        let distinct: HashSet<u64> = hashes.iter().copied().collect();
+        assert_eq!(distinct.len(), hashes.len());
+        assert_eq!(shingle_hash(&[1, 2, 3]), shingle_hash(&[1, 2, 3]));
+    }
+
+    #[test]
+    fn test_exact_shingles_recount_candidates() {
+        let windows_a = vec![create_test_window(1, 0, (1..=10).collect())];
+        let windows_b = vec![
+            create_test_window(2, 0, (1..=10).collect()),
+            create_test_window(2, 1, (21..=30).collect()),
+        ];
+        let mut params = ComparisonParams {
+            ngram_size: 3,
+            exact_shingles: true,
+            ..Default::default()
+        };
+        assert_eq!(find_candidate_pairs(&windows_a, &windows_b, &params), vec![(0, 0)]);
+
+        // A pair reported by the hash index but sharing no shingle is dropped
+        let mut candidates = vec![(0, 0), (0, 1)];
+        retain_exact_shared(&mut candidates, &windows_a, &windows_b, &params);
+        assert_eq!(candidates, vec![(0, 0)]);
+
+        params.exact_shingles = false;
+        let mut candidates = vec![(0, 0), (0, 1)];
+        retain_exact_shared(&mut candidates, &windows_a, &windows_b, &params);
+        assert_eq!(candidates.len(), 2);
+    }
+
+    #[test]
+    fn test_frequent_shingles_pruned() {
+        // Every window opens with the same formula; only window 0 of each book
+        // shares anything else
+        let formula = [100, 101, 102, 103, 104];
+        let window = |book_id, idx, rest: std::ops::Range<u32>| {
+            create_test_window(book_id, idx, formula.iter().copied().chain(rest).collect())
+        };
+        let windows_a = vec![window(1, 0, 1..8), window(1, 1, 10..15), window(1, 2, 20..25)];
+        let windows_b = vec![window(2, 0, 1..8), window(2, 1, 30..35), window(2, 2, 40..45)];
+        let mut params = ComparisonParams {
+            ngram_size: 3,
+            min_shared_shingles: 2,
+            ..Default::default()
+        };
+        assert_eq!(find_candidate_pairs(&windows_a, &windows_b, &params).len(), 9);
+
+        params.max_shingle_df = Some(2);
+        assert_eq!(find_candidate_pairs(&windows_a, &windows_b, &params), vec![(0, 0)]);
+
+        params.max_shingle_df = None;
+        params.max_shingle_df_ratio = Some(0.5);
+        assert_eq!(find_candidate_pairs(&windows_a, &windows_b, &params), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn test_candidate_cap_keeps_densest_pairs() {
+        // Book B windows share 3, 7, 5 and 1 shingles with the window of book A
+        let windows_a = vec![create_test_window(1, 0, (1..=10).collect())];
+        let windows_b: Vec<Window> = [(1..=5), (1..=9), (3..=9), (8..=10)]
+            .into_iter()
+            .enumerate()
+            .map(|(idx, lemmas)| create_test_window(2, idx as u32, lemmas.collect()))
+            .collect();
+        let mut params = ComparisonParams {
+            ngram_size: 3,
+            min_shared_shingles: 1,
+            ..Default::default()
+        };
+        let (candidates, cap) = find_capped_candidate_pairs(&windows_a, &windows_b, &params);
+        assert_eq!(candidates.len(), 4);
+        assert_eq!(cap, CandidateCap::default());
+
+        params.max_candidates_per_window = Some(2);
+        let (candidates, cap) = find_capped_candidate_pairs(&windows_a, &windows_b, &params);
+        assert_eq!(candidates, vec![(0, 1), (0, 2)]);
+        assert_eq!(cap, CandidateCap { dropped_pairs: 2, capped_windows: 1 });
+        let counted = find_candidate_pairs_with_counts(&windows_a, &windows_b, &params);
+        assert_eq!(counted, vec![(0, 1, 7), (0, 2, 5)]);
+    }
+
+    #[test]
+    fn test_gapped_shingles_survive_substitutions() {
+        let mut expected = vec![shingle_hash(&[1, 3, 4]), shingle_hash(&[1, 2, 4])];
+        expected.sort_unstable();
+        assert_eq!(sequence_shingle_hashes(&[1, 2, 3, 4], 4, ShingleKind::Gapped), expected);
+        assert_eq!(
+            sequence_shingle_hashes(&[1, 2, 3], 2, ShingleKind::Gapped),
+            sequence_shingle_hashes(&[1, 2, 3], 2, ShingleKind::Contiguous)
+        );
+
+        // Every fourth lemma substituted: no 5-gram survives, but skip-grams do
+        let lemmas: Vec<u32> = (1..=40).collect();
+        let substituted = lemmas.iter().map(|&l| if l % 4 == 0 { l + 100 } else { l }).collect();
+        let windows_a = vec![create_test_window(1, 0, lemmas)];
+        let windows_b = vec![create_test_window(2, 0, substituted)];
+        let mut params = ComparisonParams::default();
+        assert!(find_candidate_pairs(&windows_a, &windows_b, &params).is_empty());
+
+        params.shingle_kind = ShingleKind::Gapped;
+        assert_eq!(find_candidate_pairs(&windows_a, &windows_b, &params), vec![(0, 0)]);
+        let overlap = weighted_shingle_overlap(
+            &weighted_shingles(&windows_a[0], &params, &[]),
+            &weighted_shingles(&windows_b[0], &params, &[]),
+        );
+        assert!(overlap.jaccard > 0.0);
+    }
+
+    #[test]
+    fn test_surface_filter_uses_token_ids() {
+        // Different lemmas, same surface tokens
+        let mut window_a = create_test_window(1, 0, vec![1, 2, 3, 4, 5]);
+        window_a.token_ids = vec![10, 20, 30, 40, 50];
+        let mut window_b = create_test_window(2, 0, vec![6, 7, 8, 9, 11]);
+        window_b.token_ids = vec![10, 20, 30, 40, 50];
+
+        let mut params = ComparisonParams {
+            ngram_size: 3,
+            min_shared_shingles: 2,
+            ..Default::default()
+        };
+        assert!(find_candidate_pairs(&[window_a.clone()], &[window_b.clone()], &params).is_empty());
+
+        params.filter_mode = MatchMode::Surface;
+        assert_eq!(find_candidate_pairs(&[window_a.clone()], &[window_b], &params), vec![(0, 0)]);
+
+        // Windows without token IDs shingle on lemmas
+        let lemma_only = create_test_window(2, 0, vec![1, 2, 3, 4, 5]);
+        window_a.token_ids.clear();
+        assert_eq!(find_candidate_pairs(&[window_a], &[lemma_only], &params), vec![(0, 0)]);
+    }
+
+    #[test]
+    fn test_stop_lemmas_skipped_in_shingles() {
+        // Same content lemmas, different particles (50, 51) between them
+        let windows_a = vec![create_test_window(1, 0, vec![1, 50, 2, 3, 51, 4])];
+        let windows_b = vec![create_test_window(2, 0, vec![1, 51, 2, 3, 50, 4])];
+        let mut params = ComparisonParams {
+            ngram_size: 3,
+            min_shared_shingles: 2,
+            ..Default::default()
+        };
+        assert!(find_candidate_pairs(&windows_a, &windows_b, &params).is_empty());
+
+        params.stop_lemmas = Some(HashSet::from([50, 51]));
+        assert_eq!(
+            compute_shingle_hashes(&windows_a[0], 3, ShingleKind::Contiguous, MatchMode::Lemma, params.stop_lemmas.as_ref()),
+            sequence_shingle_hashes(&[1, 2, 3, 4], 3, ShingleKind::Contiguous)
+        );
+        assert_eq!(find_candidate_pairs(&windows_a, &windows_b, &params), vec![(0, 0)]);
+    }
+
 }
@@ -0,0 +1,94 @@
+//! Book-level reuse networks from a directory of comparison results.
+//!
+//! Every `*.json` file under the directory that holds a comparison result,
+//! with or without text, adds its two books as nodes and, if they share
+//! material, one edge weighted by aligned tokens. This covers `compare`
+//! output as well as batch and aggregate directories (`results/*/result.json`).
+//! Other JSON files there (manifests, summaries, candidate lists) are skipped.
+//!
+//! [`write_graphml`] and [`write_gexf`] write the network for Gephi,
+//! Cytoscape and similar tools.
+//!
+//! [`write_graphml`]: crate::output::write_graphml
+//! [`write_gexf`]: crate::output::write_gexf
+
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::models::{ComparisonSummary, GraphNode, NetworkEdge, ReuseGraph};
+use crate::output::json_files_under;
+
+#[derive(Error, Debug)]
+pub enum GraphError {
+    #[error("{}: {source}", path.display())]
+    Io { path: PathBuf, source: std::io::Error },
+    #[error("pair {book_a}-{book_b} appears in both {} and {}", first.display(), second.display())]
+    DuplicatePair {
+        book_a: u32,
+        book_b: u32,
+        first: PathBuf,
+        second: PathBuf,
+    },
+}
+
+/// The parts of a result file the network needs; edges are not read.
+#[derive(Deserialize)]
+struct ResultHeader {
+    book_a: GraphNode,
+    book_b: GraphNode,
+    summary: ComparisonSummary,
+}
+
+/// Build the reuse network from every comparison result under `dir`.
+///
+/// Nodes are sorted by book ID and edges by book pair. Books compared without
+/// finding reuse stay in the graph as unconnected nodes. A pair found in two
+/// files, in either order, is an error rather than being counted twice.
+pub fn load_network(dir: &Path) -> Result<ReuseGraph, GraphError> {
+    let files = json_files_under(dir).map_err(|source| GraphError::Io {
+        path: dir.to_path_buf(),
+        source,
+    })?;
+
+    let mut nodes = BTreeMap::new();
+    let mut edges = BTreeMap::new();
+    let mut seen: HashMap<(u32, u32), PathBuf> = HashMap::new();
+    for path in files {
+        let bytes = std::fs::read(&path).map_err(|source| GraphError::Io {
+            path: path.clone(),
+            source,
+        })?;
+        let Ok(header) = serde_json::from_slice::<ResultHeader>(&bytes) else {
+            continue;
+        };
+        let (a, b) = (header.book_a.id, header.book_b.id);
+        let key = (a.min(b), a.max(b));
+        if let Some(first) = seen.get(&key) {
+            return Err(GraphError::DuplicatePair {
+                book_a: a,
+                book_b: b,
+                first: first.clone(),
+                second: path,
+            });
+        }
+        seen.insert(key, path);
+
+        if header.summary.edge_count > 0 {
+            edges.insert(key, NetworkEdge::from_summary(a, b, &header.summary));
+        }
+        for node in [header.book_a, header.book_b] {
+            let entry = nodes.entry(node.id).or_insert_with(|| node.clone());
+            if entry.title.is_empty() {
+                entry.title = node.title;
+            }
+            entry.death_ah = entry.death_ah.or(node.death_ah);
+        }
+    }
+
+    Ok(ReuseGraph {
+        nodes: nodes.into_values().collect(),
+        edges: edges.into_values().collect(),
+    })
+}
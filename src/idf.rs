@@ -0,0 +1,145 @@
+//! Corpus-wide IDF weights.
+//!
+//! Document-internal weights ([`build_lemma_weights`]) rate a lemma by how
+//! often it occurs in the book at hand, so the same lemma weighs differently
+//! in every pair and `content_weight` is not comparable across pairs. Corpus
+//! weights apply the same formula to lemma counts over the whole corpus, which
+//! puts every pair on one scale (and keeps `min_content_weight` thresholds
+//! meaningful).
+//!
+//! Counting reads every page of corpus.db, so the weights are cached in a
+//! sidecar file next to it (`corpus.db.lemma_idf.json`) and recounted only
+//! when the database fingerprint changes.
+//!
+//! [`build_lemma_weights`]: crate::compare::build_lemma_weights
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::batch::database_fingerprint;
+use crate::db::{count_corpus_lemmas, DbError};
+use crate::models::{ComparisonParams, CorpusLemmaWeights, WeightSource};
+use crate::signature::StopLemmas;
+
+/// Suffix appended to the database file name to form the sidecar path.
+pub const CORPUS_WEIGHTS_SUFFIX: &str = "lemma_idf.json";
+
+/// Path of the corpus weight sidecar for a database.
+pub fn corpus_weights_path(db_path: &Path) -> PathBuf {
+    let mut name = db_path.file_name().unwrap_or_default().to_os_string();
+    name.push(".");
+    name.push(CORPUS_WEIGHTS_SUFFIX);
+    db_path.with_file_name(name)
+}
+
+/// IDF weights from corpus-wide lemma counts:
+///   weight(ℓ) = ln(total_tokens / count(ℓ)), clamped to [0.5, 3.0]
+///
+/// Lemmas that never occur get 0.0, which alignment treats as unknown.
+pub fn weights_from_counts(counts: &[u64]) -> Vec<f32> {
+    let total = counts.iter().sum::<u64>() as f64;
+    counts
+        .iter()
+        .map(|&count| {
+            if count == 0 {
+                0.0
+            } else {
+                ((total / count as f64).ln() as f32).clamp(0.5, 3.0)
+            }
+        })
+        .collect()
+}
+
+/// Corpus-wide weights for a database: read from the sidecar if it was
+/// written for the current database, otherwise counted and cached.
+///
+/// A sidecar that can't be written (read-only directory) only costs a recount
+/// next time.
+pub fn load_corpus_weights(db_path: &Path, token_to_lemma: &[u32]) -> Result<Arc<Vec<f32>>, DbError> {
+    Ok(Arc::new(load_corpus_lemma_stats(db_path, token_to_lemma)?.weights))
+}
+
+/// Corpus-wide stop-lemmas for edge signatures, cached in the same sidecar
+/// as the weights. See [`StopLemmas::from_counts`].
+pub fn load_corpus_stop_lemmas(db_path: &Path, token_to_lemma: &[u32]) -> Result<StopLemmas, DbError> {
+    let stats = load_corpus_lemma_stats(db_path, token_to_lemma)?;
+    Ok(StopLemmas::from_lemma_ids(stats.stop_lemmas.unwrap_or_default()))
+}
+
+/// The sidecar's contents if it was written for the current database (and
+/// records stop-lemmas), otherwise counted afresh and cached.
+fn load_corpus_lemma_stats(db_path: &Path, token_to_lemma: &[u32]) -> Result<CorpusLemmaWeights, DbError> {
+    let sidecar = corpus_weights_path(db_path);
+    let db_fingerprint = database_fingerprint(db_path).ok();
+
+    if let Some(fingerprint) = &db_fingerprint {
+        let cached = std::fs::read(&sidecar)
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<CorpusLemmaWeights>(&bytes).ok());
+        if let Some(cached) = cached.filter(|c| &c.db_fingerprint == fingerprint && c.stop_lemmas.is_some()) {
+            return Ok(cached);
+        }
+    }
+
+    let counts = count_corpus_lemmas(db_path, token_to_lemma)?;
+    let mut stop_lemmas: Vec<u32> = StopLemmas::from_counts(&counts).lemma_ids().collect();
+    stop_lemmas.sort_unstable();
+    let stats = CorpusLemmaWeights {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        db_fingerprint: db_fingerprint.unwrap_or_default(),
+        total_tokens: counts.iter().sum(),
+        weights: weights_from_counts(&counts),
+        stop_lemmas: Some(stop_lemmas),
+    };
+    if !stats.db_fingerprint.is_empty() {
+        if let Ok(json) = serde_json::to_vec(&stats) {
+            let _ = std::fs::write(&sidecar, json);
+        }
+    }
+    Ok(stats)
+}
+
+/// Corpus-wide weights if `params` asks for them, else None.
+pub fn corpus_weights_for(
+    params: &ComparisonParams,
+    db_path: &Path,
+    token_to_lemma: &[u32],
+) -> Result<Option<Arc<Vec<f32>>>, DbError> {
+    match params.weight_source {
+        WeightSource::Corpus if params.use_weights => Ok(Some(load_corpus_weights(db_path, token_to_lemma)?)),
+        _ => Ok(None),
+    }
+}
+
+/// The stop-lemmas for edge signatures under `params`: its own list when
+/// set, otherwise the corpus-wide one.
+pub fn stop_lemmas_for(
+    params: &ComparisonParams,
+    db_path: &Path,
+    token_to_lemma: &[u32],
+) -> Result<StopLemmas, DbError> {
+    StopLemmas::for_params(params, || load_corpus_stop_lemmas(db_path, token_to_lemma))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_weights_from_counts() {
+        // 1000 tokens: lemma 1 common, lemma 2 rarer, lemma 3 absent
+        let weights = weights_from_counts(&[0, 900, 100, 0]);
+        assert_eq!(weights[0], 0.0);
+        assert_eq!(weights[1], 0.5); // ln(1000/900) clamped up
+        assert!((weights[2] - 10.0f32.ln()).abs() < 1e-5);
+        assert_eq!(weights[3], 0.0);
+    }
+
+    #[test]
+    fn test_corpus_weights_path() {
+        assert_eq!(
+            corpus_weights_path(Path::new("/data/corpus.db")),
+            PathBuf::from("/data/corpus.db.lemma_idf.json")
+        );
+    }
+}
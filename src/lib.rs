@@ -5,79 +5,147 @@
 //!
 //! # Example
 //!
-//! ```no_run
-//! use kashshaf_reuse::prelude::*;
-//! use std::path::Path;
+//! Books normally come from corpus.db (see [`corpus::Corpus`]). The examples
+//! here build a small corpus in memory with [`corpus::CorpusBuilder`] instead:
 //!
-//! let db_path = Path::new("corpus.db");
-//! let params = ComparisonParams::default();
+//! ```
+//! use kashshaf_reuse::prelude::*;
 //!
-//! // Load token-to-lemma mapping
-//! let token_to_lemma = load_token_to_lemma(db_path).unwrap();
+//! const HADITH: &str = "إنما الأعمال بالنيات وإنما لكل امرئ ما نوى فمن كانت هجرته إلى الله ورسوله \
+//!     فهجرته إلى الله ورسوله ومن كانت هجرته إلى دنيا يصيبها أو امرأة ينكحها فهجرته إلى ما هاجر إليه";
 //!
-//! // Load lemma streams for two books
-//! let stream_a = load_book_lemma_stream(db_path, 230, &token_to_lemma).unwrap();
-//! let stream_b = load_book_lemma_stream(db_path, 553, &token_to_lemma).unwrap();
+//! let mut corpus = CorpusBuilder::new()
+//!     .book(1, [format!("حدثنا الحميدي قال حدثنا سفيان {HADITH} وهذا آخر الباب")])
+//!     .book(2, [format!("باب كيف كان بدء الوحي {HADITH} قال أبو عبد الله")])
+//!     .build()
+//!     .unwrap();
 //!
-//! // Compare the books
-//! let result = compare_books_from_streams(&stream_a, &stream_b, &params, false).unwrap();
+//! let params = ComparisonParams {
+//!     window_size: 20,
+//!     stride: 5,
+//!     ngram_size: 3,
+//!     ..Default::default()
+//! };
+//! let result = corpus.compare(1, 2, &params).unwrap();
 //!
 //! println!("Found {} reuse edges", result.edges.len());
+//! assert_eq!(result.edges.len(), 1);
+//! assert!(result.summary.book_b_coverage > 0.7);
 //! ```
 //!
 //! # Text Reconstruction Example
 //!
-//! ```no_run
-//! use kashshaf_reuse::prelude::*;
-//! use std::path::Path;
-//!
-//! let db_path = Path::new("corpus.db");
-//! let params = ComparisonParams::default();
-//! let context_tokens = 30;
+//! ```
+//! # use kashshaf_reuse::prelude::*;
+//! # const HADITH: &str = "إنما الأعمال بالنيات وإنما لكل امرئ ما نوى فمن كانت هجرته إلى الله ورسوله \
+//! #     فهجرته إلى الله ورسوله ومن كانت هجرته إلى دنيا يصيبها أو امرأة ينكحها فهجرته إلى ما هاجر إليه";
+//! # let mut corpus = CorpusBuilder::new()
+//! #     .book(1, [format!("حدثنا الحميدي قال حدثنا سفيان {HADITH} وهذا آخر الباب")])
+//! #     .book(2, [format!("باب كيف كان بدء الوحي {HADITH} قال أبو عبد الله")])
+//! #     .build()
+//! #     .unwrap();
+//! # let params = ComparisonParams { window_size: 20, stride: 5, ngram_size: 3, ..Default::default() };
+//! let context_tokens = 3;
 //!
 //! // Compare with text reconstruction
-//! let result = compare_books_with_text(230, 553, db_path, &params, context_tokens, true).unwrap();
+//! let result = corpus.compare_with_text(1, 2, &params, context_tokens).unwrap();
 //!
 //! // Each edge now includes the actual Arabic text
 //! for edge in &result.edges {
 //!     println!("Source: {}", edge.source.text.matched);
 //!     println!("Target: {}", edge.target.text.matched);
 //! }
+//! assert!(result.edges[0].source.text.matched.starts_with("إنما الأعمال"));
+//! assert_eq!(result.edges[0].source.text.before, "قال حدثنا سفيان");
 //! ```
+//!
+//! The same comparisons run against corpus.db with [`compare::compare_books`]
+//! and [`compare::compare_books_with_text`], or through a
+//! [`corpus::Corpus`] opened on the file.
 
+pub mod abridge;
+pub mod aggregate;
 pub mod align;
+pub mod analysis;
+pub mod audit;
+pub mod author;
+pub mod batch;
+pub mod bootstrap;
 pub mod compare;
+pub mod config;
+pub mod corpus;
 pub mod db;
 pub mod extract;
 pub mod filter;
+pub mod graph;
+pub mod idf;
 pub mod merge;
+pub mod metadata;
+pub mod metrics;
 pub mod models;
+pub mod normalize;
 pub mod output;
+pub mod pipeline;
+pub mod quran;
+pub mod redact;
+mod rng;
+pub mod script;
+pub mod segment;
+pub mod signature;
+pub mod source;
+pub mod substitution;
+pub mod sweep;
+pub mod tokenize;
+pub mod validate;
+pub mod verse;
 pub mod window;
 
 /// Prelude module for convenient imports.
 pub mod prelude {
     pub use crate::align::{align_lemma_sequences, align_sequences};
-    pub use crate::compare::{compare_books, compare_books_from_streams, compare_books_with_text};
+    pub use crate::compare::{
+        compare_books, compare_books_from_streams, compare_books_with_text, compare_texts, expand_edge, expand_edges,
+    };
+    pub use crate::corpus::{Corpus, CorpusBuilder};
+    pub use crate::db::import::Lexicon;
     pub use crate::db::{
         load_all_token_mappings, load_book_info, load_book_lemma_stream,
         load_book_token_stream_with_root, load_corpus_stats, load_token_to_lemma, DbError,
     };
-    pub use crate::filter::{find_candidate_pairs, generate_shingles};
+    pub use crate::filter::{
+        candidate_finder, find_candidate_pairs, generate_shingles, CandidateFinder, CorpusShingles, ShingleDictionary,
+    };
     pub use crate::merge::merge_overlapping_edges;
+    pub use crate::metrics::{compute_edge_metrics, EdgeMetrics};
     pub use crate::models::{
-        Alignment, AlignmentInfo, BookInfo, BookLemmaStream, BookMetadata, BookTokenStream,
-        ComparisonParams, ComparisonResult, ComparisonResultWithText, ComparisonSummary,
-        CorpusStats, MatchMode, PageInfo, PageLemmas, PageTokens, PassageRef, PassageText,
-        ReuseEdge, ReuseEdgeWithText, ViewerBookInfo, Window,
+        Alignment, AlignmentInfo, AlignmentOp, BookInfo, BookLemmaStream, BookMetadata, BookTokenStream,
+        CandidateMethod, ComparisonParams, ComparisonResult, ComparisonResultWithText, ComparisonSummary,
+        ContextBoundary, CorpusStats, MatchMode, PageInfo, PageLemmas, PageTokens, PassageContext,
+        PassageRef, PassageText, ReuseEdge, ReuseEdgeWithText, Segmentation, ShingleKind, Validation, ViewerBookInfo,
+        Window,
     };
+    pub use crate::normalize::NormalizationConfig;
     pub use crate::output::{
-        format_edge, format_edge_with_text, format_page_location, generate_viewer_html,
-        print_edges, print_edges_with_text, print_summary, print_summary_with_text, write_csv,
-        write_csv_file, write_csv_with_text, write_csv_with_text_file, write_json, write_json_file,
-        write_json_with_text, write_json_with_text_file, write_viewer_html_file, OutputError,
+        apply_validations, apply_validations_with_text, format_coverage, format_edge, format_edge_with_text, format_page_location, generate_corpus_viewer_html,
+        generate_viewer_html,
+        print_edges, print_edges_with_options, print_edges_with_text, print_edges_with_text_options, print_summary,
+        print_summary_with_text, read_csv_edges, read_csv_edges_with_options,
+        read_csv_edges_file, read_csv_edges_file_with_options, read_json_file, read_json_with_text_file, read_validations_file, write_csv,
+        write_candidates_csv, write_candidates_csv_file, write_candidates_file,
+        write_csv_file, write_csv_file_with_options, write_csv_with_options, write_csv_with_text,
+        write_csv_with_text_file, write_csv_with_text_file_options, write_csv_with_text_options, write_edges_jsonl,
+        write_edges_jsonl_file, write_edges_jsonl_file_with_columns, write_edges_jsonl_with_columns,
+        write_gexf, write_gexf_file, write_graphml, write_graphml_file, write_json,
+        write_json_file, write_network_csv, write_network_csv_file,
+        write_json_with_text, write_json_with_text_file, write_passim_json, write_passim_json_file,
+        write_result_csv_with_text, write_result_csv_with_text_file, write_xlsx, write_xlsx_file,
+        write_xlsx_file_with_headers, write_xlsx_with_headers,
+        write_corpus_viewer_html_file, write_viewer_html_file, CsvColumns, CsvOptions, EdgePrintOptions, EdgeSort, HeaderLanguage,
+        OutputColumns, OutputError, ViewerAssets,
     };
-    pub use crate::window::{generate_windows, generate_windows_with_roots};
+    pub use crate::source::{CorpusSource, SqliteSource};
+    pub use crate::tokenize::{SimpleTokenizer, Tokenizer};
+    pub use crate::window::{calculate_window_count, generate_windows, generate_windows_with_roots};
 }
 
 // Re-export commonly used types at the crate root
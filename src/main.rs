@@ -3,25 +3,40 @@
 //! High-performance text reuse detection for premodern Arabic texts.
 //! Compares lemma ID sequences to handle morphological variation automatically.
 
-use clap::{Parser, Subcommand, ValueEnum};
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use std::collections::HashSet;
+use std::ffi::OsString;
 use std::path::PathBuf;
 
-mod align;
-mod compare;
-mod db;
-mod extract;
-mod filter;
-mod merge;
-mod models;
-mod output;
-mod window;
+use kashshaf_reuse::{
+    abridge, aggregate, align, analysis, audit, author, batch, compare, config, corpus, db, graph, metadata, models,
+    normalize, output, pipeline, redact, sweep, tokenize, validate,
+};
 
+use db::import::Lexicon;
 use db::{load_book_info, load_corpus_stats};
-use models::{ComparisonParams, MatchMode};
+use normalize::NormalizationConfig;
+use models::{
+    CandidateMethod, ComparisonParams, ContextBoundary, MatchMode, Segmentation, ShingleKind, WeightSource,
+};
 use output::{
-    print_edges, print_edges_with_text, print_summary, print_summary_with_text,
-    write_csv_file, write_csv_with_text_file, write_json_file, write_json_with_text_file,
-    write_viewer_html_file,
+    apply_validations, apply_validations_with_text, jsonl_summary_path, print_edges_with_options, print_edges_with_text_options, print_summary,
+    print_summary_with_text, print_sweep_report,
+    read_csv_edges_file_with_options, read_json_file, read_json_with_text_file, read_results_with_text_dir, read_validations_file,
+    read_cluster_report_file,
+    target_output_path,
+    write_abridgment_report_file,
+    write_alignment_trace_html_file,
+    write_audit_file,
+    write_author_report_file,
+    write_corpus_viewer_html_file,
+    write_candidates_csv_file, write_candidates_file, write_cluster_report_file, write_collation_file,
+    write_collation_html_file,
+    write_csv_file_with_options, write_edges_jsonl_file_with_columns, write_gexf_file, write_graphml_file,
+    write_json_file, write_result_csv_with_text_file, write_xlsx_file_with_headers,
+    write_page_summary_csv_file, write_page_summary_csv_with_text_file, write_sweep_report_file,
+    write_json_with_text_file, write_passim_json_file, write_substitution_report_file, write_summary_file,
+    write_viewer_html_file, CsvOptions, EdgePrintOptions, EdgeSort, HeaderLanguage, OutputColumns, OutputError, ViewerAssets,
 };
 
 #[derive(Parser)]
@@ -42,6 +57,37 @@ enum OutputFormat {
     Csv,
     /// Self-contained HTML viewer with embedded React app
     Viewer,
+    /// JSON Lines, one edge per line, plus a <output>.summary.json file
+    Jsonl,
+    /// passim-style pairwise alignment records, one per line
+    Passim,
+    /// Excel workbook with an edges sheet (with text) and a summary sheet
+    Xlsx,
+}
+
+/// File format for `graph`
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum GraphFormat {
+    /// GraphML (Cytoscape, Gephi, networkx)
+    Graphml,
+    /// GEXF 1.3 (Gephi)
+    Gexf,
+}
+
+/// File format for `collate`
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum CollationFormat {
+    /// Columns of words per witness, as JSON
+    Json,
+    /// A standalone HTML table, variant columns shaded
+    Html,
+}
+
+/// Pipeline stage after which `compare` stops and exports what it has
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum StopAfter {
+    /// Windowing and candidate filtering: export candidate pairs, align nothing
+    Filter,
 }
 
 /// Matching mode for alignment (CLI version, mirrors models::MatchMode)
@@ -53,6 +99,8 @@ enum CliMatchMode {
     Root,
     /// Lemma match = full score, root-only match = partial score (default)
     Combined,
+    /// Match normalized surface forms, ignoring lemma and root data
+    Surface,
 }
 
 impl From<CliMatchMode> for MatchMode {
@@ -61,11 +109,555 @@ impl From<CliMatchMode> for MatchMode {
             CliMatchMode::Lemma => MatchMode::Lemma,
             CliMatchMode::Root => MatchMode::Root,
             CliMatchMode::Combined => MatchMode::Combined,
+            CliMatchMode::Surface => MatchMode::Surface,
+        }
+    }
+}
+
+/// Edge order for --show-edges (CLI version, mirrors output::EdgeSort)
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum CliEdgeSort {
+    /// Edge ID
+    Id,
+    /// Source position, then target position
+    Position,
+    /// Core similarity, highest first
+    Similarity,
+    /// Aligned length, longest first
+    Length,
+    /// Alignment score, highest first
+    Score,
+}
+
+impl From<CliEdgeSort> for EdgeSort {
+    fn from(key: CliEdgeSort) -> Self {
+        match key {
+            CliEdgeSort::Id => EdgeSort::Id,
+            CliEdgeSort::Position => EdgeSort::Position,
+            CliEdgeSort::Similarity => EdgeSort::Similarity,
+            CliEdgeSort::Length => EdgeSort::Length,
+            CliEdgeSort::Score => EdgeSort::Score,
+        }
+    }
+}
+
+/// Candidate finding method for CLI (mirrors models::CandidateMethod)
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum CliCandidateMethod {
+    /// Windows sharing --min-shared-shingles n-gram shingles (default)
+    Shingles,
+    /// Windows sharing --min-shared-shingles winnowed fingerprints
+    Winnowing,
+    /// Windows whose MinHash signatures collide in an LSH band
+    Minhash,
+}
+
+/// Filtering shingle kind (CLI version, mirrors models::ShingleKind)
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum CliShingleKind {
+    /// Consecutive n-grams (default)
+    Contiguous,
+    /// N-grams with one inner token skipped, for paraphrase-tolerant filtering
+    Gapped,
+}
+
+impl From<CliShingleKind> for ShingleKind {
+    fn from(kind: CliShingleKind) -> Self {
+        match kind {
+            CliShingleKind::Contiguous => ShingleKind::Contiguous,
+            CliShingleKind::Gapped => ShingleKind::Gapped,
+        }
+    }
+}
+
+/// Shingle document frequency limit for --max-shingle-df
+#[derive(Clone, Copy, Debug)]
+enum CliShingleDf {
+    /// At most this many windows
+    Windows(usize),
+    /// At most this percentage of all windows
+    Percent(f32),
+}
+
+impl From<CliCandidateMethod> for CandidateMethod {
+    fn from(method: CliCandidateMethod) -> Self {
+        match method {
+            CliCandidateMethod::Shingles => CandidateMethod::Shingles,
+            CliCandidateMethod::Winnowing => CandidateMethod::Winnowing,
+            CliCandidateMethod::Minhash => CandidateMethod::MinHash,
+        }
+    }
+}
+
+/// Export header language for CLI (mirrors output::HeaderLanguage)
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum CliHeaderLanguage {
+    /// Column names as in the JSON output (default)
+    English,
+    /// Arabic column names
+    Arabic,
+    /// An English header row followed by an Arabic one
+    Bilingual,
+}
+
+impl From<CliHeaderLanguage> for HeaderLanguage {
+    fn from(language: CliHeaderLanguage) -> Self {
+        match language {
+            CliHeaderLanguage::English => HeaderLanguage::English,
+            CliHeaderLanguage::Arabic => HeaderLanguage::Arabic,
+            CliHeaderLanguage::Bilingual => HeaderLanguage::Bilingual,
+        }
+    }
+}
+
+/// Comparison units for CLI
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum CliSegmentation {
+    /// Overlapping fixed-size windows (default)
+    Windows,
+    /// Cut streams at isnad starts; one unit per hadith report
+    Hadith,
+}
+
+/// IDF weight source for CLI
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum CliWeightSource {
+    /// Each book's own lemma frequencies (default)
+    Document,
+    /// Lemma frequencies over the whole corpus (cached next to corpus.db)
+    Corpus,
+}
+
+impl From<CliWeightSource> for WeightSource {
+    fn from(source: CliWeightSource) -> Self {
+        match source {
+            CliWeightSource::Document => WeightSource::Document,
+            CliWeightSource::Corpus => WeightSource::Corpus,
+        }
+    }
+}
+
+/// Passage context boundary for CLI
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum CliContextBoundary {
+    /// Exactly --context-tokens on either side (default)
+    Fixed,
+    /// Grow context out to the nearest sentence end
+    Sentence,
+    /// Grow context out to the nearest page break
+    Page,
+}
+
+impl From<CliContextBoundary> for ContextBoundary {
+    fn from(boundary: CliContextBoundary) -> Self {
+        match boundary {
+            CliContextBoundary::Fixed => ContextBoundary::Fixed,
+            CliContextBoundary::Sentence => ContextBoundary::Sentence,
+            CliContextBoundary::Page => ContextBoundary::Page,
+        }
+    }
+}
+
+impl From<CliSegmentation> for Segmentation {
+    fn from(segmentation: CliSegmentation) -> Self {
+        match segmentation {
+            CliSegmentation::Windows => Segmentation::Windows,
+            CliSegmentation::Hadith => Segmentation::Hadith,
+        }
+    }
+}
+
+/// Comparison parameter flags shared by the comparison subcommands.
+#[derive(Args, Debug, Clone)]
+struct ParamArgs {
+    /// Run configuration (TOML): comparison parameters in its [params] table,
+    /// and any other flag of the subcommand as a top-level key. Flags given
+    /// on the command line override the file.
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    // === Parameters that inherit from ComparisonParams::default() ===
+    // All use Option<T> so we can detect "user didn't specify" vs "user set explicitly"
+
+    /// Window size in tokens [default: 275]
+    #[arg(long)]
+    window_size: Option<usize>,
+
+    /// Stride between windows [default: 60]
+    #[arg(long)]
+    stride: Option<usize>,
+
+    /// N-gram size for filtering [default: 5]
+    #[arg(long)]
+    ngram_size: Option<usize>,
+
+    /// Minimum shared shingles [default: 3]
+    #[arg(long)]
+    min_shared_shingles: Option<usize>,
+
+    /// Also require this Jaccard similarity between candidate windows' shingle sets
+    #[arg(long)]
+    min_shingle_jaccard: Option<f32>,
+
+    /// Minimum aligned length, counting substitutions [default: 10]
+    #[arg(long)]
+    min_length: Option<usize>,
+
+    /// Minimum number of matched tokens ("at least N shared words")
+    #[arg(long)]
+    min_matched_tokens: Option<usize>,
+
+    /// Minimum similarity ratio - legacy, prefer three-metric filters [default: 0.0]
+    #[arg(long)]
+    min_similarity: Option<f32>,
+
+    /// Match score for alignment [default: 2]
+    #[arg(long)]
+    match_score: Option<i32>,
+
+    /// Mismatch penalty for alignment [default: -1]
+    #[arg(long)]
+    mismatch_penalty: Option<i32>,
+
+    /// Gap penalty for alignment [default: -1]
+    #[arg(long)]
+    gap_penalty: Option<i32>,
+
+    /// Skip filtering, compare all pairs (slower but thorough)
+    #[arg(long)]
+    brute_force: bool,
+
+    /// How candidate window pairs are found [default: shingles]
+    #[arg(long, value_enum)]
+    candidate_method: Option<CliCandidateMethod>,
+
+    /// Shingles for candidate filtering: contiguous n-grams or gapped
+    /// skip-grams [default: contiguous]
+    #[arg(long, value_enum)]
+    shingle_kind: Option<CliShingleKind>,
+
+    /// Recount candidates' shared shingles without hashing, ruling out hash collisions
+    #[arg(long)]
+    exact_shingles: bool,
+
+    /// Keep at most this many candidate pairs per window, the ones sharing
+    /// the most shingles [default: no cap]
+    #[arg(long)]
+    max_candidates_per_window: Option<usize>,
+
+    /// Leave shingles found in more than N windows, or in more than X% of
+    /// all windows, out of the candidate index [default: keep all]
+    #[arg(long, value_name = "N|X%", value_parser = parse_shingle_df)]
+    max_shingle_df: Option<CliShingleDf>,
+
+    /// Matching mode for alignment [default: combined]
+    #[arg(long, value_enum)]
+    mode: Option<CliMatchMode>,
+
+    /// Matching mode for n-gram candidate filtering; root shingles raise recall [default: lemma]
+    #[arg(long, value_enum)]
+    filter_mode: Option<CliMatchMode>,
+
+    /// Score for lemma match (used in combined mode) [default: 2]
+    #[arg(long)]
+    lemma_score: Option<i32>,
+
+    /// Score for root-only match (same root, different lemma) [default: 1]
+    #[arg(long)]
+    root_score: Option<i32>,
+
+    /// Fail when root or combined mode meets a corpus.db without root data,
+    /// instead of falling back to lemma mode
+    #[arg(long)]
+    require_roots: bool,
+
+    /// Enable document-internal IDF weighting for alignment scoring [default: true]
+    #[arg(long, action = clap::ArgAction::Set)]
+    use_weights: Option<bool>,
+
+    /// Filter by weighted similarity (IDF-weighted informational density)
+    #[arg(long)]
+    min_weighted_similarity: Option<f32>,
+
+    /// Filter by core similarity (quotation exactness) [default: 0.85]
+    #[arg(long)]
+    min_core_similarity: Option<f32>,
+
+    /// Filter by span coverage (reuse vs padding) [default: 0.30]
+    #[arg(long)]
+    min_span_coverage: Option<f32>,
+
+    /// Filter by content weight (avg IDF of matched lemmas) [default: 1.10]
+    #[arg(long)]
+    min_content_weight: Option<f32>,
+
+    /// Filter by lexical diversity (unique lemmas / matches) [default: 0.55]
+    /// Low diversity indicates formulaic content like isnād phrases
+    #[arg(long)]
+    min_lexical_diversity: Option<f32>,
+
+    /// Filter by lemma-shingle Jaccard over the two matched spans
+    #[arg(long)]
+    min_span_jaccard: Option<f32>,
+
+    /// Core similarity required of verse edges (stricter than prose, since verse is quoted verbatim)
+    #[arg(long)]
+    min_verse_core_similarity: Option<f32>,
+
+    /// Drop edges tagged suspect (either span mostly digits or non-Arabic script)
+    #[arg(long)]
+    drop_suspect: bool,
+
+    /// Corpus book ID of the Quran; edges get the share of their text also found in it (quran_overlap)
+    #[arg(long = "quran-book")]
+    quran_book_id: Option<u32>,
+
+    /// Drop edges whose quran_overlap exceeds this fraction (shared Quranic quotation)
+    #[arg(long)]
+    max_quran_overlap: Option<f32>,
+
+    /// Drop edges whose longer span exceeds the shorter by more than this factor (abridgment/expansion)
+    #[arg(long)]
+    max_span_asymmetry: Option<f32>,
+
+    /// Only merge overlapping edges whose re-aligned span keeps this core similarity
+    #[arg(long)]
+    min_merge_core_similarity: Option<f32>,
+
+    /// Re-align merged spans so their statistics are exact rather than estimated
+    #[arg(long)]
+    refine_merges: bool,
+
+    /// Chain collinear edges up to this many tokens apart into macro-edges (long borrowings broken by commentary)
+    #[arg(long)]
+    chain_gap: Option<usize>,
+
+    /// Attach each book's coverage profile, counting reused tokens per N tokens or per page
+    #[arg(long, value_name = "N|page", value_parser = parse_coverage_bins)]
+    coverage_bins: Option<models::CoverageBins>,
+
+    /// Run a coarse pass with windows of this size first, then align only inside its regions
+    #[arg(long)]
+    coarse_window_size: Option<usize>,
+
+    /// Stride of the coarse pass [default: half the coarse window]
+    #[arg(long)]
+    coarse_stride: Option<usize>,
+
+    /// Disable all metric filters (for exploratory analysis)
+    #[arg(long)]
+    no_filters: bool,
+
+    /// Attach 95% confidence intervals to coverage using N page-bootstrap resamples
+    #[arg(long)]
+    bootstrap: Option<usize>,
+
+    /// Also match tokens whose alternative lemma analyses coincide
+    /// (reads the token_lemma_alternatives table)
+    #[arg(long)]
+    lemma_alternatives: bool,
+
+    /// Comparison units: fixed windows, or one unit per hadith report [default: windows]
+    #[arg(long, value_enum)]
+    segmentation: Option<CliSegmentation>,
+
+    /// Skip any candidate pair whose alignment matrix exceeds this many cells
+    #[arg(long)]
+    max_alignment_cells: Option<usize>,
+
+    /// Abandon any single alignment still running after this many milliseconds
+    #[arg(long)]
+    alignment_timeout_ms: Option<u64>,
+
+    /// Stop an alignment's fill once a whole DP row scores more than this far
+    /// below the best score so far (X-drop)
+    #[arg(long)]
+    xdrop: Option<u32>,
+
+    /// File of lemma IDs (one per line, `#` comments) to leave out of shingles
+    /// and score as neutral in alignment
+    #[arg(long, value_name = "PATH", value_parser = parse_stop_lemmas)]
+    stop_lemmas: Option<HashSet<u32>>,
+
+    /// IDF weights from each book or from the whole corpus [default: document]
+    #[arg(long, value_enum)]
+    weight_source: Option<CliWeightSource>,
+
+    /// Grow passage context past --context-tokens to the nearest sentence or
+    /// page boundary [default: fixed]
+    #[arg(long, value_enum)]
+    context_boundary: Option<CliContextBoundary>,
+
+    /// Most context tokens per side when growing to a boundary [default: 200]
+    #[arg(long)]
+    max_context_tokens: Option<usize>,
+}
+
+impl ParamArgs {
+    /// Build params by overlaying user-specified values onto the run
+    /// configuration's [params], or the library defaults without one.
+    fn to_params(&self) -> Result<ComparisonParams, config::ConfigError> {
+        // Start with library defaults (scholar-grade settings)
+        let defaults = match &self.config {
+            Some(path) => ComparisonParams::from_toml_file(path)?,
+            None => ComparisonParams::default(),
+        };
+
+        Ok(ComparisonParams {
+            window_size: self.window_size.unwrap_or(defaults.window_size),
+            stride: self.stride.unwrap_or(defaults.stride),
+            ngram_size: self.ngram_size.unwrap_or(defaults.ngram_size),
+            min_shared_shingles: self.min_shared_shingles.unwrap_or(defaults.min_shared_shingles),
+            min_shingle_jaccard: self.min_shingle_jaccard.or(defaults.min_shingle_jaccard),
+            min_length: self.min_length.unwrap_or(defaults.min_length),
+            min_matched_tokens: self.min_matched_tokens.or(defaults.min_matched_tokens),
+            min_similarity: self.min_similarity.unwrap_or(defaults.min_similarity),
+            match_score: self.match_score.unwrap_or(defaults.match_score),
+            mismatch_penalty: self.mismatch_penalty.unwrap_or(defaults.mismatch_penalty),
+            gap_penalty: self.gap_penalty.unwrap_or(defaults.gap_penalty),
+            brute_force: self.brute_force || defaults.brute_force,
+            candidate_method: self
+                .candidate_method
+                .map(CandidateMethod::from)
+                .unwrap_or(defaults.candidate_method),
+            shingle_kind: self.shingle_kind.map(ShingleKind::from).unwrap_or(defaults.shingle_kind),
+            exact_shingles: self.exact_shingles || defaults.exact_shingles,
+            max_candidates_per_window: self.max_candidates_per_window.or(defaults.max_candidates_per_window),
+            max_shingle_df: match self.max_shingle_df {
+                Some(CliShingleDf::Windows(windows)) => Some(windows),
+                _ => defaults.max_shingle_df,
+            },
+            max_shingle_df_ratio: match self.max_shingle_df {
+                Some(CliShingleDf::Percent(percent)) => Some(percent / 100.0),
+                _ => defaults.max_shingle_df_ratio,
+            },
+            mode: self.mode.map(MatchMode::from).unwrap_or(defaults.mode),
+            filter_mode: self.filter_mode.map(MatchMode::from).unwrap_or(defaults.filter_mode),
+            lemma_score: self.lemma_score.unwrap_or(defaults.lemma_score),
+            root_score: self.root_score.unwrap_or(defaults.root_score),
+            require_roots: self.require_roots || defaults.require_roots,
+            use_weights: self.use_weights.unwrap_or(defaults.use_weights),
+            min_weighted_similarity: self.min_weighted_similarity.or(defaults.min_weighted_similarity),
+            // Metric filters: no_filters is explicit in params, effective_* methods handle it
+            no_filters: self.no_filters || defaults.no_filters,
+            min_core_similarity: self.min_core_similarity.or(defaults.min_core_similarity),
+            min_span_coverage: self.min_span_coverage.or(defaults.min_span_coverage),
+            min_content_weight: self.min_content_weight.or(defaults.min_content_weight),
+            min_lexical_diversity: self.min_lexical_diversity.or(defaults.min_lexical_diversity),
+            min_span_jaccard: self.min_span_jaccard.or(defaults.min_span_jaccard),
+            min_verse_core_similarity: self
+                .min_verse_core_similarity
+                .or(defaults.min_verse_core_similarity),
+            drop_suspect: self.drop_suspect || defaults.drop_suspect,
+            quran_book_id: self.quran_book_id.or(defaults.quran_book_id),
+            max_quran_overlap: self.max_quran_overlap.or(defaults.max_quran_overlap),
+            max_span_asymmetry: self.max_span_asymmetry.or(defaults.max_span_asymmetry),
+            min_merge_core_similarity: self
+                .min_merge_core_similarity
+                .or(defaults.min_merge_core_similarity),
+            refine_merges: self.refine_merges || defaults.refine_merges,
+            chain_gap: self.chain_gap.or(defaults.chain_gap),
+            coverage_bins: self.coverage_bins.or(defaults.coverage_bins),
+            bootstrap_samples: self.bootstrap.or(defaults.bootstrap_samples),
+            use_lemma_alternatives: self.lemma_alternatives || defaults.use_lemma_alternatives,
+            source_range: defaults.source_range,
+            coarse_window_size: self.coarse_window_size.or(defaults.coarse_window_size),
+            coarse_stride: self.coarse_stride.or(defaults.coarse_stride),
+            segmentation: self.segmentation.map(Segmentation::from).unwrap_or(defaults.segmentation),
+            max_alignment_cells: self.max_alignment_cells.or(defaults.max_alignment_cells),
+            alignment_timeout_ms: self.alignment_timeout_ms.or(defaults.alignment_timeout_ms),
+            xdrop: self.xdrop.or(defaults.xdrop),
+            stop_lemmas: self.stop_lemmas.clone().or(defaults.stop_lemmas),
+            weight_source: self.weight_source.map(WeightSource::from).unwrap_or(defaults.weight_source),
+            context_boundary: self.context_boundary.map(ContextBoundary::from).unwrap_or(defaults.context_boundary),
+            max_context_tokens: self.max_context_tokens.or(defaults.max_context_tokens),
+        })
+    }
+}
+
+/// CSV dialect and column flags shared by the subcommands that write edge CSV.
+#[derive(Args, Debug, Clone)]
+struct CsvArgs {
+    /// CSV field delimiter: one ASCII character, or `tab` [default: ,]
+    #[arg(long, value_name = "CHAR", value_parser = parse_csv_delimiter)]
+    csv_delimiter: Option<u8>,
+
+    /// Start CSV files with a UTF-8 byte order mark, so Excel shows Arabic text correctly
+    #[arg(long)]
+    csv_bom: bool,
+
+    /// Header language of CSV with text and Excel output [default: english]
+    #[arg(long, value_enum)]
+    header_language: Option<CliHeaderLanguage>,
+
+    /// Comma-separated columns to write in CSV and JSON Lines output, e.g.
+    /// `id,core_similarity,source_text_matched` [default: all]
+    #[arg(long, value_name = "LIST", value_parser = parse_output_columns)]
+    columns: Option<OutputColumns>,
+}
+
+impl CsvArgs {
+    fn to_options(&self) -> CsvOptions {
+        let defaults = CsvOptions::default();
+        CsvOptions {
+            delimiter: self.csv_delimiter.unwrap_or(defaults.delimiter),
+            bom: self.csv_bom,
+            headers: self.header_language.map(HeaderLanguage::from).unwrap_or(defaults.headers),
+            columns: self.columns.clone().unwrap_or(defaults.columns),
+        }
+    }
+}
+
+/// Lemma dictionary and orthographic normalization flags shared by the
+/// subcommands that tokenize raw text.
+#[derive(Args, Debug, Clone)]
+struct LexiconArgs {
+    /// Dictionary CSV with surface,lemma,root columns (root may be empty)
+    #[arg(long, value_name = "CSV")]
+    dictionary: Option<PathBuf>,
+
+    /// Keep harakat, shadda, sukun and dagger alif
+    #[arg(long)]
+    keep_diacritics: bool,
+
+    /// Keep tatweel
+    #[arg(long)]
+    keep_tatweel: bool,
+
+    /// Keep hamza seats distinct (أ إ آ ٱ ؤ ئ)
+    #[arg(long)]
+    keep_hamza: bool,
+
+    /// Keep ta marbuta distinct from ha
+    #[arg(long)]
+    keep_ta_marbuta: bool,
+
+    /// Keep alif maqsura distinct from ya
+    #[arg(long)]
+    keep_alif_maqsura: bool,
+}
+
+impl LexiconArgs {
+    fn to_normalization(&self) -> NormalizationConfig {
+        NormalizationConfig {
+            strip_diacritics: !self.keep_diacritics,
+            remove_tatweel: !self.keep_tatweel,
+            normalize_hamza: !self.keep_hamza,
+            normalize_ta_marbuta: !self.keep_ta_marbuta,
+            normalize_alif_maqsura: !self.keep_alif_maqsura,
+        }
+    }
+
+    fn to_lexicon(&self) -> Result<Lexicon, db::import::ImportError> {
+        match &self.dictionary {
+            Some(path) => db::import::read_lexicon_file(path, self.to_normalization()),
+            None => Ok(Lexicon::new(self.to_normalization())),
         }
     }
 }
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     /// Compare two books for text reuse
     ///
@@ -80,15 +672,21 @@ enum Commands {
         #[arg(long)]
         book_a: u32,
 
-        /// Second book ID
-        #[arg(long)]
-        book_b: u32,
+        /// Target book ID(s): comma-separated or repeated. With several
+        /// targets, each result goes to <output stem>_<book_b>.<ext>
+        #[arg(long, value_delimiter = ',', required = true)]
+        book_b: Vec<u32>,
+
+        /// Only compare windows of book A overlapping this global token range
+        /// (GLOBAL_START:GLOBAL_END, end exclusive)
+        #[arg(long, value_parser = parse_token_range)]
+        source_range: Option<(usize, usize)>,
 
         /// Output file path (extension determines format, or use --format)
         #[arg(long)]
         output: PathBuf,
 
-        /// Output format: json, csv, or viewer (HTML with embedded React app)
+        /// Output format: json, csv, jsonl, or viewer (HTML with embedded React app)
         #[arg(long, value_enum, default_value = "json")]
         format: OutputFormat,
 
@@ -96,309 +694,1929 @@ enum Commands {
         #[arg(long)]
         csv: bool,
 
+        #[command(flatten)]
+        csv_options: CsvArgs,
+
         /// Include reconstructed Arabic text in output
         #[arg(long, default_value = "true")]
         include_text: bool,
 
+        /// Re-align every edge and record its path (match, root match,
+        /// substitution, gap per word) in `alignment.ops`; implies text output.
+        /// The viewer shades matched words by these ops
+        #[arg(long)]
+        include_alignment_ops: bool,
+
+        /// Book metadata spreadsheet (xlsx/xls/ods): fills in titles and death
+        /// dates and marks each edge's likely direction (earlier → later)
+        #[arg(long)]
+        metadata: Option<PathBuf>,
+
+        /// Strip reconstructed text and signatures from the output, keeping
+        /// offsets and metrics, for sharing; `rehydrate` restores the text
+        #[arg(long, conflicts_with = "audit_sample")]
+        redact_text: bool,
+
         /// Number of context tokens before/after each match
         #[arg(long, default_value = "30")]
         context_tokens: usize,
 
-        // === Parameters that inherit from ComparisonParams::default() ===
-        // All use Option<T> so we can detect "user didn't specify" vs "user set explicitly"
-
-        /// Window size in tokens [default: 275]
+        /// Query surface forms only for the tokens the output needs instead of
+        /// loading the full surface table (less memory when there are few edges)
         #[arg(long)]
-        window_size: Option<usize>,
+        targeted_surfaces: bool,
+
+        #[command(flatten)]
+        params: ParamArgs,
 
-        /// Stride between windows [default: 60]
+        /// Inline the viewer's libraries from this directory so the HTML
+        /// opens with no network access (see README)
+        #[arg(long, value_name = "DIR")]
+        offline_viewer: Option<PathBuf>,
+
+        /// Suppress progress output
         #[arg(long)]
-        stride: Option<usize>,
+        quiet: bool,
 
-        /// N-gram size for filtering [default: 5]
+        /// Print first N edges to console
         #[arg(long)]
-        ngram_size: Option<usize>,
+        show_edges: Option<usize>,
+
+        /// Order of --show-edges: comma-separated keys, most significant first
+        #[arg(long, value_enum, value_delimiter = ',', requires = "show_edges")]
+        edge_sort: Vec<CliEdgeSort>,
+
+        /// Group --show-edges under a heading per source page
+        #[arg(long, requires = "show_edges")]
+        group_by_page: bool,
+
+        /// Print --show-edges one line per edge
+        #[arg(long, requires = "show_edges")]
+        compact_edges: bool,
 
-        /// Minimum shared shingles [default: 3]
+        /// Write a stratified random sample of N edges to <output>.audit.json
+        /// (and <output>.audit.html with --format viewer) for manual review
         #[arg(long)]
-        min_shared_shingles: Option<usize>,
+        audit_sample: Option<usize>,
 
-        /// Minimum aligned length [default: 10]
+        /// Random seed for --audit-sample [default: 42]
         #[arg(long)]
-        min_length: Option<usize>,
+        audit_seed: Option<u64>,
 
-        /// Minimum similarity ratio - legacy, prefer three-metric filters [default: 0.0]
+        /// Check whether book B is a systematic abridgment of book A and write
+        /// the ordered section mapping to <output>.abridgment.json
         #[arg(long)]
-        min_similarity: Option<f32>,
+        abridgment_report: bool,
 
-        /// Match score for alignment [default: 2]
+        /// Write each page's edge count, covered tokens and average similarity,
+        /// for both books, to <output>.pages.csv
         #[arg(long)]
-        match_score: Option<i32>,
+        page_summary: bool,
 
-        /// Mismatch penalty for alignment [default: -1]
+        /// Re-align every edge and write its substituted lemma pairs, with a
+        /// frequency table over the whole result, to <output>.substitutions.json
+        #[arg(long, conflicts_with = "redact_text")]
+        substitution_report: bool,
+
+        /// Stop after candidate filtering and export the candidate pairs
+        /// (json or csv) instead of aligning them
+        #[arg(long, value_enum)]
+        stop_after: Option<StopAfter>,
+
+        /// Print window counts and estimated candidates, alignment cells and
+        /// memory for each pair, then exit without comparing
         #[arg(long)]
-        mismatch_penalty: Option<i32>,
+        dry_run: bool,
+
+        /// Developer aid: align these window pairs (SOURCE:TARGET window
+        /// indices, comma-separated), write dot-plots of their traceback paths
+        /// and the final edges to <output>.debug.html, and exit
+        #[arg(
+            long,
+            value_delimiter = ',',
+            value_parser = parse_window_pair,
+            conflicts_with_all = ["dry_run", "stop_after"]
+        )]
+        debug_alignments: Vec<(u32, u32)>,
+    },
 
-        /// Gap penalty for alignment [default: -1]
+    /// Compare two page ranges within the same book
+    ///
+    /// Finds reuse between two disjoint sections of one work, e.g. an
+    /// abridgment bound into the same codex as its source.
+    CompareRanges {
+        /// Path to corpus.db
         #[arg(long)]
-        gap_penalty: Option<i32>,
+        corpus_db: PathBuf,
 
-        /// Skip filtering, compare all pairs (slower but thorough)
+        /// Book ID
         #[arg(long)]
-        brute_force: bool,
+        book: u32,
 
-        /// Matching mode [default: combined]
-        #[arg(long, value_enum)]
-        mode: Option<CliMatchMode>,
+        /// First section, as PART:PAGE-PART:PAGE (inclusive)
+        #[arg(long, value_parser = parse_page_range)]
+        range_a: models::PageRange,
 
-        /// Score for lemma match (used in combined mode) [default: 2]
-        #[arg(long)]
-        lemma_score: Option<i32>,
+        /// Second section, as PART:PAGE-PART:PAGE (inclusive)
+        #[arg(long, value_parser = parse_page_range)]
+        range_b: models::PageRange,
 
-        /// Score for root-only match (same root, different lemma) [default: 1]
+        /// Output file path
         #[arg(long)]
-        root_score: Option<i32>,
+        output: PathBuf,
+
+        /// Output format: json, csv, jsonl, or viewer (HTML with embedded React app)
+        #[arg(long, value_enum, default_value = "json")]
+        format: OutputFormat,
+
+        #[command(flatten)]
+        csv_options: CsvArgs,
+
+        /// Number of context tokens before/after each match
+        #[arg(long, default_value = "30")]
+        context_tokens: usize,
+
+        #[command(flatten)]
+        params: ParamArgs,
 
-        /// Enable document-internal IDF weighting for alignment scoring [default: true]
-        #[arg(long, action = clap::ArgAction::Set)]
-        use_weights: Option<bool>,
+        /// Inline the viewer's libraries from this directory so the HTML
+        /// opens with no network access (see README)
+        #[arg(long, value_name = "DIR")]
+        offline_viewer: Option<PathBuf>,
 
-        /// Filter by weighted similarity (IDF-weighted informational density)
+        /// Suppress progress output
         #[arg(long)]
-        min_weighted_similarity: Option<f32>,
+        quiet: bool,
+    },
+
+    /// Compare two text files directly, without a corpus.db
+    ///
+    /// Both files (UTF-8, pages separated by form feeds) are tokenized and
+    /// compared as books 1 and 2. Without --dictionary, orthographically
+    /// normalized surface forms stand in for lemmas.
+    CompareFiles {
+        /// First text file
+        file_a: PathBuf,
+
+        /// Second text file
+        file_b: PathBuf,
 
-        /// Filter by core similarity (quotation exactness) [default: 0.85]
+        #[command(flatten)]
+        lexicon: LexiconArgs,
+
+        /// Output file path
         #[arg(long)]
-        min_core_similarity: Option<f32>,
+        output: PathBuf,
 
-        /// Filter by span coverage (reuse vs padding) [default: 0.30]
+        /// Output format: json, csv, jsonl, or viewer (HTML with embedded React app)
+        #[arg(long, value_enum, default_value = "json")]
+        format: OutputFormat,
+
+        #[command(flatten)]
+        csv_options: CsvArgs,
+
+        /// Number of context tokens before/after each match
+        #[arg(long, default_value = "30")]
+        context_tokens: usize,
+
+        #[command(flatten)]
+        params: ParamArgs,
+
+        /// Inline the viewer's libraries from this directory so the HTML
+        /// opens with no network access (see README)
+        #[arg(long, value_name = "DIR")]
+        offline_viewer: Option<PathBuf>,
+
+        /// Suppress progress output
         #[arg(long)]
-        min_span_coverage: Option<f32>,
+        quiet: bool,
+    },
 
-        /// Filter by content weight (avg IDF of matched lemmas) [default: 1.10]
+    /// Re-align widened regions around existing edges
+    ///
+    /// Reads an edge CSV (as written by `compare --csv`), re-aligns each edge
+    /// with one window of padding on both sides, and writes the edges back with
+    /// any that grew replaced by their full extent.
+    Expand {
+        /// Path to corpus.db
         #[arg(long)]
-        min_content_weight: Option<f32>,
+        corpus_db: PathBuf,
 
-        /// Filter by lexical diversity (unique lemmas / matches) [default: 0.55]
-        /// Low diversity indicates formulaic content like isnād phrases
+        /// Edge CSV to expand
         #[arg(long)]
-        min_lexical_diversity: Option<f32>,
+        input: PathBuf,
 
-        /// Disable all metric filters (for exploratory analysis)
+        /// Output CSV path
         #[arg(long)]
-        no_filters: bool,
+        output: PathBuf,
+
+        /// Only expand these edge IDs (comma-separated); others pass through
+        #[arg(long, value_delimiter = ',')]
+        edge: Vec<u64>,
+
+        /// Dialect of both the input and the output CSV
+        #[command(flatten)]
+        csv_options: CsvArgs,
+
+        #[command(flatten)]
+        params: ParamArgs,
 
         /// Suppress progress output
         #[arg(long)]
         quiet: bool,
-
-        /// Print first N edges to console
-        #[arg(long)]
-        show_edges: Option<usize>,
     },
 
-    /// Show corpus statistics
-    Stats {
+    /// Compare many books pairwise (corpus mode)
+    ///
+    /// Writes one JSON result per pair plus a manifest.json into the output
+    /// directory. Pairs below --min-prescan-overlap are skipped before any
+    /// alignment and recorded as skipped in the manifest.
+    ///
+    /// Books come from --books or a named --collection; with --vs-collection,
+    /// only pairs across the two sets are compared.
+    CompareAll {
         /// Path to corpus.db
         #[arg(long)]
         corpus_db: PathBuf,
-    },
 
-    /// Show book information
-    Info {
-        /// Path to corpus.db
+        /// Book IDs to compare (comma-separated); every unordered pair is compared
+        #[arg(long, value_delimiter = ',', required_unless_present = "collection", conflicts_with = "collection")]
+        books: Vec<u32>,
+
+        /// Compare the books of this collection (from the collections table) instead of --books
         #[arg(long)]
-        corpus_db: PathBuf,
+        collection: Option<String>,
 
-        /// Book ID
+        /// Only compare pairs with one book from this collection and one from --books/--collection
         #[arg(long)]
-        book_id: u32,
+        vs_collection: Option<String>,
 
-        /// Show individual pages
+        /// Path to book metadata spreadsheet (xlsx/xls/ods), for the author and century filters
         #[arg(long)]
-        show_pages: bool,
-    },
+        metadata: Option<PathBuf>,
 
-    /// Benchmark alignment performance
-    Benchmark {
-        /// Number of alignment iterations
-        #[arg(long, default_value = "1000")]
-        iterations: usize,
+        /// Skip pairs of two books by the same author (needs --metadata)
+        #[arg(long, requires = "metadata")]
+        exclude_same_author: bool,
 
-        /// Sequence size
-        #[arg(long, default_value = "275")]
-        size: usize,
-    },
-}
+        /// Only compare books at least N centuries apart (needs --metadata; undated books are skipped)
+        #[arg(long, value_name = "N", requires = "metadata")]
+        min_century_gap: Option<u32>,
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let cli = Cli::parse();
+        /// Output directory for per-pair results and manifest.json
+        #[arg(long)]
+        output_dir: PathBuf,
 
-    match cli.command {
-        Commands::Compare {
-            corpus_db,
-            book_a,
-            book_b,
-            output,
-            format,
-            csv,
-            include_text,
-            context_tokens,
-            window_size,
-            stride,
-            ngram_size,
-            min_shared_shingles,
-            min_length,
-            min_similarity,
-            match_score,
-            mismatch_penalty,
-            gap_penalty,
-            brute_force,
-            mode,
-            lemma_score,
-            root_score,
-            use_weights,
-            min_weighted_similarity,
-            min_core_similarity,
-            min_span_coverage,
-            min_content_weight,
-            min_lexical_diversity,
-            no_filters,
-            quiet,
-            show_edges,
-        } => {
-            // Start with library defaults (scholar-grade settings)
-            let defaults = ComparisonParams::default();
+        /// Skip pairs whose weighted vocabulary overlap is below this floor (0.0-1.0)
+        #[arg(long)]
+        min_prescan_overlap: Option<f32>,
 
-            // Build params by overlaying user-specified values onto defaults
-            let params = ComparisonParams {
-                window_size: window_size.unwrap_or(defaults.window_size),
-                stride: stride.unwrap_or(defaults.stride),
-                ngram_size: ngram_size.unwrap_or(defaults.ngram_size),
-                min_shared_shingles: min_shared_shingles.unwrap_or(defaults.min_shared_shingles),
-                min_length: min_length.unwrap_or(defaults.min_length),
-                min_similarity: min_similarity.unwrap_or(defaults.min_similarity),
-                match_score: match_score.unwrap_or(defaults.match_score),
-                mismatch_penalty: mismatch_penalty.unwrap_or(defaults.mismatch_penalty),
-                gap_penalty: gap_penalty.unwrap_or(defaults.gap_penalty),
-                brute_force,
-                mode: mode.map(MatchMode::from).unwrap_or(defaults.mode),
-                lemma_score: lemma_score.unwrap_or(defaults.lemma_score),
-                root_score: root_score.unwrap_or(defaults.root_score),
-                use_weights: use_weights.unwrap_or(defaults.use_weights),
-                min_weighted_similarity: min_weighted_similarity.or(defaults.min_weighted_similarity),
-                // Metric filters: no_filters is explicit in params, effective_* methods handle it
-                no_filters,
-                min_core_similarity: min_core_similarity.or(defaults.min_core_similarity),
-                min_span_coverage: min_span_coverage.or(defaults.min_span_coverage),
-                min_content_weight: min_content_weight.or(defaults.min_content_weight),
-                min_lexical_diversity: min_lexical_diversity.or(defaults.min_lexical_diversity),
-            };
+        /// Number of context tokens before/after each match
+        #[arg(long, default_value = "30")]
+        context_tokens: usize,
 
-            // Determine if we need text reconstruction
-            let need_text = include_text || matches!(format, OutputFormat::Viewer);
+        /// Recompare all pairs, ignoring results cached from an earlier run
+        #[arg(long)]
+        force: bool,
 
-            if need_text {
-                // Use enhanced comparison with text reconstruction
-                let result = compare::compare_books_with_text(
-                    book_a,
-                    book_b,
-                    &corpus_db,
-                    &params,
-                    context_tokens,
-                    !quiet,
-                )?;
+        /// Resume an interrupted run from its checkpoint
+        #[arg(long)]
+        resume: bool,
 
-                // Write output based on format
-                match format {
-                    OutputFormat::Json => {
-                        write_json_with_text_file(&result, &output)?;
-                    }
-                    OutputFormat::Csv => {
-                        write_csv_with_text_file(&result.edges, &output)?;
-                    }
-                    OutputFormat::Viewer => {
-                        let html_output = output.with_extension("html");
-                        write_viewer_html_file(&result, &html_output)?;
-                        if !quiet {
-                            eprintln!("Viewer output: {}", html_output.display());
-                        }
-                    }
-                }
+        /// Only compare shard INDEX of COUNT (e.g. 2/8), to split a run across machines
+        #[arg(long, value_parser = parse_shard)]
+        shard: Option<models::Shard>,
 
-                // Also output CSV if requested (and not already CSV format)
-                if csv && !matches!(format, OutputFormat::Csv) {
-                    let csv_path = output.with_extension("csv");
-                    write_csv_with_text_file(&result.edges, &csv_path)?;
-                    if !quiet {
-                        eprintln!("CSV output: {}", csv_path.display());
-                    }
-                }
+        #[command(flatten)]
+        params: ParamArgs,
 
-                // Print summary
-                if !quiet {
-                    print_summary_with_text(&result);
-                    eprintln!("\nOutput: {}", output.display());
-                }
+        /// Inline the viewer's libraries from this directory so the HTML
+        /// opens with no network access (see README)
+        #[arg(long, value_name = "DIR")]
+        offline_viewer: Option<PathBuf>,
 
-                // Show edges if requested
-                if let Some(limit) = show_edges {
-                    println!("\n=== Sample Edges ===");
-                    print_edges_with_text(&result.edges, Some(limit));
-                }
-            } else {
-                // Use standard comparison without text
-                let result = compare::compare_books(book_a, book_b, &corpus_db, &params, !quiet)?;
+        /// Suppress progress output
+        #[arg(long)]
+        quiet: bool,
+    },
 
-                // Write output
-                match format {
-                    OutputFormat::Json => {
-                        write_json_file(&result, &output)?;
-                    }
-                    OutputFormat::Csv => {
-                        write_csv_file(&result.edges, &output)?;
-                    }
-                    OutputFormat::Viewer => {
-                        // This shouldn't happen because need_text would be true
-                        eprintln!("Warning: Viewer format requires text. Falling back to JSON.");
-                        write_json_file(&result, &output)?;
-                    }
-                }
+    /// Compare all books of one author pairwise and report self-reuse
+    ///
+    /// Books are selected by author_id from the metadata spreadsheet. Per-pair
+    /// results and manifest.json are written as in compare-all, plus
+    /// author_<id>_report.json summarising the author's self-reuse.
+    CompareAuthor {
+        /// Path to corpus.db
+        #[arg(long)]
+        corpus_db: PathBuf,
 
-                // Write CSV if requested
-                if csv && !matches!(format, OutputFormat::Csv) {
-                    let csv_path = output.with_extension("csv");
-                    write_csv_file(&result.edges, &csv_path)?;
-                    if !quiet {
-                        eprintln!("CSV output: {}", csv_path.display());
-                    }
-                }
+        /// Path to book metadata spreadsheet (xlsx/xls/ods)
+        #[arg(long)]
+        metadata: PathBuf,
 
-                // Print summary
-                if !quiet {
-                    print_summary(&result);
-                    eprintln!("\nOutput: {}", output.display());
-                }
+        /// Author ID (as in the metadata author_id column)
+        #[arg(long)]
+        author_id: u32,
 
-                // Show edges if requested
-                if let Some(limit) = show_edges {
-                    println!("\n=== Sample Edges ===");
-                    print_edges(&result.edges, Some(limit));
-                }
-            }
-        }
+        /// Output directory for per-pair results, manifest and report
+        #[arg(long)]
+        output_dir: PathBuf,
 
-        Commands::Stats { corpus_db } => {
-            let stats = load_corpus_stats(&corpus_db)?;
+        /// Skip pairs whose weighted vocabulary overlap is below this floor (0.0-1.0)
+        #[arg(long)]
+        min_prescan_overlap: Option<f32>,
 
-            println!("=== Corpus Statistics ===");
-            println!("Total books: {}", stats.total_books);
-            println!("Total pages: {}", stats.total_pages);
-            println!("Total tokens: {}", stats.total_tokens);
-            println!("Unique lemmas: {}", stats.unique_lemmas);
-            println!("Unique roots: {}", stats.unique_roots);
-            println!("Token definitions: {}", stats.token_definitions);
-        }
+        /// Number of context tokens before/after each match
+        #[arg(long, default_value = "30")]
+        context_tokens: usize,
+
+        /// Recompare all pairs, ignoring results cached from an earlier run
+        #[arg(long)]
+        force: bool,
+
+        /// Resume an interrupted run from its checkpoint
+        #[arg(long)]
+        resume: bool,
+
+        /// Only compare shard INDEX of COUNT (e.g. 2/8), to split a run across machines
+        #[arg(long, value_parser = parse_shard)]
+        shard: Option<models::Shard>,
+
+        #[command(flatten)]
+        params: ParamArgs,
+
+        /// Inline the viewer's libraries from this directory so the HTML
+        /// opens with no network access (see README)
+        #[arg(long, value_name = "DIR")]
+        offline_viewer: Option<PathBuf>,
+
+        /// Suppress progress output
+        #[arg(long)]
+        quiet: bool,
+    },
+
+    /// Merge the output directories of a sharded compare-all or compare-author run
+    ///
+    /// Checks that every directory comes from the same run, then writes one
+    /// directory laid out like an unsharded run, plus corpus_summary.json and
+    /// network.csv (a weighted book-pair edge list).
+    Aggregate {
+        /// Shard output directories to merge
+        #[arg(required = true)]
+        dirs: Vec<PathBuf>,
+
+        /// Output directory for the merged results
+        #[arg(long)]
+        output_dir: PathBuf,
+
+        /// Suppress progress output
+        #[arg(long)]
+        quiet: bool,
+    },
+
+    /// Build a book-level reuse network from a directory of result files
+    ///
+    /// Every comparison result (JSON) under the directory, including batch
+    /// and aggregate output, adds its two books as nodes and, if they share
+    /// material, an edge weighted by aligned tokens and edge count.
+    Graph {
+        /// Directory of result files, searched recursively
+        #[arg(long)]
+        input_dir: PathBuf,
+
+        /// Output file
+        #[arg(long)]
+        output: PathBuf,
+
+        /// Graph file format
+        #[arg(long, value_enum, default_value = "graphml")]
+        format: GraphFormat,
+
+        /// Suppress progress output
+        #[arg(long)]
+        quiet: bool,
+    },
+
+    /// Find passages shared by several books across a directory of results
+    ///
+    /// Joins edges of different result files whose passages overlap in the
+    /// same book, and writes every passage with at least --min-witnesses
+    /// witnesses as JSON. Needs results with text.
+    Cluster {
+        /// Directory of result files, searched recursively
+        #[arg(long)]
+        input_dir: PathBuf,
+
+        /// Output JSON file
+        #[arg(long)]
+        output: PathBuf,
+
+        /// Fewest distinct books a reported passage must appear in
+        #[arg(long, default_value_t = 3)]
+        min_witnesses: usize,
+
+        /// Share of the shorter of two passages in one book that must overlap
+        /// to count them as the same passage
+        #[arg(long, default_value_t = 0.5)]
+        min_overlap: f32,
+
+        /// Suppress progress output
+        #[arg(long)]
+        quiet: bool,
+    },
+
+    /// Align the witnesses of one passage cluster word by word
+    ///
+    /// Reads a report written by `cluster` and aligns every member of the
+    /// chosen cluster against the others (progressive multiple alignment),
+    /// for collation of their readings.
+    Collate {
+        /// Path to corpus.db
+        #[arg(long)]
+        corpus_db: PathBuf,
+
+        /// Cluster report written by `cluster`
+        #[arg(long)]
+        clusters: PathBuf,
+
+        /// ID of the cluster to collate
+        #[arg(long)]
+        cluster: usize,
+
+        /// Output file
+        #[arg(long)]
+        output: PathBuf,
+
+        /// Output file format
+        #[arg(long, value_enum, default_value = "json")]
+        format: CollationFormat,
+
+        #[command(flatten)]
+        params: ParamArgs,
+
+        /// Suppress progress output
+        #[arg(long)]
+        quiet: bool,
+    },
+
+    /// Build an HTML dashboard over a directory of results with text
+    ///
+    /// Shows a matrix and a ranked list of the book pairs, each opening the
+    /// pair viewer of `compare --format viewer`.
+    Viewer {
+        /// Directory of result files, searched recursively
+        #[arg(long)]
+        input_dir: PathBuf,
+
+        /// Output HTML file
+        #[arg(long)]
+        output: PathBuf,
+
+        /// Inline the viewer's libraries from this directory so the HTML
+        /// opens with no network access (see README)
+        #[arg(long, value_name = "DIR")]
+        offline_viewer: Option<PathBuf>,
+
+        /// Suppress progress output
+        #[arg(long)]
+        quiet: bool,
+    },
+
+    /// Run compare-all, aggregate, graph and viewer from one config file
+    ///
+    /// The TOML file selects the books and sets the batch options and
+    /// comparison parameters; see the README for its keys. Everything is
+    /// written under its output_dir. Rerunning reuses every pair compared
+    /// before, including pairs finished by an interrupted run.
+    Pipeline {
+        /// Pipeline configuration (TOML)
+        #[arg(long)]
+        config: PathBuf,
+
+        /// Recompare every pair, overriding the config's `force`
+        #[arg(long)]
+        force: bool,
+
+        /// Suppress progress output
+        #[arg(long)]
+        quiet: bool,
+    },
+
+    /// Compare one book pair over a grid of parameters
+    ///
+    /// Runs the comparison once per combination of the values in the
+    /// configuration's [grid] table and, given gold edges, scores each run by
+    /// precision, recall and F1. See the README for the file format.
+    Sweep {
+        /// Sweep configuration (TOML)
+        #[arg(long)]
+        config: PathBuf,
+
+        /// Gold edges (result JSON or edge CSV), overriding the config's `gold`
+        #[arg(long)]
+        gold: Option<PathBuf>,
+
+        /// Output JSON report
+        #[arg(long)]
+        output: PathBuf,
+
+        /// Suppress progress output
+        #[arg(long)]
+        quiet: bool,
+    },
+
+    /// Keep only the edges accepted in the viewer's validation CSV
+    ///
+    /// Reads a result JSON (with or without text) and the CSV written by the
+    /// viewer's "Export Validated" button, and writes the result with only the
+    /// edges marked valid, each carrying its label, and a recomputed summary.
+    FilterValidated {
+        /// Result JSON the viewer was built from
+        #[arg(long)]
+        input: PathBuf,
+
+        /// Validation CSV exported from the viewer
+        #[arg(long)]
+        validations: PathBuf,
+
+        /// Output JSON path
+        #[arg(long)]
+        output: PathBuf,
+
+        /// Also keep edges without a label; only edges marked noise are dropped
+        #[arg(long)]
+        keep_unreviewed: bool,
+
+        /// Suppress progress output
+        #[arg(long)]
+        quiet: bool,
+    },
+
+    /// Restore the text of a result exported with `compare --redact-text`
+    ///
+    /// Text is rebuilt from corpus.db, which must hold the same content for
+    /// both books as the database the comparison ran on.
+    Rehydrate {
+        /// Path to corpus.db
+        #[arg(long)]
+        corpus_db: PathBuf,
+
+        /// Redacted result (JSON)
+        #[arg(long)]
+        input: PathBuf,
+
+        /// Output file path
+        #[arg(long)]
+        output: PathBuf,
+
+        /// Also write a self-contained HTML viewer next to the output
+        #[arg(long)]
+        viewer: bool,
+
+        /// Inline the viewer's libraries from this directory so the HTML
+        /// opens with no network access (see README)
+        #[arg(long, value_name = "DIR")]
+        offline_viewer: Option<PathBuf>,
+
+        /// Suppress progress output
+        #[arg(long)]
+        quiet: bool,
+    },
+
+    /// Show corpus statistics
+    Stats {
+        /// Path to corpus.db
+        #[arg(long)]
+        corpus_db: PathBuf,
+    },
+
+    /// Show book information
+    Info {
+        /// Path to corpus.db
+        #[arg(long)]
+        corpus_db: PathBuf,
+
+        /// Book ID
+        #[arg(long)]
+        book_id: u32,
+
+        /// Show individual pages
+        #[arg(long)]
+        show_pages: bool,
+    },
+
+    /// Build a corpus.db from OpenITI mARkdown text files
+    ///
+    /// Each file becomes one book, numbered from --first-book-id in the order
+    /// given. Lemmas are orthographically normalized surface forms; no roots.
+    #[command(name = "import-openiti")]
+    ImportOpenITI {
+        /// mARkdown files, one per book
+        #[arg(required = true, value_name = "FILE")]
+        files: Vec<PathBuf>,
+
+        #[command(flatten)]
+        lexicon: LexiconArgs,
+
+        /// Path of the corpus.db to create (must not exist)
+        #[arg(long)]
+        corpus_db: PathBuf,
+
+        /// Book ID of the first file
+        #[arg(long, default_value = "1")]
+        first_book_id: u32,
+
+        /// Suppress progress output
+        #[arg(long)]
+        quiet: bool,
+    },
+
+    /// Build a corpus.db from a directory of plain UTF-8 text files
+    ///
+    /// Each .txt file becomes one book, in file name order, numbered from
+    /// --first-book-id; form feeds separate pages. Lemmas and roots come from
+    /// --dictionary where it lists the surface form, otherwise the lemma is the
+    /// orthographically normalized surface form.
+    #[command(name = "import-text")]
+    ImportText {
+        /// Directory of .txt files, one per book
+        #[arg(value_name = "DIR")]
+        input_dir: PathBuf,
+
+        #[command(flatten)]
+        lexicon: LexiconArgs,
+
+        /// Path of the corpus.db to create (must not exist)
+        #[arg(long)]
+        corpus_db: PathBuf,
+
+        /// Book ID of the first file
+        #[arg(long, default_value = "1")]
+        first_book_id: u32,
+
+        /// Suppress progress output
+        #[arg(long)]
+        quiet: bool,
+    },
+
+    /// Benchmark alignment performance
+    Benchmark {
+        /// Number of alignment iterations
+        #[arg(long, default_value = "1000")]
+        iterations: usize,
+
+        /// Sequence size
+        #[arg(long, default_value = "275")]
+        size: usize,
+    },
+}
+
+/// Report the first failed argument check as a plain message.
+fn check_args<const N: usize>(checks: [Result<(), validate::ValidationError>; N]) -> Result<(), String> {
+    checks
+        .into_iter()
+        .collect::<Result<(), _>>()
+        .map_err(|e| format!("invalid arguments: {}", e))
+}
+
+/// Parse a `START:END` global token range.
+fn parse_token_range(s: &str) -> Result<(usize, usize), String> {
+    let (start, end) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected GLOBAL_START:GLOBAL_END, got `{}`", s))?;
+    let parse = |v: &str| v.trim().parse::<usize>().map_err(|e| format!("`{}`: {}", v, e));
+    Ok((parse(start)?, parse(end)?))
+}
+
+/// Parse a `SOURCE:TARGET` window index pair, e.g. `12:40`.
+/// Viewer libraries from the `--offline-viewer` directory, or the CDNs without one.
+fn viewer_assets(dir: Option<PathBuf>) -> Result<ViewerAssets, OutputError> {
+    dir.map_or(Ok(ViewerAssets::Cdn), |dir| ViewerAssets::from_dir(&dir))
+}
+
+fn parse_window_pair(s: &str) -> Result<(u32, u32), String> {
+    let (source, target) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected SOURCE:TARGET, got `{}`", s))?;
+    let parse = |v: &str| v.trim().parse::<u32>().map_err(|e| format!("`{}`: {}", v, e));
+    Ok((parse(source)?, parse(target)?))
+}
+
+/// Parse a shingle document frequency limit: a window count (`500`) or a
+/// percentage of all windows (`2%`).
+fn parse_shingle_df(s: &str) -> Result<CliShingleDf, String> {
+    let s = s.trim();
+    match s.strip_suffix('%') {
+        Some(percent) => percent
+            .trim()
+            .parse::<f32>()
+            .map(CliShingleDf::Percent)
+            .map_err(|e| format!("`{}`: {}", s, e)),
+        None => s.parse::<usize>().map(CliShingleDf::Windows).map_err(|e| format!("`{}`: {}", s, e)),
+    }
+}
+
+/// Parse coverage bins: a token count, or `page` for one bin per page.
+fn parse_coverage_bins(s: &str) -> Result<models::CoverageBins, String> {
+    match s.trim() {
+        "page" => Ok(models::CoverageBins::Pages),
+        n => n.parse::<usize>().map(models::CoverageBins::Tokens).map_err(|e| format!("`{}`: {}", s, e)),
+    }
+}
+
+/// Parse a `INDEX/COUNT` shard, e.g. `2/8`.
+fn parse_shard(s: &str) -> Result<models::Shard, String> {
+    let (index, count) = s
+        .split_once('/')
+        .ok_or_else(|| format!("expected INDEX/COUNT, got `{}`", s))?;
+    let parse = |v: &str| v.trim().parse::<u32>().map_err(|e| format!("`{}`: {}", v, e));
+    Ok(models::Shard {
+        index: parse(index)?,
+        count: parse(count)?,
+    })
+}
+
+/// Read a stop-lemma file: one lemma ID per line; blank lines and text after
+/// `#` are ignored.
+fn parse_stop_lemmas(path: &str) -> Result<HashSet<u32>, String> {
+    let contents = std::fs::read_to_string(path).map_err(|e| format!("`{}`: {}", path, e))?;
+    contents
+        .lines()
+        .enumerate()
+        .filter_map(|(n, line)| {
+            let id = line.split('#').next().unwrap_or("").trim();
+            (!id.is_empty()).then(|| {
+                id.parse::<u32>()
+                    .map_err(|e| format!("`{}` line {}: `{}`: {}", path, n + 1, id, e))
+            })
+        })
+        .collect()
+}
+
+/// Parse a CSV delimiter: `tab`, or a single ASCII character that can't be
+/// confused with quoting or line ends.
+fn parse_csv_delimiter(s: &str) -> Result<u8, String> {
+    match s {
+        "tab" | "\\t" | "\t" => Ok(b'\t'),
+        _ => match s.as_bytes() {
+            &[c] if c.is_ascii() && !matches!(c, b'"' | b'\r' | b'\n') => Ok(c),
+            _ => Err(format!("expected one ASCII character other than a quote or line end, or `tab`, got `{}`", s)),
+        },
+    }
+}
+
+/// Parse a comma-separated column list.
+fn parse_output_columns(s: &str) -> Result<OutputColumns, String> {
+    s.split(',')
+        .map(str::trim)
+        .try_fold(OutputColumns::default(), |columns, name| match name {
+            "" => Err(format!("empty column name in `{}`", s)),
+            _ => Ok(columns.column(name)),
+        })
+}
+
+/// Parse a `PART:PAGE-PART:PAGE` page range, or a single `PART:PAGE`.
+fn parse_page_range(s: &str) -> Result<models::PageRange, String> {
+    let parse_page = |v: &str| -> Result<(u32, u32), String> {
+        let (part, page) = v
+            .split_once(':')
+            .ok_or_else(|| format!("expected PART:PAGE, got `{}`", v))?;
+        let num = |n: &str| n.trim().parse::<u32>().map_err(|e| format!("`{}`: {}", n, e));
+        Ok((num(part)?, num(page)?))
+    };
+    let (start, end) = s.split_once('-').unwrap_or((s, s));
+    Ok(models::PageRange {
+        start: parse_page(start)?,
+        end: parse_page(end)?,
+    })
+}
+
+/// Write a substitution report next to `output` and print its totals.
+/// Write a single result in `format`; returns the path actually written.
+fn write_result_with_text(
+    result: &models::ComparisonResultWithText,
+    format: OutputFormat,
+    csv_options: &CsvOptions,
+    viewer_assets: &ViewerAssets,
+    output: PathBuf,
+) -> Result<PathBuf, OutputError> {
+    Ok(match format {
+        OutputFormat::Json => {
+            write_json_with_text_file(result, &output)?;
+            output
+        }
+        OutputFormat::Csv => {
+            write_result_csv_with_text_file(result, csv_options, &output)?;
+            output
+        }
+        OutputFormat::Viewer => {
+            let html_output = output.with_extension("html");
+            write_viewer_html_file(result, viewer_assets, &html_output)?;
+            html_output
+        }
+        OutputFormat::Jsonl => {
+            write_edges_jsonl_file_with_columns(&result.edges, &csv_options.columns, &output)?;
+            write_summary_file(&result.summary, &jsonl_summary_path(&output))?;
+            output
+        }
+        OutputFormat::Passim => {
+            write_passim_json_file(result, &output)?;
+            output
+        }
+        OutputFormat::Xlsx => {
+            let xlsx_output = output.with_extension("xlsx");
+            write_xlsx_file_with_headers(result, csv_options.headers, &xlsx_output)?;
+            xlsx_output
+        }
+    })
+}
+
+fn print_import_report(report: &db::import::ImportReport, corpus_db: &std::path::Path, has_dictionary: bool) {
+    println!(
+        "Imported {} books into {} ({} token definitions, {} lemmas, {} roots)",
+        report.books.len(),
+        corpus_db.display(),
+        report.token_definitions,
+        report.lemmas,
+        report.roots
+    );
+    if has_dictionary {
+        println!(
+            "Dictionary covered {} of {} token definitions",
+            report.lexicon_tokens, report.token_definitions
+        );
+    }
+}
+
+fn write_substitution_report(
+    report: &models::SubstitutionReport,
+    output: &std::path::Path,
+    quiet: bool,
+) -> Result<(), output::OutputError> {
+    let path = output.with_extension("substitutions.json");
+    write_substitution_report_file(report, &path)?;
+    if !quiet {
+        eprintln!(
+            "Substitutions: {} of {} aligned positions, {} distinct lemma pairs -> {}",
+            report.substitution_count,
+            report.aligned_positions,
+            report.table.len(),
+            path.display()
+        );
+        if !report.skipped_edges.is_empty() {
+            eprintln!(
+                "Warning: {} edges hit an alignment limit and were left out of the substitution report",
+                report.skipped_edges.len()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Write an abridgment report next to `output` and print its verdict.
+fn write_abridgment_report(
+    report: &models::AbridgmentReport,
+    output: &std::path::Path,
+    quiet: bool,
+) -> Result<(), output::OutputError> {
+    let path = output.with_extension("abridgment.json");
+    write_abridgment_report_file(report, &path)?;
+    if !quiet {
+        eprintln!(
+            "Abridgment: {} (A coverage {:.1}%, {:.1}% in order, span ratio {:.2}, {} sections) -> {}",
+            if report.is_abridgment { "yes" } else { "no" },
+            report.source_coverage * 100.0,
+            report.ordered_fraction * 100.0,
+            report.span_ratio,
+            report.sections.len(),
+            path.display()
+        );
+    }
+    Ok(())
+}
+
+/// Pages of both compared books, in reading order, for --page-summary.
+fn compared_pages(corpus_db: &std::path::Path, book_a: u32, book_b: u32) -> Result<Vec<models::PageInfo>, db::DbError> {
+    let mut pages = load_book_info(corpus_db, book_a)?.pages;
+    pages.extend(load_book_info(corpus_db, book_b)?.pages);
+    Ok(pages)
+}
+
+/// Index-only mode: write each target's candidate pairs without aligning them.
+fn export_candidates(
+    corpus_db: &std::path::Path,
+    book_a: u32,
+    targets: &[u32],
+    params: &ComparisonParams,
+    format: OutputFormat,
+    output_for: impl Fn(u32) -> PathBuf,
+    quiet: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    if matches!(format, OutputFormat::Viewer | OutputFormat::Jsonl | OutputFormat::Passim | OutputFormat::Xlsx) {
+        return Err("--stop-after filter writes json or csv".into());
+    }
+    if params.coarse_window_size.is_some() && !quiet {
+        eprintln!("Warning: --stop-after filter exports single-pass candidates; the coarse pass is skipped.");
+    }
+
+    let mut corpus = corpus::Corpus::open(corpus_db)?;
+    for &target in targets {
+        let set = corpus.candidates(book_a, target, params)?;
+        let output = output_for(target);
+        match format {
+            OutputFormat::Csv => write_candidates_csv_file(&set.candidates, &output)?,
+            _ => write_candidates_file(&set, &output)?,
+        }
+        if !quiet {
+            eprintln!(
+                "{} vs {}: {} candidate pairs ({} x {} windows) -> {}",
+                book_a,
+                target,
+                set.candidates.len(),
+                set.source_windows,
+                set.target_windows,
+                output.display()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// Print a comparison plan for `compare --dry-run`.
+fn print_plan(plan: &models::ComparisonPlan) {
+    println!("=== Plan: {} vs {} ===", plan.book_a, plan.book_b);
+    println!("Tokens: {} / {}", plan.tokens_a, plan.tokens_b);
+    println!("Windows: {} x {}", plan.windows_a, plan.windows_b);
+    println!(
+        "Estimated candidate pairs: {} (from {} sampled windows)",
+        plan.estimated_candidates, plan.sampled_windows
+    );
+    println!("Estimated alignment cells: {:.2e}", plan.estimated_alignment_cells as f64);
+    println!(
+        "Estimated peak memory: {:.1} MB",
+        plan.estimated_memory_bytes as f64 / (1024.0 * 1024.0)
+    );
+}
+
+/// Splice a `--config` run configuration into the command line, right after
+/// the subcommand, for each key whose flag is not given already.
+///
+/// Top-level keys name the subcommand's long flags (`book_a = 230` for
+/// `--book-a 230`): `true` sets a switch, and an array repeats the flag once
+/// per value. Relative paths are resolved against the file's directory. The
+/// `[params]` table is read by `ParamArgs::to_params`.
+fn with_run_config(args: Vec<OsString>) -> Result<Vec<OsString>, Box<dyn std::error::Error>> {
+    let Some(sub_index) = args.iter().skip(1).position(|a| !a.to_string_lossy().starts_with('-')).map(|i| i + 1) else {
+        return Ok(args);
+    };
+    let command = Cli::command();
+    let Some(subcommand) = command.find_subcommand(&args[sub_index]) else {
+        return Ok(args);
+    };
+    // Only the comparison subcommands take a run configuration; pipeline's
+    // --config is a format of its own
+    if !subcommand.get_groups().any(|group| Some(group.get_id()) == ParamArgs::group_id().as_ref()) {
+        return Ok(args);
+    }
+    let given: Vec<String> = args[sub_index + 1..].iter().map(|a| a.to_string_lossy().into_owned()).collect();
+    let config_path = given.iter().enumerate().find_map(|(i, arg)| match arg.strip_prefix("--config") {
+        Some("") => given.get(i + 1).cloned(),
+        Some(value) => value.strip_prefix('=').map(str::to_string),
+        None => None,
+    });
+    let Some(config_path) = config_path.map(PathBuf::from) else {
+        return Ok(args);
+    };
+
+    let table = config::read_toml_file(&config_path)?;
+    let base = config_path.parent().unwrap_or(std::path::Path::new(""));
+    let mut injected = Vec::new();
+    for (key, value) in table.iter().filter(|(key, _)| *key != "params") {
+        let long = key.replace('_', "-");
+        let flag = format!("--{}", long);
+        let arg = subcommand
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(long.as_str()))
+            .ok_or_else(|| format!("{}: unknown key `{}` for {}", config_path.display(), key, subcommand.get_name()))?;
+        if given.iter().any(|g| *g == flag || g.starts_with(&format!("{}=", flag))) {
+            continue;
+        }
+        if !arg.get_action().takes_values() {
+            match value {
+                toml::Value::Boolean(true) => injected.push(OsString::from(&flag)),
+                toml::Value::Boolean(false) => {}
+                _ => return Err(format!("{}: `{}` takes true or false", config_path.display(), key).into()),
+            }
+            continue;
+        }
+        let is_path = arg.get_value_parser().type_id() == std::any::TypeId::of::<PathBuf>()
+            || arg.get_value_names().is_some_and(|names| names.iter().any(|name| name == "PATH"));
+        let values = match value {
+            toml::Value::Array(items) => items.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            let value = match value {
+                toml::Value::String(s) if is_path => base.join(s).into_os_string(),
+                toml::Value::String(s) => s.into(),
+                toml::Value::Integer(n) => n.to_string().into(),
+                toml::Value::Float(x) => x.to_string().into(),
+                toml::Value::Boolean(b) => b.to_string().into(),
+                _ => return Err(format!("{}: `{}` takes a string, number or boolean", config_path.display(), key).into()),
+            };
+            injected.extend([OsString::from(&flag), value]);
+        }
+    }
+
+    let mut args = args;
+    args.splice(sub_index + 1..sub_index + 1, injected);
+    Ok(args)
+}
+
+fn main() {
+    if let Err(e) = run() {
+        eprintln!("Error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Parse the command line and run the chosen subcommand.
+fn run() -> Result<(), Box<dyn std::error::Error>> {
+    let cli = Cli::parse_from(with_run_config(std::env::args_os().collect())?);
+
+    match cli.command {
+        Commands::Compare {
+            corpus_db,
+            book_a,
+            book_b,
+            source_range,
+            output,
+            format,
+            csv,
+            csv_options,
+            include_text,
+            include_alignment_ops,
+            metadata,
+            redact_text,
+            context_tokens,
+            targeted_surfaces,
+            params,
+            offline_viewer,
+            quiet,
+            show_edges,
+            edge_sort,
+            group_by_page,
+            compact_edges,
+            audit_sample,
+            audit_seed,
+            abridgment_report,
+            page_summary,
+            substitution_report,
+            stop_after,
+            dry_run,
+            debug_alignments,
+        } => {
+            let csv_options = csv_options.to_options();
+            let params = ComparisonParams {
+                source_range,
+                ..params.to_params()?
+            };
+            check_args([
+                validate::validate_book_list(&book_b),
+                validate::validate_params(&params),
+                validate::validate_context_tokens(context_tokens),
+                validate::validate_output_path(&output),
+            ])?;
+            for &target in &book_b {
+                check_args([validate::validate_book_pair(book_a, target)])?;
+            }
+            if redact_text && matches!(format, OutputFormat::Viewer | OutputFormat::Passim) {
+                return Err("--redact-text cannot be used with --format viewer or passim".into());
+            }
+            let book_metadata = metadata.as_deref().map(metadata::load_book_metadata).transpose()?;
+            let viewer_assets = viewer_assets(offline_viewer)?;
+            // Substitution reports and alignment ops re-align edges against streams cached here
+            let mut realign_corpus = (substitution_report || include_alignment_ops)
+                .then(|| corpus::Corpus::open(&corpus_db))
+                .transpose()?;
+            let edge_print = EdgePrintOptions {
+                limit: show_edges,
+                sort: edge_sort.into_iter().map(EdgeSort::from).collect(),
+                group_by_page,
+                compact: compact_edges,
+            };
+
+            // With several targets, each gets its own output file
+            let multiple_targets = book_b.len() > 1;
+            let output_for = |target: u32| {
+                if multiple_targets {
+                    target_output_path(&output, target)
+                } else {
+                    output.clone()
+                }
+            };
+
+            if dry_run {
+                let mut corpus = corpus::Corpus::open(&corpus_db)?;
+                for &target in &book_b {
+                    print_plan(&corpus.plan(book_a, target, &params)?);
+                }
+                return Ok(());
+            }
+
+            if !debug_alignments.is_empty() {
+                let mut corpus = corpus::Corpus::open(&corpus_db)?;
+                for &target in &book_b {
+                    let trace = corpus.trace(book_a, target, &params, &debug_alignments)?;
+                    let debug_path = output_for(target).with_extension("debug.html");
+                    write_alignment_trace_html_file(&trace, &debug_path)?;
+                    if !quiet {
+                        eprintln!(
+                            "Alignment trace: {} window pairs, {} edges -> {}",
+                            trace.pairs.len(),
+                            trace.edges.len(),
+                            debug_path.display()
+                        );
+                    }
+                }
+                return Ok(());
+            }
+
+            if stop_after == Some(StopAfter::Filter) {
+                return export_candidates(&corpus_db, book_a, &book_b, &params, format, output_for, quiet);
+            }
+
+            // Determine if we need text reconstruction
+            // Hadith segmentation reads surface forms, and Quran tagging token
+            // streams, which only the text path loads; audit samples carry text
+            let need_text = include_text
+                || include_alignment_ops
+                || redact_text
+                || audit_sample.is_some()
+                || matches!(format, OutputFormat::Viewer | OutputFormat::Passim | OutputFormat::Xlsx)
+                || params.segmentation == Segmentation::Hadith
+                || params.quran_book_id.is_some();
+
+            if need_text {
+                // Use enhanced comparison with text reconstruction
+                let compare_targets = if targeted_surfaces {
+                    compare::compare_book_targets_with_targeted_text
+                } else {
+                    compare::compare_book_targets_with_text
+                };
+                compare_targets(
+                    book_a,
+                    &book_b,
+                    &corpus_db,
+                    &params,
+                    context_tokens,
+                    !quiet,
+                    |target, mut result| -> Result<(), Box<dyn std::error::Error>> {
+                        let output = output_for(target);
+                        if let Some(book_metadata) = &book_metadata {
+                            metadata::annotate_result_with_text(&mut result, book_metadata);
+                        }
+                        if let Some(corpus) = realign_corpus.as_mut().filter(|_| include_alignment_ops) {
+                            let missing = corpus.alignment_ops(&mut result)?;
+                            if missing > 0 && !quiet {
+                                eprintln!("Warning: {} edges hit an alignment limit and have no alignment ops", missing);
+                            }
+                        }
+                        if redact_text {
+                            redact::redact_result(&mut result, context_tokens);
+                        }
+
+                        // Write output based on format
+                        match format {
+                            OutputFormat::Json => {
+                                write_json_with_text_file(&result, &output)?;
+                            }
+                            OutputFormat::Csv => {
+                                write_result_csv_with_text_file(&result, &csv_options, &output)?;
+                            }
+                            OutputFormat::Viewer => {
+                                let html_output = output.with_extension("html");
+                                write_viewer_html_file(&result, &viewer_assets, &html_output)?;
+                                if !quiet {
+                                    eprintln!("Viewer output: {}", html_output.display());
+                                }
+                            }
+                            OutputFormat::Jsonl => {
+                                write_edges_jsonl_file_with_columns(&result.edges, &csv_options.columns, &output)?;
+                                write_summary_file(&result.summary, &jsonl_summary_path(&output))?;
+                            }
+                            OutputFormat::Passim => {
+                                write_passim_json_file(&result, &output)?;
+                            }
+                            OutputFormat::Xlsx => {
+                                let xlsx_output = output.with_extension("xlsx");
+                                write_xlsx_file_with_headers(&result, csv_options.headers, &xlsx_output)?;
+                                if !quiet {
+                                    eprintln!("Excel output: {}", xlsx_output.display());
+                                }
+                            }
+                        }
+
+                        // Also output CSV if requested (and not already CSV format)
+                        if csv && !matches!(format, OutputFormat::Csv) {
+                            let csv_path = output.with_extension("csv");
+                            write_result_csv_with_text_file(&result, &csv_options, &csv_path)?;
+                            if !quiet {
+                                eprintln!("CSV output: {}", csv_path.display());
+                            }
+                        }
+
+                        // Write audit sample if requested
+                        if let Some(n) = audit_sample {
+                            let seed = audit_seed.unwrap_or(audit::DEFAULT_AUDIT_SEED);
+                            let sample = audit::sample_edges_for_audit(&result, n, seed);
+                            let audit_path = output.with_extension("audit.json");
+                            write_audit_file(&sample, &audit_path)?;
+                            if !quiet {
+                                eprintln!(
+                                    "Audit sample: {} of {} edges -> {}",
+                                    sample.edges.len(),
+                                    sample.total_edges,
+                                    audit_path.display()
+                                );
+                            }
+
+                            if matches!(format, OutputFormat::Viewer) {
+                                let audit_result = models::ComparisonResultWithText {
+                                    version: result.version.clone(),
+                                    generated_at: result.generated_at.clone(),
+                                    parameters: result.parameters.clone(),
+                                    book_a: result.book_a.clone(),
+                                    book_b: result.book_b.clone(),
+                                    summary: result.summary.clone(),
+                                    edges: sample.edges,
+                                    macro_edges: Vec::new(),
+                                    coverage_profiles: Vec::new(),
+                                    run_info: result.run_info.clone(),
+                                    redaction: None,
+                                };
+                                let audit_html = output.with_extension("audit.html");
+                                write_viewer_html_file(&audit_result, &viewer_assets, &audit_html)?;
+                                if !quiet {
+                                    eprintln!("Audit viewer: {}", audit_html.display());
+                                }
+                            }
+                        }
+
+                        if abridgment_report {
+                            let report = abridge::detect_abridgment_with_text(&result);
+                            write_abridgment_report(&report, &output, quiet)?;
+                        }
+
+                        if page_summary {
+                            let path = output.with_extension("pages.csv");
+                            let pages = compared_pages(&corpus_db, book_a, target)?;
+                            write_page_summary_csv_with_text_file(&result.edges, &pages, &path)?;
+                            if !quiet {
+                                eprintln!("Page summary: {}", path.display());
+                            }
+                        }
+
+                        if let Some(corpus) = realign_corpus.as_mut().filter(|_| substitution_report) {
+                            let spans: Vec<_> = result.edges.iter().map(abridge::EdgeSpan::from).collect();
+                            let report = corpus.substitutions(book_a, target, &params, &spans)?;
+                            write_substitution_report(&report, &output, quiet)?;
+                        }
+
+                        // Print summary
+                        if !quiet {
+                            print_summary_with_text(&result);
+                            eprintln!("\nOutput: {}", output.display());
+                        }
+
+                        // Show edges if requested
+                        if show_edges.is_some() {
+                            println!("\n=== Sample Edges ===");
+                            print_edges_with_text_options(&result.edges, &edge_print);
+                        }
+                        Ok(())
+                    },
+                )?;
+            } else {
+                // Use standard comparison without text
+                compare::compare_book_targets(
+                    book_a,
+                    &book_b,
+                    &corpus_db,
+                    &params,
+                    !quiet,
+                    |target, mut result| -> Result<(), Box<dyn std::error::Error>> {
+                        let output = output_for(target);
+                        if let Some(book_metadata) = &book_metadata {
+                            metadata::annotate_result(&mut result, book_metadata);
+                        }
+
+                        // Write output
+                        match format {
+                            OutputFormat::Json => {
+                                write_json_file(&result, &output)?;
+                            }
+                            OutputFormat::Csv => {
+                                write_csv_file_with_options(&result.edges, &csv_options, &output)?;
+                            }
+                            OutputFormat::Viewer | OutputFormat::Passim | OutputFormat::Xlsx => {
+                                // This shouldn't happen because need_text would be true
+                                eprintln!("Warning: {:?} format requires text. Falling back to JSON.", format);
+                                write_json_file(&result, &output)?;
+                            }
+                            OutputFormat::Jsonl => {
+                                write_edges_jsonl_file_with_columns(&result.edges, &csv_options.columns, &output)?;
+                                write_summary_file(&result.summary, &jsonl_summary_path(&output))?;
+                            }
+                        }
+
+                        // Write CSV if requested
+                        if csv && !matches!(format, OutputFormat::Csv) {
+                            let csv_path = output.with_extension("csv");
+                            write_csv_file_with_options(&result.edges, &csv_options, &csv_path)?;
+                            if !quiet {
+                                eprintln!("CSV output: {}", csv_path.display());
+                            }
+                        }
+
+                        if abridgment_report {
+                            let report = abridge::detect_abridgment(&result);
+                            write_abridgment_report(&report, &output, quiet)?;
+                        }
+
+                        if page_summary {
+                            let path = output.with_extension("pages.csv");
+                            let pages = compared_pages(&corpus_db, book_a, target)?;
+                            write_page_summary_csv_file(&result.edges, &pages, &path)?;
+                            if !quiet {
+                                eprintln!("Page summary: {}", path.display());
+                            }
+                        }
+
+                        if let Some(corpus) = realign_corpus.as_mut().filter(|_| substitution_report) {
+                            let spans: Vec<_> = result.edges.iter().map(abridge::EdgeSpan::from).collect();
+                            let report = corpus.substitutions(book_a, target, &params, &spans)?;
+                            write_substitution_report(&report, &output, quiet)?;
+                        }
+
+                        // Print summary
+                        if !quiet {
+                            print_summary(&result);
+                            eprintln!("\nOutput: {}", output.display());
+                        }
+
+                        // Show edges if requested
+                        if show_edges.is_some() {
+                            println!("\n=== Sample Edges ===");
+                            print_edges_with_options(&result.edges, &edge_print);
+                        }
+                        Ok(())
+                    },
+                )?;
+            }
+        }
+
+        Commands::CompareRanges {
+            corpus_db,
+            book,
+            range_a,
+            range_b,
+            output,
+            format,
+            csv_options,
+            context_tokens,
+            params,
+            offline_viewer,
+            quiet,
+        } => {
+            let csv_options = csv_options.to_options();
+            let params = params.to_params()?;
+            check_args([
+                validate::validate_page_ranges(range_a, range_b),
+                validate::validate_params(&params),
+                validate::validate_context_tokens(context_tokens),
+                validate::validate_output_path(&output),
+            ])?;
+            let viewer_assets = viewer_assets(offline_viewer)?;
+
+            let result = compare::compare_ranges(
+                book,
+                range_a,
+                range_b,
+                &corpus_db,
+                &params,
+                context_tokens,
+                !quiet,
+            )?;
+
+            let output = write_result_with_text(&result, format, &csv_options, &viewer_assets, output)?;
+
+            if !quiet {
+                print_summary_with_text(&result);
+                eprintln!("\nOutput: {}", output.display());
+            }
+        }
+
+        Commands::CompareFiles {
+            file_a,
+            file_b,
+            lexicon,
+            output,
+            format,
+            csv_options,
+            context_tokens,
+            params,
+            offline_viewer,
+            quiet,
+        } => {
+            let csv_options = csv_options.to_options();
+            let params = params.to_params()?;
+            check_args([
+                validate::validate_params(&params),
+                validate::validate_context_tokens(context_tokens),
+                validate::validate_output_path(&output),
+            ])?;
+            let viewer_assets = viewer_assets(offline_viewer)?;
+
+            let read = |path: &PathBuf| std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e));
+            let (text_a, text_b) = (read(&file_a)?, read(&file_b)?);
+            let lexicon = lexicon.to_lexicon()?;
+            let result = compare::compare_texts(&text_a, &text_b, &lexicon, &params, context_tokens)?;
+
+            let output = write_result_with_text(&result, format, &csv_options, &viewer_assets, output)?;
+
+            if !quiet {
+                print_summary_with_text(&result);
+                eprintln!("\nOutput: {}", output.display());
+            }
+        }
+
+        Commands::Expand {
+            corpus_db,
+            input,
+            output,
+            edge,
+            csv_options,
+            params,
+            quiet,
+        } => {
+            let csv_options = csv_options.to_options();
+            let params = params.to_params()?;
+            check_args([
+                validate::validate_params(&params),
+                validate::validate_output_path(&output),
+            ])?;
+
+            let mut edges = read_csv_edges_file_with_options(&input, &csv_options)?;
+            let selected: Vec<usize> = (0..edges.len())
+                .filter(|&i| edge.is_empty() || edge.contains(&edges[i].id))
+                .collect();
+            let to_expand: Vec<models::ReuseEdge> = selected.iter().map(|&i| edges[i].clone()).collect();
+            let expanded = compare::expand_edges_from_db(&to_expand, &corpus_db, &params, !quiet)?;
+
+            let mut grown = 0;
+            for (i, expanded_edge) in selected.into_iter().zip(expanded) {
+                if expanded_edge.aligned_length > edges[i].aligned_length {
+                    grown += 1;
+                }
+                edges[i] = expanded_edge;
+            }
+            write_csv_file_with_options(&edges, &csv_options, &output)?;
+
+            if !quiet {
+                eprintln!("Expanded {} of {} edges", grown, to_expand.len());
+                eprintln!("Output: {}", output.display());
+            }
+        }
+
+        Commands::CompareAll {
+            corpus_db,
+            books,
+            collection,
+            vs_collection,
+            metadata,
+            exclude_same_author,
+            min_century_gap,
+            output_dir,
+            min_prescan_overlap,
+            context_tokens,
+            force,
+            resume,
+            shard,
+            params,
+            offline_viewer,
+            quiet,
+        } => {
+            let params = params.to_params()?;
+            let books = match &collection {
+                Some(name) => db::load_collection(&corpus_db, name)?,
+                None => books,
+            };
+            let vs_books = vs_collection
+                .as_deref()
+                .map(|name| db::load_collection(&corpus_db, name))
+                .transpose()?;
+            check_args([
+                validate::validate_book_list(&books),
+                validate::validate_params(&params),
+                validate::validate_context_tokens(context_tokens),
+                shard.map_or(Ok(()), validate::validate_shard),
+            ])?;
+            let options = batch::BatchOptions {
+                min_prescan_overlap,
+                context_tokens,
+                force,
+                resume,
+                shard,
+                viewer_assets: viewer_assets(offline_viewer)?,
+            };
+            let mut pairs = match &vs_books {
+                Some(vs_books) => batch::cross_pairs(&books, vs_books),
+                None => batch::all_pairs(&books),
+            };
+            let pair_filter = metadata::PairFilter {
+                exclude_same_author,
+                min_century_gap,
+            };
+            if let Some(path) = metadata.as_deref().filter(|_| pair_filter.is_active()) {
+                let book_metadata = metadata::load_book_metadata(path)?;
+                let before = pairs.len();
+                pairs = metadata::filter_pairs(&pairs, &book_metadata, &pair_filter);
+                if !quiet {
+                    eprintln!("Metadata filters: {} of {} pairs kept", pairs.len(), before);
+                }
+            }
+
+            let manifest = batch::compare_book_pairs(
+                &corpus_db,
+                &pairs,
+                &params,
+                &options,
+                &output_dir,
+                !quiet,
+            )?;
+
+            if !quiet {
+                let summary = aggregate::summarize_corpus(&manifest);
+                let cached = manifest.pairs.iter().filter(|p| p.cached).count();
+                eprintln!(
+                    "\nCompared {} pairs ({} skipped by prescan, {} reused from cache)",
+                    summary.compared_pairs,
+                    summary.skipped_pairs,
+                    cached
+                );
+                if summary.timeout_pairs > 0 {
+                    eprintln!("{} pairs had alignments abandoned (status timeout)", summary.timeout_pairs);
+                }
+                if summary.error_pairs > 0 {
+                    eprintln!(
+                        "Warning: {} pairs failed (status error); a rerun retries them",
+                        summary.error_pairs
+                    );
+                }
+                if let Some(shard) = manifest.shard {
+                    eprintln!("Shard {}/{} of {} pairs in total", shard.index, shard.count, pairs.len());
+                }
+                eprintln!("Manifest: {}", output_dir.join(batch::MANIFEST_FILE).display());
+            }
+        }
+
+        Commands::CompareAuthor {
+            corpus_db,
+            metadata,
+            author_id,
+            output_dir,
+            min_prescan_overlap,
+            context_tokens,
+            force,
+            resume,
+            shard,
+            params,
+            offline_viewer,
+            quiet,
+        } => {
+            let params = params.to_params()?;
+            check_args([
+                validate::validate_params(&params),
+                validate::validate_context_tokens(context_tokens),
+                shard.map_or(Ok(()), validate::validate_shard),
+            ])?;
+            let all_books = metadata::load_book_metadata(&metadata)?;
+            let book_ids = metadata::books_by_author(&all_books, author_id);
+            if book_ids.len() < 2 {
+                return Err(format!(
+                    "author {} has {} book(s) in {}; need at least 2",
+                    author_id,
+                    book_ids.len(),
+                    metadata.display()
+                )
+                .into());
+            }
+            let author_books: Vec<models::BookMetadata> = all_books
+                .into_iter()
+                .filter(|b| b.author_id == Some(author_id))
+                .collect();
+
+            let options = batch::BatchOptions {
+                min_prescan_overlap,
+                context_tokens,
+                force,
+                resume,
+                shard,
+                viewer_assets: viewer_assets(offline_viewer)?,
+            };
+            let manifest = batch::compare_book_pairs(
+                &corpus_db,
+                &batch::all_pairs(&book_ids),
+                &params,
+                &options,
+                &output_dir,
+                !quiet,
+            )?;
+
+            let report = author::build_author_report(author_id, &author_books, &manifest);
+            let report_path = output_dir.join(format!("author_{}_report.json", author_id));
+            write_author_report_file(&report, &report_path)?;
+
+            if !quiet {
+                eprintln!(
+                    "\nAuthor {}: {} books, {} of {} pairs share material ({} edges, {} aligned tokens)",
+                    author_id,
+                    report.book_count,
+                    report.pairs_with_reuse,
+                    report.pair_count,
+                    report.total_edges,
+                    report.total_aligned_tokens
+                );
+                eprintln!("Report: {}", report_path.display());
+            }
+        }
+
+        Commands::Graph {
+            input_dir,
+            output,
+            format,
+            quiet,
+        } => {
+            check_args([validate::validate_output_path(&output)])?;
+            let network = graph::load_network(&input_dir)?;
+            match format {
+                GraphFormat::Graphml => write_graphml_file(&network, &output)?,
+                GraphFormat::Gexf => write_gexf_file(&network, &output)?,
+            }
+            if !quiet {
+                eprintln!(
+                    "Network: {} books, {} pairs sharing material -> {}",
+                    network.nodes.len(),
+                    network.edges.len(),
+                    output.display()
+                );
+            }
+        }
+
+        Commands::Cluster {
+            input_dir,
+            output,
+            min_witnesses,
+            min_overlap,
+            quiet,
+        } => {
+            let options = analysis::ClusterOptions {
+                min_witnesses,
+                min_overlap,
+            };
+            check_args([
+                validate::validate_output_path(&output),
+                validate::validate_cluster_options(&options),
+            ])?;
+            let results = read_results_with_text_dir(&input_dir)?;
+            if results.is_empty() {
+                return Err(format!("no results with text under {}", input_dir.display()).into());
+            }
+            let report = analysis::cluster_edges(&results, &options);
+            write_cluster_report_file(&report, &output)?;
+            if !quiet {
+                eprintln!(
+                    "Clusters: {} passages with {}+ witnesses across {} results -> {}",
+                    report.clusters.len(),
+                    min_witnesses,
+                    results.len(),
+                    output.display()
+                );
+            }
+        }
+
+        Commands::Collate {
+            corpus_db,
+            clusters,
+            cluster,
+            output,
+            format,
+            params,
+            quiet,
+        } => {
+            let params = params.to_params()?;
+            check_args([
+                validate::validate_params(&params),
+                validate::validate_output_path(&output),
+            ])?;
+            let report = read_cluster_report_file(&clusters)?;
+            let Some(cluster) = report.clusters.iter().find(|c| c.id == cluster) else {
+                return Err(format!("no cluster {} in {}", cluster, clusters.display()).into());
+            };
+            let collation = corpus::Corpus::open(&corpus_db)?.collate(cluster, &params)?;
+            match format {
+                CollationFormat::Json => write_collation_file(&collation, &output)?,
+                CollationFormat::Html => write_collation_html_file(&collation, &output)?,
+            }
+            if !quiet {
+                eprintln!(
+                    "Collation: {} witnesses, {} columns ({} with variants) -> {}",
+                    collation.witnesses.len(),
+                    collation.columns.len(),
+                    collation.variant_columns,
+                    output.display()
+                );
+            }
+        }
+
+        Commands::Viewer {
+            input_dir,
+            output,
+            offline_viewer,
+            quiet,
+        } => {
+            check_args([validate::validate_output_path(&output)])?;
+            let results = read_results_with_text_dir(&input_dir)?;
+            if results.is_empty() {
+                return Err(format!("no results with text under {}", input_dir.display()).into());
+            }
+            write_corpus_viewer_html_file(&results, &viewer_assets(offline_viewer)?, &output)?;
+            if !quiet {
+                eprintln!("Dashboard: {} pairs -> {}", results.len(), output.display());
+            }
+        }
+
+        Commands::Aggregate { dirs, output_dir, quiet } => {
+            let (_, summary) = aggregate::aggregate_batches(&dirs, &output_dir)?;
+
+            if !quiet {
+                eprintln!(
+                    "Merged {} directories: {} pairs ({} skipped by prescan), {} share material ({} edges)",
+                    dirs.len(),
+                    summary.pair_count,
+                    summary.skipped_pairs,
+                    summary.pairs_with_reuse,
+                    summary.total_edges
+                );
+                if summary.error_pairs > 0 {
+                    eprintln!("Warning: {} pairs failed (status error)", summary.error_pairs);
+                }
+                if !summary.missing_shards.is_empty() {
+                    eprintln!(
+                        "Warning: missing shards {:?} of {}",
+                        summary.missing_shards,
+                        summary.shard_count.unwrap_or(0)
+                    );
+                }
+                eprintln!("Manifest: {}", output_dir.join(batch::MANIFEST_FILE).display());
+                eprintln!("Network: {}", output_dir.join(aggregate::NETWORK_FILE).display());
+            }
+        }
+
+        Commands::Pipeline { config, force, quiet } => {
+            let mut config = pipeline::PipelineConfig::from_file(&config)?;
+            config.force |= force;
+            let report = pipeline::run_pipeline(&config, !quiet)?;
+
+            if !quiet {
+                let summary = &report.summary;
+                eprintln!(
+                    "\nCompared {} pairs ({} skipped by prescan, {} reused from cache); {} share material ({} edges)",
+                    summary.compared_pairs,
+                    summary.skipped_pairs,
+                    report.cached_pairs,
+                    summary.pairs_with_reuse,
+                    summary.total_edges
+                );
+                if summary.error_pairs > 0 {
+                    eprintln!(
+                        "Warning: {} pairs failed (status error); a rerun retries them",
+                        summary.error_pairs
+                    );
+                }
+                for file in &report.files {
+                    eprintln!("Wrote {}", file.display());
+                }
+            }
+        }
+
+        Commands::Sweep {
+            config,
+            gold,
+            output,
+            quiet,
+        } => {
+            let mut config = sweep::SweepConfig::from_file(&config)?;
+            if gold.is_some() {
+                config.gold = gold;
+            }
+            check_args([validate::validate_output_path(&output)])?;
+            let gold_edges = config
+                .gold
+                .as_deref()
+                .map(|path| sweep::read_gold_edges(path, config.book_a, config.book_b))
+                .transpose()?;
+            if let Some(path) = config.gold.as_ref().filter(|_| gold_edges.as_ref().is_some_and(|g| g.is_empty())) {
+                eprintln!(
+                    "Warning: {} holds no edges between books {} and {}",
+                    path.display(),
+                    config.book_a,
+                    config.book_b
+                );
+            }
+
+            let mut corpus = corpus::Corpus::open(&config.corpus_db)?;
+            let report = sweep::run_sweep(&mut corpus, &config, gold_edges.as_deref(), !quiet)?;
+            write_sweep_report_file(&report, &output)?;
+            if !quiet {
+                print_sweep_report(&report);
+                eprintln!("\nOutput: {}", output.display());
+            }
+        }
+
+        Commands::FilterValidated {
+            input,
+            validations,
+            output,
+            keep_unreviewed,
+            quiet,
+        } => {
+            check_args([validate::validate_output_path(&output)])?;
+            let labels = read_validations_file(&validations)?;
+            let (kept, total) = match read_json_with_text_file(&input) {
+                Ok(result) => {
+                    let total = result.edges.len();
+                    let result = apply_validations_with_text(result, &labels, keep_unreviewed);
+                    write_json_with_text_file(&result, &output)?;
+                    (result.edges.len(), total)
+                }
+                // A result without text has no generated_at and flat edges
+                Err(OutputError::Json(_)) => {
+                    let result = read_json_file(&input)?;
+                    let total = result.edges.len();
+                    let result = apply_validations(result, &labels, keep_unreviewed);
+                    write_json_file(&result, &output)?;
+                    (result.edges.len(), total)
+                }
+                Err(e) => return Err(e.into()),
+            };
+            if !quiet {
+                eprintln!(
+                    "Kept {} of {} edges ({} labels read) -> {}",
+                    kept,
+                    total,
+                    labels.len(),
+                    output.display()
+                );
+            }
+        }
+
+        Commands::Rehydrate {
+            corpus_db,
+            input,
+            output,
+            viewer,
+            offline_viewer,
+            quiet,
+        } => {
+            let viewer_assets = viewer_assets(offline_viewer)?;
+            let mut result = read_json_with_text_file(&input)?;
+            redact::rehydrate_result(&mut result, &corpus_db)?;
+            write_json_with_text_file(&result, &output)?;
+            if viewer {
+                let html_output = output.with_extension("html");
+                write_viewer_html_file(&result, &viewer_assets, &html_output)?;
+                if !quiet {
+                    eprintln!("Viewer output: {}", html_output.display());
+                }
+            }
+            if !quiet {
+                eprintln!("Restored text for {} edges: {}", result.edges.len(), output.display());
+            }
+        }
+
+        Commands::Stats { corpus_db } => {
+            let stats = load_corpus_stats(&corpus_db)?;
+
+            println!("=== Corpus Statistics ===");
+            println!("Total books: {}", stats.total_books);
+            println!("Total pages: {}", stats.total_pages);
+            println!("Total tokens: {}", stats.total_tokens);
+            println!("Unique lemmas: {}", stats.unique_lemmas);
+            println!("Unique roots: {}", stats.unique_roots);
+            println!("Token definitions: {}", stats.token_definitions);
+        }
 
         Commands::Info {
             corpus_db,
@@ -432,6 +2650,46 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             }
         }
 
+        Commands::ImportOpenITI {
+            files,
+            lexicon,
+            corpus_db,
+            first_book_id,
+            quiet,
+        } => {
+            let has_dictionary = lexicon.dictionary.is_some();
+            let lexicon = lexicon.to_lexicon()?;
+            let report = db::import::import_openiti(
+                &files,
+                &lexicon,
+                &corpus_db,
+                first_book_id,
+                &tokenize::SimpleTokenizer,
+                !quiet,
+            )?;
+            print_import_report(&report, &corpus_db, has_dictionary);
+        }
+
+        Commands::ImportText {
+            input_dir,
+            lexicon,
+            corpus_db,
+            first_book_id,
+            quiet,
+        } => {
+            let has_dictionary = lexicon.dictionary.is_some();
+            let lexicon = lexicon.to_lexicon()?;
+            let report = db::import::import_text(
+                &input_dir,
+                &lexicon,
+                &corpus_db,
+                first_book_id,
+                &tokenize::SimpleTokenizer,
+                !quiet,
+            )?;
+            print_import_report(&report, &corpus_db, has_dictionary);
+        }
+
         Commands::Benchmark { iterations, size } => {
             run_benchmark(iterations, size);
         }
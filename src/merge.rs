@@ -3,13 +3,84 @@
 //! When windows overlap, the same text reuse can be detected multiple times.
 //! This module merges these overlapping detections into single, maximal spans.
 
-use crate::models::ReuseEdge;
+use std::collections::HashMap;
+
+use crate::metrics;
+use crate::models::{MacroEdge, ReuseEdge};
 
 /// Merge overlapping edges into maximal spans.
 ///
 /// Edges are considered overlapping if they involve the same book pair
 /// and their source/target regions overlap significantly.
-pub fn merge_overlapping_edges(mut edges: Vec<ReuseEdge>) -> Vec<ReuseEdge> {
+pub fn merge_overlapping_edges(edges: Vec<ReuseEdge>) -> Vec<ReuseEdge> {
+    merge_sorted(edges, edges_overlap, |a, b| Some(merge_two_edges(a, b)))
+}
+
+/// Merge overlapping edges, keeping only merges whose span re-aligns well.
+///
+/// `realign` aligns a merged span afresh. The merge goes ahead when the
+/// re-alignment still overlaps both edges and its core similarity is at
+/// least `min_core_similarity`; the re-aligned edge then replaces the pair.
+/// Otherwise the edges stay separate, so a strong quotation is not fused with
+/// a weak coincidence next to it.
+pub fn merge_overlapping_edges_gated<F>(
+    edges: Vec<ReuseEdge>,
+    min_core_similarity: f32,
+    realign: F,
+) -> Vec<ReuseEdge>
+where
+    F: Fn(&ReuseEdge) -> Option<ReuseEdge>,
+{
+    merge_sorted(edges, edges_overlap, |a, b| {
+        let realigned = realign(&merge_two_edges(a, b))?;
+        let holds_up = realigned.core_similarity >= min_core_similarity
+            && edges_overlap(&realigned, a)
+            && edges_overlap(&realigned, b);
+        holds_up.then_some(ReuseEdge { id: a.id, ..realigned })
+    })
+}
+
+/// Re-align the edges of `merged` that came out of a merge, replacing the
+/// statistics estimated by the merge with exact ones.
+///
+/// `raw` are the edges before merging; an edge whose ID and spans match one
+/// of them was never merged and is kept as is. `realign` aligns a merged span
+/// afresh against the original streams, and its edge replaces the merged one
+/// (keeping the merged ID). Edges that fail to re-align stay estimated.
+pub fn refine_merged_edges<F>(raw: &[ReuseEdge], merged: Vec<ReuseEdge>, realign: F) -> Vec<ReuseEdge>
+where
+    F: Fn(&ReuseEdge) -> Option<ReuseEdge>,
+{
+    let spans = |e: &ReuseEdge| {
+        (
+            e.source_global_start,
+            e.source_global_end,
+            e.target_global_start,
+            e.target_global_end,
+        )
+    };
+    let raw_spans: HashMap<u64, _> = raw.iter().map(|e| (e.id, spans(e))).collect();
+    merged
+        .into_iter()
+        .map(|edge| {
+            if raw_spans.get(&edge.id) == Some(&spans(&edge)) {
+                return edge;
+            }
+            match realign(&edge) {
+                Some(realigned) => ReuseEdge { id: edge.id, ..realigned },
+                None => edge,
+            }
+        })
+        .collect()
+}
+
+/// Sort edges by position and fold each into the previous one when
+/// `joinable` holds and `combine` accepts the pair.
+fn merge_sorted<J, C>(mut edges: Vec<ReuseEdge>, joinable: J, mut combine: C) -> Vec<ReuseEdge>
+where
+    J: Fn(&ReuseEdge, &ReuseEdge) -> bool,
+    C: FnMut(&ReuseEdge, &ReuseEdge) -> Option<ReuseEdge>,
+{
     if edges.len() <= 1 {
         return edges;
     }
@@ -27,19 +98,20 @@ pub fn merge_overlapping_edges(mut edges: Vec<ReuseEdge>) -> Vec<ReuseEdge> {
     let mut merged: Vec<ReuseEdge> = Vec::new();
 
     for edge in edges {
-        let should_merge = if let Some(last) = merged.last() {
-            last.source_book_id == edge.source_book_id
-                && last.target_book_id == edge.target_book_id
-                && edges_overlap(last, &edge)
-        } else {
-            false
+        let combined = match merged.last() {
+            Some(last)
+                if last.source_book_id == edge.source_book_id
+                    && last.target_book_id == edge.target_book_id
+                    && joinable(last, &edge) =>
+            {
+                combine(last, &edge)
+            }
+            _ => None,
         };
 
-        if should_merge {
-            let last = merged.last_mut().unwrap();
-            *last = merge_two_edges(last, &edge);
-        } else {
-            merged.push(edge);
+        match combined {
+            Some(combined) => *merged.last_mut().unwrap() = combined,
+            None => merged.push(edge),
         }
     }
 
@@ -147,35 +219,28 @@ fn merge_two_edges(a: &ReuseEdge, b: &ReuseEdge) -> ReuseEdge {
     let combined_gaps = a.gaps + b.gaps;
     let gaps = combined_gaps / 2; // Rough estimate
 
-    // Calculate three orthogonal metrics
-    let match_sub_total = lemma_matches + substitutions;
-    let core_similarity = if match_sub_total > 0 {
-        lemma_matches as f32 / match_sub_total as f32
+    // Score: sum of both, less the overlap's share at a's per-position rate
+    let overlap_score = (overlap_source as f32 * a.normalized_score) as i32;
+    let score = (a.score + b.score - overlap_score).max(a.score.max(b.score));
+    let normalized_score = if aligned_length > 0 {
+        score as f32 / aligned_length as f32
     } else {
         0.0
     };
 
-    let span_coverage = if aligned_length > 0 {
-        match_sub_total as f32 / aligned_length as f32
-    } else {
-        0.0
-    };
+    // Calculate three orthogonal metrics
+    let core_similarity = metrics::core_similarity(lemma_matches, substitutions);
+    let span_coverage = metrics::span_coverage(lemma_matches, substitutions, aligned_length);
 
     // Average content weight from both edges
     let content_weight = (a.content_weight + b.content_weight) / 2.0;
 
     // Legacy metrics
-    let lemma_similarity = if aligned_length > 0 {
-        lemma_matches as f32 / aligned_length as f32
-    } else {
-        0.0
-    };
+    let lemma_similarity = metrics::lemma_similarity(lemma_matches, aligned_length);
+    let combined_similarity = metrics::combined_similarity(lemma_matches, root_only_matches, aligned_length);
 
-    let combined_similarity = if aligned_length > 0 {
-        (lemma_matches as f32 + 0.5 * root_only_matches as f32) / aligned_length as f32
-    } else {
-        0.0
-    };
+    let source_span_length = (source_global_end - source_global_start) as u32;
+    let target_span_length = (target_global_end - target_global_start) as u32;
 
     ReuseEdge {
         id: a.id, // Keep the first edge's ID
@@ -203,12 +268,174 @@ fn merge_two_edges(a: &ReuseEdge, b: &ReuseEdge) -> ReuseEdge {
         content_weight,
         // For merged edges, average the diversity from both edges
         lexical_diversity: (a.lexical_diversity + b.lexical_diversity) / 2.0,
+        shingle_jaccard: (a.shingle_jaccard + b.shingle_jaccard) / 2.0,
+        is_verse: a.is_verse || b.is_verse,
+        suspect: a.suspect || b.suspect,
+        quran_overlap: a.quran_overlap.max(b.quran_overlap),
+        source_span_length,
+        target_span_length,
+        span_ratio: metrics::span_ratio(source_span_length, target_span_length),
+        direction: a.direction,
+        // A merged edge keeps a label only if both halves agree on it
+        validation: a.validation.filter(|&v| b.validation == Some(v)),
         lemma_similarity,
         combined_similarity,
         // For merged edges, we average the weighted metrics
         weighted_similarity: (a.weighted_similarity + b.weighted_similarity) / 2.0,
         avg_match_weight: content_weight,
+        score,
+        normalized_score,
+    }
+}
+
+/// Merge edges separated by small gaps into single spans.
+///
+/// Unlike `merge_overlapping_edges`, this also joins edges whose source and
+/// target ranges are disjoint but within `max_gap` tokens of each other.
+pub fn merge_adjacent_edges(edges: Vec<ReuseEdge>, max_gap: usize) -> Vec<ReuseEdge> {
+    merge_sorted(
+        edges,
+        |a, b| edges_adjacent(a, b, max_gap),
+        |a, b| Some(merge_two_edges(a, b)),
+    )
+}
+
+/// Check if two edges are within `max_gap` tokens of each other in both books.
+fn edges_adjacent(a: &ReuseEdge, b: &ReuseEdge, max_gap: usize) -> bool {
+    ranges_overlap(
+        a.source_global_start,
+        a.source_global_end + max_gap,
+        b.source_global_start,
+        b.source_global_end + max_gap,
+    ) && ranges_overlap(
+        a.target_global_start,
+        a.target_global_end + max_gap,
+        b.target_global_start,
+        b.target_global_end + max_gap,
+    )
+}
+
+/// Chain edges lying on roughly the same diagonal into macro-edges.
+///
+/// An edge extends a chain when it starts no earlier and ends later than the
+/// chain's last edge in both books, with at most `max_gap` tokens between
+/// them in each; of several chains it could extend, it joins the one it is
+/// closest to. Unlike [`merge_adjacent_edges`], the edges themselves are left
+/// alone. Only chains of two or more edges are returned, in source order.
+pub fn chain_collinear_edges(edges: &[ReuseEdge], max_gap: usize) -> Vec<MacroEdge> {
+    let mut order: Vec<&ReuseEdge> = edges.iter().collect();
+    order.sort_by_key(|e| {
+        (
+            e.source_book_id,
+            e.target_book_id,
+            e.source_global_start,
+            e.target_global_start,
+        )
+    });
+
+    let mut chains: Vec<Vec<&ReuseEdge>> = Vec::new();
+    // Chains the current edge might still reach
+    let mut open: Vec<usize> = Vec::new();
+    for edge in order {
+        open.retain(|&i| {
+            let last = chains[i].last().unwrap();
+            last.source_book_id == edge.source_book_id
+                && last.target_book_id == edge.target_book_id
+                && last.source_global_end + max_gap >= edge.source_global_start
+        });
+        let closest = open
+            .iter()
+            .copied()
+            .filter_map(|i| chain_gaps(chains[i].last().unwrap(), edge, max_gap).map(|(s, t)| (s + t, i)))
+            .min();
+        match closest {
+            Some((_, i)) => chains[i].push(edge),
+            None => {
+                open.push(chains.len());
+                chains.push(vec![edge]);
+            }
+        }
     }
+
+    chains
+        .into_iter()
+        .filter(|chain| chain.len() > 1)
+        .map(|chain| macro_edge(&chain))
+        .collect()
+}
+
+/// Source and target gaps from `a` to `b` when `b` continues `a` along the
+/// diagonal within `max_gap`.
+fn chain_gaps(a: &ReuseEdge, b: &ReuseEdge, max_gap: usize) -> Option<(usize, usize)> {
+    let forward = b.source_global_start >= a.source_global_start
+        && b.target_global_start >= a.target_global_start
+        && b.source_global_end > a.source_global_end
+        && b.target_global_end > a.target_global_end;
+    let source_gap = b.source_global_start.saturating_sub(a.source_global_end);
+    let target_gap = b.target_global_start.saturating_sub(a.target_global_end);
+    (forward && source_gap <= max_gap && target_gap <= max_gap).then_some((source_gap, target_gap))
+}
+
+fn macro_edge(chain: &[&ReuseEdge]) -> MacroEdge {
+    let (first, last) = (chain[0], chain[chain.len() - 1]);
+    let (source_gap_tokens, target_gap_tokens) = chain
+        .windows(2)
+        .map(|pair| {
+            (
+                pair[1].source_global_start.saturating_sub(pair[0].source_global_end),
+                pair[1].target_global_start.saturating_sub(pair[0].target_global_end),
+            )
+        })
+        .fold((0, 0), |(s, t), (ds, dt)| (s + ds, t + dt));
+    let lemma_matches = chain.iter().map(|e| e.lemma_matches).sum();
+    let substitutions = chain.iter().map(|e| e.substitutions).sum();
+
+    MacroEdge {
+        id: first.id,
+        source_book_id: first.source_book_id,
+        source_global_start: first.source_global_start,
+        source_global_end: last.source_global_end,
+        target_book_id: first.target_book_id,
+        target_global_start: first.target_global_start,
+        target_global_end: last.target_global_end,
+        edge_ids: chain.iter().map(|e| e.id).collect(),
+        source_gap_tokens,
+        target_gap_tokens,
+        lemma_matches,
+        substitutions,
+        core_similarity: metrics::core_similarity(lemma_matches, substitutions),
+    }
+}
+
+/// Remove edges whose source and target ranges are fully contained in another edge.
+pub fn remove_subsumed_edges(mut edges: Vec<ReuseEdge>) -> Vec<ReuseEdge> {
+    // Longest spans first so containers are seen before the edges they contain
+    edges.sort_by_key(|e| {
+        std::cmp::Reverse(
+            (e.source_global_end - e.source_global_start)
+                + (e.target_global_end - e.target_global_start),
+        )
+    });
+
+    let mut retained: Vec<ReuseEdge> = Vec::new();
+
+    for edge in edges {
+        let subsumed = retained.iter().any(|kept| {
+            kept.source_book_id == edge.source_book_id
+                && kept.target_book_id == edge.target_book_id
+                && kept.source_global_start <= edge.source_global_start
+                && kept.source_global_end >= edge.source_global_end
+                && kept.target_global_start <= edge.target_global_start
+                && kept.target_global_end >= edge.target_global_end
+        });
+
+        if !subsumed {
+            retained.push(edge);
+        }
+    }
+
+    retained.sort_by_key(|e| (e.source_global_start, e.target_global_start));
+    retained
 }
 
 /// Calculate the overlap size between two ranges.
@@ -216,11 +443,7 @@ fn calculate_overlap_size(start_a: usize, end_a: usize, start_b: usize, end_b: u
     let overlap_start = start_a.max(start_b);
     let overlap_end = end_a.min(end_b);
 
-    if overlap_start < overlap_end {
-        overlap_end - overlap_start
-    } else {
-        0
-    }
+    overlap_end.saturating_sub(overlap_start)
 }
 
 #[cfg(test)]
@@ -260,10 +483,21 @@ mod tests {
             span_coverage: 1.0,
             content_weight: 1.0,
             lexical_diversity: 1.0,
+            shingle_jaccard: 1.0,
+            is_verse: false,
+            suspect: false,
+            quran_overlap: 0.0,
+            source_span_length: (source_end - source_start) as u32,
+            target_span_length: (target_end - target_start) as u32,
+            span_ratio: (target_end - target_start) as f32 / (source_end - source_start) as f32,
+            direction: crate::models::Direction::Unknown,
+            validation: None,
             lemma_similarity: 1.0,
             combined_similarity: 1.0,
             weighted_similarity: 1.0,
             avg_match_weight: 1.0,
+            score: 2 * aligned_length as i32,
+            normalized_score: 2.0,
         }
     }
 
@@ -291,6 +525,96 @@ mod tests {
         assert_eq!(merged[0].source_global_end, 150);
     }
 
+    #[test]
+    fn test_merge_combines_score() {
+        let edges = vec![
+            create_edge(1, 0, 100, 0, 100),
+            create_edge(2, 50, 150, 50, 150),
+        ];
+
+        let merged = merge_overlapping_edges(edges);
+        assert_eq!(merged.len(), 1);
+        // 200 + 200 minus the 50-token overlap at 2.0 per token
+        assert_eq!(merged[0].score, 300);
+        assert!((merged[0].normalized_score - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_gated_merge_keeps_weak_neighbour_separate() {
+        let edges = vec![
+            create_edge(1, 0, 100, 0, 100),
+            create_edge(2, 50, 150, 50, 150),
+        ];
+
+        // The merged span re-aligns with mediocre core similarity
+        let mushy = |merged: &ReuseEdge| {
+            Some(ReuseEdge {
+                core_similarity: 0.6,
+                ..merged.clone()
+            })
+        };
+        let merged = merge_overlapping_edges_gated(edges.clone(), 0.8, mushy);
+        assert_eq!(merged.len(), 2);
+
+        // A clean re-alignment is accepted and keeps the first edge's ID
+        let clean = |merged: &ReuseEdge| {
+            Some(ReuseEdge {
+                id: 99,
+                core_similarity: 0.95,
+                ..merged.clone()
+            })
+        };
+        let merged = merge_overlapping_edges_gated(edges, 0.8, clean);
+        assert_eq!(merged.len(), 1);
+        assert_eq!(merged[0].id, 1);
+        assert_eq!(merged[0].source_global_end, 150);
+    }
+
+    #[test]
+    fn test_gated_merge_rejects_realignment_dropping_an_edge() {
+        let edges = vec![
+            create_edge(1, 0, 100, 0, 100),
+            create_edge(2, 90, 190, 90, 190),
+        ];
+
+        // Re-alignment settles on the first edge alone
+        let first_only = |merged: &ReuseEdge| {
+            Some(ReuseEdge {
+                source_global_end: 85,
+                target_global_end: 85,
+                core_similarity: 1.0,
+                ..merged.clone()
+            })
+        };
+        let merged = merge_overlapping_edges_gated(edges, 0.8, first_only);
+        assert_eq!(merged.len(), 2);
+    }
+
+    #[test]
+    fn test_refine_only_merged_edges() {
+        let raw = vec![
+            create_edge(1, 0, 100, 0, 100),
+            create_edge(2, 50, 150, 50, 150),
+            create_edge(3, 400, 500, 400, 500),
+        ];
+        let merged = merge_overlapping_edges(raw.clone());
+        assert_eq!(merged.len(), 2);
+
+        let exact = |edge: &ReuseEdge| {
+            Some(ReuseEdge {
+                id: 99,
+                lemma_matches: 140,
+                substitutions: 10,
+                ..edge.clone()
+            })
+        };
+        let refined = refine_merged_edges(&raw, merged, exact);
+        assert_eq!(refined.len(), 2);
+        assert_eq!((refined[0].id, refined[0].lemma_matches, refined[0].substitutions), (1, 140, 10));
+        // Edge 3 was never merged, so it keeps its own statistics
+        assert_eq!((refined[1].id, refined[1].lemma_matches), (3, 100));
+    }
+
     #[test]
     fn test_merge_multiple_overlapping() {
         let edges = vec![
@@ -338,6 +662,33 @@ mod tests {
         assert_eq!(merged.len(), 1);
     }
 
+    #[test]
+    fn test_chain_collinear_edges() {
+        let edges = vec![
+            create_edge(1, 0, 100, 0, 100),
+            // 30 tokens of commentary in the target
+            create_edge(2, 130, 200, 160, 230),
+            // A scattered quotation in between
+            create_edge(5, 150, 180, 900, 930),
+            create_edge(4, 220, 300, 250, 330),
+            create_edge(3, 500, 600, 50, 150),
+        ];
+
+        let chains = chain_collinear_edges(&edges, 80);
+        assert_eq!(chains.len(), 1);
+        let chain = &chains[0];
+        assert_eq!(chain.edge_ids, vec![1, 2, 4]);
+        assert_eq!((chain.source_global_start, chain.source_global_end), (0, 300));
+        assert_eq!((chain.target_global_start, chain.target_global_end), (0, 330));
+        assert_eq!((chain.source_gap_tokens, chain.target_gap_tokens), (50, 80));
+        assert_eq!(chain.lemma_matches, 250);
+
+        // A tighter gap leaves the commentary break unbridged
+        let chains = chain_collinear_edges(&edges, 40);
+        assert_eq!(chains.len(), 1);
+        assert_eq!(chains[0].edge_ids, vec![2, 4]);
+    }
+
     #[test]
     fn test_merge_adjacent() {
         let edges = vec![
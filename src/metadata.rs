@@ -0,0 +1,329 @@
+//! Book metadata loading from the corpus spreadsheet (xlsx/xls/ods).
+//!
+//! The first sheet is read with its first row as headers. Columns are matched
+//! by name (case-insensitive); only the book ID column is required.
+
+use calamine::{open_workbook_auto, Data, DataType, Reader};
+use std::path::Path;
+use thiserror::Error;
+
+use crate::models::{BookMetadata, ComparisonResult, ComparisonResultWithText, Direction};
+
+#[derive(Error, Debug)]
+pub enum MetadataError {
+    #[error("Spreadsheet error: {0}")]
+    Spreadsheet(#[from] calamine::Error),
+    #[error("Metadata file has no sheets")]
+    NoSheet,
+    #[error("Missing required column: {0}")]
+    MissingColumn(&'static str),
+}
+
+/// Column positions resolved from the header row.
+#[derive(Debug, Default)]
+struct Columns {
+    id: usize,
+    corpus: Option<usize>,
+    title: Option<usize>,
+    author_id: Option<usize>,
+    author: Option<usize>,
+    death_ah: Option<usize>,
+    century_ah: Option<usize>,
+    genre_id: Option<usize>,
+    page_count: Option<usize>,
+    token_count: Option<usize>,
+}
+
+impl Columns {
+    fn from_header(header: &[Data]) -> Result<Self, MetadataError> {
+        let names: Vec<String> = header
+            .iter()
+            .map(|cell| cell.as_string().unwrap_or_default().trim().to_lowercase())
+            .collect();
+        let find = |candidates: &[&str]| names.iter().position(|n| candidates.contains(&n.as_str()));
+
+        Ok(Columns {
+            id: find(&["book_id", "id"]).ok_or(MetadataError::MissingColumn("book_id"))?,
+            corpus: find(&["corpus"]),
+            title: find(&["title"]),
+            author_id: find(&["author_id"]),
+            author: find(&["author", "author_name"]),
+            death_ah: find(&["death_ah"]),
+            century_ah: find(&["century_ah"]),
+            genre_id: find(&["genre_id"]),
+            page_count: find(&["page_count"]),
+            token_count: find(&["token_count"]),
+        })
+    }
+}
+
+fn cell_i64(row: &[Data], col: Option<usize>) -> Option<i64> {
+    col.and_then(|c| row.get(c)).and_then(|cell| cell.as_i64())
+}
+
+fn cell_string(row: &[Data], col: Option<usize>) -> String {
+    col.and_then(|c| row.get(c))
+        .and_then(|cell| cell.as_string())
+        .unwrap_or_default()
+}
+
+/// Parse metadata rows (header first). Rows without a numeric book ID are skipped.
+fn parse_metadata_rows<'a, I>(mut rows: I) -> Result<Vec<BookMetadata>, MetadataError>
+where
+    I: Iterator<Item = &'a [Data]>,
+{
+    let header = match rows.next() {
+        Some(header) => header,
+        None => return Ok(Vec::new()),
+    };
+    let cols = Columns::from_header(header)?;
+
+    let mut books = Vec::new();
+    for row in rows {
+        let id = match cell_i64(row, Some(cols.id)) {
+            Some(id) => id as u32,
+            None => continue,
+        };
+
+        books.push(BookMetadata {
+            id,
+            corpus: cell_string(row, cols.corpus),
+            title: cell_string(row, cols.title),
+            author_id: cell_i64(row, cols.author_id).map(|v| v as u32),
+            author: cell_string(row, cols.author),
+            death_ah: cell_i64(row, cols.death_ah).map(|v| v as u32),
+            century_ah: cell_i64(row, cols.century_ah).map(|v| v as u8),
+            genre_id: cell_i64(row, cols.genre_id).map(|v| v as u32),
+            page_count: cell_i64(row, cols.page_count).unwrap_or(0) as u32,
+            token_count: cell_i64(row, cols.token_count).unwrap_or(0) as u64,
+            ..Default::default()
+        });
+    }
+
+    Ok(books)
+}
+
+/// Load book metadata from the first sheet of a spreadsheet.
+pub fn load_book_metadata(path: &Path) -> Result<Vec<BookMetadata>, MetadataError> {
+    let mut workbook = open_workbook_auto(path)?;
+    let range = workbook.worksheet_range_at(0).ok_or(MetadataError::NoSheet)??;
+    parse_metadata_rows(range.rows())
+}
+
+/// IDs of all books by the given author, in ascending order.
+pub fn books_by_author(metadata: &[BookMetadata], author_id: u32) -> Vec<u32> {
+    let mut ids: Vec<u32> = metadata
+        .iter()
+        .filter(|b| b.author_id == Some(author_id))
+        .map(|b| b.id)
+        .collect();
+    ids.sort_unstable();
+    ids.dedup();
+    ids
+}
+
+/// Metadata entry of a book, if the spreadsheet lists it.
+fn find_book(metadata: &[BookMetadata], book_id: u32) -> Option<&BookMetadata> {
+    metadata.iter().find(|b| b.id == book_id)
+}
+
+/// Direction of reuse between two books by their metadata (Unknown if
+/// either is not listed).
+pub fn pair_direction(metadata: &[BookMetadata], book_a: u32, book_b: u32) -> Direction {
+    match (find_book(metadata, book_a), find_book(metadata, book_b)) {
+        (Some(a), Some(b)) => Direction::between(a, b),
+        _ => Direction::Unknown,
+    }
+}
+
+/// Metadata conditions a batch pair must meet to be scheduled.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct PairFilter {
+    /// Drop pairs of two books by the same author
+    pub exclude_same_author: bool,
+    /// Keep only pairs whose books are at least this many centuries apart
+    pub min_century_gap: Option<u32>,
+}
+
+impl PairFilter {
+    /// Whether any condition is set.
+    pub fn is_active(&self) -> bool {
+        self.exclude_same_author || self.min_century_gap.is_some()
+    }
+
+    /// Whether the pair passes. Books missing from the spreadsheet or without
+    /// an author_id are never the same author; a pair whose century gap can't
+    /// be told fails `min_century_gap`.
+    pub fn keeps(&self, metadata: &[BookMetadata], book_a: u32, book_b: u32) -> bool {
+        let (a, b) = (find_book(metadata, book_a), find_book(metadata, book_b));
+        if self.exclude_same_author {
+            let author = |book: Option<&BookMetadata>| book.and_then(|b| b.author_id);
+            if author(a).is_some() && author(a) == author(b) {
+                return false;
+            }
+        }
+        match self.min_century_gap {
+            Some(gap) => match (a.and_then(century), b.and_then(century)) {
+                (Some(a), Some(b)) => a.abs_diff(b) >= gap,
+                _ => false,
+            },
+            None => true,
+        }
+    }
+}
+
+/// Century (AH) of a book: the century_ah column, else derived from death_ah.
+fn century(book: &BookMetadata) -> Option<u32> {
+    book.century_ah
+        .map(u32::from)
+        .or_else(|| book.death_ah.map(|d| d.saturating_sub(1) / 100 + 1))
+}
+
+/// The pairs that pass `filter`, in their original order.
+pub fn filter_pairs(pairs: &[(u32, u32)], metadata: &[BookMetadata], filter: &PairFilter) -> Vec<(u32, u32)> {
+    pairs
+        .iter()
+        .copied()
+        .filter(|&(a, b)| filter.keeps(metadata, a, b))
+        .collect()
+}
+
+/// Fill in a result's book details from the spreadsheet and set the
+/// direction of every edge. Counts and stream hashes come from the comparison
+/// and are kept; books missing from the spreadsheet are left as they are.
+pub fn annotate_result(result: &mut ComparisonResult, metadata: &[BookMetadata]) {
+    for book in [&mut result.book_a, &mut result.book_b] {
+        if let Some(meta) = find_book(metadata, book.id) {
+            *book = BookMetadata {
+                id: book.id,
+                page_count: book.page_count,
+                token_count: book.token_count,
+                stream_hash: std::mem::take(&mut book.stream_hash),
+                ..meta.clone()
+            };
+        }
+    }
+    let direction = pair_direction(metadata, result.book_a.id, result.book_b.id);
+    for edge in &mut result.edges {
+        edge.direction = direction;
+    }
+}
+
+/// [`annotate_result`] for a result with text.
+pub fn annotate_result_with_text(result: &mut ComparisonResultWithText, metadata: &[BookMetadata]) {
+    for book in [&mut result.book_a, &mut result.book_b] {
+        if let Some(meta) = find_book(metadata, book.id) {
+            book.title = meta.title.clone();
+            book.author = meta.author.clone();
+            book.death_ah = meta.death_ah;
+        }
+    }
+    let direction = pair_direction(metadata, result.book_a.id, result.book_b.id);
+    for edge in &mut result.edges {
+        edge.direction = direction;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s(v: &str) -> Data {
+        Data::String(v.to_string())
+    }
+
+    #[test]
+    fn test_parse_metadata_rows() {
+        let rows = [
+            vec![s("Book_ID"), s("Title"), s("author_id"), s("death_ah"), s("Author")],
+            vec![Data::Float(230.0), s("Kitab A"), Data::Float(7.0), Data::Int(505), s("al-Shafi'i")],
+            vec![Data::Empty, s("no id"), Data::Float(7.0), Data::Empty, Data::Empty],
+            vec![Data::Int(553), s("Kitab B"), Data::Empty, Data::Empty, Data::Empty],
+        ];
+
+        let books = parse_metadata_rows(rows.iter().map(|r| r.as_slice())).unwrap();
+        assert_eq!(books.len(), 2);
+        assert_eq!(books[0].id, 230);
+        assert_eq!(books[0].title, "Kitab A");
+        assert_eq!(books[0].author_id, Some(7));
+        assert_eq!(books[0].death_ah, Some(505));
+        assert_eq!(books[0].author, "al-Shafi'i");
+        assert_eq!(books[1].author_id, None);
+        assert_eq!(books[1].author, "");
+    }
+
+    #[test]
+    fn test_missing_id_column() {
+        let rows = [vec![s("title")]];
+        let err = parse_metadata_rows(rows.iter().map(|r| r.as_slice()));
+        assert!(matches!(err, Err(MetadataError::MissingColumn(_))));
+    }
+
+    #[test]
+    fn test_books_by_author() {
+        let book = |id, author_id| BookMetadata {
+            id,
+            author_id,
+            ..Default::default()
+        };
+        let metadata = vec![book(9, Some(1)), book(3, Some(1)), book(5, Some(2)), book(4, None)];
+        assert_eq!(books_by_author(&metadata, 1), vec![3, 9]);
+        assert!(books_by_author(&metadata, 3).is_empty());
+    }
+
+    #[test]
+    fn test_pair_direction() {
+        let book = |id, death_ah, century_ah| BookMetadata {
+            id,
+            death_ah,
+            century_ah,
+            ..Default::default()
+        };
+        let metadata = vec![
+            book(1, Some(505), Some(6)),
+            book(2, Some(728), Some(8)),
+            book(3, None, Some(8)),
+            book(4, None, None),
+        ];
+        assert_eq!(pair_direction(&metadata, 1, 2), Direction::AtoB);
+        assert_eq!(pair_direction(&metadata, 2, 1), Direction::BtoA);
+        // Without a death year, centuries decide; the same century does not
+        assert_eq!(pair_direction(&metadata, 1, 3), Direction::AtoB);
+        assert_eq!(pair_direction(&metadata, 2, 3), Direction::Unknown);
+        assert_eq!(pair_direction(&metadata, 1, 4), Direction::Unknown);
+        assert_eq!(pair_direction(&metadata, 1, 99), Direction::Unknown);
+    }
+
+    #[test]
+    fn test_filter_pairs() {
+        let book = |id, author_id, death_ah, century_ah| BookMetadata {
+            id,
+            author_id,
+            death_ah,
+            century_ah,
+            ..Default::default()
+        };
+        let metadata = vec![
+            book(1, Some(7), Some(224), None),
+            book(2, Some(7), Some(276), Some(3)),
+            book(3, Some(9), None, Some(8)),
+            book(4, None, None, None),
+        ];
+        let pairs = [(1, 2), (1, 3), (1, 4), (2, 3), (3, 5)];
+
+        let same_author = PairFilter {
+            exclude_same_author: true,
+            ..Default::default()
+        };
+        assert_eq!(filter_pairs(&pairs, &metadata, &same_author), vec![(1, 3), (1, 4), (2, 3), (3, 5)]);
+
+        // 224 AH falls in the 3rd century; undated and unlisted books fail a gap
+        let gap = PairFilter {
+            min_century_gap: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(filter_pairs(&pairs, &metadata, &gap), vec![(1, 3), (2, 3)]);
+        assert!(!PairFilter::default().is_active());
+        assert_eq!(filter_pairs(&pairs, &metadata, &PairFilter::default()), pairs.to_vec());
+    }
+}
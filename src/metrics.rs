@@ -0,0 +1,151 @@
+//! Edge metric formulas.
+//!
+//! Every edge carries the same metrics whether it came straight from an
+//! alignment, from merging two edges, or from an external aligner. The
+//! formulas live here so all of those paths share one definition.
+
+use crate::models::Alignment;
+
+/// Metrics derived from an alignment's counts.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeMetrics {
+    pub core_similarity: f32,
+    pub span_coverage: f32,
+    pub content_weight: f32,
+    pub lemma_similarity: f32,
+    pub combined_similarity: f32,
+    pub weighted_similarity: f32,
+    pub normalized_score: f32,
+}
+
+/// Aligned length: diagonal moves (aligned pairs) plus gaps.
+pub fn aligned_length(alignment: &Alignment) -> u32 {
+    alignment.aligned_pairs.len() as u32 + alignment.gaps
+}
+
+/// Compute all edge metrics for an alignment of `aligned_length` positions.
+pub fn compute_edge_metrics(alignment: &Alignment, aligned_length: u32) -> EdgeMetrics {
+    EdgeMetrics {
+        core_similarity: core_similarity(alignment.lemma_matches, alignment.substitutions),
+        span_coverage: span_coverage(alignment.lemma_matches, alignment.substitutions, aligned_length),
+        content_weight: content_weight(alignment.match_weight_sum, alignment.lemma_matches),
+        lemma_similarity: lemma_similarity(alignment.lemma_matches, aligned_length),
+        combined_similarity: combined_similarity(
+            alignment.lemma_matches,
+            alignment.root_only_matches,
+            aligned_length,
+        ),
+        weighted_similarity: ratio(alignment.match_weight_sum, aligned_length),
+        normalized_score: ratio(alignment.score as f32, aligned_length),
+    }
+}
+
+/// Core similarity: quotation exactness, ignoring gaps.
+/// `matches / (matches + substitutions)`
+pub fn core_similarity(lemma_matches: u32, substitutions: u32) -> f32 {
+    ratio(lemma_matches as f32, lemma_matches + substitutions)
+}
+
+/// Span coverage: how much of the span is actual content rather than padding.
+/// `(matches + substitutions) / aligned_length`
+pub fn span_coverage(lemma_matches: u32, substitutions: u32, aligned_length: u32) -> f32 {
+    ratio((lemma_matches + substitutions) as f32, aligned_length)
+}
+
+/// Content weight: average IDF of matched lemmas.
+pub fn content_weight(match_weight_sum: f32, lemma_matches: u32) -> f32 {
+    ratio(match_weight_sum, lemma_matches)
+}
+
+/// Legacy lemma similarity: `matches / aligned_length`.
+pub fn lemma_similarity(lemma_matches: u32, aligned_length: u32) -> f32 {
+    ratio(lemma_matches as f32, aligned_length)
+}
+
+/// Legacy combined similarity: root-only matches count half.
+pub fn combined_similarity(lemma_matches: u32, root_only_matches: u32, aligned_length: u32) -> f32 {
+    ratio(
+        lemma_matches as f32 + 0.5 * root_only_matches as f32,
+        aligned_length,
+    )
+}
+
+/// Span ratio: `target_len / source_len`. Below 1 the target abridges the
+/// source; above 1 it expands it.
+pub fn span_ratio(source_len: u32, target_len: u32) -> f32 {
+    ratio(target_len as f32, source_len)
+}
+
+/// Span asymmetry: longer span over shorter span, 1 for equal spans.
+pub fn span_asymmetry(source_len: u32, target_len: u32) -> f32 {
+    ratio(source_len.max(target_len) as f32, source_len.min(target_len))
+}
+
+/// `numerator / denominator`, or 0 for an empty denominator.
+fn ratio(numerator: f32, denominator: u32) -> f32 {
+    if denominator > 0 {
+        numerator / denominator as f32
+    } else {
+        0.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alignment(matches: u32, subs: u32, root_only: u32, gaps: u32) -> Alignment {
+        let pairs = (matches + subs + root_only) as usize;
+        Alignment {
+            start_a: 0,
+            end_a: pairs,
+            start_b: 0,
+            end_b: pairs,
+            aligned_pairs: (0..pairs).map(|i| (i, i)).collect(),
+            lemma_matches: matches,
+            substitutions: subs,
+            root_only_matches: root_only,
+            gaps,
+            score: 2 * matches as i32 - subs as i32 - gaps as i32,
+            match_weight_sum: 1.5 * matches as f32,
+            lexical_diversity: 1.0,
+        }
+    }
+
+    #[test]
+    fn test_compute_edge_metrics() {
+        // 80 matches, 10 substitutions, 10 root-only, 20 gaps: 120 positions
+        let a = alignment(80, 10, 10, 20);
+        let len = aligned_length(&a);
+        assert_eq!(len, 120);
+
+        let m = compute_edge_metrics(&a, len);
+        assert!((m.core_similarity - 80.0 / 90.0).abs() < 1e-6);
+        assert!((m.span_coverage - 90.0 / 120.0).abs() < 1e-6);
+        assert!((m.content_weight - 1.5).abs() < 1e-6);
+        assert!((m.lemma_similarity - 80.0 / 120.0).abs() < 1e-6);
+        assert!((m.combined_similarity - 85.0 / 120.0).abs() < 1e-6);
+        assert!((m.weighted_similarity - 1.0).abs() < 1e-6);
+        assert!((m.normalized_score - 130.0 / 120.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_span_ratio_and_asymmetry() {
+        // A 40-token abridgment of a 100-token source
+        assert!((span_ratio(100, 40) - 0.4).abs() < 1e-6);
+        assert!((span_asymmetry(100, 40) - 2.5).abs() < 1e-6);
+        // An expansion is just as asymmetric
+        assert!((span_ratio(40, 100) - 2.5).abs() < 1e-6);
+        assert!((span_asymmetry(40, 100) - 2.5).abs() < 1e-6);
+        assert_eq!(span_asymmetry(50, 50), 1.0);
+    }
+
+    #[test]
+    fn test_empty_alignment_metrics_are_zero() {
+        let m = compute_edge_metrics(&alignment(0, 0, 0, 0), 0);
+        assert_eq!(m.core_similarity, 0.0);
+        assert_eq!(m.span_coverage, 0.0);
+        assert_eq!(m.content_weight, 0.0);
+        assert_eq!(m.normalized_score, 0.0);
+    }
+}
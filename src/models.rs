@@ -1,6 +1,11 @@
 //! Data structures for the Kashshaf text reuse detection pipeline.
 
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+use crate::quran::QuranIndex;
 
 /// A single page's lemma sequence
 #[derive(Debug, Clone)]
@@ -18,6 +23,27 @@ pub struct PageTokens {
     pub token_ids: Vec<u32>,  // Original token_definition IDs (for surface form lookup)
     pub lemma_ids: Vec<u32>,  // Mapped lemma IDs (for comparison)
     pub root_ids: Vec<u32>,   // Mapped root IDs (for root-based matching, 0 = no root)
+    pub alt_lemma_ids: Vec<Vec<u32>>, // Alternative lemma analyses per token (empty = not loaded)
+}
+
+/// Alternative lemma analyses for ambiguous tokens: token_id -> lemma IDs
+pub type LemmaAlternatives = std::collections::HashMap<u32, Vec<u32>>;
+
+/// An inclusive run of pages within one book, each end a `(part_index, page_id)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PageRange {
+    pub start: (u32, u32),
+    pub end: (u32, u32),
+}
+
+impl PageRange {
+    pub fn contains(&self, page: (u32, u32)) -> bool {
+        self.start <= page && page <= self.end
+    }
+
+    pub fn overlaps(&self, other: &PageRange) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
 }
 
 /// Complete token stream for a book (includes both token_ids and lemma_ids)
@@ -26,9 +52,45 @@ pub struct BookTokenStream {
     pub book_id: u32,
     pub total_tokens: usize,
     pub pages: Vec<PageTokens>,
+    /// Corpus-wide IDF weights indexed by lemma ID, shared by every stream
+    /// (None = not loaded; see `idf`)
+    pub corpus_weights: Option<Arc<Vec<f32>>>,
+    /// Lemma shingles of the Quran, shared by every stream, for tagging
+    /// Quranic quotation (None = not loaded; see `quran`)
+    pub quran: Option<Arc<QuranIndex>>,
 }
 
 impl BookTokenStream {
+    /// A book of one page whose token IDs are its lemma IDs, with no roots.
+    #[cfg(test)]
+    pub(crate) fn single_page(book_id: u32, lemma_ids: Vec<u32>) -> Self {
+        BookTokenStream {
+            book_id,
+            total_tokens: lemma_ids.len(),
+            pages: vec![PageTokens {
+                part_index: 1,
+                page_id: 1,
+                token_ids: lemma_ids.clone(),
+                root_ids: vec![0; lemma_ids.len()],
+                lemma_ids,
+                alt_lemma_ids: Vec::new(),
+            }],
+            corpus_weights: None,
+            quran: None,
+        }
+    }
+
+    /// SHA-256 (hex) of the stream's pages, token IDs and their lemma and
+    /// root analyses. Changes whenever the book's text or its lemmatization
+    /// changes in corpus.db.
+    pub fn content_hash(&self) -> String {
+        stream_hash(
+            self.pages
+                .iter()
+                .map(|p| (p.part_index, p.page_id, [&p.token_ids[..], &p.lemma_ids[..], &p.root_ids[..]])),
+        )
+    }
+
     /// Get flat array of all token IDs in order
     pub fn flat_token_ids(&self) -> Vec<u32> {
         self.pages
@@ -53,24 +115,131 @@ impl BookTokenStream {
             .collect()
     }
 
+    /// Get flat per-token alternative lemma IDs.
+    /// Returns an empty Vec if no alternatives have been attached.
+    pub fn flat_alt_lemma_ids(&self) -> Vec<Vec<u32>> {
+        if self.pages.iter().all(|p| p.alt_lemma_ids.is_empty()) {
+            return Vec::new();
+        }
+        self.pages
+            .iter()
+            .flat_map(|p| {
+                if p.alt_lemma_ids.is_empty() {
+                    vec![Vec::new(); p.token_ids.len()]
+                } else {
+                    p.alt_lemma_ids.clone()
+                }
+            })
+            .collect()
+    }
+
+    /// Attach alternative lemma analyses (token_id -> lemma IDs) to every page.
+    /// Alternatives equal to a token's primary lemma are dropped.
+    pub fn attach_lemma_alternatives(&mut self, alternatives: &LemmaAlternatives) {
+        if alternatives.is_empty() {
+            return;
+        }
+        for page in &mut self.pages {
+            page.alt_lemma_ids = page
+                .token_ids
+                .iter()
+                .zip(&page.lemma_ids)
+                .map(|(tid, &primary)| {
+                    alternatives
+                        .get(tid)
+                        .map(|alts| alts.iter().copied().filter(|&l| l != primary).collect())
+                        .unwrap_or_default()
+                })
+                .collect();
+        }
+    }
+
+    /// Attach corpus-wide IDF weights, used when `weight_source` is `Corpus`.
+    pub fn attach_corpus_weights(&mut self, weights: &Arc<Vec<f32>>) {
+        self.corpus_weights = Some(Arc::clone(weights));
+    }
+
+    /// Attach the Quran index, used when `quran_book_id` is set.
+    pub fn attach_quran(&mut self, index: &Arc<QuranIndex>) {
+        self.quran = Some(Arc::clone(index));
+    }
+
     /// Get the number of pages
     pub fn page_count(&self) -> usize {
         self.pages.len()
     }
 
+    /// The pages of this stream inside `range`, as a stream of their own,
+    /// plus the global offset of its first token in this stream.
+    pub fn page_range(&self, range: PageRange) -> (BookTokenStream, usize) {
+        let mut offset = 0;
+        let mut pages = Vec::new();
+        for page in &self.pages {
+            if range.contains((page.part_index, page.page_id)) {
+                pages.push(page.clone());
+            } else if pages.is_empty() {
+                offset += page.token_ids.len();
+            }
+        }
+
+        let stream = BookTokenStream {
+            book_id: self.book_id,
+            total_tokens: pages.iter().map(|p| p.token_ids.len()).sum(),
+            pages,
+            corpus_weights: self.corpus_weights.clone(),
+            quran: self.quran.clone(),
+        };
+        (stream, offset)
+    }
+
     /// Get surface text with context before and after
-    pub fn get_surface_text_with_context(
+    pub fn get_surface_text_with_context<S: SurfaceLookup + ?Sized>(
         &self,
         global_start: usize,
         global_end: usize,
-        context_tokens: usize,
-        token_to_surface: &[String],
+        context: impl Into<PassageContext>,
+        token_to_surface: &S,
     ) -> PassageText {
+        let context = context.into();
         let token_ids = self.flat_token_ids();
         let len = token_ids.len();
 
-        let context_start = global_start.saturating_sub(context_tokens);
-        let context_end = (global_end + context_tokens).min(len);
+        // Token offsets where a page starts (only needed for page boundaries)
+        let page_starts: Vec<usize> = match context.boundary {
+            ContextBoundary::Page => self
+                .pages
+                .iter()
+                .scan(0, |offset, page| {
+                    let start = *offset;
+                    *offset += page.token_ids.len();
+                    Some(start)
+                })
+                .collect(),
+            _ => Vec::new(),
+        };
+        // Whether context may stop between tokens `pos - 1` and `pos`
+        let is_boundary = |pos: usize| -> bool {
+            pos == 0
+                || pos >= len
+                || match context.boundary {
+                    ContextBoundary::Fixed => true,
+                    ContextBoundary::Sentence => token_to_surface
+                        .surface(token_ids[pos - 1])
+                        .is_some_and(|s| s.ends_with(SENTENCE_END)),
+                    ContextBoundary::Page => page_starts.binary_search(&pos).is_ok(),
+                }
+        };
+
+        let mut context_start = global_start.saturating_sub(context.tokens);
+        let floor = global_start.saturating_sub(context.max_tokens);
+        while context_start > floor && !is_boundary(context_start) {
+            context_start -= 1;
+        }
+        let mut context_end = (global_end + context.tokens).min(len);
+        let ceiling = (global_end + context.max_tokens).min(len);
+        while context_end < ceiling && !is_boundary(context_end) {
+            context_end += 1;
+        }
 
         let get_text = |start: usize, end: usize| -> String {
             if start >= end || start >= len {
@@ -79,13 +248,7 @@ impl BookTokenStream {
             let actual_end = end.min(len);
             token_ids[start..actual_end]
                 .iter()
-                .filter_map(|&tid| {
-                    if (tid as usize) < token_to_surface.len() {
-                        Some(token_to_surface[tid as usize].as_str())
-                    } else {
-                        None
-                    }
-                })
+                .filter_map(|&tid| token_to_surface.surface(tid))
                 .collect::<Vec<_>>()
                 .join(" ")
         };
@@ -98,6 +261,167 @@ impl BookTokenStream {
     }
 }
 
+/// Characters a token's surface form can end a sentence with.
+const SENTENCE_END: &[char] = &['.', '!', '?', '\u{61F}', '\u{6D4}'];
+
+/// Default cap on grown context per side, when `max_context_tokens` is unset.
+pub const DEFAULT_MAX_CONTEXT_TOKENS: usize = 200;
+
+/// Context reconstructed around a passage: at least `tokens` on either side,
+/// grown towards the nearest `boundary` but never past `max_tokens`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PassageContext {
+    pub tokens: usize,
+    pub boundary: ContextBoundary,
+    pub max_tokens: usize,
+}
+
+impl PassageContext {
+    /// Context of `tokens` tokens, grown as `params` asks.
+    pub fn new(tokens: usize, params: &ComparisonParams) -> Self {
+        PassageContext {
+            tokens,
+            boundary: params.context_boundary,
+            max_tokens: params.max_context_tokens.unwrap_or(DEFAULT_MAX_CONTEXT_TOKENS).max(tokens),
+        }
+    }
+
+    /// Most tokens the context can take on either side.
+    pub fn reach(&self) -> usize {
+        match self.boundary {
+            ContextBoundary::Fixed => self.tokens,
+            _ => self.max_tokens,
+        }
+    }
+}
+
+/// A fixed number of tokens on either side.
+impl From<usize> for PassageContext {
+    fn from(tokens: usize) -> Self {
+        PassageContext {
+            tokens,
+            boundary: ContextBoundary::Fixed,
+            max_tokens: tokens,
+        }
+    }
+}
+
+/// Surface forms by token ID: the full token_id-indexed table, or a map
+/// holding only the tokens a result needs.
+pub trait SurfaceLookup {
+    fn surface(&self, token_id: u32) -> Option<&str>;
+}
+
+impl SurfaceLookup for [String] {
+    fn surface(&self, token_id: u32) -> Option<&str> {
+        self.get(token_id as usize).map(String::as_str)
+    }
+}
+
+impl SurfaceLookup for Vec<String> {
+    fn surface(&self, token_id: u32) -> Option<&str> {
+        self.as_slice().surface(token_id)
+    }
+}
+
+impl SurfaceLookup for HashMap<u32, String> {
+    fn surface(&self, token_id: u32) -> Option<&str> {
+        self.get(&token_id).map(String::as_str)
+    }
+}
+
+/// Surface forms of every token, interned into one string with a byte
+/// offset per token ID.
+///
+/// Compared with a `Vec<String>` this drops the per-string header and heap
+/// allocation, roughly halving the table's memory, and clones share the same
+/// buffers, so handing the table to another thread is cheap.
+#[derive(Debug, Clone, Default)]
+pub struct SurfaceTable {
+    text: Arc<str>,
+    /// `offsets[id]..offsets[id + 1]` is the surface of token `id`
+    offsets: Arc<[u32]>,
+}
+
+impl SurfaceTable {
+    /// Number of token IDs covered (the largest ID plus one).
+    pub fn len(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, token_id: u32) -> Option<&str> {
+        let id = token_id as usize;
+        let (start, end) = (*self.offsets.get(id)?, *self.offsets.get(id + 1)?);
+        Some(&self.text[start as usize..end as usize])
+    }
+
+    /// `(token_id, surface)` for every token ID, in order.
+    pub fn iter(&self) -> impl Iterator<Item = (u32, &str)> {
+        self.offsets
+            .windows(2)
+            .enumerate()
+            .map(|(id, w)| (id as u32, &self.text[w[0] as usize..w[1] as usize]))
+    }
+}
+
+impl SurfaceLookup for SurfaceTable {
+    fn surface(&self, token_id: u32) -> Option<&str> {
+        self.get(token_id)
+    }
+}
+
+/// Surfaces in token ID order: the first is token 0.
+impl<S: AsRef<str>> FromIterator<S> for SurfaceTable {
+    fn from_iter<I: IntoIterator<Item = S>>(surfaces: I) -> Self {
+        let mut builder = SurfaceTableBuilder::default();
+        for (token_id, surface) in surfaces.into_iter().enumerate() {
+            builder.push(token_id as u32, surface.as_ref());
+        }
+        builder.finish()
+    }
+}
+
+/// Builds a [`SurfaceTable`] one token at a time.
+#[derive(Debug)]
+pub struct SurfaceTableBuilder {
+    text: String,
+    offsets: Vec<u32>,
+}
+
+impl Default for SurfaceTableBuilder {
+    fn default() -> Self {
+        SurfaceTableBuilder {
+            text: String::new(),
+            offsets: vec![0],
+        }
+    }
+}
+
+impl SurfaceTableBuilder {
+    /// Add the surface of `token_id`. IDs must come in ascending order; IDs
+    /// skipped over get an empty surface.
+    pub fn push(&mut self, token_id: u32, surface: &str) {
+        debug_assert!(self.offsets.len() <= token_id as usize + 1, "token IDs out of order");
+        let end = self.text.len() as u32;
+        while self.offsets.len() <= token_id as usize {
+            self.offsets.push(end);
+        }
+        self.text.push_str(surface);
+        self.offsets.push(self.text.len() as u32);
+    }
+
+    pub fn finish(self) -> SurfaceTable {
+        SurfaceTable {
+            text: self.text.into(),
+            offsets: self.offsets.into(),
+        }
+    }
+}
+
 /// Reconstructed text for a passage with context
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PassageText {
@@ -115,6 +439,15 @@ pub struct BookLemmaStream {
 }
 
 impl BookLemmaStream {
+    /// SHA-256 (hex) of the stream's pages and lemma IDs.
+    pub fn content_hash(&self) -> String {
+        stream_hash(
+            self.pages
+                .iter()
+                .map(|p| (p.part_index, p.page_id, [&p.lemma_ids[..], &[][..], &[][..]])),
+        )
+    }
+
     /// Get flat array of all lemma IDs in order
     pub fn flat_lemmas(&self) -> Vec<u32> {
         self.pages
@@ -129,6 +462,23 @@ impl BookLemmaStream {
     }
 }
 
+/// Hash page positions and each page's ID sequences, length-prefixed so that
+/// moving a token across a page boundary changes the hash.
+fn stream_hash<'a>(pages: impl Iterator<Item = (u32, u32, [&'a [u32]; 3])>) -> String {
+    let mut hasher = Sha256::new();
+    for (part_index, page_id, sequences) in pages {
+        hasher.update(part_index.to_le_bytes());
+        hasher.update(page_id.to_le_bytes());
+        for ids in sequences {
+            hasher.update((ids.len() as u64).to_le_bytes());
+            for id in ids {
+                hasher.update(id.to_le_bytes());
+            }
+        }
+    }
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
 /// A window into a book's lemma/root stream
 #[derive(Debug, Clone)]
 #[allow(dead_code)]
@@ -143,6 +493,9 @@ pub struct Window {
     pub end_offset: u32,
     pub lemma_ids: Vec<u32>,
     pub root_ids: Vec<u32>,     // Root IDs for root-based matching (0 = no root)
+    pub token_ids: Vec<u32>,    // Token IDs for surface shingles (empty = not kept)
+    pub alt_lemma_ids: Vec<Vec<u32>>, // Alternative lemmas per position (empty = none)
+    pub shingle_hashes: Vec<u64>, // Sorted distinct shingle hashes (empty = not computed)
 }
 
 /// Result of Smith-Waterman alignment
@@ -210,12 +563,138 @@ pub struct ReuseEdge {
     /// Lexical diversity catches cases where the same common lemmas repeat, indicating
     /// formulaic content even if individual words have moderate IDF scores.
     pub lexical_diversity: f32,
+    /// Jaccard of the lemma-shingle sets of the two matched spans. Independent of
+    /// gap placement, so it flags alignments whose metrics look good only because
+    /// of a pathological gap pattern.
+    #[serde(default)]
+    pub shingle_jaccard: f32,
+    /// Either span reads as verse (see `verse::is_verse_span`)
+    #[serde(default)]
+    pub is_verse: bool,
+    /// Either span is mostly digits or non-Arabic script, such as OCR junk or
+    /// a table (see `script::is_suspect_span`)
+    #[serde(default)]
+    pub suspect: bool,
+    /// Share of the spans covered by text also found in the Quran, the
+    /// smaller of source and target (0 unless `quran_book_id` is set; see
+    /// `quran`)
+    #[serde(default)]
+    pub quran_overlap: f32,
+    /// Source and target span lengths in tokens. A span ratio (target / source)
+    /// far from 1 means one side abridges or expands the other.
+    #[serde(default)]
+    pub source_span_length: u32,
+    #[serde(default)]
+    pub target_span_length: u32,
+    #[serde(default)]
+    pub span_ratio: f32,
+    /// Which book most plausibly borrowed from which, from author death dates
+    #[serde(default)]
+    pub direction: Direction,
+    /// Reviewer's label from the viewer (see `output::apply_validations`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validation: Option<Validation>,
 
     // Legacy metrics (kept for backward compatibility)
     pub lemma_similarity: f32,   // lemma_matches / aligned_length
     pub combined_similarity: f32, // (lemma_matches + 0.5 * root_only_matches) / aligned_length
     pub weighted_similarity: f32, // match_weight_sum / aligned_length (IDF-weighted)
     pub avg_match_weight: f32,    // match_weight_sum / lemma_matches (same as content_weight)
+
+    // Raw alignment score (for tuning scoring parameters)
+    #[serde(default)]
+    pub score: i32,              // Smith-Waterman score (IDF-weighted when use_weights)
+    #[serde(default)]
+    pub normalized_score: f32,   // score / aligned_length
+}
+
+/// Edges on roughly the same diagonal, chained across moderate gaps into one
+/// long borrowing, such as a quotation interrupted by commentary (see
+/// `merge::chain_collinear_edges`). The member edges stay in the result as
+/// they are; a macro-edge only groups them.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MacroEdge {
+    /// ID of the first member edge
+    pub id: u64,
+    pub source_book_id: u32,
+    pub source_global_start: usize,
+    pub source_global_end: usize,
+    pub target_book_id: u32,
+    pub target_global_start: usize,
+    pub target_global_end: usize,
+    /// Member edge IDs in source order
+    pub edge_ids: Vec<u64>,
+    /// Tokens between consecutive members, summed over the chain
+    pub source_gap_tokens: usize,
+    pub target_gap_tokens: usize,
+    /// Summed over the members
+    pub lemma_matches: u32,
+    pub substitutions: u32,
+    /// Core similarity of the members taken together
+    pub core_similarity: f32,
+}
+
+/// How a coverage profile divides a book
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CoverageBins {
+    /// Runs of this many tokens, the last one shorter
+    Tokens(usize),
+    /// One bin per page
+    Pages,
+}
+
+/// Where in a book the reused tokens lie: covered tokens per bin, each token
+/// counted once however many edges cover it (see `coverage_bins`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CoverageProfile {
+    pub book_id: u32,
+    pub bins: CoverageBins,
+    /// Tokens in each bin, in reading order
+    pub sizes: Vec<usize>,
+    /// Covered tokens in each bin
+    pub covered: Vec<usize>,
+}
+
+/// Likely direction of reuse between the two books of a pair, by chronology:
+/// the book whose author died first is the likely source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Direction {
+    /// Book A's author died first: A → B
+    AtoB,
+    /// Book B's author died first: B → A
+    BtoA,
+    /// No dates, or both authors in the same year (or century, without years)
+    #[default]
+    Unknown,
+}
+
+/// A reviewer's verdict on an edge, as exported by the viewer
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Validation {
+    /// Genuine reuse
+    Valid,
+    /// Spurious match (formulae, boilerplate, alignment artefact)
+    Noise,
+}
+
+impl Direction {
+    /// Direction from the books' death dates (AH), falling back on their
+    /// centuries when either death year is missing.
+    pub fn between(book_a: &BookMetadata, book_b: &BookMetadata) -> Self {
+        let order = match (book_a.death_ah, book_b.death_ah) {
+            (Some(a), Some(b)) => a.cmp(&b),
+            _ => match (book_a.century_ah, book_b.century_ah) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                _ => return Direction::Unknown,
+            },
+        };
+        match order {
+            std::cmp::Ordering::Less => Direction::AtoB,
+            std::cmp::Ordering::Greater => Direction::BtoA,
+            std::cmp::Ordering::Equal => Direction::Unknown,
+        }
+    }
 }
 
 /// Matching mode for alignment scoring
@@ -228,6 +707,64 @@ pub enum MatchMode {
     Root,
     /// Lemma match = full score, root-only match = partial score
     Combined,
+    /// Match orthographically normalized surface forms, ignoring lemma and
+    /// root data (for corpora without reliable lemmatization)
+    Surface,
+}
+
+/// How book streams are cut into comparison units
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Segmentation {
+    /// Overlapping fixed-size windows (default)
+    #[default]
+    Windows,
+    /// One unit per hadith report, cut at isnād starts (needs token streams)
+    Hadith,
+}
+
+/// Where IDF lemma weights come from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum WeightSource {
+    /// Each book's own lemma frequencies (default)
+    #[default]
+    Document,
+    /// Lemma frequencies over the whole corpus, shared by every pair
+    Corpus,
+}
+
+/// How candidate window pairs are found before alignment (see `filter::CandidateFinder`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CandidateMethod {
+    /// Windows sharing at least `min_shared_shingles` n-gram shingles (default)
+    #[default]
+    Shingles,
+    /// Windows sharing at least `min_shared_shingles` winnowed shingle fingerprints
+    Winnowing,
+    /// Windows whose MinHash signatures collide in a locality-sensitive hashing band
+    MinHash,
+}
+
+/// How n-gram shingles are formed for candidate filtering
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ShingleKind {
+    /// Runs of `ngram_size` consecutive tokens (default)
+    #[default]
+    Contiguous,
+    /// Skip-grams: every run of `ngram_size` tokens with one inner token left
+    /// out, so a word substituted every few tokens still leaves shared shingles
+    Gapped,
+}
+
+/// Where reconstructed passage context stops
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ContextBoundary {
+    /// Exactly the requested number of context tokens (default)
+    #[default]
+    Fixed,
+    /// Grown out to the nearest sentence end (., !, ?, ؟, ۔)
+    Sentence,
+    /// Grown out to the nearest page break
+    Page,
 }
 
 /// Comparison parameters
@@ -237,16 +774,45 @@ pub struct ComparisonParams {
     pub stride: usize,
     pub ngram_size: usize,
     pub min_shared_shingles: usize,
-    pub min_length: usize,
+    pub min_shingle_jaccard: Option<f32>, // Post-check on candidates: shingle-set Jaccard
+    pub min_length: usize,              // Minimum aligned positions (matches + substitutions)
+    pub min_matched_tokens: Option<usize>, // Minimum matched tokens, excluding substitutions
     pub min_similarity: f32,
     pub match_score: i32,
     pub mismatch_penalty: i32,
     pub gap_penalty: i32,
     pub brute_force: bool,
+    /// How candidate window pairs are found (ignored with brute_force)
+    #[serde(default)]
+    pub candidate_method: CandidateMethod,
+    /// Contiguous n-grams or gapped skip-grams as filtering shingles
+    #[serde(default)]
+    pub shingle_kind: ShingleKind,
+    /// Recount shared shingles without hashing before accepting a candidate,
+    /// ruling out pairs made by shingle hash collisions (shingle method only)
+    #[serde(default)]
+    pub exact_shingles: bool,
+    /// Keep at most this many candidate pairs per window, those sharing the
+    /// most shingles (None = no cap)
+    #[serde(default)]
+    pub max_candidates_per_window: Option<usize>,
+    /// Leave shingles found in more than this many windows (of both books
+    /// together) out of the candidate index (None = keep all)
+    #[serde(default)]
+    pub max_shingle_df: Option<usize>,
+    /// Leave shingles found in more than this fraction of all windows out of
+    /// the candidate index (None = keep all)
+    #[serde(default)]
+    pub max_shingle_df_ratio: Option<f32>,
     // Root matching parameters
-    pub mode: MatchMode,
+    pub mode: MatchMode,       // Mode used for alignment scoring
+    pub filter_mode: MatchMode, // Mode used for n-gram candidate filtering (default: lemma)
     pub lemma_score: i32,      // Score for lemma match (default: 2)
     pub root_score: i32,       // Score for root-only match (default: 1)
+    /// Fail instead of falling back to lemma mode when root or combined
+    /// matching meets a corpus.db without root data
+    #[serde(default)]
+    pub require_roots: bool,
     // IDF weighting parameters
     pub use_weights: bool,     // Enable document-internal IDF weighting
     pub min_weighted_similarity: Option<f32>,  // Filter by weighted similarity
@@ -258,6 +824,94 @@ pub struct ComparisonParams {
     /// Filter by lexical diversity (unique lemmas / total matches).
     /// Default 0.55 suppresses formulaic reuse where same lemmas repeat.
     pub min_lexical_diversity: Option<f32>,
+    /// Filter by lemma-shingle Jaccard over the matched spans (None = disabled)
+    pub min_span_jaccard: Option<f32>,
+    /// Core similarity required of verse edges (None = same as prose)
+    #[serde(default)]
+    pub min_verse_core_similarity: Option<f32>,
+    /// Drop edges tagged suspect instead of only flagging them
+    #[serde(default)]
+    pub drop_suspect: bool,
+    /// Corpus book ID of the Quran, for tagging each edge's `quran_overlap`
+    /// (None = no tagging)
+    #[serde(default)]
+    pub quran_book_id: Option<u32>,
+    /// Drop edges whose `quran_overlap` exceeds this fraction, i.e. shared
+    /// Quranic quotation (None = keep them)
+    #[serde(default)]
+    pub max_quran_overlap: Option<f32>,
+    /// Drop edges whose longer span exceeds the shorter by more than this
+    /// factor (None = keep abridgments and expansions)
+    #[serde(default)]
+    pub max_span_asymmetry: Option<f32>,
+    /// Only merge overlapping edges whose re-aligned merged span keeps at least
+    /// this core similarity (None = merge unconditionally)
+    pub min_merge_core_similarity: Option<f32>,
+    /// Re-align merged spans for exact statistics instead of estimating them
+    /// from the merged edges
+    #[serde(default)]
+    pub refine_merges: bool,
+    /// Chain collinear edges separated by at most this many tokens in each
+    /// book into macro-edges (None = no chaining)
+    #[serde(default)]
+    pub chain_gap: Option<usize>,
+    /// Attach a coverage profile of each book binned this way (None = no profiles)
+    #[serde(default)]
+    pub coverage_bins: Option<CoverageBins>,
+    /// Bootstrap resamples for coverage confidence intervals (None = disabled)
+    pub bootstrap_samples: Option<usize>,
+    /// Count a lemma match when any alternative analysis of the two tokens coincides
+    /// (requires the token_lemma_alternatives table)
+    #[serde(default)]
+    pub use_lemma_alternatives: bool,
+    /// Only compare book A windows overlapping this global token range [start, end)
+    #[serde(default)]
+    pub source_range: Option<(usize, usize)>,
+    /// Window size of a coarse first pass that localizes reuse regions; the
+    /// regular (fine) pass then only aligns windows inside them (None = single pass)
+    #[serde(default)]
+    pub coarse_window_size: Option<usize>,
+    /// Stride of the coarse pass (None = half the coarse window)
+    #[serde(default)]
+    pub coarse_stride: Option<usize>,
+    /// Comparison units: fixed windows or hadith reports
+    #[serde(default)]
+    pub segmentation: Segmentation,
+    /// Abandon any alignment whose DP matrix exceeds this many cells (None = no cap)
+    #[serde(default)]
+    pub max_alignment_cells: Option<usize>,
+    /// Abandon any alignment still running after this many milliseconds (None = no limit)
+    #[serde(default)]
+    pub alignment_timeout_ms: Option<u64>,
+    /// X-drop: stop filling an alignment once a whole row scores more than
+    /// this far below the best score so far (None = always fill the matrix)
+    #[serde(default)]
+    pub xdrop: Option<u32>,
+    /// Lemmas (particles, formulaic vocabulary) left out of shingles and scored
+    /// as neutral in alignment (None = every lemma counts)
+    #[serde(default, serialize_with = "serialize_sorted_lemmas")]
+    pub stop_lemmas: Option<HashSet<u32>>,
+    /// Whether IDF weights come from each book or from the whole corpus
+    #[serde(default)]
+    pub weight_source: WeightSource,
+    /// Grow passage context past the fixed token count to a sentence or
+    /// page boundary (text output only; edges are unaffected)
+    #[serde(default)]
+    pub context_boundary: ContextBoundary,
+    /// Most context tokens per side when growing to a boundary
+    /// (None = DEFAULT_MAX_CONTEXT_TOKENS)
+    #[serde(default)]
+    pub max_context_tokens: Option<usize>,
+}
+
+/// Serialize a lemma set in ascending order, so params (and their
+/// fingerprints) serialize identically across runs.
+fn serialize_sorted_lemmas<S: serde::Serializer>(
+    lemmas: &Option<HashSet<u32>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let sorted: Option<BTreeSet<u32>> = lemmas.as_ref().map(|set| set.iter().copied().collect());
+    sorted.serialize(serializer)
 }
 
 impl ComparisonParams {
@@ -290,9 +944,16 @@ impl Default for ComparisonParams {
             stride: 60,
             ngram_size: 5,
             min_shared_shingles: 3,
+            min_shingle_jaccard: None,
+            shingle_kind: ShingleKind::Contiguous,
+            exact_shingles: false,
+            max_candidates_per_window: None,
+            max_shingle_df: None,
+            max_shingle_df_ratio: None,
 
             // Alignment gates
             min_length: 10,
+            min_matched_tokens: None,
 
             // Legacy similarity (diagnostic only, not a gate)
             min_similarity: 0.0,
@@ -304,9 +965,12 @@ impl Default for ComparisonParams {
 
             // Core behavior
             brute_force: false,
+            candidate_method: CandidateMethod::Shingles,
             mode: MatchMode::Combined,
+            filter_mode: MatchMode::Lemma,
             lemma_score: 2,
             root_score: 1,
+            require_roots: false,
 
             // IDF weighting (foundational)
             use_weights: true,
@@ -319,6 +983,47 @@ impl Default for ComparisonParams {
             min_span_coverage: Some(0.30),     // embedded vs standalone
             min_content_weight: Some(1.10),    // substantive vocabulary
             min_lexical_diversity: Some(0.55), // suppress formulaic reuse (e.g., isnād)
+            min_span_jaccard: None,
+            min_verse_core_similarity: None,
+            drop_suspect: false,
+            quran_book_id: None,
+            max_quran_overlap: None,
+            max_span_asymmetry: None,
+            min_merge_core_similarity: None,
+            refine_merges: false,
+            chain_gap: None,
+            coverage_bins: None,
+
+            // Reporting
+            bootstrap_samples: None,
+
+            // Ambiguous lemmatization
+            use_lemma_alternatives: false,
+
+            // Targeted studies
+            source_range: None,
+
+            // Multi-resolution
+            coarse_window_size: None,
+            coarse_stride: None,
+
+            // Comparison units
+            segmentation: Segmentation::Windows,
+
+            // Resource limits
+            max_alignment_cells: None,
+            alignment_timeout_ms: None,
+            xdrop: None,
+
+            // Stop lemmas
+            stop_lemmas: None,
+
+            // IDF weighting
+            weight_source: WeightSource::Document,
+
+            // Passage context
+            context_boundary: ContextBoundary::Fixed,
+            max_context_tokens: None,
         }
     }
 }
@@ -331,11 +1036,17 @@ pub struct BookMetadata {
     pub corpus: String,
     pub title: String,
     pub author_id: Option<u32>,
+    /// Author name, for display in exports (empty when not listed)
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub author: String,
     pub death_ah: Option<u32>,
     pub century_ah: Option<u8>,
     pub genre_id: Option<u32>,
     pub page_count: u32,
     pub token_count: u64,
+    /// Content hash of the compared stream (empty when not computed)
+    #[serde(default)]
+    pub stream_hash: String,
 }
 
 /// Full comparison result
@@ -347,16 +1058,194 @@ pub struct ComparisonResult {
     pub book_b: BookMetadata,
     pub summary: ComparisonSummary,
     pub edges: Vec<ReuseEdge>,
+    /// Chains of collinear edges, when `chain_gap` is set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub macro_edges: Vec<MacroEdge>,
+    /// Book A's profile, then book B's, when `coverage_bins` is set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub coverage_profiles: Vec<CoverageProfile>,
+    #[serde(default)]
+    pub run_info: RunInfo,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// How a comparison ran: wall-clock milliseconds spent in each stage, where
+/// the books came from, and how many window pairs and edges each stage kept.
+/// With `version` and `parameters`, enough to audit or reproduce a result.
+///
+/// Stages a run didn't go through (text reconstruction for a plain result,
+/// loading when the caller supplied the streams) stay at 0, and `corpus_db`
+/// stays empty when the streams did not come from a database file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunInfo {
+    /// Loading mappings and token streams from corpus.db
+    pub load_ms: u64,
+    /// Lemma weights and windowing (or hadith segmentation)
+    pub window_ms: u64,
+    /// Candidate filtering, including the coarse pass of two-pass mode
+    pub filter_ms: u64,
+    pub align_ms: u64,
+    /// Merging overlapping edges and verse tagging
+    pub merge_ms: u64,
+    /// Metric-based edge filters and the summary
+    pub filter_metrics_ms: u64,
+    /// Surface text and signature reconstruction
+    pub text_ms: u64,
+    /// The whole comparison, loading and text reconstruction included
+    #[serde(default)]
+    pub wall_ms: u64,
+    /// Machine the comparison ran on
+    #[serde(default)]
+    pub hostname: String,
+    /// Peak resident memory of the whole process when the comparison
+    /// finished, in bytes (None where the platform doesn't report it)
+    #[serde(default)]
+    pub peak_rss_bytes: Option<u64>,
+    /// Path of the corpus.db the books were loaded from
+    #[serde(default)]
+    pub corpus_db: String,
+    /// Fingerprint of that corpus.db's size and modification time
+    #[serde(default)]
+    pub db_fingerprint: String,
+    #[serde(default)]
+    pub stats: RunStats,
+}
+
+/// Window pairs, alignments and edges kept by each stage of a comparison.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunStats {
+    pub source_windows: usize,
+    pub target_windows: usize,
+    /// Window pairs passed on to alignment by the candidate filter (and the
+    /// coarse pass of two-pass mode)
+    pub candidate_pairs: usize,
+    /// Candidate pairs dropped by `max_candidates_per_window`
+    #[serde(default)]
+    pub capped_pairs: usize,
+    /// Windows that hit `max_candidates_per_window`
+    #[serde(default)]
+    pub capped_windows: usize,
+    /// Candidate pairs aligned to the end (abandoned alignments excluded)
+    #[serde(default)]
+    pub alignments: usize,
+    /// Alignments that fell short of the alignment gates and produced no edge
+    #[serde(default)]
+    pub rejected_alignments: usize,
+    /// Alignments that produced an edge
+    pub raw_edges: usize,
+    /// Edges left after merging overlaps
+    pub merged_edges: usize,
+    /// Merged edges dropped by the metric filters
+    pub dropped_edges: usize,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ComparisonSummary {
     pub edge_count: usize,
     pub total_aligned_tokens: usize,
+    /// Tokens of book A inside edge spans, overlapping spans counted per edge
+    #[serde(default)]
+    pub book_a_aligned_tokens: usize,
+    /// Tokens of book B inside edge spans, overlapping spans counted per edge
+    #[serde(default)]
+    pub book_b_aligned_tokens: usize,
     pub book_a_coverage: f32,
     pub book_b_coverage: f32,
     pub avg_similarity: f32,
     pub avg_weighted_similarity: f32,  // Average IDF-weighted similarity
+    pub book_a_coverage_ci: Option<(f32, f32)>,  // 95% page-bootstrap interval (if requested)
+    pub book_b_coverage_ci: Option<(f32, f32)>,
+    /// Kendall tau between the source and target order of edges: near 1 for
+    /// structural copying, near 0 for scattered topical quotation
+    #[serde(default)]
+    pub order_tau: f32,
+    /// Candidate pairs whose alignment was abandoned at the cell cap or timeout
+    #[serde(default)]
+    pub aborted_alignments: usize,
+    #[serde(default)]
+    pub edge_classes: EdgeClassCounts,
+    /// Aligned length of the edges, binned at [`LENGTH_BINS`]
+    #[serde(default)]
+    pub length_distribution: Distribution,
+    /// Lemma similarity of the edges, binned at [`SIMILARITY_BINS`]
+    #[serde(default)]
+    pub similarity_distribution: Distribution,
+}
+
+/// Lower bounds of the edge length histogram bins, in aligned tokens
+pub const LENGTH_BINS: [f32; 9] = [0.0, 20.0, 50.0, 100.0, 200.0, 500.0, 1000.0, 2000.0, 5000.0];
+
+/// Lower bounds of the similarity histogram bins
+pub const SIMILARITY_BINS: [f32; 10] = [0.0, 0.1, 0.2, 0.3, 0.4, 0.5, 0.6, 0.7, 0.8, 0.9];
+
+/// Edges of a result by class. An edge can be verse, suspect and Quranic at
+/// once; prose edges are none of these.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EdgeClassCounts {
+    pub prose: usize,
+    pub verse: usize,
+    pub suspect: usize,
+    /// Edges more than half shared scripture (`quran_overlap` above 0.5)
+    pub quran: usize,
+}
+
+impl EdgeClassCounts {
+    /// Count edges given as `(is_verse, suspect, quran_overlap)`.
+    pub fn count(edges: impl Iterator<Item = (bool, bool, f32)>) -> Self {
+        let mut counts = EdgeClassCounts::default();
+        for (is_verse, suspect, quran_overlap) in edges {
+            let quran = quran_overlap > 0.5;
+            counts.verse += is_verse as usize;
+            counts.suspect += suspect as usize;
+            counts.quran += quran as usize;
+            counts.prose += !(is_verse || suspect || quran) as usize;
+        }
+        counts
+    }
+}
+
+/// Spread of one edge metric: nearest-rank percentiles and a histogram.
+/// All zero for a result without edges.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Distribution {
+    pub min: f32,
+    pub p25: f32,
+    pub median: f32,
+    pub p75: f32,
+    pub p90: f32,
+    pub max: f32,
+    /// Lower bound of each histogram bin; the last bin is open-ended
+    pub bins: Vec<f32>,
+    /// Values falling in each bin
+    pub counts: Vec<usize>,
+}
+
+impl Distribution {
+    /// Percentiles of `values` and their histogram over bins starting at
+    /// `bins` (ascending). Values below the first bound count in the first bin.
+    pub fn new(mut values: Vec<f32>, bins: &[f32]) -> Self {
+        let mut counts = vec![0; bins.len()];
+        for &value in &values {
+            let bin = bins.partition_point(|&lower| lower <= value).saturating_sub(1);
+            if let Some(count) = counts.get_mut(bin) {
+                *count += 1;
+            }
+        }
+        values.sort_by(f32::total_cmp);
+        let percentile = |p: f32| match values.len() {
+            0 => 0.0,
+            n => values[((p * n as f32).ceil() as usize).clamp(1, n) - 1],
+        };
+        Distribution {
+            min: percentile(0.0),
+            p25: percentile(0.25),
+            median: percentile(0.5),
+            p75: percentile(0.75),
+            p90: percentile(0.9),
+            max: percentile(1.0),
+            bins: bins.to_vec(),
+            counts,
+        }
+    }
 }
 
 /// Page metadata from the pages table
@@ -370,6 +1259,21 @@ pub struct PageInfo {
     pub token_count: u32,
 }
 
+/// Reuse on one page of a compared book, a row of the page summary CSV
+/// (see `output::write_page_summary_csv`)
+#[derive(Debug, Clone, Serialize)]
+pub struct PageReuse {
+    pub book_id: u32,
+    pub part_index: u32,
+    pub page_id: u32,
+    pub part_label: Option<String>,
+    pub page_number: Option<String>,
+    pub token_count: u32,
+    pub edge_count: usize,        // Edges with a span on this page
+    pub covered_tokens: usize,    // Tokens of the page inside any span
+    pub avg_similarity: f32,      // Mean lemma similarity of those edges (0 without any)
+}
+
 /// Corpus statistics
 #[derive(Debug, Serialize)]
 pub struct CorpusStats {
@@ -404,6 +1308,55 @@ pub struct PassageRef {
     pub text: PassageText,
 }
 
+/// One step of an alignment path, as written in [`AlignmentInfo::ops`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlignmentOp {
+    /// Same lemma (any analysis of either token)
+    Match,
+    /// Different lemmas of the same root
+    RootMatch,
+    /// Different lemmas and roots at the same position
+    Substitution,
+    /// Source token with no counterpart in the target (gap in the target)
+    SourceOnly,
+    /// Target token with no counterpart in the source (gap in the source)
+    TargetOnly,
+}
+
+impl AlignmentOp {
+    /// Letter of the op in an ops string: M, R, S, D (source only) or I (target only).
+    pub fn code(self) -> char {
+        match self {
+            AlignmentOp::Match => 'M',
+            AlignmentOp::RootMatch => 'R',
+            AlignmentOp::Substitution => 'S',
+            AlignmentOp::SourceOnly => 'D',
+            AlignmentOp::TargetOnly => 'I',
+        }
+    }
+
+    pub fn from_code(code: char) -> Option<Self> {
+        match code {
+            'M' => Some(AlignmentOp::Match),
+            'R' => Some(AlignmentOp::RootMatch),
+            'S' => Some(AlignmentOp::Substitution),
+            'D' => Some(AlignmentOp::SourceOnly),
+            'I' => Some(AlignmentOp::TargetOnly),
+            _ => None,
+        }
+    }
+
+    /// Whether the op consumes a source token (all but `TargetOnly`).
+    pub fn in_source(self) -> bool {
+        self != AlignmentOp::TargetOnly
+    }
+
+    /// Whether the op consumes a target token (all but `SourceOnly`).
+    pub fn in_target(self) -> bool {
+        self != AlignmentOp::SourceOnly
+    }
+}
+
 /// Alignment information for viewer
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AlignmentInfo {
@@ -421,18 +1374,50 @@ pub struct AlignmentInfo {
     /// Lexical diversity: unique_matched_lemmas / lemma_matches.
     /// Low values (< 0.55) indicate formulaic content; high values indicate substantive reuse.
     pub lexical_diversity: f32,
+    #[serde(default)]
+    pub shingle_jaccard: f32,      // Lemma-shingle Jaccard of the matched spans
+    #[serde(default)]
+    pub is_verse: bool,            // Poetic quotation
+    #[serde(default)]
+    pub suspect: bool,             // Mostly digits or non-Arabic script
+    #[serde(default)]
+    pub quran_overlap: f32,        // Share of the spans also found in the Quran
+    #[serde(default)]
+    pub source_span_length: u32,
+    #[serde(default)]
+    pub target_span_length: u32,
+    #[serde(default)]
+    pub span_ratio: f32,           // target / source span length
 
     // Legacy metrics (kept for backward compatibility)
     pub similarity: f32,           // lemma_similarity
     pub combined_similarity: f32,  // (lemma + 0.5*root_only) / length
     pub weighted_similarity: f32,  // IDF-weighted similarity
     pub avg_match_weight: f32,     // Same as content_weight
+    #[serde(default)]
+    pub score: i32,                // Smith-Waterman score
+    #[serde(default)]
+    pub normalized_score: f32,     // score / aligned length
+
+    /// Alignment path over the matched spans, one [`AlignmentOp`] letter per
+    /// step from the start of both spans to their end (`--include-alignment-ops`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ops: Option<String>,
 }
 
 /// A reuse edge with reconstructed text for the viewer
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReuseEdgeWithText {
     pub id: u64,
+    /// Normalized preview of the source span's content words, identical for
+    /// repeats of the same floating passage (see `signature::span_signature`)
+    #[serde(default)]
+    pub signature: String,
+    #[serde(default)]
+    pub direction: Direction,
+    /// Reviewer's label from the viewer (see `output::apply_validations`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub validation: Option<Validation>,
     pub source: PassageRef,
     pub target: PassageRef,
     pub alignment: AlignmentInfo,
@@ -440,24 +1425,25 @@ pub struct ReuseEdgeWithText {
 
 impl ReuseEdgeWithText {
     /// Create from a ReuseEdge by adding text reconstruction
-    pub fn from_edge(
+    pub fn from_edge<S: SurfaceLookup + ?Sized>(
         edge: &ReuseEdge,
         source_stream: &BookTokenStream,
         target_stream: &BookTokenStream,
-        token_to_surface: &[String],
-        context_tokens: usize,
+        token_to_surface: &S,
+        context: impl Into<PassageContext>,
     ) -> Self {
+        let context = context.into();
         let source_text = source_stream.get_surface_text_with_context(
             edge.source_global_start,
             edge.source_global_end,
-            context_tokens,
+            context,
             token_to_surface,
         );
 
         let target_text = target_stream.get_surface_text_with_context(
             edge.target_global_start,
             edge.target_global_end,
-            context_tokens,
+            context,
             token_to_surface,
         );
 
@@ -471,6 +1457,9 @@ impl ReuseEdgeWithText {
 
         ReuseEdgeWithText {
             id: edge.id,
+            signature: String::new(), // Filled in by the comparison pipeline
+            direction: edge.direction,
+            validation: edge.validation,
             source: PassageRef {
                 book_id: edge.source_book_id,
                 location: format_location(
@@ -503,10 +1492,20 @@ impl ReuseEdgeWithText {
                 span_coverage: edge.span_coverage,
                 content_weight: edge.content_weight,
                 lexical_diversity: edge.lexical_diversity,
+                shingle_jaccard: edge.shingle_jaccard,
+                is_verse: edge.is_verse,
+                suspect: edge.suspect,
+                quran_overlap: edge.quran_overlap,
+                source_span_length: edge.source_span_length,
+                target_span_length: edge.target_span_length,
+                span_ratio: edge.span_ratio,
                 similarity: edge.lemma_similarity,
                 combined_similarity: edge.combined_similarity,
                 weighted_similarity: edge.weighted_similarity,
                 avg_match_weight: edge.avg_match_weight,
+                score: edge.score,
+                normalized_score: edge.normalized_score,
+                ops: None,
             },
         }
     }
@@ -521,6 +1520,8 @@ pub struct ViewerBookInfo {
     pub death_ah: Option<u32>,
     pub token_count: u64,
     pub page_count: u32,
+    #[serde(default)]
+    pub stream_hash: String,
 }
 
 impl From<&BookMetadata> for ViewerBookInfo {
@@ -528,10 +1529,11 @@ impl From<&BookMetadata> for ViewerBookInfo {
         ViewerBookInfo {
             id: meta.id,
             title: meta.title.clone(),
-            author: String::new(), // Will be filled from external source if available
+            author: meta.author.clone(),
             death_ah: meta.death_ah,
             token_count: meta.token_count,
             page_count: meta.page_count,
+            stream_hash: meta.stream_hash.clone(),
         }
     }
 }
@@ -546,5 +1548,547 @@ pub struct ComparisonResultWithText {
     pub book_b: ViewerBookInfo,
     pub summary: ComparisonSummary,
     pub edges: Vec<ReuseEdgeWithText>,
+    /// Chains of collinear edges, when `chain_gap` is set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub macro_edges: Vec<MacroEdge>,
+    /// Book A's profile, then book B's, when `coverage_bins` is set
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub coverage_profiles: Vec<CoverageProfile>,
+    #[serde(default)]
+    pub run_info: RunInfo,
+    /// Set when the reconstructed text was stripped for sharing (see `redact`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub redaction: Option<Redaction>,
+}
+
+/// What a redacted result needs to have its text restored from corpus.db
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Redaction {
+    /// Context tokens before/after each match in the original export
+    pub context_tokens: usize,
+}
+
+// ============================================================================
+// Batch (corpus-mode) types
+// ============================================================================
+
+/// What happened to a single book pair in a batch run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PairStatus {
+    /// Compared, with at least one reuse edge (`compared` in older manifests)
+    #[serde(alias = "compared")]
+    Ok,
+    /// Compared, with no reuse edges
+    Empty,
+    /// Compared, but some alignments were abandoned at `alignment_timeout_ms`
+    /// or `max_alignment_cells`, so edges may be missing
+    Timeout,
+    /// Skipped because weighted vocabulary overlap was under the prescan floor
+    SkippedPrescan,
+    /// Could not be compared; the record's `error` says why
+    Error,
+}
+
+impl PairStatus {
+    /// Status of a pair compared with this summary.
+    pub fn from_summary(summary: &ComparisonSummary) -> Self {
+        if summary.aborted_alignments > 0 {
+            PairStatus::Timeout
+        } else if summary.edge_count == 0 {
+            PairStatus::Empty
+        } else {
+            PairStatus::Ok
+        }
+    }
+
+    /// Whether the comparison ran and wrote the pair's result files.
+    pub fn is_compared(self) -> bool {
+        matches!(self, PairStatus::Ok | PairStatus::Empty | PairStatus::Timeout)
+    }
+}
+
+/// Manifest entry for one book pair
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PairRecord {
+    pub book_a: u32,
+    pub book_b: u32,
+    pub status: PairStatus,
+    pub prescan_overlap: Option<f32>, // Weighted vocabulary overlap (None if prescan disabled)
+    pub edge_count: usize,
+    pub output: Option<String>,       // Pair directory, relative to the output directory
+    pub summary: Option<ComparisonSummary>, // None unless the pair was compared
+    /// SHA-256 (hex) of each file in the pair directory, keyed by file name
+    #[serde(default)]
+    pub checksums: std::collections::BTreeMap<String, String>,
+    /// Reused from an earlier run instead of being recompared
+    #[serde(default)]
+    pub cached: bool,
+    /// Why the pair could not be compared (status `error`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// One of `count` deterministic slices of a batch run's pair list
+/// (`index` is 1-based, as in `--shard 2/8`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shard {
+    pub index: u32,
+    pub count: u32,
+}
+
+/// Top-level manifest describing every pair in a batch run
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BatchManifest {
+    pub version: String,
+    pub generated_at: String,
+    pub parameters: ComparisonParams,
+    #[serde(default)]
+    pub params_fingerprint: String, // SHA-256 of parameters and batch options
+    #[serde(default)]
+    pub db_fingerprint: String,     // SHA-256 of corpus.db size and modification time
+    /// Content hash of every book stream loaded in the run, keyed by book ID
+    #[serde(default)]
+    pub book_hashes: std::collections::BTreeMap<u32, String>,
+    pub min_prescan_overlap: Option<f32>,
+    /// The slice of the pair list this run covered (None = every pair)
+    #[serde(default)]
+    pub shard: Option<Shard>,
+    pub pairs: Vec<PairRecord>,
+}
+
+/// First line of a batch checkpoint: the run the following entries belong to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointHeader {
+    pub version: String,
+    pub params_fingerprint: String,
+    pub db_fingerprint: String,
+}
+
+/// One completed pair in a batch checkpoint, appended as soon as it finishes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CheckpointEntry {
+    pub record: PairRecord,
+    /// Stream content hashes of the pair's two books
+    #[serde(default)]
+    pub book_hashes: std::collections::BTreeMap<u32, String>,
+}
+
+// ============================================================================
+// Aggregation types (merged batch runs)
+// ============================================================================
+
+/// Corpus-level totals over a merged batch run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorpusSummary {
+    pub version: String,
+    pub generated_at: String,
+    pub params_fingerprint: String,
+    /// Number of shards the run was split into (None = unsharded)
+    pub shard_count: Option<u32>,
+    /// Shards (1-based) for which no output directory was merged
+    pub missing_shards: Vec<u32>,
+    pub pair_count: usize,
+    pub compared_pairs: usize,
+    pub skipped_pairs: usize,
+    /// Compared pairs with abandoned alignments (status `timeout`)
+    #[serde(default)]
+    pub timeout_pairs: usize,
+    /// Pairs that could not be compared (status `error`)
+    #[serde(default)]
+    pub error_pairs: usize,
+    pub pairs_with_reuse: usize,
+    pub total_edges: usize,
+    pub total_aligned_tokens: usize,
+    pub aborted_alignments: usize,
+}
+
+/// A book pair that shares material, as one edge of the corpus network
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkEdge {
+    pub source: u32,
+    pub target: u32,
+    pub weight: usize,            // Aligned tokens over all of the pair's reuse edges
+    pub edge_count: usize,
+    pub source_coverage: f32,
+    pub target_coverage: f32,
+    pub avg_similarity: f32,
+    pub order_tau: f32,
+}
+
+impl NetworkEdge {
+    /// The edge for a compared pair, weighted by its summary's aligned tokens.
+    pub fn from_summary(source: u32, target: u32, summary: &ComparisonSummary) -> Self {
+        NetworkEdge {
+            source,
+            target,
+            weight: summary.total_aligned_tokens,
+            edge_count: summary.edge_count,
+            source_coverage: summary.book_a_coverage,
+            target_coverage: summary.book_b_coverage,
+            avg_similarity: summary.avg_similarity,
+            order_tau: summary.order_tau,
+        }
+    }
+}
+
+/// A book in a reuse network. Reads the book entries of plain and text
+/// results alike.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct GraphNode {
+    pub id: u32,
+    #[serde(default)]
+    pub title: String,
+    #[serde(default)]
+    pub death_ah: Option<u32>,
+    #[serde(default)]
+    pub token_count: u64,
+}
+
+/// Books and the weighted edges between those that share material, for
+/// graph tools
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReuseGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<NetworkEdge>,
+}
+
+// ============================================================================
+// Corpus IDF types
+// ============================================================================
+
+/// Corpus-wide lemma weights as cached in the sidecar file next to corpus.db
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CorpusLemmaWeights {
+    pub version: String,
+    pub db_fingerprint: String, // Database the weights were counted from
+    pub total_tokens: u64,
+    pub weights: Vec<f32>,      // Indexed by lemma ID (0.0 = lemma never occurs)
+    /// Corpus-wide stop-lemmas for edge signatures (None in sidecars written
+    /// before they were recorded)
+    #[serde(default)]
+    pub stop_lemmas: Option<Vec<u32>>,
+}
+
+// ============================================================================
+// Audit sampling types
+// ============================================================================
+
+/// Population and sample size for one core-similarity band
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuditBand {
+    pub min_core_similarity: f32,  // Inclusive
+    pub max_core_similarity: f32,  // Exclusive, except for the top band
+    pub population: usize,
+    pub sampled: usize,
+}
+
+/// Stratified random sample of edges for manual review
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuditSample {
+    pub version: String,
+    pub generated_at: String,
+    pub seed: u64,
+    pub requested: usize,
+    pub total_edges: usize,
+    pub book_a: ViewerBookInfo,
+    pub book_b: ViewerBookInfo,
+    pub bands: Vec<AuditBand>,
+    pub edges: Vec<ReuseEdgeWithText>,
+}
+
+// ============================================================================
+// Abridgment report types
+// ============================================================================
+
+/// A run of ordered edges mapping one section of book A to book B
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AbridgmentSection {
+    pub source_start: usize,   // Global token range in book A
+    pub source_end: usize,
+    pub target_start: usize,   // Global token range in book B
+    pub target_end: usize,
+    pub edge_ids: Vec<u64>,
+    pub span_ratio: f32,       // Matched target tokens / matched source tokens
+}
+
+/// Whether book B reads as a systematic abridgment of book A
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AbridgmentReport {
+    pub version: String,
+    pub generated_at: String,
+    pub book_a: u32,
+    pub book_b: u32,
+    pub edge_count: usize,
+    pub chain_edges: usize,       // Edges on the ordered chain
+    pub source_coverage: f32,     // Share of book A covered by the chain
+    pub target_coverage: f32,     // Share of book B covered by the chain
+    pub ordered_fraction: f32,    // Chain source tokens / all matched source tokens
+    pub span_ratio: f32,          // Chain target tokens / chain source tokens
+    pub is_abridgment: bool,
+    pub sections: Vec<AbridgmentSection>,  // In source order
+}
+
+// ============================================================================
+// Substitution report types
+// ============================================================================
+
+/// A lemma of book A aligned against a different lemma of book B
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LemmaSubstitution {
+    pub source_position: usize, // Global token position in book A
+    pub target_position: usize, // Global token position in book B
+    pub source_lemma: u32,
+    pub target_lemma: u32,
+    pub source_surface: String,
+    pub target_surface: String,
+    pub same_root: bool,        // Both lemmas share a (nonzero) root
+}
+
+/// The substitutions inside one edge, in source order
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EdgeSubstitutions {
+    pub edge_id: u64,
+    pub aligned_positions: usize, // Diagonal positions of the re-alignment
+    pub substitutions: Vec<LemmaSubstitution>,
+}
+
+/// A surface form pair seen for a substituted lemma pair
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SurfacePair {
+    pub source: String,
+    pub target: String,
+    pub count: usize,
+}
+
+/// How often one lemma replaces another across a result
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubstitutionCount {
+    pub source_lemma: u32,
+    pub target_lemma: u32,
+    pub same_root: bool,
+    pub count: usize,
+    pub edge_count: usize,          // Edges the pair occurs in
+    pub surfaces: Vec<SurfacePair>, // Most frequent first
 }
 
+/// Lemma substitutions across the edges of a comparison
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SubstitutionReport {
+    pub version: String,
+    pub generated_at: String,
+    pub book_a: u32,
+    pub book_b: u32,
+    pub edge_count: usize,
+    pub aligned_positions: usize,
+    pub substitution_count: usize,
+    /// Edges whose re-alignment hit a resource limit, left out of the counts
+    pub skipped_edges: Vec<u64>,
+    pub edges: Vec<EdgeSubstitutions>,
+    pub table: Vec<SubstitutionCount>, // Most frequent first
+}
+
+// ============================================================================
+// Candidate export types (index-only mode)
+// ============================================================================
+
+/// A window pair that passed candidate filtering, with its token ranges
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CandidatePair {
+    pub source_window: u32,
+    pub target_window: u32,
+    pub source_start: usize, // Global token range of the source window [start, end)
+    pub source_end: usize,
+    pub target_start: usize,
+    pub target_end: usize,
+    pub shared_shingles: u64,
+    /// IDF-weighted Jaccard of the two windows' shingle sets
+    #[serde(default)]
+    pub weighted_jaccard: f32,
+    /// IDF-weighted containment of the lighter window's shingles in the other's
+    #[serde(default)]
+    pub weighted_containment: f32,
+}
+
+/// Every candidate pair between two books, for analysis or alignment elsewhere
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CandidateSet {
+    pub version: String,
+    pub generated_at: String,
+    pub parameters: ComparisonParams,
+    pub book_a: BookMetadata,
+    pub book_b: BookMetadata,
+    pub source_windows: usize,
+    pub target_windows: usize,
+    pub candidates: Vec<CandidatePair>,
+}
+
+// ============================================================================
+// Planning types
+// ============================================================================
+
+/// Size and cost estimates for a comparison, computed without aligning
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ComparisonPlan {
+    pub book_a: u32,
+    pub book_b: u32,
+    pub tokens_a: usize,
+    pub tokens_b: usize,
+    pub windows_a: usize,
+    pub windows_b: usize,
+    /// Book A windows actually filtered to estimate the candidate count
+    pub sampled_windows: usize,
+    pub estimated_candidates: u64,
+    /// DP cells filled by aligning every estimated candidate
+    pub estimated_alignment_cells: u64,
+    /// Peak memory of streams, windows, candidates and alignment matrices
+    pub estimated_memory_bytes: u64,
+}
+
+// ============================================================================
+// Author self-reuse types
+// ============================================================================
+
+/// Reuse between two books by the same author
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorPairReuse {
+    pub book_a: u32,
+    pub book_b: u32,
+    pub edge_count: usize,
+    pub total_aligned_tokens: usize,
+    pub book_a_coverage: f32,
+    pub book_b_coverage: f32,
+    pub output: Option<String>,
+}
+
+/// Per-book view of how much of a book reappears elsewhere in the author's works
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AuthorBookReuse {
+    pub book_id: u32,
+    pub title: String,
+    pub death_ah: Option<u32>,
+    pub pairs_with_reuse: usize,  // Other books of the author sharing at least one edge
+    pub max_coverage: f32,        // Highest coverage of this book against any single other book
+}
+
+/// Author self-reuse report
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AuthorReport {
+    pub version: String,
+    pub generated_at: String,
+    pub author_id: u32,
+    pub book_count: usize,
+    pub pair_count: usize,
+    pub pairs_with_reuse: usize,
+    pub skipped_pairs: usize,
+    pub total_edges: usize,
+    pub total_aligned_tokens: usize,
+    pub books: Vec<AuthorBookReuse>,
+    pub pairs: Vec<AuthorPairReuse>,  // Sorted by total aligned tokens, descending
+}
+
+// ============================================================================
+// Passage cluster types
+// ============================================================================
+
+/// One witness of a clustered passage: its span in one book
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClusterMember {
+    pub book_id: u32,
+    pub title: String,
+    pub author: String,
+    pub death_ah: Option<u32>,
+    pub global_range: (usize, usize), // Union of the book's passages in the cluster
+    pub location: String,             // Of the longest of those passages
+    pub text: String,                 // Matched text of the longest passage
+}
+
+/// A passage found in several books, with every edge between its witnesses
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PassageCluster {
+    pub id: usize,
+    pub witnesses: usize,             // Distinct books
+    pub edge_count: usize,
+    pub members: Vec<ClusterMember>,  // By death date, then book ID
+}
+
+/// Multi-witness passages across a set of results
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ClusterReport {
+    pub version: String,
+    pub generated_at: String,
+    pub result_count: usize,
+    pub min_witnesses: usize,
+    pub min_overlap: f32,
+    pub clusters: Vec<PassageCluster>,  // Most witnesses first
+}
+
+/// One witness of a collation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollationWitness {
+    pub book_id: u32,
+    pub title: String,
+    pub death_ah: Option<u32>,
+    pub global_range: (usize, usize),
+    pub location: String,
+}
+
+/// One column of a collation: a word per witness, None for a gap
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollationColumn {
+    pub words: Vec<Option<String>>,  // In witness order
+    pub variant: bool,               // Witnesses differ in lemma, or some lack the word
+}
+
+/// A cluster's passages aligned word by word
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Collation {
+    pub version: String,
+    pub generated_at: String,
+    pub cluster_id: usize,
+    pub witnesses: Vec<CollationWitness>,
+    pub variant_columns: usize,
+    pub columns: Vec<CollationColumn>,
+}
+
+// ============================================================================
+// Parameter sweep types
+// ============================================================================
+
+/// How the edges of one run compare with a set of reference edges
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GoldScores {
+    pub gold_edges: usize,
+    pub matched_edges: usize,  // Found edges matching some gold edge
+    pub found_gold: usize,     // Gold edges matched by some found edge
+    pub precision: f32,        // matched_edges / found edges
+    pub recall: f32,           // found_gold / gold_edges
+    pub f1: f32,
+}
+
+/// One configuration of a parameter sweep and what it found
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SweepRun {
+    /// This run's grid values, by parameter name
+    pub values: BTreeMap<String, serde_json::Value>,
+    pub edge_count: usize,
+    pub book_a_coverage: f32,
+    pub book_b_coverage: f32,
+    pub wall_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub scores: Option<GoldScores>,
+}
+
+/// Every run of a parameter sweep over one book pair
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SweepReport {
+    pub version: String,
+    pub generated_at: String,
+    pub book_a: u32,
+    pub book_b: u32,
+    /// Parameters shared by every run, before the grid values
+    pub parameters: ComparisonParams,
+    pub gold: Option<String>,
+    pub min_overlap: f32,
+    pub runs: Vec<SweepRun>,  // In grid order
+}
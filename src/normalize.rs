@@ -0,0 +1,170 @@
+//! Arabic orthographic normalization.
+//!
+//! Surface forms that differ only in spelling conventions (vocalization,
+//! hamza seats, ta marbuta written as ha, ya written without dots) should
+//! usually count as the same word. A [`NormalizationConfig`] selects which of
+//! these variants are folded together; the default folds all of them, which
+//! is what the importers and [`compare_texts`](crate::compare::compare_texts)
+//! use for lemma-less tokens and lexicon lookups. Punctuation and other
+//! characters that are neither letters nor digits are always dropped.
+//!
+//! ```
+//! use kashshaf_reuse::normalize::NormalizationConfig;
+//!
+//! assert_eq!(NormalizationConfig::default().normalize("إِسْمَاعِيلُ"), "اسماعيل");
+//!
+//! let keep_hamza = NormalizationConfig {
+//!     normalize_hamza: false,
+//!     ..Default::default()
+//! };
+//! assert_eq!(keep_hamza.normalize("إِسْمَاعِيلُ"), "إسماعيل");
+//! ```
+
+use std::collections::HashMap;
+
+/// Which orthographic variants to fold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NormalizationConfig {
+    /// Drop harakat, tanwin, shadda, sukun and dagger alif
+    pub strip_diacritics: bool,
+    /// Drop tatweel (kashida)
+    pub remove_tatweel: bool,
+    /// Write أ إ آ ٱ as ا, ؤ as و and ئ as ي
+    pub normalize_hamza: bool,
+    /// Write ة as ه
+    pub normalize_ta_marbuta: bool,
+    /// Write ى as ي
+    pub normalize_alif_maqsura: bool,
+}
+
+impl Default for NormalizationConfig {
+    fn default() -> Self {
+        Self {
+            strip_diacritics: true,
+            remove_tatweel: true,
+            normalize_hamza: true,
+            normalize_ta_marbuta: true,
+            normalize_alif_maqsura: true,
+        }
+    }
+}
+
+impl NormalizationConfig {
+    /// Fold nothing; only punctuation is dropped.
+    pub fn none() -> Self {
+        Self {
+            strip_diacritics: false,
+            remove_tatweel: false,
+            normalize_hamza: false,
+            normalize_ta_marbuta: false,
+            normalize_alif_maqsura: false,
+        }
+    }
+
+    /// Normalize one word.
+    pub fn normalize(&self, text: &str) -> String {
+        text.chars()
+            .filter_map(|c| match c {
+                '\u{064B}'..='\u{0652}' | '\u{0670}' => (!self.strip_diacritics).then_some(c),
+                '\u{0640}' => (!self.remove_tatweel).then_some(c),
+                'أ' | 'إ' | 'آ' | 'ٱ' if self.normalize_hamza => Some('ا'),
+                'ؤ' if self.normalize_hamza => Some('و'),
+                'ئ' if self.normalize_hamza => Some('ي'),
+                'ة' if self.normalize_ta_marbuta => Some('ه'),
+                'ى' if self.normalize_alif_maqsura => Some('ي'),
+                c if c.is_alphanumeric() => Some(c),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+/// Numbers normalized surface forms, so that token IDs whose surfaces
+/// normalize alike share one class ID. Classes count from 1 in order of first
+/// appearance; tokens never pushed, or normalizing to nothing, are class 0.
+#[derive(Debug)]
+pub struct SurfaceClasses {
+    config: NormalizationConfig,
+    classes: HashMap<String, u32>,
+    token_to_class: Vec<u32>,
+}
+
+impl SurfaceClasses {
+    pub fn new(config: NormalizationConfig) -> Self {
+        Self {
+            config,
+            classes: HashMap::new(),
+            token_to_class: Vec::new(),
+        }
+    }
+
+    /// Add the surface of `token_id`, in any order.
+    pub fn push(&mut self, token_id: u32, surface: &str) {
+        let normalized = self.config.normalize(surface);
+        if normalized.is_empty() {
+            return;
+        }
+        let next = self.classes.len() as u32 + 1;
+        let class = *self.classes.entry(normalized).or_insert(next);
+        let id = token_id as usize;
+        if self.token_to_class.len() <= id {
+            self.token_to_class.resize(id + 1, 0);
+        }
+        self.token_to_class[id] = class;
+    }
+
+    /// Class ID of every token, indexed by token ID.
+    pub fn finish(self) -> Vec<u32> {
+        self.token_to_class
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalization_steps() {
+        let word = "فَأَعْطَىٰ الـصَّلاةَ مسؤول";
+        let only = |config: NormalizationConfig| {
+            word.split(' ').map(|w| config.normalize(w)).collect::<Vec<_>>().join(" ")
+        };
+        let none = NormalizationConfig::none();
+        assert_eq!(only(none), "فَأَعْطَىٰ الـصَّلاةَ مسؤول");
+        assert_eq!(only(NormalizationConfig::default()), "فاعطي الصلاه مسوول");
+        assert_eq!(
+            only(NormalizationConfig {
+                strip_diacritics: true,
+                ..none
+            }),
+            "فأعطى الـصلاة مسؤول"
+        );
+        assert_eq!(
+            only(NormalizationConfig {
+                normalize_hamza: true,
+                normalize_alif_maqsura: true,
+                ..none
+            }),
+            "فَاَعْطَيٰ الـصَّلاةَ مسوول"
+        );
+        assert_eq!(
+            only(NormalizationConfig {
+                remove_tatweel: true,
+                normalize_ta_marbuta: true,
+                ..none
+            }),
+            "فَأَعْطَىٰ الصَّلاهَ مسؤول"
+        );
+        // Punctuation always goes
+        assert_eq!(none.normalize("موسى،"), "موسى");
+    }
+
+    #[test]
+    fn test_surface_classes() {
+        let mut classes = SurfaceClasses::new(NormalizationConfig::default());
+        for (token_id, surface) in [(1, "قالَ"), (4, "،"), (2, "موسى"), (3, "قال"), (5, "موسي")] {
+            classes.push(token_id, surface);
+        }
+        assert_eq!(classes.finish(), vec![0, 1, 2, 1, 0, 2]);
+    }
+}
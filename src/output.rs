@@ -1,8 +1,17 @@
 //! Output formatting for comparison results (JSON, CSV, HTML viewer).
 
-use crate::models::{ComparisonResult, ComparisonResultWithText, ReuseEdge, ReuseEdgeWithText};
-use std::io::{self, Write};
-use std::path::Path;
+use crate::compare::{covered_tokens_per_bin, merge_ranges, AlignmentTrace, TraceOutcome, WindowPairTrace};
+use crate::metrics;
+use crate::models::{
+    AbridgmentReport, AuditSample, AuthorReport, BatchManifest, CandidateMethod, CandidatePair, CandidateSet,
+    ClusterReport, Collation, ComparisonResult, ComparisonResultWithText, ComparisonSummary, CorpusSummary, CoverageBins,
+    Direction, Distribution, GraphNode, NetworkEdge, PageInfo, PageReuse, ReuseEdge, ReuseEdgeWithText, ReuseGraph,
+    RunInfo, Segmentation, ShingleKind, SubstitutionReport, SweepReport, Validation, ViewerBookInfo,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -11,6 +20,12 @@ pub enum OutputError {
     Io(#[from] io::Error),
     #[error("JSON serialization error: {0}")]
     Json(#[from] serde_json::Error),
+    #[error("CSV error: {0}")]
+    Csv(#[from] csv::Error),
+    #[error("Excel error: {0}")]
+    Xlsx(#[from] rust_xlsxwriter::XlsxError),
+    #[error("unknown column `{column}`; available columns: {available}")]
+    UnknownColumn { column: String, available: String },
 }
 
 /// Write comparison result as JSON.
@@ -26,66 +41,1091 @@ pub fn write_json_file(result: &ComparisonResult, path: &Path) -> Result<(), Out
     write_json(result, &mut file)
 }
 
-/// Write edges as CSV.
-pub fn write_csv<W: Write>(edges: &[ReuseEdge], writer: &mut W) -> Result<(), OutputError> {
-    // Write header
-    writeln!(
-        writer,
-        "id,source_book_id,source_start_part,source_start_page,source_start_offset,\
-         source_end_part,source_end_page,source_end_offset,source_global_start,source_global_end,\
-         target_book_id,target_start_part,target_start_page,target_start_offset,\
-         target_end_part,target_end_page,target_end_offset,target_global_start,target_global_end,\
-         aligned_length,lemma_matches,substitutions,root_only_matches,gaps,\
-         core_similarity,span_coverage,content_weight,lexical_diversity,\
-         lemma_similarity,combined_similarity,weighted_similarity"
-    )?;
+/// Read a comparison result written by `write_json_file`.
+pub fn read_json_file(path: &Path) -> Result<ComparisonResult, OutputError> {
+    let file = std::fs::File::open(path)?;
+    Ok(serde_json::from_reader(io::BufReader::new(file))?)
+}
+
+/// Write a batch manifest as JSON to a file.
+pub fn write_manifest_file(manifest: &BatchManifest, path: &Path) -> Result<(), OutputError> {
+    let mut file = std::fs::File::create(path)?;
+    let json = serde_json::to_string_pretty(manifest)?;
+    file.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+/// Read a batch manifest written by `write_manifest_file`.
+pub fn read_manifest_file(path: &Path) -> Result<BatchManifest, OutputError> {
+    let file = std::fs::File::open(path)?;
+    Ok(serde_json::from_reader(io::BufReader::new(file))?)
+}
+
+/// Write a comparison summary as JSON to a file.
+pub fn write_summary_file(summary: &ComparisonSummary, path: &Path) -> Result<(), OutputError> {
+    let mut file = std::fs::File::create(path)?;
+    let json = serde_json::to_string_pretty(summary)?;
+    file.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+/// Write an audit sample as JSON to a file.
+pub fn write_audit_file(sample: &AuditSample, path: &Path) -> Result<(), OutputError> {
+    let mut file = std::fs::File::create(path)?;
+    let json = serde_json::to_string_pretty(sample)?;
+    file.write_all(json.as_bytes())?;
+    Ok(())
+}
 
-    // Write rows
+/// Write a substitution report as JSON to a file.
+pub fn write_substitution_report_file(report: &SubstitutionReport, path: &Path) -> Result<(), OutputError> {
+    let mut file = std::fs::File::create(path)?;
+    let json = serde_json::to_string_pretty(report)?;
+    file.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+/// Write an abridgment report as JSON to a file.
+pub fn write_abridgment_report_file(report: &AbridgmentReport, path: &Path) -> Result<(), OutputError> {
+    let mut file = std::fs::File::create(path)?;
+    let json = serde_json::to_string_pretty(report)?;
+    file.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+/// Write a passage cluster report as JSON to a file.
+pub fn write_cluster_report_file(report: &ClusterReport, path: &Path) -> Result<(), OutputError> {
+    let mut file = std::fs::File::create(path)?;
+    let json = serde_json::to_string_pretty(report)?;
+    file.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+/// Read a passage cluster report written by `write_cluster_report_file`.
+pub fn read_cluster_report_file(path: &Path) -> Result<ClusterReport, OutputError> {
+    let file = std::fs::File::open(path)?;
+    Ok(serde_json::from_reader(io::BufReader::new(file))?)
+}
+
+/// Write a collation as JSON to a file.
+pub fn write_collation_file(collation: &Collation, path: &Path) -> Result<(), OutputError> {
+    let mut file = std::fs::File::create(path)?;
+    let json = serde_json::to_string_pretty(collation)?;
+    file.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+/// Write a parameter sweep report as JSON to a file.
+pub fn write_sweep_report_file(report: &SweepReport, path: &Path) -> Result<(), OutputError> {
+    let mut file = std::fs::File::create(path)?;
+    let json = serde_json::to_string_pretty(report)?;
+    file.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+/// Write a candidate pair set (index-only mode) as JSON to a file.
+pub fn write_candidates_file(set: &CandidateSet, path: &Path) -> Result<(), OutputError> {
+    let mut file = std::fs::File::create(path)?;
+    let json = serde_json::to_string_pretty(set)?;
+    file.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+/// Column order of candidate pair CSV output.
+const CSV_CANDIDATE_HEADER: [&str; 9] = [
+    "source_window",
+    "target_window",
+    "source_start",
+    "source_end",
+    "target_start",
+    "target_end",
+    "shared_shingles",
+    "weighted_jaccard",
+    "weighted_containment",
+];
+
+/// Write candidate pairs as CSV, one row per window pair.
+pub fn write_candidates_csv<W: Write>(candidates: &[CandidatePair], writer: &mut W) -> Result<(), OutputError> {
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    csv_writer.write_record(CSV_CANDIDATE_HEADER)?;
+    for candidate in candidates {
+        csv_writer.serialize(candidate)?;
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+/// Write candidate pairs as CSV to a file.
+pub fn write_candidates_csv_file(candidates: &[CandidatePair], path: &Path) -> Result<(), OutputError> {
+    let mut file = std::fs::File::create(path)?;
+    write_candidates_csv(candidates, &mut file)
+}
+
+/// Write a corpus summary as JSON to a file.
+pub fn write_corpus_summary_file(summary: &CorpusSummary, path: &Path) -> Result<(), OutputError> {
+    let mut file = std::fs::File::create(path)?;
+    let json = serde_json::to_string_pretty(summary)?;
+    file.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+/// Column order of network CSV output.
+const CSV_NETWORK_HEADER: [&str; 8] = [
+    "source",
+    "target",
+    "weight",
+    "edge_count",
+    "source_coverage",
+    "target_coverage",
+    "avg_similarity",
+    "order_tau",
+];
+
+/// Write a book network as a weighted CSV edge list, one row per book pair.
+pub fn write_network_csv<W: Write>(edges: &[NetworkEdge], writer: &mut W) -> Result<(), OutputError> {
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    csv_writer.write_record(CSV_NETWORK_HEADER)?;
     for edge in edges {
+        csv_writer.serialize(edge)?;
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+/// Write a book network as CSV to a file.
+pub fn write_network_csv_file(edges: &[NetworkEdge], path: &Path) -> Result<(), OutputError> {
+    let mut file = std::fs::File::create(path)?;
+    write_network_csv(edges, &mut file)
+}
+
+/// Column order of page summary CSV output.
+const CSV_PAGE_SUMMARY_HEADER: [&str; 9] = [
+    "book_id",
+    "part_index",
+    "page_id",
+    "part_label",
+    "page_number",
+    "token_count",
+    "edge_count",
+    "covered_tokens",
+    "avg_similarity",
+];
+
+/// One side of an edge: its book, global token range and lemma similarity.
+type PageSpan = (u32, (usize, usize), f32);
+
+/// Reuse on each of `pages`, which hold one or more books' pages in reading
+/// order, from the edge spans falling on them.
+fn page_reuse(spans: &[PageSpan], pages: &[PageInfo]) -> Vec<PageReuse> {
+    let mut rows = Vec::with_capacity(pages.len());
+    for book_pages in pages.chunk_by(|a, b| a.book_id == b.book_id) {
+        let book_id = book_pages[0].book_id;
+        let book_spans: Vec<&PageSpan> = spans.iter().filter(|span| span.0 == book_id).collect();
+        let mut ranges: Vec<(usize, usize)> = book_spans.iter().map(|span| span.1).collect();
+        ranges.sort_by_key(|r| r.0);
+        let sizes: Vec<usize> = book_pages.iter().map(|page| page.token_count as usize).collect();
+        let covered = covered_tokens_per_bin(&merge_ranges(&ranges), &sizes);
+
+        let mut page_start = 0;
+        for (page, covered_tokens) in book_pages.iter().zip(covered) {
+            let page_end = page_start + page.token_count as usize;
+            let similarities: Vec<f32> = book_spans
+                .iter()
+                .filter(|(_, (start, end), _)| (*end).min(page_end) > (*start).max(page_start))
+                .map(|span| span.2)
+                .collect();
+            rows.push(PageReuse {
+                book_id,
+                part_index: page.part_index,
+                page_id: page.page_id,
+                part_label: page.part_label.clone(),
+                page_number: page.page_number.clone(),
+                token_count: page.token_count,
+                edge_count: similarities.len(),
+                covered_tokens,
+                avg_similarity: if similarities.is_empty() {
+                    0.0
+                } else {
+                    similarities.iter().sum::<f32>() / similarities.len() as f32
+                },
+            });
+            page_start = page_end;
+        }
+    }
+    rows
+}
+
+fn write_page_reuse_csv<W: Write>(spans: &[PageSpan], pages: &[PageInfo], writer: &mut W) -> Result<(), OutputError> {
+    let mut csv_writer = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(writer);
+    csv_writer.write_record(CSV_PAGE_SUMMARY_HEADER)?;
+    for row in page_reuse(spans, pages) {
+        csv_writer.serialize(row)?;
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+/// Write a page-level reuse summary as CSV, one row per page of `pages`
+/// (usually both compared books, from `db::load_book_info`): how many edges
+/// have a span on the page, how many of its tokens they cover and their
+/// average lemma similarity. Pages without reuse get a row of zeros.
+pub fn write_page_summary_csv<W: Write>(
+    edges: &[ReuseEdge],
+    pages: &[PageInfo],
+    writer: &mut W,
+) -> Result<(), OutputError> {
+    let spans: Vec<PageSpan> = edges
+        .iter()
+        .flat_map(|edge| {
+            [
+                (edge.source_book_id, (edge.source_global_start, edge.source_global_end), edge.lemma_similarity),
+                (edge.target_book_id, (edge.target_global_start, edge.target_global_end), edge.lemma_similarity),
+            ]
+        })
+        .collect();
+    write_page_reuse_csv(&spans, pages, writer)
+}
+
+/// Write a page-level reuse summary as CSV to a file.
+pub fn write_page_summary_csv_file(edges: &[ReuseEdge], pages: &[PageInfo], path: &Path) -> Result<(), OutputError> {
+    let mut file = std::fs::File::create(path)?;
+    write_page_summary_csv(edges, pages, &mut file)
+}
+
+/// [`write_page_summary_csv`] for edges with text.
+pub fn write_page_summary_csv_with_text<W: Write>(
+    edges: &[ReuseEdgeWithText],
+    pages: &[PageInfo],
+    writer: &mut W,
+) -> Result<(), OutputError> {
+    let spans: Vec<PageSpan> = edges
+        .iter()
+        .flat_map(|edge| {
+            [
+                (edge.source.book_id, edge.source.global_range, edge.alignment.similarity),
+                (edge.target.book_id, edge.target.global_range, edge.alignment.similarity),
+            ]
+        })
+        .collect();
+    write_page_reuse_csv(&spans, pages, writer)
+}
+
+/// Write a page-level reuse summary of edges with text as CSV to a file.
+pub fn write_page_summary_csv_with_text_file(
+    edges: &[ReuseEdgeWithText],
+    pages: &[PageInfo],
+    path: &Path,
+) -> Result<(), OutputError> {
+    let mut file = std::fs::File::create(path)?;
+    write_page_summary_csv_with_text(edges, pages, &mut file)
+}
+
+/// Node label for graph tools: the title, or `Book <id>` without one.
+fn graph_label(node: &GraphNode) -> String {
+    if node.title.is_empty() {
+        format!("Book {}", node.id)
+    } else {
+        node.title.clone()
+    }
+}
+
+/// Escape text for an XML attribute or element.
+fn xml_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Write a reuse network as undirected GraphML. Edge `weight` is the pair's
+/// aligned tokens; the other network columns are edge attributes too.
+pub fn write_graphml<W: Write>(graph: &ReuseGraph, writer: &mut W) -> Result<(), OutputError> {
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(writer, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+    for (id, target, kind) in [
+        ("label", "node", "string"),
+        ("death_ah", "node", "int"),
+        ("token_count", "node", "long"),
+        ("weight", "edge", "long"),
+        ("edge_count", "edge", "long"),
+        ("source_coverage", "edge", "float"),
+        ("target_coverage", "edge", "float"),
+        ("avg_similarity", "edge", "float"),
+        ("order_tau", "edge", "float"),
+    ] {
         writeln!(
             writer,
-            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
-            edge.id,
-            edge.source_book_id,
-            edge.source_start_page.0,
-            edge.source_start_page.1,
-            edge.source_start_offset,
-            edge.source_end_page.0,
-            edge.source_end_page.1,
-            edge.source_end_offset,
-            edge.source_global_start,
-            edge.source_global_end,
-            edge.target_book_id,
-            edge.target_start_page.0,
-            edge.target_start_page.1,
-            edge.target_start_offset,
-            edge.target_end_page.0,
-            edge.target_end_page.1,
-            edge.target_end_offset,
-            edge.target_global_start,
-            edge.target_global_end,
-            edge.aligned_length,
-            edge.lemma_matches,
-            edge.substitutions,
-            edge.root_only_matches,
-            edge.gaps,
-            edge.core_similarity,
-            edge.span_coverage,
-            edge.content_weight,
-            edge.lexical_diversity,
-            edge.lemma_similarity,
-            edge.combined_similarity,
-            edge.weighted_similarity
+            r#"  <key id="{id}" for="{target}" attr.name="{id}" attr.type="{kind}"/>"#
         )?;
     }
+    writeln!(writer, r#"  <graph id="reuse" edgedefault="undirected">"#)?;
+    for node in &graph.nodes {
+        writeln!(writer, r#"    <node id="{}">"#, node.id)?;
+        writeln!(writer, r#"      <data key="label">{}</data>"#, xml_escape(&graph_label(node)))?;
+        if let Some(death_ah) = node.death_ah {
+            writeln!(writer, r#"      <data key="death_ah">{}</data>"#, death_ah)?;
+        }
+        writeln!(writer, r#"      <data key="token_count">{}</data>"#, node.token_count)?;
+        writeln!(writer, "    </node>")?;
+    }
+    for edge in &graph.edges {
+        writeln!(writer, r#"    <edge source="{}" target="{}">"#, edge.source, edge.target)?;
+        writeln!(writer, r#"      <data key="weight">{}</data>"#, edge.weight)?;
+        writeln!(writer, r#"      <data key="edge_count">{}</data>"#, edge.edge_count)?;
+        writeln!(writer, r#"      <data key="source_coverage">{}</data>"#, edge.source_coverage)?;
+        writeln!(writer, r#"      <data key="target_coverage">{}</data>"#, edge.target_coverage)?;
+        writeln!(writer, r#"      <data key="avg_similarity">{}</data>"#, edge.avg_similarity)?;
+        writeln!(writer, r#"      <data key="order_tau">{}</data>"#, edge.order_tau)?;
+        writeln!(writer, "    </edge>")?;
+    }
+    writeln!(writer, "  </graph>")?;
+    writeln!(writer, "</graphml>")?;
+    Ok(())
+}
+
+/// Write a reuse network as GraphML to a file, buffered.
+pub fn write_graphml_file(graph: &ReuseGraph, path: &Path) -> Result<(), OutputError> {
+    let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+    write_graphml(graph, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// GEXF edge attributes after the built-in weight: (id, title, type).
+const GEXF_EDGE_ATTRIBUTES: [(&str, &str, &str); 5] = [
+    ("0", "edge_count", "long"),
+    ("1", "source_coverage", "float"),
+    ("2", "target_coverage", "float"),
+    ("3", "avg_similarity", "float"),
+    ("4", "order_tau", "float"),
+];
+
+/// Write a reuse network as undirected GEXF 1.3, with the pair's aligned
+/// tokens as the edge weight.
+pub fn write_gexf<W: Write>(graph: &ReuseGraph, writer: &mut W) -> Result<(), OutputError> {
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(writer, r#"<gexf xmlns="http://gexf.net/1.3" version="1.3">"#)?;
+    writeln!(writer, r#"  <graph mode="static" defaultedgetype="undirected">"#)?;
+    writeln!(writer, r#"    <attributes class="node">"#)?;
+    writeln!(writer, r#"      <attribute id="0" title="death_ah" type="integer"/>"#)?;
+    writeln!(writer, r#"      <attribute id="1" title="token_count" type="long"/>"#)?;
+    writeln!(writer, "    </attributes>")?;
+    writeln!(writer, r#"    <attributes class="edge">"#)?;
+    for (id, title, kind) in GEXF_EDGE_ATTRIBUTES {
+        writeln!(writer, r#"      <attribute id="{id}" title="{title}" type="{kind}"/>"#)?;
+    }
+    writeln!(writer, "    </attributes>")?;
+
+    writeln!(writer, "    <nodes>")?;
+    for node in &graph.nodes {
+        writeln!(writer, r#"      <node id="{}" label="{}">"#, node.id, xml_escape(&graph_label(node)))?;
+        writeln!(writer, "        <attvalues>")?;
+        if let Some(death_ah) = node.death_ah {
+            writeln!(writer, r#"          <attvalue for="0" value="{}"/>"#, death_ah)?;
+        }
+        writeln!(writer, r#"          <attvalue for="1" value="{}"/>"#, node.token_count)?;
+        writeln!(writer, "        </attvalues>")?;
+        writeln!(writer, "      </node>")?;
+    }
+    writeln!(writer, "    </nodes>")?;
+
+    writeln!(writer, "    <edges>")?;
+    for (i, edge) in graph.edges.iter().enumerate() {
+        writeln!(
+            writer,
+            r#"      <edge id="{}" source="{}" target="{}" weight="{}">"#,
+            i, edge.source, edge.target, edge.weight
+        )?;
+        writeln!(writer, "        <attvalues>")?;
+        let values = [
+            edge.edge_count.to_string(),
+            edge.source_coverage.to_string(),
+            edge.target_coverage.to_string(),
+            edge.avg_similarity.to_string(),
+            edge.order_tau.to_string(),
+        ];
+        for ((id, _, _), value) in GEXF_EDGE_ATTRIBUTES.iter().zip(values) {
+            writeln!(writer, r#"          <attvalue for="{}" value="{}"/>"#, id, value)?;
+        }
+        writeln!(writer, "        </attvalues>")?;
+        writeln!(writer, "      </edge>")?;
+    }
+    writeln!(writer, "    </edges>")?;
+    writeln!(writer, "  </graph>")?;
+    writeln!(writer, "</gexf>")?;
+    Ok(())
+}
+
+/// Write a reuse network as GEXF to a file, buffered.
+pub fn write_gexf_file(graph: &ReuseGraph, path: &Path) -> Result<(), OutputError> {
+    let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+    write_gexf(graph, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write an author self-reuse report as JSON to a file.
+pub fn write_author_report_file(report: &AuthorReport, path: &Path) -> Result<(), OutputError> {
+    let mut file = std::fs::File::create(path)?;
+    let json = serde_json::to_string_pretty(report)?;
+    file.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+/// Column order of the plain edge CSV (also accepted by `read_csv_edges`).
+const CSV_EDGE_HEADER: [&str; 41] = [
+    "id",
+    "source_book_id",
+    "source_start_part",
+    "source_start_page",
+    "source_start_offset",
+    "source_end_part",
+    "source_end_page",
+    "source_end_offset",
+    "source_global_start",
+    "source_global_end",
+    "target_book_id",
+    "target_start_part",
+    "target_start_page",
+    "target_start_offset",
+    "target_end_part",
+    "target_end_page",
+    "target_end_offset",
+    "target_global_start",
+    "target_global_end",
+    "aligned_length",
+    "lemma_matches",
+    "substitutions",
+    "root_only_matches",
+    "gaps",
+    "core_similarity",
+    "span_coverage",
+    "content_weight",
+    "lexical_diversity",
+    "lemma_similarity",
+    "combined_similarity",
+    "weighted_similarity",
+    "score",
+    "normalized_score",
+    "shingle_jaccard",
+    "is_verse",
+    "suspect",
+    "quran_overlap",
+    "source_span_length",
+    "target_span_length",
+    "span_ratio",
+    "direction",
+];
+
+/// One row of the plain edge CSV. Field order matches `CSV_EDGE_HEADER`;
+/// reading goes by header name, so reordered spreadsheet columns still load.
+#[derive(Debug, Serialize, Deserialize)]
+struct CsvEdgeRow {
+    id: u64,
+    source_book_id: u32,
+    source_start_part: u32,
+    source_start_page: u32,
+    source_start_offset: u32,
+    source_end_part: u32,
+    source_end_page: u32,
+    source_end_offset: u32,
+    source_global_start: usize,
+    source_global_end: usize,
+    target_book_id: u32,
+    target_start_part: u32,
+    target_start_page: u32,
+    target_start_offset: u32,
+    target_end_part: u32,
+    target_end_page: u32,
+    target_end_offset: u32,
+    target_global_start: usize,
+    target_global_end: usize,
+    aligned_length: u32,
+    lemma_matches: u32,
+    substitutions: u32,
+    root_only_matches: u32,
+    gaps: u32,
+    core_similarity: f32,
+    span_coverage: f32,
+    content_weight: f32,
+    lexical_diversity: f32,
+    lemma_similarity: f32,
+    combined_similarity: f32,
+    weighted_similarity: f32,
+    // Absent in CSVs exported before scores were included
+    #[serde(default)]
+    score: i32,
+    #[serde(default)]
+    normalized_score: f32,
+    #[serde(default)]
+    shingle_jaccard: f32,
+    #[serde(default)]
+    is_verse: bool,
+    #[serde(default)]
+    suspect: bool,
+    #[serde(default)]
+    quran_overlap: f32,
+    // Derived from the global ranges; recomputed on read
+    #[serde(default)]
+    source_span_length: u32,
+    #[serde(default)]
+    target_span_length: u32,
+    #[serde(default)]
+    span_ratio: f32,
+    #[serde(default)]
+    direction: Direction,
+}
+
+impl From<&ReuseEdge> for CsvEdgeRow {
+    fn from(edge: &ReuseEdge) -> Self {
+        CsvEdgeRow {
+            id: edge.id,
+            source_book_id: edge.source_book_id,
+            source_start_part: edge.source_start_page.0,
+            source_start_page: edge.source_start_page.1,
+            source_start_offset: edge.source_start_offset,
+            source_end_part: edge.source_end_page.0,
+            source_end_page: edge.source_end_page.1,
+            source_end_offset: edge.source_end_offset,
+            source_global_start: edge.source_global_start,
+            source_global_end: edge.source_global_end,
+            target_book_id: edge.target_book_id,
+            target_start_part: edge.target_start_page.0,
+            target_start_page: edge.target_start_page.1,
+            target_start_offset: edge.target_start_offset,
+            target_end_part: edge.target_end_page.0,
+            target_end_page: edge.target_end_page.1,
+            target_end_offset: edge.target_end_offset,
+            target_global_start: edge.target_global_start,
+            target_global_end: edge.target_global_end,
+            aligned_length: edge.aligned_length,
+            lemma_matches: edge.lemma_matches,
+            substitutions: edge.substitutions,
+            root_only_matches: edge.root_only_matches,
+            gaps: edge.gaps,
+            core_similarity: edge.core_similarity,
+            span_coverage: edge.span_coverage,
+            content_weight: edge.content_weight,
+            lexical_diversity: edge.lexical_diversity,
+            lemma_similarity: edge.lemma_similarity,
+            combined_similarity: edge.combined_similarity,
+            weighted_similarity: edge.weighted_similarity,
+            score: edge.score,
+            normalized_score: edge.normalized_score,
+            shingle_jaccard: edge.shingle_jaccard,
+            is_verse: edge.is_verse,
+            suspect: edge.suspect,
+            quran_overlap: edge.quran_overlap,
+            source_span_length: edge.source_span_length,
+            target_span_length: edge.target_span_length,
+            span_ratio: edge.span_ratio,
+            direction: edge.direction,
+        }
+    }
+}
+
+impl From<CsvEdgeRow> for ReuseEdge {
+    fn from(row: CsvEdgeRow) -> Self {
+        let source_span_length = (row.source_global_end - row.source_global_start) as u32;
+        let target_span_length = (row.target_global_end - row.target_global_start) as u32;
+        ReuseEdge {
+            id: row.id,
+            source_book_id: row.source_book_id,
+            source_start_page: (row.source_start_part, row.source_start_page),
+            source_start_offset: row.source_start_offset,
+            source_end_page: (row.source_end_part, row.source_end_page),
+            source_end_offset: row.source_end_offset,
+            source_global_start: row.source_global_start,
+            source_global_end: row.source_global_end,
+            target_book_id: row.target_book_id,
+            target_start_page: (row.target_start_part, row.target_start_page),
+            target_start_offset: row.target_start_offset,
+            target_end_page: (row.target_end_part, row.target_end_page),
+            target_end_offset: row.target_end_offset,
+            target_global_start: row.target_global_start,
+            target_global_end: row.target_global_end,
+            aligned_length: row.aligned_length,
+            lemma_matches: row.lemma_matches,
+            substitutions: row.substitutions,
+            root_only_matches: row.root_only_matches,
+            gaps: row.gaps,
+            core_similarity: row.core_similarity,
+            span_coverage: row.span_coverage,
+            content_weight: row.content_weight,
+            lexical_diversity: row.lexical_diversity,
+            shingle_jaccard: row.shingle_jaccard,
+            is_verse: row.is_verse,
+            suspect: row.suspect,
+            quran_overlap: row.quran_overlap,
+            source_span_length,
+            target_span_length,
+            span_ratio: metrics::span_ratio(source_span_length, target_span_length),
+            direction: row.direction,
+            validation: None,
+            lemma_similarity: row.lemma_similarity,
+            combined_similarity: row.combined_similarity,
+            weighted_similarity: row.weighted_similarity,
+            avg_match_weight: row.content_weight, // Same as content_weight by construction
+            score: row.score,
+            normalized_score: row.normalized_score,
+        }
+    }
+}
+
+/// UTF-8 byte order mark; Excel reads a CSV as UTF-8 only when it starts with one.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Language of the header rows of the reviewer exports (CSV with text, Excel)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HeaderLanguage {
+    /// Column names as in the JSON output (default)
+    #[default]
+    English,
+    /// Arabic column names
+    Arabic,
+    /// An English header row followed by an Arabic one
+    Bilingual,
+}
+
+/// Arabic names of the reviewer export columns.
+const ARABIC_COLUMN_NAMES: [(&str, &str); 40] = [
+    ("id", "المعرف"),
+    ("signature", "البصمة"),
+    ("source_book_id", "رقم الكتاب المصدر"),
+    ("source_location", "موضع المصدر"),
+    ("source_global_start", "بداية المصدر"),
+    ("source_global_end", "نهاية المصدر"),
+    ("source_text_before", "سياق المصدر السابق"),
+    ("source_text_matched", "نص المصدر المطابق"),
+    ("source_text_after", "سياق المصدر اللاحق"),
+    ("target_book_id", "رقم الكتاب الهدف"),
+    ("target_location", "موضع الهدف"),
+    ("target_global_start", "بداية الهدف"),
+    ("target_global_end", "نهاية الهدف"),
+    ("target_text_before", "سياق الهدف السابق"),
+    ("target_text_matched", "نص الهدف المطابق"),
+    ("target_text_after", "سياق الهدف اللاحق"),
+    ("aligned_length", "طول المحاذاة"),
+    ("lemma_matches", "تطابق المداخل"),
+    ("gaps", "الفجوات"),
+    ("similarity", "التشابه"),
+    ("direction", "الاتجاه"),
+    ("source_title", "عنوان الكتاب المصدر"),
+    ("source_author", "مؤلف الكتاب المصدر"),
+    ("target_title", "عنوان الكتاب الهدف"),
+    ("target_author", "مؤلف الكتاب الهدف"),
+    ("substitutions", "الاستبدالات"),
+    ("root_only_matches", "تطابق الجذور وحدها"),
+    ("core_similarity", "تشابه اللب"),
+    ("span_coverage", "تغطية المقطع"),
+    ("content_weight", "وزن المحتوى"),
+    ("lexical_diversity", "التنوع المعجمي"),
+    ("shingle_jaccard", "تشابه جاكارد للقطع"),
+    ("combined_similarity", "التشابه المركب"),
+    ("weighted_similarity", "التشابه الموزون"),
+    ("score", "الدرجة"),
+    ("normalized_score", "الدرجة المعيارية"),
+    ("is_verse", "شعر"),
+    ("suspect", "مشتبه"),
+    ("quran_overlap", "نسبة النص القرآني"),
+    ("span_ratio", "نسبة طول المقطعين"),
+];
+
+impl HeaderLanguage {
+    /// Header rows for columns with the given (English) names.
+    fn rows(self, names: &[&'static str]) -> Vec<Vec<&'static str>> {
+        let arabic = || {
+            names
+                .iter()
+                .map(|&name| {
+                    ARABIC_COLUMN_NAMES
+                        .iter()
+                        .find(|(english, _)| *english == name)
+                        .map_or(name, |(_, arabic)| arabic)
+                })
+                .collect()
+        };
+        match self {
+            HeaderLanguage::English => vec![names.to_vec()],
+            HeaderLanguage::Arabic => vec![arabic()],
+            HeaderLanguage::Bilingual => vec![names.to_vec(), arabic()],
+        }
+    }
+}
+
+/// Dialect of edge CSV files. Quoting follows RFC 4180 whatever the delimiter.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CsvOptions {
+    /// Field separator (`,` by default; `;` or tab for some spreadsheet locales)
+    pub delimiter: u8,
+    /// Start the file with a UTF-8 byte order mark
+    pub bom: bool,
+    /// Header language of the CSV with text; edge CSV without text always has
+    /// English headers so it can be read back
+    pub headers: HeaderLanguage,
+    /// Columns to write; ignored when reading
+    pub columns: OutputColumns,
+}
+
+impl Default for CsvOptions {
+    fn default() -> Self {
+        CsvOptions {
+            delimiter: b',',
+            bom: false,
+            headers: HeaderLanguage::English,
+            columns: OutputColumns::default(),
+        }
+    }
+}
+
+impl CsvOptions {
+    /// A CSV writer in this dialect, after the BOM if requested. Headers are
+    /// written explicitly so that an empty edge list still yields one.
+    fn writer<W: Write>(&self, mut writer: W) -> Result<csv::Writer<W>, OutputError> {
+        if self.bom {
+            writer.write_all(UTF8_BOM)?;
+        }
+        Ok(csv::WriterBuilder::new()
+            .has_headers(false)
+            .delimiter(self.delimiter)
+            .from_writer(writer))
+    }
+
+    /// A CSV reader in this dialect. A leading BOM is skipped.
+    fn reader<R: Read>(&self, reader: R) -> csv::Reader<R> {
+        csv::ReaderBuilder::new()
+            .trim(csv::Trim::All)
+            .delimiter(self.delimiter)
+            .from_reader(reader)
+    }
+}
+
+/// Columns kept in CSV and JSON Lines edge output, by CSV header name.
+///
+/// The default selection is every column of the file's usual layout. Naming
+/// columns narrows and reorders the output; the CSV with text also has
+/// alignment metrics (`core_similarity`, `score`, ...) that are written only
+/// when named:
+///
+/// ```
+/// use kashshaf_reuse::output::OutputColumns;
+///
+/// let columns = OutputColumns::default()
+///     .column("id")
+///     .column("core_similarity")
+///     .column("source_text_matched");
+/// assert_eq!(columns.names(), ["id", "core_similarity", "source_text_matched"]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct OutputColumns {
+    names: Vec<String>,
+}
+
+impl OutputColumns {
+    /// Append a column to the selection; the first call replaces the default columns.
+    pub fn column(mut self, name: impl Into<String>) -> Self {
+        self.names.push(name.into());
+        self
+    }
+
+    /// Selected column names, in output order; empty for the default columns.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// Whether no column was named.
+    pub fn is_default(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    /// The selected columns, checked against `available`, or `default` if
+    /// none were named.
+    fn resolve(&self, default: &[&'static str], available: &[&'static str]) -> Result<Vec<&'static str>, OutputError> {
+        if self.is_default() {
+            return Ok(default.to_vec());
+        }
+        self.names
+            .iter()
+            .map(|name| {
+                available
+                    .iter()
+                    .find(|&&column| column == name)
+                    .copied()
+                    .ok_or_else(|| OutputError::UnknownColumn {
+                        column: name.clone(),
+                        available: available.join(", "),
+                    })
+            })
+            .collect()
+    }
+}
+
+/// Edges whose CSV row can be narrowed to selected columns.
+pub trait CsvColumns: Serialize {
+    /// Every column the edge's CSV row can hold.
+    fn available_columns() -> Vec<&'static str>;
+
+    /// The edge's CSV row, by column name.
+    fn column_values(&self) -> Result<serde_json::Map<String, serde_json::Value>, OutputError>;
+}
+
+impl CsvColumns for ReuseEdge {
+    fn available_columns() -> Vec<&'static str> {
+        CSV_EDGE_HEADER.to_vec()
+    }
+
+    fn column_values(&self) -> Result<serde_json::Map<String, serde_json::Value>, OutputError> {
+        row_values(&CsvEdgeRow::from(self))
+    }
+}
+
+impl CsvColumns for ReuseEdgeWithText {
+    fn available_columns() -> Vec<&'static str> {
+        CSV_TEXT_HEADER.into_iter().chain(CSV_TEXT_EXTRA_HEADER).collect()
+    }
+
+    fn column_values(&self) -> Result<serde_json::Map<String, serde_json::Value>, OutputError> {
+        row_values(&CsvTextRow::from(self))
+    }
+}
+
+impl<T: CsvColumns> CsvColumns for &T {
+    fn available_columns() -> Vec<&'static str> {
+        T::available_columns()
+    }
+
+    fn column_values(&self) -> Result<serde_json::Map<String, serde_json::Value>, OutputError> {
+        (*self).column_values()
+    }
+}
+
+/// A row's fields by name. The row goes through JSON text rather than
+/// `serde_json::to_value`, which would widen f32 values into long decimals.
+fn row_values<T: Serialize>(row: &T) -> Result<serde_json::Map<String, serde_json::Value>, OutputError> {
+    Ok(serde_json::from_str(&serde_json::to_string(row)?)?)
+}
+
+/// A row value as CSV field text.
+fn csv_cell(value: Option<&serde_json::Value>) -> String {
+    match value {
+        Some(serde_json::Value::String(text)) => text.clone(),
+        Some(serde_json::Value::Null) | None => String::new(),
+        Some(value) => value.to_string(),
+    }
+}
+
+/// Write edges as CSV.
+pub fn write_csv<W: Write>(edges: &[ReuseEdge], writer: &mut W) -> Result<(), OutputError> {
+    write_csv_with_options(edges, &CsvOptions::default(), writer)
+}
+
+/// Write edges as CSV in the given dialect.
+pub fn write_csv_with_options<W: Write>(
+    edges: &[ReuseEdge],
+    options: &CsvOptions,
+    writer: &mut W,
+) -> Result<(), OutputError> {
+    let mut csv_writer = options.writer(writer)?;
+    if options.columns.is_default() {
+        csv_writer.write_record(CSV_EDGE_HEADER)?;
+        for edge in edges {
+            csv_writer.serialize(CsvEdgeRow::from(edge))?;
+        }
+    } else {
+        let columns = options.columns.resolve(&CSV_EDGE_HEADER, &CSV_EDGE_HEADER)?;
+        csv_writer.write_record(&columns)?;
+        for edge in edges {
+            let values = edge.column_values()?;
+            csv_writer.write_record(columns.iter().map(|&column| csv_cell(values.get(column))))?;
+        }
+    }
 
+    csv_writer.flush()?;
     Ok(())
 }
 
+/// Read edges from CSV produced by `write_csv` (possibly edited in a spreadsheet).
+///
+/// Every column up to `weighted_similarity` is required, so a CSV written
+/// with fewer columns does not read back.
+pub fn read_csv_edges<R: Read>(reader: R) -> Result<Vec<ReuseEdge>, OutputError> {
+    read_csv_edges_with_options(reader, &CsvOptions::default())
+}
+
+/// Read edges from CSV in the given dialect.
+pub fn read_csv_edges_with_options<R: Read>(reader: R, options: &CsvOptions) -> Result<Vec<ReuseEdge>, OutputError> {
+    options
+        .reader(reader)
+        .deserialize::<CsvEdgeRow>()
+        .map(|row| Ok(ReuseEdge::from(row?)))
+        .collect()
+}
+
+/// Read edges from a CSV file produced by `write_csv_file`.
+pub fn read_csv_edges_file(path: &Path) -> Result<Vec<ReuseEdge>, OutputError> {
+    read_csv_edges_file_with_options(path, &CsvOptions::default())
+}
+
+/// Read edges from a CSV file in the given dialect.
+pub fn read_csv_edges_file_with_options(path: &Path, options: &CsvOptions) -> Result<Vec<ReuseEdge>, OutputError> {
+    let file = std::fs::File::open(path)?;
+    read_csv_edges_with_options(io::BufReader::new(file), options)
+}
+
 /// Write edges as CSV to a file.
 pub fn write_csv_file(edges: &[ReuseEdge], path: &Path) -> Result<(), OutputError> {
+    write_csv_file_with_options(edges, &CsvOptions::default(), path)
+}
+
+/// Write edges as CSV to a file in the given dialect.
+pub fn write_csv_file_with_options(edges: &[ReuseEdge], options: &CsvOptions, path: &Path) -> Result<(), OutputError> {
     let mut file = std::fs::File::create(path)?;
-    write_csv(edges, &mut file)
+    write_csv_with_options(edges, options, &mut file)
+}
+
+/// Write edges as JSON Lines, one compact edge object per line.
+///
+/// Each edge is serialized on its own as it is written, so no document
+/// holding every edge is built in memory. Works for `ReuseEdge` and
+/// `ReuseEdgeWithText` alike.
+pub fn write_edges_jsonl<T: Serialize, W: Write>(
+    edges: impl IntoIterator<Item = T>,
+    writer: &mut W,
+) -> Result<(), OutputError> {
+    for edge in edges {
+        serde_json::to_writer(&mut *writer, &edge)?;
+        writer.write_all(b"\n")?;
+    }
+    Ok(())
+}
+
+/// Write edges as JSON Lines to a file, buffered.
+pub fn write_edges_jsonl_file<T: Serialize>(
+    edges: impl IntoIterator<Item = T>,
+    path: &Path,
+) -> Result<(), OutputError> {
+    let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+    write_edges_jsonl(edges, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write edges as JSON Lines with only the selected columns.
+///
+/// With the default selection each line is the full edge object, as from
+/// `write_edges_jsonl`. Otherwise each line is a flat object holding the
+/// named CSV columns, in the order given.
+pub fn write_edges_jsonl_with_columns<T: CsvColumns, W: Write>(
+    edges: impl IntoIterator<Item = T>,
+    columns: &OutputColumns,
+    writer: &mut W,
+) -> Result<(), OutputError> {
+    if columns.is_default() {
+        return write_edges_jsonl(edges, writer);
+    }
+    let available = T::available_columns();
+    let columns = columns.resolve(&available, &available)?;
+    for edge in edges {
+        let values = edge.column_values()?;
+        writer.write_all(b"{")?;
+        for (i, &column) in columns.iter().enumerate() {
+            if i > 0 {
+                writer.write_all(b",")?;
+            }
+            serde_json::to_writer(&mut *writer, column)?;
+            writer.write_all(b":")?;
+            serde_json::to_writer(&mut *writer, values.get(column).unwrap_or(&serde_json::Value::Null))?;
+        }
+        writer.write_all(b"}\n")?;
+    }
+    Ok(())
+}
+
+/// Write edges as JSON Lines with only the selected columns to a file, buffered.
+pub fn write_edges_jsonl_file_with_columns<T: CsvColumns>(
+    edges: impl IntoIterator<Item = T>,
+    columns: &OutputColumns,
+    path: &Path,
+) -> Result<(), OutputError> {
+    let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+    write_edges_jsonl_with_columns(edges, columns, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Summary file accompanying a JSON Lines edge file: `out.jsonl` becomes
+/// `out.summary.json`.
+pub fn jsonl_summary_path(path: &Path) -> PathBuf {
+    path.with_extension("summary.json")
+}
+
+// ============================================================================
+// passim interchange
+// ============================================================================
+
+/// One aligned passage pair in the pairwise alignment schema of passim (and
+/// seriatim), for tools that already read that format.
+///
+/// passim gives `b`/`e` as character offsets into the document text; here
+/// they are token offsets into the book's token stream (end exclusive), and
+/// `n1`/`n2` are the books' token counts.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PassimAlignment {
+    pub id1: String,
+    pub id2: String,
+    pub b1: usize,
+    pub e1: usize,
+    pub n1: u64,
+    pub b2: usize,
+    pub e2: usize,
+    pub n2: u64,
+    /// Matched text of each side
+    pub s1: String,
+    pub s2: String,
+    pub matches: u32,
+    pub score: i32,
+}
+
+/// The edges of a result as passim alignment records.
+pub fn passim_alignments(result: &ComparisonResultWithText) -> impl Iterator<Item = PassimAlignment> + '_ {
+    result.edges.iter().map(|edge| PassimAlignment {
+        id1: edge.source.book_id.to_string(),
+        id2: edge.target.book_id.to_string(),
+        b1: edge.source.global_range.0,
+        e1: edge.source.global_range.1,
+        n1: result.book_a.token_count,
+        b2: edge.target.global_range.0,
+        e2: edge.target.global_range.1,
+        n2: result.book_b.token_count,
+        s1: edge.source.text.matched.clone(),
+        s2: edge.target.text.matched.clone(),
+        matches: edge.alignment.lemma_matches,
+        score: edge.alignment.score,
+    })
+}
+
+/// Write a result as passim alignment records, one JSON object per line.
+pub fn write_passim_json<W: Write>(result: &ComparisonResultWithText, writer: &mut W) -> Result<(), OutputError> {
+    write_edges_jsonl(passim_alignments(result), writer)
+}
+
+/// Write a result as passim alignment records to a file.
+pub fn write_passim_json_file(result: &ComparisonResultWithText, path: &Path) -> Result<(), OutputError> {
+    write_edges_jsonl_file(passim_alignments(result), path)
 }
 
 /// Write a summary report to stdout.
@@ -102,15 +1142,212 @@ pub fn print_summary(result: &ComparisonResult) {
     println!("  N-gram size: {}", result.parameters.ngram_size);
     println!("  Min shared shingles: {}", result.parameters.min_shared_shingles);
     println!("  Min length: {}", result.parameters.min_length);
+    if let Some(min) = result.parameters.min_matched_tokens {
+        println!("  Min matched tokens: {}", min);
+    }
+    if let Some(min) = result.parameters.min_span_jaccard {
+        println!("  Min span Jaccard: {:.2}", min);
+    }
+    if let Some(min) = result.parameters.min_verse_core_similarity {
+        println!("  Min verse core similarity: {:.2}", min);
+    }
+    if result.parameters.drop_suspect {
+        println!("  Drop suspect edges: yes");
+    }
+    if let Some(book_id) = result.parameters.quran_book_id {
+        match result.parameters.max_quran_overlap {
+            Some(max) => println!("  Quran: book {}, max overlap {:.2}", book_id, max),
+            None => println!("  Quran: book {}", book_id),
+        }
+    }
+    if let Some(max) = result.parameters.max_span_asymmetry {
+        println!("  Max span asymmetry: {:.2}", max);
+    }
+    if let Some(min) = result.parameters.min_merge_core_similarity {
+        println!("  Min merge core similarity: {:.2}", min);
+    }
+    if result.parameters.refine_merges {
+        println!("  Refine merges: yes");
+    }
+    if let Some(gap) = result.parameters.chain_gap {
+        println!("  Chain gap: {}", gap);
+    }
+    match result.parameters.coverage_bins {
+        Some(CoverageBins::Tokens(n)) => println!("  Coverage bins: {} tokens", n),
+        Some(CoverageBins::Pages) => println!("  Coverage bins: pages"),
+        None => {}
+    }
+    if result.parameters.segmentation == Segmentation::Hadith {
+        println!("  Segmentation: hadith reports");
+    }
+    if let Some(size) = result.parameters.coarse_window_size {
+        let stride = result.parameters.coarse_stride.unwrap_or(size / 2);
+        println!("  Coarse pass: window {}, stride {}", size, stride);
+    }
+    if let Some((start, end)) = result.parameters.source_range {
+        println!("  Source range: {}:{}", start, end);
+    }
     println!("  Min similarity: {:.1}%", result.parameters.min_similarity * 100.0);
     println!("  Brute force: {}", result.parameters.brute_force);
+    if result.parameters.candidate_method != CandidateMethod::Shingles {
+        println!("  Candidate method: {:?}", result.parameters.candidate_method);
+    }
+    if result.parameters.shingle_kind != ShingleKind::Contiguous {
+        println!("  Shingles: {:?}", result.parameters.shingle_kind);
+    }
     println!();
     println!("Results:");
     println!("  Edges found: {}", result.summary.edge_count);
     println!("  Total aligned tokens: {}", result.summary.total_aligned_tokens);
-    println!("  Book A coverage: {:.1}%", result.summary.book_a_coverage * 100.0);
-    println!("  Book B coverage: {:.1}%", result.summary.book_b_coverage * 100.0);
+    println!(
+        "  Book A coverage: {}",
+        format_coverage(result.summary.book_a_coverage, result.summary.book_a_coverage_ci)
+    );
+    println!(
+        "  Book B coverage: {}",
+        format_coverage(result.summary.book_b_coverage, result.summary.book_b_coverage_ci)
+    );
     println!("  Average similarity: {:.1}%", result.summary.avg_similarity * 100.0);
+    print_edge_statistics(&result.summary);
+    if result.summary.edge_count > 1 {
+        println!("  Order preservation (Kendall tau): {:.2}", result.summary.order_tau);
+    }
+    if result.summary.aborted_alignments > 0 {
+        println!("  Abandoned alignments: {}", result.summary.aborted_alignments);
+    }
+    if result.run_info.stats.capped_pairs > 0 {
+        println!(
+            "  Capped candidates: {} pairs dropped from {} windows",
+            result.run_info.stats.capped_pairs, result.run_info.stats.capped_windows
+        );
+    }
+    if !result.macro_edges.is_empty() {
+        println!(
+            "  Macro edges: {} (chaining {} edges)",
+            result.macro_edges.len(),
+            result.macro_edges.iter().map(|m| m.edge_ids.len()).sum::<usize>()
+        );
+    }
+    println!("  Pipeline: {}", format_run_stats(&result.run_info));
+    println!("  Timings: {}", format_run_info(&result.run_info));
+}
+
+/// Print a summary's per-book aligned tokens, edge classes and length and
+/// similarity distributions. Summaries read from older results have none.
+fn print_edge_statistics(summary: &ComparisonSummary) {
+    if summary.length_distribution.bins.is_empty() {
+        return;
+    }
+    println!(
+        "  Aligned tokens: A {}, B {}",
+        summary.book_a_aligned_tokens, summary.book_b_aligned_tokens
+    );
+    let classes = &summary.edge_classes;
+    println!(
+        "  Edges by class: {} prose, {} verse, {} suspect, {} Quran",
+        classes.prose, classes.verse, classes.suspect, classes.quran
+    );
+    if summary.edge_count == 0 {
+        return;
+    }
+    let tokens = |value: f32| format!("{:.0}", value);
+    let percent = |value: f32| format!("{:.0}%", value * 100.0);
+    println!("  Edge length: {}", format_distribution(&summary.length_distribution, tokens));
+    for line in format_histogram(&summary.length_distribution, tokens) {
+        println!("    {}", line);
+    }
+    println!("  Similarity: {}", format_distribution(&summary.similarity_distribution, percent));
+    for line in format_histogram(&summary.similarity_distribution, percent) {
+        println!("    {}", line);
+    }
+}
+
+/// Format the percentiles of a distribution as a single line.
+pub fn format_distribution(distribution: &Distribution, value: impl Fn(f32) -> String) -> String {
+    format!(
+        "min {}, p25 {}, median {}, p75 {}, p90 {}, max {}",
+        value(distribution.min),
+        value(distribution.p25),
+        value(distribution.median),
+        value(distribution.p75),
+        value(distribution.p90),
+        value(distribution.max)
+    )
+}
+
+/// Widest bar of a console histogram, in characters.
+const HISTOGRAM_WIDTH: usize = 30;
+
+/// Format a distribution's histogram, one line per bin with a bar scaled to
+/// the fullest bin.
+pub fn format_histogram(distribution: &Distribution, value: impl Fn(f32) -> String) -> Vec<String> {
+    let fullest = distribution.counts.iter().copied().max().unwrap_or(0).max(1);
+    distribution
+        .bins
+        .iter()
+        .zip(&distribution.counts)
+        .enumerate()
+        .map(|(i, (&lower, &count))| {
+            let label = match distribution.bins.get(i + 1) {
+                Some(&upper) => format!("{}-{}", value(lower), value(upper)),
+                None => format!("{}+", value(lower)),
+            };
+            let bar = "#".repeat((count * HISTOGRAM_WIDTH).div_ceil(fullest));
+            format!("{:>9} {:>6} {}", label, count, bar).trim_end().to_string()
+        })
+        .collect()
+}
+
+/// Format a coverage figure, with its confidence interval if one was computed.
+pub fn format_coverage(coverage: f32, ci: Option<(f32, f32)>) -> String {
+    match ci {
+        Some((lo, hi)) => format!(
+            "{:.1}% (95% CI {:.1}-{:.1}%)",
+            coverage * 100.0,
+            lo * 100.0,
+            hi * 100.0
+        ),
+        None => format!("{:.1}%", coverage * 100.0),
+    }
+}
+
+/// Format per-stage timings as a single line, in stage order.
+pub fn format_run_info(run_info: &RunInfo) -> String {
+    let stages = [
+        ("load", run_info.load_ms),
+        ("window", run_info.window_ms),
+        ("filter", run_info.filter_ms),
+        ("align", run_info.align_ms),
+        ("merge", run_info.merge_ms),
+        ("filter-metrics", run_info.filter_metrics_ms),
+        ("text", run_info.text_ms),
+    ];
+    stages
+        .iter()
+        .map(|(stage, ms)| format!("{} {}ms", stage, ms))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Format the counts of each pipeline stage (and peak memory, if known) as a
+/// single line.
+pub fn format_run_stats(run_info: &RunInfo) -> String {
+    let stats = &run_info.stats;
+    let mut line = format!(
+        "{} + {} windows, {} candidates, {} aligned ({} rejected), {} raw edges, {} merged, {} dropped",
+        stats.source_windows,
+        stats.target_windows,
+        stats.candidate_pairs,
+        stats.alignments,
+        stats.rejected_alignments,
+        stats.raw_edges,
+        stats.merged_edges,
+        stats.dropped_edges
+    );
+    if let Some(bytes) = run_info.peak_rss_bytes {
+        line.push_str(&format!(", peak RSS {:.1} MB", bytes as f64 / (1024.0 * 1024.0)));
+    }
+    line
 }
 
 /// Format a page location as a string.
@@ -118,6 +1355,16 @@ pub fn format_page_location(part_index: u32, page_id: u32, offset: u32) -> Strin
     format!("{}:{}.{}", part_index, page_id, offset)
 }
 
+/// Output path for one of several comparison targets: `out.json` becomes `out_553.json`.
+pub fn target_output_path(path: &Path, book_id: u32) -> PathBuf {
+    let stem = path.file_stem().map(|s| s.to_string_lossy()).unwrap_or_default();
+    let name = match path.extension() {
+        Some(ext) => format!("{}_{}.{}", stem, book_id, ext.to_string_lossy()),
+        None => format!("{}_{}", stem, book_id),
+    };
+    path.with_file_name(name)
+}
+
 /// Format an edge as a human-readable string.
 pub fn format_edge(edge: &ReuseEdge) -> String {
     let diversity_label = if edge.lexical_diversity < 0.55 {
@@ -126,19 +1373,27 @@ pub fn format_edge(edge: &ReuseEdge) -> String {
         "substantive"
     };
     format!(
-        "Edge {}: len={} matches={} subs={} gaps={}\n\
+        "Edge {}{}{}: len={} matches={} subs={} gaps={} score={} ({:.2}/tok)\n\
          \x20 Core: {:.1}%  Coverage: {:.1}%  Weight: {:.2}  Diversity: {:.2} ({})\n\
+         \x20 Spans: {}→{} tokens (ratio {:.2})\n\
          \x20 Book {} [{}→{}] ↔ Book {} [{}→{}]",
         edge.id,
+        if edge.is_verse { " [verse]" } else { "" },
+        if edge.suspect { " [suspect]" } else { "" },
         edge.aligned_length,
         edge.lemma_matches,
         edge.substitutions,
         edge.gaps,
+        edge.score,
+        edge.normalized_score,
         edge.core_similarity * 100.0,
         edge.span_coverage * 100.0,
         edge.content_weight,
         edge.lexical_diversity,
         diversity_label,
+        edge.source_span_length,
+        edge.target_span_length,
+        edge.span_ratio,
         edge.source_book_id,
         format_page_location(
             edge.source_start_page.0,
@@ -164,89 +1419,516 @@ pub fn format_edge(edge: &ReuseEdge) -> String {
     )
 }
 
+/// Format an edge on one line: locations, length, core similarity and score.
+pub fn format_edge_compact(edge: &ReuseEdge) -> String {
+    format!(
+        "#{} {} ↔ {}  len={} core={:.0}% score={}{}{}",
+        edge.id,
+        format_page_location(edge.source_start_page.0, edge.source_start_page.1, edge.source_start_offset),
+        format_page_location(edge.target_start_page.0, edge.target_start_page.1, edge.target_start_offset),
+        edge.aligned_length,
+        edge.core_similarity * 100.0,
+        edge.score,
+        if edge.is_verse { " [verse]" } else { "" },
+        if edge.suspect { " [suspect]" } else { "" },
+    )
+}
+
 /// Print edges in a human-readable format.
 pub fn print_edges(edges: &[ReuseEdge], limit: Option<usize>) {
-    let to_print = match limit {
-        Some(n) => &edges[..n.min(edges.len())],
-        None => edges,
-    };
+    print_edges_with_options(
+        edges,
+        &EdgePrintOptions {
+            limit,
+            ..Default::default()
+        },
+    );
+}
+
+/// Print edges selected, ordered and laid out by `options`.
+pub fn print_edges_with_options(edges: &[ReuseEdge], options: &EdgePrintOptions) {
+    print_edges_by(edges, options, edge_fields, |edge, compact| {
+        if compact {
+            format_edge_compact(edge)
+        } else {
+            format_edge(edge)
+        }
+    });
+}
+
+fn edge_fields(edge: &ReuseEdge) -> EdgeFields {
+    EdgeFields {
+        id: edge.id,
+        position: (edge.source_global_start, edge.target_global_start),
+        similarity: edge.core_similarity,
+        length: edge.aligned_length,
+        score: edge.score,
+        page: format!("{}:{}", edge.source_start_page.0, edge.source_start_page.1),
+    }
+}
+
+// ============================================================================
+// Console edge ordering
+// ============================================================================
+
+/// Sort key for the console edge printers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeSort {
+    /// Edge ID, ascending
+    Id,
+    /// Source position, then target position
+    Position,
+    /// Core similarity, highest first
+    Similarity,
+    /// Aligned length, longest first
+    Length,
+    /// Alignment score, highest first
+    Score,
+}
+
+/// Which edges the console printers show, in what order and how.
+#[derive(Debug, Clone, Default)]
+pub struct EdgePrintOptions {
+    /// Print at most this many edges, after sorting
+    pub limit: Option<usize>,
+    /// Sort keys, most significant first; ties keep the result's order
+    pub sort: Vec<EdgeSort>,
+    /// Print edges under a heading per source start page, pages in book order
+    pub group_by_page: bool,
+    /// One line per edge instead of the full block
+    pub compact: bool,
+}
+
+/// The fields an edge is sorted and grouped by, for either edge type.
+struct EdgeFields {
+    id: u64,
+    position: (usize, usize),
+    similarity: f32,
+    length: u32,
+    score: i32,
+    /// `part:page` of the source start
+    page: String,
+}
+
+fn compare_edge_fields(a: &EdgeFields, b: &EdgeFields, keys: &[EdgeSort]) -> std::cmp::Ordering {
+    keys.iter()
+        .map(|key| match key {
+            EdgeSort::Id => a.id.cmp(&b.id),
+            EdgeSort::Position => a.position.cmp(&b.position),
+            EdgeSort::Similarity => b.similarity.total_cmp(&a.similarity),
+            EdgeSort::Length => b.length.cmp(&a.length),
+            EdgeSort::Score => b.score.cmp(&a.score),
+        })
+        .find(|ordering| ordering.is_ne())
+        .unwrap_or(std::cmp::Ordering::Equal)
+}
+
+/// Sort, cut and optionally group edges of either type, then print each with
+/// `format(edge, compact)`.
+fn print_edges_by<E>(
+    edges: &[E],
+    options: &EdgePrintOptions,
+    fields: impl Fn(&E) -> EdgeFields,
+    format: impl Fn(&E, bool) -> String,
+) {
+    for line in edge_listing(edges, options, fields, format) {
+        println!("{}", line);
+    }
+}
+
+/// The lines `print_edges_by` prints.
+fn edge_listing<E>(
+    edges: &[E],
+    options: &EdgePrintOptions,
+    fields: impl Fn(&E) -> EdgeFields,
+    format: impl Fn(&E, bool) -> String,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut ordered: Vec<(EdgeFields, &E)> = edges.iter().map(|edge| (fields(edge), edge)).collect();
+    ordered.sort_by(|(a, _), (b, _)| compare_edge_fields(a, b, &options.sort));
+    ordered.truncate(options.limit.unwrap_or(usize::MAX));
+
+    if options.group_by_page {
+        // (page, first source position, edges), ordered by position below
+        let mut groups: Vec<(String, usize, Vec<&E>)> = Vec::new();
+        for (fields, edge) in ordered {
+            match groups.iter_mut().find(|(page, _, _)| *page == fields.page) {
+                Some((_, start, group)) => {
+                    *start = (*start).min(fields.position.0);
+                    group.push(edge);
+                }
+                None => groups.push((fields.page, fields.position.0, vec![edge])),
+            }
+        }
+        groups.sort_by_key(|&(_, start, _)| start);
+        for (page, _, group) in groups {
+            lines.push(format!("── Source page {} ({} edges)", page, group.len()));
+            lines.extend(group.into_iter().map(|edge| format(edge, options.compact)));
+        }
+    } else {
+        lines.extend(ordered.into_iter().map(|(_, edge)| format(edge, options.compact)));
+    }
+
+    if let Some(n) = options.limit {
+        if edges.len() > n {
+            lines.push(format!("... and {} more edges", edges.len() - n));
+        }
+    }
+    lines
+}
+
+// ============================================================================
+// Enhanced output with text
+// ============================================================================
+
+/// Write comparison result with text as JSON.
+pub fn write_json_with_text<W: Write>(
+    result: &ComparisonResultWithText,
+    writer: &mut W,
+) -> Result<(), OutputError> {
+    let json = serde_json::to_string_pretty(result)?;
+    writer.write_all(json.as_bytes())?;
+    Ok(())
+}
+
+/// Write comparison result with text as JSON to a file.
+pub fn write_json_with_text_file(
+    result: &ComparisonResultWithText,
+    path: &Path,
+) -> Result<(), OutputError> {
+    let mut file = std::fs::File::create(path)?;
+    write_json_with_text(result, &mut file)
+}
+
+/// Read a comparison result written by `write_json_with_text_file`.
+pub fn read_json_with_text_file(path: &Path) -> Result<ComparisonResultWithText, OutputError> {
+    let file = std::fs::File::open(path)?;
+    Ok(serde_json::from_reader(io::BufReader::new(file))?)
+}
+
+// ============================================================================
+// Viewer validations
+// ============================================================================
+
+/// One row of the viewer's "Export Validated" CSV; the text and similarity
+/// columns are ignored.
+#[derive(Deserialize)]
+struct ValidationRow {
+    id: u64,
+    validation: Validation,
+}
+
+/// Read edge labels from a CSV exported by the viewer.
+pub fn read_validations<R: Read>(reader: R) -> Result<HashMap<u64, Validation>, OutputError> {
+    let mut csv_reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(reader);
+
+    csv_reader
+        .deserialize::<ValidationRow>()
+        .map(|row| {
+            let row = row?;
+            Ok((row.id, row.validation))
+        })
+        .collect()
+}
+
+/// Read edge labels from a viewer CSV file.
+pub fn read_validations_file(path: &Path) -> Result<HashMap<u64, Validation>, OutputError> {
+    let file = std::fs::File::open(path)?;
+    read_validations(io::BufReader::new(file))
+}
+
+/// Keep the edges a reviewer accepted, label them, and recompute the summary.
+///
+/// Labels in `validations` replace any an edge already carries. Edges marked
+/// noise are dropped, and so are unlabelled edges unless `keep_unreviewed`;
+/// macro-edges are dropped with any of their members.
+pub fn apply_validations(
+    mut result: ComparisonResult,
+    validations: &HashMap<u64, Validation>,
+    keep_unreviewed: bool,
+) -> ComparisonResult {
+    result.edges.retain_mut(|edge| {
+        edge.validation = validations.get(&edge.id).copied().or(edge.validation);
+        is_accepted(edge.validation, keep_unreviewed)
+    });
+    let kept: HashSet<u64> = result.edges.iter().map(|e| e.id).collect();
+    result.macro_edges.retain(|m| m.edge_ids.iter().all(|id| kept.contains(id)));
+    result.recompute_summary();
+    result
+}
+
+/// Like [`apply_validations`], for a result with text.
+pub fn apply_validations_with_text(
+    mut result: ComparisonResultWithText,
+    validations: &HashMap<u64, Validation>,
+    keep_unreviewed: bool,
+) -> ComparisonResultWithText {
+    result.edges.retain_mut(|edge| {
+        edge.validation = validations.get(&edge.id).copied().or(edge.validation);
+        is_accepted(edge.validation, keep_unreviewed)
+    });
+    let kept: HashSet<u64> = result.edges.iter().map(|e| e.id).collect();
+    result.macro_edges.retain(|m| m.edge_ids.iter().all(|id| kept.contains(id)));
+    result.recompute_summary();
+    result
+}
+
+fn is_accepted(validation: Option<Validation>, keep_unreviewed: bool) -> bool {
+    match validation {
+        Some(Validation::Valid) => true,
+        Some(Validation::Noise) => false,
+        None => keep_unreviewed,
+    }
+}
+
+/// All `*.json` files under `dir`, recursively and in sorted order. Symbolic
+/// links to directories are not followed.
+pub(crate) fn json_files_under(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries = std::fs::read_dir(dir)?.collect::<Result<Vec<_>, _>>()?;
+    entries.sort_by_key(|entry| entry.path());
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            files.extend(json_files_under(&path)?);
+        } else if path.extension().is_some_and(|ext| ext == "json") {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
 
-    for edge in to_print {
-        println!("{}", format_edge(edge));
+/// Every result with text under `dir` (see [`json_files_under`]). Other JSON
+/// files, plain results among them, are skipped, and so is a pair already
+/// read from an earlier file.
+pub fn read_results_with_text_dir(dir: &Path) -> Result<Vec<ComparisonResultWithText>, OutputError> {
+    let mut seen = HashSet::new();
+    let mut results = Vec::new();
+    for path in json_files_under(dir)? {
+        let result = match read_json_with_text_file(&path) {
+            Ok(result) => result,
+            Err(OutputError::Json(_)) => continue,
+            Err(e) => return Err(e),
+        };
+        let (a, b) = (result.book_a.id, result.book_b.id);
+        if seen.insert((a.min(b), a.max(b))) {
+            results.push(result);
+        }
     }
+    Ok(results)
+}
 
-    if let Some(n) = limit {
-        if edges.len() > n {
-            println!("... and {} more edges", edges.len() - n);
+/// Column order of the edge-with-text CSV.
+const CSV_TEXT_HEADER: [&str; 21] = [
+    "id",
+    "signature",
+    "source_book_id",
+    "source_location",
+    "source_global_start",
+    "source_global_end",
+    "source_text_before",
+    "source_text_matched",
+    "source_text_after",
+    "target_book_id",
+    "target_location",
+    "target_global_start",
+    "target_global_end",
+    "target_text_before",
+    "target_text_matched",
+    "target_text_after",
+    "aligned_length",
+    "lemma_matches",
+    "gaps",
+    "similarity",
+    "direction",
+];
+
+/// Alignment metrics the edge-with-text CSV holds only when they are selected
+/// (`CsvOptions::columns`).
+const CSV_TEXT_EXTRA_HEADER: [&str; 15] = [
+    "substitutions",
+    "root_only_matches",
+    "core_similarity",
+    "span_coverage",
+    "content_weight",
+    "lexical_diversity",
+    "shingle_jaccard",
+    "combined_similarity",
+    "weighted_similarity",
+    "score",
+    "normalized_score",
+    "is_verse",
+    "suspect",
+    "quran_overlap",
+    "span_ratio",
+];
+
+/// One row of the edge-with-text CSV: the CSV_TEXT_HEADER columns, then the
+/// CSV_TEXT_EXTRA_HEADER ones.
+#[derive(Serialize)]
+struct CsvTextRow<'a> {
+    id: u64,
+    signature: &'a str,
+    source_book_id: u32,
+    source_location: &'a str,
+    source_global_start: usize,
+    source_global_end: usize,
+    source_text_before: &'a str,
+    source_text_matched: &'a str,
+    source_text_after: &'a str,
+    target_book_id: u32,
+    target_location: &'a str,
+    target_global_start: usize,
+    target_global_end: usize,
+    target_text_before: &'a str,
+    target_text_matched: &'a str,
+    target_text_after: &'a str,
+    aligned_length: u32,
+    lemma_matches: u32,
+    gaps: u32,
+    similarity: f32,
+    direction: Direction,
+    substitutions: u32,
+    root_only_matches: u32,
+    core_similarity: f32,
+    span_coverage: f32,
+    content_weight: f32,
+    lexical_diversity: f32,
+    shingle_jaccard: f32,
+    combined_similarity: f32,
+    weighted_similarity: f32,
+    score: i32,
+    normalized_score: f32,
+    is_verse: bool,
+    suspect: bool,
+    quran_overlap: f32,
+    span_ratio: f32,
+}
+
+impl<'a> From<&'a ReuseEdgeWithText> for CsvTextRow<'a> {
+    fn from(edge: &'a ReuseEdgeWithText) -> Self {
+        let alignment = &edge.alignment;
+        CsvTextRow {
+            id: edge.id,
+            signature: &edge.signature,
+            source_book_id: edge.source.book_id,
+            source_location: &edge.source.location,
+            source_global_start: edge.source.global_range.0,
+            source_global_end: edge.source.global_range.1,
+            source_text_before: &edge.source.text.before,
+            source_text_matched: &edge.source.text.matched,
+            source_text_after: &edge.source.text.after,
+            target_book_id: edge.target.book_id,
+            target_location: &edge.target.location,
+            target_global_start: edge.target.global_range.0,
+            target_global_end: edge.target.global_range.1,
+            target_text_before: &edge.target.text.before,
+            target_text_matched: &edge.target.text.matched,
+            target_text_after: &edge.target.text.after,
+            aligned_length: alignment.length,
+            lemma_matches: alignment.lemma_matches,
+            gaps: alignment.gaps,
+            similarity: alignment.similarity,
+            direction: edge.direction,
+            substitutions: alignment.substitutions,
+            root_only_matches: alignment.root_only_matches,
+            core_similarity: alignment.core_similarity,
+            span_coverage: alignment.span_coverage,
+            content_weight: alignment.content_weight,
+            lexical_diversity: alignment.lexical_diversity,
+            shingle_jaccard: alignment.shingle_jaccard,
+            combined_similarity: alignment.combined_similarity,
+            weighted_similarity: alignment.weighted_similarity,
+            score: alignment.score,
+            normalized_score: alignment.normalized_score,
+            is_verse: alignment.is_verse,
+            suspect: alignment.suspect,
+            quran_overlap: alignment.quran_overlap,
+            span_ratio: alignment.span_ratio,
         }
     }
 }
 
-// ============================================================================
-// Enhanced output with text
-// ============================================================================
+/// Write edges with text as CSV.
+pub fn write_csv_with_text<W: Write>(
+    edges: &[ReuseEdgeWithText],
+    writer: &mut W,
+) -> Result<(), OutputError> {
+    write_csv_with_text_options(edges, &CsvOptions::default(), writer)
+}
 
-/// Write comparison result with text as JSON.
-pub fn write_json_with_text<W: Write>(
+/// Columns naming each edge's books, after the CSV_TEXT_HEADER columns.
+const BOOK_LABEL_HEADER: [&str; 4] = ["source_title", "source_author", "target_title", "target_author"];
+
+/// Write edges with text as CSV in the given dialect.
+pub fn write_csv_with_text_options<W: Write>(
+    edges: &[ReuseEdgeWithText],
+    options: &CsvOptions,
+    writer: &mut W,
+) -> Result<(), OutputError> {
+    write_text_csv(edges, None, options, writer)
+}
+
+/// Write a result's edges with text as CSV, followed by the title and author
+/// of each edge's two books, so the file can be read without a book list.
+pub fn write_result_csv_with_text<W: Write>(
     result: &ComparisonResultWithText,
+    options: &CsvOptions,
     writer: &mut W,
 ) -> Result<(), OutputError> {
-    let json = serde_json::to_string_pretty(result)?;
-    writer.write_all(json.as_bytes())?;
-    Ok(())
+    write_text_csv(&result.edges, Some((&result.book_a, &result.book_b)), options, writer)
 }
 
-/// Write comparison result with text as JSON to a file.
-pub fn write_json_with_text_file(
+/// Write a result's edges with text, and their book titles, as a CSV file.
+pub fn write_result_csv_with_text_file(
     result: &ComparisonResultWithText,
+    options: &CsvOptions,
     path: &Path,
 ) -> Result<(), OutputError> {
     let mut file = std::fs::File::create(path)?;
-    write_json_with_text(result, &mut file)
+    write_result_csv_with_text(result, options, &mut file)
 }
 
-/// Write edges with text as CSV.
-pub fn write_csv_with_text<W: Write>(
+/// The book of `books` with this ID (the second one if neither matches).
+fn book_label<'a>(books: (&'a ViewerBookInfo, &'a ViewerBookInfo), book_id: u32) -> &'a ViewerBookInfo {
+    if books.0.id == book_id {
+        books.0
+    } else {
+        books.1
+    }
+}
+
+fn write_text_csv<W: Write>(
     edges: &[ReuseEdgeWithText],
+    books: Option<(&ViewerBookInfo, &ViewerBookInfo)>,
+    options: &CsvOptions,
     writer: &mut W,
 ) -> Result<(), OutputError> {
-    // Write header
-    writeln!(
-        writer,
-        "id,source_book_id,source_location,source_global_start,source_global_end,\
-         source_text_before,source_text_matched,source_text_after,\
-         target_book_id,target_location,target_global_start,target_global_end,\
-         target_text_before,target_text_matched,target_text_after,\
-         aligned_length,lemma_matches,gaps,similarity"
-    )?;
+    let mut default = CSV_TEXT_HEADER.to_vec();
+    let mut available = ReuseEdgeWithText::available_columns();
+    if books.is_some() {
+        default.extend(BOOK_LABEL_HEADER);
+        available.extend(BOOK_LABEL_HEADER);
+    }
+    let columns = options.columns.resolve(&default, &available)?;
+    let mut csv_writer = options.writer(writer)?;
+    for row in options.headers.rows(&columns) {
+        csv_writer.write_record(row)?;
+    }
 
-    // Write rows
     for edge in edges {
-        writeln!(
-            writer,
-            "{},{},{:?},{},{},{:?},{:?},{:?},{},{},{},{},{:?},{:?},{:?},{},{},{},{}",
-            edge.id,
-            edge.source.book_id,
-            edge.source.location,
-            edge.source.global_range.0,
-            edge.source.global_range.1,
-            edge.source.text.before,
-            edge.source.text.matched,
-            edge.source.text.after,
-            edge.target.book_id,
-            edge.target.location,
-            edge.target.global_range.0,
-            edge.target.global_range.1,
-            edge.target.text.before,
-            edge.target.text.matched,
-            edge.target.text.after,
-            edge.alignment.length,
-            edge.alignment.lemma_matches,
-            edge.alignment.gaps,
-            edge.alignment.similarity
-        )?;
+        let mut values = edge.column_values()?;
+        if let Some(books) = books {
+            for (side, book_id) in [("source", edge.source.book_id), ("target", edge.target.book_id)] {
+                let book = book_label(books, book_id);
+                values.insert(format!("{}_title", side), book.title.clone().into());
+                values.insert(format!("{}_author", side), book.author.clone().into());
+            }
+        }
+        csv_writer.write_record(columns.iter().map(|&column| csv_cell(values.get(column))))?;
     }
 
+    csv_writer.flush()?;
     Ok(())
 }
 
@@ -254,27 +1936,75 @@ pub fn write_csv_with_text<W: Write>(
 pub fn write_csv_with_text_file(
     edges: &[ReuseEdgeWithText],
     path: &Path,
+) -> Result<(), OutputError> {
+    write_csv_with_text_file_options(edges, &CsvOptions::default(), path)
+}
+
+/// Write edges with text as CSV to a file in the given dialect.
+pub fn write_csv_with_text_file_options(
+    edges: &[ReuseEdgeWithText],
+    options: &CsvOptions,
+    path: &Path,
 ) -> Result<(), OutputError> {
     let mut file = std::fs::File::create(path)?;
-    write_csv_with_text(edges, &mut file)
+    write_csv_with_text_options(edges, options, &mut file)
 }
 
 /// Print edges with text in a human-readable format.
 pub fn print_edges_with_text(edges: &[ReuseEdgeWithText], limit: Option<usize>) {
-    let to_print = match limit {
-        Some(n) => &edges[..n.min(edges.len())],
-        None => edges,
-    };
+    print_edges_with_text_options(
+        edges,
+        &EdgePrintOptions {
+            limit,
+            ..Default::default()
+        },
+    );
+}
 
-    for edge in to_print {
-        println!("{}", format_edge_with_text(edge));
-    }
+/// Print edges with text selected, ordered and laid out by `options`.
+pub fn print_edges_with_text_options(edges: &[ReuseEdgeWithText], options: &EdgePrintOptions) {
+    print_edges_by(
+        edges,
+        options,
+        |edge| EdgeFields {
+            id: edge.id,
+            position: (edge.source.global_range.0, edge.target.global_range.0),
+            similarity: edge.alignment.core_similarity,
+            length: edge.alignment.length,
+            score: edge.alignment.score,
+            page: location_page(&edge.source.location).to_string(),
+        },
+        |edge, compact| {
+            if compact {
+                format_edge_with_text_compact(edge)
+            } else {
+                format_edge_with_text(edge)
+            }
+        },
+    );
+}
 
-    if let Some(n) = limit {
-        if edges.len() > n {
-            println!("... and {} more edges", edges.len() - n);
-        }
-    }
+/// `part:page` of the start of a passage location (`part:page.offset → ...`).
+fn location_page(location: &str) -> &str {
+    location.split('.').next().unwrap_or(location)
+}
+
+/// Format an edge with text on one line: start locations, length, core
+/// similarity, score and the start of the source passage.
+pub fn format_edge_with_text_compact(edge: &ReuseEdgeWithText) -> String {
+    let start = |location: &str| location.split(" → ").next().unwrap_or(location).to_string();
+    format!(
+        "#{} {} ↔ {}  len={} core={:.0}% score={}{}{}  {}",
+        edge.id,
+        start(&edge.source.location),
+        start(&edge.target.location),
+        edge.alignment.length,
+        edge.alignment.core_similarity * 100.0,
+        edge.alignment.score,
+        if edge.alignment.is_verse { " [verse]" } else { "" },
+        if edge.alignment.suspect { " [suspect]" } else { "" },
+        truncate_text(&edge.source.text.matched, 40),
+    )
 }
 
 /// Format an edge with text as a human-readable string.
@@ -285,21 +2015,29 @@ pub fn format_edge_with_text(edge: &ReuseEdgeWithText) -> String {
         "substantive"
     };
     format!(
-        "Edge {}: len={} matches={} subs={} gaps={}\n\
+        "Edge {}{}{}: len={} matches={} subs={} gaps={} score={} ({:.2}/tok)\n\
          \x20 Core: {:.1}%  Coverage: {:.1}%  Weight: {:.2}  Diversity: {:.2} ({})\n\
+         \x20 Spans: {}→{} tokens (ratio {:.2})\n\
          \x20 Book {} [{}] ↔ Book {} [{}]\n\
          Source: {}\n\
          Target: {}",
         edge.id,
+        if edge.alignment.is_verse { " [verse]" } else { "" },
+        if edge.alignment.suspect { " [suspect]" } else { "" },
         edge.alignment.length,
         edge.alignment.lemma_matches,
         edge.alignment.substitutions,
         edge.alignment.gaps,
+        edge.alignment.score,
+        edge.alignment.normalized_score,
         edge.alignment.core_similarity * 100.0,
         edge.alignment.span_coverage * 100.0,
         edge.alignment.content_weight,
         edge.alignment.lexical_diversity,
         diversity_label,
+        edge.alignment.source_span_length,
+        edge.alignment.target_span_length,
+        edge.alignment.span_ratio,
         edge.source.book_id,
         edge.source.location,
         edge.target.book_id,
@@ -319,74 +2057,658 @@ fn truncate_text(text: &str, max_len: usize) -> String {
     }
 }
 
-/// Print summary for results with text.
-pub fn print_summary_with_text(result: &ComparisonResultWithText) {
-    println!("\n=== Comparison Summary ===");
-    println!("Version: {}", result.version);
-    println!("Generated: {}", result.generated_at);
-    println!();
-    println!(
-        "Book A: {} - {} ({} tokens)",
-        result.book_a.id,
-        if result.book_a.title.is_empty() {
-            "(untitled)"
-        } else {
-            &result.book_a.title
-        },
-        result.book_a.token_count
-    );
-    println!(
-        "Book B: {} - {} ({} tokens)",
-        result.book_b.id,
-        if result.book_b.title.is_empty() {
-            "(untitled)"
-        } else {
-            &result.book_b.title
-        },
-        result.book_b.token_count
-    );
-    println!();
-    println!("Parameters:");
-    println!("  Window size: {}", result.parameters.window_size);
-    println!("  Stride: {}", result.parameters.stride);
-    println!("  N-gram size: {}", result.parameters.ngram_size);
-    println!(
-        "  Min shared shingles: {}",
-        result.parameters.min_shared_shingles
-    );
-    println!("  Min length: {}", result.parameters.min_length);
-    println!(
-        "  Min similarity: {:.1}%",
-        result.parameters.min_similarity * 100.0
-    );
-    println!("  Brute force: {}", result.parameters.brute_force);
-    println!();
-    println!("Results:");
-    println!("  Edges found: {}", result.summary.edge_count);
-    println!(
-        "  Total aligned tokens: {}",
-        result.summary.total_aligned_tokens
-    );
-    println!(
-        "  Book A coverage: {:.1}%",
-        result.summary.book_a_coverage * 100.0
-    );
-    println!(
-        "  Book B coverage: {:.1}%",
-        result.summary.book_b_coverage * 100.0
-    );
-    println!(
-        "  Average similarity: {:.1}%",
-        result.summary.avg_similarity * 100.0
-    );
+/// Print summary for results with text.
+pub fn print_summary_with_text(result: &ComparisonResultWithText) {
+    println!("\n=== Comparison Summary ===");
+    println!("Version: {}", result.version);
+    println!("Generated: {}", result.generated_at);
+    println!();
+    println!(
+        "Book A: {} - {} ({} tokens)",
+        result.book_a.id,
+        if result.book_a.title.is_empty() {
+            "(untitled)"
+        } else {
+            &result.book_a.title
+        },
+        result.book_a.token_count
+    );
+    println!(
+        "Book B: {} - {} ({} tokens)",
+        result.book_b.id,
+        if result.book_b.title.is_empty() {
+            "(untitled)"
+        } else {
+            &result.book_b.title
+        },
+        result.book_b.token_count
+    );
+    println!();
+    println!("Parameters:");
+    println!("  Window size: {}", result.parameters.window_size);
+    println!("  Stride: {}", result.parameters.stride);
+    println!("  N-gram size: {}", result.parameters.ngram_size);
+    println!(
+        "  Min shared shingles: {}",
+        result.parameters.min_shared_shingles
+    );
+    println!("  Min length: {}", result.parameters.min_length);
+    if let Some(min) = result.parameters.min_matched_tokens {
+        println!("  Min matched tokens: {}", min);
+    }
+    if let Some(min) = result.parameters.min_span_jaccard {
+        println!("  Min span Jaccard: {:.2}", min);
+    }
+    if let Some(min) = result.parameters.min_verse_core_similarity {
+        println!("  Min verse core similarity: {:.2}", min);
+    }
+    if result.parameters.drop_suspect {
+        println!("  Drop suspect edges: yes");
+    }
+    if let Some(book_id) = result.parameters.quran_book_id {
+        match result.parameters.max_quran_overlap {
+            Some(max) => println!("  Quran: book {}, max overlap {:.2}", book_id, max),
+            None => println!("  Quran: book {}", book_id),
+        }
+    }
+    if let Some(max) = result.parameters.max_span_asymmetry {
+        println!("  Max span asymmetry: {:.2}", max);
+    }
+    if let Some(min) = result.parameters.min_merge_core_similarity {
+        println!("  Min merge core similarity: {:.2}", min);
+    }
+    if result.parameters.refine_merges {
+        println!("  Refine merges: yes");
+    }
+    if let Some(gap) = result.parameters.chain_gap {
+        println!("  Chain gap: {}", gap);
+    }
+    match result.parameters.coverage_bins {
+        Some(CoverageBins::Tokens(n)) => println!("  Coverage bins: {} tokens", n),
+        Some(CoverageBins::Pages) => println!("  Coverage bins: pages"),
+        None => {}
+    }
+    if result.parameters.segmentation == Segmentation::Hadith {
+        println!("  Segmentation: hadith reports");
+    }
+    if let Some(size) = result.parameters.coarse_window_size {
+        let stride = result.parameters.coarse_stride.unwrap_or(size / 2);
+        println!("  Coarse pass: window {}, stride {}", size, stride);
+    }
+    if let Some((start, end)) = result.parameters.source_range {
+        println!("  Source range: {}:{}", start, end);
+    }
+    println!(
+        "  Min similarity: {:.1}%",
+        result.parameters.min_similarity * 100.0
+    );
+    println!("  Brute force: {}", result.parameters.brute_force);
+    if result.parameters.candidate_method != CandidateMethod::Shingles {
+        println!("  Candidate method: {:?}", result.parameters.candidate_method);
+    }
+    if result.parameters.shingle_kind != ShingleKind::Contiguous {
+        println!("  Shingles: {:?}", result.parameters.shingle_kind);
+    }
+    println!();
+    println!("Results:");
+    println!("  Edges found: {}", result.summary.edge_count);
+    println!(
+        "  Total aligned tokens: {}",
+        result.summary.total_aligned_tokens
+    );
+    println!(
+        "  Book A coverage: {}",
+        format_coverage(result.summary.book_a_coverage, result.summary.book_a_coverage_ci)
+    );
+    println!(
+        "  Book B coverage: {}",
+        format_coverage(result.summary.book_b_coverage, result.summary.book_b_coverage_ci)
+    );
+    println!(
+        "  Average similarity: {:.1}%",
+        result.summary.avg_similarity * 100.0
+    );
+    print_edge_statistics(&result.summary);
+    if result.summary.edge_count > 1 {
+        println!("  Order preservation (Kendall tau): {:.2}", result.summary.order_tau);
+    }
+    if result.summary.aborted_alignments > 0 {
+        println!("  Abandoned alignments: {}", result.summary.aborted_alignments);
+    }
+    if result.run_info.stats.capped_pairs > 0 {
+        println!(
+            "  Capped candidates: {} pairs dropped from {} windows",
+            result.run_info.stats.capped_pairs, result.run_info.stats.capped_windows
+        );
+    }
+    if !result.macro_edges.is_empty() {
+        println!(
+            "  Macro edges: {} (chaining {} edges)",
+            result.macro_edges.len(),
+            result.macro_edges.iter().map(|m| m.edge_ids.len()).sum::<usize>()
+        );
+    }
+    println!("  Pipeline: {}", format_run_stats(&result.run_info));
+    println!("  Timings: {}", format_run_info(&result.run_info));
+}
+
+/// Format a sweep's runs as a table, one line per run under a header: the
+/// run's grid values, edges, coverage, the gold scores if any, and time.
+pub fn format_sweep_table(report: &SweepReport) -> Vec<String> {
+    let keys: Vec<&String> = report.runs.first().map(|run| run.values.keys().collect()).unwrap_or_default();
+    let scored = report.runs.iter().any(|run| run.scores.is_some());
+
+    let mut header: Vec<String> = vec!["#".to_string()];
+    header.extend(keys.iter().map(|key| key.to_string()));
+    header.extend(["edges", "A cov", "B cov"].map(String::from));
+    if scored {
+        header.extend(["precision", "recall", "F1"].map(String::from));
+    }
+    header.push("ms".to_string());
+
+    let mut rows = vec![header];
+    for (i, run) in report.runs.iter().enumerate() {
+        let mut row = vec![(i + 1).to_string()];
+        row.extend(keys.iter().map(|key| match run.values.get(*key) {
+            Some(serde_json::Value::String(s)) => s.clone(),
+            Some(value) => value.to_string(),
+            None => String::new(),
+        }));
+        row.extend([
+            run.edge_count.to_string(),
+            format!("{:.1}%", run.book_a_coverage * 100.0),
+            format!("{:.1}%", run.book_b_coverage * 100.0),
+        ]);
+        if let Some(scores) = &run.scores {
+            row.extend([scores.precision, scores.recall, scores.f1].map(|score| format!("{:.3}", score)));
+        } else if scored {
+            row.extend(["-", "-", "-"].map(String::from));
+        }
+        row.push(run.wall_ms.to_string());
+        rows.push(row);
+    }
+
+    let widths: Vec<usize> = (0..rows[0].len())
+        .map(|col| rows.iter().map(|row| row[col].chars().count()).max().unwrap_or(0))
+        .collect();
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .zip(&widths)
+                .map(|(cell, &width)| format!("{:>width$}", cell, width = width))
+                .collect::<Vec<_>>()
+                .join("  ")
+        })
+        .collect()
+}
+
+/// Print a sweep's runs as a table, and the run with the best F1 when
+/// the runs were scored.
+pub fn print_sweep_report(report: &SweepReport) {
+    println!("\n=== Parameter Sweep: book {} vs {} ===", report.book_a, report.book_b);
+    for line in format_sweep_table(report) {
+        println!("{}", line);
+    }
+    let best = report
+        .runs
+        .iter()
+        .enumerate()
+        .filter_map(|(i, run)| run.scores.map(|scores| (i, scores)))
+        .min_by(|(_, a), (_, b)| b.f1.total_cmp(&a.f1));
+    if let Some((i, scores)) = best {
+        println!(
+            "\nBest F1: run {} ({:.3}; precision {:.3}, recall {:.3}, {} of {} gold edges found)",
+            i + 1,
+            scores.f1,
+            scores.precision,
+            scores.recall,
+            scores.found_gold,
+            scores.gold_edges
+        );
+    }
+}
+
+// ============================================================================
+// Excel output
+// ============================================================================
+
+/// Longest text, in characters, that an Excel cell holds.
+const XLSX_MAX_CELL_CHARS: usize = 32_767;
+
+/// Cut text to what an Excel cell holds.
+fn xlsx_text(text: &str) -> &str {
+    match text.char_indices().nth(XLSX_MAX_CELL_CHARS) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+/// A summary sheet value.
+enum XlsxValue<'a> {
+    Number(f64),
+    Text(&'a str),
+    /// Arabic text, laid out right to left
+    Arabic(&'a str),
+}
+
+/// Write a result with text as an Excel workbook with English headers.
+pub fn write_xlsx<W: Write>(result: &ComparisonResultWithText, writer: &mut W) -> Result<(), OutputError> {
+    write_xlsx_with_headers(result, HeaderLanguage::English, writer)
+}
+
+/// Write a result with text as an Excel workbook.
+///
+/// The "Edges" sheet has the same columns as `write_result_csv_with_text`,
+/// with the Arabic text, title and author columns laid out right to left; the
+/// "Summary" sheet lists the two books and the summary totals. Header rows
+/// are frozen. Passages longer than a cell holds (32,767 characters) are cut.
+pub fn write_xlsx_with_headers<W: Write>(
+    result: &ComparisonResultWithText,
+    headers: HeaderLanguage,
+    writer: &mut W,
+) -> Result<(), OutputError> {
+    let mut workbook = rust_xlsxwriter::Workbook::new();
+    let header = rust_xlsxwriter::Format::new().set_bold();
+    let rtl = rust_xlsxwriter::Format::new().set_reading_direction(2).set_text_wrap();
+
+    let sheet = workbook.add_worksheet().set_name("Edges")?;
+    let columns: Vec<&str> = CSV_TEXT_HEADER.into_iter().chain(BOOK_LABEL_HEADER).collect();
+    let header_rows = headers.rows(&columns);
+    let first_row = header_rows.len() as u32;
+    for (row, names) in (0u32..).zip(header_rows) {
+        sheet.write_row_with_format(row, 0, names, &header)?;
+    }
+    sheet.set_freeze_panes(first_row, 0)?;
+    for (col, name) in (0u16..).zip(&columns) {
+        if name.contains("_text_") {
+            sheet.set_column_width(col, 60)?;
+            sheet.set_column_format(col, &rtl)?;
+        } else if name.ends_with("_title") || name.ends_with("_author") {
+            sheet.set_column_width(col, 30)?;
+            sheet.set_column_format(col, &rtl)?;
+        }
+    }
+    let books = (&result.book_a, &result.book_b);
+    for (row, edge) in (first_row..).zip(&result.edges) {
+        // IDs are written as text: Excel numbers lose precision past 2^53
+        sheet.write_string(row, 0, edge.id.to_string())?;
+        sheet.write_string(row, 1, &edge.signature)?;
+        for (col, passage) in [(2, &edge.source), (9, &edge.target)] {
+            sheet.write_number(row, col, passage.book_id)?;
+            sheet.write_string(row, col + 1, &passage.location)?;
+            sheet.write_number(row, col + 2, passage.global_range.0 as f64)?;
+            sheet.write_number(row, col + 3, passage.global_range.1 as f64)?;
+            sheet.write_string(row, col + 4, xlsx_text(&passage.text.before))?;
+            sheet.write_string(row, col + 5, xlsx_text(&passage.text.matched))?;
+            sheet.write_string(row, col + 6, xlsx_text(&passage.text.after))?;
+        }
+        sheet.write_number(row, 16, edge.alignment.length as f64)?;
+        sheet.write_number(row, 17, edge.alignment.lemma_matches as f64)?;
+        sheet.write_number(row, 18, edge.alignment.gaps as f64)?;
+        sheet.write_number(row, 19, edge.alignment.similarity)?;
+        sheet.write_string(row, 20, format!("{:?}", edge.direction))?;
+        for (col, book_id) in [(21, edge.source.book_id), (23, edge.target.book_id)] {
+            let book = book_label(books, book_id);
+            sheet.write_string(row, col, xlsx_text(&book.title))?;
+            sheet.write_string(row, col + 1, xlsx_text(&book.author))?;
+        }
+    }
+
+    let summary = &result.summary;
+    let mut rows = Vec::new();
+    for (prefix, book) in [("book_a", &result.book_a), ("book_b", &result.book_b)] {
+        rows.extend([
+            (format!("{}_id", prefix), XlsxValue::Number(book.id.into())),
+            (format!("{}_title", prefix), XlsxValue::Arabic(&book.title)),
+            (format!("{}_author", prefix), XlsxValue::Arabic(&book.author)),
+            (format!("{}_token_count", prefix), XlsxValue::Number(book.token_count as f64)),
+        ]);
+        if let Some(death_ah) = book.death_ah {
+            rows.push((format!("{}_death_ah", prefix), XlsxValue::Number(death_ah.into())));
+        }
+    }
+    rows.extend([
+        ("edge_count".to_string(), XlsxValue::Number(summary.edge_count as f64)),
+        ("total_aligned_tokens".to_string(), XlsxValue::Number(summary.total_aligned_tokens as f64)),
+        ("book_a_aligned_tokens".to_string(), XlsxValue::Number(summary.book_a_aligned_tokens as f64)),
+        ("book_b_aligned_tokens".to_string(), XlsxValue::Number(summary.book_b_aligned_tokens as f64)),
+        ("book_a_coverage".to_string(), XlsxValue::Number(summary.book_a_coverage.into())),
+        ("book_b_coverage".to_string(), XlsxValue::Number(summary.book_b_coverage.into())),
+        ("avg_similarity".to_string(), XlsxValue::Number(summary.avg_similarity.into())),
+        ("avg_weighted_similarity".to_string(), XlsxValue::Number(summary.avg_weighted_similarity.into())),
+        ("order_tau".to_string(), XlsxValue::Number(summary.order_tau.into())),
+        ("aborted_alignments".to_string(), XlsxValue::Number(summary.aborted_alignments as f64)),
+        ("prose_edges".to_string(), XlsxValue::Number(summary.edge_classes.prose as f64)),
+        ("verse_edges".to_string(), XlsxValue::Number(summary.edge_classes.verse as f64)),
+        ("suspect_edges".to_string(), XlsxValue::Number(summary.edge_classes.suspect as f64)),
+        ("quran_edges".to_string(), XlsxValue::Number(summary.edge_classes.quran as f64)),
+        ("median_length".to_string(), XlsxValue::Number(summary.length_distribution.median.into())),
+        ("median_similarity".to_string(), XlsxValue::Number(summary.similarity_distribution.median.into())),
+        ("version".to_string(), XlsxValue::Text(&result.version)),
+        ("generated_at".to_string(), XlsxValue::Text(&result.generated_at)),
+    ]);
+
+    let sheet = workbook.add_worksheet().set_name("Summary")?;
+    let header_rows = match headers {
+        HeaderLanguage::English => vec![["field", "value"]],
+        HeaderLanguage::Arabic => vec![["الحقل", "القيمة"]],
+        HeaderLanguage::Bilingual => vec![["field", "value"], ["الحقل", "القيمة"]],
+    };
+    let first_row = header_rows.len() as u32;
+    for (row, names) in (0u32..).zip(header_rows) {
+        sheet.write_row_with_format(row, 0, names, &header)?;
+    }
+    sheet.set_freeze_panes(first_row, 0)?;
+    sheet.set_column_width(0, 24)?;
+    sheet.set_column_width(1, 40)?;
+    for (row, (field, value)) in (first_row..).zip(&rows) {
+        sheet.write_string(row, 0, field)?;
+        match value {
+            XlsxValue::Number(n) => sheet.write_number(row, 1, *n)?,
+            XlsxValue::Text(s) => sheet.write_string(row, 1, xlsx_text(s))?,
+            XlsxValue::Arabic(s) => sheet.write_string_with_format(row, 1, xlsx_text(s), &rtl)?,
+        };
+    }
+
+    writer.write_all(&workbook.save_to_buffer()?)?;
+    Ok(())
+}
+
+/// Write a result with text as an Excel workbook file with English headers.
+pub fn write_xlsx_file(result: &ComparisonResultWithText, path: &Path) -> Result<(), OutputError> {
+    write_xlsx_file_with_headers(result, HeaderLanguage::English, path)
+}
+
+/// Write a result with text as an Excel workbook file.
+pub fn write_xlsx_file_with_headers(
+    result: &ComparisonResultWithText,
+    headers: HeaderLanguage,
+    path: &Path,
+) -> Result<(), OutputError> {
+    let mut file = std::fs::File::create(path)?;
+    write_xlsx_with_headers(result, headers, &mut file)
+}
+
+// ============================================================================
+// Alignment debug dump
+// ============================================================================
+
+/// Longest side of a dot-plot, in pixels.
+const TRACE_PLOT_MAX_PX: usize = 600;
+
+/// Write an alignment trace as a standalone HTML page with one dot-plot per
+/// window pair, source window down and target window across.
+///
+/// Grey cells mark positions with the same lemma. The traceback path is drawn
+/// over them (red for lemma matches, orange for other diagonal moves, joined
+/// by a line so gaps show as bends), and blue boxes mark the final edges that
+/// overlap the pair.
+pub fn write_alignment_trace_html<W: Write>(trace: &AlignmentTrace, writer: &mut W) -> Result<(), OutputError> {
+    writeln!(
+        writer,
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+<title>Alignment trace - book {a} vs book {b}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2rem; color: #111827; }}
+section {{ margin-bottom: 2rem; }}
+svg {{ border: 1px solid #d1d5db; background: #ffffff; }}
+</style>
+</head>
+<body>
+<h1>Alignment trace: book {a} vs book {b}</h1>
+<p>{pairs} window pairs, {edges} final edges. Book {a} runs down, book {b} across.</p>"#,
+        a = trace.book_a,
+        b = trace.book_b,
+        pairs = trace.pairs.len(),
+        edges = trace.edges.len(),
+    )?;
+    if trace.pairs.is_empty() {
+        writeln!(writer, "<p>None of the selected window pairs exist in these books.</p>")?;
+    }
+    for pair in &trace.pairs {
+        write_trace_plot(pair, &trace.edges, writer)?;
+    }
+    writeln!(writer, "</body>\n</html>")?;
+    Ok(())
+}
+
+/// Write an alignment trace as HTML to a file, buffered.
+pub fn write_alignment_trace_html_file(trace: &AlignmentTrace, path: &Path) -> Result<(), OutputError> {
+    let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+    write_alignment_trace_html(trace, &mut writer)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// One window pair's heading and dot-plot.
+fn write_trace_plot<W: Write>(pair: &WindowPairTrace, edges: &[ReuseEdge], writer: &mut W) -> io::Result<()> {
+    let (n, m) = (pair.source_lemmas.len(), pair.target_lemmas.len());
+    let cell = (TRACE_PLOT_MAX_PX / n.max(m).max(1)).clamp(1, 8);
+    let source_end = pair.source_start + n;
+    let target_end = pair.target_start + m;
+
+    writeln!(writer, "<section>")?;
+    writeln!(writer, "<h2>Window {} &times; window {}</h2>", pair.source_window, pair.target_window)?;
+    writeln!(
+        writer,
+        "<p>Tokens {}&ndash;{} &times; {}&ndash;{}; {}; {}.</p>",
+        pair.source_start,
+        source_end,
+        pair.target_start,
+        target_end,
+        if pair.candidate { "candidate" } else { "not a candidate" },
+        describe_trace_outcome(&pair.outcome),
+    )?;
+    writeln!(
+        writer,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}">"#,
+        m * cell,
+        n * cell
+    )?;
+
+    for (i, lemma_a) in pair.source_lemmas.iter().enumerate() {
+        for (j, lemma_b) in pair.target_lemmas.iter().enumerate() {
+            if lemma_a == lemma_b {
+                write_trace_cell(writer, i, j, cell, "#9ca3af")?;
+            }
+        }
+    }
+
+    if let TraceOutcome::Aligned(alignment) = &pair.outcome {
+        let mut points = String::new();
+        for &(i, j) in &alignment.aligned_pairs {
+            let color = if pair.source_lemmas[i] == pair.target_lemmas[j] { "#dc2626" } else { "#f59e0b" };
+            write_trace_cell(writer, i, j, cell, color)?;
+            let center = |k: usize| k * cell + cell / 2;
+            points.push_str(&format!("{},{} ", center(j), center(i)));
+        }
+        writeln!(
+            writer,
+            r##"<polyline points="{}" fill="none" stroke="#dc2626" stroke-width="1"/>"##,
+            points.trim_end()
+        )?;
+    }
+
+    for edge in edges {
+        let (top, bottom) = (edge.source_global_start.max(pair.source_start), edge.source_global_end.min(source_end));
+        let (left, right) = (edge.target_global_start.max(pair.target_start), edge.target_global_end.min(target_end));
+        if top >= bottom || left >= right {
+            continue;
+        }
+        writeln!(
+            writer,
+            r##"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="#2563eb" stroke-width="2"><title>edge {}: {}..{} / {}..{}</title></rect>"##,
+            (left - pair.target_start) * cell,
+            (top - pair.source_start) * cell,
+            (right - left) * cell,
+            (bottom - top) * cell,
+            edge.id,
+            edge.source_global_start,
+            edge.source_global_end,
+            edge.target_global_start,
+            edge.target_global_end,
+        )?;
+    }
+
+    writeln!(writer, "</svg>\n</section>")
+}
+
+/// One filled dot-plot cell at source position `i`, target position `j`.
+fn write_trace_cell<W: Write>(writer: &mut W, i: usize, j: usize, cell: usize, color: &str) -> io::Result<()> {
+    writeln!(
+        writer,
+        r#"<rect x="{}" y="{}" width="{}" height="{}" fill="{}"/>"#,
+        j * cell,
+        i * cell,
+        cell,
+        cell,
+        color
+    )
+}
+
+/// Plain-language result of aligning a traced pair.
+fn describe_trace_outcome(outcome: &TraceOutcome) -> String {
+    match outcome {
+        TraceOutcome::Aligned(alignment) => format!(
+            "aligned with score {} ({} lemma matches, {} substitutions, {} gaps; window offsets {}..{} &times; {}..{})",
+            alignment.score,
+            alignment.lemma_matches,
+            alignment.substitutions,
+            alignment.gaps,
+            alignment.start_a,
+            alignment.end_a,
+            alignment.start_b,
+            alignment.end_b,
+        ),
+        TraceOutcome::Rejected => "no alignment met the thresholds".to_string(),
+        TraceOutcome::Aborted(reason) => format!("alignment abandoned ({})", reason),
+    }
+}
+
+// ============================================================================
+// Collation table
+// ============================================================================
+
+/// Write a collation as a standalone HTML table: one row per witness, one
+/// column per aligned word, variant columns shaded.
+pub fn write_collation_html<W: Write>(collation: &Collation, writer: &mut W) -> Result<(), OutputError> {
+    writeln!(
+        writer,
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="UTF-8">
+<title>Collation - cluster {id}</title>
+<style>
+body {{ font-family: sans-serif; margin: 2rem; color: #111827; }}
+.scroll {{ overflow-x: auto; }}
+table {{ border-collapse: collapse; direction: rtl; }}
+th, td {{ border: 1px solid #d1d5db; padding: 0.25rem 0.5rem; white-space: nowrap; }}
+th {{ text-align: right; background: #f3f4f6; font-weight: normal; }}
+td.variant {{ background: #fef3c7; }}
+td.gap {{ color: #9ca3af; }}
+</style>
+</head>
+<body>
+<h1>Collation: cluster {id}</h1>
+<p>{witnesses} witnesses, {columns} columns, {variants} with variants.</p>
+<div class="scroll">
+<table>"#,
+        id = collation.cluster_id,
+        witnesses = collation.witnesses.len(),
+        columns = collation.columns.len(),
+        variants = collation.variant_columns,
+    )?;
+    for (w, witness) in collation.witnesses.iter().enumerate() {
+        let death = witness.death_ah.map(|d| format!(" (d. {})", d)).unwrap_or_default();
+        write!(
+            writer,
+            r#"<tr><th title="{}">{}{}</th>"#,
+            xml_escape(&witness.location),
+            xml_escape(&witness.title),
+            death
+        )?;
+        for column in &collation.columns {
+            match &column.words[w] {
+                Some(word) if column.variant => write!(writer, r#"<td class="variant">{}</td>"#, xml_escape(word))?,
+                Some(word) => write!(writer, "<td>{}</td>", xml_escape(word))?,
+                None => write!(writer, r#"<td class="gap">&ndash;</td>"#)?,
+            }
+        }
+        writeln!(writer, "</tr>")?;
+    }
+    writeln!(writer, "</table>\n</div>\n</body>\n</html>")?;
+    Ok(())
+}
+
+/// Write a collation as HTML to a file, buffered.
+pub fn write_collation_html_file(collation: &Collation, path: &Path) -> Result<(), OutputError> {
+    let mut writer = io::BufWriter::new(std::fs::File::create(path)?);
+    write_collation_html(collation, &mut writer)?;
+    writer.flush()?;
+    Ok(())
 }
 
 // ============================================================================
 // HTML Viewer generation
 // ============================================================================
 
+/// Viewer libraries in load order: the file `ViewerAssets::from_dir` reads
+/// for each, and the CDN URL it replaces.
+pub const VIEWER_ASSET_FILES: [(&str, &str); 4] = [
+    ("tailwindcss.js", "https://cdn.tailwindcss.com"),
+    ("react.production.min.js", "https://unpkg.com/react@18/umd/react.production.min.js"),
+    ("react-dom.production.min.js", "https://unpkg.com/react-dom@18/umd/react-dom.production.min.js"),
+    ("babel.min.js", "https://unpkg.com/@babel/standalone/babel.min.js"),
+];
+
+/// Where a generated viewer loads Tailwind, React, ReactDOM and Babel from.
+#[derive(Debug, Clone, Default)]
+pub enum ViewerAssets {
+    /// Public CDNs, fetched when the page is opened (default)
+    #[default]
+    Cdn,
+    /// Library sources inlined into the page, in `VIEWER_ASSET_FILES` order,
+    /// so it opens with no network access
+    Inline(Vec<String>),
+}
+
+impl ViewerAssets {
+    /// Read the libraries named in `VIEWER_ASSET_FILES` from `dir`.
+    pub fn from_dir(dir: &Path) -> Result<Self, OutputError> {
+        let mut sources = Vec::with_capacity(VIEWER_ASSET_FILES.len());
+        for (file, _) in VIEWER_ASSET_FILES {
+            let path = dir.join(file);
+            let source = std::fs::read_to_string(&path)
+                .map_err(|e| io::Error::new(e.kind(), format!("{}: {e}", path.display())))?;
+            sources.push(source);
+        }
+        Ok(ViewerAssets::Inline(sources))
+    }
+
+    /// The `<script>` elements loading the libraries.
+    fn script_tags(&self) -> String {
+        match self {
+            ViewerAssets::Cdn => VIEWER_ASSET_FILES
+                .iter()
+                .map(|(_, url)| format!(r#"<script src="{url}"></script>"#))
+                .collect::<Vec<_>>()
+                .join("\n    "),
+            // A literal </script inside a library would end its element early
+            ViewerAssets::Inline(sources) => sources
+                .iter()
+                .map(|source| format!("<script>\n{}\n    </script>", source.replace("</script", "<\\/script")))
+                .collect::<Vec<_>>()
+                .join("\n    "),
+        }
+    }
+}
+
 /// Generate a self-contained HTML viewer for the comparison results.
-pub fn generate_viewer_html(result: &ComparisonResultWithText) -> String {
+pub fn generate_viewer_html(result: &ComparisonResultWithText, assets: &ViewerAssets) -> String {
     let data_json = serde_json::to_string(result).unwrap_or_else(|_| "{}".to_string());
 
     // Escape any </script> tags in the JSON to prevent breaking the HTML
@@ -411,28 +2733,8 @@ pub fn generate_viewer_html(result: &ComparisonResultWithText) -> String {
     <meta charset="UTF-8">
     <meta name="viewport" content="width=device-width, initial-scale=1.0">
     <title>Kashshaf Reuse Viewer - {book_a} vs {book_b}</title>
-    <script src="https://cdn.tailwindcss.com"></script>
-    <script src="https://unpkg.com/react@18/umd/react.production.min.js"></script>
-    <script src="https://unpkg.com/react-dom@18/umd/react-dom.production.min.js"></script>
-    <script src="https://unpkg.com/@babel/standalone/babel.min.js"></script>
-    <style>
-        .arabic-text {{
-            font-family: 'Amiri', 'Traditional Arabic', 'Scheherazade', serif;
-            font-size: 1.1rem;
-            line-height: 2;
-        }}
-        .highlight-match {{
-            background-color: #fef08a;
-            padding: 2px 4px;
-            border-radius: 3px;
-        }}
-        .context-text {{
-            color: #9ca3af;
-        }}
-        .similarity-high {{ color: #16a34a; }}
-        .similarity-medium {{ color: #ca8a04; }}
-        .similarity-low {{ color: #dc2626; }}
-    </style>
+    {scripts}
+    <style>{styles}    </style>
 </head>
 <body class="bg-gray-50">
     <div id="root"></div>
@@ -443,11 +2745,15 @@ pub fn generate_viewer_html(result: &ComparisonResultWithText) -> String {
 
     <script type="text/babel">
 {viewer_app}
+const root = ReactDOM.createRoot(document.getElementById('root'));
+root.render(<App />);
     </script>
 </body>
 </html>"##,
         book_a = book_a_title,
         book_b = book_b_title,
+        scripts = assets.script_tags(),
+        styles = VIEWER_STYLES,
         data_json = escaped_json,
         viewer_app = VIEWER_APP_CODE,
     )
@@ -456,35 +2762,344 @@ pub fn generate_viewer_html(result: &ComparisonResultWithText) -> String {
 /// Write viewer HTML to a file.
 pub fn write_viewer_html_file(
     result: &ComparisonResultWithText,
+    assets: &ViewerAssets,
     path: &Path,
 ) -> Result<(), OutputError> {
-    let html = generate_viewer_html(result);
+    let html = generate_viewer_html(result, assets);
     std::fs::write(path, html)?;
     Ok(())
 }
 
-/// Embedded React viewer application code
+/// Generate a self-contained dashboard over many book pairs: a pair matrix
+/// shaded by coverage, aligned tokens or match count, and a pair list, each
+/// opening the regular viewer for the pair.
+///
+/// Every result is embedded with its text, so the page grows with the total
+/// number of edges.
+pub fn generate_corpus_viewer_html(results: &[ComparisonResultWithText], assets: &ViewerAssets) -> String {
+    let data_json = serde_json::to_string(results).unwrap_or_else(|_| "[]".to_string());
+
+    // Escape any </script> tags in the JSON to prevent breaking the HTML
+    let escaped_json = data_json.replace("</script>", "<\\/script>");
+
+    format!(
+        r##"<!DOCTYPE html>
+<html lang="en" dir="ltr">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Kashshaf Reuse Dashboard - {pairs} pairs</title>
+    {scripts}
+    <style>{styles}    </style>
+</head>
+<body class="bg-gray-50">
+    <div id="root"></div>
+
+    <script type="text/javascript">
+        window.__CORPUS_DATA__ = {data_json};
+    </script>
+
+    <script type="text/babel">
+{viewer_app}
+{dashboard_app}
+    </script>
+</body>
+</html>"##,
+        pairs = results.len(),
+        scripts = assets.script_tags(),
+        styles = VIEWER_STYLES,
+        data_json = escaped_json,
+        viewer_app = VIEWER_APP_CODE,
+        dashboard_app = DASHBOARD_APP_CODE,
+    )
+}
+
+/// Write a multi-pair dashboard to a file.
+pub fn write_corpus_viewer_html_file(
+    results: &[ComparisonResultWithText],
+    assets: &ViewerAssets,
+    path: &Path,
+) -> Result<(), OutputError> {
+    std::fs::write(path, generate_corpus_viewer_html(results, assets))?;
+    Ok(())
+}
+
+/// Styles shared by the pair viewer and the dashboard.
+const VIEWER_STYLES: &str = r##"
+        .arabic-text {
+            font-family: 'Amiri', 'Traditional Arabic', 'Scheherazade', serif;
+            font-size: 1.1rem;
+            line-height: 2;
+        }
+        .highlight-match {
+            background-color: #fef08a;
+            padding: 2px 4px;
+            border-radius: 3px;
+        }
+        .context-text {
+            color: #9ca3af;
+        }
+        .op-match { background-color: #fef08a; }
+        .op-root { background-color: #fed7aa; }
+        .op-sub { background-color: #fecaca; }
+        .op-gap { text-decoration: underline dotted #9ca3af; }
+        .similarity-high { color: #16a34a; }
+        .similarity-medium { color: #ca8a04; }
+        .similarity-low { color: #dc2626; }
+"##;
+
+/// Embedded dashboard application code; needs the viewer components.
+const DASHBOARD_APP_CODE: &str = r##"
+const bookLabel = (book) => book.title || `Book ${book.id}`;
+
+const METRICS = {
+    coverage: {
+        label: 'Coverage (larger side)',
+        value: (r) => Math.max(r.summary.book_a_coverage, r.summary.book_b_coverage),
+        format: (v) => `${(v * 100).toFixed(1)}%`,
+    },
+    tokens: {
+        label: 'Aligned tokens',
+        value: (r) => r.summary.total_aligned_tokens,
+        format: (v) => v.toLocaleString(),
+    },
+    edges: {
+        label: 'Matches',
+        value: (r) => r.summary.edge_count,
+        format: (v) => v.toLocaleString(),
+    },
+};
+
+function Dashboard() {
+    const results = window.__CORPUS_DATA__;
+    const [metric, setMetric] = useState('coverage');
+    const [selected, setSelected] = useState(null);
+
+    const books = useMemo(() => {
+        const byId = new Map();
+        results.forEach(r => [r.book_a, r.book_b].forEach(b => {
+            if (!byId.has(b.id)) byId.set(b.id, b);
+        }));
+        return [...byId.values()].sort((a, b) => a.id - b.id);
+    }, [results]);
+
+    // Result index by unordered book pair
+    const cells = useMemo(() => {
+        const map = new Map();
+        results.forEach((r, i) => {
+            map.set(`${r.book_a.id}:${r.book_b.id}`, i);
+            map.set(`${r.book_b.id}:${r.book_a.id}`, i);
+        });
+        return map;
+    }, [results]);
+
+    const { value, format, label } = METRICS[metric];
+    const maxValue = Math.max(0, ...results.map(value));
+    const ranked = results
+        .map((r, i) => ({ r, i }))
+        .sort((x, y) => value(y.r) - value(x.r));
+
+    if (selected !== null) {
+        return (
+            <div>
+                <div className="bg-gray-800 text-white px-4 py-2 text-sm">
+                    <button onClick={() => setSelected(null)} className="hover:underline">
+                        ← All pairs
+                    </button>
+                </div>
+                <App key={selected} pair={results[selected]} />
+            </div>
+        );
+    }
+
+    const totalEdges = results.reduce((n, r) => n + r.summary.edge_count, 0);
+
+    return (
+        <div className="p-6">
+            <header className="mb-4 flex justify-between items-end">
+                <div>
+                    <h1 className="text-xl font-bold">Kashshaf Reuse Dashboard</h1>
+                    <p className="text-sm text-gray-600">
+                        {books.length} books, {results.length} pairs, {totalEdges} matches
+                    </p>
+                </div>
+                <label className="text-sm flex items-center gap-2">
+                    Shade by:
+                    <select value={metric} onChange={e => setMetric(e.target.value)} className="border rounded px-2 py-1">
+                        {Object.entries(METRICS).map(([key, m]) => (
+                            <option key={key} value={key}>{m.label}</option>
+                        ))}
+                    </select>
+                </label>
+            </header>
+
+            <div className="overflow-auto mb-6 bg-white border rounded">
+                <table className="text-xs">
+                    <thead>
+                        <tr>
+                            <th></th>
+                            {books.map(b => (
+                                <th key={b.id} className="px-1 py-1 font-normal text-gray-600" title={bookLabel(b)}>
+                                    {b.id}
+                                </th>
+                            ))}
+                        </tr>
+                    </thead>
+                    <tbody>
+                        {books.map(row => (
+                            <tr key={row.id}>
+                                <th className="px-2 py-1 text-left font-normal text-gray-600 whitespace-nowrap">
+                                    {row.id} {row.title}
+                                </th>
+                                {books.map(col => {
+                                    const i = cells.get(`${row.id}:${col.id}`);
+                                    if (i === undefined) {
+                                        return <td key={col.id} className="w-8 h-8 border bg-gray-50"></td>;
+                                    }
+                                    const v = value(results[i]);
+                                    const alpha = maxValue > 0 ? v / maxValue : 0;
+                                    return (
+                                        <td
+                                            key={col.id}
+                                            className="w-8 h-8 border cursor-pointer hover:ring-2 hover:ring-blue-400"
+                                            style={{ backgroundColor: `rgba(37, 99, 235, ${alpha})` }}
+                                            title={`${bookLabel(row)} / ${bookLabel(col)}: ${format(v)}`}
+                                            onClick={() => setSelected(i)}
+                                        ></td>
+                                    );
+                                })}
+                            </tr>
+                        ))}
+                    </tbody>
+                </table>
+            </div>
+
+            <table className="w-full text-sm bg-white border rounded">
+                <thead className="bg-gray-100 text-left">
+                    <tr>
+                        <th className="px-3 py-2">Book A</th>
+                        <th className="px-3 py-2">Book B</th>
+                        <th className="px-3 py-2 text-right">Matches</th>
+                        <th className="px-3 py-2 text-right">{label}</th>
+                    </tr>
+                </thead>
+                <tbody>
+                    {ranked.map(({ r, i }) => (
+                        <tr key={i} onClick={() => setSelected(i)} className="border-t cursor-pointer hover:bg-blue-50">
+                            <td className="px-3 py-2">{bookLabel(r.book_a)}</td>
+                            <td className="px-3 py-2">{bookLabel(r.book_b)}</td>
+                            <td className="px-3 py-2 text-right">{r.summary.edge_count}</td>
+                            <td className="px-3 py-2 text-right">{format(value(r))}</td>
+                        </tr>
+                    ))}
+                </tbody>
+            </table>
+        </div>
+    );
+}
+
+const root = ReactDOM.createRoot(document.getElementById('root'));
+root.render(<Dashboard />);
+"##;
+
+/// Embedded React viewer components. The page mounts `App`, or a dashboard
+/// that shows `App` for the selected pair.
 const VIEWER_APP_CODE: &str = r##"
-const {{ useState, useEffect, useMemo }} = React;
+const { useState, useEffect, useMemo } = React;
+
+// Optional bootstrap interval suffix for coverage figures
+const formatCi = (ci) => ci
+    ? ` (95% CI ${(ci[0] * 100).toFixed(1)}-${(ci[1] * 100).toFixed(1)}%)`
+    : '';
+
+// Density strip per book: each coverage bin as wide as its tokens, shaded by
+// the share of them reused
+function CoverageStrips({ profiles, books }) {
+    return (
+        <div className="bg-white px-4 py-2 border-b text-xs text-gray-500 space-y-1">
+            {profiles.map((profile, i) => {
+                const book = books.find(b => b.id === profile.book_id);
+                let start = 0;
+                return (
+                    <div key={i} className="flex items-center gap-2">
+                        <span className="w-32 truncate" title={book && book.title}>
+                            {(book && book.title) || `Book ${profile.book_id}`}
+                        </span>
+                        <div className="flex-1 flex h-3 border border-gray-200">
+                            {profile.sizes.map((size, bin) => {
+                                const share = size > 0 ? profile.covered[bin] / size : 0;
+                                const label = `Tokens ${start}–${start + size}: ${(share * 100).toFixed(0)}% reused`;
+                                start += size;
+                                return (
+                                    <div
+                                        key={bin}
+                                        style={{ flex: `${size} 1 0`, backgroundColor: `rgba(37, 99, 235, ${share})` }}
+                                        title={label}
+                                    />
+                                );
+                            })}
+                        </div>
+                    </div>
+                );
+            })}
+        </div>
+    );
+}
+
+// Bar per histogram bin, height by its share of the fullest bin; percentiles
+// in the tooltip
+function Histogram({ label, distribution, format }) {
+    const fullest = Math.max(1, ...distribution.counts);
+    const percentiles = ['min', 'p25', 'median', 'p75', 'p90', 'max']
+        .map(p => `${p} ${format(distribution[p])}`)
+        .join(', ');
+    return (
+        <span className="flex items-end gap-1 text-gray-500" title={`${label}: ${percentiles}`}>
+            {label}:
+            <span className="flex items-end h-4 gap-px">
+                {distribution.counts.map((count, i) => {
+                    const upper = distribution.bins[i + 1];
+                    const range = upper === undefined
+                        ? `${format(distribution.bins[i])}+`
+                        : `${format(distribution.bins[i])}–${format(upper)}`;
+                    return (
+                        <span
+                            key={i}
+                            className="w-1.5 bg-blue-500"
+                            style={{ height: `${(count / fullest) * 100}%` }}
+                            title={`${range}: ${count}`}
+                        />
+                    );
+                })}
+            </span>
+            <span>median {format(distribution.median)}</span>
+        </span>
+    );
+}
 
-// Main App Component
-function App() {{
+// Main App Component: one book pair, `pair` or the page's embedded result
+function App({ pair }) {
     const [data, setData] = useState(null);
     const [selectedEdge, setSelectedEdge] = useState(null);
-    const [filters, setFilters] = useState({{
+    const [filters, setFilters] = useState({
         minSimilarity: 0,
         minLength: 0,
         searchText: '',
         sortBy: 'similarity',
         sortDesc: true,
-    }});
-    const [validations, setValidations] = useState({{}});
-
-    useEffect(() => {{
-        setData(window.__COMPARISON_DATA__);
-    }}, []);
-
-    const filteredEdges = useMemo(() => {{
+    });
+    const [validations, setValidations] = useState({});
+
+    useEffect(() => {
+        const result = pair || window.__COMPARISON_DATA__;
+        setData(result);
+        // Labels brought back by `filter-validated` start out set
+        setValidations(Object.fromEntries(
+            result.edges.filter(e => e.validation).map(e => [e.id, e.validation])
+        ));
+    }, [pair]);
+
+    const filteredEdges = useMemo(() => {
         if (!data) return [];
 
         let edges = data.edges.filter(edge =>
@@ -496,9 +3111,9 @@ function App() {{
         );
 
         // Sort
-        edges.sort((a, b) => {{
+        edges.sort((a, b) => {
             let cmp = 0;
-            switch (filters.sortBy) {{
+            switch (filters.sortBy) {
                 case 'similarity':
                     cmp = a.alignment.similarity - b.alignment.similarity;
                     break;
@@ -510,56 +3125,76 @@ function App() {{
                     break;
                 default:
                     cmp = a.id - b.id;
-            }}
+            }
             return filters.sortDesc ? -cmp : cmp;
-        }});
+        });
 
         return edges;
-    }}, [data, filters]);
+    }, [data, filters]);
 
-    if (!data) {{
+    if (!data) {
         return (
             <div className="h-screen flex items-center justify-center">
                 <div className="text-gray-500">Loading...</div>
             </div>
         );
-    }}
+    }
 
     const validCount = Object.values(validations).filter(v => v === 'valid').length;
     const noiseCount = Object.values(validations).filter(v => v === 'noise').length;
 
     return (
         <div className="h-screen flex flex-col">
-            {{/* Header */}}
+            {/* Header */}
             <header className="bg-white border-b px-4 py-3">
                 <div className="flex justify-between items-center">
                     <div>
                         <h1 className="text-xl font-bold">Kashshaf Text Reuse Viewer</h1>
                         <p className="text-sm text-gray-600">
-                            {{data.book_a.title || `Book ${{data.book_a.id}}`}} vs {{data.book_b.title || `Book ${{data.book_b.id}}`}}
+                            {data.book_a.title || `Book ${data.book_a.id}`} vs {data.book_b.title || `Book ${data.book_b.id}`}
                         </p>
                     </div>
                     <div className="text-right text-sm">
-                        <div>{{data.summary.edge_count}} total matches</div>
+                        <div>{data.summary.edge_count} total matches</div>
                         <div className="text-gray-500">
-                            Avg similarity: {{(data.summary.avg_similarity * 100).toFixed(1)}}%
+                            Avg similarity: {(data.summary.avg_similarity * 100).toFixed(1)}%
                         </div>
                     </div>
                 </div>
             </header>
 
-            {{/* Stats Bar */}}
-            <div className="bg-gray-100 px-4 py-2 border-b flex gap-6 text-sm">
-                <span>Showing: <strong>{{filteredEdges.length}}</strong> matches</span>
-                <span className="text-green-600">✓ Valid: {{validCount}}</span>
-                <span className="text-red-600">✗ Noise: {{noiseCount}}</span>
+            {/* Stats Bar */}
+            <div className="bg-gray-100 px-4 py-2 border-b flex gap-6 text-sm flex-wrap items-end">
+                <span>Showing: <strong>{filteredEdges.length}</strong> matches</span>
+                <span className="text-green-600">✓ Valid: {validCount}</span>
+                <span className="text-red-600">✗ Noise: {noiseCount}</span>
                 <span className="text-gray-500">
-                    Book A coverage: {{(data.summary.book_a_coverage * 100).toFixed(1)}}% |
-                    Book B coverage: {{(data.summary.book_b_coverage * 100).toFixed(1)}}%
+                    Book A coverage: {(data.summary.book_a_coverage * 100).toFixed(1)}%{formatCi(data.summary.book_a_coverage_ci)} |
+                    Book B coverage: {(data.summary.book_b_coverage * 100).toFixed(1)}%{formatCi(data.summary.book_b_coverage_ci)}
                 </span>
+                {data.summary.edge_classes && (
+                    <span className="text-gray-500">
+                        Aligned tokens A {data.summary.book_a_aligned_tokens} | B {data.summary.book_b_aligned_tokens} ·{' '}
+                        {data.summary.edge_classes.prose} prose, {data.summary.edge_classes.verse} verse,{' '}
+                        {data.summary.edge_classes.suspect} suspect, {data.summary.edge_classes.quran} Quran
+                    </span>
+                )}
+                {data.summary.length_distribution && data.summary.edge_count > 0 && (
+                    <Histogram label="Length" distribution={data.summary.length_distribution} format={v => v.toFixed(0)} />
+                )}
+                {data.summary.similarity_distribution && data.summary.edge_count > 0 && (
+                    <Histogram
+                        label="Similarity"
+                        distribution={data.summary.similarity_distribution}
+                        format={v => `${(v * 100).toFixed(0)}%`}
+                    />
+                )}
             </div>
+            {data.coverage_profiles && data.coverage_profiles.length > 0 && (
+                <CoverageStrips profiles={data.coverage_profiles} books={[data.book_a, data.book_b]} />
+            )}
 
-            {{/* Filter Bar */}}
+            {/* Filter Bar */}
             <div className="bg-white px-4 py-2 border-b flex gap-4 items-center text-sm">
                 <label className="flex items-center gap-2">
                     Min similarity:
@@ -567,19 +3202,19 @@ function App() {{
                         type="range"
                         min="0"
                         max="100"
-                        value={{filters.minSimilarity * 100}}
-                        onChange={{e => setFilters(f => ({{ ...f, minSimilarity: e.target.value / 100 }}))}}
+                        value={filters.minSimilarity * 100}
+                        onChange={e => setFilters(f => ({ ...f, minSimilarity: e.target.value / 100 }))}
                         className="w-24"
                     />
-                    <span className="w-12">{{(filters.minSimilarity * 100).toFixed(0)}}%</span>
+                    <span className="w-12">{(filters.minSimilarity * 100).toFixed(0)}%</span>
                 </label>
                 <label className="flex items-center gap-2">
                     Min length:
                     <input
                         type="number"
                         min="0"
-                        value={{filters.minLength}}
-                        onChange={{e => setFilters(f => ({{ ...f, minLength: parseInt(e.target.value) || 0 }}))}}
+                        value={filters.minLength}
+                        onChange={e => setFilters(f => ({ ...f, minLength: parseInt(e.target.value) || 0 }))}
                         className="w-16 border rounded px-2 py-1"
                     />
                 </label>
@@ -587,8 +3222,8 @@ function App() {{
                     Search:
                     <input
                         type="text"
-                        value={{filters.searchText}}
-                        onChange={{e => setFilters(f => ({{ ...f, searchText: e.target.value }}))}}
+                        value={filters.searchText}
+                        onChange={e => setFilters(f => ({ ...f, searchText: e.target.value }))}
                         placeholder="Arabic text..."
                         className="w-48 border rounded px-2 py-1"
                         dir="rtl"
@@ -597,8 +3232,8 @@ function App() {{
                 <label className="flex items-center gap-2">
                     Sort by:
                     <select
-                        value={{filters.sortBy}}
-                        onChange={{e => setFilters(f => ({{ ...f, sortBy: e.target.value }}))}}
+                        value={filters.sortBy}
+                        onChange={e => setFilters(f => ({ ...f, sortBy: e.target.value }))}
                         className="border rounded px-2 py-1"
                     >
                         <option value="similarity">Similarity</option>
@@ -608,183 +3243,188 @@ function App() {{
                     </select>
                 </label>
                 <button
-                    onClick={{() => setFilters(f => ({{ ...f, sortDesc: !f.sortDesc }}))}}
+                    onClick={() => setFilters(f => ({ ...f, sortDesc: !f.sortDesc }))}
                     className="border rounded px-2 py-1 hover:bg-gray-100"
                 >
-                    {{filters.sortDesc ? '↓ Desc' : '↑ Asc'}}
+                    {filters.sortDesc ? '↓ Desc' : '↑ Asc'}
                 </button>
                 <button
-                    onClick={{() => {{
+                    onClick={() => {
                         const validated = filteredEdges.filter(e => validations[e.id]);
                         const csvContent = [
                             ['id', 'validation', 'source_text', 'target_text', 'similarity'].join(','),
                             ...validated.map(e => [
                                 e.id,
                                 validations[e.id],
-                                `"${{e.source.text.matched.replace(/"/g, '""')}}"`,
-                                `"${{e.target.text.matched.replace(/"/g, '""')}}"`,
+                                `"${e.source.text.matched.replace(/"/g, '""')}"`,
+                                `"${e.target.text.matched.replace(/"/g, '""')}"`,
                                 e.alignment.similarity
                             ].join(','))
                         ].join('\n');
-                        const blob = new Blob([csvContent], {{ type: 'text/csv' }});
+                        const blob = new Blob([csvContent], { type: 'text/csv' });
                         const url = URL.createObjectURL(blob);
                         const a = document.createElement('a');
                         a.href = url;
                         a.download = 'validated_matches.csv';
                         a.click();
-                    }}}}
+                    }}
                     className="ml-auto border rounded px-3 py-1 bg-blue-50 hover:bg-blue-100 text-blue-700"
                 >
                     Export Validated
                 </button>
             </div>
 
-            {{/* Main Content */}}
+            {/* Main Content */}
             <div className="flex-1 flex overflow-hidden">
-                {{/* Match List */}}
+                {/* Match List */}
                 <div className="w-80 border-r overflow-auto bg-white">
-                    {{filteredEdges.map(edge => (
+                    {filteredEdges.map(edge => (
                         <div
-                            key={{edge.id}}
-                            onClick={{() => setSelectedEdge(edge)}}
-                            className={{`p-3 border-b cursor-pointer hover:bg-gray-50 ${{
+                            key={edge.id}
+                            onClick={() => setSelectedEdge(edge)}
+                            className={`p-3 border-b cursor-pointer hover:bg-gray-50 ${
                                 selectedEdge?.id === edge.id ? 'bg-blue-50 border-l-4 border-l-blue-500' : ''
-                            }}`}}
+                            }`}
                         >
                             <div className="flex justify-between items-start">
-                                <span className="text-sm text-gray-500">#{{edge.id}}</span>
+                                <span className="text-sm text-gray-500">#{edge.id}</span>
                                 <div className="flex items-center gap-1">
-                                    {{validations[edge.id] === 'valid' && (
+                                    {validations[edge.id] === 'valid' && (
                                         <span className="text-green-500">✓</span>
-                                    )}}
-                                    {{validations[edge.id] === 'noise' && (
+                                    )}
+                                    {validations[edge.id] === 'noise' && (
                                         <span className="text-red-500">✗</span>
-                                    )}}
-                                    <span className={{`text-sm font-bold ${{
+                                    )}
+                                    <span className={`text-sm font-bold ${
                                         (edge.alignment.core_similarity || edge.alignment.similarity) >= 0.9 ? 'similarity-high' :
                                         (edge.alignment.core_similarity || edge.alignment.similarity) >= 0.7 ? 'similarity-medium' :
                                         'similarity-low'
-                                    }}`}}>
-                                        {{((edge.alignment.core_similarity || edge.alignment.similarity) * 100).toFixed(0)}}%
+                                    }`}>
+                                        {((edge.alignment.core_similarity || edge.alignment.similarity) * 100).toFixed(0)}%
                                     </span>
                                 </div>
                             </div>
                             <div className="text-sm mt-1 text-gray-600">
-                                {{edge.alignment.length}} tok • {{((edge.alignment.span_coverage || 1) * 100).toFixed(0)}}% cov
+                                {edge.alignment.length} tok • {((edge.alignment.span_coverage || 1) * 100).toFixed(0)}% cov
                             </div>
                             <div
                                 className="text-sm text-gray-600 mt-1 truncate arabic-text"
                                 dir="rtl"
                                 lang="ar"
                             >
-                                {{edge.source.text.matched.slice(0, 50)}}...
+                                {edge.source.text.matched.slice(0, 50)}...
                             </div>
                         </div>
-                    ))}}
+                    ))}
                 </div>
 
-                {{/* Detail View */}}
+                {/* Detail View */}
                 <div className="flex-1 overflow-auto p-4">
-                    {{selectedEdge ? (
+                    {selectedEdge ? (
                         <div>
-                            {{/* Header with stats */}}
+                            {/* Header with stats */}
                             <div className="mb-4 p-3 bg-gray-100 rounded-lg">
                                 <div className="flex justify-between items-center mb-3">
-                                    <span className="font-bold text-lg">Match #{{selectedEdge.id}}</span>
+                                    <span className="font-bold text-lg">Match #{selectedEdge.id}</span>
                                     <div className="flex gap-2">
                                         <button
-                                            onClick={{() => setValidations(v => ({{ ...v, [selectedEdge.id]: 'valid' }}))}}
-                                            className={{`px-3 py-1 rounded ${{
+                                            onClick={() => setValidations(v => ({ ...v, [selectedEdge.id]: 'valid' }))}
+                                            className={`px-3 py-1 rounded ${
                                                 validations[selectedEdge.id] === 'valid'
                                                     ? 'bg-green-500 text-white'
                                                     : 'bg-gray-200 hover:bg-green-100'
-                                            }}`}}
+                                            }`}
                                         >
                                             ✓ Valid
                                         </button>
                                         <button
-                                            onClick={{() => setValidations(v => ({{ ...v, [selectedEdge.id]: 'noise' }}))}}
-                                            className={{`px-3 py-1 rounded ${{
+                                            onClick={() => setValidations(v => ({ ...v, [selectedEdge.id]: 'noise' }))}
+                                            className={`px-3 py-1 rounded ${
                                                 validations[selectedEdge.id] === 'noise'
                                                     ? 'bg-red-500 text-white'
                                                     : 'bg-gray-200 hover:bg-red-100'
-                                            }}`}}
+                                            }`}
                                         >
                                             ✗ Noise
                                         </button>
                                     </div>
                                 </div>
-                                {{/* Four metrics display */}}
+                                {/* Four metrics display */}
                                 <div className="grid grid-cols-4 gap-4 mb-3">
                                     <div className="bg-white p-2 rounded text-center">
                                         <div className="text-xs text-gray-500">Core Similarity</div>
-                                        <div className={{`text-xl font-bold ${{
+                                        <div className={`text-xl font-bold ${
                                             (selectedEdge.alignment.core_similarity || 0) >= 0.9 ? 'text-green-600' :
                                             (selectedEdge.alignment.core_similarity || 0) >= 0.7 ? 'text-yellow-600' :
                                             'text-red-600'
-                                        }}`}}>
-                                            {{((selectedEdge.alignment.core_similarity || 0) * 100).toFixed(1)}}%
+                                        }`}>
+                                            {((selectedEdge.alignment.core_similarity || 0) * 100).toFixed(1)}%
                                         </div>
                                         <div className="text-xs text-gray-400">quotation exactness</div>
                                     </div>
                                     <div className="bg-white p-2 rounded text-center">
                                         <div className="text-xs text-gray-500">Span Coverage</div>
-                                        <div className={{`text-xl font-bold ${{
+                                        <div className={`text-xl font-bold ${
                                             (selectedEdge.alignment.span_coverage || 0) >= 0.7 ? 'text-green-600' :
                                             (selectedEdge.alignment.span_coverage || 0) >= 0.3 ? 'text-yellow-600' :
                                             'text-red-600'
-                                        }}`}}>
-                                            {{((selectedEdge.alignment.span_coverage || 0) * 100).toFixed(1)}}%
+                                        }`}>
+                                            {((selectedEdge.alignment.span_coverage || 0) * 100).toFixed(1)}%
                                         </div>
                                         <div className="text-xs text-gray-400">reuse vs padding</div>
                                     </div>
                                     <div className="bg-white p-2 rounded text-center">
                                         <div className="text-xs text-gray-500">Content Weight</div>
-                                        <div className={{`text-xl font-bold ${{
+                                        <div className={`text-xl font-bold ${
                                             (selectedEdge.alignment.content_weight || 0) >= 1.5 ? 'text-green-600' :
                                             (selectedEdge.alignment.content_weight || 0) >= 1.0 ? 'text-yellow-600' :
                                             'text-gray-600'
-                                        }}`}}>
-                                            {{(selectedEdge.alignment.content_weight || 0).toFixed(2)}}
+                                        }`}>
+                                            {(selectedEdge.alignment.content_weight || 0).toFixed(2)}
                                         </div>
                                         <div className="text-xs text-gray-400">avg IDF</div>
                                     </div>
                                     <div className="bg-white p-2 rounded text-center">
                                         <div className="text-xs text-gray-500">Diversity</div>
-                                        <div className={{`text-xl font-bold ${{
+                                        <div className={`text-xl font-bold ${
                                             (selectedEdge.alignment.lexical_diversity || 0) >= 0.7 ? 'text-green-600' :
                                             (selectedEdge.alignment.lexical_diversity || 0) >= 0.55 ? 'text-yellow-600' :
                                             'text-red-600'
-                                        }}`}}>
-                                            {{(selectedEdge.alignment.lexical_diversity || 0).toFixed(2)}}
+                                        }`}>
+                                            {(selectedEdge.alignment.lexical_diversity || 0).toFixed(2)}
                                         </div>
                                         <div className="text-xs text-gray-400">
-                                            {{(selectedEdge.alignment.lexical_diversity || 0) < 0.55 ? 'formulaic' : 'substantive'}}
+                                            {(selectedEdge.alignment.lexical_diversity || 0) < 0.55 ? 'formulaic' : 'substantive'}
                                         </div>
                                     </div>
                                 </div>
-                                {{/* Raw counts */}}
+                                {/* Raw counts */}
                                 <div className="flex gap-4 text-sm text-gray-600">
-                                    <span>{{selectedEdge.alignment.length}} tokens</span>
-                                    <span>{{selectedEdge.alignment.lemma_matches}} matches</span>
-                                    <span>{{selectedEdge.alignment.substitutions || 0}} subs</span>
-                                    <span>{{selectedEdge.alignment.gaps}} gaps</span>
+                                    <span>{selectedEdge.alignment.length} tokens</span>
+                                    <span>{selectedEdge.alignment.lemma_matches} matches</span>
+                                    <span>{selectedEdge.alignment.substitutions || 0} subs</span>
+                                    <span>{selectedEdge.alignment.gaps} gaps</span>
                                 </div>
+                                {selectedEdge.alignment.ops && <OpLegend />}
                             </div>
 
-                            {{/* Side-by-side passages */}}
+                            {/* Side-by-side passages */}
                             <div className="grid grid-cols-2 gap-4">
                                 <PassageDisplay
                                     title="Source"
-                                    bookTitle={{data.book_a.title || `Book ${{data.book_a.id}}`}}
-                                    location={{selectedEdge.source.location}}
-                                    text={{selectedEdge.source.text}}
+                                    bookTitle={data.book_a.title || `Book ${data.book_a.id}`}
+                                    location={selectedEdge.source.location}
+                                    text={selectedEdge.source.text}
+                                    ops={selectedEdge.alignment.ops}
+                                    side="source"
                                 />
                                 <PassageDisplay
                                     title="Target"
-                                    bookTitle={{data.book_b.title || `Book ${{data.book_b.id}}`}}
-                                    location={{selectedEdge.target.location}}
-                                    text={{selectedEdge.target.text}}
+                                    bookTitle={data.book_b.title || `Book ${data.book_b.id}`}
+                                    location={selectedEdge.target.location}
+                                    text={selectedEdge.target.text}
+                                    ops={selectedEdge.alignment.ops}
+                                    side="target"
                                 />
                             </div>
                         </div>
@@ -792,36 +3432,62 @@ function App() {{
                         <div className="h-full flex items-center justify-center text-gray-500">
                             Select a match to view details
                         </div>
-                    )}}
+                    )}
                 </div>
             </div>
         </div>
     );
-}}
+}
+
+// Word shading by alignment op (results written with --include-alignment-ops)
+const OP_CLASSES = { M: 'op-match', R: 'op-root', S: 'op-sub', D: 'op-gap', I: 'op-gap' };
+const OP_LABELS = { M: 'match', R: 'root match', S: 'substitution', D: 'gap', I: 'gap' };
+
+function OpLegend() {
+    return (
+        <div className="flex gap-3 mt-2 text-xs text-gray-600">
+            {['M', 'R', 'S', 'D'].map(op => (
+                <span key={op} className={`${OP_CLASSES[op]} px-1 rounded`}>{OP_LABELS[op]}</span>
+            ))}
+        </div>
+    );
+}
+
+function MatchedText({ text, ops, side }) {
+    const words = text.split(' ');
+    // A target-only op has no source word, and a source-only op no target word
+    const sideOps = [...(ops || '')].filter(op => op !== (side === 'source' ? 'I' : 'D'));
+    if (sideOps.length !== words.length) {
+        return <span className="highlight-match">{text}</span>;
+    }
+    return words.map((word, i) => (
+        <React.Fragment key={i}>
+            {i > 0 && ' '}
+            <span className={OP_CLASSES[sideOps[i]]} title={OP_LABELS[sideOps[i]]}>{word}</span>
+        </React.Fragment>
+    ));
+}
 
 // Passage Display Component
-function PassageDisplay({{ title, bookTitle, location, text }}) {{
+function PassageDisplay({ title, bookTitle, location, text, ops, side }) {
     return (
         <div className="p-4 border rounded-lg bg-white">
             <div className="mb-3">
-                <h3 className="font-bold text-lg">{{title}}</h3>
-                <p className="text-sm text-gray-600">{{bookTitle}}</p>
-                <p className="text-sm text-gray-500">{{location}}</p>
+                <h3 className="font-bold text-lg">{title}</h3>
+                <p className="text-sm text-gray-600">{bookTitle}</p>
+                <p className="text-sm text-gray-500">{location}</p>
             </div>
             <div className="arabic-text text-right leading-loose" dir="rtl" lang="ar">
-                <span className="context-text">{{text.before}}</span>
-                {{text.before && ' '}}
-                <span className="highlight-match">{{text.matched}}</span>
-                {{text.after && ' '}}
-                <span className="context-text">{{text.after}}</span>
+                <span className="context-text">{text.before}</span>
+                {text.before && ' '}
+                <MatchedText text={text.matched} ops={ops} side={side} />
+                {text.after && ' '}
+                <span className="context-text">{text.after}</span>
             </div>
         </div>
     );
-}}
+}
 
-// Render the app
-const root = ReactDOM.createRoot(document.getElementById('root'));
-root.render(<App />);
 "##;
 
 #[cfg(test)]
@@ -854,10 +3520,21 @@ mod tests {
             span_coverage: 0.90,     // (85 + 5) / 100
             content_weight: 1.5,
             lexical_diversity: 0.70, // 70% unique lemmas
+            shingle_jaccard: 0.60,
+            is_verse: true,
+            suspect: false,
+            quran_overlap: 0.0,
+            source_span_length: 100,
+            target_span_length: 100,
+            span_ratio: 1.0,
+            direction: Direction::Unknown,
+            validation: None,
             lemma_similarity: 0.85,
             combined_similarity: 0.90,
             weighted_similarity: 0.85,
             avg_match_weight: 1.5,
+            score: 160,
+            normalized_score: 1.6,
         }
     }
 
@@ -867,12 +3544,57 @@ mod tests {
         assert_eq!(format_page_location(0, 0, 0), "0:0.0");
     }
 
+    #[test]
+    fn test_format_run_info() {
+        let run_info = RunInfo {
+            load_ms: 120,
+            align_ms: 45,
+            ..Default::default()
+        };
+        assert_eq!(
+            format_run_info(&run_info),
+            "load 120ms, window 0ms, filter 0ms, align 45ms, merge 0ms, filter-metrics 0ms, text 0ms"
+        );
+    }
+
+    #[test]
+    fn test_format_run_stats() {
+        let stats = crate::models::RunStats {
+            source_windows: 12,
+            target_windows: 9,
+            candidate_pairs: 30,
+            alignments: 28,
+            rejected_alignments: 20,
+            raw_edges: 8,
+            merged_edges: 3,
+            dropped_edges: 1,
+            ..Default::default()
+        };
+        let mut run_info = RunInfo {
+            stats,
+            peak_rss_bytes: Some(3 * 1024 * 1024 / 2),
+            ..Default::default()
+        };
+        assert_eq!(
+            format_run_stats(&run_info),
+            "12 + 9 windows, 30 candidates, 28 aligned (20 rejected), 8 raw edges, 3 merged, 1 dropped, peak RSS 1.5 MB"
+        );
+        run_info.peak_rss_bytes = None;
+        assert!(!format_run_stats(&run_info).contains("RSS"));
+    }
+
+    #[test]
+    fn test_target_output_path() {
+        assert_eq!(target_output_path(Path::new("out/r.json"), 553), Path::new("out/r_553.json"));
+        assert_eq!(target_output_path(Path::new("result"), 7), Path::new("result_7"));
+    }
+
     #[test]
     fn test_format_edge() {
         let edge = create_test_edge();
         let formatted = format_edge(&edge);
 
-        assert!(formatted.contains("Edge 1"));
+        assert!(formatted.contains("Edge 1 [verse]"));
         assert!(formatted.contains("Book 100"));
         assert!(formatted.contains("Book 200"));
         assert!(formatted.contains("len=100"));
@@ -881,6 +3603,7 @@ mod tests {
         assert!(formatted.contains("Core: 94.4%"));
         assert!(formatted.contains("Coverage: 90.0%"));
         assert!(formatted.contains("Weight: 1.50"));
+        assert!(formatted.contains("Spans: 100→100 tokens (ratio 1.00)"));
     }
 
     #[test]
@@ -895,6 +3618,154 @@ mod tests {
         assert!(csv.contains("1,100,1,10")); // Data
     }
 
+    #[test]
+    fn test_csv_round_trip() {
+        let mut second = create_test_edge();
+        second.id = 2;
+        second.source_start_page = (3, 7);
+        second.core_similarity = 0.123_456_79;
+        let edges = vec![create_test_edge(), second];
+
+        let mut output = Vec::new();
+        write_csv(&edges, &mut output).unwrap();
+        let read = read_csv_edges(output.as_slice()).unwrap();
+
+        assert_eq!(read.len(), 2);
+        for (a, b) in edges.iter().zip(&read) {
+            assert_eq!(a.id, b.id);
+            assert_eq!(a.source_start_page, b.source_start_page);
+            assert_eq!(a.target_global_end, b.target_global_end);
+            assert_eq!(a.lemma_matches, b.lemma_matches);
+            assert_eq!(a.core_similarity, b.core_similarity);
+            assert_eq!(a.weighted_similarity, b.weighted_similarity);
+            assert_eq!(a.shingle_jaccard, b.shingle_jaccard);
+            assert_eq!(a.is_verse, b.is_verse);
+            assert_eq!(a.span_ratio, b.span_ratio);
+        }
+    }
+
+    #[test]
+    fn test_write_edges_jsonl() {
+        let mut second = create_test_edge();
+        second.id = 2;
+        let edges = vec![create_test_edge(), second];
+
+        let mut output = Vec::new();
+        write_edges_jsonl(&edges, &mut output).unwrap();
+        let text = String::from_utf8(output).unwrap();
+
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        for (line, edge) in lines.iter().zip(&edges) {
+            let read: ReuseEdge = serde_json::from_str(line).unwrap();
+            assert_eq!(read.id, edge.id);
+            assert_eq!(read.source_global_start, edge.source_global_start);
+            assert_eq!(read.core_similarity, edge.core_similarity);
+        }
+        assert_eq!(
+            jsonl_summary_path(Path::new("out/edges.jsonl")),
+            PathBuf::from("out/edges.summary.json")
+        );
+    }
+
+    #[test]
+    fn test_read_edge_without_score() {
+        // Results written before raw scores were recorded
+        let mut value = serde_json::to_value(create_test_edge()).unwrap();
+        let fields = value.as_object_mut().unwrap();
+        fields.remove("score");
+        fields.remove("normalized_score");
+
+        let edge: ReuseEdge = serde_json::from_value(value).unwrap();
+        assert_eq!(edge.id, 1);
+        assert_eq!((edge.score, edge.normalized_score), (0, 0.0));
+    }
+
+    #[test]
+    fn test_read_params_without_lemma_alternatives() {
+        use crate::models::ComparisonParams;
+
+        let mut value = serde_json::to_value(ComparisonParams::default()).unwrap();
+        value.as_object_mut().unwrap().remove("use_lemma_alternatives");
+
+        let params: ComparisonParams = serde_json::from_value(value).unwrap();
+        assert!(!params.use_lemma_alternatives);
+    }
+
+    #[test]
+    fn test_output_columns() {
+        let options = CsvOptions {
+            columns: OutputColumns::default().column("core_similarity").column("id").column("direction"),
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        write_csv_with_options(&[create_test_edge()], &options, &mut output).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "core_similarity,id,direction\n0.944,1,Unknown\n");
+
+        let mut output = Vec::new();
+        write_edges_jsonl_with_columns(&[create_test_edge()], &options.columns, &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "{\"core_similarity\":0.944,\"id\":1,\"direction\":\"Unknown\"}\n"
+        );
+
+        let options = CsvOptions {
+            columns: OutputColumns::default().column("id").column("source_text_matched"),
+            ..Default::default()
+        };
+        let err = write_csv_with_options(&[create_test_edge()], &options, &mut Vec::new()).unwrap_err();
+        assert!(matches!(err, OutputError::UnknownColumn { column, .. } if column == "source_text_matched"));
+    }
+
+    #[test]
+    fn test_csv_dialect_round_trip() {
+        // Edge CSV keeps English headers whatever the header language
+        let options = CsvOptions {
+            delimiter: b';',
+            bom: true,
+            headers: HeaderLanguage::Arabic,
+            ..Default::default()
+        };
+        let mut output = Vec::new();
+        write_csv_with_options(&[create_test_edge()], &options, &mut output).unwrap();
+
+        assert!(output.starts_with(UTF8_BOM));
+        let csv = String::from_utf8(output[UTF8_BOM.len()..].to_vec()).unwrap();
+        assert!(csv.starts_with("id;source_book_id;"));
+        assert!(csv.contains("1;100;1;10"));
+
+        let read = read_csv_edges_with_options(csv.as_bytes(), &options).unwrap();
+        assert_eq!(read[0].id, 1);
+        // The BOM is skipped when reading
+        let read = read_csv_edges_with_options(
+            [UTF8_BOM, csv.as_bytes()].concat().as_slice(),
+            &options,
+        )
+        .unwrap();
+        assert_eq!(read[0].source_book_id, 100);
+    }
+
+    #[test]
+    fn test_read_csv_reordered_columns() {
+        let mut output = Vec::new();
+        write_csv(&[create_test_edge()], &mut output).unwrap();
+        let text = String::from_utf8(output).unwrap();
+
+        // Swap the first two columns, as a spreadsheet user might
+        let swapped: String = text
+            .lines()
+            .map(|line| {
+                let mut fields: Vec<&str> = line.split(',').collect();
+                fields.swap(0, 1);
+                fields.join(",") + "\n"
+            })
+            .collect();
+
+        let read = read_csv_edges(swapped.as_bytes()).unwrap();
+        assert_eq!(read[0].id, 1);
+        assert_eq!(read[0].source_book_id, 100);
+    }
+
     #[test]
     fn test_write_csv_empty() {
         let edges: Vec<ReuseEdge> = vec![];
@@ -907,4 +3778,197 @@ mod tests {
         assert!(csv.contains("id,source_book_id"));
         assert_eq!(csv.lines().count(), 1);
     }
+
+    #[test]
+    fn test_format_sweep_table() {
+        use crate::models::{GoldScores, SweepRun};
+
+        let run = |window_size: u32, scores: Option<GoldScores>| SweepRun {
+            values: [("window_size".to_string(), serde_json::json!(window_size))].into(),
+            edge_count: 12,
+            book_a_coverage: 0.05,
+            book_b_coverage: 0.5,
+            wall_ms: 830,
+            scores,
+        };
+        let scores = GoldScores {
+            gold_edges: 10,
+            matched_edges: 9,
+            found_gold: 8,
+            precision: 0.75,
+            recall: 0.8,
+            f1: 0.774,
+        };
+        let report = SweepReport {
+            version: String::new(),
+            generated_at: String::new(),
+            book_a: 1,
+            book_b: 2,
+            parameters: Default::default(),
+            gold: Some("gold.json".to_string()),
+            min_overlap: 0.5,
+            runs: vec![run(200, Some(scores)), run(300, None)],
+        };
+        assert_eq!(
+            format_sweep_table(&report),
+            [
+                "#  window_size  edges  A cov  B cov  precision  recall     F1   ms",
+                "1          200     12   5.0%  50.0%      0.750   0.800  0.774  830",
+                "2          300     12   5.0%  50.0%          -       -      -  830",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_histogram() {
+        let distribution = Distribution::new(vec![10.0, 30.0, 40.0, 90.0], &[0.0, 20.0, 50.0]);
+        let tokens = |value: f32| format!("{:.0}", value);
+        assert_eq!(
+            format_distribution(&distribution, tokens),
+            "min 10, p25 10, median 30, p75 40, p90 90, max 90"
+        );
+        assert_eq!(
+            format_histogram(&distribution, tokens),
+            [
+                "     0-20      1 ###############",
+                "    20-50      2 ##############################",
+                "      50+      1 ###############",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_page_summary_csv() {
+        let edges = vec![
+            ReuseEdge {
+                lemma_similarity: 0.75,
+                ..create_test_edge()
+            },
+            ReuseEdge {
+                id: 2,
+                source_global_start: 550,
+                source_global_end: 650,
+                lemma_similarity: 0.25,
+                ..create_test_edge()
+            },
+        ];
+        let page = |book_id: u32, page_id: u32, token_count: u32| PageInfo {
+            book_id,
+            part_index: 1,
+            page_id,
+            part_label: None,
+            page_number: Some(page_id.to_string()),
+            token_count,
+        };
+        let pages = vec![page(100, 1, 520), page(100, 2, 100), page(100, 3, 100), page(100, 4, 100), page(200, 1, 2000)];
+
+        let mut output = Vec::new();
+        write_page_summary_csv(&edges, &pages, &mut output).unwrap();
+        let csv = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(
+            lines,
+            [
+                "book_id,part_index,page_id,part_label,page_number,token_count,edge_count,covered_tokens,avg_similarity",
+                "100,1,1,,1,520,1,20,0.75",
+                "100,1,2,,2,100,2,100,0.5",
+                "100,1,3,,3,100,1,30,0.25",
+                "100,1,4,,4,100,0,0,0.0",
+                "200,1,1,,1,2000,2,100,0.5",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_edge_listing_sort_and_group() {
+        let edge = |id: u64, page: u32, start: usize, similarity: f32, score: i32| ReuseEdge {
+            id,
+            source_start_page: (1, page),
+            source_global_start: start,
+            core_similarity: similarity,
+            score,
+            ..create_test_edge()
+        };
+        let edges = vec![
+            edge(1, 20, 2000, 0.8, 50),
+            edge(2, 10, 1000, 0.9, 40),
+            edge(3, 20, 2100, 0.9, 60),
+            edge(4, 10, 1100, 0.5, 70),
+        ];
+        let ids = |options: &EdgePrintOptions| -> Vec<String> {
+            edge_listing(&edges, options, edge_fields, |edge, _| edge.id.to_string())
+        };
+
+        let by_similarity_then_score = EdgePrintOptions {
+            sort: vec![EdgeSort::Similarity, EdgeSort::Score],
+            ..Default::default()
+        };
+        assert_eq!(ids(&by_similarity_then_score), ["3", "2", "1", "4"]);
+
+        let grouped = EdgePrintOptions {
+            limit: Some(3),
+            group_by_page: true,
+            ..by_similarity_then_score
+        };
+        assert_eq!(
+            ids(&grouped),
+            [
+                "── Source page 1:10 (1 edges)",
+                "2",
+                "── Source page 1:20 (2 edges)",
+                "3",
+                "1",
+                "... and 1 more edges",
+            ]
+        );
+
+        assert_eq!(
+            format_edge_compact(&edges[0]),
+            "#1 1:20.5 ↔ 2:5.10  len=100 core=80% score=50 [verse]"
+        );
+    }
+
+    #[test]
+    fn test_write_graph_formats() {
+        let graph = ReuseGraph {
+            nodes: vec![
+                GraphNode {
+                    id: 1,
+                    title: "Sīra & \"Maghāzī\"".to_string(),
+                    death_ah: Some(151),
+                    token_count: 1000,
+                },
+                GraphNode {
+                    id: 2,
+                    ..Default::default()
+                },
+            ],
+            edges: vec![NetworkEdge {
+                source: 1,
+                target: 2,
+                weight: 420,
+                edge_count: 3,
+                source_coverage: 0.25,
+                target_coverage: 0.5,
+                avg_similarity: 0.9,
+                order_tau: 1.0,
+            }],
+        };
+
+        let mut graphml = Vec::new();
+        write_graphml(&graph, &mut graphml).unwrap();
+        let graphml = String::from_utf8(graphml).unwrap();
+        assert!(graphml.contains(r#"<data key="label">Sīra &amp; &quot;Maghāzī&quot;</data>"#));
+        assert!(graphml.contains(r#"<data key="label">Book 2</data>"#));
+        assert!(graphml.contains(r#"<edge source="1" target="2">"#));
+        assert!(graphml.contains(r#"<data key="weight">420</data>"#));
+        assert_eq!(graphml.matches(r#"key="death_ah""#).count(), 1);
+
+        let mut gexf = Vec::new();
+        write_gexf(&graph, &mut gexf).unwrap();
+        let gexf = String::from_utf8(gexf).unwrap();
+        assert!(gexf.contains(r#"<node id="1" label="Sīra &amp; &quot;Maghāzī&quot;">"#));
+        assert!(gexf.contains(r#"<edge id="0" source="1" target="2" weight="420">"#));
+        assert!(gexf.contains(r#"<attvalue for="0" value="3"/>"#));
+    }
 }
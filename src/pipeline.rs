@@ -0,0 +1,339 @@
+//! Corpus runs from a single configuration file.
+//!
+//! `kashshaf-reuse pipeline --config pipeline.toml` chains the corpus
+//! commands into one workflow:
+//!
+//! 1. pairwise comparison of the selected books, with the vocabulary prescan
+//!    (`compare-all`),
+//! 2. corpus totals and the book network edge list (`aggregate`),
+//! 3. the network as GraphML and/or GEXF (`graph`),
+//! 4. the corpus dashboard (`viewer`).
+//!
+//! Everything is written under `output_dir`:
+//!
+//! ```text
+//! <output_dir>/
+//!   manifest.json
+//!   corpus_summary.json
+//!   network.csv
+//!   network.graphml
+//!   network.gexf
+//!   dashboard.html
+//!   results/{a}_{b}/...
+//! ```
+//!
+//! Rerunning the same configuration is cheap: the comparison step reuses every
+//! pair whose results are still valid, including pairs finished by an
+//! interrupted run, and the later steps are rebuilt from the pair results.
+//!
+//! A configuration file looks like this (paths are relative to the file):
+//!
+//! ```toml
+//! corpus_db = "corpus.db"
+//! output_dir = "runs/tafsir"
+//! collection = "tafsir"
+//! min_prescan_overlap = 0.05
+//!
+//! [params]
+//! min_core_similarity = 0.9
+//! weight_source = "Corpus"
+//!
+//! [outputs]
+//! gexf = true
+//! ```
+
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::aggregate::{aggregate_batches, AggregateError};
+use crate::batch::{all_pairs, compare_book_pairs, cross_pairs, BatchError, BatchOptions};
+use crate::config::ConfigError;
+use crate::db::{load_collection, DbError};
+use crate::graph::{load_network, GraphError};
+use crate::metadata::{filter_pairs, load_book_metadata, MetadataError, PairFilter};
+use crate::models::{ComparisonParams, CorpusSummary};
+use crate::output::{
+    read_results_with_text_dir, write_corpus_viewer_html_file, write_gexf_file, write_graphml_file, OutputError,
+    ViewerAssets,
+};
+use crate::validate::{validate_book_list, validate_context_tokens, validate_params, ValidationError};
+
+/// Name of the GraphML network file.
+pub const GRAPHML_FILE: &str = "network.graphml";
+/// Name of the GEXF network file.
+pub const GEXF_FILE: &str = "network.gexf";
+/// Name of the corpus dashboard.
+pub const DASHBOARD_FILE: &str = "dashboard.html";
+
+#[derive(Error, Debug)]
+pub enum PipelineError {
+    #[error("{}: {source}", path.display())]
+    Io { path: PathBuf, source: std::io::Error },
+    #[error("{}: {source}", path.display())]
+    Config { path: PathBuf, source: toml::de::Error },
+    #[error(transparent)]
+    Params(#[from] ConfigError),
+    #[error("set either `books` or `collection`, not both")]
+    BooksAndCollection,
+    #[error(transparent)]
+    Invalid(#[from] ValidationError),
+    #[error(transparent)]
+    Db(#[from] DbError),
+    #[error(transparent)]
+    Metadata(#[from] MetadataError),
+    #[error(transparent)]
+    Batch(#[from] BatchError),
+    #[error(transparent)]
+    Aggregate(#[from] AggregateError),
+    #[error(transparent)]
+    Graph(#[from] GraphError),
+    #[error(transparent)]
+    Output(#[from] OutputError),
+}
+
+// ============================================================================
+// Configuration
+// ============================================================================
+
+/// A pipeline configuration file.
+///
+/// The book selection and batch keys mirror the `compare-all` flags. The
+/// `[params]` table takes `ComparisonParams` fields by name (as in the
+/// `parameters` block of a result file); fields left out keep their defaults.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PipelineConfig {
+    pub corpus_db: PathBuf,
+    pub output_dir: PathBuf,
+    /// Books to compare all-vs-all
+    #[serde(default)]
+    pub books: Vec<u32>,
+    /// Compare the books of this collection instead of `books`
+    pub collection: Option<String>,
+    /// Compare against this collection (cross pairs) instead of all-vs-all
+    pub vs_collection: Option<String>,
+    /// Book metadata spreadsheet, for the pair filters
+    pub metadata: Option<PathBuf>,
+    #[serde(default)]
+    pub exclude_same_author: bool,
+    pub min_century_gap: Option<u32>,
+    pub min_prescan_overlap: Option<f32>,
+    #[serde(default = "default_context_tokens")]
+    pub context_tokens: usize,
+    /// Recompare every pair instead of reusing earlier results
+    #[serde(default)]
+    pub force: bool,
+    /// Directory with the viewer libraries, for offline HTML output
+    pub offline_viewer: Option<PathBuf>,
+    #[serde(default)]
+    pub params: toml::Table,
+    #[serde(default)]
+    pub outputs: PipelineOutputs,
+}
+
+fn default_context_tokens() -> usize {
+    BatchOptions::default().context_tokens
+}
+
+/// Which corpus-level files to write after the comparison step.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipelineOutputs {
+    pub graphml: bool,
+    pub gexf: bool,
+    pub dashboard: bool,
+}
+
+impl Default for PipelineOutputs {
+    fn default() -> Self {
+        Self {
+            graphml: true,
+            gexf: false,
+            dashboard: true,
+        }
+    }
+}
+
+impl PipelineConfig {
+    /// Parse a configuration from TOML text; relative paths are kept as is.
+    pub fn parse(text: &str, path: &Path) -> Result<Self, PipelineError> {
+        toml::from_str(text).map_err(|source| PipelineError::Config {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Read a configuration file, resolving its relative paths against the
+    /// file's directory.
+    pub fn from_file(path: &Path) -> Result<Self, PipelineError> {
+        let text = std::fs::read_to_string(path).map_err(|source| PipelineError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let mut config = Self::parse(&text, path)?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        for path in [
+            Some(&mut config.corpus_db),
+            Some(&mut config.output_dir),
+            config.metadata.as_mut(),
+            config.offline_viewer.as_mut(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *path = base.join(&*path);
+        }
+        Ok(config)
+    }
+
+    /// Comparison parameters: the defaults, overridden by `[params]`.
+    pub fn comparison_params(&self) -> Result<ComparisonParams, PipelineError> {
+        Ok(ComparisonParams::from_toml_table(&self.params)?)
+    }
+
+    /// The book pairs to compare, after the metadata filters.
+    pub fn pairs(&self) -> Result<Vec<(u32, u32)>, PipelineError> {
+        let books = match &self.collection {
+            Some(_) if !self.books.is_empty() => return Err(PipelineError::BooksAndCollection),
+            Some(name) => load_collection(&self.corpus_db, name)?,
+            None => self.books.clone(),
+        };
+        validate_book_list(&books)?;
+        let mut pairs = match &self.vs_collection {
+            Some(name) => cross_pairs(&books, &load_collection(&self.corpus_db, name)?),
+            None => all_pairs(&books),
+        };
+        let pair_filter = PairFilter {
+            exclude_same_author: self.exclude_same_author,
+            min_century_gap: self.min_century_gap,
+        };
+        if let Some(path) = self.metadata.as_deref().filter(|_| pair_filter.is_active()) {
+            pairs = filter_pairs(&pairs, &load_book_metadata(path)?, &pair_filter);
+        }
+        Ok(pairs)
+    }
+}
+
+// ============================================================================
+// Running
+// ============================================================================
+
+/// What a pipeline run did.
+#[derive(Debug, Clone)]
+pub struct PipelineReport {
+    pub summary: CorpusSummary,
+    /// Pairs reused from an earlier run instead of compared
+    pub cached_pairs: usize,
+    /// Network and dashboard files written, in order
+    pub files: Vec<PathBuf>,
+}
+
+/// Run every step of a pipeline configuration.
+///
+/// The comparison step always resumes from an earlier run's results unless
+/// `force` is set. The dashboard is skipped when no pair has results.
+pub fn run_pipeline(config: &PipelineConfig, show_progress: bool) -> Result<PipelineReport, PipelineError> {
+    let params = config.comparison_params()?;
+    validate_params(&params)?;
+    validate_context_tokens(config.context_tokens)?;
+    let viewer_assets = match &config.offline_viewer {
+        Some(dir) => ViewerAssets::from_dir(dir)?,
+        None => ViewerAssets::Cdn,
+    };
+    let pairs = config.pairs()?;
+    let output_dir = &config.output_dir;
+
+    if show_progress {
+        eprintln!("[1/4] Comparing {} pairs", pairs.len());
+    }
+    let options = BatchOptions {
+        min_prescan_overlap: config.min_prescan_overlap,
+        context_tokens: config.context_tokens,
+        force: config.force,
+        resume: true,
+        shard: None,
+        viewer_assets: viewer_assets.clone(),
+    };
+    let manifest = compare_book_pairs(&config.corpus_db, &pairs, &params, &options, output_dir, show_progress)?;
+    let cached_pairs = manifest.pairs.iter().filter(|p| p.cached).count();
+
+    if show_progress {
+        eprintln!("[2/4] Aggregating corpus totals");
+    }
+    let (_, summary) = aggregate_batches(std::slice::from_ref(output_dir), output_dir)?;
+
+    let mut files = Vec::new();
+    if config.outputs.graphml || config.outputs.gexf {
+        if show_progress {
+            eprintln!("[3/4] Writing the book network");
+        }
+        let network = load_network(output_dir)?;
+        if config.outputs.graphml {
+            let path = output_dir.join(GRAPHML_FILE);
+            write_graphml_file(&network, &path)?;
+            files.push(path);
+        }
+        if config.outputs.gexf {
+            let path = output_dir.join(GEXF_FILE);
+            write_gexf_file(&network, &path)?;
+            files.push(path);
+        }
+    }
+
+    if config.outputs.dashboard {
+        if show_progress {
+            eprintln!("[4/4] Writing the dashboard");
+        }
+        let results = read_results_with_text_dir(output_dir)?;
+        if !results.is_empty() {
+            let path = output_dir.join(DASHBOARD_FILE);
+            write_corpus_viewer_html_file(&results, &viewer_assets, &path)?;
+            files.push(path);
+        }
+    }
+
+    Ok(PipelineReport {
+        summary,
+        cached_pairs,
+        files,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::WeightSource;
+
+    #[test]
+    fn test_parse_config() {
+        let text = r#"
+            corpus_db = "corpus.db"
+            output_dir = "out"
+            books = [1, 2, 3]
+
+            [params]
+            min_core_similarity = 0.9
+            weight_source = "Corpus"
+            stop_lemmas = [7, 8]
+
+            [outputs]
+            gexf = true
+        "#;
+        let config = PipelineConfig::parse(text, Path::new("pipeline.toml")).unwrap();
+        assert_eq!(config.pairs().unwrap(), vec![(1, 2), (1, 3), (2, 3)]);
+        assert_eq!(config.context_tokens, 30);
+        assert!(config.outputs.graphml && config.outputs.gexf && config.outputs.dashboard);
+
+        let params = config.comparison_params().unwrap();
+        assert_eq!(params.min_core_similarity, Some(0.9));
+        assert_eq!(params.weight_source, WeightSource::Corpus);
+        assert_eq!(params.stop_lemmas.map(|s| s.len()), Some(2));
+        assert_eq!(params.window_size, ComparisonParams::default().window_size);
+
+        let typo = text.replace("min_core_similarity", "min_core_simlarity");
+        let config = PipelineConfig::parse(&typo, Path::new("pipeline.toml")).unwrap();
+        assert!(matches!(config.comparison_params(), Err(PipelineError::Params(_))));
+        assert!(PipelineConfig::parse("corpus_db = \"a\"\noutput_dir = \"b\"\nbook = [1]", Path::new("x")).is_err());
+    }
+}
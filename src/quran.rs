@@ -0,0 +1,119 @@
+//! Quranic quotation detection.
+//!
+//! Two authors quoting the same verse share its wording without either
+//! borrowing from the other. When `quran_book_id` names the Quran's book in
+//! the corpus, its lemma stream is indexed by shingles of
+//! [`QURAN_SHINGLE_SIZE`] lemmas, and each edge's `quran_overlap` is the share
+//! of its spans covered by shingles that also occur in the Quran (the smaller
+//! of the source and target shares). `max_quran_overlap` then drops edges that
+//! are mostly shared scripture.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+
+use crate::db::{load_book_token_stream_with_root, DbError};
+use crate::filter::shingle_hash;
+use crate::models::{BookTokenStream, ComparisonParams, ReuseEdge};
+
+/// Lemmas per shingle of the Quran index. Shorter shingles would count
+/// stock phrases of prose as scripture.
+pub const QURAN_SHINGLE_SIZE: usize = 5;
+
+/// Lemma shingles of the Quran.
+#[derive(Debug, Clone, Default)]
+pub struct QuranIndex {
+    shingles: HashSet<u64>,
+}
+
+impl QuranIndex {
+    /// Index the lemma shingles of the Quran's stream. Shingles holding an
+    /// unanalysed token (lemma 0) are skipped, here and in [`Self::overlap`].
+    pub fn from_stream(stream: &BookTokenStream) -> Self {
+        let shingles = stream
+            .flat_lemma_ids()
+            .windows(QURAN_SHINGLE_SIZE)
+            .filter(|shingle| !shingle.contains(&0))
+            .map(shingle_hash)
+            .collect();
+        QuranIndex { shingles }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shingles.is_empty()
+    }
+
+    /// Fraction of `lemmas` covered by a shingle that also occurs in the Quran.
+    pub fn overlap(&self, lemmas: &[u32]) -> f32 {
+        if lemmas.is_empty() {
+            return 0.0;
+        }
+        let mut covered = 0;
+        let mut covered_until = 0;
+        for (start, shingle) in lemmas.windows(QURAN_SHINGLE_SIZE).enumerate() {
+            if !shingle.contains(&0) && self.shingles.contains(&shingle_hash(shingle)) {
+                let end = start + QURAN_SHINGLE_SIZE;
+                covered += end - start.max(covered_until);
+                covered_until = end;
+            }
+        }
+        covered as f32 / lemmas.len() as f32
+    }
+}
+
+/// Load book `book_id` of corpus.db as the Quran and index it.
+pub fn load_quran_index(db_path: &Path, book_id: u32, token_to_lemma: &[u32]) -> Result<Arc<QuranIndex>, DbError> {
+    let stream = load_book_token_stream_with_root(db_path, book_id, token_to_lemma, &[])?;
+    Ok(Arc::new(QuranIndex::from_stream(&stream)))
+}
+
+/// The Quran index if `params` asks for one, else None.
+pub fn quran_index_for(
+    params: &ComparisonParams,
+    db_path: &Path,
+    token_to_lemma: &[u32],
+) -> Result<Option<Arc<QuranIndex>>, DbError> {
+    params
+        .quran_book_id
+        .map(|book_id| load_quran_index(db_path, book_id, token_to_lemma))
+        .transpose()
+}
+
+/// Set `quran_overlap` on every edge: the smaller of the overlaps of its
+/// source span in `stream_a` and its target span in `stream_b`.
+pub fn tag_quran_edges(edges: &mut [ReuseEdge], stream_a: &BookTokenStream, stream_b: &BookTokenStream, index: &QuranIndex) {
+    if index.is_empty() {
+        return;
+    }
+    let (lemmas_a, lemmas_b) = (stream_a.flat_lemma_ids(), stream_b.flat_lemma_ids());
+    let span_overlap = |lemmas: &[u32], start: usize, end: usize| {
+        let end = end.min(lemmas.len());
+        index.overlap(&lemmas[start.min(end)..end])
+    };
+    for edge in edges {
+        edge.quran_overlap = span_overlap(&lemmas_a, edge.source_global_start, edge.source_global_end)
+            .min(span_overlap(&lemmas_b, edge.target_global_start, edge.target_global_end));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(book_id: u32, lemma_ids: Vec<u32>) -> BookTokenStream {
+        BookTokenStream::single_page(book_id, lemma_ids)
+    }
+
+    #[test]
+    fn test_quran_overlap() {
+        let index = QuranIndex::from_stream(&stream(114, (1..=50).collect()));
+
+        // Ten tokens of prose, then ten of a verse
+        let mixed: Vec<u32> = (100..110).chain(20..30).collect();
+        assert_eq!(index.overlap(&mixed), 0.5);
+        assert_eq!(index.overlap(&(20..30).collect::<Vec<_>>()), 1.0);
+        // A verse fragment shorter than a shingle is not recognized
+        assert_eq!(index.overlap(&[100, 101, 20, 21, 22, 23, 102]), 0.0);
+        assert_eq!(index.overlap(&[]), 0.0);
+    }
+}
@@ -0,0 +1,85 @@
+//! Stripping reconstructed text from results, and restoring it.
+//!
+//! Results on texts under restrictive licenses can't always be shared with
+//! their text. [`redact_result`] empties every passage's text and signature
+//! but keeps locations, token offsets and metrics; whoever receives the result
+//! restores the text from their own copy of corpus.db with
+//! [`rehydrate_result`]. The stream hashes recorded for both books make sure
+//! the restored text comes from the same book content the comparison ran on.
+
+use std::path::Path;
+use thiserror::Error;
+
+use crate::db::{load_all_token_mappings, load_book_token_stream_with_root, DbError};
+use crate::idf::stop_lemmas_for;
+use crate::models::{
+    BookTokenStream, ComparisonResultWithText, PassageContext, PassageText, Redaction, ViewerBookInfo,
+};
+use crate::signature::span_signature;
+
+#[derive(Error, Debug)]
+pub enum RedactError {
+    #[error(transparent)]
+    Db(#[from] DbError),
+    #[error("result is not redacted")]
+    NotRedacted,
+    #[error("book {0} in corpus.db differs from the book the result was computed on")]
+    StreamMismatch(u32),
+}
+
+fn empty_text() -> PassageText {
+    PassageText {
+        before: String::new(),
+        matched: String::new(),
+        after: String::new(),
+    }
+}
+
+/// Strip the text and signature of every edge, recording the context width
+/// so [`rehydrate_result`] can rebuild the same passages.
+pub fn redact_result(result: &mut ComparisonResultWithText, context_tokens: usize) {
+    for edge in &mut result.edges {
+        edge.signature.clear();
+        edge.source.text = empty_text();
+        edge.target.text = empty_text();
+    }
+    result.redaction = Some(Redaction { context_tokens });
+}
+
+/// Restore the text and signatures of a redacted result from corpus.db.
+///
+/// Fails if either book's token stream no longer hashes to the value recorded
+/// in the result. For a comparison of two sections of one book, context near
+/// the section boundaries may run past them, and signatures are computed
+/// against the whole book.
+pub fn rehydrate_result(result: &mut ComparisonResultWithText, db_path: &Path) -> Result<(), RedactError> {
+    let redaction = result.redaction.ok_or(RedactError::NotRedacted)?;
+    let (token_to_lemma, token_to_root, token_to_surface) = load_all_token_mappings(db_path)?;
+
+    let load = |book: &ViewerBookInfo| -> Result<BookTokenStream, RedactError> {
+        let stream = load_book_token_stream_with_root(db_path, book.id, &token_to_lemma, &token_to_root)?;
+        if !book.stream_hash.is_empty() && stream.content_hash() != book.stream_hash {
+            return Err(RedactError::StreamMismatch(book.id));
+        }
+        Ok(stream)
+    };
+    let stream_a = load(&result.book_a)?;
+    let stream_b = match result.book_b.id == result.book_a.id {
+        true => None,
+        false => Some(load(&result.book_b)?),
+    };
+    let stream_b = stream_b.as_ref().unwrap_or(&stream_a);
+
+    let stop_lemmas = stop_lemmas_for(&result.parameters, db_path, &token_to_lemma)?;
+    let context = PassageContext::new(redaction.context_tokens, &result.parameters);
+    let text = |stream: &BookTokenStream, (start, end): (usize, usize)| {
+        stream.get_surface_text_with_context(start, end, context, &token_to_surface)
+    };
+    for edge in &mut result.edges {
+        edge.source.text = text(&stream_a, edge.source.global_range);
+        edge.target.text = text(stream_b, edge.target.global_range);
+        edge.signature = span_signature(&stream_a, edge.source.global_range, &token_to_surface, &stop_lemmas);
+    }
+    result.redaction = None;
+    Ok(())
+}
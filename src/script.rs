@@ -0,0 +1,153 @@
+//! Script sanity checks.
+//!
+//! OCR junk, page furniture and numeric tables align as well as prose: a run
+//! of digits or Latin fragments repeats verbatim between two editions and
+//! scores high on every metric. Such edges are tagged `suspect` when too many
+//! tokens of either span are not Arabic words, i.e. contain a digit (Western
+//! or Arabic-Indic) or a letter of another script. Punctuation-only tokens
+//! (including hemistich markers) are neutral.
+
+use std::collections::HashSet;
+
+use crate::models::{BookTokenStream, ReuseEdge, SurfaceTable};
+
+/// Fraction of non-Arabic tokens in a span above which its edge is suspect.
+pub const MAX_NON_ARABIC_FRACTION: f32 = 0.3;
+
+/// Whether a character is an Arabic letter or mark (Arabic, Arabic
+/// Supplement and Arabic Extended-A blocks, and presentation forms).
+fn is_arabic(c: char) -> bool {
+    matches!(c, '\u{0600}'..='\u{06FF}' | '\u{0750}'..='\u{077F}' | '\u{08A0}'..='\u{08FF}'
+        | '\u{FB50}'..='\u{FDFF}' | '\u{FE70}'..='\u{FEFF}')
+}
+
+/// Whether a surface form is not an Arabic word: it holds a digit of any
+/// script, or a letter outside the Arabic blocks.
+pub fn is_non_arabic_token(surface: &str) -> bool {
+    surface
+        .chars()
+        .any(|c| c.is_numeric() || (c.is_alphabetic() && !is_arabic(c)))
+}
+
+/// Token IDs whose surface form is not an Arabic word.
+#[derive(Debug, Clone, Default)]
+pub struct NonArabicTokens {
+    token_ids: HashSet<u32>,
+}
+
+impl NonArabicTokens {
+    /// Collect non-Arabic tokens from a token_id -> surface mapping.
+    pub fn from_surfaces(token_to_surface: &SurfaceTable) -> Self {
+        let token_ids = token_to_surface
+            .iter()
+            .filter(|(_, surface)| is_non_arabic_token(surface))
+            .map(|(token_id, _)| token_id)
+            .collect();
+        NonArabicTokens { token_ids }
+    }
+
+    /// Build from explicit token IDs.
+    pub fn from_token_ids(token_ids: impl IntoIterator<Item = u32>) -> Self {
+        NonArabicTokens {
+            token_ids: token_ids.into_iter().collect(),
+        }
+    }
+
+    pub fn contains(&self, token_id: u32) -> bool {
+        self.token_ids.contains(&token_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.token_ids.is_empty()
+    }
+
+    /// Running counts over a stream: `counts[i]` is the number of non-Arabic
+    /// tokens among the first `i` tokens.
+    fn prefix_counts(&self, stream: &BookTokenStream) -> Vec<usize> {
+        let mut counts = Vec::with_capacity(stream.total_tokens + 1);
+        counts.push(0);
+        let mut seen = 0;
+        for page in &stream.pages {
+            for &token_id in &page.token_ids {
+                seen += usize::from(self.contains(token_id));
+                counts.push(seen);
+            }
+        }
+        counts
+    }
+}
+
+/// Whether a span of `span_len` tokens holding `non_arabic` non-Arabic tokens
+/// is suspect.
+pub fn is_suspect_span(span_len: usize, non_arabic: usize) -> bool {
+    span_len > 0 && non_arabic as f32 / span_len as f32 > MAX_NON_ARABIC_FRACTION
+}
+
+/// Set `suspect` on every edge whose source or target span is suspect.
+pub fn tag_suspect_edges(
+    edges: &mut [ReuseEdge],
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+    tokens: &NonArabicTokens,
+) {
+    if tokens.is_empty() {
+        return;
+    }
+    let counts_a = tokens.prefix_counts(stream_a);
+    let counts_b = tokens.prefix_counts(stream_b);
+    let span_is_suspect = |counts: &[usize], start: usize, end: usize| {
+        let end = end.min(counts.len() - 1);
+        let start = start.min(end);
+        is_suspect_span(end - start, counts[end] - counts[start])
+    };
+
+    for edge in edges {
+        edge.suspect = span_is_suspect(&counts_a, edge.source_global_start, edge.source_global_end)
+            || span_is_suspect(&counts_b, edge.target_global_start, edge.target_global_end);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_non_arabic_tokens() {
+        assert!(!is_non_arabic_token("قال"));
+        assert!(!is_non_arabic_token("وَقَالَ"));
+        assert!(!is_non_arabic_token("%~%"));
+        assert!(!is_non_arabic_token("،"));
+        assert!(is_non_arabic_token("123"));
+        assert!(is_non_arabic_token("١٢٣"));
+        assert!(is_non_arabic_token("ms0012"));
+        assert!(is_non_arabic_token("Vol"));
+        assert!(is_non_arabic_token("قال3"));
+
+        let surfaces: SurfaceTable = ["", "قال", "٢٠", "page"].into_iter().collect();
+        let tokens = NonArabicTokens::from_surfaces(&surfaces);
+        assert!(!tokens.contains(1));
+        assert!(tokens.contains(2));
+        assert!(tokens.contains(3));
+    }
+
+    #[test]
+    fn test_tag_suspect_edges() {
+        // Prose at [0, 50), then a table of numbers at [50, 80)
+        let mut token_ids = vec![5; 50];
+        token_ids.extend([6, 1, 1, 1, 6, 1].repeat(5));
+        let stream_a = BookTokenStream::single_page(1, token_ids);
+        let edge = |start, end| ReuseEdge {
+            source_global_start: start,
+            source_global_end: end,
+            target_global_start: start,
+            target_global_end: end,
+            ..Default::default()
+        };
+        // Prose, the table, and prose with a few page numbers
+        let mut edges = vec![edge(0, 50), edge(50, 80), edge(30, 56)];
+        tag_suspect_edges(&mut edges, &stream_a, &stream_a, &NonArabicTokens::from_token_ids([1]));
+        assert!(!edges[0].suspect);
+        assert!(edges[1].suspect);
+        assert!(!edges[2].suspect);
+    }
+}
@@ -0,0 +1,152 @@
+//! Hadith-aware segmentation.
+//!
+//! Hadith collections are sequences of reports, each opening with an isnād
+//! ("حدثنا فلان قال حدثنا فلان عن ..."). Comparing two collections with
+//! fixed windows splits a shared report across several windows and yields
+//! fragments. In hadith mode the streams are instead cut at isnād starts and
+//! each report becomes one comparison unit.
+//!
+//! Isnād starts are found formulaically from the transmission verbs in
+//! [`ISNAD_MARKERS`]. A chain repeats these verbs link after link, so only a
+//! marker at least [`ISNAD_LINK_GAP`] tokens after the previous one opens a
+//! new report.
+
+use std::collections::HashSet;
+
+use crate::models::{BookTokenStream, SurfaceTable};
+
+/// Transmission verbs that open (and link) an isnād, without diacritics.
+pub const ISNAD_MARKERS: &[&str] = &[
+    "حدثنا", "حدثني", "أخبرنا", "أخبرني", "أنبأنا", "أنبأني", "حدثه", "سمعت",
+];
+
+/// Markers closer than this to the previous marker continue the same chain.
+pub const ISNAD_LINK_GAP: usize = 12;
+
+/// Token IDs whose surface form is an isnād marker.
+#[derive(Debug, Clone, Default)]
+pub struct IsnadMarkers {
+    token_ids: HashSet<u32>,
+}
+
+impl IsnadMarkers {
+    /// Collect marker tokens from a token_id -> surface mapping. Surfaces are
+    /// matched without diacritics and with a conjunction prefix (و/ف) allowed.
+    pub fn from_surfaces(token_to_surface: &SurfaceTable) -> Self {
+        let is_marker = isnad_marker_matcher();
+        let token_ids = token_to_surface
+            .iter()
+            .filter(|(_, surface)| is_marker(surface))
+            .map(|(token_id, _)| token_id)
+            .collect();
+        IsnadMarkers { token_ids }
+    }
+
+    /// Build from explicit token IDs.
+    pub fn from_token_ids(token_ids: impl IntoIterator<Item = u32>) -> Self {
+        IsnadMarkers {
+            token_ids: token_ids.into_iter().collect(),
+        }
+    }
+
+    pub fn contains(&self, token_id: u32) -> bool {
+        self.token_ids.contains(&token_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.token_ids.is_empty()
+    }
+}
+
+/// Split a stream into report segments, as global `[start, end)` ranges that
+/// tile the whole stream. Text before the first isnād is its own segment.
+pub fn isnad_segments(stream: &BookTokenStream, markers: &IsnadMarkers) -> Vec<(usize, usize)> {
+    let mut starts = vec![0usize];
+    let mut last_marker: Option<usize> = None;
+
+    let token_ids = stream.pages.iter().flat_map(|p| p.token_ids.iter().copied());
+    for (pos, token_id) in token_ids.enumerate() {
+        if !markers.contains(token_id) {
+            continue;
+        }
+        let opens_report = last_marker.is_none_or(|last| pos - last >= ISNAD_LINK_GAP);
+        if opens_report && pos > 0 {
+            starts.push(pos);
+        }
+        last_marker = Some(pos);
+    }
+
+    let mut segments: Vec<(usize, usize)> = starts
+        .windows(2)
+        .map(|pair| (pair[0], pair[1]))
+        .collect();
+    if stream.total_tokens > 0 {
+        segments.push((*starts.last().unwrap(), stream.total_tokens));
+    }
+    segments
+}
+
+/// Predicate matching isnād marker surfaces, as
+/// [`IsnadMarkers::from_surfaces`] does.
+pub fn isnad_marker_matcher() -> impl Fn(&str) -> bool {
+    let markers: HashSet<String> = ISNAD_MARKERS.iter().map(|m| strip_diacritics(m)).collect();
+    move |surface| is_marker(surface, &markers)
+}
+
+fn is_marker(surface: &str, markers: &HashSet<String>) -> bool {
+    let bare = strip_diacritics(surface);
+    if markers.contains(&bare) {
+        return true;
+    }
+    bare.strip_prefix(['و', 'ف'])
+        .is_some_and(|rest| markers.contains(rest))
+}
+
+/// Drop harakat, shadda, sukun, dagger alif and tatweel.
+pub(crate) fn strip_diacritics(text: &str) -> String {
+    text.chars()
+        .filter(|c| !matches!(c, '\u{064B}'..='\u{0652}' | '\u{0670}' | '\u{0640}'))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(token_ids: Vec<u32>) -> BookTokenStream {
+        BookTokenStream::single_page(1, token_ids)
+    }
+
+    #[test]
+    fn test_marker_surfaces() {
+        let surfaces: SurfaceTable = ["", "حَدَّثَنَا", "وحدثنا", "قال", "فأخبرني", "حديث"]
+            .into_iter()
+            .collect();
+        let markers = IsnadMarkers::from_surfaces(&surfaces);
+        assert!(markers.contains(1));
+        assert!(markers.contains(2));
+        assert!(!markers.contains(3));
+        assert!(markers.contains(4));
+        assert!(!markers.contains(5));
+    }
+
+    #[test]
+    fn test_chain_links_stay_in_one_segment() {
+        let markers = IsnadMarkers::from_token_ids([1]);
+        // Preface, then two reports; each chain has a second marker 4 tokens in
+        let mut tokens = vec![9; 10];
+        for _ in 0..2 {
+            tokens.extend([1, 9, 9, 9, 1]);
+            tokens.extend(vec![9; 30]);
+        }
+        let segments = isnad_segments(&stream(tokens), &markers);
+        assert_eq!(segments, vec![(0, 10), (10, 45), (45, 80)]);
+    }
+
+    #[test]
+    fn test_no_markers_is_one_segment() {
+        let segments = isnad_segments(&stream(vec![9; 50]), &IsnadMarkers::default());
+        assert_eq!(segments, vec![(0, 50)]);
+        assert!(isnad_segments(&stream(Vec::new()), &IsnadMarkers::default()).is_empty());
+    }
+}
@@ -0,0 +1,150 @@
+//! Edge signatures for deduplication review.
+//!
+//! The same floating passage (a well-known report, a stock definition) often
+//! turns up at many locations across a pair of books, each as its own edge.
+//! A signature is a short normalized preview of an edge's source span: its
+//! first [`SIGNATURE_LEMMAS`] content words, with stop-lemmas dropped and
+//! orthographic variants folded, so that such repeats read identically and
+//! can be sorted or clustered together.
+//!
+//! The stop-lemmas come from one fixed list per run: `params.stop_lemmas`
+//! when set, otherwise the corpus's most frequent lemmas (see
+//! [`StopLemmas::for_params`]). A list taken from each source book's own
+//! frequencies would give the same passage a different signature in every
+//! pair.
+
+use std::collections::HashSet;
+
+use crate::models::{BookTokenStream, ComparisonParams, SurfaceLookup};
+use crate::normalize::NormalizationConfig;
+
+/// Content words kept in a signature.
+pub const SIGNATURE_LEMMAS: usize = 12;
+
+/// A lemma making up at least this share of the corpus's tokens is a stop-lemma.
+pub const STOP_LEMMA_SHARE: f32 = 0.005;
+
+/// The most frequent lemmas of a corpus: particles, prepositions, قال and the like.
+#[derive(Debug, Clone, Default)]
+pub struct StopLemmas {
+    lemma_ids: HashSet<u32>,
+}
+
+impl StopLemmas {
+    /// Lemmas at or above [`STOP_LEMMA_SHARE`] of all tokens, given counts
+    /// indexed by lemma ID (as `db::count_corpus_lemmas` returns them).
+    pub fn from_counts(counts: &[u64]) -> Self {
+        let total: u64 = counts.iter().sum();
+        let min_count = (total as f32 * STOP_LEMMA_SHARE).ceil().max(1.0) as u64;
+        StopLemmas {
+            lemma_ids: (0u32..)
+                .zip(counts)
+                .filter(|&(_, &count)| count >= min_count)
+                .map(|(lemma_id, _)| lemma_id)
+                .collect(),
+        }
+    }
+
+    /// `params.stop_lemmas` when set, otherwise the list `corpus` returns.
+    pub fn for_params<E>(
+        params: &ComparisonParams,
+        corpus: impl FnOnce() -> Result<StopLemmas, E>,
+    ) -> Result<Self, E> {
+        match &params.stop_lemmas {
+            Some(lemma_ids) => Ok(Self::from_lemma_ids(lemma_ids.iter().copied())),
+            None => corpus(),
+        }
+    }
+
+    /// Build from explicit lemma IDs.
+    pub fn from_lemma_ids(lemma_ids: impl IntoIterator<Item = u32>) -> Self {
+        StopLemmas {
+            lemma_ids: lemma_ids.into_iter().collect(),
+        }
+    }
+
+    pub fn contains(&self, lemma_id: u32) -> bool {
+        self.lemma_ids.contains(&lemma_id)
+    }
+
+    /// The stop-lemma IDs, in no particular order.
+    pub fn lemma_ids(&self) -> impl Iterator<Item = u32> + '_ {
+        self.lemma_ids.iter().copied()
+    }
+}
+
+/// Signature of the `[start, end)` global token range of a stream.
+pub fn span_signature<S: SurfaceLookup + ?Sized>(
+    stream: &BookTokenStream,
+    (start, end): (usize, usize),
+    token_to_surface: &S,
+    stop_lemmas: &StopLemmas,
+) -> String {
+    let tokens = stream
+        .pages
+        .iter()
+        .flat_map(|p| p.token_ids.iter().zip(&p.lemma_ids))
+        .skip(start)
+        .take(end.saturating_sub(start));
+
+    tokens
+        .filter(|&(_, &lemma_id)| !stop_lemmas.contains(lemma_id))
+        .filter_map(|(&token_id, _)| token_to_surface.surface(token_id))
+        .map(normalize_orthography)
+        .filter(|word| !word.is_empty())
+        .take(SIGNATURE_LEMMAS)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Fold orthographic variants: drop diacritics and tatweel, unify hamzated
+/// and wasla alifs, alif maqsura, ta marbuta, and hamza carriers (every step
+/// of the default [`NormalizationConfig`]).
+pub fn normalize_orthography(text: &str) -> String {
+    NormalizationConfig::default().normalize(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_orthography() {
+        assert_eq!(normalize_orthography("إِسْمَاعِيلُ"), "اسماعيل");
+        assert_eq!(normalize_orthography("الصَّلاةُ"), normalize_orthography("الصلاه"));
+        assert_eq!(normalize_orthography("مُوسَى،"), "موسي");
+    }
+
+    #[test]
+    fn test_span_signature_skips_stop_lemmas() {
+        // Token i has lemma i; tokens 0 and 1 are stop-lemmas
+        let surfaces: Vec<String> = ["في", "قال", "الصَّلاة", "خير", "من", "النوم"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        let token_ids: Vec<u32> = vec![1, 0, 2, 3, 4, 0, 5, 2];
+        let stream = BookTokenStream::single_page(1, token_ids);
+        let stop = StopLemmas::from_lemma_ids([0, 1]);
+
+        assert_eq!(span_signature(&stream, (0, 7), &surfaces, &stop), "الصلاه خير من النوم");
+        assert_eq!(span_signature(&stream, (3, 5), &surfaces, &stop), "خير من");
+    }
+
+    #[test]
+    fn test_stop_lemmas_from_counts() {
+        // Lemma 7 is a quarter of the corpus; every other lemma appears once
+        let mut counts = vec![1u64; 400];
+        counts[7] = 100;
+        let stop = StopLemmas::from_counts(&counts);
+        assert!(stop.contains(7));
+        assert!(!stop.contains(8));
+
+        let params = ComparisonParams {
+            stop_lemmas: Some(HashSet::from([8])),
+            ..Default::default()
+        };
+        let stop = StopLemmas::for_params(&params, || Ok::<_, ()>(StopLemmas::from_counts(&counts))).unwrap();
+        assert!(stop.contains(8));
+        assert!(!stop.contains(7));
+    }
+}
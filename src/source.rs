@@ -0,0 +1,203 @@
+//! Pluggable corpus storage.
+//!
+//! A [`Corpus`](crate::corpus::Corpus) reads books through a [`CorpusSource`].
+//! [`SqliteSource`] reads the corpus.db schema (`token_definitions`,
+//! `page_tokens` and the optional tables); a corpus kept elsewhere (Postgres,
+//! flat files, an OpenITI checkout) can implement the trait instead:
+//!
+//! ```no_run
+//! use kashshaf_reuse::corpus::Corpus;
+//! use kashshaf_reuse::db::{DbError, TokenMappings};
+//! use kashshaf_reuse::models::{BookInfo, BookTokenStream};
+//! use kashshaf_reuse::source::CorpusSource;
+//!
+//! struct FlatFiles;
+//!
+//! impl CorpusSource for FlatFiles {
+//!     fn load_token_mappings(&self) -> Result<TokenMappings, DbError> {
+//!         unimplemented!()
+//!     }
+//!     fn load_book_stream(&self, book_id: u32, token_to_lemma: &[u32], token_to_root: &[u32])
+//!         -> Result<BookTokenStream, DbError> {
+//!         Err(DbError::BookNotFound(book_id))
+//!     }
+//!     fn load_metadata(&self, book_id: u32) -> Result<BookInfo, DbError> {
+//!         Err(DbError::BookNotFound(book_id))
+//!     }
+//!     fn list_books(&self) -> Result<Vec<u32>, DbError> {
+//!         Ok(Vec::new())
+//!     }
+//! }
+//!
+//! let corpus = Corpus::from_source(FlatFiles);
+//! ```
+//!
+//! Errors of other backends are wrapped in [`DbError::Source`].
+
+use rusqlite::Connection;
+use std::path::Path;
+
+use crate::db::{
+    count_corpus_lemmas_with_conn, list_books_with_conn, load_all_token_mappings_with_conn, load_book_info_with_conn,
+    load_book_token_stream_with_conn, load_lemma_alternatives_with_conn, DbError, TokenMappings,
+};
+use crate::models::{BookInfo, BookTokenStream, LemmaAlternatives};
+
+/// Where a corpus's token tables and book streams come from.
+pub trait CorpusSource {
+    /// token_to_lemma, token_to_root and token_to_surface, indexed by token ID.
+    /// Tokens without a root map to 0.
+    fn load_token_mappings(&self) -> Result<TokenMappings, DbError>;
+
+    /// A book's token stream, pages in reading order, with lemma and root IDs
+    /// looked up in the given mappings. A missing book is
+    /// [`DbError::BookNotFound`].
+    fn load_book_stream(
+        &self,
+        book_id: u32,
+        token_to_lemma: &[u32],
+        token_to_root: &[u32],
+    ) -> Result<BookTokenStream, DbError>;
+
+    /// A book's page structure and size.
+    fn load_metadata(&self, book_id: u32) -> Result<BookInfo, DbError>;
+
+    /// IDs of every book in the corpus, ascending.
+    fn list_books(&self) -> Result<Vec<u32>, DbError>;
+
+    /// Alternative lemma analyses by token ID; none by default.
+    fn load_lemma_alternatives(&self) -> Result<LemmaAlternatives, DbError> {
+        Ok(LemmaAlternatives::new())
+    }
+
+    /// Every lemma's occurrences over the whole corpus, indexed by lemma ID.
+    /// By default every book is loaded in turn.
+    fn count_lemmas(&self, token_to_lemma: &[u32]) -> Result<Vec<u64>, DbError> {
+        let max_lemma_id = token_to_lemma.iter().copied().max().unwrap_or(0) as usize;
+        let mut counts = vec![0u64; max_lemma_id + 1];
+        for book_id in self.list_books()? {
+            let stream = self.load_book_stream(book_id, token_to_lemma, &[])?;
+            for lemma_id in stream.flat_lemma_ids() {
+                counts[lemma_id as usize] += 1;
+            }
+        }
+        Ok(counts)
+    }
+}
+
+/// A corpus.db, or an in-memory database with the same schema.
+pub struct SqliteSource {
+    conn: Connection,
+}
+
+impl SqliteSource {
+    pub fn open(db_path: &Path) -> Result<Self, DbError> {
+        Ok(Self::from_connection(Connection::open(db_path)?))
+    }
+
+    pub fn from_connection(conn: Connection) -> Self {
+        Self { conn }
+    }
+}
+
+impl CorpusSource for SqliteSource {
+    fn load_token_mappings(&self) -> Result<TokenMappings, DbError> {
+        load_all_token_mappings_with_conn(&self.conn)
+    }
+
+    fn load_book_stream(
+        &self,
+        book_id: u32,
+        token_to_lemma: &[u32],
+        token_to_root: &[u32],
+    ) -> Result<BookTokenStream, DbError> {
+        load_book_token_stream_with_conn(&self.conn, book_id, token_to_lemma, token_to_root)
+    }
+
+    fn load_metadata(&self, book_id: u32) -> Result<BookInfo, DbError> {
+        load_book_info_with_conn(&self.conn, book_id)
+    }
+
+    fn list_books(&self) -> Result<Vec<u32>, DbError> {
+        list_books_with_conn(&self.conn)
+    }
+
+    fn load_lemma_alternatives(&self) -> Result<LemmaAlternatives, DbError> {
+        load_lemma_alternatives_with_conn(&self.conn)
+    }
+
+    fn count_lemmas(&self, token_to_lemma: &[u32]) -> Result<Vec<u64>, DbError> {
+        count_corpus_lemmas_with_conn(&self.conn, token_to_lemma)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::corpus::Corpus;
+    use crate::models::{ComparisonParams, PageTokens, SurfaceTableBuilder};
+
+    /// Books held as token ID lists, each token its own lemma
+    struct Books(Vec<(u32, Vec<u32>)>);
+
+    impl CorpusSource for Books {
+        fn load_token_mappings(&self) -> Result<TokenMappings, DbError> {
+            let max_id = self.0.iter().flat_map(|(_, tokens)| tokens.iter().copied()).max().unwrap_or(0);
+            let mut surfaces = SurfaceTableBuilder::default();
+            for token_id in 0..=max_id {
+                surfaces.push(token_id, &format!("w{}", token_id));
+            }
+            Ok(((0..=max_id).collect(), vec![0; max_id as usize + 1], surfaces.finish()))
+        }
+
+        fn load_book_stream(
+            &self,
+            book_id: u32,
+            token_to_lemma: &[u32],
+            _token_to_root: &[u32],
+        ) -> Result<BookTokenStream, DbError> {
+            let (_, token_ids) = self
+                .0
+                .iter()
+                .find(|(id, _)| *id == book_id)
+                .ok_or(DbError::BookNotFound(book_id))?;
+            Ok(BookTokenStream {
+                book_id,
+                total_tokens: token_ids.len(),
+                pages: vec![PageTokens {
+                    part_index: 1,
+                    page_id: 1,
+                    lemma_ids: token_ids.iter().map(|&t| token_to_lemma[t as usize]).collect(),
+                    root_ids: vec![0; token_ids.len()],
+                    token_ids: token_ids.clone(),
+                    alt_lemma_ids: Vec::new(),
+                }],
+                corpus_weights: None,
+                quran: None,
+            })
+        }
+
+        fn load_metadata(&self, book_id: u32) -> Result<BookInfo, DbError> {
+            Err(DbError::BookNotFound(book_id))
+        }
+
+        fn list_books(&self) -> Result<Vec<u32>, DbError> {
+            Ok(self.0.iter().map(|(id, _)| *id).collect())
+        }
+    }
+
+    #[test]
+    fn test_corpus_from_source() {
+        let source = Books(vec![(1, (1..300).collect()), (2, (1..300).chain(500..600).collect())]);
+        let counts = source.count_lemmas(&(0..600).collect::<Vec<_>>()).unwrap();
+        assert_eq!((counts[5], counts[550], counts[400]), (2, 1, 0));
+
+        let mut corpus = Corpus::from_source(source);
+        assert_eq!(corpus.source().list_books().unwrap(), vec![1, 2]);
+        let result = corpus.compare_with_text(1, 2, &ComparisonParams::default(), 5).unwrap();
+        assert!(!result.edges.is_empty());
+        assert!(result.run_info.corpus_db.is_empty());
+        assert!(corpus.corpus_weights().unwrap().iter().any(|&w| w > 0.0));
+        assert!(matches!(corpus.compare(1, 3, &ComparisonParams::default()), Err(DbError::BookNotFound(3))));
+    }
+}
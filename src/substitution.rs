@@ -0,0 +1,376 @@
+//! Lemma substitutions inside reuse edges.
+//!
+//! Where a passage was rephrased rather than copied, the alignment pairs a
+//! lemma of book A with a different lemma of book B at the same position.
+//! Copyists rephrase systematically (a common synonym for a rare word, one
+//! particle for another, a regularized verb form), so the same pairs recur
+//! across a comparison. Edges don't keep their alignment path, so each edge's
+//! two spans are aligned afresh to recover the pairs, which are then counted
+//! across the result.
+//!
+//! The same re-alignment gives each edge's full path as an ops string
+//! ([`alignment_ops`]), which the viewer uses to shade individual words.
+
+use rayon::prelude::*;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+
+use crate::abridge::EdgeSpan;
+use crate::compare::{align_window_pair, chrono_lite_timestamp, stream_weights};
+use crate::models::{
+    Alignment, AlignmentOp, BookTokenStream, ComparisonParams, ComparisonResultWithText, EdgeSubstitutions,
+    LemmaSubstitution, SubstitutionCount, SubstitutionReport, SurfaceLookup, SurfacePair, Window,
+};
+use crate::window::window_for_range;
+
+/// Surface form pairs kept per lemma pair in the frequency table.
+pub const MAX_SURFACE_PAIRS: usize = 5;
+
+/// Substitutions in every edge between two books, and their frequency table.
+///
+/// `spans` are the edges of an earlier comparison of `stream_a` with
+/// `stream_b`. Each is re-aligned with the scoring of `params` but without
+/// its length and similarity thresholds, which the edge already passed.
+pub fn substitution_report<S: SurfaceLookup + Sync + ?Sized>(
+    spans: &[EdgeSpan],
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+    token_to_surface: &S,
+    params: &ComparisonParams,
+) -> SubstitutionReport {
+    let (weights_a, weights_b) = stream_weights(stream_a, stream_b, params);
+    let relaxed = relaxed_params(params);
+    let (ids_a, ids_b) = (stream_a.flat_token_ids(), stream_b.flat_token_ids());
+    let surface = |ids: &[u32], position: usize| {
+        ids.get(position)
+            .and_then(|&tid| token_to_surface.surface(tid))
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    let aligned: Vec<Result<EdgeSubstitutions, u64>> = spans
+        .par_iter()
+        .map(|span| {
+            let (window_a, window_b, alignment) =
+                realign_edge(span, stream_a, stream_b, &weights_a, &weights_b, &relaxed)?;
+            let mut edge = edge_substitutions(span.id, &window_a, &window_b, alignment.as_ref());
+            for substitution in &mut edge.substitutions {
+                substitution.source_surface = surface(&ids_a, substitution.source_position);
+                substitution.target_surface = surface(&ids_b, substitution.target_position);
+            }
+            Ok(edge)
+        })
+        .collect();
+
+    let mut edges = Vec::with_capacity(aligned.len());
+    let mut skipped_edges = Vec::new();
+    for edge in aligned {
+        match edge {
+            Ok(edge) => edges.push(edge),
+            Err(id) => skipped_edges.push(id),
+        }
+    }
+
+    SubstitutionReport {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        generated_at: chrono_lite_timestamp(),
+        book_a: stream_a.book_id,
+        book_b: stream_b.book_id,
+        edge_count: spans.len(),
+        aligned_positions: edges.iter().map(|e| e.aligned_positions).sum(),
+        substitution_count: edges.iter().map(|e| e.substitutions.len()).sum(),
+        skipped_edges,
+        table: substitution_table(&edges),
+        edges,
+    }
+}
+
+/// `params` without the length and similarity thresholds an edge already passed.
+fn relaxed_params(params: &ComparisonParams) -> ComparisonParams {
+    ComparisonParams {
+        min_length: 0,
+        min_matched_tokens: None,
+        min_similarity: 0.0,
+        ..params.clone()
+    }
+}
+
+/// Align an edge's two spans afresh, or return its ID if alignment hit a limit.
+fn realign_edge(
+    span: &EdgeSpan,
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+    weights_a: &[f32],
+    weights_b: &[f32],
+    relaxed: &ComparisonParams,
+) -> Result<(Window, Window, Option<Alignment>), u64> {
+    let window_a = window_for_range(stream_a, span.source);
+    let window_b = window_for_range(stream_b, span.target);
+    let alignment = align_window_pair(&window_a, &window_b, weights_a, weights_b, relaxed).map_err(|_| span.id)?;
+    Ok((window_a, window_b, alignment))
+}
+
+/// All analyses (primary and alternative lemmas) of a window's token.
+fn analyses(window: &Window, i: usize) -> Vec<u32> {
+    std::iter::once(window.lemma_ids[i])
+        .chain(window.alt_lemma_ids.get(i).into_iter().flatten().copied())
+        .collect()
+}
+
+/// The diagonal positions of an alignment whose lemmas differ, surfaces
+/// left empty. A position counts as matched when any analysis of one token
+/// (primary or alternative lemma) is an analysis of the other.
+fn edge_substitutions(
+    edge_id: u64,
+    window_a: &Window,
+    window_b: &Window,
+    alignment: Option<&Alignment>,
+) -> EdgeSubstitutions {
+    let pairs = alignment.map(|a| &a.aligned_pairs[..]).unwrap_or_default();
+    let substitutions = pairs
+        .iter()
+        .filter(|&&(i, j)| {
+            let lemmas_b = analyses(window_b, j);
+            !analyses(window_a, i).iter().any(|lemma| lemmas_b.contains(lemma))
+        })
+        .map(|&(i, j)| LemmaSubstitution {
+            source_position: window_a.global_start + i,
+            target_position: window_b.global_start + j,
+            source_lemma: window_a.lemma_ids[i],
+            target_lemma: window_b.lemma_ids[j],
+            source_surface: String::new(),
+            target_surface: String::new(),
+            same_root: window_a.root_ids[i] != 0 && window_a.root_ids[i] == window_b.root_ids[j],
+        })
+        .collect();
+
+    EdgeSubstitutions {
+        edge_id,
+        aligned_positions: pairs.len(),
+        substitutions,
+    }
+}
+
+/// Count substitutions by lemma pair, most frequent first (ties by lemma IDs).
+pub fn substitution_table(edges: &[EdgeSubstitutions]) -> Vec<SubstitutionCount> {
+    #[derive(Default)]
+    struct Tally {
+        same_root: bool,
+        count: usize,
+        edge_count: usize,
+        surfaces: BTreeMap<(String, String), usize>,
+    }
+
+    let mut tallies: BTreeMap<(u32, u32), Tally> = BTreeMap::new();
+    for edge in edges {
+        let mut seen = HashSet::new();
+        for s in &edge.substitutions {
+            let key = (s.source_lemma, s.target_lemma);
+            let tally = tallies.entry(key).or_default();
+            tally.same_root |= s.same_root;
+            tally.count += 1;
+            if seen.insert(key) {
+                tally.edge_count += 1;
+            }
+            *tally
+                .surfaces
+                .entry((s.source_surface.clone(), s.target_surface.clone()))
+                .or_default() += 1;
+        }
+    }
+
+    let mut table: Vec<SubstitutionCount> = tallies
+        .into_iter()
+        .map(|((source_lemma, target_lemma), tally)| {
+            let mut surfaces: Vec<SurfacePair> = tally
+                .surfaces
+                .into_iter()
+                .map(|((source, target), count)| SurfacePair { source, target, count })
+                .collect();
+            surfaces.sort_by_key(|s| Reverse(s.count));
+            surfaces.truncate(MAX_SURFACE_PAIRS);
+            SubstitutionCount {
+                source_lemma,
+                target_lemma,
+                same_root: tally.same_root,
+                count: tally.count,
+                edge_count: tally.edge_count,
+                surfaces,
+            }
+        })
+        .collect();
+    table.sort_by_key(|row| Reverse(row.count));
+    table
+}
+
+// ============================================================================
+// Alignment ops
+// ============================================================================
+
+/// Ops string of every edge between two books, in the order of `spans`, or
+/// None for an edge whose re-alignment hit a limit. See [`AlignmentOp`].
+pub fn alignment_ops(
+    spans: &[EdgeSpan],
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+    params: &ComparisonParams,
+) -> Vec<Option<String>> {
+    let (weights_a, weights_b) = stream_weights(stream_a, stream_b, params);
+    let relaxed = relaxed_params(params);
+    spans
+        .par_iter()
+        .map(|span| {
+            let (window_a, window_b, alignment) =
+                realign_edge(span, stream_a, stream_b, &weights_a, &weights_b, &relaxed).ok()?;
+            Some(edge_ops(&window_a, &window_b, alignment.as_ref()).into_iter().map(AlignmentOp::code).collect())
+        })
+        .collect()
+}
+
+/// Set `alignment.ops` on every edge of a result compared from `stream_a`
+/// and `stream_b`. Returns the number of edges left without ops.
+pub fn attach_alignment_ops(
+    result: &mut ComparisonResultWithText,
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+) -> usize {
+    let spans: Vec<EdgeSpan> = result.edges.iter().map(EdgeSpan::from).collect();
+    let ops = alignment_ops(&spans, stream_a, stream_b, &result.parameters);
+    for (edge, ops) in result.edges.iter_mut().zip(ops) {
+        edge.alignment.ops = ops;
+    }
+    result.edges.iter().filter(|e| e.alignment.ops.is_none()).count()
+}
+
+/// The path through both windows from start to end. Tokens the local
+/// alignment leaves out at either end are gaps, so the ops cover the spans.
+fn edge_ops(window_a: &Window, window_b: &Window, alignment: Option<&Alignment>) -> Vec<AlignmentOp> {
+    let pairs = alignment.map(|a| &a.aligned_pairs[..]).unwrap_or_default();
+    let mut ops = Vec::with_capacity(window_a.lemma_ids.len().max(window_b.lemma_ids.len()));
+    let gaps = |ops: &mut Vec<AlignmentOp>, (from_a, from_b): (usize, usize), (to_a, to_b): (usize, usize)| {
+        ops.extend(std::iter::repeat_n(AlignmentOp::SourceOnly, to_a - from_a));
+        ops.extend(std::iter::repeat_n(AlignmentOp::TargetOnly, to_b - from_b));
+    };
+
+    let mut next = (0, 0);
+    for &(i, j) in pairs {
+        gaps(&mut ops, next, (i, j));
+        let lemmas_b = analyses(window_b, j);
+        ops.push(if analyses(window_a, i).iter().any(|lemma| lemmas_b.contains(lemma)) {
+            AlignmentOp::Match
+        } else if window_a.root_ids[i] != 0 && window_a.root_ids[i] == window_b.root_ids[j] {
+            AlignmentOp::RootMatch
+        } else {
+            AlignmentOp::Substitution
+        });
+        next = (i + 1, j + 1);
+    }
+    gaps(&mut ops, next, (window_a.lemma_ids.len(), window_b.lemma_ids.len()));
+    ops
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn stream(book_id: u32, lemmas: Vec<u32>, roots: Vec<u32>) -> BookTokenStream {
+        let mut stream = BookTokenStream::single_page(book_id, lemmas);
+        let page = &mut stream.pages[0];
+        page.token_ids = page.lemma_ids.iter().map(|l| l + book_id * 1000).collect();
+        page.root_ids = roots;
+        stream
+    }
+
+    #[test]
+    fn test_substitutions_in_rephrased_passage() {
+        // Book B rephrases positions 5 (same root) and 12 of book A's passage
+        let lemmas_a: Vec<u32> = (1..=20).collect();
+        let mut lemmas_b = lemmas_a.clone();
+        lemmas_b[5] = 106;
+        lemmas_b[12] = 113;
+        let mut roots_b = vec![0; 20];
+        roots_b[5] = 50;
+        let mut roots_a = vec![0; 20];
+        roots_a[5] = 50;
+        let stream_a = stream(1, lemmas_a, roots_a);
+        let stream_b = stream(2, lemmas_b, roots_b);
+        let surfaces: HashMap<u32, String> = (0..3000).map(|id| (id, format!("s{}", id))).collect();
+        let span = EdgeSpan {
+            id: 7,
+            source: (0, 20),
+            target: (0, 20),
+        };
+        let params = ComparisonParams {
+            use_weights: false,
+            ..Default::default()
+        };
+
+        let report = substitution_report(&[span, EdgeSpan { id: 8, ..span }], &stream_a, &stream_b, &surfaces, &params);
+        assert_eq!(report.edge_count, 2);
+        assert!(report.skipped_edges.is_empty());
+        assert_eq!(report.edges[0].aligned_positions, 20);
+        let found: Vec<_> = report.edges[0]
+            .substitutions
+            .iter()
+            .map(|s| (s.source_position, s.source_lemma, s.target_lemma, s.same_root))
+            .collect();
+        assert_eq!(found, vec![(5, 6, 106, true), (12, 13, 113, false)]);
+        assert_eq!(report.edges[0].substitutions[0].source_surface, "s1006");
+        assert_eq!(report.edges[0].substitutions[0].target_surface, "s2106");
+
+        assert_eq!(report.substitution_count, 4);
+        assert_eq!(report.table.len(), 2);
+        assert_eq!((report.table[0].count, report.table[0].edge_count), (2, 2));
+        assert_eq!(
+            report.table[0].surfaces,
+            vec![SurfacePair {
+                source: "s1006".to_string(),
+                target: "s2106".to_string(),
+                count: 2
+            }]
+        );
+    }
+
+    #[test]
+    fn test_alignment_ops_cover_both_spans() {
+        // Book B rephrases positions 5 (same root) and 12, and adds a word after 16
+        let lemmas_a: Vec<u32> = (1..=20).collect();
+        let mut lemmas_b = lemmas_a.clone();
+        lemmas_b[5] = 106;
+        lemmas_b[12] = 113;
+        lemmas_b.insert(17, 500);
+        let mut roots_a = vec![0; 20];
+        roots_a[5] = 50;
+        let mut roots_b = vec![0; 21];
+        roots_b[5] = 50;
+        let stream_a = stream(1, lemmas_a, roots_a);
+        let stream_b = stream(2, lemmas_b, roots_b);
+        let params = ComparisonParams {
+            use_weights: false,
+            ..Default::default()
+        };
+        let spans = [
+            EdgeSpan {
+                id: 1,
+                source: (0, 20),
+                target: (0, 21),
+            },
+            // Target starts a word early: a gap before the alignment
+            EdgeSpan {
+                id: 2,
+                source: (2, 10),
+                target: (1, 10),
+            },
+        ];
+
+        let ops = alignment_ops(&spans, &stream_a, &stream_b, &params);
+        assert_eq!(ops[0].as_deref(), Some("MMMMMRMMMMMMSMMMMIMMM"));
+        assert_eq!(ops[1].as_deref(), Some("IMMMRMMMM"));
+        for (span, ops) in spans.iter().zip(&ops) {
+            let ops: Vec<AlignmentOp> = ops.as_ref().unwrap().chars().filter_map(AlignmentOp::from_code).collect();
+            assert_eq!(ops.iter().filter(|op| op.in_source()).count(), span.source.1 - span.source.0);
+            assert_eq!(ops.iter().filter(|op| op.in_target()).count(), span.target.1 - span.target.0);
+        }
+    }
+}
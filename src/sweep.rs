@@ -0,0 +1,335 @@
+//! Parameter sweeps over one book pair.
+//!
+//! `kashshaf-reuse sweep --config sweep.toml` compares one pair once for
+//! every combination of the values in the `[grid]` table, on top of the
+//! shared `[params]`, and scores each run against a set of reference edges
+//! when `gold` names one:
+//!
+//! ```toml
+//! corpus_db = "corpus.db"
+//! book_a = 230
+//! book_b = 553
+//! gold = "gold/230_553.json"
+//! min_overlap = 0.5
+//!
+//! [params]
+//! weight_source = "Corpus"
+//!
+//! [grid]
+//! window_size = [200, 300, 400]
+//! ngram_size = [3, 4]
+//! min_core_similarity = [0.8, 0.85, 0.9]
+//! ```
+//!
+//! The gold file is a result JSON (with or without text, typically cut down
+//! to the reviewed edges by `filter-validated`) or an edge CSV. A found edge
+//! matches a gold edge when their spans overlap by at least `min_overlap` of
+//! the shorter span, in both books. Precision is the share of found edges
+//! matching some gold edge, recall the share of gold edges found.
+//!
+//! The books stay cached between runs, so a sweep costs about as much as its
+//! alignments.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::abridge::EdgeSpan;
+use crate::compare::chrono_lite_timestamp;
+use crate::config::ConfigError;
+use crate::corpus::Corpus;
+use crate::db::DbError;
+use crate::models::{ComparisonParams, GoldScores, SweepReport, SweepRun};
+use crate::output::{read_csv_edges_file, read_json_file, read_json_with_text_file, OutputError};
+use crate::validate::{validate_book_pair, validate_gold_overlap, validate_params, ValidationError};
+
+#[derive(Error, Debug)]
+pub enum SweepError {
+    #[error("{}: {source}", path.display())]
+    Io { path: PathBuf, source: std::io::Error },
+    #[error("{}: {source}", path.display())]
+    Config { path: PathBuf, source: toml::de::Error },
+    #[error(transparent)]
+    Params(#[from] ConfigError),
+    #[error("[grid] `{0}` must be a non-empty list of values")]
+    NotAList(String),
+    #[error(transparent)]
+    Invalid(#[from] ValidationError),
+    #[error(transparent)]
+    Db(#[from] DbError),
+    #[error(transparent)]
+    Output(#[from] OutputError),
+}
+
+// ============================================================================
+// Configuration
+// ============================================================================
+
+/// A sweep configuration file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SweepConfig {
+    pub corpus_db: PathBuf,
+    pub book_a: u32,
+    pub book_b: u32,
+    /// Reference edges to score each run against
+    pub gold: Option<PathBuf>,
+    /// Share of the shorter span, in each book, a found edge must overlap a
+    /// gold edge by to match it
+    #[serde(default = "default_min_overlap")]
+    pub min_overlap: f32,
+    /// `ComparisonParams` fields shared by every run
+    #[serde(default)]
+    pub params: toml::Table,
+    /// Values to try for each `ComparisonParams` field
+    pub grid: toml::Table,
+}
+
+fn default_min_overlap() -> f32 {
+    0.5
+}
+
+impl SweepConfig {
+    /// Parse a configuration from TOML text; relative paths are kept as is.
+    pub fn parse(text: &str, path: &Path) -> Result<Self, SweepError> {
+        toml::from_str(text).map_err(|source| SweepError::Config {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    /// Read a configuration file, resolving its relative paths against the
+    /// file's directory.
+    pub fn from_file(path: &Path) -> Result<Self, SweepError> {
+        let text = std::fs::read_to_string(path).map_err(|source| SweepError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let mut config = Self::parse(&text, path)?;
+        let base = path.parent().unwrap_or(Path::new(""));
+        for path in [Some(&mut config.corpus_db), config.gold.as_mut()].into_iter().flatten() {
+            *path = base.join(&*path);
+        }
+        Ok(config)
+    }
+
+    /// Every combination of the grid values. Keys are taken in alphabetical
+    /// order, the first varying slowest.
+    pub fn grid_points(&self) -> Result<Vec<toml::Table>, SweepError> {
+        let mut points = vec![toml::Table::new()];
+        for (key, values) in &self.grid {
+            let values = match values {
+                toml::Value::Array(values) if !values.is_empty() => values,
+                _ => return Err(SweepError::NotAList(key.clone())),
+            };
+            points = points
+                .into_iter()
+                .flat_map(|point| {
+                    values.iter().map(move |value| {
+                        let mut point = point.clone();
+                        point.insert(key.clone(), value.clone());
+                        point
+                    })
+                })
+                .collect();
+        }
+        Ok(points)
+    }
+
+    /// Parameters of one grid point: the defaults, overridden by `[params]`
+    /// and then by the point's values.
+    pub fn point_params(&self, point: &toml::Table) -> Result<ComparisonParams, SweepError> {
+        let mut table = self.params.clone();
+        table.extend(point.clone());
+        Ok(ComparisonParams::from_toml_table(&table)?)
+    }
+}
+
+// ============================================================================
+// Scoring
+// ============================================================================
+
+/// Read reference edges between `book_a` and `book_b` from a result JSON
+/// (with or without text) or, by its `.csv` extension, an edge CSV. Edges
+/// running from `book_b` to `book_a` are turned around; edges of other books
+/// are left out.
+pub fn read_gold_edges(path: &Path, book_a: u32, book_b: u32) -> Result<Vec<EdgeSpan>, OutputError> {
+    let oriented = |source_book: u32, target_book: u32, span: EdgeSpan| match (source_book, target_book) {
+        (s, t) if (s, t) == (book_a, book_b) => Some(span),
+        (s, t) if (s, t) == (book_b, book_a) => Some(EdgeSpan {
+            source: span.target,
+            target: span.source,
+            ..span
+        }),
+        _ => None,
+    };
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("csv")) {
+        let edges = read_csv_edges_file(path)?;
+        return Ok(edges
+            .iter()
+            .filter_map(|e| oriented(e.source_book_id, e.target_book_id, EdgeSpan::from(e)))
+            .collect());
+    }
+    match read_json_with_text_file(path) {
+        Ok(result) => Ok(result
+            .edges
+            .iter()
+            .filter_map(|e| oriented(e.source.book_id, e.target.book_id, EdgeSpan::from(e)))
+            .collect()),
+        // A result without text has no generated_at and flat edges
+        Err(OutputError::Json(_)) => Ok(read_json_file(path)?
+            .edges
+            .iter()
+            .filter_map(|e| oriented(e.source_book_id, e.target_book_id, EdgeSpan::from(e)))
+            .collect()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Whether two ranges share at least `min_overlap` of the shorter one.
+fn ranges_overlap((a_start, a_end): (usize, usize), (b_start, b_end): (usize, usize), min_overlap: f32) -> bool {
+    let shared = a_end.min(b_end).saturating_sub(a_start.max(b_start));
+    let shorter = (a_end - a_start).min(b_end - b_start);
+    shared > 0 && shared as f32 >= min_overlap * shorter as f32
+}
+
+/// Precision, recall and F1 of `found` edges against `gold` edges.
+pub fn score_edges(found: &[EdgeSpan], gold: &[EdgeSpan], min_overlap: f32) -> GoldScores {
+    let matches = |a: &EdgeSpan, b: &EdgeSpan| {
+        ranges_overlap(a.source, b.source, min_overlap) && ranges_overlap(a.target, b.target, min_overlap)
+    };
+    let matched_edges = found.iter().filter(|f| gold.iter().any(|g| matches(f, g))).count();
+    let found_gold = gold.iter().filter(|g| found.iter().any(|f| matches(f, g))).count();
+    let ratio = |n: usize, total: usize| if total == 0 { 0.0 } else { n as f32 / total as f32 };
+    let precision = ratio(matched_edges, found.len());
+    let recall = ratio(found_gold, gold.len());
+    GoldScores {
+        gold_edges: gold.len(),
+        matched_edges,
+        found_gold,
+        precision,
+        recall,
+        f1: if precision + recall > 0.0 {
+            2.0 * precision * recall / (precision + recall)
+        } else {
+            0.0
+        },
+    }
+}
+
+// ============================================================================
+// Running
+// ============================================================================
+
+/// Compare the configured pair once per grid point, scoring each run
+/// against `gold` if given.
+///
+/// Every point's parameters are checked before the first comparison, so a
+/// bad value late in the grid fails at once.
+pub fn run_sweep(
+    corpus: &mut Corpus,
+    config: &SweepConfig,
+    gold: Option<&[EdgeSpan]>,
+    show_progress: bool,
+) -> Result<SweepReport, SweepError> {
+    validate_book_pair(config.book_a, config.book_b)?;
+    validate_gold_overlap(config.min_overlap)?;
+    let points = config.grid_points()?;
+    let point_params = points
+        .iter()
+        .map(|point| {
+            let params = config.point_params(point)?;
+            validate_params(&params)?;
+            Ok(params)
+        })
+        .collect::<Result<Vec<_>, SweepError>>()?;
+
+    let mut runs = Vec::with_capacity(points.len());
+    for (i, (point, params)) in points.iter().zip(&point_params).enumerate() {
+        let values: BTreeMap<String, serde_json::Value> = point
+            .iter()
+            .map(|(key, value)| (key.clone(), serde_json::to_value(value).unwrap_or_default()))
+            .collect();
+        if show_progress {
+            let label: Vec<String> = values.iter().map(|(key, value)| format!("{}={}", key, value)).collect();
+            eprintln!("[{}/{}] {}", i + 1, points.len(), label.join(" "));
+        }
+        let result = corpus.compare(config.book_a, config.book_b, params)?;
+        let found: Vec<EdgeSpan> = result.edges.iter().map(EdgeSpan::from).collect();
+        runs.push(SweepRun {
+            values,
+            edge_count: result.summary.edge_count,
+            book_a_coverage: result.summary.book_a_coverage,
+            book_b_coverage: result.summary.book_b_coverage,
+            wall_ms: result.run_info.wall_ms,
+            scores: gold.map(|gold| score_edges(&found, gold, config.min_overlap)),
+        });
+    }
+
+    Ok(SweepReport {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        generated_at: chrono_lite_timestamp(),
+        book_a: config.book_a,
+        book_b: config.book_b,
+        parameters: config.point_params(&toml::Table::new())?,
+        gold: config.gold.as_ref().map(|path| path.display().to_string()),
+        min_overlap: config.min_overlap,
+        runs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn span(source: (usize, usize), target: (usize, usize)) -> EdgeSpan {
+        EdgeSpan { id: 0, source, target }
+    }
+
+    #[test]
+    fn test_grid_points() {
+        let text = r#"
+            corpus_db = "corpus.db"
+            book_a = 1
+            book_b = 2
+
+            [params]
+            stride = 20
+
+            [grid]
+            window_size = [50, 100]
+            min_core_similarity = [0.8, 0.9, 1.0]
+        "#;
+        let config = SweepConfig::parse(text, Path::new("sweep.toml")).unwrap();
+        let points = config.grid_points().unwrap();
+        assert_eq!(points.len(), 6);
+        let params = config.point_params(&points[5]).unwrap();
+        assert_eq!((params.stride, params.window_size), (20, 100));
+        assert_eq!(params.min_core_similarity, Some(1.0));
+        assert_eq!(config.min_overlap, 0.5);
+
+        let scalar = SweepConfig::parse(&text.replace("[50, 100]", "50"), Path::new("x")).unwrap();
+        assert!(matches!(scalar.grid_points(), Err(SweepError::NotAList(key)) if key == "window_size"));
+        let typo = SweepConfig::parse(&text.replace("window_size", "windowsize"), Path::new("x")).unwrap();
+        assert!(matches!(typo.point_params(&typo.grid_points().unwrap()[0]), Err(SweepError::Params(_))));
+    }
+
+    #[test]
+    fn test_score_edges() {
+        let gold = [span((0, 100), (500, 600)), span((1000, 1100), (2000, 2100)), span((3000, 3050), (10, 60))];
+        let found = [
+            span((20, 110), (510, 620)),      // Matches the first
+            span((1090, 1200), (2090, 2200)), // Overlaps the second by 10 tokens only
+            span((5000, 5100), (10, 60)),     // Right target, wrong source
+        ];
+        let scores = score_edges(&found, &gold, 0.5);
+        assert_eq!((scores.matched_edges, scores.found_gold, scores.gold_edges), (1, 1, 3));
+        assert!((scores.precision - 1.0 / 3.0).abs() < 1e-6);
+        assert!((scores.f1 - 1.0 / 3.0).abs() < 1e-6);
+
+        let loose = score_edges(&found, &gold, 0.1);
+        assert_eq!((loose.matched_edges, loose.found_gold), (2, 2));
+        assert_eq!(score_edges(&[], &gold, 0.5).f1, 0.0);
+    }
+}
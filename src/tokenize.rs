@@ -0,0 +1,91 @@
+//! Tokenization of raw Arabic text into surface tokens.
+//!
+//! The comparison pipeline works on pre-tokenized streams from corpus.db. To
+//! bring in texts from elsewhere, raw strings go through a [`Tokenizer`]. The
+//! default [`SimpleTokenizer`] splits on whitespace and punctuation; external
+//! analyzers (morphological segmenters, CAMeL Tools wrappers, ...) can be
+//! plugged in by implementing the trait or passing a closure.
+
+/// Splits raw text into surface tokens.
+pub trait Tokenizer: Send + Sync {
+    /// Tokenize `text`, returning surface forms in reading order.
+    fn tokenize(&self, text: &str) -> Vec<String>;
+}
+
+/// Any `Fn(&str) -> Vec<String>` can serve as a tokenizer.
+impl<F> Tokenizer for F
+where
+    F: Fn(&str) -> Vec<String> + Send + Sync,
+{
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        self(text)
+    }
+}
+
+/// Whitespace and punctuation tokenizer.
+///
+/// Punctuation (ASCII and Arabic: ، ؛ ؟ « » etc.) separates tokens and is
+/// dropped. Letters, digits and combining marks (harakat) are kept as-is; no
+/// normalization is applied.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SimpleTokenizer;
+
+impl SimpleTokenizer {
+    fn is_separator(c: char) -> bool {
+        c.is_whitespace()
+            || c.is_ascii_punctuation()
+            || matches!(
+                c,
+                '\u{060C}' // ، Arabic comma
+                    | '\u{061B}' // ؛ Arabic semicolon
+                    | '\u{061F}' // ؟ Arabic question mark
+                    | '\u{066A}' // ٪ Arabic percent sign
+                    | '\u{066B}' // ٫ Arabic decimal separator
+                    | '\u{066C}' // ٬ Arabic thousands separator
+                    | '\u{066D}' // ٭ Arabic five-pointed star
+                    | '\u{06D4}' // ۔ Arabic full stop
+                    | '\u{00AB}' // «
+                    | '\u{00BB}' // »
+                    | '\u{2013}' // –
+                    | '\u{2014}' // —
+                    | '\u{2026}' // …
+                    | '\u{FD3E}' // ﴾
+                    | '\u{FD3F}' // ﴿
+            )
+    }
+}
+
+impl Tokenizer for SimpleTokenizer {
+    fn tokenize(&self, text: &str) -> Vec<String> {
+        text.split(Self::is_separator)
+            .filter(|t| !t.is_empty())
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_simple_tokenizer_arabic_punctuation() {
+        let tokens = SimpleTokenizer.tokenize("قال: حدثنا مالك، عن نافع؛ «عن ابن عمر»؟");
+        assert_eq!(tokens, vec!["قال", "حدثنا", "مالك", "عن", "نافع", "عن", "ابن", "عمر"]);
+    }
+
+    #[test]
+    fn test_simple_tokenizer_keeps_diacritics() {
+        let tokens = SimpleTokenizer.tokenize("  كِتَابٌ\n\tجَدِيدٌ ");
+        assert_eq!(tokens, vec!["كِتَابٌ", "جَدِيدٌ"]);
+    }
+
+    #[test]
+    fn test_closure_tokenizer() {
+        let by_dash = |text: &str| text.split('-').map(str::to_string).collect::<Vec<_>>();
+        assert_eq!(by_dash.tokenize("a-b"), vec!["a", "b"]);
+
+        let boxed: Box<dyn Tokenizer> = Box::new(SimpleTokenizer);
+        assert_eq!(boxed.tokenize("a b").len(), 2);
+    }
+}
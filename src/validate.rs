@@ -0,0 +1,363 @@
+//! Up-front validation of comparison arguments.
+//!
+//! Bad combinations (a book compared with itself, a stride of zero, an n-gram
+//! longer than a window) otherwise surface as confusing results or hangs deep
+//! in the pipeline. These checks run before any data is loaded and explain
+//! what to change.
+
+use std::path::{Path, PathBuf};
+use thiserror::Error;
+
+use crate::analysis::ClusterOptions;
+use crate::models::{ComparisonParams, CoverageBins, PageRange, Shard};
+
+/// Upper bound on context tokens per side; larger values bloat every edge in
+/// the output without helping a reader place the match.
+pub const MAX_CONTEXT_TOKENS: usize = 1000;
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ValidationError {
+    #[error("book {0} cannot be compared with itself; pass two different book IDs")]
+    SameBook(u32),
+    #[error("book {0} is listed more than once")]
+    DuplicateBook(u32),
+    #[error("window size must be at least 1")]
+    ZeroWindowSize,
+    #[error("stride must be at least 1")]
+    ZeroStride,
+    #[error(
+        "stride ({stride}) is larger than window size ({window_size}); \
+         tokens between windows would never be compared"
+    )]
+    StrideExceedsWindow { stride: usize, window_size: usize },
+    #[error("n-gram size must be between 1 and the window size ({window_size}), got {ngram_size}")]
+    InvalidNgramSize { ngram_size: usize, window_size: usize },
+    #[error(
+        "min length ({min_length}) is larger than window size ({window_size}); \
+         no alignment could pass"
+    )]
+    MinLengthExceedsWindow { min_length: usize, window_size: usize },
+    #[error(
+        "coarse window size ({coarse_window_size}) must be larger than the window size ({window_size})"
+    )]
+    CoarseWindowTooSmall { coarse_window_size: usize, window_size: usize },
+    #[error("x-drop must be at least 1")]
+    ZeroXdrop,
+    #[error("max candidates per window must be at least 1")]
+    ZeroCandidateCap,
+    #[error("max shingle document frequency must be at least 1")]
+    ZeroShingleDf,
+    #[error("coverage bins must hold at least 1 token")]
+    ZeroCoverageBin,
+    #[error("max shingle document frequency ratio must be above 0 and at most 1, got {0}")]
+    InvalidShingleDfRatio(f32),
+    #[error("max Quran overlap must be between 0 and 1, got {0}")]
+    InvalidQuranOverlap(f32),
+    #[error("max Quran overlap needs the Quran's book ID (--quran-book)")]
+    QuranOverlapWithoutBook,
+    #[error("coarse stride must be between 1 and the coarse window size ({coarse_window_size}), got {stride}")]
+    InvalidCoarseStride { stride: usize, coarse_window_size: usize },
+    #[error("source range {start}:{end} is empty; the end must be after the start")]
+    EmptySourceRange { start: usize, end: usize },
+    #[error("page range {}:{}-{}:{} ends before it starts", .0.start.0, .0.start.1, .0.end.0, .0.end.1)]
+    EmptyPageRange(PageRange),
+    #[error("page ranges overlap; the two sections must be disjoint")]
+    OverlappingPageRanges,
+    #[error("context tokens ({0}) exceeds the maximum of {MAX_CONTEXT_TOKENS}")]
+    ContextTooLarge(usize),
+    #[error("output directory {} does not exist", .0.display())]
+    MissingOutputDir(PathBuf),
+    #[error("shard {index}/{count} is out of range; use 1/N through N/N")]
+    InvalidShard { index: u32, count: u32 },
+    #[error("a cluster needs at least 2 witnesses, got {0}")]
+    TooFewWitnesses(usize),
+    #[error("cluster overlap must be above 0 and at most 1, got {0}")]
+    InvalidClusterOverlap(f32),
+    #[error("gold overlap must be above 0 and at most 1, got {0}")]
+    InvalidGoldOverlap(f32),
+}
+
+/// Check windowing and filtering parameters for consistency.
+pub fn validate_params(params: &ComparisonParams) -> Result<(), ValidationError> {
+    let window_size = params.window_size;
+    if window_size == 0 {
+        return Err(ValidationError::ZeroWindowSize);
+    }
+    if params.stride == 0 {
+        return Err(ValidationError::ZeroStride);
+    }
+    if params.stride > window_size {
+        return Err(ValidationError::StrideExceedsWindow {
+            stride: params.stride,
+            window_size,
+        });
+    }
+    if !params.brute_force && (params.ngram_size == 0 || params.ngram_size > window_size) {
+        return Err(ValidationError::InvalidNgramSize {
+            ngram_size: params.ngram_size,
+            window_size,
+        });
+    }
+    if params.min_length > window_size {
+        return Err(ValidationError::MinLengthExceedsWindow {
+            min_length: params.min_length,
+            window_size,
+        });
+    }
+    if let Some((start, end)) = params.source_range {
+        if start >= end {
+            return Err(ValidationError::EmptySourceRange { start, end });
+        }
+    }
+    if params.xdrop == Some(0) {
+        return Err(ValidationError::ZeroXdrop);
+    }
+    if params.max_candidates_per_window == Some(0) {
+        return Err(ValidationError::ZeroCandidateCap);
+    }
+    if params.max_shingle_df == Some(0) {
+        return Err(ValidationError::ZeroShingleDf);
+    }
+    if params.coverage_bins == Some(CoverageBins::Tokens(0)) {
+        return Err(ValidationError::ZeroCoverageBin);
+    }
+    if let Some(ratio) = params.max_shingle_df_ratio {
+        if !(ratio > 0.0 && ratio <= 1.0) {
+            return Err(ValidationError::InvalidShingleDfRatio(ratio));
+        }
+    }
+    if let Some(max) = params.max_quran_overlap {
+        if !(0.0..=1.0).contains(&max) {
+            return Err(ValidationError::InvalidQuranOverlap(max));
+        }
+        if params.quran_book_id.is_none() {
+            return Err(ValidationError::QuranOverlapWithoutBook);
+        }
+    }
+    if let Some(max_context_tokens) = params.max_context_tokens {
+        validate_context_tokens(max_context_tokens)?;
+    }
+    if let Some(coarse_window_size) = params.coarse_window_size {
+        if coarse_window_size <= window_size {
+            return Err(ValidationError::CoarseWindowTooSmall {
+                coarse_window_size,
+                window_size,
+            });
+        }
+        if let Some(stride) = params.coarse_stride {
+            if stride == 0 || stride > coarse_window_size {
+                return Err(ValidationError::InvalidCoarseStride {
+                    stride,
+                    coarse_window_size,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Check that a shard is one of `1/N` through `N/N`.
+pub fn validate_shard(shard: Shard) -> Result<(), ValidationError> {
+    if shard.count == 0 || shard.index == 0 || shard.index > shard.count {
+        return Err(ValidationError::InvalidShard {
+            index: shard.index,
+            count: shard.count,
+        });
+    }
+    Ok(())
+}
+
+/// Reject comparing a book with itself.
+pub fn validate_book_pair(book_a: u32, book_b: u32) -> Result<(), ValidationError> {
+    if book_a == book_b {
+        return Err(ValidationError::SameBook(book_a));
+    }
+    Ok(())
+}
+
+/// Reject book lists that would produce self-pairs.
+pub fn validate_book_list(book_ids: &[u32]) -> Result<(), ValidationError> {
+    for (i, id) in book_ids.iter().enumerate() {
+        if book_ids[..i].contains(id) {
+            return Err(ValidationError::DuplicateBook(*id));
+        }
+    }
+    Ok(())
+}
+
+/// Check that two page ranges of one book are well-formed and disjoint.
+pub fn validate_page_ranges(range_a: PageRange, range_b: PageRange) -> Result<(), ValidationError> {
+    for range in [range_a, range_b] {
+        if range.start > range.end {
+            return Err(ValidationError::EmptyPageRange(range));
+        }
+    }
+    if range_a.overlaps(&range_b) {
+        return Err(ValidationError::OverlappingPageRanges);
+    }
+    Ok(())
+}
+
+pub fn validate_context_tokens(context_tokens: usize) -> Result<(), ValidationError> {
+    if context_tokens > MAX_CONTEXT_TOKENS {
+        return Err(ValidationError::ContextTooLarge(context_tokens));
+    }
+    Ok(())
+}
+
+/// Check the thresholds of passage clustering.
+pub fn validate_cluster_options(options: &ClusterOptions) -> Result<(), ValidationError> {
+    if options.min_witnesses < 2 {
+        return Err(ValidationError::TooFewWitnesses(options.min_witnesses));
+    }
+    if !(options.min_overlap > 0.0 && options.min_overlap <= 1.0) {
+        return Err(ValidationError::InvalidClusterOverlap(options.min_overlap));
+    }
+    Ok(())
+}
+
+/// Check the share of a gold edge's spans a found edge must overlap.
+pub fn validate_gold_overlap(min_overlap: f32) -> Result<(), ValidationError> {
+    if !(min_overlap > 0.0 && min_overlap <= 1.0) {
+        return Err(ValidationError::InvalidGoldOverlap(min_overlap));
+    }
+    Ok(())
+}
+
+/// Check that the directory an output file will be written into exists.
+pub fn validate_output_path(path: &Path) -> Result<(), ValidationError> {
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() && !dir.is_dir() => {
+            Err(ValidationError::MissingOutputDir(dir.to_path_buf()))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_params_valid() {
+        assert_eq!(validate_params(&ComparisonParams::default()), Ok(()));
+    }
+
+    #[test]
+    fn test_window_stride_consistency() {
+        let params = ComparisonParams {
+            stride: 0,
+            ..Default::default()
+        };
+        assert_eq!(validate_params(&params), Err(ValidationError::ZeroStride));
+
+        let params = ComparisonParams {
+            window_size: 50,
+            stride: 60,
+            ..Default::default()
+        };
+        assert!(matches!(
+            validate_params(&params),
+            Err(ValidationError::StrideExceedsWindow { .. })
+        ));
+
+        let params = ComparisonParams {
+            window_size: 8,
+            stride: 4,
+            ..Default::default()
+        };
+        assert!(matches!(
+            validate_params(&params),
+            Err(ValidationError::MinLengthExceedsWindow { .. })
+        ));
+
+        let params = ComparisonParams {
+            source_range: Some((500, 500)),
+            ..Default::default()
+        };
+        assert_eq!(
+            validate_params(&params),
+            Err(ValidationError::EmptySourceRange { start: 500, end: 500 })
+        );
+
+        let params = ComparisonParams {
+            coarse_window_size: Some(200),
+            ..Default::default()
+        };
+        assert!(matches!(
+            validate_params(&params),
+            Err(ValidationError::CoarseWindowTooSmall { .. })
+        ));
+
+        let params = ComparisonParams {
+            coarse_window_size: Some(1000),
+            coarse_stride: Some(1200),
+            ..Default::default()
+        };
+        assert!(matches!(
+            validate_params(&params),
+            Err(ValidationError::InvalidCoarseStride { .. })
+        ));
+
+        let params = ComparisonParams {
+            xdrop: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(validate_params(&params), Err(ValidationError::ZeroXdrop));
+
+        let params = ComparisonParams {
+            max_candidates_per_window: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(validate_params(&params), Err(ValidationError::ZeroCandidateCap));
+
+        let params = ComparisonParams {
+            coverage_bins: Some(CoverageBins::Tokens(0)),
+            ..Default::default()
+        };
+        assert_eq!(validate_params(&params), Err(ValidationError::ZeroCoverageBin));
+
+        let params = ComparisonParams {
+            max_shingle_df_ratio: Some(1.5),
+            ..Default::default()
+        };
+        assert_eq!(validate_params(&params), Err(ValidationError::InvalidShingleDfRatio(1.5)));
+
+        let params = ComparisonParams {
+            max_quran_overlap: Some(0.5),
+            ..Default::default()
+        };
+        assert_eq!(validate_params(&params), Err(ValidationError::QuranOverlapWithoutBook));
+
+        let params = ComparisonParams {
+            max_context_tokens: Some(MAX_CONTEXT_TOKENS + 1),
+            ..Default::default()
+        };
+        assert_eq!(validate_params(&params), Err(ValidationError::ContextTooLarge(1001)));
+    }
+
+    #[test]
+    fn test_books_and_paths() {
+        assert_eq!(validate_book_pair(230, 230), Err(ValidationError::SameBook(230)));
+        assert_eq!(validate_book_pair(230, 553), Ok(()));
+        assert_eq!(validate_book_list(&[1, 2, 1]), Err(ValidationError::DuplicateBook(1)));
+        assert_eq!(validate_context_tokens(MAX_CONTEXT_TOKENS + 1), Err(ValidationError::ContextTooLarge(1001)));
+
+        let range = |start, end| PageRange { start: (1, start), end: (1, end) };
+        assert_eq!(validate_page_ranges(range(1, 10), range(11, 20)), Ok(()));
+        assert_eq!(
+            validate_page_ranges(range(1, 10), range(10, 20)),
+            Err(ValidationError::OverlappingPageRanges)
+        );
+        assert!(matches!(
+            validate_page_ranges(range(5, 1), range(11, 20)),
+            Err(ValidationError::EmptyPageRange(_))
+        ));
+
+        assert_eq!(validate_output_path(Path::new("out.json")), Ok(()));
+        assert!(matches!(
+            validate_output_path(Path::new("/nonexistent-kashshaf-dir/out.json")),
+            Err(ValidationError::MissingOutputDir(_))
+        ));
+    }
+}
@@ -0,0 +1,154 @@
+//! Heuristic verse detection.
+//!
+//! Poetry is quoted verbatim far more often than prose, so verse edges are
+//! worth tagging and, optionally, holding to a stricter exactness threshold.
+//! Where the corpus encodes hemistich breaks as tokens (the OpenITI `%~%`
+//! marker, or the `*` / `**` separators of printed editions), a span is taken
+//! to be verse when its markers are dense enough to delimit hemistichs: at
+//! most [`MAX_HEMISTICH_TOKENS`] tokens per hemistich on average. A stray
+//! asterisk in a long prose passage does not qualify.
+
+use std::collections::HashSet;
+
+use crate::models::{BookTokenStream, ReuseEdge, SurfaceTable};
+
+/// Surface forms that mark a hemistich break.
+pub const HEMISTICH_MARKERS: &[&str] = &["%~%", "*", "**"];
+
+/// Whether a surface form is a hemistich marker.
+pub fn is_hemistich_marker(surface: &str) -> bool {
+    HEMISTICH_MARKERS.contains(&surface.trim())
+}
+
+/// Longest average hemistich, in tokens, for a span to count as verse.
+pub const MAX_HEMISTICH_TOKENS: usize = 12;
+
+/// Token IDs whose surface form is a hemistich marker.
+#[derive(Debug, Clone, Default)]
+pub struct VerseMarkers {
+    token_ids: HashSet<u32>,
+}
+
+impl VerseMarkers {
+    /// Collect marker tokens from a token_id -> surface mapping.
+    pub fn from_surfaces(token_to_surface: &SurfaceTable) -> Self {
+        let token_ids = token_to_surface
+            .iter()
+            .filter(|(_, surface)| is_hemistich_marker(surface))
+            .map(|(token_id, _)| token_id)
+            .collect();
+        VerseMarkers { token_ids }
+    }
+
+    /// Build from explicit token IDs.
+    pub fn from_token_ids(token_ids: impl IntoIterator<Item = u32>) -> Self {
+        VerseMarkers {
+            token_ids: token_ids.into_iter().collect(),
+        }
+    }
+
+    pub fn contains(&self, token_id: u32) -> bool {
+        self.token_ids.contains(&token_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.token_ids.is_empty()
+    }
+
+    /// Running marker counts over a stream: `counts[i]` is the number of
+    /// markers among the first `i` tokens.
+    fn prefix_counts(&self, stream: &BookTokenStream) -> Vec<usize> {
+        let mut counts = Vec::with_capacity(stream.total_tokens + 1);
+        counts.push(0);
+        let mut seen = 0;
+        for page in &stream.pages {
+            for &token_id in &page.token_ids {
+                seen += usize::from(self.contains(token_id));
+                counts.push(seen);
+            }
+        }
+        counts
+    }
+}
+
+/// Whether a span of `span_len` tokens holding `markers` hemistich markers
+/// reads as verse.
+pub fn is_verse_span(span_len: usize, markers: usize) -> bool {
+    markers > 0 && span_len <= (markers + 1) * MAX_HEMISTICH_TOKENS
+}
+
+/// Set `is_verse` on every edge whose source or target span reads as verse.
+pub fn tag_verse_edges(
+    edges: &mut [ReuseEdge],
+    stream_a: &BookTokenStream,
+    stream_b: &BookTokenStream,
+    markers: &VerseMarkers,
+) {
+    if markers.is_empty() {
+        return;
+    }
+    let counts_a = markers.prefix_counts(stream_a);
+    let counts_b = markers.prefix_counts(stream_b);
+    let span_is_verse = |counts: &[usize], start: usize, end: usize| {
+        let end = end.min(counts.len() - 1);
+        let start = start.min(end);
+        is_verse_span(end - start, counts[end] - counts[start])
+    };
+
+    for edge in edges {
+        edge.is_verse = span_is_verse(&counts_a, edge.source_global_start, edge.source_global_end)
+            || span_is_verse(&counts_b, edge.target_global_start, edge.target_global_end);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stream(token_ids: Vec<u32>) -> BookTokenStream {
+        BookTokenStream::single_page(1, token_ids)
+    }
+
+    #[test]
+    fn test_marker_surfaces() {
+        let surfaces: SurfaceTable = ["", "%~%", "*", "قال"].into_iter().collect();
+        let markers = VerseMarkers::from_surfaces(&surfaces);
+        assert!(markers.contains(1));
+        assert!(markers.contains(2));
+        assert!(!markers.contains(3));
+    }
+
+    #[test]
+    fn test_is_verse_span() {
+        // Two verses: four hemistichs of six tokens, three markers
+        assert!(is_verse_span(24, 3));
+        // One asterisk in 100 tokens of prose
+        assert!(!is_verse_span(100, 1));
+        assert!(!is_verse_span(10, 0));
+    }
+
+    #[test]
+    fn test_tag_verse_edges() {
+        let markers = VerseMarkers::from_token_ids([1]);
+        // Prose at [0, 60), then two verses at [60, 87)
+        let mut tokens = vec![5; 60];
+        for _ in 0..3 {
+            tokens.extend([6, 6, 6, 6, 6, 6, 1]);
+        }
+        tokens.extend([6; 6]);
+        let stream_a = stream(tokens);
+        let stream_b = stream(vec![5; 100]);
+
+        let edge = |start, end| ReuseEdge {
+            source_global_start: start,
+            source_global_end: end,
+            target_global_start: 0,
+            target_global_end: end - start,
+            ..Default::default()
+        };
+        let mut edges = vec![edge(0, 60), edge(60, 87)];
+        tag_verse_edges(&mut edges, &stream_a, &stream_b, &markers);
+        assert!(!edges[0].is_verse);
+        assert!(edges[1].is_verse);
+    }
+}
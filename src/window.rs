@@ -1,6 +1,8 @@
 //! Windowing logic for generating overlapping windows from lemma streams.
 
-use crate::models::{BookLemmaStream, BookTokenStream, ComparisonParams, Window};
+use crate::filter::compute_shingle_hashes;
+use crate::models::{BookLemmaStream, BookTokenStream, ComparisonParams, MatchMode, Window};
+use rayon::prelude::*;
 
 /// Generate overlapping windows from a book's lemma stream.
 ///
@@ -11,6 +13,186 @@ use crate::models::{BookLemmaStream, BookTokenStream, ComparisonParams, Window};
 /// Note: This creates windows with empty root_ids for backward compatibility.
 /// Use `generate_windows_with_roots` for root-based matching.
 pub fn generate_windows(stream: &BookLemmaStream, params: &ComparisonParams) -> Vec<Window> {
+    let mut windows = lemma_stream_windows(stream, params);
+    attach_shingle_hashes(&mut windows, params);
+    windows
+}
+
+/// Generate overlapping windows from a book's token stream with root support.
+///
+/// Windows are created with the specified size and stride.
+/// Each window contains slices of lemma IDs and root IDs.
+pub fn generate_windows_with_roots(stream: &BookTokenStream, params: &ComparisonParams) -> Vec<Window> {
+    let mut windows = token_stream_windows(stream, params);
+    attach_shingle_hashes(&mut windows, params);
+    windows
+}
+
+/// Generate one window per segment (e.g. per hadith report, see
+/// [`crate::segment`]). Segments longer than four windows are cut into regular
+/// windows so a long preface cannot produce one huge alignment.
+pub fn generate_segment_windows(
+    stream: &BookTokenStream,
+    segments: &[(usize, usize)],
+    params: &ComparisonParams,
+) -> Vec<Window> {
+    let max_segment = params.window_size * 4;
+    let mut ranges = Vec::with_capacity(segments.len());
+    for &(start, end) in segments {
+        if end - start <= max_segment {
+            ranges.push((start, end));
+            continue;
+        }
+        let mut window_start = start;
+        loop {
+            let window_end = (window_start + params.window_size).min(end);
+            ranges.push((window_start, window_end));
+            if window_end == end {
+                break;
+            }
+            window_start += params.stride;
+        }
+    }
+
+    let mut windows: Vec<Window> = ranges
+        .into_iter()
+        .filter(|(start, end)| end > start)
+        .enumerate()
+        .map(|(idx, range)| Window {
+            window_idx: idx as u32,
+            ..window_for_range(stream, range)
+        })
+        .collect();
+    attach_shingle_hashes(&mut windows, params);
+    windows
+}
+
+/// Keep only windows overlapping the global token range `[start, end)`.
+pub fn retain_windows_in_range(windows: &mut Vec<Window>, (start, end): (usize, usize)) {
+    windows.retain(|w| w.global_start < end && w.global_end > start);
+}
+
+/// Build a single window over the global token range `[start, end)` of a
+/// token stream, clamped to the book. Only the pages overlapping the range
+/// are visited.
+pub fn window_for_range(stream: &BookTokenStream, range: (usize, usize)) -> Window {
+    range_window(
+        stream.book_id,
+        stream.total_tokens,
+        stream.pages.iter().map(|p| {
+            (
+                (p.part_index, p.page_id),
+                &p.lemma_ids[..],
+                &p.root_ids[..],
+                &p.token_ids[..],
+                &p.alt_lemma_ids[..],
+            )
+        }),
+        range,
+    )
+}
+
+/// Like `window_for_range`, for a lemma stream (root IDs are all 0).
+pub fn window_for_lemma_range(stream: &BookLemmaStream, range: (usize, usize)) -> Window {
+    range_window(
+        stream.book_id,
+        stream.total_tokens,
+        stream.pages.iter().map(|p| ((p.part_index, p.page_id), &p.lemma_ids[..], &[][..], &[][..], &[][..])),
+        range,
+    )
+}
+
+/// Shared body of the range-window builders. Pages yield their location,
+/// lemma IDs, root IDs (empty = none), token IDs (empty = none) and
+/// alternatives (empty = none).
+fn range_window<'a>(
+    book_id: u32,
+    total_tokens: usize,
+    pages: impl Iterator<Item = ((u32, u32), &'a [u32], &'a [u32], &'a [u32], &'a [Vec<u32>])> + Clone,
+    (start, end): (usize, usize),
+) -> Window {
+    let end = end.min(total_tokens);
+    let start = start.min(end);
+    let has_alts = pages.clone().any(|(_, _, _, _, alts)| !alts.is_empty());
+    let has_tokens = pages.clone().any(|(_, _, _, tokens, _)| !tokens.is_empty());
+
+    let mut window = Window {
+        book_id,
+        window_idx: 0,
+        global_start: start,
+        global_end: end,
+        start_page: (0, 0),
+        start_offset: 0,
+        end_page: (0, 0),
+        end_offset: 0,
+        lemma_ids: Vec::with_capacity(end - start),
+        root_ids: Vec::with_capacity(end - start),
+        token_ids: Vec::new(),
+        alt_lemma_ids: Vec::new(),
+        shingle_hashes: Vec::new(),
+    };
+
+    let mut page_start = 0usize;
+    for (page, lemma_ids, root_ids, token_ids, alt_lemma_ids) in pages {
+        let page_end = page_start + lemma_ids.len();
+        if page_end > start && page_start < end && page_end > page_start {
+            let from = start.max(page_start) - page_start;
+            let to = end.min(page_end) - page_start;
+            if window.lemma_ids.is_empty() {
+                window.start_page = page;
+                window.start_offset = from as u32;
+            }
+            window.end_page = page;
+            window.end_offset = (to - 1) as u32;
+            window.lemma_ids.extend_from_slice(&lemma_ids[from..to]);
+            if root_ids.is_empty() {
+                window.root_ids.resize(window.lemma_ids.len(), 0);
+            } else {
+                window.root_ids.extend_from_slice(&root_ids[from..to]);
+            }
+            if has_tokens {
+                if token_ids.is_empty() {
+                    window.token_ids.resize(window.lemma_ids.len(), 0);
+                } else {
+                    window.token_ids.extend_from_slice(&token_ids[from..to]);
+                }
+            }
+            if has_alts {
+                if alt_lemma_ids.is_empty() {
+                    window.alt_lemma_ids.resize(window.lemma_ids.len(), Vec::new());
+                } else {
+                    window.alt_lemma_ids.extend_from_slice(&alt_lemma_ids[from..to]);
+                }
+            }
+        }
+        if page_end >= end {
+            break;
+        }
+        page_start = page_end;
+    }
+
+    window
+}
+
+/// Compute each window's shingle-hash set once, for reuse by candidate
+/// filtering and the Jaccard post-check. Skipped in brute-force mode, which
+/// uses neither.
+fn attach_shingle_hashes(windows: &mut [Window], params: &ComparisonParams) {
+    if params.brute_force {
+        return;
+    }
+    windows.par_iter_mut().for_each(|w| {
+        w.shingle_hashes = compute_shingle_hashes(
+            w,
+            params.ngram_size,
+            params.shingle_kind,
+            params.filter_mode,
+            params.stop_lemmas.as_ref(),
+        );
+    });
+}
+
+fn lemma_stream_windows(stream: &BookLemmaStream, params: &ComparisonParams) -> Vec<Window> {
     let flat_lemmas = stream.flat_lemmas();
     let mut windows = Vec::new();
 
@@ -38,12 +220,16 @@ pub fn generate_windows(stream: &BookLemmaStream, params: &ComparisonParams) ->
             end_offset,
             lemma_ids: flat_lemmas.clone(),
             root_ids: vec![0; flat_lemmas.len()],  // Empty roots
+            token_ids: Vec::new(),
+            alt_lemma_ids: Vec::new(),
+            shingle_hashes: Vec::new(),
         });
         return windows;
     }
 
     let mut window_idx = 0u32;
     let mut start = 0usize;
+    let mut last_end = 0usize;
 
     while start + params.window_size <= flat_lemmas.len() {
         let end = start + params.window_size;
@@ -62,14 +248,21 @@ pub fn generate_windows(stream: &BookLemmaStream, params: &ComparisonParams) ->
             end_offset,
             lemma_ids: flat_lemmas[start..end].to_vec(),
             root_ids: vec![0; end - start],  // Empty roots
+            token_ids: Vec::new(),
+            alt_lemma_ids: Vec::new(),
+            shingle_hashes: Vec::new(),
         });
 
+        last_end = end;
         window_idx += 1;
         start += params.stride;
     }
 
-    // Handle final partial window if needed
-    if start < flat_lemmas.len() && flat_lemmas.len() - start >= params.min_length {
+    // Handle final partial window if the tail isn't already covered
+    if last_end < flat_lemmas.len()
+        && start < flat_lemmas.len()
+        && flat_lemmas.len() - start >= params.min_length
+    {
         let (start_page, start_offset) = find_page_and_offset(&page_offsets, stream, start);
         let (end_page, end_offset) =
             find_page_and_offset(&page_offsets, stream, flat_lemmas.len() - 1);
@@ -86,19 +279,39 @@ pub fn generate_windows(stream: &BookLemmaStream, params: &ComparisonParams) ->
             end_offset,
             lemma_ids: flat_lemmas[start..].to_vec(),
             root_ids: vec![0; remaining],  // Empty roots
+            token_ids: Vec::new(),
+            alt_lemma_ids: Vec::new(),
+            shingle_hashes: Vec::new(),
         });
     }
 
     windows
 }
 
-/// Generate overlapping windows from a book's token stream with root support.
-///
-/// Windows are created with the specified size and stride.
-/// Each window contains slices of lemma IDs and root IDs.
-pub fn generate_windows_with_roots(stream: &BookTokenStream, params: &ComparisonParams) -> Vec<Window> {
+fn token_stream_windows(stream: &BookTokenStream, params: &ComparisonParams) -> Vec<Window> {
     let flat_lemmas = stream.flat_lemma_ids();
     let flat_roots = stream.flat_root_ids();
+    let flat_alts = stream.flat_alt_lemma_ids();
+    let alts_slice = |start: usize, end: usize| {
+        if flat_alts.is_empty() {
+            Vec::new()
+        } else {
+            flat_alts[start..end].to_vec()
+        }
+    };
+    // Token IDs are only kept when candidates are found on surface shingles
+    let flat_tokens = if params.filter_mode == MatchMode::Surface {
+        stream.flat_token_ids()
+    } else {
+        Vec::new()
+    };
+    let tokens_slice = |start: usize, end: usize| {
+        if flat_tokens.is_empty() {
+            Vec::new()
+        } else {
+            flat_tokens[start..end].to_vec()
+        }
+    };
     let mut windows = Vec::new();
 
     if flat_lemmas.is_empty() {
@@ -123,14 +336,18 @@ pub fn generate_windows_with_roots(stream: &BookTokenStream, params: &Comparison
             start_offset,
             end_page,
             end_offset,
+            alt_lemma_ids: alts_slice(0, flat_lemmas.len()),
+            token_ids: tokens_slice(0, flat_lemmas.len()),
             lemma_ids: flat_lemmas,
             root_ids: flat_roots,
+            shingle_hashes: Vec::new(),
         });
         return windows;
     }
 
     let mut window_idx = 0u32;
     let mut start = 0usize;
+    let mut last_end = 0usize;
 
     while start + params.window_size <= flat_lemmas.len() {
         let end = start + params.window_size;
@@ -149,14 +366,21 @@ pub fn generate_windows_with_roots(stream: &BookTokenStream, params: &Comparison
             end_offset,
             lemma_ids: flat_lemmas[start..end].to_vec(),
             root_ids: flat_roots[start..end].to_vec(),
+            token_ids: tokens_slice(start, end),
+            alt_lemma_ids: alts_slice(start, end),
+            shingle_hashes: Vec::new(),
         });
 
+        last_end = end;
         window_idx += 1;
         start += params.stride;
     }
 
-    // Handle final partial window if needed
-    if start < flat_lemmas.len() && flat_lemmas.len() - start >= params.min_length {
+    // Handle final partial window if the tail isn't already covered
+    if last_end < flat_lemmas.len()
+        && start < flat_lemmas.len()
+        && flat_lemmas.len() - start >= params.min_length
+    {
         let (start_page, start_offset) = find_page_and_offset_tokens(&page_offsets, stream, start);
         let (end_page, end_offset) =
             find_page_and_offset_tokens(&page_offsets, stream, flat_lemmas.len() - 1);
@@ -172,12 +396,42 @@ pub fn generate_windows_with_roots(stream: &BookTokenStream, params: &Comparison
             end_offset,
             lemma_ids: flat_lemmas[start..].to_vec(),
             root_ids: flat_roots[start..].to_vec(),
+            token_ids: tokens_slice(start, flat_lemmas.len()),
+            alt_lemma_ids: alts_slice(start, flat_lemmas.len()),
+            shingle_hashes: Vec::new(),
         });
     }
 
     windows
 }
 
+/// Calculate how many windows `generate_windows` would produce for a stream
+/// of `total_tokens` tokens, without materializing them.
+pub fn calculate_window_count(total_tokens: usize, params: &ComparisonParams) -> usize {
+    if total_tokens == 0 {
+        return 0;
+    }
+
+    if total_tokens < params.window_size {
+        return 1;
+    }
+
+    let stride = params.stride.max(1);
+    let full_windows = (total_tokens - params.window_size) / stride + 1;
+
+    // Final partial window, mirroring generate_windows
+    let last_end = (full_windows - 1) * stride + params.window_size;
+    let next_start = full_windows * stride;
+    if last_end < total_tokens
+        && next_start < total_tokens
+        && total_tokens - next_start >= params.min_length
+    {
+        full_windows + 1
+    } else {
+        full_windows
+    }
+}
+
 /// Page offset entry for efficient position lookups
 struct PageOffset {
     part_index: u32,
@@ -279,7 +533,7 @@ fn find_page_and_offset_tokens(
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::models::PageLemmas;
+    use crate::models::{PageLemmas, PageTokens};
 
     fn create_test_stream(page_sizes: &[usize]) -> BookLemmaStream {
         let mut pages = Vec::new();
@@ -305,6 +559,58 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_retain_windows_in_range() {
+        let stream = create_test_stream(&[500]);
+        let params = ComparisonParams {
+            window_size: 100,
+            stride: 50,
+            ..Default::default()
+        };
+        let mut windows = generate_windows(&stream, &params);
+        retain_windows_in_range(&mut windows, (120, 180));
+
+        // [50,150), [100,200) and [150,250) overlap; [200,300) only touches the end
+        let starts: Vec<usize> = windows.iter().map(|w| w.global_start).collect();
+        assert_eq!(starts, vec![50, 100, 150]);
+    }
+
+    #[test]
+    fn test_window_for_range_spans_pages() {
+        let pages: Vec<PageTokens> = [(1, 40), (2, 40), (3, 40)]
+            .iter()
+            .map(|&(page_id, size)| PageTokens {
+                part_index: 1,
+                page_id,
+                token_ids: (0..size).collect(),
+                lemma_ids: (0..size).map(|i| page_id * 100 + i).collect(),
+                root_ids: vec![0; size as usize],
+                alt_lemma_ids: Vec::new(),
+            })
+            .collect();
+        let stream = BookTokenStream {
+            book_id: 7,
+            total_tokens: 120,
+            pages,
+            corpus_weights: None,
+            quran: None,
+        };
+
+        let window = window_for_range(&stream, (30, 90));
+        assert_eq!((window.global_start, window.global_end), (30, 90));
+        assert_eq!((window.start_page, window.start_offset), ((1, 1), 30));
+        assert_eq!((window.end_page, window.end_offset), ((1, 3), 9));
+        assert_eq!(window.lemma_ids.len(), 60);
+        assert_eq!(window.lemma_ids[0], 130);
+        assert_eq!(window.lemma_ids[10], 200);
+        assert_eq!(window.lemma_ids[59], 309);
+
+        // Clamped to the end of the book
+        let tail = window_for_range(&stream, (100, 500));
+        assert_eq!((tail.global_start, tail.global_end), (100, 120));
+        assert_eq!(tail.lemma_ids.len(), 20);
+    }
+
     #[test]
     fn test_empty_stream() {
         let stream = BookLemmaStream {
@@ -432,4 +738,23 @@ mod tests {
         let count = calculate_window_count(1000, &params);
         assert!(count > 1);
     }
+
+    #[test]
+    fn test_windows_carry_shingle_hashes() {
+        let stream = create_test_stream(&[50]);
+        let params = ComparisonParams {
+            ngram_size: 5,
+            ..Default::default()
+        };
+        let windows = generate_windows(&stream, &params);
+        // 46 distinct 5-grams in 50 distinct lemmas
+        assert_eq!(windows[0].shingle_hashes.len(), 46);
+        assert!(windows[0].shingle_hashes.windows(2).all(|w| w[0] < w[1]));
+
+        let brute = ComparisonParams {
+            brute_force: true,
+            ..params
+        };
+        assert!(generate_windows(&stream, &brute)[0].shingle_hashes.is_empty());
+    }
 }
@@ -2,12 +2,47 @@
 //!
 //! These tests verify the end-to-end functionality of the text reuse detection pipeline.
 
+use kashshaf_reuse::abridge::EdgeSpan;
+use kashshaf_reuse::aggregate::{aggregate_batches, AggregateError, NETWORK_FILE};
 use kashshaf_reuse::align::align_lemma_sequences;
-use kashshaf_reuse::compare::compare_books_from_streams;
+use kashshaf_reuse::analysis::{cluster_edges, ClusterOptions};
+use kashshaf_reuse::batch::{
+    all_pairs, compare_book_pairs, cross_pairs, pair_dir, BatchOptions, CHECKPOINT_FILE, MANIFEST_FILE, RESULT_FILE,
+};
+use kashshaf_reuse::compare::{
+    compare_book_targets_with_targeted_text, compare_book_targets_with_text, compare_books_from_streams,
+    compare_books_with_text, compare_ranges, compare_texts, plan_comparison, TraceOutcome,
+};
+use kashshaf_reuse::corpus::{Corpus, CorpusBuilder};
+use kashshaf_reuse::db::import::{import_openiti, import_text, read_lexicon, ImportError, Lexicon};
+use kashshaf_reuse::db::{
+    list_books, load_all_token_mappings, load_book_info, load_book_token_stream_with_root, load_collection, load_lemma_alternatives,
+    DbError,
+};
 use kashshaf_reuse::filter::{find_candidate_pairs, generate_shingles};
+use kashshaf_reuse::graph::{load_network, GraphError};
+use kashshaf_reuse::idf::{corpus_weights_path, load_corpus_weights};
 use kashshaf_reuse::merge::merge_overlapping_edges;
-use kashshaf_reuse::models::{BookLemmaStream, ComparisonParams, PageLemmas, ReuseEdge};
+use kashshaf_reuse::models::{
+    BookLemmaStream, CheckpointEntry, CheckpointHeader, ComparisonParams, ComparisonResultWithText, Direction,
+    MatchMode, PageLemmas, PageRange, PairStatus, ReuseEdge, Shard, SurfaceTableBuilder, Validation, WeightSource,
+};
+use kashshaf_reuse::normalize::NormalizationConfig;
+use kashshaf_reuse::output::{
+    apply_validations, apply_validations_with_text, generate_corpus_viewer_html, read_json_with_text_file,
+    read_results_with_text_dir, read_validations_file, write_alignment_trace_html, write_collation_html,
+    write_csv_file, write_csv_with_text_options,
+    write_graphml, write_json_file, write_json_with_text_file, write_passim_json, write_result_csv_with_text, write_xlsx,
+    write_xlsx_with_headers, CsvOptions, HeaderLanguage, OutputColumns, PassimAlignment,
+    ViewerAssets, VIEWER_ASSET_FILES,
+};
+use kashshaf_reuse::pipeline::{run_pipeline, PipelineConfig, DASHBOARD_FILE, GEXF_FILE, GRAPHML_FILE};
+use kashshaf_reuse::redact::{redact_result, rehydrate_result, RedactError};
+use kashshaf_reuse::sweep::{read_gold_edges, run_sweep, SweepConfig};
+use kashshaf_reuse::tokenize::SimpleTokenizer;
 use kashshaf_reuse::window::generate_windows;
+use rusqlite::{params, Connection};
+use std::path::{Path, PathBuf};
 
 /// Helper to create a test book stream with specified content.
 fn create_book(book_id: u32, page_sizes: &[usize], base_lemma: u32) -> BookLemmaStream {
@@ -45,7 +80,7 @@ fn create_book_with_shared(
 
     // Fill with unique lemmas before shared region
     for i in 0..shared_start {
-        lemmas.push((book_id as u32 * 100000) + i as u32);
+        lemmas.push((book_id * 100000) + i as u32);
     }
 
     // Insert shared content
@@ -54,7 +89,7 @@ fn create_book_with_shared(
     // Fill with unique lemmas after shared region
     let remaining = total_size.saturating_sub(shared_start + shared_content.len());
     for i in 0..remaining {
-        lemmas.push((book_id as u32 * 100000) + 50000 + i as u32);
+        lemmas.push((book_id * 100000) + 50000 + i as u32);
     }
 
     BookLemmaStream {
@@ -272,10 +307,22 @@ fn test_merge_overlapping() {
             core_similarity: 1.0,
             span_coverage: 0.9,
             content_weight: 1.0,
+            lexical_diversity: 1.0,
+            shingle_jaccard: 1.0,
+            is_verse: false,
+            suspect: false,
+            quran_overlap: 0.0,
+            source_span_length: 100,
+            target_span_length: 100,
+            span_ratio: 1.0,
+            direction: Direction::Unknown,
+            validation: None,
             lemma_similarity: 0.9,
             combined_similarity: 0.925,
             weighted_similarity: 0.9,
             avg_match_weight: 1.0,
+            score: 175,
+            normalized_score: 1.75,
         },
         ReuseEdge {
             id: 2,
@@ -301,10 +348,22 @@ fn test_merge_overlapping() {
             core_similarity: 1.0,
             span_coverage: 0.9,
             content_weight: 1.0,
+            lexical_diversity: 1.0,
+            shingle_jaccard: 1.0,
+            is_verse: false,
+            suspect: false,
+            quran_overlap: 0.0,
+            source_span_length: 100,
+            target_span_length: 100,
+            span_ratio: 1.0,
+            direction: Direction::Unknown,
+            validation: None,
             lemma_similarity: 0.9,
             combined_similarity: 0.925,
             weighted_similarity: 0.9,
             avg_match_weight: 1.0,
+            score: 175,
+            normalized_score: 1.75,
         },
     ];
 
@@ -342,7 +401,7 @@ fn test_comparison_params_defaults() {
     assert_eq!(params.ngram_size, 5);
     assert_eq!(params.min_shared_shingles, 3);
     assert_eq!(params.min_length, 10);
-    assert!((params.min_similarity - 0.4).abs() < 0.001);
+    assert!(params.min_similarity.abs() < 0.001);
     assert_eq!(params.match_score, 2);
     assert_eq!(params.mismatch_penalty, -1);
     assert_eq!(params.gap_penalty, -1);
@@ -398,3 +457,1636 @@ fn test_brute_force_mode() {
     // Brute force might find more edges before merging
     // but after merging, results should be similar
 }
+
+/// Scratch directory unique to one test.
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("kashshaf-reuse-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Build a minimal corpus.db where token id == lemma id == root id.
+fn create_corpus_db(path: &Path, books: &[(u32, Vec<u32>)]) {
+    let conn = Connection::open(path).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE token_definitions (id INTEGER PRIMARY KEY, surface TEXT, lemma_id INTEGER, root_id INTEGER);
+         CREATE TABLE page_tokens (book_id INTEGER, part_index INTEGER, page_id INTEGER, token_ids BLOB);",
+    )
+    .unwrap();
+
+    let max_token = books
+        .iter()
+        .flat_map(|(_, tokens)| tokens.iter().copied())
+        .max()
+        .unwrap_or(0);
+    for id in 0..=max_token {
+        conn.execute(
+            "INSERT INTO token_definitions (id, surface, lemma_id, root_id) VALUES (?, ?, ?, ?)",
+            params![id, format!("w{}", id), id, id],
+        )
+        .unwrap();
+    }
+
+    for (book_id, tokens) in books {
+        let blob: Vec<u8> = tokens.iter().flat_map(|t| t.to_le_bytes()).collect();
+        conn.execute(
+            "INSERT INTO page_tokens (book_id, part_index, page_id, token_ids) VALUES (?, 1, 1, ?)",
+            params![book_id, blob],
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+fn test_batch_prescan_skips_unrelated_pairs() {
+    let dir = temp_dir("batch-prescan");
+    let db_path = dir.join("corpus.db");
+    create_corpus_db(
+        &db_path,
+        &[
+            (1, (1..400).collect()),
+            (2, (1..400).collect()),
+            (3, (1000..1400).collect()),
+        ],
+    );
+
+    let options = BatchOptions {
+        min_prescan_overlap: Some(0.1),
+        ..Default::default()
+    };
+    let out_dir = dir.join("results");
+    let manifest = compare_book_pairs(
+        &db_path,
+        &all_pairs(&[1, 2, 3]),
+        &ComparisonParams::default(),
+        &options,
+        &out_dir,
+        false,
+    )
+    .unwrap();
+
+    assert_eq!(manifest.pairs.len(), 3);
+    let related = &manifest.pairs[0];
+    assert_eq!(related.status, PairStatus::Ok);
+    assert!(related.edge_count > 0);
+    let pair_dir = out_dir.join(related.output.as_ref().unwrap());
+    assert_eq!(pair_dir, out_dir.join("results").join("1_2"));
+    for file in ["result.json", "summary.json", "viewer.html"] {
+        assert!(pair_dir.join(file).exists());
+        assert_eq!(related.checksums[file].len(), 64);
+    }
+
+    for record in &manifest.pairs[1..] {
+        assert_eq!(record.status, PairStatus::SkippedPrescan);
+        assert!(record.output.is_none());
+        assert!(record.checksums.is_empty());
+    }
+    assert!(out_dir.join("manifest.json").exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_lemma_alternatives_attach_to_stream() {
+    let dir = temp_dir("lemma-alternatives");
+    let db_path = dir.join("corpus.db");
+    create_corpus_db(&db_path, &[(1, vec![1, 2, 3])]);
+
+    // Table is optional
+    assert!(load_lemma_alternatives(&db_path).unwrap().is_empty());
+
+    let conn = Connection::open(&db_path).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE token_lemma_alternatives (token_id INTEGER, lemma_id INTEGER);
+         INSERT INTO token_lemma_alternatives VALUES (2, 2), (2, 50), (2, 60);",
+    )
+    .unwrap();
+
+    let alternatives = load_lemma_alternatives(&db_path).unwrap();
+    let (token_to_lemma, token_to_root, _) = load_all_token_mappings(&db_path).unwrap();
+    let mut stream = load_book_token_stream_with_root(&db_path, 1, &token_to_lemma, &token_to_root).unwrap();
+    stream.attach_lemma_alternatives(&alternatives);
+
+    // The primary lemma is not repeated among the alternatives
+    assert_eq!(stream.pages[0].alt_lemma_ids, vec![vec![], vec![50, 60], vec![]]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_collections_select_cross_pairs() {
+    let dir = temp_dir("collections");
+    let db_path = dir.join("corpus.db");
+    create_corpus_db(&db_path, &[(1, vec![1]), (2, vec![2]), (3, vec![3])]);
+
+    // Table is optional, but naming a collection without it is an error
+    assert!(matches!(load_collection(&db_path, "tafsir"), Err(DbError::CollectionNotFound(_))));
+
+    let conn = Connection::open(&db_path).unwrap();
+    conn.execute_batch(
+        "CREATE TABLE collections (name TEXT, book_id INTEGER);
+         INSERT INTO collections VALUES ('tafsir', 3), ('tafsir', 1), ('hadith', 2), ('hadith', 3);",
+    )
+    .unwrap();
+
+    let tafsir = load_collection(&db_path, "tafsir").unwrap();
+    let hadith = load_collection(&db_path, "hadith").unwrap();
+    assert_eq!(tafsir, vec![1, 3]);
+    assert_eq!(cross_pairs(&tafsir, &hadith), vec![(1, 2), (1, 3), (3, 2)]);
+    assert!(matches!(load_collection(&db_path, "sira"), Err(DbError::CollectionNotFound(_))));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_batch_pair_statuses() {
+    let dir = temp_dir("batch-statuses");
+    let db_path = dir.join("corpus.db");
+    create_corpus_db(
+        &db_path,
+        &[(1, (1..400).collect()), (2, (1..400).collect()), (3, (1000..1400).collect())],
+    );
+
+    let out_dir = dir.join("results");
+    // Book 9 is not in the corpus: its pairs fail without stopping the run
+    let pairs = [(1, 2), (1, 3), (1, 9)];
+    let run = |params: &ComparisonParams| {
+        compare_book_pairs(&db_path, &pairs, params, &BatchOptions::default(), &out_dir, false).unwrap()
+    };
+
+    let manifest = run(&ComparisonParams::default());
+    let statuses: Vec<PairStatus> = manifest.pairs.iter().map(|p| p.status).collect();
+    assert_eq!(statuses, vec![PairStatus::Ok, PairStatus::Empty, PairStatus::Error]);
+    assert!(manifest.pairs[1].output.is_some());
+    let failed = &manifest.pairs[2];
+    assert_eq!(failed.error.as_deref(), Some("Book not found: 9"));
+    assert!(failed.output.is_none() && failed.summary.is_none());
+
+    // Compared pairs are reused, failed ones retried
+    let rerun = run(&ComparisonParams::default());
+    let cached: Vec<bool> = rerun.pairs.iter().map(|p| p.cached).collect();
+    assert_eq!(cached, vec![true, true, false]);
+
+    // Alignments abandoned at the cell cap mark the pair as incomplete
+    let capped = ComparisonParams {
+        max_alignment_cells: Some(100),
+        ..Default::default()
+    };
+    assert_eq!(run(&capped).pairs[0].status, PairStatus::Timeout);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_batch_reuses_cached_pairs() {
+    let dir = temp_dir("batch-cache");
+    let db_path = dir.join("corpus.db");
+    create_corpus_db(&db_path, &[(1, (1..400).collect()), (2, (1..400).collect())]);
+
+    let out_dir = dir.join("results");
+    let run = |options: &BatchOptions, params: &ComparisonParams| {
+        compare_book_pairs(&db_path, &[(1, 2)], params, options, &out_dir, false).unwrap()
+    };
+    let params = ComparisonParams::default();
+
+    let first = run(&BatchOptions::default(), &params);
+    assert!(!first.pairs[0].cached);
+    assert!(!first.params_fingerprint.is_empty());
+
+    let second = run(&BatchOptions::default(), &params);
+    assert!(second.pairs[0].cached);
+    assert_eq!(second.pairs[0].checksums, first.pairs[0].checksums);
+
+    // --force and changed parameters both bypass the cache
+    let forced = BatchOptions {
+        force: true,
+        ..Default::default()
+    };
+    assert!(!run(&forced, &params).pairs[0].cached);
+    let changed = ComparisonParams {
+        min_length: 12,
+        ..Default::default()
+    };
+    assert!(!run(&BatchOptions::default(), &changed).pairs[0].cached);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_batch_shards_cover_every_pair() {
+    let dir = temp_dir("batch-shards");
+    let db_path = dir.join("corpus.db");
+    let books: Vec<(u32, Vec<u32>)> = (1..=4).map(|id| (id, (1..300).collect())).collect();
+    create_corpus_db(&db_path, &books);
+
+    let pairs = all_pairs(&[1, 2, 3, 4]);
+    let params = ComparisonParams::default();
+    let mut covered = Vec::new();
+    for index in 1..=2 {
+        let options = BatchOptions {
+            shard: Some(Shard { index, count: 2 }),
+            ..Default::default()
+        };
+        let out_dir = dir.join(format!("shard-{}", index));
+        let manifest = compare_book_pairs(&db_path, &pairs, &params, &options, &out_dir, false).unwrap();
+        assert_eq!(manifest.shard, Some(Shard { index, count: 2 }));
+        covered.extend(manifest.pairs.iter().map(|p| (p.book_a, p.book_b)));
+    }
+
+    covered.sort();
+    assert_eq!(covered, pairs);
+
+    let out_of_range = BatchOptions {
+        shard: Some(Shard { index: 3, count: 2 }),
+        ..Default::default()
+    };
+    assert!(compare_book_pairs(&db_path, &pairs, &params, &out_of_range, &dir.join("bad"), false).is_err());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_aggregate_merges_shards() {
+    let dir = temp_dir("aggregate");
+    let db_path = dir.join("corpus.db");
+    let books: Vec<(u32, Vec<u32>)> = (1..=4).map(|id| (id, (1..300).collect())).collect();
+    create_corpus_db(&db_path, &books);
+
+    let pairs = all_pairs(&[1, 2, 3, 4]);
+    let params = ComparisonParams::default();
+    let shard_dirs: Vec<PathBuf> = (1..=2)
+        .map(|index| {
+            let options = BatchOptions {
+                shard: Some(Shard { index, count: 2 }),
+                ..Default::default()
+            };
+            let out_dir = dir.join(format!("shard-{}", index));
+            compare_book_pairs(&db_path, &pairs, &params, &options, &out_dir, false).unwrap();
+            out_dir
+        })
+        .collect();
+
+    let merged_dir = dir.join("merged");
+    let (manifest, summary) = aggregate_batches(&shard_dirs, &merged_dir).unwrap();
+    let merged: Vec<(u32, u32)> = manifest.pairs.iter().map(|p| (p.book_a, p.book_b)).collect();
+    assert_eq!(merged, pairs);
+    assert_eq!(manifest.shard, None);
+    assert!(summary.missing_shards.is_empty());
+    assert_eq!(summary.pairs_with_reuse, pairs.len());
+    for record in &manifest.pairs {
+        let pair_dir = merged_dir.join(record.output.as_ref().unwrap());
+        for file in record.checksums.keys() {
+            assert!(pair_dir.join(file).exists());
+        }
+    }
+    let network = std::fs::read_to_string(merged_dir.join(NETWORK_FILE)).unwrap();
+    assert_eq!(network.lines().count(), 1 + pairs.len());
+
+    // A merged directory is a regular batch output: rerunning reuses every pair
+    let rerun = compare_book_pairs(&db_path, &pairs, &params, &BatchOptions::default(), &merged_dir, false).unwrap();
+    assert!(rerun.pairs.iter().all(|p| p.cached));
+
+    let (_, partial) = aggregate_batches(&shard_dirs[..1], &dir.join("partial")).unwrap();
+    assert_eq!(partial.missing_shards, vec![2]);
+
+    let twice = [shard_dirs[0].clone(), shard_dirs[0].clone()];
+    assert!(matches!(
+        aggregate_batches(&twice, &dir.join("twice")),
+        Err(AggregateError::ShardMismatch { .. })
+    ));
+
+    let other_params = ComparisonParams {
+        min_length: params.min_length + 1,
+        ..Default::default()
+    };
+    let other_dir = dir.join("other");
+    let options = BatchOptions {
+        shard: Some(Shard { index: 2, count: 2 }),
+        ..Default::default()
+    };
+    compare_book_pairs(&db_path, &pairs, &other_params, &options, &other_dir, false).unwrap();
+    assert!(matches!(
+        aggregate_batches(&[shard_dirs[0].clone(), other_dir], &dir.join("mismatch")),
+        Err(AggregateError::FingerprintMismatch { .. })
+    ));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_graph_from_result_directory() {
+    let dir = temp_dir("graph");
+    let db_path = dir.join("corpus.db");
+    let mut books: Vec<(u32, Vec<u32>)> = (1..=3).map(|id| (id, (1..300).collect())).collect();
+    books.push((4, (1000..1300).collect()));
+    create_corpus_db(&db_path, &books);
+
+    let out_dir = dir.join("batch");
+    let pairs = all_pairs(&[1, 2, 3, 4]);
+    compare_book_pairs(&db_path, &pairs, &ComparisonParams::default(), &BatchOptions::default(), &out_dir, false)
+        .unwrap();
+
+    let network = load_network(&out_dir).unwrap();
+    let ids: Vec<u32> = network.nodes.iter().map(|n| n.id).collect();
+    assert_eq!(ids, vec![1, 2, 3, 4]);
+    let edges: Vec<(u32, u32)> = network.edges.iter().map(|e| (e.source, e.target)).collect();
+    assert_eq!(edges, vec![(1, 2), (1, 3), (2, 3)]);
+    assert!(network.edges.iter().all(|e| e.weight > 0 && e.edge_count > 0));
+
+    let mut graphml = Vec::new();
+    write_graphml(&network, &mut graphml).unwrap();
+    let graphml = String::from_utf8(graphml).unwrap();
+    assert_eq!(graphml.matches("<node ").count(), 4);
+    assert_eq!(graphml.matches("<edge ").count(), 3);
+
+    // A pair found in a second file is an error, not a second edge
+    let copy = out_dir.join("copy.json");
+    std::fs::copy(out_dir.join(pair_dir(1, 2)).join(RESULT_FILE), &copy).unwrap();
+    assert!(matches!(
+        load_network(&out_dir),
+        Err(GraphError::DuplicatePair { book_a: 1, book_b: 2, .. })
+    ));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_pipeline_from_config_file() {
+    let dir = temp_dir("pipeline");
+    create_corpus_db(
+        &dir.join("corpus.db"),
+        &[(1, (1..300).collect()), (2, (1..300).collect()), (3, (500..800).collect())],
+    );
+    let config_path = dir.join("pipeline.toml");
+    std::fs::write(
+        &config_path,
+        "corpus_db = \"corpus.db\"\noutput_dir = \"run\"\nbooks = [1, 2, 3]\n\
+         [params]\nmin_length = 12\n[outputs]\ngexf = true\n",
+    )
+    .unwrap();
+    let config = PipelineConfig::from_file(&config_path).unwrap();
+    assert_eq!(config.output_dir, dir.join("run"));
+
+    let report = run_pipeline(&config, false).unwrap();
+    assert_eq!(report.summary.pair_count, 3);
+    assert_eq!(report.summary.pairs_with_reuse, 1);
+    assert_eq!(report.cached_pairs, 0);
+    let run_dir = dir.join("run");
+    assert_eq!(
+        report.files,
+        vec![run_dir.join(GRAPHML_FILE), run_dir.join(GEXF_FILE), run_dir.join(DASHBOARD_FILE)]
+    );
+    for file in [MANIFEST_FILE, NETWORK_FILE, "corpus_summary.json"] {
+        assert!(run_dir.join(file).exists());
+    }
+    assert_eq!(load_network(&run_dir).unwrap().nodes.len(), 3);
+
+    // A rerun reuses every pair and rebuilds the corpus outputs
+    let rerun = run_pipeline(&config, false).unwrap();
+    assert_eq!(rerun.cached_pairs, 3);
+    assert_eq!(rerun.summary.total_edges, report.summary.total_edges);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_sweep_scores_runs_against_gold_edges() {
+    let dir = temp_dir("sweep");
+    let db_path = dir.join("corpus.db");
+    // Book 2 quotes tokens 1..200 of book 1 after 100 tokens of its own
+    create_corpus_db(&db_path, &[(1, (1..400).collect()), (2, (1000..1100).chain(1..200).collect())]);
+
+    // Gold: the quotation, written as if found comparing 2 with 1, and a
+    // passage no run finds
+    let gold_edges = [((100, 299), (0, 199)), ((300, 310), (350, 360))]
+        .map(|(source, target)| ReuseEdge {
+            source_book_id: 2,
+            source_global_start: source.0,
+            source_global_end: source.1,
+            target_book_id: 1,
+            target_global_start: target.0,
+            target_global_end: target.1,
+            ..Default::default()
+        });
+    write_csv_file(&gold_edges, &dir.join("gold.csv")).unwrap();
+    let gold = read_gold_edges(&dir.join("gold.csv"), 1, 2).unwrap();
+    assert_eq!((gold[0].source, gold[0].target), ((0, 199), (100, 299)));
+
+    std::fs::write(
+        dir.join("sweep.toml"),
+        "corpus_db = \"corpus.db\"\nbook_a = 1\nbook_b = 2\ngold = \"gold.csv\"\n\
+         [params]\nstride = 25\n[grid]\nwindow_size = [50, 100]\nmin_core_similarity = [0.8, 0.9]\n",
+    )
+    .unwrap();
+    let config = SweepConfig::from_file(&dir.join("sweep.toml")).unwrap();
+    assert_eq!(config.gold, Some(dir.join("gold.csv")));
+
+    let mut corpus = Corpus::open(&config.corpus_db).unwrap();
+    let report = run_sweep(&mut corpus, &config, Some(&gold), false).unwrap();
+    assert_eq!(report.runs.len(), 4);
+    assert_eq!(report.parameters.stride, 25);
+    let first = &report.runs[0];
+    assert_eq!(first.values["min_core_similarity"], serde_json::json!(0.8));
+    assert_eq!(first.values["window_size"], serde_json::json!(50));
+    for run in &report.runs {
+        let scores = run.scores.unwrap();
+        assert!(run.edge_count > 0);
+        assert_eq!((scores.precision, scores.recall), (1.0, 0.5));
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_cli_audit_sample_without_include_text() {
+    let dir = temp_dir("cli-audit-sample");
+    let db_path = dir.join("corpus.db");
+    create_corpus_db(&db_path, &[(1, (1..400).collect()), (2, (1..400).collect())]);
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_kashshaf-reuse"))
+        .arg("compare")
+        .arg("--corpus-db")
+        .arg(&db_path)
+        .args(["--book-a", "1", "--book-b", "2", "--audit-sample", "2", "--quiet", "--output"])
+        .arg(dir.join("out.json"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(dir.join("out.audit.json").exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_sweep_cli_reports_errors_as_plain_text() {
+    let dir = temp_dir("sweep-cli");
+    create_corpus_db(&dir.join("corpus.db"), &[(1, (1..100).collect())]);
+    std::fs::write(
+        dir.join("sweep.toml"),
+        "corpus_db = \"corpus.db\"\nbook_a = 1\nbook_b = 1\n[grid]\nwindow_size = [50]\n",
+    )
+    .unwrap();
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_kashshaf-reuse"))
+        .arg("sweep")
+        .arg("--config")
+        .arg(dir.join("sweep.toml"))
+        .arg("--output")
+        .arg(dir.join("sweep.json"))
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("cannot be compared with itself"));
+    assert!(!stderr.contains("SameBook"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_batch_resumes_from_checkpoint() {
+    let dir = temp_dir("batch-resume");
+    let db_path = dir.join("corpus.db");
+    create_corpus_db(
+        &db_path,
+        &[(1, (1..400).collect()), (2, (1..400).collect()), (3, (1..400).collect())],
+    );
+
+    let out_dir = dir.join("results");
+    let params = ComparisonParams::default();
+    let first = compare_book_pairs(&db_path, &[(1, 2)], &params, &BatchOptions::default(), &out_dir, false).unwrap();
+    assert!(!out_dir.join(CHECKPOINT_FILE).exists());
+
+    // An interrupted run: pair (1, 2) checkpointed, a second pair cut off
+    // mid-line, and no manifest
+    let header = CheckpointHeader {
+        version: first.version.clone(),
+        params_fingerprint: first.params_fingerprint.clone(),
+        db_fingerprint: first.db_fingerprint.clone(),
+    };
+    let entry = CheckpointEntry {
+        record: first.pairs[0].clone(),
+        book_hashes: first.book_hashes.clone(),
+    };
+    let checkpoint = format!(
+        "{}\n{}\n{{\"record\": {{\"book_a\": 1, \"bo",
+        serde_json::to_string(&header).unwrap(),
+        serde_json::to_string(&entry).unwrap()
+    );
+    std::fs::remove_file(out_dir.join(MANIFEST_FILE)).unwrap();
+    std::fs::write(out_dir.join(CHECKPOINT_FILE), &checkpoint).unwrap();
+
+    let pairs = [(1, 2), (1, 3)];
+    let resume = BatchOptions {
+        resume: true,
+        ..Default::default()
+    };
+    let resumed = compare_book_pairs(&db_path, &pairs, &params, &resume, &out_dir, false).unwrap();
+    assert!(resumed.pairs[0].cached);
+    assert!(!resumed.pairs[1].cached);
+    assert_eq!(resumed.pairs[0].checksums, first.pairs[0].checksums);
+    assert!(!out_dir.join(CHECKPOINT_FILE).exists());
+
+    // Without --resume the checkpoint is ignored
+    std::fs::remove_file(out_dir.join(MANIFEST_FILE)).unwrap();
+    std::fs::write(out_dir.join(CHECKPOINT_FILE), &checkpoint).unwrap();
+    let fresh = compare_book_pairs(&db_path, &pairs, &params, &BatchOptions::default(), &out_dir, false).unwrap();
+    assert!(!fresh.pairs[0].cached);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_batch_verifies_stream_hashes_after_db_update() {
+    let dir = temp_dir("batch-stream-hash");
+    let db_path = dir.join("corpus.db");
+    create_corpus_db(
+        &db_path,
+        &[(1, (1..400).collect()), (2, (1..400).collect()), (3, (1..400).collect())],
+    );
+
+    let out_dir = dir.join("results");
+    let pairs = [(1, 2), (1, 3)];
+    let params = ComparisonParams::default();
+    let run = || compare_book_pairs(&db_path, &pairs, &params, &BatchOptions::default(), &out_dir, false).unwrap();
+
+    let first = run();
+    assert_eq!(first.book_hashes.len(), 3);
+    assert_ne!(first.book_hashes[&1], String::new());
+
+    // Rewrite book 3 in place; corpus.db's fingerprint changes with it
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    let blob: Vec<u8> = (1..300u32).flat_map(|t| t.to_le_bytes()).collect();
+    Connection::open(&db_path)
+        .unwrap()
+        .execute("UPDATE page_tokens SET token_ids = ? WHERE book_id = 3", params![blob])
+        .unwrap();
+
+    let second = run();
+    assert_ne!(second.db_fingerprint, first.db_fingerprint);
+    assert!(second.pairs[0].cached); // Books 1 and 2 unchanged
+    assert!(!second.pairs[1].cached); // Book 3 updated
+    assert_ne!(second.book_hashes[&3], first.book_hashes[&3]);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_compare_book_targets_shares_source() {
+    let dir = temp_dir("multi-target");
+    let db_path = dir.join("corpus.db");
+    create_corpus_db(
+        &db_path,
+        &[
+            (1, (1..400).collect()),
+            (2, (1..400).collect()),
+            (3, (1000..1400).collect()),
+        ],
+    );
+
+    let mut seen = Vec::new();
+    compare_book_targets_with_text(
+        1,
+        &[2, 3],
+        &db_path,
+        &ComparisonParams::default(),
+        30,
+        false,
+        |target, result| -> Result<(), DbError> {
+            assert_eq!(result.book_a.id, 1);
+            assert_eq!(result.book_b.id, target);
+            // No lemma is frequent enough to be a stop-lemma here
+            for edge in &result.edges {
+                assert_eq!(edge.signature.split(' ').count(), 12);
+                assert!(edge.signature.starts_with('w'));
+            }
+            seen.push((target, result.edges.len()));
+            Ok(())
+        },
+    )
+    .unwrap();
+
+    assert_eq!(seen.len(), 2);
+    assert!(seen[0].1 > 0);
+    assert_eq!(seen[1], (3, 0));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_signatures_use_corpus_stop_lemmas() {
+    let dir = temp_dir("corpus-stop-lemmas");
+    let db_path = dir.join("corpus.db");
+    // Lemma 7 fills most of book 1, so it is a stop-lemma corpus-wide but
+    // rare in book 2
+    let passage: Vec<u32> = (1..200).collect();
+    let book_1: Vec<u32> = passage.iter().copied().chain(std::iter::repeat_n(7, 300)).collect();
+    let book_2: Vec<u32> = passage.iter().copied().chain(3000..3300).collect();
+    let book_3: Vec<u32> = (5000..5300).chain(passage.iter().copied()).collect();
+    create_corpus_db(&db_path, &[(1, book_1), (2, book_2), (3, book_3)]);
+    let params = ComparisonParams::default();
+
+    let first_signature = |book_a: u32| {
+        let result = compare_books_with_text(book_a, 3, &db_path, &params, 0, false).unwrap();
+        let edge = result.edges.iter().min_by_key(|e| e.source.global_range).unwrap().clone();
+        edge.signature
+    };
+    let signature_1 = first_signature(1);
+    assert_eq!(signature_1, first_signature(2));
+    assert!(!signature_1.split(' ').any(|word| word == "w7"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_targeted_surfaces_match_full_table() {
+    let dir = temp_dir("targeted-surfaces");
+    let db_path = dir.join("corpus.db");
+    // A shared passage in the middle of otherwise unrelated books
+    let book_a: Vec<u32> = (1000..1300).chain(1..200).chain(2000..2300).collect();
+    let book_b: Vec<u32> = (3000..3300).chain(1..200).collect();
+    create_corpus_db(&db_path, &[(1, book_a), (2, book_b)]);
+    let params = ComparisonParams::default();
+
+    let run = |targeted: bool| {
+        let mut results = Vec::new();
+        let on_result = |_, result: ComparisonResultWithText| -> Result<(), DbError> {
+            results.push(result);
+            Ok(())
+        };
+        if targeted {
+            compare_book_targets_with_targeted_text(1, &[2], &db_path, &params, 10, false, on_result)
+        } else {
+            compare_book_targets_with_text(1, &[2], &db_path, &params, 10, false, on_result)
+        }
+        .unwrap();
+        results.pop().unwrap()
+    };
+    let full = run(false);
+    let targeted = run(true);
+
+    assert!(!full.edges.is_empty());
+    assert_eq!(full.edges.len(), targeted.edges.len());
+    for (a, b) in full.edges.iter().zip(&targeted.edges) {
+        assert_eq!(a.signature, b.signature);
+        assert_eq!(a.source.text.before, b.source.text.before);
+        assert_eq!(a.source.text.matched, b.source.text.matched);
+        assert_eq!(a.target.text.after, b.target.text.after);
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_surface_table_pads_missing_ids() {
+    let mut builder = SurfaceTableBuilder::default();
+    builder.push(1, "قال");
+    builder.push(4, "حدثنا");
+    let table = builder.finish();
+
+    assert_eq!(table.len(), 5);
+    assert_eq!(table.get(0), Some(""));
+    assert_eq!(table.get(1), Some("قال"));
+    assert_eq!(table.get(3), Some(""));
+    assert_eq!(table.get(4), Some("حدثنا"));
+    assert_eq!(table.get(5), None);
+    let clone = table.clone();
+    assert_eq!(clone.iter().filter(|(_, s)| !s.is_empty()).count(), 2);
+}
+
+#[test]
+fn test_in_memory_corpus_weights() {
+    let text = |words: std::ops::Range<u32>| words.map(|w| format!("w{}", w)).collect::<Vec<_>>().join(" ");
+    let mut corpus = CorpusBuilder::new()
+        .book(1, [text(1..300)])
+        .book(2, [text(1..300), text(500..600)])
+        .build()
+        .unwrap();
+
+    // No sidecar file to read or write: the weights are counted in memory
+    let weights = corpus.corpus_weights().unwrap();
+    assert!(weights.iter().any(|&w| w > 0.0));
+
+    let params = ComparisonParams {
+        weight_source: WeightSource::Corpus,
+        ..Default::default()
+    };
+    let result = corpus.compare_with_text(1, 2, &params, 5).unwrap();
+    assert!(!result.edges.is_empty());
+    assert_eq!(result.book_b.page_count, 2);
+}
+
+#[test]
+fn test_root_modes_without_root_data() {
+    let root_params = ComparisonParams {
+        mode: MatchMode::Root,
+        filter_mode: MatchMode::Root,
+        ..Default::default()
+    };
+    let strict = ComparisonParams {
+        require_roots: true,
+        ..root_params.clone()
+    };
+
+    // No root_id column at all
+    let dir = temp_dir("no-root-column");
+    let db_path = dir.join("corpus.db");
+    create_corpus_db(&db_path, &[(1, (1..400).collect()), (2, (1..400).collect())]);
+    Connection::open(&db_path)
+        .unwrap()
+        .execute_batch("ALTER TABLE token_definitions DROP COLUMN root_id")
+        .unwrap();
+    let result = compare_books_with_text(1, 2, &db_path, &root_params, 5, false).unwrap();
+    assert!(!result.edges.is_empty());
+    assert_eq!(result.parameters.mode, MatchMode::Lemma);
+    assert_eq!(result.parameters.filter_mode, MatchMode::Lemma);
+    assert!(matches!(
+        compare_books_with_text(1, 2, &db_path, &strict, 5, false),
+        Err(DbError::NoRootData)
+    ));
+
+    // A root_id column that is entirely NULL
+    let text = |words: std::ops::Range<u32>| words.map(|w| format!("w{}", w)).collect::<Vec<_>>().join(" ");
+    let mut corpus = CorpusBuilder::new()
+        .book(1, [text(1..300)])
+        .book(2, [text(1..300)])
+        .build()
+        .unwrap();
+    let result = corpus.compare_with_text(1, 2, &root_params, 5).unwrap();
+    assert!(!result.edges.is_empty());
+    assert_eq!(result.parameters.mode, MatchMode::Lemma);
+    assert!(matches!(corpus.compare(1, 2, &strict), Err(DbError::NoRootData)));
+
+    // Lemma mode never needs roots
+    let lemma_params = ComparisonParams {
+        mode: MatchMode::Lemma,
+        filter_mode: MatchMode::Lemma,
+        ..strict
+    };
+    assert!(corpus.compare(1, 2, &lemma_params).is_ok());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_alignment_trace() {
+    let text = |words: std::ops::Range<u32>| words.map(|w| format!("w{}", w)).collect::<Vec<_>>().join(" ");
+    let mut corpus = CorpusBuilder::new()
+        .book(7, [format!("{} {}", text(900..950), text(1..100))])
+        .book(9, [text(1..100)])
+        .build()
+        .unwrap();
+    let params = ComparisonParams {
+        window_size: 20,
+        stride: 10,
+        ngram_size: 3,
+        ..Default::default()
+    };
+    // Window 5 of book 7 starts at w1, like window 0 of book 9; window 99 doesn't exist
+    let trace = corpus.trace(7, 9, &params, &[(5, 0), (0, 0), (99, 0)]).unwrap();
+
+    assert_eq!(trace.pairs.len(), 2);
+    let (shared, unrelated) = (&trace.pairs[0], &trace.pairs[1]);
+    assert_eq!((shared.source_start, shared.target_start), (50, 0));
+    assert!(shared.candidate);
+    match &shared.outcome {
+        TraceOutcome::Aligned(alignment) => assert_eq!(alignment.lemma_matches, 20),
+        other => panic!("expected an alignment, got {:?}", other),
+    }
+    assert!(!unrelated.candidate);
+    assert!(matches!(unrelated.outcome, TraceOutcome::Rejected));
+    assert!(!trace.edges.is_empty());
+
+    let mut html = Vec::new();
+    write_alignment_trace_html(&trace, &mut html).unwrap();
+    let html = String::from_utf8(html).unwrap();
+    assert_eq!(html.matches("<svg").count(), 2);
+    assert!(html.contains("not a candidate"));
+    assert!(html.contains("<polyline"));
+}
+
+#[test]
+fn test_substitution_report() {
+    // Book 2 copies book 1, swapping w40 for v40 and w120 for v120
+    let words = |range: std::ops::Range<u32>| -> Vec<String> {
+        range
+            .map(|w| match w {
+                40 | 120 => format!("v{}", w),
+                _ => format!("w{}", w),
+            })
+            .collect()
+    };
+    let original = (1..200).map(|w| format!("w{}", w)).collect::<Vec<_>>().join(" ");
+    let mut corpus = CorpusBuilder::new()
+        .book(1, [original])
+        .book(2, [words(1..200).join(" ")])
+        .build()
+        .unwrap();
+    let params = ComparisonParams {
+        no_filters: true,
+        ..Default::default()
+    };
+    let result = corpus.compare(1, 2, &params).unwrap();
+    assert!(!result.edges.is_empty());
+
+    let spans: Vec<EdgeSpan> = result.edges.iter().map(EdgeSpan::from).collect();
+    let report = corpus.substitutions(1, 2, &params, &spans).unwrap();
+    assert_eq!((report.book_a, report.book_b), (1, 2));
+    assert_eq!(report.edges.len(), result.edges.len());
+    assert!(report.skipped_edges.is_empty());
+    let mut surfaces: Vec<(String, String)> = report
+        .table
+        .iter()
+        .flat_map(|row| row.surfaces.iter().map(|s| (s.source.clone(), s.target.clone())))
+        .collect();
+    surfaces.sort();
+    assert_eq!(
+        surfaces,
+        vec![
+            ("w120".to_string(), "v120".to_string()),
+            ("w40".to_string(), "v40".to_string())
+        ]
+    );
+    assert_eq!(report.substitution_count, report.table.iter().map(|row| row.count).sum::<usize>());
+}
+
+#[test]
+fn test_csv_with_text_quoting() {
+    let text = |words: std::ops::Range<u32>| words.map(|w| format!("w{}", w)).collect::<Vec<_>>().join(" ");
+    let mut corpus = CorpusBuilder::new()
+        .book(1, [text(1..300)])
+        .book(2, [text(1..300)])
+        .build()
+        .unwrap();
+    let mut result = corpus.compare_with_text(1, 2, &ComparisonParams::default(), 5).unwrap();
+    let matched = "قال; \"حدثنا\"\nفلان";
+    result.edges[0].source.text.matched = matched.to_string();
+
+    let options = CsvOptions {
+        delimiter: b';',
+        bom: true,
+        headers: HeaderLanguage::English,
+        ..Default::default()
+    };
+    let mut output = Vec::new();
+    write_csv_with_text_options(&result.edges, &options, &mut output).unwrap();
+    assert!(String::from_utf8_lossy(&output).contains("قال"));
+
+    let mut reader = csv::ReaderBuilder::new().delimiter(b';').from_reader(output.as_slice());
+    let headers = reader.headers().unwrap().clone();
+    let column = headers.iter().position(|h| h == "source_text_matched").unwrap();
+    let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+    assert_eq!(rows.len(), result.edges.len());
+    assert_eq!(&rows[0][column], matched);
+    assert_eq!(rows[0].len(), headers.len());
+
+    // Bilingual headers, and the books' titles from the result
+    result.book_a.title = "كتاب الأم".to_string();
+    let options = CsvOptions {
+        headers: HeaderLanguage::Bilingual,
+        ..Default::default()
+    };
+    let mut output = Vec::new();
+    write_result_csv_with_text(&result, &options, &mut output).unwrap();
+    let mut reader = csv::Reader::from_reader(output.as_slice());
+    let headers = reader.headers().unwrap().clone();
+    let rows: Vec<csv::StringRecord> = reader.records().map(Result::unwrap).collect();
+    assert_eq!(rows.len(), 1 + result.edges.len());
+    assert_eq!(&rows[0][0], "المعرف");
+    assert_eq!(&rows[0][column], "نص المصدر المطابق");
+    let title = headers.iter().position(|h| h == "source_title").unwrap();
+    assert_eq!(&rows[1][title], "كتاب الأم");
+    assert_eq!(rows[1].len(), headers.len());
+
+    // A column selection, including a metric the default layout leaves out
+    let options = CsvOptions {
+        columns: OutputColumns::default()
+            .column("id")
+            .column("core_similarity")
+            .column("source_title")
+            .column("source_text_matched"),
+        ..Default::default()
+    };
+    let mut output = Vec::new();
+    write_result_csv_with_text(&result, &options, &mut output).unwrap();
+    let mut reader = csv::Reader::from_reader(output.as_slice());
+    assert_eq!(
+        reader.headers().unwrap(),
+        vec!["id", "core_similarity", "source_title", "source_text_matched"]
+    );
+    let row = reader.records().next().unwrap().unwrap();
+    assert_eq!(row[1].parse::<f32>().unwrap(), result.edges[0].alignment.core_similarity);
+    assert_eq!(&row[2], "كتاب الأم");
+    assert_eq!(&row[3], matched);
+}
+
+#[test]
+fn test_xlsx_export() {
+    use calamine::{Data, Reader};
+
+    let text = |words: std::ops::Range<u32>| words.map(|w| format!("w{}", w)).collect::<Vec<_>>().join(" ");
+    let mut corpus = CorpusBuilder::new()
+        .book(1, [text(1..300)])
+        .book(2, [text(1..300)])
+        .build()
+        .unwrap();
+    let mut result = corpus.compare_with_text(1, 2, &ComparisonParams::default(), 5).unwrap();
+    result.book_a.title = "كتاب الأم".to_string();
+    result.edges[0].source.text.matched = "قال حدثنا".to_string();
+
+    let mut output = Vec::new();
+    write_xlsx(&result, &mut output).unwrap();
+    let mut workbook = calamine::Xlsx::new(std::io::Cursor::new(output)).unwrap();
+    assert_eq!(workbook.sheet_names(), vec!["Edges", "Summary"]);
+
+    let edges = workbook.worksheet_range("Edges").unwrap();
+    assert_eq!(edges.height(), 1 + result.edges.len());
+    assert_eq!(edges.get((0, 7)), Some(&Data::String("source_text_matched".to_string())));
+    assert_eq!(edges.get((1, 7)), Some(&Data::String("قال حدثنا".to_string())));
+    assert_eq!(edges.get((1, 2)), Some(&Data::Float(1.0)));
+
+    assert_eq!(edges.get((1, 21)), Some(&Data::String("كتاب الأم".to_string())));
+
+    let mut output = Vec::new();
+    write_xlsx_with_headers(&result, HeaderLanguage::Arabic, &mut output).unwrap();
+    let mut arabic = calamine::Xlsx::new(std::io::Cursor::new(output)).unwrap();
+    let arabic_edges = arabic.worksheet_range("Edges").unwrap();
+    assert_eq!(arabic_edges.get((0, 7)), Some(&Data::String("نص المصدر المطابق".to_string())));
+    assert_eq!(arabic_edges.height(), edges.height());
+
+    let summary = workbook.worksheet_range("Summary").unwrap();
+    let value = |field: &str| {
+        summary
+            .rows()
+            .find(|row| row[0] == Data::String(field.to_string()))
+            .map(|row| row[1].clone())
+    };
+    assert_eq!(value("book_a_title"), Some(Data::String("كتاب الأم".to_string())));
+    assert_eq!(value("edge_count"), Some(Data::Float(result.edges.len() as f64)));
+}
+
+#[test]
+fn test_suspect_edges() {
+    // Shared prose and a shared table of numbers, 300 tokens apart. Prose
+    // words are distinct: the digits of n spelled with the letters ا..ر
+    let letters: Vec<char> = "ابتثجحخدذر".chars().collect();
+    let word = |n: u32| n.to_string().bytes().map(|d| letters[(d - b'0') as usize]).collect::<String>();
+    let prose = (100..180).map(word).collect::<Vec<_>>().join(" ");
+    let table = (1..80).map(|n| (n * 7).to_string()).collect::<Vec<_>>().join(" ");
+    let book = |filler: &str| [format!("{} {} {}", prose, filler, table)];
+    let mut corpus = CorpusBuilder::new()
+        .book(1, book(&"x ".repeat(300)))
+        .book(2, book(&"y ".repeat(300)))
+        .build()
+        .unwrap();
+    let params = ComparisonParams::default();
+    let result = corpus.compare_with_text(1, 2, &params, 5).unwrap();
+    let suspect: Vec<bool> = result.edges.iter().map(|e| e.alignment.suspect).collect();
+    assert!(suspect.contains(&true) && suspect.contains(&false), "{:?}", suspect);
+
+    let params = ComparisonParams {
+        drop_suspect: true,
+        ..params
+    };
+    let kept = corpus.compare(1, 2, &params).unwrap();
+    assert_eq!(kept.edges.len(), suspect.iter().filter(|&&s| !s).count());
+    assert!(kept.edges.iter().all(|e| !e.suspect));
+}
+
+#[test]
+fn test_quran_quotation() {
+    // Both books quote the same verses, and share a passage of prose 300 tokens on
+    let text = |prefix: &str, words: std::ops::Range<u32>| {
+        words.map(|w| format!("{}{}", prefix, w)).collect::<Vec<_>>().join(" ")
+    };
+    let book = |filler: &str| [format!("{} {} {}", text("q", 0..80), filler.repeat(300), text("p", 0..80))];
+    let mut corpus = CorpusBuilder::new()
+        .book(1, book("x "))
+        .book(2, book("y "))
+        .book(3, [text("q", 0..500)])
+        .build()
+        .unwrap();
+    let params = ComparisonParams {
+        quran_book_id: Some(3),
+        ..Default::default()
+    };
+    let result = corpus.compare(1, 2, &params).unwrap();
+    let mut overlaps: Vec<f32> = result.edges.iter().map(|e| e.quran_overlap).collect();
+    overlaps.sort_by(f32::total_cmp);
+    assert_eq!(overlaps, vec![0.0, 1.0]);
+
+    let params = ComparisonParams {
+        max_quran_overlap: Some(0.5),
+        ..params
+    };
+    let kept = corpus.compare_with_text(1, 2, &params, 5).unwrap();
+    assert_eq!(kept.edges.len(), 1);
+    assert_eq!(kept.edges[0].alignment.quran_overlap, 0.0);
+    assert_eq!(kept.edges[0].source.global_range.0, 380);
+}
+
+#[test]
+fn test_alignment_ops_follow_matched_words() {
+    let text = |words: std::ops::Range<u32>| words.map(|w| format!("w{}", w)).collect::<Vec<_>>().join(" ");
+    let mut corpus = CorpusBuilder::new()
+        .book(1, [text(1..200)])
+        .book(2, [format!("{} x1 x2 {}", text(1..100), text(100..180))])
+        .build()
+        .unwrap();
+    let mut result = corpus.compare_with_text(1, 2, &ComparisonParams::default(), 5).unwrap();
+    assert!(!result.edges.is_empty());
+    assert_eq!(corpus.alignment_ops(&mut result).unwrap(), 0);
+
+    // One op per matched word on each side, which is what the viewer relies on
+    for edge in &result.edges {
+        let ops = edge.alignment.ops.as_deref().unwrap();
+        assert_eq!(ops.chars().filter(|&op| op != 'I').count(), edge.source.text.matched.split(' ').count());
+        assert_eq!(ops.chars().filter(|&op| op != 'D').count(), edge.target.text.matched.split(' ').count());
+    }
+    assert!(result.edges.iter().any(|e| e.alignment.ops.as_deref().unwrap().contains('I')));
+}
+
+#[test]
+fn test_filter_validated_round_trip() {
+    let dir = temp_dir("filter-validated");
+    let text = |words: std::ops::Range<u32>| words.map(|w| format!("w{}", w)).collect::<Vec<_>>().join(" ");
+    let mut corpus = CorpusBuilder::new()
+        .book(1, [format!("{} {} {}", text(1..60), text(500..600), text(1000..1060))])
+        .book(2, [format!("{} {} {}", text(1..60), text(700..800), text(1000..1060))])
+        .build()
+        .unwrap();
+    let params = ComparisonParams {
+        window_size: 20,
+        stride: 10,
+        ngram_size: 3,
+        ..Default::default()
+    };
+    let result = corpus.compare_with_text(1, 2, &params, 3).unwrap();
+    let ids: Vec<u64> = result.edges.iter().map(|e| e.id).collect();
+    assert!(ids.len() >= 2);
+
+    // As written by the viewer's "Export Validated" button
+    let csv = format!(
+        "id,validation,source_text,target_text,similarity\n{},valid,\"w1, w2 \"\"quoted\"\"\",\"w1 w2\",1\n{},noise,\"a\",\"b\",0.9\n",
+        ids[0], ids[1]
+    );
+    let csv_path = dir.join("validated_matches.csv");
+    std::fs::write(&csv_path, csv).unwrap();
+    let labels = read_validations_file(&csv_path).unwrap();
+    assert_eq!(labels.len(), 2);
+
+    let kept = apply_validations_with_text(result, &labels, false);
+    assert_eq!(kept.edges.len(), 1);
+    assert_eq!(kept.edges[0].id, ids[0]);
+    assert_eq!(kept.edges[0].validation, Some(Validation::Valid));
+    assert_eq!(kept.summary.edge_count, 1);
+
+    // Labels survive the JSON round trip
+    let path = dir.join("validated.json");
+    write_json_with_text_file(&kept, &path).unwrap();
+    assert_eq!(read_json_with_text_file(&path).unwrap().edges[0].validation, Some(Validation::Valid));
+
+    // Without text; unreviewed edges kept on request, noise still dropped
+    let plain = corpus.compare(1, 2, &params).unwrap();
+    let noise = plain.edges[1].id;
+    let labels = std::collections::HashMap::from([(plain.edges[0].id, Validation::Valid), (noise, Validation::Noise)]);
+    let plain = apply_validations(plain, &labels, true);
+    assert_eq!(plain.edges.len(), ids.len() - 1);
+    assert!(plain.edges.iter().all(|e| e.id != noise));
+    assert_eq!(plain.edges.iter().filter(|e| e.validation.is_some()).count(), 1);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_corpus_viewer_from_result_directory() {
+    let dir = temp_dir("corpus-viewer");
+    let text = |words: std::ops::Range<u32>| words.map(|w| format!("w{}", w)).collect::<Vec<_>>().join(" ");
+    let mut corpus = CorpusBuilder::new()
+        .book(1, [text(1..200)])
+        .book(2, [text(1..200)])
+        .book(3, [text(500..700)])
+        .build()
+        .unwrap();
+    let params = ComparisonParams::default();
+    for (a, b) in [(1, 2), (1, 3)] {
+        let result = corpus.compare_with_text(a, b, &params, 5).unwrap();
+        std::fs::create_dir_all(dir.join(format!("{}_{}", a, b))).unwrap();
+        write_json_with_text_file(&result, &dir.join(format!("{}_{}", a, b)).join("result.json")).unwrap();
+    }
+    // A repeat of a pair in the other order, and a result without text
+    let repeat = corpus.compare_with_text(2, 1, &params, 5).unwrap();
+    write_json_with_text_file(&repeat, &dir.join("repeat.json")).unwrap();
+    write_json_file(&corpus.compare(2, 3, &params).unwrap(), &dir.join("plain.json")).unwrap();
+
+    let results = read_results_with_text_dir(&dir).unwrap();
+    let pairs: Vec<(u32, u32)> = results.iter().map(|r| (r.book_a.id, r.book_b.id)).collect();
+    assert_eq!(pairs, vec![(1, 2), (1, 3)]);
+    assert!(results[0].summary.edge_count > 0);
+
+    let html = generate_corpus_viewer_html(&results, &ViewerAssets::Cdn);
+    assert!(html.contains("window.__CORPUS_DATA__ = [{"));
+    assert!(html.contains("root.render(<Dashboard />)"));
+    assert!(html.contains("const { useState, useEffect, useMemo } = React;"));
+    assert!(html.contains(r#"<script src="https://unpkg.com/react@18/umd/react.production.min.js"></script>"#));
+
+    // Offline: every library is inlined in load order, none fetched
+    let assets_dir = dir.join("assets");
+    std::fs::create_dir_all(&assets_dir).unwrap();
+    assert!(ViewerAssets::from_dir(&assets_dir).is_err());
+    for (file, _) in VIEWER_ASSET_FILES {
+        std::fs::write(assets_dir.join(file), format!("var lib = '{} </script>';", file)).unwrap();
+    }
+    let html = generate_corpus_viewer_html(&results, &ViewerAssets::from_dir(&assets_dir).unwrap());
+    assert!(!html.contains("https://"));
+    let positions: Vec<usize> = VIEWER_ASSET_FILES
+        .iter()
+        .map(|(file, _)| html.find(&format!("var lib = '{} <\\/script>';", file)).unwrap())
+        .collect();
+    assert!(positions.windows(2).all(|w| w[0] < w[1]));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_cluster_and_collate_across_result_files() {
+    let dir = temp_dir("cluster");
+    let text = |prefix: &str, words: std::ops::Range<u32>| {
+        words.map(|w| format!("{}{}", prefix, w)).collect::<Vec<_>>().join(" ")
+    };
+    // One passage quoted by all three books at different offsets, 3 reading
+    // one word differently; 1 and 2 also share a second passage that 3 lacks
+    let mut corpus = CorpusBuilder::new()
+        .book(1, [format!("{} {} {}", text("p", 0..100), text("a", 0..200), text("s", 0..100))])
+        .book(2, [format!("{} {} {} {}", text("b", 0..50), text("p", 0..100), text("d", 0..200), text("s", 0..100))])
+        .book(3, [format!("{} {} v {}", text("c", 0..300), text("p", 0..50), text("p", 51..100))])
+        .build()
+        .unwrap();
+    let params = ComparisonParams::default();
+    // 1 and 3 are never compared directly
+    for (a, b) in [(1, 2), (2, 3)] {
+        let result = corpus.compare_with_text(a, b, &params, 5).unwrap();
+        write_json_with_text_file(&result, &dir.join(format!("{}_{}.json", a, b))).unwrap();
+    }
+
+    let results = read_results_with_text_dir(&dir).unwrap();
+    let report = cluster_edges(&results, &ClusterOptions::default());
+    assert_eq!(report.result_count, 2);
+    assert_eq!(report.clusters.len(), 1);
+    let members: Vec<(u32, (usize, usize))> =
+        report.clusters[0].members.iter().map(|m| (m.book_id, m.global_range)).collect();
+    assert_eq!(members, vec![(1, (0, 100)), (2, (50, 150)), (3, (300, 400))]);
+    assert!(report.clusters[0].members.iter().all(|m| m.text.starts_with("p0 p1")));
+
+    let two = ClusterOptions {
+        min_witnesses: 2,
+        ..Default::default()
+    };
+    assert_eq!(cluster_edges(&results, &two).clusters.len(), 2);
+
+    let collation = corpus.collate(&report.clusters[0], &params).unwrap();
+    assert_eq!((collation.cluster_id, collation.witnesses.len()), (1, 3));
+    assert_eq!((collation.columns.len(), collation.variant_columns), (100, 1));
+    let variant = collation.columns.iter().find(|c| c.variant).unwrap();
+    assert_eq!(variant.words, vec![Some("p50".to_string()), Some("p50".to_string()), Some("v".to_string())]);
+    let mut html = Vec::new();
+    write_collation_html(&collation, &mut html).unwrap();
+    let html = String::from_utf8(html).unwrap();
+    assert_eq!(html.matches("<tr>").count(), 3);
+    assert_eq!(html.matches(r#"<td class="variant">"#).count(), 3);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_passim_export() {
+    let text = |words: std::ops::Range<u32>| words.map(|w| format!("w{}", w)).collect::<Vec<_>>().join(" ");
+    let mut corpus = CorpusBuilder::new()
+        .book(7, [format!("{} {}", text(900..950), text(1..300))])
+        .book(9, [text(1..300)])
+        .build()
+        .unwrap();
+    let result = corpus.compare_with_text(7, 9, &ComparisonParams::default(), 5).unwrap();
+    assert!(!result.edges.is_empty());
+
+    let mut output = Vec::new();
+    write_passim_json(&result, &mut output).unwrap();
+    let records: Vec<PassimAlignment> = String::from_utf8(output)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+
+    assert_eq!(records.len(), result.edges.len());
+    let (record, edge) = (&records[0], &result.edges[0]);
+    assert_eq!((record.id1.as_str(), record.id2.as_str()), ("7", "9"));
+    assert_eq!((record.b1, record.e1), edge.source.global_range);
+    assert!(record.b1 >= 50);
+    assert_eq!((record.n1, record.n2), (349, 299));
+    assert_eq!(record.s2, edge.target.text.matched);
+    assert!(record.s1.starts_with('w'));
+}
+
+#[test]
+fn test_corpus_caches_streams() {
+    let dir = temp_dir("corpus-cache");
+    let db_path = dir.join("corpus.db");
+    create_corpus_db(
+        &db_path,
+        &[(1, (1..400).collect()), (2, (1..400).collect()), (3, (1..400).collect())],
+    );
+    let params = ComparisonParams::default();
+
+    let mut corpus = Corpus::open(&db_path).unwrap();
+    let result = corpus.compare_with_text(1, 2, &params, 30).unwrap();
+    let expected = compare_books_with_text(1, 2, &db_path, &params, 30, false).unwrap();
+    assert_eq!(result.edges.len(), expected.edges.len());
+    assert_eq!(result.summary.book_a_coverage, expected.summary.book_a_coverage);
+    assert_eq!(corpus.compare(1, 2, &params).unwrap().edges.len(), expected.edges.len());
+    assert_eq!(corpus.cached_books(), 2);
+    assert_eq!(corpus.source().list_books().unwrap(), list_books(&db_path).unwrap());
+    assert_eq!(list_books(&db_path).unwrap(), vec![1, 2, 3]);
+
+    // Both record where the books came from and what each stage kept
+    let run_info = &expected.run_info;
+    assert_eq!(run_info.corpus_db, db_path.canonicalize().unwrap().display().to_string());
+    assert_eq!(run_info.db_fingerprint.len(), 64);
+    assert_eq!(result.run_info.db_fingerprint, run_info.db_fingerprint);
+    assert!(run_info.wall_ms >= run_info.load_ms + run_info.align_ms);
+    assert_eq!(run_info.stats, result.run_info.stats);
+    assert!(run_info.stats.candidate_pairs > 0 && run_info.stats.raw_edges > 0);
+    assert_eq!(
+        run_info.stats.alignments,
+        run_info.stats.rejected_alignments + run_info.stats.raw_edges
+    );
+    if cfg!(target_os = "linux") {
+        assert!(run_info.peak_rss_bytes.is_some_and(|bytes| bytes > 0));
+    }
+    assert_eq!(run_info.stats.merged_edges - run_info.stats.dropped_edges, expected.edges.len());
+
+    // Each stream is 399 tokens x 3 IDs x 4 bytes; room for two of them
+    let mut small = Corpus::open(&db_path).unwrap().with_cache_limit(2 * 399 * 12);
+    small.compare(1, 2, &params).unwrap();
+    small.compare(1, 3, &params).unwrap();
+    assert_eq!(small.cached_books(), 2);
+    assert!(small.cached_bytes() <= 2 * 399 * 12);
+    assert!(matches!(small.compare(1, 4, &params), Err(DbError::BookNotFound(4))));
+
+    // Index-only mode: candidates without alignment
+    let set = corpus.candidates(1, 2, &params).unwrap();
+    assert_eq!((set.book_a.id, set.book_b.id), (1, 2));
+    assert!(!set.candidates.is_empty());
+    assert!(set.candidates.iter().all(|c| c.shared_shingles >= params.min_shared_shingles as u64));
+    assert!(set
+        .candidates
+        .iter()
+        .any(|c| c.source_start == c.target_start && c.source_end == c.target_end));
+    for candidate in &set.candidates {
+        assert!(candidate.weighted_jaccard > 0.0 && candidate.weighted_jaccard <= candidate.weighted_containment);
+        assert!(candidate.weighted_containment <= 1.0 + 1e-6);
+        if candidate.source_start == candidate.target_start && candidate.source_end == candidate.target_end {
+            assert_eq!(candidate.weighted_containment, 1.0);
+        }
+    }
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_corpus_wide_idf_weights() {
+    let dir = temp_dir("corpus-idf");
+    let db_path = dir.join("corpus.db");
+    // Lemma 1 makes up most of the corpus through book 3
+    create_corpus_db(
+        &db_path,
+        &[(1, (1..400).collect()), (2, (1..400).collect()), (3, vec![1; 2000])],
+    );
+
+    let (token_to_lemma, _, _) = load_all_token_mappings(&db_path).unwrap();
+    let weights = load_corpus_weights(&db_path, &token_to_lemma).unwrap();
+    assert_eq!(weights[1], 0.5);
+    assert_eq!(weights[300], 3.0);
+    assert!(corpus_weights_path(&db_path).exists());
+    assert_eq!(load_corpus_weights(&db_path, &token_to_lemma).unwrap(), weights);
+
+    // Within book 1 every lemma occurs once, so document weights are flat
+    let mut corpus = Corpus::open(&db_path).unwrap();
+    let document = corpus.compare(1, 2, &ComparisonParams::default()).unwrap();
+    assert!(document.edges.iter().all(|e| e.content_weight == 3.0));
+
+    let corpus_params = ComparisonParams {
+        weight_source: WeightSource::Corpus,
+        ..Default::default()
+    };
+    let corpus_wide = corpus.compare(1, 2, &corpus_params).unwrap();
+    assert!(corpus_wide.edges.iter().any(|e| e.content_weight < 3.0));
+    let from_db = compare_books_with_text(1, 2, &db_path, &corpus_params, 30, false).unwrap();
+    assert_eq!(
+        from_db.edges.iter().map(|e| e.alignment.content_weight).collect::<Vec<_>>(),
+        corpus_wide.edges.iter().map(|e| e.content_weight).collect::<Vec<_>>()
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_plan_comparison() {
+    let dir = temp_dir("plan");
+    let db_path = dir.join("corpus.db");
+    create_corpus_db(&db_path, &[(1, (1..2000).collect()), (2, (1..2000).collect())]);
+    let params = ComparisonParams::default();
+
+    // Few enough windows that every one is sampled: the estimate is exact
+    let plan = plan_comparison(&db_path, 1, 2, &params).unwrap();
+    let mut corpus = Corpus::open(&db_path).unwrap();
+    let set = corpus.candidates(1, 2, &params).unwrap();
+    assert_eq!((plan.windows_a, plan.windows_b), (set.source_windows, set.target_windows));
+    assert_eq!(plan.sampled_windows, plan.windows_a);
+    assert_eq!(plan.estimated_candidates, set.candidates.len() as u64);
+    assert!(plan.estimated_alignment_cells > plan.estimated_candidates);
+    assert!(plan.estimated_memory_bytes > 2 * 1999 * 12);
+    assert_eq!(corpus.plan(1, 2, &params).unwrap().estimated_candidates, plan.estimated_candidates);
+
+    let brute = ComparisonParams {
+        brute_force: true,
+        ..Default::default()
+    };
+    let plan = plan_comparison(&db_path, 1, 2, &brute).unwrap();
+    assert_eq!(plan.estimated_candidates, (plan.windows_a * plan.windows_b) as u64);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_compare_ranges_within_one_book() {
+    let dir = temp_dir("compare-ranges");
+    let db_path = dir.join("corpus.db");
+
+    // Pages 1-3 hold a passage, pages 4-5 unrelated text, pages 6-7 repeat the passage
+    let conn_pages: Vec<Vec<u32>> = vec![
+        (1..100).collect(),
+        (100..200).collect(),
+        (200..300).collect(),
+        (1000..1100).collect(),
+        (1100..1200).collect(),
+        (1..150).collect(),
+        (150..300).collect(),
+    ];
+    create_corpus_db(&db_path, &[(1, conn_pages.iter().flatten().copied().collect())]);
+    let conn = Connection::open(&db_path).unwrap();
+    conn.execute("DELETE FROM page_tokens", []).unwrap();
+    for (i, tokens) in conn_pages.iter().enumerate() {
+        let blob: Vec<u8> = tokens.iter().flat_map(|t| t.to_le_bytes()).collect();
+        conn.execute(
+            "INSERT INTO page_tokens (book_id, part_index, page_id, token_ids) VALUES (1, 1, ?, ?)",
+            params![i as u32 + 1, blob],
+        )
+        .unwrap();
+    }
+
+    let range = |start, end| PageRange { start: (1, start), end: (1, end) };
+    let result = compare_ranges(1, range(1, 3), range(6, 7), &db_path, &ComparisonParams::default(), 5, false).unwrap();
+
+    assert!(!result.edges.is_empty());
+    for edge in &result.edges {
+        // Global positions are in book coordinates: section B starts at token 499
+        assert!(edge.source.global_range.1 <= 299);
+        assert!(edge.target.global_range.0 >= 499);
+        assert_eq!(edge.source.book_id, edge.target.book_id);
+    }
+    assert!(result.summary.book_a_coverage > 0.9);
+    assert!(result.summary.book_b_coverage > 0.9);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_recompute_compare_ranges_summary() {
+    let dir = temp_dir("compare-ranges-recompute");
+    let db_path = dir.join("corpus.db");
+
+    // Page 1 holds a passage and page 2 unrelated text; page 3 repeats the
+    // passage with an extra token after every tenth, so the copy is longer
+    let repeat: Vec<u32> = (1..200).flat_map(|t| if t % 10 == 0 { vec![t, 5000 + t] } else { vec![t] }).collect();
+    let pages: Vec<Vec<u32>> = vec![(1..200).collect(), (1000..1200).collect(), repeat];
+    create_corpus_db(&db_path, &[(1, pages.iter().flatten().copied().collect())]);
+    let conn = Connection::open(&db_path).unwrap();
+    conn.execute("DELETE FROM page_tokens", []).unwrap();
+    for (i, tokens) in pages.iter().enumerate() {
+        let blob: Vec<u8> = tokens.iter().flat_map(|t| t.to_le_bytes()).collect();
+        conn.execute(
+            "INSERT INTO page_tokens (book_id, part_index, page_id, token_ids) VALUES (1, 1, ?, ?)",
+            params![i as u32 + 1, blob],
+        )
+        .unwrap();
+    }
+
+    let range = |start, end| PageRange { start: (1, start), end: (1, end) };
+    let mut result = compare_ranges(1, range(1, 2), range(3, 3), &db_path, &ComparisonParams::default(), 5, false).unwrap();
+    assert!(!result.edges.is_empty());
+    let (coverage_a, coverage_b) = (result.summary.book_a_coverage, result.summary.book_b_coverage);
+    assert!(coverage_a < 0.6);
+    assert!(coverage_b > 0.8);
+
+    // Recomputing, as filter-validated does, keeps each side's coverage
+    result.recompute_summary();
+    assert_eq!(result.summary.book_a_coverage, coverage_a);
+    assert_eq!(result.summary.book_b_coverage, coverage_b);
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_redact_and_rehydrate() {
+    let dir = temp_dir("redact");
+    let db_path = dir.join("corpus.db");
+    create_corpus_db(&db_path, &[(1, (1..400).collect()), (2, (1..400).collect())]);
+
+    let original = compare_books_with_text(1, 2, &db_path, &ComparisonParams::default(), 10, false).unwrap();
+    assert!(!original.edges.is_empty());
+
+    let path = dir.join("result.json");
+    let mut redacted = compare_books_with_text(1, 2, &db_path, &ComparisonParams::default(), 10, false).unwrap();
+    redact_result(&mut redacted, 10);
+    write_json_with_text_file(&redacted, &path).unwrap();
+    assert!(!std::fs::read_to_string(&path).unwrap().contains("w100"));
+
+    let mut restored = read_json_with_text_file(&path).unwrap();
+    assert_eq!(restored.edges[0].source.global_range, original.edges[0].source.global_range);
+    rehydrate_result(&mut restored, &db_path).unwrap();
+    assert!(restored.redaction.is_none());
+    for (a, b) in original.edges.iter().zip(&restored.edges) {
+        assert_eq!(a.signature, b.signature);
+        assert_eq!(a.source.text.matched, b.source.text.matched);
+        assert_eq!(a.target.text.before, b.target.text.before);
+        assert_eq!(a.target.text.after, b.target.text.after);
+    }
+    assert!(matches!(rehydrate_result(&mut restored, &db_path), Err(RedactError::NotRedacted)));
+
+    // A different copy of book 2 can't restore the text
+    let mut redacted = read_json_with_text_file(&path).unwrap();
+    let other_db = dir.join("other.db");
+    create_corpus_db(&other_db, &[(1, (1..400).collect()), (2, (2..401).collect())]);
+    assert!(matches!(rehydrate_result(&mut redacted, &other_db), Err(RedactError::StreamMismatch(2))));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_import_openiti() {
+    let dir = temp_dir("import-openiti");
+    let words = |range: std::ops::Range<u32>| range.map(|w| format!("كلمة{}", w)).collect::<Vec<_>>().join(" ");
+    let book = |first: u32| {
+        format!(
+            "######OpenITI#\n#META# 000.BookTITLE\t:: test\n#META#Header#End#\n\n# {} PageV01P001\n# ms1 {} PageV01P002\n",
+            words(first..first + 200),
+            words(first + 200..first + 400)
+        )
+    };
+    let files = [dir.join("0100Author.First.txt"), dir.join("0200Author.Second.txt")];
+    std::fs::write(&files[0], book(0)).unwrap();
+    std::fs::write(&files[1], book(100)).unwrap();
+
+    let db_path = dir.join("corpus.db");
+    let report = import_openiti(&files, &Lexicon::default(), &db_path, 7, &SimpleTokenizer, false).unwrap();
+    assert_eq!(report.books.len(), 2);
+    assert_eq!(report.books[1].uri, "0200Author.Second.txt");
+    assert_eq!(report.token_definitions, 500);
+    assert_eq!(list_books(&db_path).unwrap(), vec![7, 8]);
+
+    let info = load_book_info(&db_path, 7).unwrap();
+    assert_eq!(info.total_tokens, 400);
+    assert_eq!(info.pages.len(), 2);
+    assert_eq!(info.pages[1].page_number.as_deref(), Some("002"));
+
+    // No roots, so the default combined mode falls back to lemmas
+    let result = compare_books_with_text(7, 8, &db_path, &ComparisonParams::default(), 5, false).unwrap();
+    assert!(!result.edges.is_empty());
+    assert!(result.edges[0].source.text.matched.contains("كلمة"));
+
+    assert!(matches!(
+        import_openiti(&files, &Lexicon::default(), &db_path, 1, &SimpleTokenizer, false),
+        Err(ImportError::Exists(_))
+    ));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_import_text_with_dictionary() {
+    let dir = temp_dir("import-text");
+    let input = dir.join("texts");
+    std::fs::create_dir_all(&input).unwrap();
+    let words = |range: std::ops::Range<u32>| range.map(|w| format!("كلمة{}", w)).collect::<Vec<_>>().join(" ");
+    std::fs::write(input.join("b.txt"), format!("{}\u{c}{}", words(0..200), words(200..400))).unwrap();
+    std::fs::write(input.join("a.txt"), words(100..500)).unwrap();
+    std::fs::write(input.join("notes.md"), "ignored").unwrap();
+
+    // Every word shares one of ten roots
+    let mut csv = String::from("surface,lemma,root\n");
+    for w in 0..500 {
+        csv.push_str(&format!("كلمة{},lemma{},root{}\n", w, w, w % 10));
+    }
+    let lexicon = read_lexicon(csv.as_bytes(), NormalizationConfig::default()).unwrap();
+
+    let db_path = dir.join("corpus.db");
+    let report = import_text(&input, &lexicon, &db_path, 1, &SimpleTokenizer, false).unwrap();
+    assert_eq!(report.books.iter().map(|b| b.uri.as_str()).collect::<Vec<_>>(), vec!["a.txt", "b.txt"]);
+    assert_eq!((report.roots, report.lexicon_tokens), (10, 500));
+    assert_eq!(load_book_info(&db_path, 2).unwrap().pages.len(), 2);
+
+    // Roots are present, so combined mode is kept
+    let params = ComparisonParams {
+        require_roots: true,
+        ..Default::default()
+    };
+    let result = compare_books_with_text(1, 2, &db_path, &params, 5, false).unwrap();
+    assert_eq!(result.parameters.mode, MatchMode::Combined);
+    assert!(!result.edges.is_empty());
+
+    assert!(matches!(
+        import_text(&dir.join("missing"), &Lexicon::default(), &dir.join("other.db"), 1, &SimpleTokenizer, false),
+        Err(ImportError::Io { .. })
+    ));
+    assert!(!dir.join("other.db").exists());
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_compare_texts() {
+    let words = |range: std::ops::Range<u32>| range.map(|w| format!("كلمة{}", w)).collect::<Vec<_>>().join(" ");
+    let text_a = format!("{}\u{c}{}", words(0..150), words(150..300));
+    // Same passage after unrelated text, every word with a damma
+    let text_b = format!("{} {}", words(1000..1050), words(0..300).replace(' ', "\u{64f} ") + "\u{64f}");
+
+    let params = ComparisonParams::default();
+    let result = compare_texts(&text_a, &text_b, &Lexicon::default(), &params, 5).unwrap();
+    assert_eq!(result.parameters.mode, MatchMode::Lemma);
+    assert!(!result.edges.is_empty());
+    assert!(result.summary.book_a_coverage > 0.9);
+    assert_eq!(result.edges[0].source.book_id, 1);
+    assert!(result.edges[0].target.text.matched.contains('\u{64f}'));
+
+    // The lexicon ties otherwise unrelated surface forms to one lemma
+    let renamed = words(0..300).replace("كلمة", "لفظ");
+    assert!(compare_texts(&text_a, &renamed, &Lexicon::default(), &params, 5)
+        .unwrap()
+        .edges
+        .is_empty());
+    let mut csv = String::from("surface,lemma,root\n");
+    for w in 0..300 {
+        csv.push_str(&format!("كلمة{w},lemma{w},\nلفظ{w},lemma{w},\n"));
+    }
+    let lexicon = read_lexicon(csv.as_bytes(), NormalizationConfig::default()).unwrap();
+    let result = compare_texts(&text_a, &renamed, &lexicon, &params, 5).unwrap();
+    assert!(result.summary.book_b_coverage > 0.9);
+}
+
+#[test]
+fn test_surface_mode_ignores_lemmas() {
+    let words = |range: std::ops::Range<u32>| range.map(|w| format!("كلمة{}", w)).collect::<Vec<_>>().join(" ");
+    let text_a = words(0..300);
+    let text_b = words(0..300).replace(' ', "\u{64f} ") + "\u{64f}";
+
+    // An unnormalized lexicon gives the vocalized forms lemmas of their own
+    let mut csv = String::from("surface,lemma,root\n");
+    for w in 0..300 {
+        csv.push_str(&format!("كلمة{w},a{w},\nكلمة{w}\u{64f},b{w},\n"));
+    }
+    let lexicon = read_lexicon(csv.as_bytes(), NormalizationConfig::none()).unwrap();
+    let lemma = ComparisonParams::default();
+    assert!(compare_texts(&text_a, &text_b, &lexicon, &lemma, 5).unwrap().edges.is_empty());
+
+    let surface = ComparisonParams {
+        mode: MatchMode::Surface,
+        filter_mode: MatchMode::Surface,
+        ..Default::default()
+    };
+    let result = compare_texts(&text_a, &text_b, &lexicon, &surface, 5).unwrap();
+    assert_eq!(result.parameters.mode, MatchMode::Surface);
+    assert!(result.summary.book_a_coverage > 0.9);
+}
+
+#[test]
+fn test_cli_reports_invalid_arguments_as_plain_text() {
+    let dir = temp_dir("cli-invalid-args");
+    let db_path = dir.join("corpus.db");
+    create_corpus_db(&db_path, &[(1, (1..100).collect())]);
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_kashshaf-reuse"))
+        .arg("compare")
+        .arg("--corpus-db")
+        .arg(&db_path)
+        .args(["--book-a", "1", "--book-b", "1", "--output"])
+        .arg(dir.join("out.json"))
+        .output()
+        .unwrap();
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(!output.status.success());
+    assert!(stderr.contains("invalid arguments: book 1 cannot be compared with itself"));
+    assert!(!stderr.contains('"'));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,178 @@
+//! Regression tests built from validated parallels.
+//!
+//! Each JSON file under `tests/fixtures/parallels/` holds an anonymized pair
+//! of lemma sequences and the edges a scholar confirmed between them. The
+//! runner feeds every fixture through the full pipeline and asserts that each
+//! expected edge is recovered within the fixture's tolerance. See
+//! `tests/fixtures/parallels/README.md` for the format.
+
+use kashshaf_reuse::compare::compare_books_from_streams;
+use kashshaf_reuse::models::{BookLemmaStream, ComparisonParams, PageLemmas, ReuseEdge};
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_TOLERANCE: usize = 5;
+
+#[derive(Debug, Deserialize)]
+struct ParallelFixture {
+    name: String,
+    #[serde(default)]
+    description: String,
+    source: FixtureBook,
+    target: FixtureBook,
+    /// Overrides applied on top of `ComparisonParams::default()`
+    #[serde(default)]
+    params: serde_json::Map<String, serde_json::Value>,
+    /// Allowed drift, in tokens, of each boundary of a recovered edge
+    #[serde(default = "default_tolerance")]
+    tolerance: usize,
+    expected: Vec<ExpectedEdge>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FixtureBook {
+    book_id: u32,
+    /// Lemma IDs page by page
+    pages: Vec<Vec<u32>>,
+}
+
+/// Global token ranges (start inclusive, end exclusive) of a validated parallel.
+#[derive(Debug, Deserialize)]
+struct ExpectedEdge {
+    source: (usize, usize),
+    target: (usize, usize),
+}
+
+fn default_tolerance() -> usize {
+    DEFAULT_TOLERANCE
+}
+
+impl FixtureBook {
+    fn to_stream(&self) -> BookLemmaStream {
+        let pages: Vec<PageLemmas> = self
+            .pages
+            .iter()
+            .enumerate()
+            .map(|(i, lemma_ids)| PageLemmas {
+                part_index: 1,
+                page_id: (i + 1) as u32,
+                lemma_ids: lemma_ids.clone(),
+            })
+            .collect();
+
+        BookLemmaStream {
+            book_id: self.book_id,
+            total_tokens: pages.iter().map(|p| p.lemma_ids.len()).sum(),
+            pages,
+        }
+    }
+}
+
+impl ParallelFixture {
+    fn params(&self) -> ComparisonParams {
+        let mut value = serde_json::to_value(ComparisonParams::default()).unwrap();
+        let object = value.as_object_mut().unwrap();
+        for (key, override_value) in &self.params {
+            assert!(
+                object.contains_key(key),
+                "{}: unknown parameter `{}`",
+                self.name,
+                key
+            );
+            object.insert(key.clone(), override_value.clone());
+        }
+        serde_json::from_value(value)
+            .unwrap_or_else(|e| panic!("{}: invalid params: {}", self.name, e))
+    }
+}
+
+fn within(actual: (usize, usize), expected: (usize, usize), tolerance: usize) -> bool {
+    actual.0.abs_diff(expected.0) <= tolerance && actual.1.abs_diff(expected.1) <= tolerance
+}
+
+fn recovers(edge: &ReuseEdge, expected: &ExpectedEdge, tolerance: usize) -> bool {
+    within(
+        (edge.source_global_start, edge.source_global_end),
+        expected.source,
+        tolerance,
+    ) && within(
+        (edge.target_global_start, edge.target_global_end),
+        expected.target,
+        tolerance,
+    )
+}
+
+fn fixture_paths() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/parallels");
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("cannot read {}: {}", dir.display(), e))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+fn load_fixture(path: &Path) -> ParallelFixture {
+    let text = std::fs::read_to_string(path).unwrap();
+    serde_json::from_str(&text).unwrap_or_else(|e| panic!("{}: {}", path.display(), e))
+}
+
+#[test]
+fn test_validated_parallels_recovered() {
+    let paths = fixture_paths();
+    assert!(!paths.is_empty(), "no parallel fixtures found");
+
+    let mut failures = Vec::new();
+    for path in &paths {
+        let fixture = load_fixture(path);
+        let params = fixture.params();
+        let result = compare_books_from_streams(
+            &fixture.source.to_stream(),
+            &fixture.target.to_stream(),
+            &params,
+            false,
+        )
+        .unwrap();
+
+        for expected in &fixture.expected {
+            if !result.edges.iter().any(|e| recovers(e, expected, fixture.tolerance)) {
+                let found: Vec<_> = result
+                    .edges
+                    .iter()
+                    .map(|e| {
+                        (
+                            (e.source_global_start, e.source_global_end),
+                            (e.target_global_start, e.target_global_end),
+                        )
+                    })
+                    .collect();
+                failures.push(format!(
+                    "{} ({}): expected source {:?} / target {:?} within {} tokens; found {:?}",
+                    fixture.name,
+                    fixture.description,
+                    expected.source,
+                    expected.target,
+                    fixture.tolerance,
+                    found
+                ));
+            }
+        }
+    }
+
+    assert!(failures.is_empty(), "unrecovered parallels:\n{}", failures.join("\n"));
+}
+
+#[test]
+fn test_fixture_ranges_in_bounds() {
+    for path in fixture_paths() {
+        let fixture = load_fixture(&path);
+        let source_len = fixture.source.to_stream().total_tokens;
+        let target_len = fixture.target.to_stream().total_tokens;
+        assert!(!fixture.expected.is_empty(), "{}: no expected edges", fixture.name);
+        for expected in &fixture.expected {
+            assert!(expected.source.0 < expected.source.1 && expected.source.1 <= source_len);
+            assert!(expected.target.0 < expected.target.1 && expected.target.1 <= target_len);
+        }
+    }
+}
@@ -1,7 +1,7 @@
 import { useState, useEffect, useCallback, useRef } from 'react';
 import type { ComparisonResult, ReuseEdge } from './types';
 import { useMatchData } from './hooks/useMatchData';
-import { Header, StatsBar, FilterBar, MatchList, DetailView } from './components';
+import { Header, StatsBar, CoverageStrips, FilterBar, MatchList, DetailView } from './components';
 
 declare global {
   interface Window {
@@ -187,6 +187,7 @@ function App() {
         bookA={data.book_a}
         bookB={data.book_b}
         summary={data.summary}
+        direction={data.edges[0]?.direction}
         onOpenFile={handleOpenFile}
       />
       <StatsBar
@@ -195,6 +196,9 @@ function App() {
         validCount={validCount}
         noiseCount={noiseCount}
       />
+      {data.coverage_profiles && data.coverage_profiles.length > 0 && (
+        <CoverageStrips profiles={data.coverage_profiles} books={[data.book_a, data.book_b]} />
+      )}
       <FilterBar
         filters={filters}
         onUpdateFilter={updateFilter}
@@ -0,0 +1,40 @@
+import type { BookInfo, CoverageProfile } from '../types';
+
+interface Props {
+  profiles: CoverageProfile[];
+  books: BookInfo[];
+}
+
+// One strip per book, each bin as wide as its tokens and shaded by the share
+// of them reused
+export function CoverageStrips({ profiles, books }: Props) {
+  return (
+    <div className="bg-white px-4 py-2 border-b text-xs text-gray-500 space-y-1">
+      {profiles.map((profile, i) => {
+        const book = books.find(b => b.id === profile.book_id);
+        let start = 0;
+        return (
+          <div key={i} className="flex items-center gap-2">
+            <span className="w-32 truncate" title={book?.title}>
+              {book?.title || `Book ${profile.book_id}`}
+            </span>
+            <div className="flex-1 flex h-3 border border-gray-200">
+              {profile.sizes.map((size, bin) => {
+                const share = size > 0 ? profile.covered[bin] / size : 0;
+                const label = `Tokens ${start}–${start + size}: ${(share * 100).toFixed(0)}% reused`;
+                start += size;
+                return (
+                  <div
+                    key={bin}
+                    style={{ flex: `${size} 1 0`, backgroundColor: `rgba(37, 99, 235, ${share})` }}
+                    title={label}
+                  />
+                );
+              })}
+            </div>
+          </div>
+        );
+      })}
+    </div>
+  );
+}
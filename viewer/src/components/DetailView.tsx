@@ -40,6 +40,9 @@ export function DetailView({ edge, bookA, bookB, validation, onValidate }: Props
             <span>{edge.alignment.substitutions ?? 0} subs</span>
             <span className="text-gray-400">•</span>
             <span>{edge.alignment.gaps} gaps</span>
+            {edge.alignment.is_verse && (
+              <span className="px-2 py-0.5 rounded bg-purple-100 text-purple-700 text-xs font-semibold">verse</span>
+            )}
           </div>
 
           {/* Validation buttons */}
@@ -99,6 +102,31 @@ export function DetailView({ edge, bookA, bookB, validation, onValidate }: Props
               {(edge.alignment.lexical_diversity ?? 0) < 0.55 ? 'formulaic' : 'substantive'}
             </span>
           </div>
+          {edge.alignment.shingle_jaccard !== undefined && (
+            <div className="flex flex-col items-center">
+              <span className="text-xs text-gray-500 uppercase tracking-wide">Jaccard</span>
+              <span className="text-lg font-bold text-gray-700">{edge.alignment.shingle_jaccard.toFixed(2)}</span>
+              <span className="text-xs text-gray-400">span shingles</span>
+            </div>
+          )}
+          {edge.alignment.span_ratio !== undefined && (
+            <div className="flex flex-col items-center">
+              <span className="text-xs text-gray-500 uppercase tracking-wide">Spans</span>
+              <span className="text-lg font-bold text-gray-700">{edge.alignment.span_ratio.toFixed(2)}</span>
+              <span className="text-xs text-gray-400">
+                {edge.alignment.source_span_length} → {edge.alignment.target_span_length} tokens
+              </span>
+            </div>
+          )}
+          {edge.alignment.score !== undefined && (
+            <div className="flex flex-col items-center">
+              <span className="text-xs text-gray-500 uppercase tracking-wide">Score</span>
+              <span className="text-lg font-bold text-gray-700">{edge.alignment.score}</span>
+              <span className="text-xs text-gray-400">
+                {(edge.alignment.normalized_score ?? 0).toFixed(2)} / token
+              </span>
+            </div>
+          )}
         </div>
       </div>
 
@@ -1,13 +1,19 @@
-import type { BookInfo, ComparisonSummary } from '../types';
+import type { BookInfo, ComparisonSummary, Direction } from '../types';
 
 interface Props {
   bookA: BookInfo;
   bookB: BookInfo;
   summary: ComparisonSummary;
+  direction?: Direction;
   onOpenFile?: () => void;
 }
 
-export function Header({ bookA, bookB, summary, onOpenFile }: Props) {
+function bookLabel(book: BookInfo) {
+  const title = book.title || `Book ${book.id}`;
+  return book.death_ah ? `${title} (d. ${book.death_ah} AH)` : title;
+}
+
+export function Header({ bookA, bookB, summary, direction, onOpenFile }: Props) {
   return (
     <header className="bg-white border-b px-4 py-3">
       <div className="flex justify-between items-center flex-wrap gap-2">
@@ -15,7 +21,9 @@ export function Header({ bookA, bookB, summary, onOpenFile }: Props) {
           <div>
             <h1 className="text-xl font-bold">Kashshaf Text Reuse Viewer</h1>
             <p className="text-sm text-gray-600">
-              {bookA.title || `Book ${bookA.id}`} vs {bookB.title || `Book ${bookB.id}`}
+              {direction === 'AtoB' && <>{bookLabel(bookA)} → {bookLabel(bookB)} <span className="text-gray-400">(earlier → later)</span></>}
+              {direction === 'BtoA' && <>{bookLabel(bookB)} → {bookLabel(bookA)} <span className="text-gray-400">(earlier → later)</span></>}
+              {(!direction || direction === 'Unknown') && <>{bookLabel(bookA)} vs {bookLabel(bookB)}</>}
             </p>
           </div>
           {onOpenFile && (
@@ -65,14 +65,18 @@ export function MatchList({ edges, selectedId, validations, onSelect }: Props) {
             </span>
           </div>
 
-          {/* Preview of matched text */}
+          {/* Preview: the signature, so repeats of one passage read alike */}
           <div
             className="text-sm text-gray-500 mt-1 truncate arabic-text"
             dir="rtl"
             lang="ar"
           >
-            {edge.source.text.matched.slice(0, 60)}
-            {edge.source.text.matched.length > 60 ? '...' : ''}
+            {edge.signature ? edge.signature : (
+              <>
+                {edge.source.text.matched.slice(0, 60)}
+                {edge.source.text.matched.length > 60 ? '...' : ''}
+              </>
+            )}
           </div>
         </div>
       ))}
@@ -1,4 +1,48 @@
-import type { ComparisonSummary } from '../types';
+import type { ComparisonSummary, Distribution } from '../types';
+
+function formatCi(ci?: [number, number] | null): string {
+  return ci ? ` (95% CI ${(ci[0] * 100).toFixed(1)}-${(ci[1] * 100).toFixed(1)}%)` : '';
+}
+
+const PERCENTILES = ['min', 'p25', 'median', 'p75', 'p90', 'max'] as const;
+
+// Bar per histogram bin, height by its share of the fullest bin; percentiles
+// in the tooltip
+function Histogram({
+  label,
+  distribution,
+  format,
+}: {
+  label: string;
+  distribution: Distribution;
+  format: (value: number) => string;
+}) {
+  const fullest = Math.max(1, ...distribution.counts);
+  const percentiles = PERCENTILES.map((p) => `${p} ${format(distribution[p])}`).join(', ');
+  return (
+    <span className="flex items-end gap-1 text-gray-500" title={`${label}: ${percentiles}`}>
+      {label}:
+      <span className="flex items-end h-4 gap-px">
+        {distribution.counts.map((count, i) => {
+          const upper = distribution.bins[i + 1];
+          const range =
+            upper === undefined
+              ? `${format(distribution.bins[i])}+`
+              : `${format(distribution.bins[i])}–${format(upper)}`;
+          return (
+            <span
+              key={i}
+              className="w-1.5 bg-blue-500"
+              style={{ height: `${(count / fullest) * 100}%` }}
+              title={`${range}: ${count}`}
+            />
+          );
+        })}
+      </span>
+      <span>median {format(distribution.median)}</span>
+    </span>
+  );
+}
 
 interface Props {
   summary: ComparisonSummary;
@@ -9,19 +53,41 @@ interface Props {
 
 export function StatsBar({ summary, filteredCount, validCount, noiseCount }: Props) {
   return (
-    <div className="bg-gray-100 px-4 py-2 border-b flex gap-6 text-sm flex-wrap">
+    <div className="bg-gray-100 px-4 py-2 border-b flex gap-6 text-sm flex-wrap items-end">
       <span>
         Showing: <strong>{filteredCount}</strong> of {summary.edge_count} matches
       </span>
       <span className="text-green-600">✓ Valid: {validCount}</span>
       <span className="text-red-600">✗ Noise: {noiseCount}</span>
       <span className="text-gray-500">
-        Book A coverage: {(summary.book_a_coverage * 100).toFixed(1)}% |
-        Book B coverage: {(summary.book_b_coverage * 100).toFixed(1)}%
+        Book A coverage: {(summary.book_a_coverage * 100).toFixed(1)}%{formatCi(summary.book_a_coverage_ci)} |
+        Book B coverage: {(summary.book_b_coverage * 100).toFixed(1)}%{formatCi(summary.book_b_coverage_ci)}
       </span>
       <span className="text-gray-500">
         Avg similarity: {(summary.avg_similarity * 100).toFixed(1)}%
       </span>
+      {summary.order_tau !== undefined && summary.edge_count > 1 && (
+        <span className="text-gray-500" title="Kendall tau of edge order in A vs B: 1 = same order, 0 = scattered">
+          Order τ: {summary.order_tau.toFixed(2)}
+        </span>
+      )}
+      {summary.edge_classes && (
+        <span className="text-gray-500">
+          Aligned tokens A {summary.book_a_aligned_tokens} | B {summary.book_b_aligned_tokens} ·{' '}
+          {summary.edge_classes.prose} prose, {summary.edge_classes.verse} verse,{' '}
+          {summary.edge_classes.suspect} suspect, {summary.edge_classes.quran} Quran
+        </span>
+      )}
+      {summary.length_distribution && summary.edge_count > 0 && (
+        <Histogram label="Length" distribution={summary.length_distribution} format={(v) => v.toFixed(0)} />
+      )}
+      {summary.similarity_distribution && summary.edge_count > 0 && (
+        <Histogram
+          label="Similarity"
+          distribution={summary.similarity_distribution}
+          format={(v) => `${(v * 100).toFixed(0)}%`}
+        />
+      )}
     </div>
   );
 }
@@ -1,5 +1,6 @@
 export { Header } from './Header';
 export { StatsBar } from './StatsBar';
+export { CoverageStrips } from './CoverageStrips';
 export { FilterBar } from './FilterBar';
 export { MatchList } from './MatchList';
 export { DetailView } from './DetailView';
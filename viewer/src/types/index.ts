@@ -8,6 +8,22 @@ export interface ComparisonResult {
   book_b: BookInfo;
   summary: ComparisonSummary;
   edges: ReuseEdge[];
+  // Book A's profile, then book B's (written with --coverage-bins)
+  coverage_profiles?: CoverageProfile[];
+  run_info?: RunInfo;
+  // Present when the text was stripped with --redact-text
+  redaction?: { context_tokens: number };
+}
+
+// Wall-clock milliseconds per comparison stage
+export interface RunInfo {
+  load_ms: number;
+  window_ms: number;
+  filter_ms: number;
+  align_ms: number;
+  merge_ms: number;
+  filter_metrics_ms: number;
+  text_ms: number;
 }
 
 export interface ComparisonParams {
@@ -16,6 +32,7 @@ export interface ComparisonParams {
   ngram_size: number;
   min_shared_shingles: number;
   min_length: number;
+  min_matched_tokens?: number | null;
   min_similarity: number;
   match_score: number;
   mismatch_penalty: number;
@@ -30,6 +47,15 @@ export interface BookInfo {
   death_ah: number | null;
   token_count: number;
   page_count: number;
+  stream_hash?: string;
+}
+
+// Reused tokens per bin of a book, bins of N tokens or one per page
+export interface CoverageProfile {
+  book_id: number;
+  bins: { Tokens: number } | 'Pages';
+  sizes: number[];
+  covered: number[];
 }
 
 export interface ComparisonSummary {
@@ -38,15 +64,53 @@ export interface ComparisonSummary {
   book_a_coverage: number;
   book_b_coverage: number;
   avg_similarity: number;
+  book_a_coverage_ci?: [number, number] | null;
+  book_b_coverage_ci?: [number, number] | null;
+  // Kendall tau of source vs target edge order (absent in older results)
+  order_tau?: number;
+  // Alignments abandoned at the cell cap or timeout (absent in older results)
+  aborted_alignments?: number;
+  // Tokens of each book inside edge spans, edges by class and the length and
+  // similarity distributions (absent in older results)
+  book_a_aligned_tokens?: number;
+  book_b_aligned_tokens?: number;
+  edge_classes?: EdgeClassCounts;
+  length_distribution?: Distribution;
+  similarity_distribution?: Distribution;
+}
+
+export interface EdgeClassCounts {
+  prose: number;
+  verse: number;
+  suspect: number;
+  quran: number;
+}
+
+export interface Distribution {
+  min: number;
+  p25: number;
+  median: number;
+  p75: number;
+  p90: number;
+  max: number;
+  // Lower bound of each histogram bin; the last bin is open-ended
+  bins: number[];
+  counts: number[];
 }
 
 export interface ReuseEdge {
   id: number;
+  // Normalized content-word preview, shared by repeats of one passage (absent in older results)
+  signature?: string;
+  // Likely direction of reuse by author death dates (absent in older results)
+  direction?: Direction;
   source: PassageRef;
   target: PassageRef;
   alignment: AlignmentInfo;
 }
 
+export type Direction = 'AtoB' | 'BtoA' | 'Unknown';
+
 export interface PassageRef {
   book_id: number;
   location: string;
@@ -73,11 +137,22 @@ export interface AlignmentInfo {
   // Lexical diversity: unique_matched_lemmas / lemma_matches
   // Low values (< 0.55) indicate formulaic content; high values indicate substantive reuse
   lexical_diversity: number;
+  // Lemma-shingle Jaccard of the matched spans (absent in older results)
+  shingle_jaccard?: number;
+  // Either span reads as verse (absent in older results)
+  is_verse?: boolean;
+  // Span lengths in tokens and target / source (absent in older results)
+  source_span_length?: number;
+  target_span_length?: number;
+  span_ratio?: number;
   // Legacy metrics
   similarity: number;
   combined_similarity: number;
   weighted_similarity: number;
   avg_match_weight: number;
+  // Raw Smith-Waterman score and score per aligned position
+  score?: number;
+  normalized_score?: number;
 }
 
 export interface Filters {